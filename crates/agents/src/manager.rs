@@ -6,6 +6,7 @@ use campaign_analytics::AnalyticsLogger;
 use campaign_cache::RedisCache;
 use campaign_core::config::AppConfig;
 use campaign_npu::NpuEngine;
+use dashmap::DashMap;
 use std::sync::Arc;
 use tokio::task::JoinHandle;
 use tracing::{error, info};
@@ -16,6 +17,9 @@ pub struct AgentManager {
     npu: Arc<NpuEngine>,
     cache: Arc<RedisCache>,
     analytics: Arc<AnalyticsLogger>,
+    /// offer_id -> campaign_id, shared with every processor so bid events
+    /// reach the campaign rollups.
+    offer_campaigns: Arc<DashMap<String, String>>,
     handles: Vec<JoinHandle<()>>,
 }
 
@@ -31,6 +35,7 @@ impl AgentManager {
             npu,
             cache,
             analytics,
+            offer_campaigns: Arc::new(DashMap::new()),
             handles: Vec::new(),
         }
     }
@@ -54,12 +59,15 @@ impl AgentManager {
 
         info!("NATS connection established");
 
-        let processor = Arc::new(BidProcessor::new(
-            self.npu.clone(),
-            self.cache.clone(),
-            self.analytics.clone(),
-            self.config.node_id.clone(),
-        ));
+        let processor = Arc::new(
+            BidProcessor::new(
+                self.npu.clone(),
+                self.cache.clone(),
+                self.analytics.clone(),
+                self.config.node_id.clone(),
+            )
+            .with_offer_campaigns(self.offer_campaigns.clone()),
+        );
 
         let subject = format!("{}.bid-requests", self.config.nats.stream_name);
 
@@ -89,12 +97,15 @@ impl AgentManager {
 
     /// Get a reference to the bid processor for direct API use.
     pub fn processor(&self) -> Arc<BidProcessor> {
-        Arc::new(BidProcessor::new(
-            self.npu.clone(),
-            self.cache.clone(),
-            self.analytics.clone(),
-            self.config.node_id.clone(),
-        ))
+        Arc::new(
+            BidProcessor::new(
+                self.npu.clone(),
+                self.cache.clone(),
+                self.analytics.clone(),
+                self.config.node_id.clone(),
+            )
+            .with_offer_campaigns(self.offer_campaigns.clone()),
+        )
     }
 
     /// Wait for all agents to complete (blocks until shutdown).
@@ -3,21 +3,28 @@
 
 use campaign_analytics::AnalyticsLogger;
 use campaign_cache::RedisCache;
+use campaign_core::event_bus::make_event;
 use campaign_core::loyalty::LoyaltyTier;
 use campaign_core::openrtb::{Bid, BidRequest, BidResponse, SeatBid};
-use campaign_core::types::{BidDecision, EventType};
+use campaign_core::types::{AnalyticsEvent, BidDecision, EventType};
 use campaign_npu::NpuEngine;
 use chrono::Utc;
+use dashmap::DashMap;
 use std::sync::Arc;
 use tracing::{debug, warn};
 use uuid::Uuid;
 
+/// Channel that bid-path events are attributed to in the rollups.
+pub const BID_CHANNEL: &str = "programmatic";
+
 /// Processes a single bid request through the full pipeline.
 pub struct BidProcessor {
     npu: Arc<NpuEngine>,
     cache: Arc<RedisCache>,
     analytics: Arc<AnalyticsLogger>,
     node_id: String,
+    /// offer_id -> campaign_id, shared by every processor on the node.
+    offer_campaigns: Arc<DashMap<String, String>>,
 }
 
 impl BidProcessor {
@@ -32,9 +39,39 @@ impl BidProcessor {
             cache,
             analytics,
             node_id,
+            offer_campaigns: Arc::new(DashMap::new()),
         }
     }
 
+    /// Share the offer-to-campaign index used to attribute bid events.
+    pub fn with_offer_campaigns(mut self, offer_campaigns: Arc<DashMap<String, String>>) -> Self {
+        self.offer_campaigns = offer_campaigns;
+        self
+    }
+
+    /// The offer-to-campaign index, for the campaign store to keep current.
+    pub fn offer_campaigns(&self) -> Arc<DashMap<String, String>> {
+        self.offer_campaigns.clone()
+    }
+
+    fn bid_event(
+        &self,
+        event_type: EventType,
+        request_id: &str,
+        agent_id: &str,
+        user_id: &str,
+        offer_id: Option<&str>,
+    ) -> AnalyticsEvent {
+        bid_event(
+            &self.offer_campaigns,
+            event_type,
+            request_id,
+            agent_id,
+            user_id,
+            offer_id,
+        )
+    }
+
     /// Process a bid request and return a bid response.
     pub async fn process(
         &self,
@@ -70,20 +107,9 @@ impl BidProcessor {
         // Check frequency cap
         if profile.frequency_cap.impressions_1h >= profile.frequency_cap.max_per_hour {
             metrics::counter!("bids.frequency_capped").increment(1);
-            self.analytics
-                .log_event(
-                    EventType::NoBid,
-                    request_id.clone(),
-                    agent_id.to_string(),
-                    None,
-                    Some(user_id),
-                    None,
-                    None,
-                    None,
-                    None,
-                    Some(start.elapsed().as_micros() as u64),
-                )
-                .await;
+            let mut event = self.bid_event(EventType::NoBid, request_id, agent_id, &user_id, None);
+            event.total_latency_us = Some(start.elapsed().as_micros() as u64);
+            self.analytics.log(event);
             return Ok(BidResponse::no_bid(request_id.clone()));
         }
 
@@ -169,20 +195,18 @@ impl BidProcessor {
                 });
 
                 // Log bid response event
-                self.analytics
-                    .log_event(
-                        EventType::BidResponse,
-                        request_id.clone(),
-                        agent_id.to_string(),
-                        Some(imp.id.clone()),
-                        Some(user_id.clone()),
-                        Some(decision.offer_id),
-                        Some(decision.bid_price),
-                        None,
-                        Some(inference_latency_us),
-                        Some(decision.total_latency_us),
-                    )
-                    .await;
+                let mut event = self.bid_event(
+                    EventType::BidResponse,
+                    request_id,
+                    agent_id,
+                    &user_id,
+                    Some(&decision.offer_id),
+                );
+                event.impression_id = Some(imp.id.clone());
+                event.bid_price = Some(decision.bid_price);
+                event.inference_latency_us = Some(inference_latency_us);
+                event.total_latency_us = Some(decision.total_latency_us);
+                self.analytics.log(event);
             }
         }
 
@@ -204,3 +228,99 @@ impl BidProcessor {
         })
     }
 }
+
+/// Event for the bid path, attributed to the offer's campaign (once the
+/// offer is indexed) and to its creative, which is the `crid` we bid with.
+fn bid_event(
+    offer_campaigns: &DashMap<String, String>,
+    event_type: EventType,
+    request_id: &str,
+    agent_id: &str,
+    user_id: &str,
+    offer_id: Option<&str>,
+) -> AnalyticsEvent {
+    let mut event = make_event(
+        event_type,
+        request_id,
+        Some(user_id.to_string()),
+        offer_id.map(str::to_string),
+    )
+    .with_channel(BID_CHANNEL);
+    event.agent_id = agent_id.to_string();
+    if let Some(offer_id) = offer_id {
+        event = event.with_creative(offer_id);
+        if let Some(campaign_id) = offer_campaigns.get(offer_id) {
+            event = event.with_campaign(campaign_id.value().clone());
+        }
+    }
+    event
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use campaign_analytics::{InMemoryAnalyticsStore, RollupDimension, RollupGrain, RollupQuery};
+    use campaign_management::models::{CreateCreativeRequest, CreativeFormat};
+    use campaign_management::ManagementStore;
+    use chrono::Duration;
+
+    #[test]
+    fn test_bid_event_reaches_campaign_rollup() {
+        let offer_campaigns = Arc::new(DashMap::new());
+        let store = ManagementStore::new().with_offer_campaigns(offer_campaigns.clone());
+        let campaign = store.list_campaigns()[0].clone();
+        let creative = store
+            .create_creative(
+                CreateCreativeRequest {
+                    campaign_id: campaign.id,
+                    name: "Spring banner".into(),
+                    format: CreativeFormat::Banner,
+                    asset_url: "https://cdn.example.com/spring.png".into(),
+                    width: 300,
+                    height: 250,
+                    metadata: serde_json::json!({"offer_id": "offer-0001"}),
+                },
+                "tester",
+            )
+            .unwrap();
+
+        let analytics = InMemoryAnalyticsStore::new();
+        let mut event = bid_event(
+            &offer_campaigns,
+            EventType::BidResponse,
+            "req-1",
+            "node-agent-00",
+            "user-1",
+            Some("offer-0001"),
+        );
+        event.bid_price = Some(2.5);
+        analytics.ingest(&event);
+
+        let now = Utc::now();
+        let mut query = RollupQuery::new(
+            RollupDimension::Campaign,
+            RollupGrain::Hourly,
+            now - Duration::hours(1),
+            now + Duration::hours(1),
+        );
+        query.totals = true;
+        let rows = analytics.query(&query);
+        assert_eq!(rows.len(), 1);
+        assert_eq!(rows[0].key, campaign.id.to_string());
+        assert_eq!(rows[0].metrics.bids, 1);
+        assert_eq!(rows[0].metrics.bid_value, 2.5);
+
+        // Deleting the creative stops attributing its offer.
+        assert!(store.delete_creative(creative.id, "tester"));
+        let event = bid_event(
+            &offer_campaigns,
+            EventType::BidResponse,
+            "req-2",
+            "node-agent-00",
+            "user-1",
+            Some("offer-0001"),
+        );
+        assert!(event.campaign_id.is_none());
+    }
+}
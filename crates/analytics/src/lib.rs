@@ -1,3 +1,11 @@
 pub mod logger;
+pub mod migrations;
+pub mod rollups;
+pub mod spool;
+pub mod store;
 
 pub use logger::AnalyticsLogger;
+pub use migrations::Migrator;
+pub use rollups::{RollupDimension, RollupGrain, RollupQuery, RollupRow};
+pub use spool::{Spool, SpoolConfig, SpoolStats};
+pub use store::{AnalyticsStore, ClickHouseAnalyticsStore, InMemoryAnalyticsStore};
@@ -1,27 +1,71 @@
 //! Asynchronous analytics logger that batches events and writes to ClickHouse.
 //! Uses a channel-based architecture for non-blocking event submission.
+//!
+//! With a spool directory configured, batches that fail to insert are written
+//! to a [`Spool`] on disk and replayed in order once ClickHouse catches up,
+//! instead of being dropped. The writer task owns the spool and does its file
+//! I/O on the blocking pool, so [`AnalyticsLogger::log`] never touches disk.
+//! When the channel is full, `log` parks the event in a bounded in-memory
+//! overflow queue that the writer drains to the spool on its next tick.
+//!
+//! An optional [`EventSink`] also receives every logged event, so bid-path
+//! events reach the same live and cross-channel reporting as the rest of
+//! the platform.
 
+use crate::migrations::Migrator;
+use crate::spool::{AppendOutcome, Spool, SpoolConfig};
 use campaign_core::config::ClickHouseConfig;
+use campaign_core::event_bus::EventSink;
 use campaign_core::types::{AnalyticsEvent, EventType};
 use chrono::Utc;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use tokio::sync::mpsc;
 use tracing::{debug, error, info, warn};
 use uuid::Uuid;
 
+/// How long one insert may take before the batch is treated as failed (and
+/// spooled, when a spool is configured).
+const INSERT_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Capacity of the channel to the writer task.
+const CHANNEL_CAPACITY: usize = 100_000;
+
+/// Events held for the writer once the channel is full.
+const OVERFLOW_CAPACITY: usize = 100_000;
+
 /// Analytics logger with background batch writer.
 pub struct AnalyticsLogger {
     sender: mpsc::Sender<AnalyticsEvent>,
+    overflow: Arc<Overflow>,
     node_id: String,
+    event_sink: Option<Arc<dyn EventSink>>,
 }
 
 impl AnalyticsLogger {
     /// Create a new analytics logger and spawn the background writer.
     pub async fn new(config: &ClickHouseConfig, node_id: String) -> anyhow::Result<Self> {
-        let (sender, receiver) = mpsc::channel::<AnalyticsEvent>(100_000);
+        let (sender, receiver) = mpsc::channel::<AnalyticsEvent>(CHANNEL_CAPACITY);
+        let overflow = Arc::new(Overflow::new(OVERFLOW_CAPACITY));
 
-        let writer = BatchWriter::new(config).await?;
+        let spool = match SpoolConfig::from_clickhouse(config) {
+            Some(spool_config) => {
+                let spool =
+                    tokio::task::spawn_blocking(move || Spool::open(spool_config)).await??;
+                info!(
+                    dir = %spool.config().dir.display(),
+                    pending = spool.pending(),
+                    "Analytics spool opened"
+                );
+                Some(Arc::new(spool))
+            }
+            None => None,
+        };
+
+        let writer = BatchWriter::new(config, spool, overflow.clone()).await?;
         let batch_size = config.batch_size;
-        let flush_interval = std::time::Duration::from_millis(config.flush_interval_ms);
+        let flush_interval = Duration::from_millis(config.flush_interval_ms);
 
         // Spawn background batch writer
         tokio::spawn(async move {
@@ -30,7 +74,18 @@ impl AnalyticsLogger {
 
         info!("Analytics logger initialized with ClickHouse backend");
 
-        Ok(Self { sender, node_id })
+        Ok(Self {
+            sender,
+            overflow,
+            node_id,
+            event_sink: None,
+        })
+    }
+
+    /// Also emit every logged event to `sink`.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = Some(sink);
+        self
     }
 
     /// Log an analytics event (non-blocking).
@@ -62,13 +117,96 @@ impl AnalyticsLogger {
             inference_latency_us,
             total_latency_us,
             timestamp: Utc::now(),
+            campaign_id: None,
+            channel: None,
+            creative_id: None,
         };
 
-        if let Err(e) = self.sender.try_send(event) {
+        self.log(event);
+    }
+
+    /// Log a prebuilt event (e.g. one carrying campaign, channel and
+    /// creative attribution for the rollups). Stamped with this node's id.
+    /// Never blocks: an event that does not fit in the channel goes to the
+    /// overflow queue, and is dropped only when that is full too.
+    pub fn log(&self, mut event: AnalyticsEvent) {
+        event.node_id = self.node_id.clone();
+        if let Some(sink) = &self.event_sink {
+            sink.emit(event.clone());
+        }
+        match self.sender.try_send(event) {
+            Ok(()) => {
+                metrics::counter!("analytics.queued").increment(1);
+            }
+            Err(mpsc::error::TrySendError::Full(event)) => {
+                if self.overflow.push(event) {
+                    metrics::counter!("analytics.overflowed").increment(1);
+                } else {
+                    metrics::counter!("analytics.dropped").increment(1);
+                    warn!("Analytics channel and overflow queue full; event dropped");
+                }
+            }
+            Err(e) => {
+                metrics::counter!("analytics.dropped").increment(1);
+                warn!("Analytics event dropped: {}", e);
+            }
+        }
+    }
+}
+
+/// Bounded queue for events that found the writer channel full. Pushing only
+/// takes a short uncontended lock, so the bid path never waits on the writer.
+struct Overflow {
+    events: Mutex<VecDeque<AnalyticsEvent>>,
+    capacity: usize,
+}
+
+impl Overflow {
+    fn new(capacity: usize) -> Self {
+        Self {
+            events: Mutex::new(VecDeque::new()),
+            capacity,
+        }
+    }
+
+    /// Queue `event`, or return false when the queue is full.
+    fn push(&self, event: AnalyticsEvent) -> bool {
+        let mut events = self.lock();
+        if events.len() >= self.capacity {
+            return false;
+        }
+        events.push_back(event);
+        true
+    }
+
+    /// Take everything queued so far, oldest first.
+    fn drain(&self) -> Vec<AnalyticsEvent> {
+        self.lock().drain(..).collect()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, VecDeque<AnalyticsEvent>> {
+        self.events.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Append to the spool, counting what the overflow policy did.
+fn spool_event(spool: &Spool, event: &AnalyticsEvent) {
+    match spool.append(event) {
+        Ok(AppendOutcome::Spooled) => {
+            metrics::counter!("analytics.spooled").increment(1);
+        }
+        Ok(AppendOutcome::Evicted { records }) => {
+            metrics::counter!("analytics.spooled").increment(1);
+            metrics::counter!("analytics.spool.evicted").increment(records);
+            warn!(records, "Analytics spool full; evicted oldest events");
+        }
+        Ok(AppendOutcome::Rejected) => {
+            metrics::counter!("analytics.dropped").increment(1);
+            warn!("Analytics spool full; event dropped");
+        }
+        Err(e) => {
             metrics::counter!("analytics.dropped").increment(1);
-            warn!("Analytics event dropped: {}", e);
-        } else {
-            metrics::counter!("analytics.queued").increment(1);
+            error!(error = %e, "Failed to spool analytics event");
         }
     }
 }
@@ -76,108 +214,323 @@ impl AnalyticsLogger {
 /// Background writer that batches events and flushes to ClickHouse.
 struct BatchWriter {
     client: clickhouse::Client,
+    spool: Option<Arc<Spool>>,
+    overflow: Arc<Overflow>,
 }
 
 impl BatchWriter {
-    async fn new(config: &ClickHouseConfig) -> anyhow::Result<Self> {
+    async fn new(
+        config: &ClickHouseConfig,
+        spool: Option<Arc<Spool>>,
+        overflow: Arc<Overflow>,
+    ) -> anyhow::Result<Self> {
         let client = clickhouse::Client::default()
             .with_url(&config.url)
             .with_database(&config.database);
 
-        // Create the analytics table if it doesn't exist
-        Self::ensure_schema(&client).await?;
-
-        Ok(Self { client })
-    }
-
-    async fn ensure_schema(client: &clickhouse::Client) -> anyhow::Result<()> {
-        client
-            .query(
-                "CREATE TABLE IF NOT EXISTS analytics_events (
-                    event_id UUID,
-                    event_type String,
-                    request_id String,
-                    impression_id Nullable(String),
-                    user_id Nullable(String),
-                    offer_id Nullable(String),
-                    bid_price Nullable(Float64),
-                    win_price Nullable(Float64),
-                    agent_id String,
-                    node_id String,
-                    inference_latency_us Nullable(UInt64),
-                    total_latency_us Nullable(UInt64),
-                    timestamp DateTime64(3)
-                ) ENGINE = MergeTree()
-                ORDER BY (timestamp, event_type, node_id)
-                PARTITION BY toYYYYMM(timestamp)
-                TTL timestamp + INTERVAL 90 DAY",
-            )
-            .execute()
-            .await?;
-
-        info!("ClickHouse schema verified");
-        Ok(())
+        // Bring the schema (raw events, rollups) up to date
+        let applied = Migrator::new(client.clone()).run().await?;
+        info!(applied = ?applied, "ClickHouse schema verified");
+
+        Ok(Self {
+            client,
+            spool,
+            overflow,
+        })
     }
 
     async fn run(
         self,
         mut receiver: mpsc::Receiver<AnalyticsEvent>,
         batch_size: usize,
-        flush_interval: std::time::Duration,
+        flush_interval: Duration,
     ) {
         let mut buffer: Vec<AnalyticsEvent> = Vec::with_capacity(batch_size);
+        // Events that arrive while the spool has a backlog; they go to disk
+        // behind it so replay keeps them in order.
+        let mut spill: Vec<AnalyticsEvent> = Vec::new();
         let mut interval = tokio::time::interval(flush_interval);
 
         loop {
             tokio::select! {
                 Some(event) = receiver.recv() => {
-                    buffer.push(event);
-                    if buffer.len() >= batch_size {
-                        self.flush(&mut buffer).await;
+                    if self.spooling(&spill) {
+                        spill.push(event);
+                        if spill.len() >= batch_size {
+                            self.spool_events(&mut spill).await;
+                        }
+                    } else {
+                        buffer.push(event);
+                        if buffer.len() >= batch_size {
+                            self.flush(&mut buffer).await;
+                        }
                     }
                 }
                 _ = interval.tick() => {
                     if !buffer.is_empty() {
                         self.flush(&mut buffer).await;
                     }
+                    // Overflowed events go straight to disk, behind anything
+                    // already spooled; without a spool they take the normal
+                    // insert path.
+                    let overflowed = self.overflow.drain();
+                    if !overflowed.is_empty() {
+                        if self.spool.is_some() {
+                            spill.extend(overflowed);
+                        } else {
+                            buffer.extend(overflowed);
+                            self.flush(&mut buffer).await;
+                        }
+                    }
+                    if !spill.is_empty() {
+                        self.spool_events(&mut spill).await;
+                    }
+                    self.replay(batch_size).await;
                 }
             }
         }
     }
 
+    /// Whether new events must queue behind spooled ones.
+    fn spooling(&self, spill: &[AnalyticsEvent]) -> bool {
+        match &self.spool {
+            Some(spool) => !spill.is_empty() || spool.pending() > 0,
+            None => false,
+        }
+    }
+
+    /// Append `events` to the spool on the blocking pool.
+    async fn spool_events(&self, events: &mut Vec<AnalyticsEvent>) {
+        let events = std::mem::take(events);
+        let Some(spool) = &self.spool else {
+            metrics::counter!("analytics.dropped").increment(events.len() as u64);
+            return;
+        };
+        let spool = spool.clone();
+        let count = events.len() as u64;
+        let appended = tokio::task::spawn_blocking(move || {
+            for event in &events {
+                spool_event(&spool, event);
+            }
+        })
+        .await;
+        if let Err(e) = appended {
+            metrics::counter!("analytics.dropped").increment(count);
+            error!(error = %e, "Analytics spool task failed");
+        }
+    }
+
     async fn flush(&self, buffer: &mut Vec<AnalyticsEvent>) {
         let count = buffer.len();
         debug!(count = count, "Flushing analytics batch to ClickHouse");
 
-        // Serialize events as NDJSON and insert
-        let mut json_rows = Vec::with_capacity(buffer.len());
-        for e in buffer.iter() {
+        match self.insert(buffer).await {
+            Ok(()) => {
+                metrics::counter!("analytics.flushed").increment(count as u64);
+                debug!(count = count, "Analytics batch flushed successfully");
+            }
+            Err(e) => {
+                metrics::counter!("analytics.flush_errors").increment(1);
+                if self.spool.is_some() {
+                    warn!(error = %e, count = count, "Failed to flush analytics batch; spooling");
+                    self.spool_events(buffer).await;
+                } else {
+                    metrics::counter!("analytics.dropped").increment(count as u64);
+                    error!(error = %e, count = count, "Failed to flush analytics batch");
+                }
+            }
+        }
+
+        buffer.clear();
+    }
+
+    /// Insert spooled events oldest first until the spool is empty or
+    /// ClickHouse fails again, then publish the spool gauges.
+    async fn replay(&self, batch_size: usize) {
+        let Some(spool) = &self.spool else {
+            return;
+        };
+
+        while spool.pending() > 0 {
+            let reader = spool.clone();
+            let batch = match tokio::task::spawn_blocking(move || reader.read_batch(batch_size))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|read| read)
+            {
+                Ok(batch) if !batch.is_empty() => batch,
+                Ok(_) => break,
+                Err(e) => {
+                    error!(error = %e, "Failed to read analytics spool");
+                    break;
+                }
+            };
+            if let Err(e) = self.insert(&batch.events).await {
+                metrics::counter!("analytics.flush_errors").increment(1);
+                debug!(error = %e, pending = spool.pending(), "Analytics spool replay deferred");
+                break;
+            }
+            let committer = spool.clone();
+            let replayed = batch.events.len() as u64;
+            let committed = tokio::task::spawn_blocking(move || committer.commit(&batch))
+                .await
+                .map_err(anyhow::Error::from)
+                .and_then(|commit| commit);
+            if let Err(e) = committed {
+                // The batch is stored; it will be replayed again after a
+                // restart, which ClickHouse sees as duplicates.
+                error!(error = %e, "Failed to commit analytics spool cursor");
+                break;
+            }
+            metrics::counter!("analytics.replayed").increment(replayed);
+        }
+
+        let syncer = spool.clone();
+        let stats = match tokio::task::spawn_blocking(move || {
+            if let Err(e) = syncer.sync() {
+                error!(error = %e, "Failed to sync analytics spool");
+            }
+            syncer.stats()
+        })
+        .await
+        {
+            Ok(stats) => stats,
+            Err(e) => {
+                error!(error = %e, "Analytics spool task failed");
+                return;
+            }
+        };
+        metrics::gauge!("analytics.spool.depth_bytes").set(stats.bytes as f64);
+        metrics::gauge!("analytics.spool.pending_events").set(stats.pending_records as f64);
+        metrics::gauge!("analytics.spool.segments").set(stats.segments as f64);
+        metrics::gauge!("analytics.spool.replay_lag_seconds").set(stats.replay_lag_seconds);
+    }
+
+    /// Insert events as NDJSON.
+    async fn insert(&self, events: &[AnalyticsEvent]) -> anyhow::Result<()> {
+        let mut json_rows = Vec::with_capacity(events.len());
+        for e in events {
             if let Ok(json) = serde_json::to_string(e) {
                 json_rows.push(json);
             }
         }
-
         if json_rows.is_empty() {
-            buffer.clear();
-            return;
+            return Ok(());
         }
 
         let insert_sql = format!(
             "INSERT INTO analytics_events FORMAT JSONEachRow {}",
             json_rows.join("\n")
         );
+        tokio::time::timeout(INSERT_TIMEOUT, self.client.query(&insert_sql).execute())
+            .await
+            .map_err(|_| {
+                anyhow::anyhow!("ClickHouse insert timed out after {INSERT_TIMEOUT:?}")
+            })??;
+        Ok(())
+    }
+}
 
-        match self.client.query(&insert_sql).execute().await {
-            Ok(_) => {
-                metrics::counter!("analytics.flushed").increment(count as u64);
-                debug!(count = count, "Analytics batch flushed successfully");
-            }
-            Err(e) => {
-                metrics::counter!("analytics.flush_errors").increment(1);
-                error!(error = %e, count = count, "Failed to flush analytics batch");
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_core::types::EventType;
+
+    fn event(n: usize) -> AnalyticsEvent {
+        AnalyticsEvent {
+            event_id: Uuid::new_v4(),
+            event_type: EventType::BidResponse,
+            request_id: format!("req-{n}"),
+            impression_id: None,
+            user_id: None,
+            offer_id: None,
+            bid_price: None,
+            win_price: None,
+            agent_id: "agent".into(),
+            node_id: "node".into(),
+            inference_latency_us: None,
+            total_latency_us: None,
+            timestamp: Utc::now(),
+            campaign_id: None,
+            channel: None,
+            creative_id: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_failed_flush_spools_ahead_of_later_events() {
+        let dir = std::env::temp_dir().join(format!("analytics-writer-{}", Uuid::new_v4()));
+        let spool = Arc::new(Spool::open(SpoolConfig::new(&dir)).unwrap());
+        // Nothing listens here, so every insert fails.
+        let writer = BatchWriter {
+            client: clickhouse::Client::default().with_url("http://127.0.0.1:1"),
+            spool: Some(spool.clone()),
+            overflow: Arc::new(Overflow::new(16)),
+        };
+        let (sender, receiver) = mpsc::channel(16);
+        let task = tokio::spawn(writer.run(receiver, 2, Duration::from_millis(20)));
+
+        for n in 0..5 {
+            sender.send(event(n)).await.unwrap();
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while spool.pending() < 5 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
             }
+        })
+        .await
+        .unwrap();
+        task.abort();
+
+        let batch = spool.read_batch(10).unwrap();
+        let ids: Vec<_> = batch.events.iter().map(|e| e.request_id.as_str()).collect();
+        assert_eq!(ids, ["req-0", "req-1", "req-2", "req-3", "req-4"]);
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_full_channel_overflows_to_spool() {
+        let dir = std::env::temp_dir().join(format!("analytics-overflow-{}", Uuid::new_v4()));
+        let spool = Arc::new(Spool::open(SpoolConfig::new(&dir)).unwrap());
+        let overflow = Arc::new(Overflow::new(16));
+        let (sender, receiver) = mpsc::channel(2);
+        let logger = AnalyticsLogger {
+            sender,
+            overflow: overflow.clone(),
+            node_id: "node".into(),
+            event_sink: None,
+        };
+
+        // The writer is not running yet, so everything past the channel's
+        // capacity has to overflow.
+        for n in 0..10 {
+            logger.log(event(n));
         }
+        assert_eq!(overflow.lock().len(), 8);
 
-        buffer.clear();
+        // Nothing listens here, so every insert fails and is spooled.
+        let writer = BatchWriter {
+            client: clickhouse::Client::default().with_url("http://127.0.0.1:1"),
+            spool: Some(spool.clone()),
+            overflow,
+        };
+        let task = tokio::spawn(writer.run(receiver, 2, Duration::from_millis(20)));
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while spool.pending() < 10 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .unwrap();
+        task.abort();
+
+        // Every event reached disk exactly once.
+        let batch = spool.read_batch(20).unwrap();
+        let mut ids: Vec<_> = batch.events.iter().map(|e| e.request_id.clone()).collect();
+        ids.sort();
+        let mut expected: Vec<_> = (0..10).map(|n| format!("req-{n}")).collect();
+        expected.sort();
+        assert_eq!(ids, expected);
+        std::fs::remove_dir_all(dir).unwrap();
     }
 }
@@ -0,0 +1,209 @@
+//! Versioned ClickHouse schema migrations.
+//!
+//! Applied migrations are recorded in `schema_migrations` with a checksum of
+//! their statements. Statements are idempotent (`IF NOT EXISTS`), so nodes
+//! starting concurrently may both run a migration without harm. Editing a
+//! migration that has already been applied is an error; add a new one.
+
+use crate::rollups::{rollup_ddl, RollupDimension, RollupGrain};
+use serde::Deserialize;
+use tracing::info;
+
+/// One schema change.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub version: u32,
+    pub name: &'static str,
+    pub statements: Vec<String>,
+}
+
+impl Migration {
+    /// FNV-1a over the statements, used to detect edited migrations.
+    pub fn checksum(&self) -> u64 {
+        let mut hash: u64 = 0xcbf2_9ce4_8422_2325;
+        for statement in &self.statements {
+            for byte in statement.bytes().chain(std::iter::once(0)) {
+                hash ^= byte as u64;
+                hash = hash.wrapping_mul(0x0100_0000_01b3);
+            }
+        }
+        hash
+    }
+}
+
+/// A row of `schema_migrations`.
+#[derive(Debug, Clone, PartialEq, Eq, clickhouse::Row, Deserialize)]
+pub struct AppliedMigration {
+    pub version: u32,
+    pub name: String,
+    pub checksum: u64,
+}
+
+const CREATE_MIGRATIONS_TABLE: &str = "CREATE TABLE IF NOT EXISTS schema_migrations (
+    version UInt32,
+    name String,
+    checksum UInt64,
+    applied_at DateTime64(3) DEFAULT now64(3)
+) ENGINE = ReplacingMergeTree(applied_at)
+ORDER BY version";
+
+/// All migrations, in version order.
+pub fn migrations() -> Vec<Migration> {
+    let mut rollups = Vec::new();
+    for dimension in RollupDimension::ALL {
+        for grain in RollupGrain::ALL {
+            rollups.extend(rollup_ddl(dimension, grain));
+        }
+    }
+
+    vec![
+        Migration {
+            version: 1,
+            name: "create_analytics_events",
+            statements: vec!["CREATE TABLE IF NOT EXISTS analytics_events (
+                    event_id UUID,
+                    event_type String,
+                    request_id String,
+                    impression_id Nullable(String),
+                    user_id Nullable(String),
+                    offer_id Nullable(String),
+                    bid_price Nullable(Float64),
+                    win_price Nullable(Float64),
+                    agent_id String,
+                    node_id String,
+                    inference_latency_us Nullable(UInt64),
+                    total_latency_us Nullable(UInt64),
+                    timestamp DateTime64(3)
+                ) ENGINE = MergeTree()
+                ORDER BY (timestamp, event_type, node_id)
+                PARTITION BY toYYYYMM(timestamp)
+                TTL timestamp + INTERVAL 90 DAY"
+                .to_string()],
+        },
+        Migration {
+            version: 2,
+            name: "add_event_attribution",
+            statements: vec!["ALTER TABLE analytics_events
+                    ADD COLUMN IF NOT EXISTS campaign_id Nullable(String),
+                    ADD COLUMN IF NOT EXISTS channel Nullable(String),
+                    ADD COLUMN IF NOT EXISTS creative_id Nullable(String)"
+                .to_string()],
+        },
+        Migration {
+            version: 3,
+            name: "create_rollups",
+            statements: rollups,
+        },
+    ]
+}
+
+/// Migrations still to run given what the database has recorded. Fails if
+/// an applied migration was edited or the database has a version this build
+/// does not know, since running against either would corrupt the schema.
+pub fn pending<'a>(
+    migrations: &'a [Migration],
+    applied: &[AppliedMigration],
+) -> anyhow::Result<Vec<&'a Migration>> {
+    let latest = migrations.iter().map(|m| m.version).max().unwrap_or(0);
+    for record in applied {
+        match migrations.iter().find(|m| m.version == record.version) {
+            Some(migration) if migration.checksum() != record.checksum => anyhow::bail!(
+                "migration {} ({}) was changed after being applied",
+                record.version,
+                record.name
+            ),
+            Some(_) => {}
+            None if record.version > latest => anyhow::bail!(
+                "database schema version {} is newer than this build ({latest})",
+                record.version
+            ),
+            None => anyhow::bail!("unknown applied migration {}", record.version),
+        }
+    }
+    let mut pending: Vec<&Migration> = migrations
+        .iter()
+        .filter(|m| !applied.iter().any(|a| a.version == m.version))
+        .collect();
+    pending.sort_by_key(|m| m.version);
+    Ok(pending)
+}
+
+/// Applies [`migrations`] to a ClickHouse database.
+pub struct Migrator {
+    client: clickhouse::Client,
+}
+
+impl Migrator {
+    pub fn new(client: clickhouse::Client) -> Self {
+        Self { client }
+    }
+
+    pub async fn applied(&self) -> anyhow::Result<Vec<AppliedMigration>> {
+        self.client.query(CREATE_MIGRATIONS_TABLE).execute().await?;
+        let applied = self
+            .client
+            .query("SELECT version, name, checksum FROM schema_migrations FINAL ORDER BY version")
+            .fetch_all::<AppliedMigration>()
+            .await?;
+        Ok(applied)
+    }
+
+    /// Run pending migrations and return the versions applied.
+    pub async fn run(&self) -> anyhow::Result<Vec<u32>> {
+        let all = migrations();
+        let applied = self.applied().await?;
+        let mut versions = Vec::new();
+        for migration in pending(&all, &applied)? {
+            for statement in &migration.statements {
+                self.client.query(statement).execute().await?;
+            }
+            self.client
+                .query("INSERT INTO schema_migrations (version, name, checksum) VALUES (?, ?, ?)")
+                .bind(migration.version)
+                .bind(migration.name)
+                .bind(migration.checksum())
+                .execute()
+                .await?;
+            info!(
+                version = migration.version,
+                name = migration.name,
+                "Applied ClickHouse migration"
+            );
+            versions.push(migration.version);
+        }
+        Ok(versions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_pending_migrations() {
+        let all = migrations();
+        let versions: Vec<u32> = all.iter().map(|m| m.version).collect();
+        assert_eq!(versions, (1..=all.len() as u32).collect::<Vec<_>>());
+        assert!(all.iter().all(|m| !m.statements.is_empty()));
+        assert_eq!(all[2].statements.len(), 12);
+
+        let record = |m: &Migration| AppliedMigration {
+            version: m.version,
+            name: m.name.to_string(),
+            checksum: m.checksum(),
+        };
+        assert_eq!(pending(&all, &[]).unwrap().len(), all.len());
+        let remaining = pending(&all, &[record(&all[0])]).unwrap();
+        assert_eq!(remaining[0].version, 2);
+
+        let mut edited = record(&all[0]);
+        edited.checksum ^= 1;
+        assert!(pending(&all, &[edited]).is_err());
+        let newer = AppliedMigration {
+            version: 99,
+            name: "future".into(),
+            checksum: 0,
+        };
+        assert!(pending(&all, &[newer]).is_err());
+    }
+}
@@ -0,0 +1,451 @@
+//! Pre-aggregated hourly and daily rollups of `analytics_events` by campaign,
+//! channel and creative.
+//!
+//! Each rollup is an `AggregatingMergeTree` table fed by a materialized view,
+//! so dashboards read a few rows per period instead of scanning raw events.
+//! Event types map to metric columns through [`METRIC_EVENTS`], which both
+//! the ClickHouse views and the in-memory store use.
+
+use campaign_core::types::{AnalyticsEvent, EventType};
+use chrono::{DateTime, DurationRound, NaiveTime, TimeDelta, Utc};
+use serde::{Deserialize, Serialize};
+
+/// Dimension a rollup is keyed by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupDimension {
+    Campaign,
+    Channel,
+    Creative,
+}
+
+impl RollupDimension {
+    pub const ALL: [RollupDimension; 3] = [Self::Campaign, Self::Channel, Self::Creative];
+
+    /// Column of `analytics_events` (and of the rollup) holding the key.
+    pub fn column(&self) -> &'static str {
+        match self {
+            Self::Campaign => "campaign_id",
+            Self::Channel => "channel",
+            Self::Creative => "creative_id",
+        }
+    }
+
+    pub fn key_of<'a>(&self, event: &'a AnalyticsEvent) -> Option<&'a str> {
+        match self {
+            Self::Campaign => event.campaign_id.as_deref(),
+            Self::Channel => event.channel.as_deref(),
+            Self::Creative => event.creative_id.as_deref(),
+        }
+    }
+}
+
+/// Time bucket of a rollup.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RollupGrain {
+    Hourly,
+    Daily,
+}
+
+impl RollupGrain {
+    pub const ALL: [RollupGrain; 2] = [Self::Hourly, Self::Daily];
+
+    pub fn truncate(&self, at: DateTime<Utc>) -> DateTime<Utc> {
+        match self {
+            Self::Hourly => at.duration_trunc(TimeDelta::hours(1)).unwrap_or(at),
+            Self::Daily => at.date_naive().and_time(NaiveTime::MIN).and_utc(),
+        }
+    }
+
+    fn sql_truncate(&self) -> &'static str {
+        match self {
+            Self::Hourly => "toStartOfHour(timestamp)",
+            Self::Daily => "toDateTime(toStartOfDay(timestamp))",
+        }
+    }
+
+    fn ttl_days(&self) -> u32 {
+        match self {
+            Self::Hourly => 90,
+            Self::Daily => 730,
+        }
+    }
+
+    fn suffix(&self) -> &'static str {
+        match self {
+            Self::Hourly => "hourly",
+            Self::Daily => "daily",
+        }
+    }
+}
+
+/// Name of the rollup table, e.g. `campaign_rollup_hourly`.
+pub fn rollup_table(dimension: RollupDimension, grain: RollupGrain) -> String {
+    let prefix = match dimension {
+        RollupDimension::Campaign => "campaign",
+        RollupDimension::Channel => "channel",
+        RollupDimension::Creative => "creative",
+    };
+    format!("{prefix}_rollup_{}", grain.suffix())
+}
+
+/// Counter columns and the event types that increment them.
+pub const METRIC_EVENTS: &[(&str, &[EventType])] = &[
+    ("requests", &[EventType::BidRequest]),
+    ("bids", &[EventType::BidResponse, EventType::DspBidSent]),
+    ("wins", &[EventType::DspBidWon]),
+    (
+        "impressions",
+        &[EventType::Impression, EventType::DcoImpression],
+    ),
+    (
+        "clicks",
+        &[
+            EventType::Click,
+            EventType::DcoClick,
+            EventType::LoyaltyOfferClicked,
+        ],
+    ),
+    (
+        "conversions",
+        &[
+            EventType::Conversion,
+            EventType::DcoConversion,
+            EventType::ExperimentConversion,
+        ],
+    ),
+    (
+        "sends",
+        &[EventType::ActivationSent, EventType::TemplateDelivered],
+    ),
+    ("deliveries", &[EventType::ActivationDelivered]),
+    ("failures", &[EventType::ActivationFailed, EventType::Error]),
+];
+
+/// Event types whose users count towards `unique_impressions`.
+pub(crate) const IMPRESSION_EVENTS: &[EventType] =
+    &[EventType::Impression, EventType::DcoImpression];
+/// Event types whose users count towards `unique_clicks`.
+pub(crate) const CLICK_EVENTS: &[EventType] = &[
+    EventType::Click,
+    EventType::DcoClick,
+    EventType::LoyaltyOfferClicked,
+];
+
+/// Name an event type has in `analytics_events.event_type`.
+pub fn event_type_name(event_type: EventType) -> String {
+    serde_json::to_value(event_type)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_default()
+}
+
+fn in_list(types: &[EventType]) -> String {
+    let names: Vec<String> = types
+        .iter()
+        .map(|t| format!("'{}'", event_type_name(*t)))
+        .collect();
+    format!("event_type IN ({})", names.join(", "))
+}
+
+/// Additive metrics for one key and period. Unique counts are exact per
+/// rollup row; summing them across rows overcounts users seen in several.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct RollupMetrics {
+    pub requests: u64,
+    pub bids: u64,
+    pub wins: u64,
+    pub impressions: u64,
+    pub clicks: u64,
+    pub conversions: u64,
+    pub sends: u64,
+    pub deliveries: u64,
+    pub failures: u64,
+    /// Sum of win prices.
+    pub spend: f64,
+    /// Sum of bid prices.
+    pub bid_value: f64,
+    pub latency_us_sum: u64,
+    pub latency_count: u64,
+    pub unique_impressions: u64,
+    pub unique_clicks: u64,
+}
+
+impl RollupMetrics {
+    /// Count one event's contribution (uniques are handled by the caller).
+    pub fn record(&mut self, event: &AnalyticsEvent) {
+        for (column, types) in METRIC_EVENTS {
+            if types.contains(&event.event_type) {
+                *self.counter_mut(column) += 1;
+            }
+        }
+        self.spend += event.win_price.unwrap_or(0.0);
+        self.bid_value += event.bid_price.unwrap_or(0.0);
+        if let Some(latency) = event.total_latency_us {
+            self.latency_us_sum += latency;
+            self.latency_count += 1;
+        }
+    }
+
+    fn counter_mut(&mut self, column: &str) -> &mut u64 {
+        match column {
+            "requests" => &mut self.requests,
+            "bids" => &mut self.bids,
+            "wins" => &mut self.wins,
+            "impressions" => &mut self.impressions,
+            "clicks" => &mut self.clicks,
+            "conversions" => &mut self.conversions,
+            "sends" => &mut self.sends,
+            "deliveries" => &mut self.deliveries,
+            _ => &mut self.failures,
+        }
+    }
+
+    pub fn merge(&mut self, other: &RollupMetrics) {
+        self.requests += other.requests;
+        self.bids += other.bids;
+        self.wins += other.wins;
+        self.impressions += other.impressions;
+        self.clicks += other.clicks;
+        self.conversions += other.conversions;
+        self.sends += other.sends;
+        self.deliveries += other.deliveries;
+        self.failures += other.failures;
+        self.spend += other.spend;
+        self.bid_value += other.bid_value;
+        self.latency_us_sum += other.latency_us_sum;
+        self.latency_count += other.latency_count;
+        self.unique_impressions += other.unique_impressions;
+        self.unique_clicks += other.unique_clicks;
+    }
+
+    fn ratio(numerator: u64, denominator: u64) -> f64 {
+        if denominator > 0 {
+            numerator as f64 / denominator as f64
+        } else {
+            0.0
+        }
+    }
+
+    /// Clicks per impression.
+    pub fn ctr(&self) -> f64 {
+        Self::ratio(self.clicks, self.impressions)
+    }
+
+    /// Conversions per click.
+    pub fn conversion_rate(&self) -> f64 {
+        Self::ratio(self.conversions, self.clicks)
+    }
+
+    /// Wins per bid.
+    pub fn win_rate(&self) -> f64 {
+        Self::ratio(self.wins, self.bids)
+    }
+
+    /// Deliveries per send.
+    pub fn delivery_rate(&self) -> f64 {
+        Self::ratio(self.deliveries, self.sends)
+    }
+
+    /// Cost per thousand impressions.
+    pub fn cpm(&self) -> f64 {
+        if self.impressions > 0 {
+            self.spend / self.impressions as f64 * 1000.0
+        } else {
+            0.0
+        }
+    }
+
+    pub fn avg_latency_us(&self) -> f64 {
+        if self.latency_count > 0 {
+            self.latency_us_sum as f64 / self.latency_count as f64
+        } else {
+            0.0
+        }
+    }
+}
+
+/// One rollup row: a key's metrics for a period (or a whole range).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RollupRow {
+    pub period: DateTime<Utc>,
+    pub key: String,
+    pub metrics: RollupMetrics,
+}
+
+/// Typed request for rollup rows in `[from, to)`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RollupQuery {
+    pub dimension: RollupDimension,
+    pub grain: RollupGrain,
+    pub from: DateTime<Utc>,
+    pub to: DateTime<Utc>,
+    /// Restrict to these keys (empty = all).
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Merge all periods into one row per key (with `period = from`), so
+    /// unique counts are exact over the whole range.
+    #[serde(default)]
+    pub totals: bool,
+}
+
+impl RollupQuery {
+    pub fn new(
+        dimension: RollupDimension,
+        grain: RollupGrain,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            dimension,
+            grain,
+            from,
+            to,
+            keys: Vec::new(),
+            totals: false,
+        }
+    }
+
+    pub fn with_keys<I, S>(mut self, keys: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.keys.extend(keys.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn totals(mut self) -> Self {
+        self.totals = true;
+        self
+    }
+
+    /// ClickHouse SQL with `?` placeholders bound, in order, to `from` and
+    /// `to` as Unix seconds and, when keys are given, the key array.
+    pub fn to_sql(&self) -> String {
+        let table = rollup_table(self.dimension, self.grain);
+        let key = self.dimension.column();
+        let period = if self.totals {
+            "toUInt32(toUnixTimestamp(min(period)))".to_string()
+        } else {
+            "toUInt32(toUnixTimestamp(period))".to_string()
+        };
+        let mut columns = vec![format!("{period} AS period_ts"), format!("{key} AS key")];
+        for (column, _) in METRIC_EVENTS {
+            columns.push(format!("sum({column}) AS {column}"));
+        }
+        for column in ["spend", "bid_value", "latency_us_sum", "latency_count"] {
+            columns.push(format!("sum({column}) AS {column}"));
+        }
+        for column in ["unique_impressions", "unique_clicks"] {
+            columns.push(format!("uniqIfMerge({column}) AS {column}"));
+        }
+
+        let mut sql = format!(
+            "SELECT {} FROM {table} WHERE period >= toDateTime(?) AND period < toDateTime(?)",
+            columns.join(", ")
+        );
+        if !self.keys.is_empty() {
+            sql.push_str(&format!(" AND has(?, {key})"));
+        }
+        if self.totals {
+            sql.push_str(&format!(" GROUP BY {key} ORDER BY {key}"));
+        } else {
+            sql.push_str(&format!(" GROUP BY period, {key} ORDER BY period, {key}"));
+        }
+        sql
+    }
+}
+
+/// `SELECT` aggregating raw events into a rollup's columns, shared by the
+/// materialized view and backfills.
+pub fn rollup_select(dimension: RollupDimension, grain: RollupGrain) -> String {
+    let key = dimension.column();
+    let mut columns = vec![
+        format!("{} AS period", grain.sql_truncate()),
+        format!("assumeNotNull({key}) AS {key}"),
+    ];
+    for (column, types) in METRIC_EVENTS {
+        columns.push(format!("countIf({}) AS {column}", in_list(types)));
+    }
+    columns.extend([
+        "sum(ifNull(win_price, 0)) AS spend".to_string(),
+        "sum(ifNull(bid_price, 0)) AS bid_value".to_string(),
+        "sum(ifNull(total_latency_us, 0)) AS latency_us_sum".to_string(),
+        "countIf(isNotNull(total_latency_us)) AS latency_count".to_string(),
+        format!(
+            "uniqIfState(ifNull(user_id, ''), toUInt8(isNotNull(user_id) AND {})) AS unique_impressions",
+            in_list(IMPRESSION_EVENTS)
+        ),
+        format!(
+            "uniqIfState(ifNull(user_id, ''), toUInt8(isNotNull(user_id) AND {})) AS unique_clicks",
+            in_list(CLICK_EVENTS)
+        ),
+    ]);
+    format!(
+        "SELECT {} FROM analytics_events WHERE isNotNull({key}) GROUP BY period, {key}",
+        columns.join(", ")
+    )
+}
+
+/// DDL creating a rollup table and the materialized view that feeds it.
+pub fn rollup_ddl(dimension: RollupDimension, grain: RollupGrain) -> Vec<String> {
+    let table = rollup_table(dimension, grain);
+    let key = dimension.column();
+    let mut columns = vec!["period DateTime".to_string(), format!("{key} String")];
+    for (column, _) in METRIC_EVENTS {
+        columns.push(format!("{column} SimpleAggregateFunction(sum, UInt64)"));
+    }
+    columns.extend([
+        "spend SimpleAggregateFunction(sum, Float64)".to_string(),
+        "bid_value SimpleAggregateFunction(sum, Float64)".to_string(),
+        "latency_us_sum SimpleAggregateFunction(sum, UInt64)".to_string(),
+        "latency_count SimpleAggregateFunction(sum, UInt64)".to_string(),
+        "unique_impressions AggregateFunction(uniqIf, String, UInt8)".to_string(),
+        "unique_clicks AggregateFunction(uniqIf, String, UInt8)".to_string(),
+    ]);
+
+    vec![
+        format!(
+            "CREATE TABLE IF NOT EXISTS {table} ({}) ENGINE = AggregatingMergeTree() \
+             PARTITION BY toYYYYMM(period) ORDER BY ({key}, period) \
+             TTL period + INTERVAL {} DAY",
+            columns.join(", "),
+            grain.ttl_days()
+        ),
+        format!(
+            "CREATE MATERIALIZED VIEW IF NOT EXISTS {table}_mv TO {table} AS {}",
+            rollup_select(dimension, grain)
+        ),
+    ]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_rollup_sql() {
+        let ddl = rollup_ddl(RollupDimension::Channel, RollupGrain::Daily);
+        assert!(ddl[0].starts_with("CREATE TABLE IF NOT EXISTS channel_rollup_daily ("));
+        assert!(ddl[0].contains("ORDER BY (channel, period)"));
+        assert!(ddl[1].contains("TO channel_rollup_daily AS SELECT toDateTime(toStartOfDay"));
+        assert!(ddl[1]
+            .contains("countIf(event_type IN ('impression', 'dco_impression')) AS impressions"));
+
+        let query = RollupQuery::new(
+            RollupDimension::Campaign,
+            RollupGrain::Hourly,
+            Utc::now(),
+            Utc::now(),
+        )
+        .with_keys(["c1"])
+        .totals();
+        let sql = query.to_sql();
+        assert!(sql.contains("FROM campaign_rollup_hourly WHERE"));
+        assert!(sql.contains("has(?, campaign_id)"));
+        assert!(sql.ends_with("GROUP BY campaign_id ORDER BY campaign_id"));
+        assert_eq!(sql.matches('?').count(), 3);
+    }
+}
@@ -0,0 +1,874 @@
+//! Write-ahead spool for analytics events that ClickHouse cannot take yet.
+//!
+//! Events are appended to numbered segment files (`{seq:020}.seg`). Each
+//! record is a little-endian `u32` payload length, a `u32` CRC-32 of the
+//! payload and the event as JSON. A cursor file records how far replay has
+//! got; it is replaced atomically on every commit, so a crash replays at
+//! most the last uncommitted batch. Segments behind the cursor are deleted.
+//!
+//! On open, each segment is checked record by record and truncated at the
+//! first torn or corrupt record, which is what a crash mid-append leaves.
+
+use campaign_core::config::{ClickHouseConfig, SpoolOverflowPolicy};
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::collections::VecDeque;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tracing::warn;
+
+const HEADER_BYTES: u64 = 8;
+const SEGMENT_EXT: &str = "seg";
+const CURSOR_FILE: &str = "cursor.json";
+
+/// Spool location and limits.
+#[derive(Debug, Clone)]
+pub struct SpoolConfig {
+    pub dir: PathBuf,
+    /// A new segment is started once the active one reaches this size.
+    pub segment_bytes: u64,
+    /// Upper bound on all segments together.
+    pub max_bytes: u64,
+    pub overflow: SpoolOverflowPolicy,
+}
+
+impl SpoolConfig {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        let defaults = ClickHouseConfig::default();
+        Self {
+            dir: dir.into(),
+            segment_bytes: defaults.spool_segment_bytes,
+            max_bytes: defaults.spool_max_bytes,
+            overflow: defaults.spool_overflow,
+        }
+    }
+
+    /// The spool configured for the analytics logger, if any.
+    pub fn from_clickhouse(config: &ClickHouseConfig) -> Option<Self> {
+        config.spool_dir.as_ref().map(|dir| Self {
+            dir: dir.into(),
+            segment_bytes: config.spool_segment_bytes,
+            max_bytes: config.spool_max_bytes,
+            overflow: config.spool_overflow,
+        })
+    }
+
+    pub fn with_segment_bytes(mut self, bytes: u64) -> Self {
+        self.segment_bytes = bytes;
+        self
+    }
+
+    pub fn with_max_bytes(mut self, bytes: u64) -> Self {
+        self.max_bytes = bytes;
+        self
+    }
+
+    pub fn with_overflow(mut self, overflow: SpoolOverflowPolicy) -> Self {
+        self.overflow = overflow;
+        self
+    }
+}
+
+/// Result of [`Spool::append`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AppendOutcome {
+    Spooled,
+    /// Spooled after deleting the oldest segments; `records` unreplayed
+    /// events were lost.
+    Evicted {
+        records: u64,
+    },
+    /// The spool is full and the policy keeps older events.
+    Rejected,
+}
+
+/// A point in the spool: segment, byte offset and record index within it.
+/// Positions order by segment, then by place within the segment.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct SpoolPosition {
+    pub segment: u64,
+    pub offset: u64,
+    pub record: u64,
+}
+
+/// Events read from the spool, to be passed to [`Spool::commit`] once they
+/// are stored elsewhere.
+#[derive(Debug, Clone, Default)]
+pub struct SpoolBatch {
+    pub events: Vec<AnalyticsEvent>,
+    /// The cursor the batch was read from.
+    pub start: SpoolPosition,
+    /// Where the next batch starts.
+    pub end: SpoolPosition,
+    /// Records consumed, including unreadable ones that were skipped.
+    pub records: u64,
+}
+
+impl SpoolBatch {
+    pub fn is_empty(&self) -> bool {
+        self.records == 0
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct SpoolStats {
+    pub segments: usize,
+    pub bytes: u64,
+    pub pending_records: u64,
+    /// Timestamp of the oldest event not yet replayed.
+    pub oldest_pending: Option<DateTime<Utc>>,
+    /// How far replay is behind: now minus `oldest_pending`.
+    pub replay_lag_seconds: f64,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Segment {
+    seq: u64,
+    bytes: u64,
+    records: u64,
+}
+
+struct Inner {
+    segments: VecDeque<Segment>,
+    /// Append handle for the last segment.
+    active: Option<File>,
+    cursor: SpoolPosition,
+    pending: u64,
+    bytes: u64,
+    oldest_pending: Option<DateTime<Utc>>,
+}
+
+/// Durable FIFO of analytics events on local disk.
+pub struct Spool {
+    config: SpoolConfig,
+    inner: Mutex<Inner>,
+    /// Fails the next append after writing this many bytes of its record.
+    #[cfg(test)]
+    short_write: Mutex<Option<usize>>,
+}
+
+impl Spool {
+    /// Open (or create) the spool in `config.dir`, recovering from a crash.
+    pub fn open(config: SpoolConfig) -> anyhow::Result<Self> {
+        fs::create_dir_all(&config.dir)?;
+
+        let mut seqs = Vec::new();
+        for entry in fs::read_dir(&config.dir)? {
+            let path = entry?.path();
+            if path.extension().and_then(|e| e.to_str()) != Some(SEGMENT_EXT) {
+                continue;
+            }
+            if let Some(seq) = path
+                .file_stem()
+                .and_then(|s| s.to_str())
+                .and_then(|s| s.parse::<u64>().ok())
+            {
+                seqs.push(seq);
+            }
+        }
+        seqs.sort_unstable();
+
+        let mut segments = VecDeque::with_capacity(seqs.len());
+        for seq in seqs {
+            segments.push_back(recover_segment(&config, seq)?);
+        }
+
+        let cursor_path = config.dir.join(CURSOR_FILE);
+        let mut cursor: SpoolPosition = match fs::read(&cursor_path) {
+            Ok(bytes) => serde_json::from_slice(&bytes).unwrap_or_else(|e| {
+                warn!(error = %e, "Unreadable analytics spool cursor; replaying from the start");
+                SpoolPosition::default()
+            }),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => SpoolPosition::default(),
+            Err(e) => return Err(e.into()),
+        };
+        match segments.iter().find(|s| s.seq >= cursor.segment) {
+            Some(segment) if segment.seq > cursor.segment => {
+                cursor = SpoolPosition {
+                    segment: segment.seq,
+                    ..Default::default()
+                };
+            }
+            Some(segment) if cursor.offset > segment.bytes || cursor.record > segment.records => {
+                // The segment was truncated past the cursor.
+                cursor.offset = segment.bytes;
+                cursor.record = segment.records;
+            }
+            Some(_) => {}
+            None => {
+                cursor = SpoolPosition {
+                    segment: segments.back().map_or(0, |s| s.seq + 1),
+                    ..Default::default()
+                };
+            }
+        }
+
+        let pending = segments
+            .iter()
+            .filter(|s| s.seq >= cursor.segment)
+            .map(|s| s.records)
+            .sum::<u64>()
+            - cursor.record;
+        let bytes = segments.iter().map(|s| s.bytes).sum();
+
+        let spool = Self {
+            config,
+            inner: Mutex::new(Inner {
+                segments,
+                active: None,
+                cursor,
+                pending,
+                bytes,
+                oldest_pending: None,
+            }),
+            #[cfg(test)]
+            short_write: Mutex::new(None),
+        };
+        {
+            let mut inner = spool.lock();
+            spool.refresh_oldest(&mut inner)?;
+        }
+        Ok(spool)
+    }
+
+    pub fn config(&self) -> &SpoolConfig {
+        &self.config
+    }
+
+    /// Number of events waiting to be replayed.
+    pub fn pending(&self) -> u64 {
+        self.lock().pending
+    }
+
+    /// Append one event, applying the overflow policy if the spool is full.
+    pub fn append(&self, event: &AnalyticsEvent) -> anyhow::Result<AppendOutcome> {
+        let payload = serde_json::to_vec(event)?;
+        let len = HEADER_BYTES + payload.len() as u64;
+        if len > self.config.max_bytes {
+            return Ok(AppendOutcome::Rejected);
+        }
+
+        let mut inner = self.lock();
+        let mut evicted = 0;
+        if inner.bytes + len > self.config.max_bytes {
+            match self.config.overflow {
+                SpoolOverflowPolicy::DropNewest => return Ok(AppendOutcome::Rejected),
+                SpoolOverflowPolicy::DropOldest => {
+                    while inner.bytes + len > self.config.max_bytes {
+                        if inner.segments.len() == 1 {
+                            // Only the active segment is left; start a new
+                            // one so the old can go.
+                            self.roll(&mut inner)?;
+                        }
+                        evicted += self.evict_oldest(&mut inner)?;
+                    }
+                    self.refresh_oldest(&mut inner)?;
+                }
+            }
+        }
+
+        let needs_roll = match inner.segments.back() {
+            None => true,
+            Some(active) => active.bytes > 0 && active.bytes + len > self.config.segment_bytes,
+        };
+        if needs_roll {
+            self.roll(&mut inner)?;
+        } else if inner.active.is_none() {
+            let segment = *inner.segments.back().expect("checked above");
+            let file = OpenOptions::new()
+                .append(true)
+                .open(self.segment_path(segment.seq))?;
+            // Drop a torn record a failed append could not cut off.
+            if file.metadata()?.len() > segment.bytes {
+                file.set_len(segment.bytes)?;
+            }
+            inner.active = Some(file);
+        }
+
+        let mut record = Vec::with_capacity(len as usize);
+        record.extend_from_slice(&(payload.len() as u32).to_le_bytes());
+        record.extend_from_slice(&crc32fast::hash(&payload).to_le_bytes());
+        record.extend_from_slice(&payload);
+        if let Err(e) = self.write_record(&mut inner, &record) {
+            self.discard_torn_write(&mut inner);
+            return Err(e.into());
+        }
+
+        let active = inner.segments.back_mut().expect("active segment exists");
+        active.bytes += len;
+        active.records += 1;
+        inner.bytes += len;
+        inner.pending += 1;
+        if inner.oldest_pending.is_none() {
+            inner.oldest_pending = Some(event.timestamp);
+        }
+
+        Ok(if evicted > 0 {
+            AppendOutcome::Evicted { records: evicted }
+        } else {
+            AppendOutcome::Spooled
+        })
+    }
+
+    /// Read up to `max` events from the cursor without consuming them.
+    pub fn read_batch(&self, max: usize) -> anyhow::Result<SpoolBatch> {
+        let inner = self.lock();
+        self.read_from(&inner, inner.cursor, max)
+    }
+
+    /// Mark a batch from [`Spool::read_batch`] as stored and delete the
+    /// segments it finished. If `DropOldest` evicted part of the batch since
+    /// it was read, only the records still ahead of the cursor are consumed;
+    /// the cursor never moves backwards.
+    pub fn commit(&self, batch: &SpoolBatch) -> anyhow::Result<()> {
+        if batch.is_empty() {
+            return Ok(());
+        }
+        let mut inner = self.lock();
+        if batch.end <= inner.cursor {
+            // Everything in the batch was evicted (and counted) already.
+            return Ok(());
+        }
+        let consumed = if inner.cursor == batch.start {
+            batch.records
+        } else {
+            records_between(&inner, inner.cursor, batch.end)
+        };
+        inner.cursor = batch.end;
+        inner.pending = inner.pending.saturating_sub(consumed);
+        self.write_cursor(inner.cursor)?;
+
+        while let Some(oldest) = inner.segments.front().copied() {
+            if oldest.seq >= inner.cursor.segment {
+                break;
+            }
+            inner.segments.pop_front();
+            inner.bytes -= oldest.bytes;
+            remove_if_exists(&self.segment_path(oldest.seq))?;
+        }
+        self.refresh_oldest(&mut inner)
+    }
+
+    /// Flush appended records to stable storage.
+    pub fn sync(&self) -> anyhow::Result<()> {
+        let inner = self.lock();
+        if let Some(active) = &inner.active {
+            active.sync_data()?;
+        }
+        Ok(())
+    }
+
+    pub fn stats(&self) -> SpoolStats {
+        let inner = self.lock();
+        SpoolStats {
+            segments: inner.segments.len(),
+            bytes: inner.bytes,
+            pending_records: inner.pending,
+            oldest_pending: inner.oldest_pending,
+            replay_lag_seconds: inner.oldest_pending.map_or(0.0, |oldest| {
+                (Utc::now() - oldest).num_milliseconds().max(0) as f64 / 1000.0
+            }),
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, Inner> {
+        self.inner.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    fn segment_path(&self, seq: u64) -> PathBuf {
+        segment_path(&self.config, seq)
+    }
+
+    fn write_record(&self, inner: &mut Inner, record: &[u8]) -> io::Result<()> {
+        let file = inner.active.as_mut().expect("active segment opened");
+        #[cfg(test)]
+        if let Some(written) = self.short_write.lock().unwrap().take() {
+            file.write_all(&record[..written])?;
+            return Err(io::Error::other("injected short write"));
+        }
+        file.write_all(record)
+    }
+
+    /// Cut a partly written record off the active segment so the next
+    /// append starts where the accounted records end; otherwise replay
+    /// would hit the torn bytes and recovery would truncate every record
+    /// after them. If the file cannot be truncated, the handle is dropped
+    /// and the next append cuts it when it reopens the segment.
+    fn discard_torn_write(&self, inner: &mut Inner) {
+        let accounted = inner.segments.back().map_or(0, |s| s.bytes);
+        let Some(file) = &inner.active else {
+            return;
+        };
+        if let Err(e) = file.set_len(accounted) {
+            warn!(error = %e, "Could not truncate torn analytics spool record");
+            inner.active = None;
+        }
+    }
+
+    fn roll(&self, inner: &mut Inner) -> anyhow::Result<()> {
+        if let Some(active) = inner.active.take() {
+            active.sync_data()?;
+        }
+        let seq = inner
+            .segments
+            .back()
+            .map_or(inner.cursor.segment, |s| s.seq + 1);
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(self.segment_path(seq))?;
+        inner.segments.push_back(Segment {
+            seq,
+            bytes: 0,
+            records: 0,
+        });
+        inner.active = Some(file);
+        Ok(())
+    }
+
+    /// Delete the oldest segment, returning how many unreplayed events it held.
+    fn evict_oldest(&self, inner: &mut Inner) -> anyhow::Result<u64> {
+        let Some(oldest) = inner.segments.pop_front() else {
+            return Ok(0);
+        };
+        inner.bytes -= oldest.bytes;
+        remove_if_exists(&self.segment_path(oldest.seq))?;
+
+        let lost = match inner.cursor.segment.cmp(&oldest.seq) {
+            Ordering::Less => oldest.records,
+            Ordering::Equal => oldest.records - inner.cursor.record,
+            Ordering::Greater => 0,
+        };
+        if inner.cursor.segment <= oldest.seq {
+            inner.cursor = SpoolPosition {
+                segment: oldest.seq + 1,
+                ..Default::default()
+            };
+            self.write_cursor(inner.cursor)?;
+        }
+        inner.pending -= lost;
+        Ok(lost)
+    }
+
+    fn refresh_oldest(&self, inner: &mut Inner) -> anyhow::Result<()> {
+        inner.oldest_pending = if inner.pending == 0 {
+            None
+        } else {
+            self.read_from(inner, inner.cursor, 1)?
+                .events
+                .first()
+                .map(|e| e.timestamp)
+        };
+        Ok(())
+    }
+
+    fn read_from(
+        &self,
+        inner: &Inner,
+        start: SpoolPosition,
+        max: usize,
+    ) -> anyhow::Result<SpoolBatch> {
+        let mut batch = SpoolBatch {
+            start,
+            end: start,
+            ..Default::default()
+        };
+        let mut pos = start;
+
+        for segment in inner.segments.iter().filter(|s| s.seq >= start.segment) {
+            if segment.seq > pos.segment {
+                pos = SpoolPosition {
+                    segment: segment.seq,
+                    ..Default::default()
+                };
+            }
+            if pos.offset < segment.bytes && batch.events.len() < max {
+                let mut file = File::open(self.segment_path(segment.seq))?;
+                file.seek(SeekFrom::Start(pos.offset))?;
+                let mut reader = BufReader::new(file);
+                while pos.offset < segment.bytes && batch.events.len() < max {
+                    match read_record(&mut reader, segment.bytes - pos.offset)? {
+                        Some(payload) => {
+                            pos.offset += HEADER_BYTES + payload.len() as u64;
+                            pos.record += 1;
+                            batch.records += 1;
+                            match serde_json::from_slice(&payload) {
+                                Ok(event) => batch.events.push(event),
+                                Err(e) => {
+                                    metrics::counter!("analytics.spool.corrupt").increment(1);
+                                    warn!(error = %e, segment = segment.seq, "Skipping undecodable spooled event");
+                                }
+                            }
+                        }
+                        None => {
+                            // Damaged since it was written; the rest of the
+                            // segment cannot be framed.
+                            metrics::counter!("analytics.spool.corrupt").increment(1);
+                            warn!(
+                                segment = segment.seq,
+                                "Skipping corrupt analytics spool segment"
+                            );
+                            batch.records += segment.records - pos.record;
+                            pos.offset = segment.bytes;
+                            pos.record = segment.records;
+                        }
+                    }
+                }
+            }
+            batch.end = pos;
+            if batch.events.len() >= max {
+                break;
+            }
+        }
+        Ok(batch)
+    }
+
+    fn write_cursor(&self, cursor: SpoolPosition) -> anyhow::Result<()> {
+        let path = self.config.dir.join(CURSOR_FILE);
+        let tmp = path.with_extension("tmp");
+        {
+            let mut file = File::create(&tmp)?;
+            file.write_all(&serde_json::to_vec(&cursor)?)?;
+            file.sync_data()?;
+        }
+        fs::rename(tmp, path)?;
+        Ok(())
+    }
+}
+
+/// Records from `from` up to (not including) `to`.
+fn records_between(inner: &Inner, from: SpoolPosition, to: SpoolPosition) -> u64 {
+    inner
+        .segments
+        .iter()
+        .filter(|s| s.seq >= from.segment && s.seq <= to.segment)
+        .map(|s| {
+            let first = if s.seq == from.segment {
+                from.record
+            } else {
+                0
+            };
+            let last = if s.seq == to.segment {
+                to.record
+            } else {
+                s.records
+            };
+            last.saturating_sub(first)
+        })
+        .sum()
+}
+
+fn segment_path(config: &SpoolConfig, seq: u64) -> PathBuf {
+    config.dir.join(format!("{seq:020}.{SEGMENT_EXT}"))
+}
+
+fn remove_if_exists(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path) {
+        Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+        _ => Ok(()),
+    }
+}
+
+/// Next record's payload, or `None` if the record is torn or fails its
+/// checksum.
+/// Read one record from a segment with `remaining` bytes left in it. A
+/// length prefix that claims more than that is corrupt, and is rejected
+/// before its payload is allocated.
+fn read_record(reader: &mut impl Read, remaining: u64) -> io::Result<Option<Vec<u8>>> {
+    let mut header = [0u8; HEADER_BYTES as usize];
+    match reader.read_exact(&mut header) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as usize;
+    let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+    if HEADER_BYTES + len as u64 > remaining {
+        return Ok(None);
+    }
+    let mut payload = vec![0u8; len];
+    match reader.read_exact(&mut payload) {
+        Ok(()) => {}
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(None),
+        Err(e) => return Err(e),
+    }
+    Ok((crc32fast::hash(&payload) == crc).then_some(payload))
+}
+
+/// Count a segment's valid records and truncate anything after them.
+fn recover_segment(config: &SpoolConfig, seq: u64) -> anyhow::Result<Segment> {
+    let path = segment_path(config, seq);
+    let file_len = fs::metadata(&path)?.len();
+    let mut reader = BufReader::new(File::open(&path)?);
+    let mut segment = Segment {
+        seq,
+        bytes: 0,
+        records: 0,
+    };
+    while segment.bytes < file_len {
+        let mut header = [0u8; HEADER_BYTES as usize];
+        if file_len - segment.bytes < HEADER_BYTES {
+            break;
+        }
+        reader.read_exact(&mut header)?;
+        let len = u32::from_le_bytes([header[0], header[1], header[2], header[3]]) as u64;
+        // A torn or corrupt length prefix can claim more than the file holds.
+        if segment.bytes + HEADER_BYTES + len > file_len {
+            break;
+        }
+        let mut payload = vec![0u8; len as usize];
+        reader.read_exact(&mut payload)?;
+        let crc = u32::from_le_bytes([header[4], header[5], header[6], header[7]]);
+        if crc32fast::hash(&payload) != crc {
+            break;
+        }
+        segment.bytes += HEADER_BYTES + len;
+        segment.records += 1;
+    }
+    if segment.bytes < file_len {
+        metrics::counter!("analytics.spool.truncated_bytes").increment(file_len - segment.bytes);
+        warn!(
+            segment = seq,
+            kept = segment.bytes,
+            dropped = file_len - segment.bytes,
+            "Truncated damaged analytics spool segment"
+        );
+        OpenOptions::new()
+            .write(true)
+            .open(&path)?
+            .set_len(segment.bytes)?;
+    }
+    Ok(segment)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_core::event_bus::make_event;
+    use campaign_core::types::EventType;
+    use chrono::TimeZone;
+    use uuid::Uuid;
+
+    fn temp_spool() -> SpoolConfig {
+        SpoolConfig::new(std::env::temp_dir().join(format!("spool-test-{}", Uuid::new_v4())))
+    }
+
+    fn event(n: usize) -> AnalyticsEvent {
+        let mut event = make_event(EventType::Impression, format!("req-{n}"), None, None);
+        // Fixed so every record has the same encoded length.
+        event.timestamp = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        event
+    }
+
+    fn request_ids(batch: &SpoolBatch) -> Vec<String> {
+        batch.events.iter().map(|e| e.request_id.clone()).collect()
+    }
+
+    fn record_len() -> u64 {
+        HEADER_BYTES + serde_json::to_vec(&event(0)).unwrap().len() as u64
+    }
+
+    #[test]
+    fn test_append_replay_in_order() {
+        let config = temp_spool().with_segment_bytes(record_len() * 2);
+        let dir = config.dir.clone();
+        let spool = Spool::open(config).unwrap();
+        for n in 0..5 {
+            assert_eq!(spool.append(&event(n)).unwrap(), AppendOutcome::Spooled);
+        }
+        let stats = spool.stats();
+        assert_eq!((stats.segments, stats.pending_records), (3, 5));
+        assert!(stats.oldest_pending.is_some());
+
+        let batch = spool.read_batch(3).unwrap();
+        assert_eq!(request_ids(&batch), ["req-0", "req-1", "req-2"]);
+        // Reading does not consume.
+        assert_eq!(spool.read_batch(3).unwrap().records, 3);
+        spool.commit(&batch).unwrap();
+        assert_eq!(spool.pending(), 2);
+        assert_eq!(spool.stats().segments, 2);
+
+        let rest = spool.read_batch(10).unwrap();
+        assert_eq!(request_ids(&rest), ["req-3", "req-4"]);
+        spool.commit(&rest).unwrap();
+        assert_eq!(spool.pending(), 0);
+        assert!(spool.read_batch(10).unwrap().is_empty());
+        assert_eq!(spool.stats().oldest_pending, None);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_reopen_recovers_cursor_and_torn_tail() {
+        let config = temp_spool();
+        let dir = config.dir.clone();
+        {
+            let spool = Spool::open(config.clone()).unwrap();
+            for n in 0..4 {
+                spool.append(&event(n)).unwrap();
+            }
+            let batch = spool.read_batch(1).unwrap();
+            spool.commit(&batch).unwrap();
+            spool.sync().unwrap();
+        }
+        // A crash mid-append leaves half a record behind.
+        let segment = segment_path(&config, 0);
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+
+        let spool = Spool::open(config.clone()).unwrap();
+        assert_eq!(spool.pending(), 3);
+        assert_eq!(fs::metadata(&segment).unwrap().len(), record_len() * 4);
+        spool.append(&event(4)).unwrap();
+        let batch = spool.read_batch(10).unwrap();
+        assert_eq!(request_ids(&batch), ["req-1", "req-2", "req-3", "req-4"]);
+
+        // A flipped payload byte fails the checksum and cuts the segment there.
+        drop(spool);
+        let mut bytes = fs::read(&segment).unwrap();
+        let third = (record_len() * 2 + HEADER_BYTES) as usize;
+        bytes[third] ^= 0xff;
+        fs::write(&segment, bytes).unwrap();
+        let spool = Spool::open(config).unwrap();
+        assert_eq!(request_ids(&spool.read_batch(10).unwrap()), ["req-1"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_read_record_rejects_lengths_past_the_segment() {
+        let payload = b"{}".repeat(8);
+        let mut record = (payload.len() as u32).to_le_bytes().to_vec();
+        record.extend(crc32fast::hash(&payload).to_le_bytes());
+        record.extend(&payload);
+        let len = record.len() as u64;
+
+        let read = |remaining| read_record(&mut io::Cursor::new(&record), remaining).unwrap();
+        assert_eq!(read(len), Some(payload.clone()));
+        assert_eq!(read(len - 1), None);
+
+        let mut huge = record.clone();
+        huge[..4].copy_from_slice(&u32::MAX.to_le_bytes());
+        assert_eq!(read_record(&mut io::Cursor::new(&huge), len).unwrap(), None);
+    }
+
+    #[test]
+    fn test_failed_append_does_not_tear_later_records() {
+        let config = temp_spool();
+        let dir = config.dir.clone();
+        let spool = Spool::open(config.clone()).unwrap();
+        spool.append(&event(0)).unwrap();
+        *spool.short_write.lock().unwrap() = Some(HEADER_BYTES as usize + 3);
+        assert!(spool.append(&event(1)).is_err());
+        let segment = segment_path(&config, 0);
+        assert_eq!(fs::metadata(&segment).unwrap().len(), record_len());
+
+        // Truncation failed: the segment is cut when it is next reopened.
+        spool.lock().active = None;
+        let mut file = OpenOptions::new().append(true).open(&segment).unwrap();
+        file.write_all(&[42, 0, 0, 0, 1, 2]).unwrap();
+        drop(file);
+
+        spool.append(&event(2)).unwrap();
+        spool.append(&event(3)).unwrap();
+        assert_eq!(spool.pending(), 3);
+        assert_eq!(
+            request_ids(&spool.read_batch(10).unwrap()),
+            ["req-0", "req-2", "req-3"]
+        );
+        spool.sync().unwrap();
+        drop(spool);
+
+        let spool = Spool::open(config).unwrap();
+        assert_eq!(
+            request_ids(&spool.read_batch(10).unwrap()),
+            ["req-0", "req-2", "req-3"]
+        );
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_overflow_policies() {
+        let len = record_len();
+        let config = temp_spool()
+            .with_segment_bytes(len * 2)
+            .with_max_bytes(len * 4);
+        let dir = config.dir.clone();
+
+        let newest = Spool::open(config.clone()).unwrap();
+        for n in 0..4 {
+            assert_eq!(newest.append(&event(n)).unwrap(), AppendOutcome::Spooled);
+        }
+        assert_eq!(newest.append(&event(4)).unwrap(), AppendOutcome::Rejected);
+        assert_eq!(newest.pending(), 4);
+        drop(newest);
+        fs::remove_dir_all(&dir).unwrap();
+
+        let oldest = Spool::open(config.with_overflow(SpoolOverflowPolicy::DropOldest)).unwrap();
+        for n in 0..4 {
+            oldest.append(&event(n)).unwrap();
+        }
+        assert_eq!(
+            oldest.append(&event(4)).unwrap(),
+            AppendOutcome::Evicted { records: 2 }
+        );
+        assert_eq!(oldest.pending(), 3);
+        assert!(oldest.stats().bytes <= len * 4);
+        let batch = oldest.read_batch(10).unwrap();
+        assert_eq!(request_ids(&batch), ["req-2", "req-3", "req-4"]);
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_commit_after_eviction_does_not_rewind_or_double_count() {
+        let len = record_len();
+        let config = temp_spool()
+            .with_segment_bytes(len * 2)
+            .with_max_bytes(len * 4)
+            .with_overflow(SpoolOverflowPolicy::DropOldest);
+        let dir = config.dir.clone();
+        let spool = Spool::open(config).unwrap();
+        for n in 0..4 {
+            spool.append(&event(n)).unwrap();
+        }
+
+        // Replay reads three events, then an append evicts the first
+        // segment (two of them) before the batch is committed.
+        let batch = spool.read_batch(3).unwrap();
+        assert_eq!(request_ids(&batch), ["req-0", "req-1", "req-2"]);
+        assert_eq!(
+            spool.append(&event(4)).unwrap(),
+            AppendOutcome::Evicted { records: 2 }
+        );
+        assert_eq!(spool.pending(), 3);
+        spool.commit(&batch).unwrap();
+        // Only req-2 was still pending; req-3 and req-4 remain.
+        assert_eq!(spool.pending(), 2);
+        assert_eq!(
+            request_ids(&spool.read_batch(10).unwrap()),
+            ["req-3", "req-4"]
+        );
+
+        // A batch that was evicted entirely leaves the cursor alone.
+        let stale = spool.read_batch(1).unwrap();
+        for n in 5..9 {
+            spool.append(&event(n)).unwrap();
+        }
+        let cursor = spool.lock().cursor;
+        spool.commit(&stale).unwrap();
+        assert_eq!(spool.lock().cursor, cursor);
+        assert_eq!(spool.pending(), 3);
+        assert_eq!(
+            request_ids(&spool.read_batch(10).unwrap()),
+            ["req-6", "req-7", "req-8"]
+        );
+
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
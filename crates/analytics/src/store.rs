@@ -0,0 +1,321 @@
+//! Typed query layer over the analytics rollups.
+//!
+//! [`ClickHouseAnalyticsStore`] reads the rollup tables shared by every node,
+//! so dashboards agree across the cluster and survive restarts.
+//! [`InMemoryAnalyticsStore`] aggregates events the same way for tests and
+//! single-node deployments.
+
+use crate::rollups::{
+    rollup_select, rollup_table, RollupDimension, RollupGrain, RollupMetrics, RollupQuery,
+    RollupRow, CLICK_EVENTS, IMPRESSION_EVENTS,
+};
+use campaign_core::config::ClickHouseConfig;
+use campaign_core::event_bus::EventSink;
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::Deserialize;
+use std::collections::{BTreeMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+
+pub type RollupFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<Vec<RollupRow>>> + Send + 'a>>;
+
+/// Source of rollup rows for dashboards and reports.
+pub trait AnalyticsStore: Send + Sync {
+    fn rollups<'a>(&'a self, query: &'a RollupQuery) -> RollupFuture<'a>;
+}
+
+// ---------------------------------------------------------------------------
+// ClickHouse
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, clickhouse::Row, Deserialize)]
+struct RollupRecord {
+    period_ts: u32,
+    key: String,
+    requests: u64,
+    bids: u64,
+    wins: u64,
+    impressions: u64,
+    clicks: u64,
+    conversions: u64,
+    sends: u64,
+    deliveries: u64,
+    failures: u64,
+    spend: f64,
+    bid_value: f64,
+    latency_us_sum: u64,
+    latency_count: u64,
+    unique_impressions: u64,
+    unique_clicks: u64,
+}
+
+impl From<RollupRecord> for RollupRow {
+    fn from(r: RollupRecord) -> Self {
+        Self {
+            period: DateTime::from_timestamp(r.period_ts as i64, 0).unwrap_or_default(),
+            key: r.key,
+            metrics: RollupMetrics {
+                requests: r.requests,
+                bids: r.bids,
+                wins: r.wins,
+                impressions: r.impressions,
+                clicks: r.clicks,
+                conversions: r.conversions,
+                sends: r.sends,
+                deliveries: r.deliveries,
+                failures: r.failures,
+                spend: r.spend,
+                bid_value: r.bid_value,
+                latency_us_sum: r.latency_us_sum,
+                latency_count: r.latency_count,
+                unique_impressions: r.unique_impressions,
+                unique_clicks: r.unique_clicks,
+            },
+        }
+    }
+}
+
+/// Reads rollups from ClickHouse.
+pub struct ClickHouseAnalyticsStore {
+    client: clickhouse::Client,
+}
+
+impl ClickHouseAnalyticsStore {
+    pub fn new(config: &ClickHouseConfig) -> Self {
+        Self::with_client(
+            clickhouse::Client::default()
+                .with_url(&config.url)
+                .with_database(&config.database),
+        )
+    }
+
+    pub fn with_client(client: clickhouse::Client) -> Self {
+        Self { client }
+    }
+
+    /// Populate every rollup from raw events with `timestamp < before`.
+    /// Materialized views only see rows inserted after they were created, so
+    /// run this once after the rollup migration with `before` set to when
+    /// it ran; running it twice double-counts.
+    pub async fn backfill_rollups(&self, before: DateTime<Utc>) -> anyhow::Result<()> {
+        for dimension in RollupDimension::ALL {
+            for grain in RollupGrain::ALL {
+                let select = rollup_select(dimension, grain).replacen(
+                    " WHERE ",
+                    " WHERE timestamp < toDateTime64(?, 3) AND ",
+                    1,
+                );
+                self.client
+                    .query(&format!(
+                        "INSERT INTO {} {select}",
+                        rollup_table(dimension, grain)
+                    ))
+                    .bind(before.timestamp_millis() as f64 / 1000.0)
+                    .execute()
+                    .await?;
+            }
+        }
+        Ok(())
+    }
+}
+
+impl AnalyticsStore for ClickHouseAnalyticsStore {
+    fn rollups<'a>(&'a self, query: &'a RollupQuery) -> RollupFuture<'a> {
+        Box::pin(async move {
+            let mut request = self
+                .client
+                .query(&query.to_sql())
+                .bind(query.from.timestamp())
+                .bind(query.to.timestamp());
+            if !query.keys.is_empty() {
+                request = request.bind(&query.keys);
+            }
+            let records = request.fetch_all::<RollupRecord>().await?;
+            Ok(records
+                .into_iter()
+                .map(|record| {
+                    let mut row = RollupRow::from(record);
+                    if query.totals {
+                        row.period = query.from;
+                    }
+                    row
+                })
+                .collect())
+        })
+    }
+}
+
+// ---------------------------------------------------------------------------
+// In memory
+// ---------------------------------------------------------------------------
+
+#[derive(Default)]
+struct Bucket {
+    metrics: RollupMetrics,
+    impression_users: HashSet<String>,
+    click_users: HashSet<String>,
+}
+
+/// Aggregates events into rollups in process. Usable as the event bus sink.
+#[derive(Default)]
+pub struct InMemoryAnalyticsStore {
+    /// (dimension, grain, key, period) -> bucket
+    buckets: DashMap<(RollupDimension, RollupGrain, String, DateTime<Utc>), Bucket>,
+}
+
+impl InMemoryAnalyticsStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn ingest(&self, event: &AnalyticsEvent) {
+        for dimension in RollupDimension::ALL {
+            let Some(key) = dimension.key_of(event) else {
+                continue;
+            };
+            for grain in RollupGrain::ALL {
+                let period = grain.truncate(event.timestamp);
+                let mut bucket = self
+                    .buckets
+                    .entry((dimension, grain, key.to_string(), period))
+                    .or_default();
+                bucket.metrics.record(event);
+                if let Some(user) = &event.user_id {
+                    if IMPRESSION_EVENTS.contains(&event.event_type) {
+                        bucket.impression_users.insert(user.clone());
+                    }
+                    if CLICK_EVENTS.contains(&event.event_type) {
+                        bucket.click_users.insert(user.clone());
+                    }
+                }
+            }
+        }
+    }
+
+    /// Rows matching the query, computed synchronously.
+    pub fn query(&self, query: &RollupQuery) -> Vec<RollupRow> {
+        // (period, key) -> merged buckets
+        let mut rows: BTreeMap<(DateTime<Utc>, String), Bucket> = BTreeMap::new();
+        for entry in self.buckets.iter() {
+            let (dimension, grain, key, period) = entry.key();
+            if *dimension != query.dimension
+                || *grain != query.grain
+                || *period < query.from
+                || *period >= query.to
+                || (!query.keys.is_empty() && !query.keys.contains(key))
+            {
+                continue;
+            }
+            let row_period = if query.totals { query.from } else { *period };
+            let row = rows.entry((row_period, key.clone())).or_default();
+            row.metrics.merge(&entry.metrics);
+            row.impression_users
+                .extend(entry.impression_users.iter().cloned());
+            row.click_users.extend(entry.click_users.iter().cloned());
+        }
+
+        rows.into_iter()
+            .map(|((period, key), mut bucket)| {
+                bucket.metrics.unique_impressions = bucket.impression_users.len() as u64;
+                bucket.metrics.unique_clicks = bucket.click_users.len() as u64;
+                RollupRow {
+                    period,
+                    key,
+                    metrics: bucket.metrics,
+                }
+            })
+            .collect()
+    }
+}
+
+impl EventSink for InMemoryAnalyticsStore {
+    fn emit(&self, event: AnalyticsEvent) {
+        self.ingest(&event);
+    }
+}
+
+impl AnalyticsStore for InMemoryAnalyticsStore {
+    fn rollups<'a>(&'a self, query: &'a RollupQuery) -> RollupFuture<'a> {
+        Box::pin(async move { Ok(self.query(query)) })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_core::event_bus::make_event;
+    use campaign_core::types::EventType;
+    use chrono::TimeZone;
+
+    fn event(event_type: EventType, user: &str, hour: u32, channel: &str) -> AnalyticsEvent {
+        let mut event = make_event(event_type, "req", Some(user.to_string()), None)
+            .with_campaign("camp-1")
+            .with_channel(channel);
+        event.timestamp = Utc.with_ymd_and_hms(2024, 6, 1, hour, 15, 0).unwrap();
+        event
+    }
+
+    #[tokio::test]
+    async fn test_in_memory_rollups() {
+        let store = InMemoryAnalyticsStore::new();
+        for (event_type, user, hour, channel) in [
+            (EventType::Impression, "u1", 9, "email"),
+            (EventType::Impression, "u1", 10, "email"),
+            (EventType::Impression, "u2", 10, "push"),
+            (EventType::Click, "u1", 10, "email"),
+            (EventType::Conversion, "u1", 11, "email"),
+        ] {
+            store.emit(event(event_type, user, hour, channel));
+        }
+        let mut won = event(EventType::DspBidWon, "u3", 9, "display");
+        won.win_price = Some(2.5);
+        store.emit(won);
+
+        let day = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let next = Utc.with_ymd_and_hms(2024, 6, 2, 0, 0, 0).unwrap();
+        let hourly = store
+            .rollups(&RollupQuery::new(
+                RollupDimension::Campaign,
+                RollupGrain::Hourly,
+                day,
+                next,
+            ))
+            .await
+            .unwrap();
+        assert_eq!(hourly.len(), 3);
+        assert_eq!(hourly[1].metrics.impressions, 2);
+        assert_eq!(hourly[0].metrics.spend, 2.5);
+
+        let totals = store
+            .rollups(
+                &RollupQuery::new(RollupDimension::Campaign, RollupGrain::Hourly, day, next)
+                    .totals(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(totals.len(), 1);
+        let m = &totals[0].metrics;
+        assert_eq!((m.impressions, m.unique_impressions), (3, 2));
+        assert_eq!((m.clicks, m.conversions, m.wins), (1, 1, 1));
+        assert!((m.ctr() - 1.0 / 3.0).abs() < 1e-9);
+
+        let channels = store.query(
+            &RollupQuery::new(RollupDimension::Channel, RollupGrain::Daily, day, next)
+                .with_keys(["email", "push"]),
+        );
+        let keys: Vec<&str> = channels.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, ["email", "push"]);
+        assert_eq!(channels[0].metrics.impressions, 2);
+        assert!(store
+            .query(&RollupQuery::new(
+                RollupDimension::Creative,
+                RollupGrain::Daily,
+                day,
+                next
+            ))
+            .is_empty());
+    }
+}
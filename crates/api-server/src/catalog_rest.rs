@@ -0,0 +1,250 @@
+//! Catalog REST endpoints — catalog definitions and product feed ingestion
+//! into the catalog engine shared by rendering and recommendations.
+
+use crate::rest::ErrorResponse;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::Json;
+use campaign_personalization::catalog::{Catalog, CatalogField, IngestMode, IngestReport};
+use campaign_personalization::catalog_feed::FeedFormat;
+use campaign_personalization::CatalogEngine;
+use chrono::Utc;
+use serde::Deserialize;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Shared state for catalog endpoints.
+#[derive(Clone)]
+pub struct CatalogState {
+    pub engine: Arc<CatalogEngine>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CreateCatalogRequest {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    pub fields: Vec<CatalogField>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct FeedQuery {
+    pub format: FeedFormat,
+    pub mode: IngestMode,
+}
+
+fn catalog_error(
+    status: StatusCode,
+    error: &str,
+    message: String,
+) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            message,
+        }),
+    )
+}
+
+/// POST /v1/catalogs — Define a catalog that feeds can be loaded into.
+#[utoipa::path(
+    post,
+    path = "/v1/catalogs",
+    tag = "Catalogs",
+    request_body(content = Object, description = "Catalog name, description and fields"),
+    responses(
+        (status = 201, description = "The created catalog", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 409, description = "A catalog with this name exists", body = ErrorResponse),
+    )
+)]
+pub async fn handle_create_catalog(
+    State(state): State<CatalogState>,
+    Json(request): Json<CreateCatalogRequest>,
+) -> Result<(StatusCode, Json<Catalog>), (StatusCode, Json<ErrorResponse>)> {
+    if state.engine.find_catalog(&request.name).is_some() {
+        return Err(catalog_error(
+            StatusCode::CONFLICT,
+            "catalog_exists",
+            format!("catalog '{}' already exists", request.name),
+        ));
+    }
+    let now = Utc::now();
+    let catalog = Catalog {
+        id: Uuid::new_v4(),
+        name: request.name,
+        description: request.description,
+        fields: request.fields,
+        item_count: 0,
+        created_at: now,
+        updated_at: now,
+    };
+    state.engine.create_catalog(catalog.clone());
+    Ok((StatusCode::CREATED, Json(catalog)))
+}
+
+/// GET /v1/catalogs — Every catalog.
+#[utoipa::path(
+    get,
+    path = "/v1/catalogs",
+    tag = "Catalogs",
+    responses(
+        (status = 200, description = "Every catalog", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_list_catalogs(State(state): State<CatalogState>) -> Json<Vec<Catalog>> {
+    Json(state.engine.list_catalogs())
+}
+
+/// POST /v1/catalogs/{id}/feed — Load a CSV, JSON Lines or Google Merchant
+/// XML feed into a catalog.
+#[utoipa::path(
+    post,
+    path = "/v1/catalogs/{id}/feed",
+    tag = "Catalogs",
+    params(
+        ("id" = String, Path, description = "Catalog id"),
+        ("format" = String, Query, description = "csv, json_lines or google_merchant_xml"),
+        ("mode" = String, Query, description = "full (missing items are deleted) or delta"),
+    ),
+    request_body(content = String, description = "The raw feed"),
+    responses(
+        (status = 200, description = "Created, updated, unchanged, deleted and rejected rows", body = Object),
+        (status = 400, description = "The feed could not be parsed", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No such catalog", body = ErrorResponse),
+    )
+)]
+pub async fn handle_ingest_feed(
+    State(state): State<CatalogState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<FeedQuery>,
+    feed: String,
+) -> Result<Json<IngestReport>, (StatusCode, Json<ErrorResponse>)> {
+    if state.engine.get_catalog(&id).is_none() {
+        return Err(catalog_error(
+            StatusCode::NOT_FOUND,
+            "catalog_not_found",
+            format!("catalog {id} not found"),
+        ));
+    }
+    // Large feeds take a while to validate and index; keep them off the
+    // async workers.
+    let engine = state.engine.clone();
+    let report = tokio::task::spawn_blocking(move || {
+        engine.ingest_feed(&id, &feed, query.format, query.mode)
+    })
+    .await
+    .map_err(|e| {
+        catalog_error(
+            StatusCode::INTERNAL_SERVER_ERROR,
+            "ingest_failed",
+            e.to_string(),
+        )
+    })?
+    .map_err(|e| catalog_error(StatusCode::BAD_REQUEST, "invalid_feed", format!("{e:#}")))?;
+    metrics::counter!("catalog.feed_rows_rejected").increment(report.rejected.len() as u64);
+    Ok(Json(report))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use campaign_personalization::recommendations::{
+        RecommendationRequest, RecommendationStrategy,
+    };
+    use campaign_personalization::RecommendationEngine;
+    use std::collections::HashMap;
+    use tower::ServiceExt;
+
+    async fn call(app: &Router, uri: &str, body: String) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_ingested_feed_reaches_recommendations() {
+        let catalogs = Arc::new(CatalogEngine::new());
+        let recommendations = RecommendationEngine::new().with_catalog(catalogs.clone());
+        let app = Router::new()
+            .route(
+                "/v1/catalogs",
+                get(handle_list_catalogs).post(handle_create_catalog),
+            )
+            .route("/v1/catalogs/:id/feed", post(handle_ingest_feed))
+            .with_state(CatalogState { engine: catalogs });
+
+        let request = Request::builder()
+            .method("POST")
+            .uri("/v1/catalogs")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                serde_json::json!({
+                    "name": "products",
+                    "fields": [
+                        {"name": "name", "field_type": "string", "required": true},
+                        {"name": "in_stock", "field_type": "boolean", "required": false},
+                    ],
+                })
+                .to_string(),
+            ))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CREATED);
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let catalog: serde_json::Value = serde_json::from_slice(&bytes).unwrap();
+        let id = catalog["id"].as_str().unwrap();
+
+        let feed = "id,name,in_stock\nsku-1,Espresso,true\nsku-2,Grinder,false\nsku-3,,true\n";
+        let (status, report) = call(
+            &app,
+            &format!("/v1/catalogs/{id}/feed?format=csv&mode=full"),
+            feed.to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(report["created"], 2);
+        assert_eq!(report["rejected"].as_array().unwrap().len(), 1);
+
+        // The out-of-stock row is filtered from recommendations.
+        for item in ["sku-1", "sku-2"] {
+            recommendations.record_interaction(Uuid::new_v4(), item.to_string());
+        }
+        let recs = recommendations.recommend(&RecommendationRequest {
+            user_id: Uuid::new_v4(),
+            strategy: RecommendationStrategy::MostPopular,
+            catalog_name: None,
+            limit: 5,
+            exclude_ids: vec![],
+            context: HashMap::new(),
+        });
+        let ids: Vec<_> = recs.items.iter().map(|r| r.item_id.as_str()).collect();
+        assert_eq!(ids, vec!["sku-1"]);
+
+        let (status, _) = call(
+            &app,
+            &format!("/v1/catalogs/{}/feed?format=csv&mode=full", Uuid::new_v4()),
+            feed.to_string(),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -1,13 +1,28 @@
 //! Omnichannel ingest and activation REST API endpoints.
 
+use axum::body::Bytes;
+use axum::extract::Form;
 use axum::extract::State;
-use axum::http::StatusCode;
+use axum::http::{HeaderMap, StatusCode};
+use axum::response::IntoResponse;
 use axum::Json;
-use campaign_channels::{ActivationDispatcher, IngestProcessor, SendGridProvider};
+use campaign_channels::content_studio::{BlockPersonalization, EmailBlock};
+use campaign_channels::rendering::{
+    CatalogLookup, RecommendationSlot, RenderPersonalization, RenderRequest,
+};
+use campaign_channels::whatsapp::WhatsAppWebhook;
+use campaign_channels::{
+    ActivationDispatcher, IngestProcessor, RenderingService, SendGridProvider, SmsProvider,
+    WhatsAppProvider,
+};
 use campaign_core::channels::*;
-use serde::Serialize;
+use campaign_mobile_sdk::device::{DeviceAccessError, DeviceRegistration, PushTokenUpdate};
+use campaign_mobile_sdk::DeviceRegistry;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 use std::sync::Arc;
-use tracing::error;
+use subtle::ConstantTimeEq;
+use tracing::{error, warn};
 use utoipa::ToSchema;
 
 /// Shared state for channel endpoints.
@@ -16,6 +31,28 @@ pub struct ChannelState {
     pub ingest: Arc<IngestProcessor>,
     pub activation: Arc<ActivationDispatcher>,
     pub sendgrid: Arc<SendGridProvider>,
+    pub sms: Arc<SmsProvider>,
+    pub whatsapp: Arc<WhatsAppProvider>,
+    pub rendering: Arc<RenderingService>,
+}
+
+/// Shared state for SDK device endpoints.
+#[derive(Clone)]
+pub struct DeviceState {
+    pub devices: Arc<DeviceRegistry>,
+    /// App keys the SDK must send as `X-Api-Key`.
+    pub sdk_api_keys: Arc<Vec<String>>,
+}
+
+impl DeviceState {
+    /// The request's `X-Api-Key`, if it is one of the configured app keys.
+    fn sdk_api_key<'a>(&self, headers: &'a HeaderMap) -> Option<&'a str> {
+        let key = headers.get("x-api-key")?.to_str().ok()?;
+        self.sdk_api_keys
+            .iter()
+            .any(|k| bool::from(k.as_bytes().ct_eq(key.as_bytes())))
+            .then_some(key)
+    }
 }
 
 /// POST /v1/channels/ingest — Process a real-time ingest event.
@@ -78,20 +115,52 @@ pub async fn handle_activate(
     Json(result)
 }
 
-/// POST /v1/webhooks/sendgrid — SendGrid delivery webhook receiver.
+/// POST /v1/channels/email/webhook — SendGrid signed event webhook receiver.
+///
+/// Payloads without a valid `X-Twilio-Email-Event-Webhook-Signature` are
+/// rejected. Also mounted at the legacy `/v1/webhooks/sendgrid` path.
 #[utoipa::path(
     post,
-    path = "/v1/webhooks/sendgrid",
+    path = "/v1/channels/email/webhook",
     tag = "Channels",
     request_body = Vec<EmailWebhookEvent>,
     responses(
         (status = 200, description = "Webhook events processed"),
+        (status = 400, description = "Payload is not a valid event array"),
+        (status = 401, description = "Missing or invalid webhook signature"),
     )
 )]
 pub async fn handle_sendgrid_webhook(
     State(state): State<ChannelState>,
-    Json(events): Json<Vec<EmailWebhookEvent>>,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> StatusCode {
+    let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok());
+    let (Some(signature), Some(timestamp)) = (
+        header(campaign_channels::email::SIGNATURE_HEADER),
+        header(campaign_channels::email::TIMESTAMP_HEADER),
+    ) else {
+        warn!("Rejecting unsigned SendGrid webhook");
+        metrics::counter!("sendgrid.webhooks_rejected").increment(1);
+        return StatusCode::UNAUTHORIZED;
+    };
+
+    if let Err(e) = state
+        .sendgrid
+        .verify_webhook_signature(&body, signature, timestamp)
+    {
+        warn!(error = %e, "Rejecting SendGrid webhook with invalid signature");
+        metrics::counter!("sendgrid.webhooks_rejected").increment(1);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let events: Vec<EmailWebhookEvent> = match serde_json::from_slice(&body) {
+        Ok(events) => events,
+        Err(e) => {
+            warn!(error = %e, "Malformed SendGrid webhook payload");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
     for event in &events {
         state.sendgrid.process_webhook(event);
     }
@@ -99,6 +168,225 @@ pub async fn handle_sendgrid_webhook(
     StatusCode::OK
 }
 
+/// Check `X-Twilio-Signature` against the URL Twilio was configured to call.
+fn twilio_request_authentic(
+    sms: &SmsProvider,
+    url: Option<&str>,
+    headers: &HeaderMap,
+    params: &[(String, String)],
+) -> bool {
+    let signature = headers
+        .get("x-twilio-signature")
+        .and_then(|v| v.to_str().ok());
+    match (url, signature) {
+        (Some(url), Some(signature)) => sms.validate_signature(url, params, signature),
+        _ => false,
+    }
+}
+
+fn param<'a>(params: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    params
+        .iter()
+        .find(|(k, _)| k == name)
+        .map(|(_, v)| v.as_str())
+}
+
+/// POST /v1/channels/sms/inbound — Twilio inbound message webhook.
+/// Applies STOP/START/HELP keywords and answers with TwiML.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/sms/inbound",
+    tag = "Channels",
+    responses(
+        (status = 200, description = "TwiML response", content_type = "text/xml"),
+        (status = 400, description = "Missing From parameter"),
+        (status = 401, description = "Missing or invalid X-Twilio-Signature"),
+    )
+)]
+pub async fn handle_sms_inbound(
+    State(state): State<ChannelState>,
+    headers: HeaderMap,
+    Form(params): Form<Vec<(String, String)>>,
+) -> axum::response::Response {
+    let url = state.sms.config().inbound_webhook_url.as_deref();
+    if !twilio_request_authentic(&state.sms, url, &headers, &params) {
+        warn!("Rejecting Twilio inbound webhook with invalid signature");
+        metrics::counter!("sms.webhooks_rejected").increment(1);
+        return StatusCode::UNAUTHORIZED.into_response();
+    }
+    let Some(from) = param(&params, "From") else {
+        return StatusCode::BAD_REQUEST.into_response();
+    };
+
+    let result = state
+        .sms
+        .handle_inbound(from, param(&params, "Body").unwrap_or_default());
+    (
+        [(axum::http::header::CONTENT_TYPE, "text/xml")],
+        campaign_channels::sms::twiml_response(result.reply.as_deref()),
+    )
+        .into_response()
+}
+
+/// POST /v1/channels/sms/status — Twilio message status callback.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/sms/status",
+    tag = "Channels",
+    responses(
+        (status = 200, description = "Status recorded"),
+        (status = 400, description = "Missing MessageSid or MessageStatus"),
+        (status = 401, description = "Missing or invalid X-Twilio-Signature"),
+        (status = 404, description = "Unknown message"),
+    )
+)]
+pub async fn handle_sms_status(
+    State(state): State<ChannelState>,
+    headers: HeaderMap,
+    Form(params): Form<Vec<(String, String)>>,
+) -> StatusCode {
+    let url = state.sms.config().status_callback_url.as_deref();
+    if !twilio_request_authentic(&state.sms, url, &headers, &params) {
+        warn!("Rejecting Twilio status callback with invalid signature");
+        metrics::counter!("sms.webhooks_rejected").increment(1);
+        return StatusCode::UNAUTHORIZED;
+    }
+    let (Some(sid), Some(status)) = (
+        param(&params, "MessageSid"),
+        param(&params, "MessageStatus"),
+    ) else {
+        return StatusCode::BAD_REQUEST;
+    };
+
+    if state
+        .sms
+        .handle_status_callback(sid, status, param(&params, "ErrorCode"))
+    {
+        StatusCode::OK
+    } else {
+        StatusCode::NOT_FOUND
+    }
+}
+
+/// Query parameters of the WhatsApp webhook subscription handshake.
+#[derive(Debug, serde::Deserialize)]
+pub struct WhatsAppVerifyQuery {
+    #[serde(rename = "hub.mode")]
+    pub mode: String,
+    #[serde(rename = "hub.verify_token")]
+    pub verify_token: String,
+    #[serde(rename = "hub.challenge")]
+    pub challenge: String,
+}
+
+/// GET /v1/channels/whatsapp/webhook — Cloud API subscription handshake.
+#[utoipa::path(
+    get,
+    path = "/v1/channels/whatsapp/webhook",
+    tag = "Channels",
+    responses(
+        (status = 200, description = "Echoed hub.challenge", body = String),
+        (status = 403, description = "Verify token mismatch"),
+    )
+)]
+pub async fn handle_whatsapp_verify(
+    State(state): State<ChannelState>,
+    axum::extract::Query(query): axum::extract::Query<WhatsAppVerifyQuery>,
+) -> Result<String, StatusCode> {
+    state
+        .whatsapp
+        .verify_subscription(&query.mode, &query.verify_token, &query.challenge)
+        .ok_or(StatusCode::FORBIDDEN)
+}
+
+/// POST /v1/channels/whatsapp/webhook — Cloud API webhook receiver for
+/// inbound messages, delivery statuses and template review updates.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/whatsapp/webhook",
+    tag = "Channels",
+    responses(
+        (status = 200, description = "Notification processed"),
+        (status = 400, description = "Malformed notification"),
+        (status = 401, description = "Missing or invalid X-Hub-Signature-256"),
+    )
+)]
+pub async fn handle_whatsapp_webhook(
+    State(state): State<ChannelState>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> StatusCode {
+    let signature = headers
+        .get(campaign_channels::whatsapp::SIGNATURE_HEADER)
+        .and_then(|v| v.to_str().ok())
+        .unwrap_or_default();
+    if !state.whatsapp.verify_signature(&body, signature) {
+        warn!("Rejecting WhatsApp webhook with invalid signature");
+        metrics::counter!("whatsapp.webhooks_rejected").increment(1);
+        return StatusCode::UNAUTHORIZED;
+    }
+
+    let webhook: WhatsAppWebhook = match serde_json::from_slice(&body) {
+        Ok(webhook) => webhook,
+        Err(e) => {
+            warn!(error = %e, "Malformed WhatsApp webhook payload");
+            return StatusCode::BAD_REQUEST;
+        }
+    };
+    let events = state.whatsapp.handle_webhook(&webhook);
+    metrics::counter!("whatsapp.webhook_events").increment(events.len() as u64);
+    StatusCode::OK
+}
+
+/// POST /v1/channels/render/preview — Render a template for a user exactly
+/// as the channel would send it.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/render/preview",
+    tag = "Channels",
+    request_body = RenderPreviewRequest,
+    responses(
+        (status = 200, description = "Rendered message", body = RenderPreviewResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = crate::rest::ErrorResponse),
+        (status = 422, description = "Template error, unknown variable or missing required variable", body = ChannelErrorResponse),
+    )
+)]
+pub async fn handle_render_preview(
+    State(state): State<ChannelState>,
+    Json(preview): Json<RenderPreviewRequest>,
+) -> Result<Json<RenderPreviewResponse>, (StatusCode, Json<ChannelErrorResponse>)> {
+    let mut request = RenderRequest::new(preview.user_id, preview.channel, preview.body)
+        .with_personalization(RenderPersonalization {
+            connected: preview.connected,
+            catalog: preview.catalog,
+            recommendations: preview.recommendations,
+            blocks: preview.blocks,
+            block_rules: preview.block_rules,
+        });
+    request.subject = preview.subject;
+    request.variables = preview.variables;
+    request.event = preview.event;
+    request.campaign = preview.campaign;
+
+    match state.rendering.render(&request).await {
+        Ok(rendered) => Ok(Json(RenderPreviewResponse {
+            user_id: rendered.user_id,
+            channel: rendered.channel,
+            subject: rendered.subject,
+            body: rendered.body,
+            blocks: rendered.blocks,
+            rendered_at: rendered.rendered_at,
+        })),
+        Err(e) => Err((
+            StatusCode::UNPROCESSABLE_ENTITY,
+            Json(ChannelErrorResponse {
+                error: "render_failed".to_string(),
+                message: e.to_string(),
+            }),
+        )),
+    }
+}
+
 /// GET /v1/channels/email/analytics/{activation_id} — Get email analytics.
 #[utoipa::path(
     get,
@@ -137,6 +425,88 @@ pub async fn handle_all_email_analytics(
     Json(state.sendgrid.all_analytics())
 }
 
+/// POST /v1/channels/push/devices — Register (or re-register) an SDK device
+/// so push activations can reach it.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/push/devices",
+    tag = "Channels",
+    request_body = Object,
+    responses(
+        (status = 200, description = "Device registered", body = DeviceRegistrationResponse),
+        (status = 401, description = "Missing or unknown X-Api-Key"),
+        (status = 403, description = "Device registered by another app, or missing or wrong X-Device-Secret"),
+    )
+)]
+pub async fn handle_register_device(
+    State(state): State<DeviceState>,
+    headers: HeaderMap,
+    Json(device): Json<DeviceRegistration>,
+) -> Result<Json<DeviceRegistrationResponse>, StatusCode> {
+    let api_key = state
+        .sdk_api_key(&headers)
+        .ok_or(StatusCode::UNAUTHORIZED)?;
+    let device_id = device.device_id.clone();
+    match state
+        .devices
+        .register_for_app(api_key, device_secret(&headers), device)
+    {
+        Ok(device_secret) => {
+            metrics::counter!("push.devices_registered").increment(1);
+            Ok(Json(DeviceRegistrationResponse {
+                device_id,
+                device_secret,
+            }))
+        }
+        Err(e) => {
+            warn!(device_id = %device_id, error = ?e, "Rejecting device registration");
+            metrics::counter!("push.device_requests_rejected").increment(1);
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// POST /v1/channels/push/token — Store a refreshed push token for a
+/// registered device.
+#[utoipa::path(
+    post,
+    path = "/v1/channels/push/token",
+    tag = "Channels",
+    request_body = Object,
+    responses(
+        (status = 204, description = "Token updated"),
+        (status = 401, description = "Missing or unknown X-Api-Key"),
+        (status = 403, description = "Device registered by another app, or missing or wrong X-Device-Secret"),
+        (status = 404, description = "Device not registered"),
+    )
+)]
+pub async fn handle_update_push_token(
+    State(state): State<DeviceState>,
+    headers: HeaderMap,
+    Json(update): Json<PushTokenUpdate>,
+) -> StatusCode {
+    let Some(api_key) = state.sdk_api_key(&headers) else {
+        return StatusCode::UNAUTHORIZED;
+    };
+    match state
+        .devices
+        .update_push_token_for_app(api_key, device_secret(&headers), &update)
+    {
+        Ok(()) => StatusCode::NO_CONTENT,
+        Err(DeviceAccessError::NotRegistered) => StatusCode::NOT_FOUND,
+        Err(DeviceAccessError::NotOwner) => {
+            warn!(device_id = %update.device_id, "Rejecting push token update");
+            metrics::counter!("push.device_requests_rejected").increment(1);
+            StatusCode::FORBIDDEN
+        }
+    }
+}
+
+/// The `X-Device-Secret` issued when the device was first registered.
+fn device_secret(headers: &HeaderMap) -> Option<&str> {
+    headers.get("x-device-secret").and_then(|v| v.to_str().ok())
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct IngestResponse {
     pub event_id: String,
@@ -145,8 +515,182 @@ pub struct IngestResponse {
     pub loyalty_relevant: bool,
 }
 
+#[derive(Deserialize, ToSchema)]
+pub struct RenderPreviewRequest {
+    pub user_id: String,
+    pub channel: ActivationChannel,
+    pub subject: Option<String>,
+    pub body: String,
+    /// Message variable overrides (`{{ offer_url }}`).
+    #[serde(default)]
+    pub variables: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub event: HashMap<String, serde_json::Value>,
+    #[serde(default)]
+    pub campaign: HashMap<String, serde_json::Value>,
+    /// Connected content source IDs by alias (`connected.<alias>`).
+    #[serde(default)]
+    pub connected: HashMap<String, uuid::Uuid>,
+    /// Catalog items by alias (`catalog.<alias>`): `catalog_id`, `item_id`.
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub catalog: HashMap<String, CatalogLookup>,
+    /// Recommendation slots (`recommendations.<slot>`): `strategy`, `limit`,
+    /// `catalog_name`, `catalog_id`.
+    #[serde(default)]
+    #[schema(value_type = Object)]
+    pub recommendations: HashMap<String, RecommendationSlot>,
+    /// Email builder blocks, personalized by `block_rules` and then
+    /// rendered.
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub blocks: Vec<EmailBlock>,
+    #[serde(default)]
+    #[schema(value_type = Vec<Object>)]
+    pub block_rules: Vec<BlockPersonalization>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct RenderPreviewResponse {
+    pub user_id: String,
+    pub channel: ActivationChannel,
+    pub subject: Option<String>,
+    pub body: String,
+    #[schema(value_type = Vec<Object>)]
+    pub blocks: Vec<EmailBlock>,
+    pub rendered_at: chrono::DateTime<chrono::Utc>,
+}
+
+#[derive(Serialize, ToSchema)]
+pub struct DeviceRegistrationResponse {
+    pub device_id: String,
+    /// Send as `X-Device-Secret` to re-register the device or update its
+    /// push token.
+    pub device_secret: String,
+}
+
 #[derive(Serialize, ToSchema)]
 pub struct ChannelErrorResponse {
     pub error: String,
     pub message: String,
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::post;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    #[tokio::test]
+    async fn test_registered_devices_become_push_targets() {
+        let devices = Arc::new(DeviceRegistry::new());
+        let app = Router::new()
+            .route("/v1/channels/push/devices", post(handle_register_device))
+            .route("/v1/channels/push/token", post(handle_update_push_token))
+            .with_state(DeviceState {
+                devices: devices.clone(),
+                sdk_api_keys: Arc::new(vec!["app-key".to_string(), "other-app".to_string()]),
+            });
+        let post = |uri: &str, api_key: &str, secret: &str, body: &serde_json::Value| {
+            Request::builder()
+                .method("POST")
+                .uri(uri)
+                .header("content-type", "application/json")
+                .header("x-api-key", api_key)
+                .header("x-device-secret", secret)
+                .body(Body::from(body.to_string()))
+                .unwrap()
+        };
+        let call = |request: Request<Body>| {
+            let app = app.clone();
+            async move {
+                let response = app.oneshot(request).await.unwrap();
+                let status = response.status();
+                let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+                    .await
+                    .unwrap();
+                let body: serde_json::Value = serde_json::from_slice(&bytes).unwrap_or_default();
+                (status, body)
+            }
+        };
+        let user_id = uuid::Uuid::new_v4();
+        let now = chrono::Utc::now();
+
+        let token = serde_json::json!({
+            "device_id": "dev-1",
+            "push_token": "fcm-token",
+            "platform": "android",
+            "provider": "fcm",
+        });
+        let (status, _) = call(post("/v1/channels/push/token", "app-key", "", &token)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let device = |user_id: uuid::Uuid| {
+            serde_json::json!({
+                "id": uuid::Uuid::new_v4(),
+                "user_id": user_id,
+                "device_id": "dev-1",
+                "platform": "android",
+                "push_token": null,
+                "push_enabled": false,
+                "app_version": "1.0",
+                "sdk_version": "1.0",
+                "os_version": "14",
+                "device_model": null,
+                "locale": null,
+                "timezone": null,
+                "ad_tracking_enabled": false,
+                "idfa": null,
+                "gaid": null,
+                "created_at": now,
+                "updated_at": now,
+                "last_active": now,
+            })
+        };
+        let (status, _) = call(post(
+            "/v1/channels/push/devices",
+            "unknown-key",
+            "",
+            &device(user_id),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+
+        let (status, body) = call(post(
+            "/v1/channels/push/devices",
+            "app-key",
+            "",
+            &device(user_id),
+        ))
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let secret = body["device_secret"].as_str().unwrap().to_string();
+        assert!(devices.get_user_push_targets(&user_id).is_empty());
+
+        // Neither another app nor a caller without the device secret can
+        // re-bind the device or replace its token.
+        let attacker = uuid::Uuid::new_v4();
+        for (api_key, secret) in [("other-app", secret.as_str()), ("app-key", "guess")] {
+            let (status, _) = call(post(
+                "/v1/channels/push/devices",
+                api_key,
+                secret,
+                &device(attacker),
+            ))
+            .await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+            let (status, _) = call(post("/v1/channels/push/token", api_key, secret, &token)).await;
+            assert_eq!(status, StatusCode::FORBIDDEN);
+        }
+        assert_eq!(devices.get_device("dev-1").unwrap().user_id, Some(user_id));
+
+        let (status, _) = call(post("/v1/channels/push/token", "app-key", &secret, &token)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let targets = devices.get_user_push_targets(&user_id);
+        assert_eq!(targets[0].push_token.as_deref(), Some("fcm-token"));
+    }
+}
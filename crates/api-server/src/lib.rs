@@ -1,9 +1,12 @@
 #![warn(clippy::unwrap_used)]
 
+pub mod catalog_rest;
 pub mod channel_rest;
 pub mod dsp_rest;
 pub mod grpc;
 pub mod loyalty_rest;
+pub mod profiles;
+pub mod reporting_rest;
 pub mod rest;
 pub mod server;
 pub mod swagger;
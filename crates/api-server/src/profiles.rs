@@ -0,0 +1,72 @@
+//! Render profiles backed by the Redis user-profile cache, so previews and
+//! channel sends personalize against the same profiles the bid path reads.
+
+use campaign_cache::RedisCache;
+use campaign_channels::rendering::{ProfileFuture, ProfileSource, RenderProfile};
+use campaign_core::types::UserProfile;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+pub struct CacheProfileSource {
+    cache: Arc<RedisCache>,
+}
+
+impl CacheProfileSource {
+    pub fn new(cache: Arc<RedisCache>) -> Self {
+        Self { cache }
+    }
+}
+
+impl ProfileSource for CacheProfileSource {
+    fn load_profile<'a>(&'a self, user_id: &'a str) -> ProfileFuture<'a> {
+        Box::pin(async move {
+            Ok(self
+                .cache
+                .get_profile(user_id)
+                .await?
+                .map(|p| render_profile(&p)))
+        })
+    }
+}
+
+/// Expose the profile's fields as `user.*`. The loyalty tier and star
+/// balance also fill the `loyalty_tier` and `points_balance` variables.
+pub fn render_profile(profile: &UserProfile) -> RenderProfile {
+    let mut attributes: HashMap<String, Value> = match serde_json::to_value(profile) {
+        Ok(Value::Object(fields)) => fields.into_iter().collect(),
+        _ => Default::default(),
+    };
+    if let Some(loyalty) = &profile.loyalty {
+        attributes.insert(
+            "loyalty_tier".to_string(),
+            Value::String(format!("{:?}", loyalty.tier)),
+        );
+        attributes.insert(
+            "points_balance".to_string(),
+            Value::from(loyalty.stars_balance),
+        );
+    }
+    RenderProfile {
+        attributes,
+        segments: profile.segments.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_profile_exposes_profile_fields() {
+        let mut profile = RedisCache::default_profile("u-1");
+        profile.segments = vec![3, 9];
+        profile.geo_region = Some("us-west".to_string());
+
+        let rendered = render_profile(&profile);
+        assert_eq!(rendered.segments, vec![3, 9]);
+        assert_eq!(rendered.attributes["user_id"], "u-1");
+        assert_eq!(rendered.attributes["geo_region"], "us-west");
+        assert!(!rendered.attributes.contains_key("loyalty_tier"));
+    }
+}
@@ -0,0 +1,824 @@
+//! Live reporting REST and server-sent-events endpoints, cross-channel
+//! breakdowns over the canonical event envelope, and saved report
+//! definitions run against ClickHouse and delivered on schedule.
+
+use crate::rest::ErrorResponse;
+use axum::extract::{Path, Query, State};
+use axum::http::StatusCode;
+use axum::response::sse::{Event, KeepAlive, Sse};
+use axum::Json;
+use campaign_analytics::{AnalyticsStore, ClickHouseAnalyticsStore, RollupDimension};
+use campaign_core::channels::SendGridConfig;
+use campaign_core::config::{ClickHouseConfig, ReportingConfig};
+use campaign_platform::{EventRouter, SchemaRegistry};
+use campaign_reporting::dashboard::{CampaignMetrics, DashboardOverview, TimeSeriesPoint};
+use campaign_reporting::live::{LiveConfig, LiveMetric, LiveSnapshot, LiveWindow};
+use campaign_reporting::measurement::{BreakdownReport, ReportingBreakdown};
+use campaign_reporting::report_builder::{ReportDefinition, ReportOutput};
+use campaign_reporting::report_query::ClickHouseBackend;
+use campaign_reporting::report_scheduler::{EmailRelayConfig, HttpReportDelivery, WebhookPolicy};
+use campaign_reporting::{
+    CampaignDashboard, LiveMetrics, MeasurementEngine, ReportBuilder, ReportScheduler,
+};
+use chrono::{Duration, Utc};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+use tokio_stream::wrappers::IntervalStream;
+use tokio_stream::{Stream, StreamExt};
+use utoipa::IntoParams;
+use uuid::Uuid;
+
+/// How often the report scheduler checks for due reports.
+const REPORT_SCHEDULER_TICK: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// How often live metrics drop buckets past retention.
+const LIVE_PRUNE_TICK: std::time::Duration = std::time::Duration::from_secs(60);
+
+/// Shared state for reporting endpoints.
+#[derive(Clone)]
+pub struct ReportingState {
+    pub live: Arc<LiveMetrics>,
+    pub dashboard: Arc<CampaignDashboard>,
+    pub measurement: Arc<MeasurementEngine>,
+    /// Producers emit here; events reach live metrics as-is and measurement
+    /// as envelopes, schema-validated on a sample.
+    pub router: Arc<EventRouter>,
+    /// Saved report definitions; in memory until [`Self::with_clickhouse`].
+    pub reports: Arc<ReportBuilder>,
+    /// Hosts that scheduled reports may be posted to.
+    pub webhooks: WebhookPolicy,
+}
+
+impl ReportingState {
+    pub fn new() -> Self {
+        Self::build(Arc::new(LiveMetrics::new()), None)
+    }
+
+    fn build(live: Arc<LiveMetrics>, store: Option<Arc<dyn AnalyticsStore>>) -> Self {
+        let mut dashboard = CampaignDashboard::new().with_live(live.clone());
+        let mut measurement = MeasurementEngine::new();
+        if let Some(store) = store {
+            dashboard = dashboard.with_store(store.clone());
+            measurement = measurement.with_store(store);
+        }
+        let measurement = Arc::new(measurement);
+        let router = EventRouter::new(Arc::new(SchemaRegistry::new()))
+            .with_event_sink(live.clone())
+            .with_envelope_sink(measurement.clone());
+        Self {
+            dashboard: Arc::new(dashboard),
+            live,
+            measurement,
+            router: Arc::new(router),
+            reports: Arc::new(ReportBuilder::new()),
+            webhooks: WebhookPolicy::default(),
+        }
+    }
+
+    /// Serve dashboards and breakdowns from the analytics rollups in `store`.
+    pub fn with_store(self, store: Arc<dyn AnalyticsStore>) -> Self {
+        Self {
+            reports: self.reports,
+            webhooks: self.webhooks,
+            ..Self::build(self.live, Some(store))
+        }
+    }
+
+    /// Restrict report webhooks to the configured hosts.
+    pub fn with_reporting_config(mut self, config: &ReportingConfig) -> Self {
+        self.webhooks = WebhookPolicy::new(&config.webhook_allowed_hosts);
+        self
+    }
+
+    /// Serve dashboards and breakdowns from the ClickHouse rollups and run
+    /// saved reports against the ClickHouse analytics tables.
+    pub fn with_clickhouse(self, config: &ClickHouseConfig) -> Self {
+        let mut state = self.with_store(Arc::new(ClickHouseAnalyticsStore::new(config)));
+        state.reports =
+            Arc::new(ReportBuilder::new().with_backend(Arc::new(ClickHouseBackend::new(config))));
+        state
+    }
+
+    /// Deliver scheduled reports to webhooks, and to email through SendGrid
+    /// when it is configured.
+    pub fn spawn_report_scheduler(&self, sendgrid: &SendGridConfig) -> JoinHandle<()> {
+        let mut delivery = HttpReportDelivery::new().with_webhook_policy(self.webhooks.clone());
+        if let Some(email) = EmailRelayConfig::from_sendgrid(sendgrid) {
+            delivery = delivery.with_email(email);
+        }
+        Arc::new(ReportScheduler::new(
+            self.reports.clone(),
+            Arc::new(delivery),
+        ))
+        .spawn(REPORT_SCHEDULER_TICK)
+    }
+
+    /// Drop live buckets of campaigns, channels and creatives that stopped
+    /// receiving events.
+    pub fn spawn_live_pruner(&self) -> JoinHandle<()> {
+        self.live.clone().spawn_pruner(LIVE_PRUNE_TICK)
+    }
+}
+
+impl Default for ReportingState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WindowKind {
+    Tumbling,
+    #[default]
+    Sliding,
+}
+
+/// Which keys to report and over what window.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LiveQuery {
+    /// `campaign`, `channel` or `creative` (default `campaign`).
+    #[param(value_type = Option<String>)]
+    pub dimension: Option<RollupDimension>,
+    /// Only this key; all keys with recent events otherwise.
+    pub key: Option<String>,
+    /// `sliding` (default) or `tumbling`.
+    #[param(value_type = Option<String>)]
+    pub window: Option<WindowKind>,
+    /// Window length in seconds, up to the live retention (default 60).
+    pub seconds: Option<i64>,
+    /// Stream only: milliseconds between updates (default 1000).
+    pub interval_ms: Option<u64>,
+}
+
+impl LiveQuery {
+    fn dimension(&self) -> RollupDimension {
+        self.dimension.unwrap_or(RollupDimension::Campaign)
+    }
+
+    /// The requested window; 400 unless it fits in the live retention.
+    fn live_window(
+        &self,
+        config: &LiveConfig,
+    ) -> Result<LiveWindow, (StatusCode, Json<ErrorResponse>)> {
+        let seconds = checked_seconds("seconds", self.seconds.unwrap_or(60), config)?;
+        Ok(match self.window.unwrap_or_default() {
+            WindowKind::Tumbling => LiveWindow::Tumbling { seconds },
+            WindowKind::Sliding => LiveWindow::Sliding { seconds },
+        })
+    }
+
+    fn snapshots(&self, live: &LiveMetrics, window: LiveWindow) -> Vec<LiveSnapshot> {
+        let now = Utc::now();
+        match &self.key {
+            Some(key) => live
+                .snapshot(self.dimension(), key, window, now)
+                .into_iter()
+                .collect(),
+            None => live.snapshots(self.dimension(), window, now),
+        }
+    }
+}
+
+/// `seconds` must be positive and no longer than the live retention.
+fn checked_seconds(
+    name: &str,
+    seconds: i64,
+    config: &LiveConfig,
+) -> Result<i64, (StatusCode, Json<ErrorResponse>)> {
+    let max = config.retention.num_seconds();
+    if (1..=max).contains(&seconds) {
+        Ok(seconds)
+    } else {
+        Err(report_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_window",
+            format!("{name} must be between 1 and {max}"),
+        ))
+    }
+}
+
+/// Time series request for one key.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct LiveSeriesQuery {
+    /// `campaign`, `channel` or `creative` (default `campaign`).
+    #[param(value_type = Option<String>)]
+    pub dimension: Option<RollupDimension>,
+    pub key: String,
+    /// e.g. `impressions`, `clicks`, `spend`, `latency_p95` (default `impressions`).
+    #[param(value_type = Option<String>)]
+    pub metric: Option<LiveMetric>,
+    /// Seconds per point, up to the live retention (default 10).
+    pub step_seconds: Option<i64>,
+    /// Minutes of history, capped at the live retention (default 15).
+    pub minutes: Option<i64>,
+}
+
+/// GET /v1/reporting/live — Windowed metrics per campaign, channel or creative.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/live",
+    tag = "Reporting",
+    params(LiveQuery),
+    responses(
+        (status = 200, description = "Live snapshots with counts, spend and latency percentiles", body = Object),
+        (status = 400, description = "Window outside the live retention", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_live_snapshots(
+    State(state): State<ReportingState>,
+    Query(query): Query<LiveQuery>,
+) -> Result<Json<Vec<LiveSnapshot>>, (StatusCode, Json<ErrorResponse>)> {
+    let window = query.live_window(state.live.config())?;
+    Ok(Json(query.snapshots(&state.live, window)))
+}
+
+/// GET /v1/reporting/live/series — Time series of one live metric.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/live/series",
+    tag = "Reporting",
+    params(LiveSeriesQuery),
+    responses(
+        (status = 200, description = "One point per step, oldest first", body = Object),
+        (status = 400, description = "Step outside the live retention", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_live_series(
+    State(state): State<ReportingState>,
+    Query(query): Query<LiveSeriesQuery>,
+) -> Result<Json<Vec<TimeSeriesPoint>>, (StatusCode, Json<ErrorResponse>)> {
+    let config = state.live.config();
+    let step = checked_seconds("step_seconds", query.step_seconds.unwrap_or(10), config)?;
+    let retention_minutes = config.retention.num_minutes().max(1);
+    let minutes = query.minutes.unwrap_or(15).clamp(1, retention_minutes);
+    let now = Utc::now();
+    Ok(Json(state.live.series(
+        query.dimension.unwrap_or(RollupDimension::Campaign),
+        &query.key,
+        query.metric.unwrap_or(LiveMetric::Impressions),
+        Duration::seconds(step),
+        now - Duration::minutes(minutes),
+        now,
+    )))
+}
+
+/// GET /v1/reporting/live/stream — Server-sent events with a `snapshot`
+/// event (a JSON array of live snapshots) every interval.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/live/stream",
+    tag = "Reporting",
+    params(LiveQuery),
+    responses(
+        (status = 200, description = "text/event-stream of `snapshot` events", content_type = "text/event-stream"),
+        (status = 400, description = "Window outside the live retention", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_live_stream(
+    State(state): State<ReportingState>,
+    Query(query): Query<LiveQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, (StatusCode, Json<ErrorResponse>)> {
+    let window = query.live_window(state.live.config())?;
+    let every = std::time::Duration::from_millis(query.interval_ms.unwrap_or(1000).max(250));
+    let updates = IntervalStream::new(tokio::time::interval(every)).map(move |_| {
+        let event = Event::default()
+            .event("snapshot")
+            .json_data(query.snapshots(&state.live, window))
+            .unwrap_or_else(|e| Event::default().comment(format!("serialization failed: {e}")));
+        Ok(event)
+    });
+    Ok(Sse::new(updates).keep_alive(KeepAlive::default()))
+}
+
+/// GET /v1/reporting/live/campaigns — Campaign metrics over the live window.
+/// Window counts only; the cumulative dashboard metrics are left untouched.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/live/campaigns",
+    tag = "Reporting",
+    params(LiveQuery),
+    responses(
+        (status = 200, description = "Campaign metrics for campaigns active in the window", body = Object),
+        (status = 400, description = "Window outside the live retention", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_live_campaigns(
+    State(state): State<ReportingState>,
+    Query(query): Query<LiveQuery>,
+) -> Result<Json<Vec<CampaignMetrics>>, (StatusCode, Json<ErrorResponse>)> {
+    let window = query.live_window(state.live.config())?;
+    let mut campaigns = state.dashboard.live_campaign_metrics(window, Utc::now());
+    campaigns.sort_by_key(|m| std::cmp::Reverse(m.sends));
+    Ok(Json(campaigns))
+}
+
+/// Range for the dashboard endpoints.
+#[derive(Debug, Clone, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct DashboardQuery {
+    /// Days of history (default 30).
+    pub days: Option<i64>,
+}
+
+/// GET /v1/reporting/overview — 30-day totals, daily sends and a
+/// per-channel breakdown from the analytics rollups.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/overview",
+    tag = "Reporting",
+    responses(
+        (status = 200, description = "Dashboard overview", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 502, description = "The analytics store failed", body = ErrorResponse),
+    )
+)]
+pub async fn handle_overview(
+    State(state): State<ReportingState>,
+) -> Result<Json<DashboardOverview>, (StatusCode, Json<ErrorResponse>)> {
+    state
+        .dashboard
+        .load_overview(Utc::now())
+        .await
+        .map(Json)
+        .map_err(store_error)
+}
+
+/// GET /v1/reporting/campaigns/{id} — A campaign's metrics from the
+/// analytics rollups.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/campaigns/{id}",
+    tag = "Reporting",
+    params(("id" = Uuid, Path, description = "Campaign id"), DashboardQuery),
+    responses(
+        (status = 200, description = "Campaign metrics over the range", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No events for the campaign in the range", body = ErrorResponse),
+        (status = 502, description = "The analytics store failed", body = ErrorResponse),
+    )
+)]
+pub async fn handle_campaign_metrics(
+    State(state): State<ReportingState>,
+    Path(id): Path<Uuid>,
+    Query(query): Query<DashboardQuery>,
+) -> Result<Json<CampaignMetrics>, (StatusCode, Json<ErrorResponse>)> {
+    let now = Utc::now();
+    let days = query.days.unwrap_or(30).clamp(1, 366);
+    state
+        .dashboard
+        .load_campaign_metrics(&id, now - Duration::days(days), now)
+        .await
+        .map_err(store_error)?
+        .map(Json)
+        .ok_or_else(|| {
+            report_error(
+                StatusCode::NOT_FOUND,
+                "not_found",
+                format!("no events for campaign {id}"),
+            )
+        })
+}
+
+/// POST /v1/reporting/breakdown — Cross-channel breakdown over every
+/// producer's events (bidding, email, SMS, push, journeys, SDKs).
+#[utoipa::path(
+    post,
+    path = "/v1/reporting/breakdown",
+    tag = "Reporting",
+    request_body(content = Object, description = "Breakdown request: name, group_by, metrics, filters, time_range"),
+    responses(
+        (status = 200, description = "One row per dimension combination, with totals", body = Object),
+        (status = 400, description = "The time range is reversed or longer than 366 days", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 502, description = "The analytics store failed", body = ErrorResponse),
+    )
+)]
+pub async fn handle_breakdown(
+    State(state): State<ReportingState>,
+    Json(request): Json<ReportingBreakdown>,
+) -> Result<Json<BreakdownReport>, (StatusCode, Json<ErrorResponse>)> {
+    request.time_range.validate().map_err(|e| {
+        report_error(
+            StatusCode::BAD_REQUEST,
+            "invalid_time_range",
+            format!("{e:#}"),
+        )
+    })?;
+    state
+        .measurement
+        .load_breakdown(&request)
+        .await
+        .map(Json)
+        .map_err(store_error)
+}
+
+/// Log an analytics store failure and answer with a generic 502, so store
+/// addresses and query details stay out of responses.
+fn store_error(e: anyhow::Error) -> (StatusCode, Json<ErrorResponse>) {
+    tracing::warn!(error = %format!("{e:#}"), "Analytics store query failed");
+    report_error(
+        StatusCode::BAD_GATEWAY,
+        "store_failed",
+        "the analytics store is unavailable".to_string(),
+    )
+}
+
+fn report_error(
+    status: StatusCode,
+    error: &str,
+    message: String,
+) -> (StatusCode, Json<ErrorResponse>) {
+    (
+        status,
+        Json(ErrorResponse {
+            error: error.to_string(),
+            message,
+        }),
+    )
+}
+
+/// POST /v1/reporting/reports — Save a report definition, optionally with a
+/// delivery schedule.
+#[utoipa::path(
+    post,
+    path = "/v1/reporting/reports",
+    tag = "Reporting",
+    request_body(content = Object, description = "Report definition: report_type, metrics, dimensions, filters, schedule"),
+    responses(
+        (status = 201, description = "The saved definition", body = Object),
+        (status = 400, description = "The definition does not plan to a valid query, or a recipient is invalid or not an allowed webhook host", body = ErrorResponse),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_create_report(
+    State(state): State<ReportingState>,
+    Json(def): Json<ReportDefinition>,
+) -> Result<(StatusCode, Json<ReportDefinition>), (StatusCode, Json<ErrorResponse>)> {
+    campaign_reporting::report_query::ReportQuery::plan(&def)
+        .map_err(|e| report_error(StatusCode::BAD_REQUEST, "invalid_report", format!("{e:#}")))?;
+    for recipient in def.schedule.iter().flat_map(|s| &s.recipients) {
+        state.webhooks.recipient(recipient).map_err(|e| {
+            report_error(
+                StatusCode::BAD_REQUEST,
+                "invalid_recipient",
+                format!("{e:#}"),
+            )
+        })?;
+    }
+    state.reports.create_report(def.clone());
+    Ok((StatusCode::CREATED, Json(def)))
+}
+
+/// GET /v1/reporting/reports — Saved report definitions.
+#[utoipa::path(
+    get,
+    path = "/v1/reporting/reports",
+    tag = "Reporting",
+    responses(
+        (status = 200, description = "Every saved definition", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+    )
+)]
+pub async fn handle_list_reports(
+    State(state): State<ReportingState>,
+) -> Json<Vec<ReportDefinition>> {
+    Json(state.reports.list_reports(None))
+}
+
+/// POST /v1/reporting/reports/{id}/generate — Run a saved report now.
+#[utoipa::path(
+    post,
+    path = "/v1/reporting/reports/{id}/generate",
+    tag = "Reporting",
+    params(("id" = String, Path, description = "Report definition id")),
+    responses(
+        (status = 200, description = "Columns, rows and summary", body = Object),
+        (status = 401, description = "Missing or invalid bearer token", body = ErrorResponse),
+        (status = 404, description = "No such report", body = ErrorResponse),
+        (status = 502, description = "The query backend failed", body = ErrorResponse),
+    )
+)]
+pub async fn handle_generate_report(
+    State(state): State<ReportingState>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<ReportOutput>, (StatusCode, Json<ErrorResponse>)> {
+    if state.reports.get_report(&id).is_none() {
+        return Err(report_error(
+            StatusCode::NOT_FOUND,
+            "report_not_found",
+            format!("report {id} not found"),
+        ));
+    }
+    state
+        .reports
+        .generate(&id)
+        .await
+        .map(Json)
+        .map_err(|e| report_error(StatusCode::BAD_GATEWAY, "report_failed", format!("{e:#}")))
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::Request;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn app(state: ReportingState) -> Router {
+        Router::new()
+            .route("/v1/reporting/overview", get(handle_overview))
+            .route("/v1/reporting/campaigns/:id", get(handle_campaign_metrics))
+            .route("/v1/reporting/breakdown", post(handle_breakdown))
+            .route(
+                "/v1/reporting/reports",
+                get(handle_list_reports).post(handle_create_report),
+            )
+            .route(
+                "/v1/reporting/reports/:id/generate",
+                post(handle_generate_report),
+            )
+            .layer(axum::middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
+            .with_state(state)
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: serde_json::Value,
+    ) -> (StatusCode, serde_json::Value) {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .header("authorization", "Bearer ce_dev_test")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap_or_default())
+    }
+
+    #[tokio::test]
+    async fn test_live_queries_reject_windows_outside_retention() {
+        let app = Router::new()
+            .route("/v1/reporting/live", get(handle_live_snapshots))
+            .route("/v1/reporting/live/series", get(handle_live_series))
+            .route("/v1/reporting/live/campaigns", get(handle_live_campaigns))
+            .layer(axum::middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
+            .with_state(ReportingState::new());
+
+        for uri in [
+            "/v1/reporting/live?seconds=0",
+            "/v1/reporting/live?seconds=-5",
+            "/v1/reporting/live?seconds=9223372036854775807",
+            "/v1/reporting/live/campaigns?window=tumbling&seconds=3601",
+            "/v1/reporting/live/series?key=c&step_seconds=-9223372036854775808",
+            "/v1/reporting/live/series?key=c&step_seconds=0",
+        ] {
+            let (status, body) = call(&app, "GET", uri, serde_json::Value::Null).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{uri}");
+            assert_eq!(body["error"], "invalid_window");
+        }
+
+        let (status, _) = call(
+            &app,
+            "GET",
+            "/v1/reporting/live?seconds=3600",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let (status, body) = call(
+            &app,
+            "GET",
+            "/v1/reporting/live/series?key=c&step_seconds=60&minutes=9223372036854775807",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.as_array().unwrap().len() <= 61);
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_reads_the_store() {
+        use campaign_analytics::InMemoryAnalyticsStore;
+        use campaign_core::event_bus::{make_event, EventSink};
+        use campaign_core::types::EventType;
+
+        let store = Arc::new(InMemoryAnalyticsStore::new());
+        let campaign = Uuid::new_v4();
+        for event_type in [EventType::ActivationSent, EventType::ActivationDelivered] {
+            store.emit(
+                make_event(event_type, "req", None, None)
+                    .with_campaign(campaign.to_string())
+                    .with_channel("email"),
+            );
+        }
+        let app = app(ReportingState::new().with_store(store));
+
+        let (status, metrics) = call(
+            &app,
+            "GET",
+            &format!("/v1/reporting/campaigns/{campaign}"),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(
+            (metrics["sends"].as_u64(), metrics["deliveries"].as_u64()),
+            (Some(1), Some(1))
+        );
+
+        let (status, _) = call(
+            &app,
+            "GET",
+            &format!("/v1/reporting/campaigns/{}", Uuid::new_v4()),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        let (status, overview) = call(
+            &app,
+            "GET",
+            "/v1/reporting/overview",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(overview["total_active_campaigns"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_breakdown_requires_login_and_a_bounded_range() {
+        let app = app(ReportingState::new());
+        let anonymous = Request::builder()
+            .uri("/v1/reporting/overview")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(anonymous).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let now = Utc::now();
+        let breakdown = |start: chrono::DateTime<Utc>| {
+            serde_json::json!({
+                "name": "By channel",
+                "group_by": ["channel"],
+                "metrics": ["deliveries"],
+                "filters": [],
+                "time_range": {"start": start, "end": now},
+            })
+        };
+        for start in [now + Duration::hours(1), now - Duration::days(400)] {
+            let (status, body) =
+                call(&app, "POST", "/v1/reporting/breakdown", breakdown(start)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["error"], "invalid_time_range");
+        }
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/reporting/breakdown",
+            breakdown(now - Duration::days(7)),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_report_definitions_round_trip() {
+        let app = app(ReportingState::new());
+        let id = Uuid::new_v4();
+        let mut def = serde_json::json!({
+            "id": id,
+            "name": "Channels",
+            "description": "",
+            "report_type": "channel_comparison",
+            "metrics": [{"name": "sends", "aggregation": "sum", "format": "number"}],
+            "dimensions": [{"name": "channel", "group_by": true}],
+            "filters": [],
+            "sort_by": null,
+            "sort_order": "descending",
+            "limit": null,
+            "created_by": Uuid::nil(),
+            "schedule": null,
+            "created_at": Utc::now(),
+            "updated_at": Utc::now(),
+        });
+        let (status, _) = call(&app, "POST", "/v1/reporting/reports", def.clone()).await;
+        assert_eq!(status, StatusCode::CREATED);
+
+        let anonymous = Request::builder()
+            .uri("/v1/reporting/reports")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.clone().oneshot(anonymous).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let (status, list) = call(
+            &app,
+            "GET",
+            "/v1/reporting/reports",
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(list.as_array().unwrap().len(), 1);
+
+        let (status, output) = call(
+            &app,
+            "POST",
+            &format!("/v1/reporting/reports/{id}/generate"),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(output["columns"], serde_json::json!(["channel", "sends"]));
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            &format!("/v1/reporting/reports/{}/generate", Uuid::new_v4()),
+            serde_json::Value::Null,
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+
+        def["dimensions"][0]["name"] = serde_json::json!("channel`; DROP TABLE x");
+        let (status, body) = call(&app, "POST", "/v1/reporting/reports", def).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert_eq!(body["error"], "invalid_report");
+    }
+
+    #[tokio::test]
+    async fn test_report_recipients_are_validated() {
+        let state = ReportingState::new().with_reporting_config(&ReportingConfig {
+            webhook_allowed_hosts: vec!["hooks.example.com".to_string()],
+        });
+        let app = app(state);
+        let def = |recipients: &[&str]| {
+            serde_json::json!({
+                "id": Uuid::new_v4(),
+                "name": "Channels",
+                "description": "",
+                "report_type": "channel_comparison",
+                "metrics": [{"name": "sends", "aggregation": "sum", "format": "number"}],
+                "dimensions": [{"name": "channel", "group_by": true}],
+                "filters": [],
+                "sort_by": null,
+                "sort_order": "descending",
+                "limit": null,
+                "created_by": Uuid::nil(),
+                "schedule": {
+                    "frequency": "daily",
+                    "recipients": recipients,
+                    "format": "csv",
+                    "next_run": Utc::now(),
+                    "enabled": true,
+                    "timezone": "UTC",
+                },
+                "created_at": Utc::now(),
+                "updated_at": Utc::now(),
+            })
+        };
+
+        let (status, _) = call(
+            &app,
+            "POST",
+            "/v1/reporting/reports",
+            def(&["ops@example.com", "https://hooks.example.com/reports"]),
+        )
+        .await;
+        assert_eq!(status, StatusCode::CREATED);
+        for recipient in [
+            "not-a-recipient",
+            "http://169.254.169.254/latest/meta-data",
+            "https://internal.example.net/hook",
+        ] {
+            let (status, body) =
+                call(&app, "POST", "/v1/reporting/reports", def(&[recipient])).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{recipient}");
+            assert_eq!(body["error"], "invalid_recipient");
+        }
+    }
+}
@@ -1,8 +1,10 @@
 //! API server — starts both HTTP (REST) and gRPC servers.
 
-use crate::channel_rest::{self, ChannelState};
+use crate::catalog_rest::{self, CatalogState};
+use crate::channel_rest::{self, ChannelState, DeviceState};
 use crate::dsp_rest::{self, DspState};
 use crate::loyalty_rest::{self, LoyaltyState};
+use crate::reporting_rest::{self, ReportingState};
 use crate::rest::{self, AppState};
 use crate::swagger::ApiDoc;
 use axum::extract::DefaultBodyLimit;
@@ -10,18 +12,28 @@ use axum::middleware;
 use axum::routing::{get, post};
 use axum::Router;
 use campaign_agents::BidProcessor;
-use campaign_channels::{ActivationDispatcher, IngestProcessor, SendGridProvider};
-use campaign_core::channels::{ActivationChannel, SendGridConfig};
+use campaign_channels::rendering::ProfileSource;
+use campaign_channels::{
+    ActivationDispatcher, IngestProcessor, MobilePushProvider, RenderingService, SendGridProvider,
+    SmsProvider, VariableBrowser, WhatsAppProvider,
+};
+use campaign_core::channels::ActivationChannel;
 use campaign_core::config::AppConfig;
+use campaign_core::event_bus::EventSink;
 use campaign_dsp::DspRouter;
+use campaign_intelligent_delivery::suppression::SuppressionList;
+use campaign_journey::JourneyEngine;
 use campaign_loyalty::LoyaltyEngine;
+use campaign_management::ManagementStore;
+use campaign_mobile_sdk::DeviceRegistry;
+use campaign_personalization::{CatalogEngine, ConnectedContentEngine, RecommendationEngine};
 use std::net::SocketAddr;
 use std::sync::Arc;
 use std::time::Instant;
 use tower_http::compression::CompressionLayer;
 use tower_http::cors::CorsLayer;
 use tower_http::trace::TraceLayer;
-use tracing::info;
+use tracing::{error, info};
 use utoipa::OpenApi;
 use utoipa_swagger_ui::SwaggerUi;
 
@@ -31,20 +43,113 @@ const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
 /// Maximum bid request body size (1 MB — bid requests should be small).
 const MAX_BID_BODY_SIZE: usize = 1024 * 1024;
 
+/// How often SendGrid and WhatsApp delivery state past its TTL is swept.
+const CHANNEL_STATE_SWEEP_TICK: std::time::Duration = std::time::Duration::from_secs(15 * 60);
+
 /// Main API server managing both REST and gRPC endpoints.
 pub struct ApiServer {
     config: AppConfig,
     processor: Arc<BidProcessor>,
+    reporting: ReportingState,
+    variables: Arc<VariableBrowser>,
+    profiles: Option<Arc<dyn ProfileSource>>,
+    devices: Arc<DeviceRegistry>,
+    suppression: Arc<SuppressionList>,
+    journeys: JourneyEngine,
+    catalogs: Arc<CatalogEngine>,
+    recommendations: Arc<RecommendationEngine>,
+    connected: Arc<ConnectedContentEngine>,
 }
 
 impl ApiServer {
     pub fn new(config: AppConfig, processor: Arc<BidProcessor>) -> Self {
-        Self { config, processor }
+        let catalogs = Arc::new(CatalogEngine::new());
+        Self {
+            config,
+            processor,
+            reporting: ReportingState::new(),
+            variables: Arc::new(VariableBrowser::new()),
+            profiles: None,
+            devices: Arc::new(DeviceRegistry::new()),
+            suppression: Arc::new(SuppressionList::new()),
+            journeys: JourneyEngine::new(),
+            recommendations: Arc::new(RecommendationEngine::new().with_catalog(catalogs.clone())),
+            catalogs,
+            connected: Arc::new(ConnectedContentEngine::new()),
+        }
+    }
+
+    /// Share reporting (and its event router) with producers outside the
+    /// API, such as the bid path's analytics logger.
+    pub fn with_reporting(mut self, reporting: ReportingState) -> Self {
+        self.reporting = reporting;
+        self
+    }
+
+    /// Share the message variable registry that templates are checked
+    /// against.
+    pub fn with_variable_browser(mut self, variables: Arc<VariableBrowser>) -> Self {
+        self.variables = variables;
+        self
+    }
+
+    /// Render messages against profiles from `profiles`, e.g. the Redis
+    /// profile cache.
+    pub fn with_profile_source(mut self, profiles: Arc<dyn ProfileSource>) -> Self {
+        self.profiles = Some(profiles);
+        self
+    }
+
+    /// Share the device registry that push activations are delivered to and
+    /// SDK device registrations are written to.
+    pub fn with_device_registry(mut self, devices: Arc<DeviceRegistry>) -> Self {
+        self.devices = devices;
+        self
+    }
+
+    /// Share the suppression list that SMS opt-outs (STOP/START) are written
+    /// to and sends are checked against.
+    pub fn with_suppression_list(mut self, suppression: Arc<SuppressionList>) -> Self {
+        self.suppression = suppression;
+        self
+    }
+
+    /// Share the journey engine that WhatsApp interactive replies are
+    /// routed into.
+    pub fn with_journey_engine(mut self, journeys: JourneyEngine) -> Self {
+        self.journeys = journeys;
+        self
+    }
+
+    /// Share the catalogs that templates look items up in. Pass the same
+    /// engine to the recommendation engine given to
+    /// [`Self::with_recommendations`] so both see the same stock.
+    pub fn with_catalogs(mut self, catalogs: Arc<CatalogEngine>) -> Self {
+        self.catalogs = catalogs;
+        self
+    }
+
+    /// Share the recommendation engine that template slots are filled from,
+    /// e.g. one serving a trained CF model.
+    pub fn with_recommendations(mut self, recommendations: Arc<RecommendationEngine>) -> Self {
+        self.recommendations = recommendations;
+        self
+    }
+
+    /// Share the connected content sources that templates fetch from as
+    /// `connected.<alias>`.
+    pub fn with_connected_content(mut self, connected: Arc<ConnectedContentEngine>) -> Self {
+        self.connected = connected;
+        self
     }
 
     /// Build the Axum router without starting the server.
     /// Used by main.rs for graceful shutdown integration.
     pub fn into_router(self) -> anyhow::Result<Router> {
+        self.router()
+    }
+
+    fn router(&self) -> anyhow::Result<Router> {
         let state = AppState {
             processor: self.processor.clone(),
             node_id: self.config.node_id.clone(),
@@ -61,35 +166,11 @@ impl ApiServer {
         let dsp_router = Arc::new(DspRouter::new(&self.config.dsp, Vec::new()));
         let dsp_state = DspState { router: dsp_router };
 
-        // Initialize channel processors
-        let ingest = Arc::new(IngestProcessor::new(vec![
-            campaign_core::channels::IngestSource::MobileApp,
-            campaign_core::channels::IngestSource::Pos,
-            campaign_core::channels::IngestSource::Kiosk,
-            campaign_core::channels::IngestSource::Web,
-            campaign_core::channels::IngestSource::CallCenter,
-            campaign_core::channels::IngestSource::PartnerApi,
-            campaign_core::channels::IngestSource::IoTDevice,
-        ]));
-        let activation = Arc::new(ActivationDispatcher::new(vec![
-            ActivationChannel::PushNotification,
-            ActivationChannel::Sms,
-            ActivationChannel::Email,
-            ActivationChannel::InAppMessage,
-            ActivationChannel::WebPersonalization,
-            ActivationChannel::PaidMediaFacebook,
-            ActivationChannel::PaidMediaTradeDesk,
-            ActivationChannel::PaidMediaGoogle,
-            ActivationChannel::PaidMediaAmazon,
-            ActivationChannel::DigitalSignage,
-            ActivationChannel::KioskDisplay,
-        ]));
-        let sendgrid = Arc::new(SendGridProvider::new(SendGridConfig::default()));
-        let channel_state = ChannelState {
-            ingest,
-            activation,
-            sendgrid,
-        };
+        // Live and cross-channel reporting, fed through the event router
+        let reporting_state = self.reporting.clone();
+        let event_sink: Arc<dyn EventSink> = reporting_state.router.clone();
+
+        let channel_state = self.channel_state(event_sink);
 
         // Bid routes (stricter body limit for bid requests)
         let bid_routes = Router::new()
@@ -129,10 +210,27 @@ impl ApiServer {
         let channel_routes = Router::new()
             .route("/v1/channels/ingest", post(channel_rest::handle_ingest))
             .route("/v1/channels/activate", post(channel_rest::handle_activate))
+            .route(
+                "/v1/channels/email/webhook",
+                post(channel_rest::handle_sendgrid_webhook),
+            )
             .route(
                 "/v1/webhooks/sendgrid",
                 post(channel_rest::handle_sendgrid_webhook),
             )
+            .route(
+                "/v1/channels/sms/inbound",
+                post(channel_rest::handle_sms_inbound),
+            )
+            .route(
+                "/v1/channels/sms/status",
+                post(channel_rest::handle_sms_status),
+            )
+            .route(
+                "/v1/channels/whatsapp/webhook",
+                get(channel_rest::handle_whatsapp_verify)
+                    .post(channel_rest::handle_whatsapp_webhook),
+            )
             .route(
                 "/v1/channels/email/analytics/{activation_id}",
                 get(channel_rest::handle_email_analytics),
@@ -141,16 +239,117 @@ impl ApiServer {
                 "/v1/channels/email/analytics",
                 get(channel_rest::handle_all_email_analytics),
             )
+            .with_state(channel_state.clone());
+
+        // Previews render against any user's cached profile, so they need a
+        // management login
+        let preview_routes = Router::new()
+            .route(
+                "/v1/channels/render/preview",
+                post(channel_rest::handle_render_preview),
+            )
+            .layer(middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
             .with_state(channel_state);
 
-        // Management UI routes (with auth middleware)
-        let mgmt_routes = campaign_management::management_router().layer(middleware::from_fn(
-            campaign_management::auth::auth_middleware,
-        ));
+        // SDK device registration, read by push activations. Requests carry
+        // an SDK app key, and updates the secret issued to the device.
+        let device_routes = Router::new()
+            .route(
+                "/v1/channels/push/devices",
+                post(channel_rest::handle_register_device),
+            )
+            .route(
+                "/v1/channels/push/token",
+                post(channel_rest::handle_update_push_token),
+            )
+            .with_state(DeviceState {
+                devices: self.devices.clone(),
+                sdk_api_keys: Arc::new(self.config.push.sdk_api_keys.clone()),
+            });
+
+        // Live reporting exposes per-campaign spend and performance, so it
+        // needs a management login
+        let reporting_routes = Router::new()
+            .route(
+                "/v1/reporting/live",
+                get(reporting_rest::handle_live_snapshots),
+            )
+            .route(
+                "/v1/reporting/live/series",
+                get(reporting_rest::handle_live_series),
+            )
+            .route(
+                "/v1/reporting/live/stream",
+                get(reporting_rest::handle_live_stream),
+            )
+            .route(
+                "/v1/reporting/live/campaigns",
+                get(reporting_rest::handle_live_campaigns),
+            )
+            .layer(middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
+            .with_state(reporting_state.clone());
+
+        // Dashboards, breakdowns and saved reports query the warehouse, and
+        // saved reports deliver off-box, so they need a management login
+        let report_routes = Router::new()
+            .route(
+                "/v1/reporting/overview",
+                get(reporting_rest::handle_overview),
+            )
+            .route(
+                "/v1/reporting/campaigns/:id",
+                get(reporting_rest::handle_campaign_metrics),
+            )
+            .route(
+                "/v1/reporting/breakdown",
+                post(reporting_rest::handle_breakdown),
+            )
+            .route(
+                "/v1/reporting/reports",
+                get(reporting_rest::handle_list_reports).post(reporting_rest::handle_create_report),
+            )
+            .route(
+                "/v1/reporting/reports/:id/generate",
+                post(reporting_rest::handle_generate_report),
+            )
+            .layer(middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
+            .with_state(reporting_state);
+
+        // Catalog definitions and feed ingestion, read by rendering and
+        // recommendations
+        let catalog_routes = Router::new()
+            .route(
+                "/v1/catalogs",
+                get(catalog_rest::handle_list_catalogs).post(catalog_rest::handle_create_catalog),
+            )
+            .route(
+                "/v1/catalogs/:id/feed",
+                post(catalog_rest::handle_ingest_feed),
+            )
+            .layer(middleware::from_fn(
+                campaign_management::auth::require_bearer,
+            ))
+            .with_state(CatalogState {
+                engine: self.catalogs.clone(),
+            });
+
+        // Management UI routes (with auth middleware). The store keeps the bid
+        // path's offer-to-campaign index in step with campaign creatives.
+        let mgmt_store =
+            Arc::new(ManagementStore::new().with_offer_campaigns(self.processor.offer_campaigns()));
+        let mgmt_routes = campaign_management::management_router_with_store(mgmt_store).layer(
+            middleware::from_fn(campaign_management::auth::auth_middleware),
+        );
 
         // Swagger UI + OpenAPI JSON
-        let swagger_ui = SwaggerUi::new("/swagger-ui")
-            .url("/api-docs/openapi.json", ApiDoc::openapi());
+        let swagger_ui =
+            SwaggerUi::new("/swagger-ui").url("/api-docs/openapi.json", ApiDoc::openapi());
 
         let app = Router::new()
             .merge(bid_routes)
@@ -158,6 +357,11 @@ impl ApiServer {
             .merge(loyalty_routes)
             .merge(dsp_routes)
             .merge(channel_routes)
+            .merge(preview_routes)
+            .merge(device_routes)
+            .merge(reporting_routes)
+            .merge(report_routes)
+            .merge(catalog_routes)
             .merge(mgmt_routes)
             .merge(swagger_ui)
             .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
@@ -170,33 +374,43 @@ impl ApiServer {
 
     /// Start the HTTP REST server.
     pub async fn start_http(&self) -> anyhow::Result<()> {
-        let state = AppState {
-            processor: self.processor.clone(),
-            node_id: self.config.node_id.clone(),
-            start_time: Instant::now(),
-        };
+        let app = self.router()?;
 
-        // Initialize loyalty engine
-        let loyalty_engine = Arc::new(LoyaltyEngine::new(&self.config.loyalty));
-        let loyalty_state = LoyaltyState {
-            engine: loyalty_engine,
-        };
+        let addr = SocketAddr::new(self.config.api.host.parse()?, self.config.api.http_port);
 
-        // Initialize DSP router
-        let dsp_router = Arc::new(DspRouter::new(&self.config.dsp, Vec::new()));
-        let dsp_state = DspState { router: dsp_router };
+        info!(addr = %addr, "Starting HTTP server");
+
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, app).await?;
+
+        Ok(())
+    }
 
-        // Initialize channel processors
-        let ingest = Arc::new(IngestProcessor::new(vec![
-            campaign_core::channels::IngestSource::MobileApp,
-            campaign_core::channels::IngestSource::Pos,
-            campaign_core::channels::IngestSource::Kiosk,
-            campaign_core::channels::IngestSource::Web,
-            campaign_core::channels::IngestSource::CallCenter,
-            campaign_core::channels::IngestSource::PartnerApi,
-            campaign_core::channels::IngestSource::IoTDevice,
-        ]));
-        let activation = Arc::new(ActivationDispatcher::new(vec![
+    /// Channel processors. Every send renders through one
+    /// [`RenderingService`] over the shared variable registry and profiles.
+    fn channel_state(&self, event_sink: Arc<dyn EventSink>) -> ChannelState {
+        let mut rendering = RenderingService::new(self.variables.clone())
+            .with_connected_content(self.connected.clone())
+            .with_catalogs(self.catalogs.clone())
+            .with_recommendations(self.recommendations.clone());
+        if let Some(profiles) = &self.profiles {
+            rendering = rendering.with_profiles(profiles.clone());
+        }
+        let rendering = Arc::new(rendering);
+
+        let ingest = Arc::new(
+            IngestProcessor::new(vec![
+                campaign_core::channels::IngestSource::MobileApp,
+                campaign_core::channels::IngestSource::Pos,
+                campaign_core::channels::IngestSource::Kiosk,
+                campaign_core::channels::IngestSource::Web,
+                campaign_core::channels::IngestSource::CallCenter,
+                campaign_core::channels::IngestSource::PartnerApi,
+                campaign_core::channels::IngestSource::IoTDevice,
+            ])
+            .with_event_sink(event_sink.clone()),
+        );
+        let activation = ActivationDispatcher::new(vec![
             ActivationChannel::PushNotification,
             ActivationChannel::Sms,
             ActivationChannel::Email,
@@ -208,96 +422,61 @@ impl ApiServer {
             ActivationChannel::PaidMediaAmazon,
             ActivationChannel::DigitalSignage,
             ActivationChannel::KioskDisplay,
-        ]));
-        let sendgrid = Arc::new(SendGridProvider::new(SendGridConfig::default()));
-        let channel_state = ChannelState {
+        ])
+        .with_event_sink(event_sink.clone())
+        .with_rendering(rendering.clone());
+        let activation = Arc::new(match self.mobile_push(&event_sink) {
+            Some(mobile_push) => activation.with_mobile_push(mobile_push),
+            None => activation,
+        });
+        let sendgrid = Arc::new(
+            SendGridProvider::new(self.config.sendgrid.clone())
+                .with_rendering(rendering.clone())
+                .with_event_sink(event_sink.clone()),
+        );
+        let sms = Arc::new(
+            SmsProvider::new(self.config.twilio.clone())
+                .with_suppression_list(self.suppression.clone())
+                .with_rendering(rendering.clone())
+                .with_event_sink(event_sink.clone()),
+        );
+        let whatsapp = Arc::new(
+            WhatsAppProvider::from_config(&self.config.whatsapp)
+                .with_journey_engine(self.journeys.clone())
+                .with_rendering(rendering.clone())
+                .with_event_sink(event_sink),
+        );
+        sendgrid
+            .clone()
+            .spawn_attribution_sweep(CHANNEL_STATE_SWEEP_TICK);
+        whatsapp.clone().spawn_state_sweep(CHANNEL_STATE_SWEEP_TICK);
+        if self.config.whatsapp.business_account_id.is_some() {
+            whatsapp
+                .clone()
+                .spawn_template_sync(std::time::Duration::from_secs(
+                    self.config.whatsapp.template_sync_interval_secs,
+                ));
+        }
+        ChannelState {
             ingest,
             activation,
             sendgrid,
-        };
-
-        // Bid routes (stricter body limit for bid requests)
-        let bid_routes = Router::new()
-            .route("/v1/bid", post(rest::handle_bid))
-            .layer(DefaultBodyLimit::max(MAX_BID_BODY_SIZE))
-            .with_state(state.clone());
-
-        // Operational routes
-        let ops_routes = Router::new()
-            .route("/health", get(rest::health_check))
-            .route("/ready", get(rest::readiness))
-            .route("/live", get(rest::liveness))
-            .with_state(state);
-
-        // Loyalty routes
-        let loyalty_routes = Router::new()
-            .route("/v1/loyalty/earn", post(loyalty_rest::handle_earn_stars))
-            .route("/v1/loyalty/redeem", post(loyalty_rest::handle_redeem))
-            .route(
-                "/v1/loyalty/balance/{user_id}",
-                get(loyalty_rest::handle_balance),
-            )
-            .route(
-                "/v1/loyalty/reward-signal",
-                post(loyalty_rest::handle_reward_signal),
-            )
-            .with_state(loyalty_state);
-
-        // DSP routes
-        let dsp_routes = Router::new()
-            .route("/v1/dsp/bid", post(dsp_rest::handle_dsp_bid))
-            .route("/v1/dsp/win", post(dsp_rest::handle_dsp_win))
-            .route("/v1/dsp/status", get(dsp_rest::handle_dsp_status))
-            .with_state(dsp_state);
-
-        // Channel routes
-        let channel_routes = Router::new()
-            .route("/v1/channels/ingest", post(channel_rest::handle_ingest))
-            .route("/v1/channels/activate", post(channel_rest::handle_activate))
-            .route(
-                "/v1/webhooks/sendgrid",
-                post(channel_rest::handle_sendgrid_webhook),
-            )
-            .route(
-                "/v1/channels/email/analytics/{activation_id}",
-                get(channel_rest::handle_email_analytics),
-            )
-            .route(
-                "/v1/channels/email/analytics",
-                get(channel_rest::handle_all_email_analytics),
-            )
-            .with_state(channel_state);
-
-        // Management UI routes (with auth middleware)
-        let mgmt_routes = campaign_management::management_router().layer(middleware::from_fn(
-            campaign_management::auth::auth_middleware,
-        ));
-
-        // Swagger UI + OpenAPI JSON
-        let swagger_ui = SwaggerUi::new("/swagger-ui")
-            .url("/api-docs/openapi.json", ApiDoc::openapi());
-
-        let app = Router::new()
-            .merge(bid_routes)
-            .merge(ops_routes)
-            .merge(loyalty_routes)
-            .merge(dsp_routes)
-            .merge(channel_routes)
-            .merge(mgmt_routes)
-            .merge(swagger_ui)
-            .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
-            .layer(CompressionLayer::new())
-            .layer(CorsLayer::permissive())
-            .layer(TraceLayer::new_for_http());
-
-        let addr = SocketAddr::new(self.config.api.host.parse()?, self.config.api.http_port);
-
-        info!(addr = %addr, "Starting HTTP server");
-
-        let listener = tokio::net::TcpListener::bind(addr).await?;
-        axum::serve(listener, app).await?;
+            sms,
+            whatsapp,
+            rendering,
+        }
+    }
 
-        Ok(())
+    /// APNs/FCM delivery for push activations, when credentials are
+    /// configured. A bad key is logged and push falls back to the stub.
+    fn mobile_push(&self, event_sink: &Arc<dyn EventSink>) -> Option<Arc<MobilePushProvider>> {
+        match MobilePushProvider::from_config(self.devices.clone(), &self.config.push) {
+            Ok(push) => push.map(|p| Arc::new(p.with_event_sink(event_sink.clone()))),
+            Err(e) => {
+                error!(error = %e, "Failed to initialize mobile push providers");
+                None
+            }
+        }
     }
 
     /// Start the metrics server on a separate port.
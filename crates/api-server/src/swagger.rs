@@ -16,6 +16,8 @@ use utoipa::OpenApi;
         (name = "Loyalty", description = "Loyalty program — earn/redeem stars, tier management"),
         (name = "DSP", description = "Demand-Side Platform bid routing and win notifications"),
         (name = "Channels", description = "Omnichannel ingest and activation endpoints"),
+        (name = "Reporting", description = "Live campaign metrics and server-sent events"),
+        (name = "Catalogs", description = "Product catalogs and feed ingestion"),
     ),
     paths(
         // Bidding
@@ -37,8 +39,30 @@ use utoipa::OpenApi;
         crate::channel_rest::handle_ingest,
         crate::channel_rest::handle_activate,
         crate::channel_rest::handle_sendgrid_webhook,
+        crate::channel_rest::handle_sms_inbound,
+        crate::channel_rest::handle_sms_status,
+        crate::channel_rest::handle_whatsapp_verify,
+        crate::channel_rest::handle_whatsapp_webhook,
+        crate::channel_rest::handle_render_preview,
         crate::channel_rest::handle_email_analytics,
         crate::channel_rest::handle_all_email_analytics,
+        crate::channel_rest::handle_register_device,
+        crate::channel_rest::handle_update_push_token,
+        // Reporting
+        crate::reporting_rest::handle_live_snapshots,
+        crate::reporting_rest::handle_live_series,
+        crate::reporting_rest::handle_live_stream,
+        crate::reporting_rest::handle_live_campaigns,
+        crate::reporting_rest::handle_overview,
+        crate::reporting_rest::handle_campaign_metrics,
+        crate::reporting_rest::handle_breakdown,
+        crate::reporting_rest::handle_create_report,
+        crate::reporting_rest::handle_list_reports,
+        crate::reporting_rest::handle_generate_report,
+        // Catalogs
+        crate::catalog_rest::handle_create_catalog,
+        crate::catalog_rest::handle_list_catalogs,
+        crate::catalog_rest::handle_ingest_feed,
     ),
     components(schemas(
         // OpenRTB types
@@ -91,6 +115,9 @@ use utoipa::OpenApi;
         campaign_core::channels::EmailAnalytics,
         crate::channel_rest::IngestResponse,
         crate::channel_rest::ChannelErrorResponse,
+        crate::channel_rest::RenderPreviewRequest,
+        crate::channel_rest::RenderPreviewResponse,
+        crate::channel_rest::DeviceRegistrationResponse,
     ))
 )]
 pub struct ApiDoc;
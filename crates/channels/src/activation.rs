@@ -2,18 +2,38 @@
 //! across multiple output channels (push, SMS, email, paid media, in-store).
 //! Emits `ActivationSent`, `ActivationDelivered`, or `ActivationFailed` events.
 
+use crate::mobile_push::MobilePushProvider;
+use crate::rendering::RenderingService;
 use campaign_core::channels::*;
 use campaign_core::event_bus::{make_event, EventSink};
-use campaign_core::types::EventType;
+use campaign_core::types::{AnalyticsEvent, EventType};
 use chrono::Utc;
 use std::sync::Arc;
 use tracing::{debug, info};
 use uuid::Uuid;
 
+/// Event for an activation, attributed to its campaign, creative and channel
+/// so the rollups and live metrics can break it down.
+fn activation_event(request: &ActivationRequest, event_type: EventType) -> AnalyticsEvent {
+    let mut event = make_event(
+        event_type,
+        &request.activation_id,
+        Some(request.user_id.clone()),
+        Some(request.offer_id.clone()),
+    )
+    .with_attribution(&request.attribution());
+    event.channel = serde_json::to_value(request.channel)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string));
+    event
+}
+
 /// Dispatches activation messages to the appropriate output channel.
 pub struct ActivationDispatcher {
     enabled_channels: Vec<ActivationChannel>,
     event_sink: Arc<dyn EventSink>,
+    mobile_push: Option<Arc<MobilePushProvider>>,
+    rendering: Option<Arc<RenderingService>>,
 }
 
 impl ActivationDispatcher {
@@ -25,6 +45,8 @@ impl ActivationDispatcher {
         Self {
             enabled_channels: channels,
             event_sink: campaign_core::event_bus::noop_sink(),
+            mobile_push: None,
+            rendering: None,
         }
     }
 
@@ -34,15 +56,23 @@ impl ActivationDispatcher {
         self
     }
 
+    /// Route push notifications through APNs/FCM to the user's devices.
+    pub fn with_mobile_push(mut self, mobile_push: Arc<MobilePushProvider>) -> Self {
+        self.mobile_push = Some(mobile_push);
+        self
+    }
+
+    /// Render headline and body for the recipient before dispatching.
+    pub fn with_rendering(mut self, rendering: Arc<RenderingService>) -> Self {
+        self.rendering = Some(rendering);
+        self
+    }
+
     /// Dispatch an activation to the target channel.
     pub async fn dispatch(&self, request: &ActivationRequest) -> ActivationResult {
         if !self.enabled_channels.contains(&request.channel) {
-            self.event_sink.emit(make_event(
-                EventType::ActivationFailed,
-                &request.activation_id,
-                Some(request.user_id.clone()),
-                Some(request.offer_id.clone()),
-            ));
+            self.event_sink
+                .emit(activation_event(request, EventType::ActivationFailed));
 
             return ActivationResult {
                 activation_id: request.activation_id.clone(),
@@ -57,6 +87,31 @@ impl ActivationDispatcher {
 
         let start = std::time::Instant::now();
 
+        let rendered;
+        let request = match &self.rendering {
+            Some(rendering) => match rendering.render_queued_activation(request).await {
+                Ok(r) => {
+                    rendered = r;
+                    &rendered
+                }
+                Err(e) => {
+                    metrics::counter!("activation.render_failed").increment(1);
+                    self.event_sink
+                        .emit(activation_event(request, EventType::ActivationFailed));
+                    return ActivationResult {
+                        activation_id: request.activation_id.clone(),
+                        channel: request.channel,
+                        status: ActivationStatus::Failed,
+                        provider_message_id: None,
+                        latency_ms: start.elapsed().as_millis() as u64,
+                        error: Some(format!("Render failed: {}", e)),
+                        delivered_at: None,
+                    };
+                }
+            },
+            None => request,
+        };
+
         metrics::counter!(
             "activation.dispatched",
             "channel" => request.channel.display_name()
@@ -78,7 +133,8 @@ impl ActivationDispatcher {
             }
         };
 
-        let latency_ms = start.elapsed().as_millis() as u64;
+        let elapsed = start.elapsed();
+        let latency_ms = elapsed.as_millis() as u64;
         metrics::histogram!(
             "activation.latency_ms",
             "channel" => request.channel.display_name()
@@ -91,12 +147,9 @@ impl ActivationDispatcher {
             ActivationStatus::Failed => EventType::ActivationFailed,
             _ => EventType::ActivationSent,
         };
-        self.event_sink.emit(make_event(
-            event_type,
-            &request.activation_id,
-            Some(request.user_id.clone()),
-            Some(request.offer_id.clone()),
-        ));
+        let mut event = activation_event(request, event_type);
+        event.total_latency_us = Some(elapsed.as_micros() as u64);
+        self.event_sink.emit(event);
 
         ActivationResult {
             latency_ms,
@@ -168,14 +221,18 @@ impl ActivationDispatcher {
 
     async fn send_push(&self, req: &ActivationRequest) -> ActivationResult {
         debug!(user_id = %req.user_id, "Sending push notification");
-        ActivationResult {
-            activation_id: req.activation_id.clone(),
-            channel: ActivationChannel::PushNotification,
-            status: ActivationStatus::Sent,
-            provider_message_id: Some(Uuid::new_v4().to_string()),
-            latency_ms: 0,
-            error: None,
-            delivered_at: Some(Utc::now()),
+        match &self.mobile_push {
+            Some(mobile_push) => mobile_push.dispatch(req).await,
+            // No APNs/FCM credentials configured: keep the stub behaviour.
+            None => ActivationResult {
+                activation_id: req.activation_id.clone(),
+                channel: ActivationChannel::PushNotification,
+                status: ActivationStatus::Sent,
+                provider_message_id: Some(Uuid::new_v4().to_string()),
+                latency_ms: 0,
+                error: None,
+                delivered_at: Some(Utc::now()),
+            },
         }
     }
 
@@ -0,0 +1,433 @@
+//! Apple Push Notification service provider (HTTP/2, token-based auth).
+//!
+//! Requests are authorized with an ES256 provider token signed by the
+//! team's `.p8` key. The token is cached and refreshed well inside Apple's
+//! one-hour validity window.
+
+use crate::mobile_push::{PushSendOutcome, RichPushPayload};
+use chrono::Utc;
+use jsonwebtoken::{Algorithm, EncodingKey, Header};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::Duration;
+
+/// Provider tokens are valid for an hour; refresh after 50 minutes.
+const PROVIDER_TOKEN_REFRESH_SECS: i64 = 50 * 60;
+
+/// Rejection reasons that mean the device token itself is unusable.
+const INVALID_TOKEN_REASONS: &[&str] =
+    &["BadDeviceToken", "Unregistered", "DeviceTokenNotForTopic"];
+
+/// Configuration for token-based APNs authentication.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ApnsConfig {
+    pub team_id: String,
+    pub key_id: String,
+    /// Contents of the `AuthKey_<key_id>.p8` file (PKCS#8 PEM).
+    pub private_key_pem: String,
+    /// App bundle ID, sent as `apns-topic`.
+    pub bundle_id: String,
+    pub endpoint: String,
+    pub sandbox_endpoint: String,
+    pub request_timeout_ms: u64,
+}
+
+impl Default for ApnsConfig {
+    fn default() -> Self {
+        Self {
+            team_id: String::new(),
+            key_id: String::new(),
+            private_key_pem: String::new(),
+            bundle_id: String::new(),
+            endpoint: "https://api.push.apple.com".to_string(),
+            sandbox_endpoint: "https://api.sandbox.push.apple.com".to_string(),
+            request_timeout_ms: 10_000,
+        }
+    }
+}
+
+#[derive(Serialize)]
+struct ProviderTokenClaims<'a> {
+    iss: &'a str,
+    iat: i64,
+}
+
+#[derive(Deserialize)]
+struct ApnsErrorBody {
+    reason: String,
+}
+
+/// APNs sender.
+pub struct ApnsProvider {
+    config: ApnsConfig,
+    http: reqwest::Client,
+    signing_key: EncodingKey,
+    /// Cached provider token and the time it was issued.
+    provider_token: Mutex<Option<(String, i64)>>,
+}
+
+impl ApnsProvider {
+    pub fn new(config: ApnsConfig) -> anyhow::Result<Self> {
+        let signing_key = EncodingKey::from_ec_pem(config.private_key_pem.as_bytes())
+            .map_err(|e| anyhow::anyhow!("invalid APNs .p8 key: {}", e))?;
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.request_timeout_ms))
+            .build()?;
+
+        tracing::info!(
+            team_id = %config.team_id,
+            key_id = %config.key_id,
+            bundle_id = %config.bundle_id,
+            "APNs provider initialized"
+        );
+        Ok(Self {
+            config,
+            http,
+            signing_key,
+            provider_token: Mutex::new(None),
+        })
+    }
+
+    /// Send a push to one device token.
+    pub async fn send(
+        &self,
+        device_token: &str,
+        payload: &RichPushPayload,
+        sandbox: bool,
+    ) -> PushSendOutcome {
+        let token = match self.provider_token() {
+            Ok(t) => t,
+            Err(e) => {
+                return PushSendOutcome::Failed {
+                    status: None,
+                    error: e.to_string(),
+                }
+            }
+        };
+
+        let base = if sandbox {
+            &self.config.sandbox_endpoint
+        } else {
+            &self.config.endpoint
+        };
+        let url = format!("{}/3/device/{}", base.trim_end_matches('/'), device_token);
+        let expiration = if payload.ttl_seconds == 0 {
+            0
+        } else {
+            Utc::now().timestamp() + payload.ttl_seconds as i64
+        };
+
+        let mut request = self
+            .http
+            .post(&url)
+            .bearer_auth(token)
+            .header("apns-topic", &self.config.bundle_id)
+            .header("apns-push-type", "alert")
+            .header(
+                "apns-priority",
+                if payload.high_priority { "10" } else { "5" },
+            )
+            .header("apns-expiration", expiration.to_string())
+            .json(&Self::build_payload(payload));
+        if let Some(collapse_id) = &payload.collapse_id {
+            request = request.header("apns-collapse-id", collapse_id);
+        }
+
+        let response = match request.send().await {
+            Ok(r) => r,
+            Err(e) => {
+                return PushSendOutcome::Failed {
+                    status: None,
+                    error: e.to_string(),
+                }
+            }
+        };
+
+        let status = response.status().as_u16();
+        if response.status().is_success() {
+            let apns_id = response
+                .headers()
+                .get("apns-id")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            return PushSendOutcome::Accepted {
+                message_id: apns_id,
+            };
+        }
+
+        let reason = response
+            .json::<ApnsErrorBody>()
+            .await
+            .map(|b| b.reason)
+            .unwrap_or_else(|_| format!("HTTP {}", status));
+        if reason == "ExpiredProviderToken" || reason == "InvalidProviderToken" {
+            *self
+                .provider_token
+                .lock()
+                .expect("apns token mutex poisoned") = None;
+        }
+        if INVALID_TOKEN_REASONS.contains(&reason.as_str()) {
+            PushSendOutcome::InvalidToken { reason }
+        } else {
+            PushSendOutcome::Failed {
+                status: Some(status),
+                error: reason,
+            }
+        }
+    }
+
+    /// Return the cached provider token, signing a new one if it is stale.
+    fn provider_token(&self) -> anyhow::Result<String> {
+        let now = Utc::now().timestamp();
+        let mut cached = self
+            .provider_token
+            .lock()
+            .expect("apns token mutex poisoned");
+        if let Some((token, issued_at)) = cached.as_ref() {
+            if now - issued_at < PROVIDER_TOKEN_REFRESH_SECS {
+                return Ok(token.clone());
+            }
+        }
+
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some(self.config.key_id.clone());
+        let claims = ProviderTokenClaims {
+            iss: &self.config.team_id,
+            iat: now,
+        };
+        let token = jsonwebtoken::encode(&header, &claims, &self.signing_key)?;
+        *cached = Some((token.clone(), now));
+        Ok(token)
+    }
+
+    /// Build the APNs JSON body. `mutable-content` lets the app's
+    /// notification service extension download the image.
+    fn build_payload(payload: &RichPushPayload) -> serde_json::Value {
+        let mut aps = serde_json::json!({
+            "alert": {
+                "title": payload.title,
+                "body": payload.body,
+            },
+        });
+        if let Some(sound) = &payload.sound {
+            aps["sound"] = serde_json::json!(sound);
+        }
+        if let Some(badge) = payload.badge {
+            aps["badge"] = serde_json::json!(badge);
+        }
+        if let Some(category) = &payload.category {
+            aps["category"] = serde_json::json!(category);
+        }
+        if payload.image_url.is_some() {
+            aps["mutable-content"] = serde_json::json!(1);
+        }
+
+        let mut body = serde_json::json!({ "aps": aps });
+        for (key, value) in &payload.data {
+            body[key] = serde_json::json!(value);
+        }
+        if let Some(image_url) = &payload.image_url {
+            body["image_url"] = serde_json::json!(image_url);
+        }
+        if let Some(deep_link) = &payload.deep_link {
+            body["deep_link"] = serde_json::json!(deep_link);
+        }
+        if !payload.actions.is_empty() {
+            body["actions"] = serde_json::json!(payload.actions);
+        }
+        body
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::Path;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Json;
+    use p256::pkcs8::{EncodePrivateKey, LineEnding};
+    use std::collections::HashMap;
+    use std::sync::Arc;
+
+    #[derive(Default)]
+    struct Recorded {
+        headers: Mutex<Vec<HeaderMap>>,
+        bodies: Mutex<Vec<serde_json::Value>>,
+    }
+
+    /// Mock APNs endpoint. The device token picks the response: `bad`,
+    /// `expired-auth`, `down` or success.
+    async fn mock_apns() -> (String, Arc<Recorded>) {
+        let recorded = Arc::new(Recorded::default());
+        let on_send = recorded.clone();
+        let app = axum::Router::new().route(
+            "/3/device/:token",
+            post(
+                move |Path(token): Path<String>,
+                      headers: HeaderMap,
+                      Json(body): Json<serde_json::Value>| {
+                    let recorded = on_send.clone();
+                    async move {
+                        recorded.headers.lock().unwrap().push(headers);
+                        recorded.bodies.lock().unwrap().push(body);
+                        let reject = |status: StatusCode, reason: &str| {
+                            (
+                                status,
+                                [("apns-id", "")],
+                                format!(r#"{{"reason":"{}"}}"#, reason),
+                            )
+                        };
+                        match token.as_str() {
+                            "bad" => reject(StatusCode::BAD_REQUEST, "BadDeviceToken"),
+                            "expired-auth" => reject(StatusCode::FORBIDDEN, "ExpiredProviderToken"),
+                            "down" => (
+                                StatusCode::INTERNAL_SERVER_ERROR,
+                                [("apns-id", "")],
+                                String::new(),
+                            ),
+                            _ => (StatusCode::OK, [("apns-id", "apns-42")], String::new()),
+                        }
+                    }
+                },
+            ),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (base, recorded)
+    }
+
+    fn provider(base: &str) -> ApnsProvider {
+        let p8 = p256::SecretKey::from_slice(&[3u8; 32])
+            .unwrap()
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+        ApnsProvider::new(ApnsConfig {
+            team_id: "TEAM123".to_string(),
+            key_id: "KEY123".to_string(),
+            private_key_pem: p8.to_string(),
+            bundle_id: "io.campaignexpress.app".to_string(),
+            endpoint: format!("{}/production", base),
+            sandbox_endpoint: base.to_string(),
+            request_timeout_ms: 5_000,
+        })
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_send_sets_apns_headers_and_payload() {
+        let (base, recorded) = mock_apns().await;
+        let apns = provider(&base);
+        let mut data = HashMap::new();
+        data.insert("offer_id".to_string(), "offer-1".to_string());
+        let payload = RichPushPayload {
+            title: "Flash sale".to_string(),
+            body: "Ends tonight".to_string(),
+            image_url: Some("https://cdn.example.com/sale.png".to_string()),
+            deep_link: Some("app://sale".to_string()),
+            category: Some("SALE".to_string()),
+            data,
+            badge: Some(2),
+            sound: Some("default".to_string()),
+            collapse_id: Some("sale".to_string()),
+            ttl_seconds: 600,
+            high_priority: true,
+            ..Default::default()
+        };
+
+        let outcome = apns.send("device-1", &payload, true).await;
+        assert_eq!(
+            outcome,
+            PushSendOutcome::Accepted {
+                message_id: Some("apns-42".to_string())
+            }
+        );
+
+        let headers = recorded.headers.lock().unwrap()[0].clone();
+        assert_eq!(headers["apns-topic"], "io.campaignexpress.app");
+        assert_eq!(headers["apns-push-type"], "alert");
+        assert_eq!(headers["apns-priority"], "10");
+        assert_eq!(headers["apns-collapse-id"], "sale");
+        let expiration: i64 = headers["apns-expiration"]
+            .to_str()
+            .unwrap()
+            .parse()
+            .unwrap();
+        assert!(expiration > Utc::now().timestamp());
+
+        let bearer = headers["authorization"].to_str().unwrap();
+        let jwt = jsonwebtoken::decode_header(bearer.trim_start_matches("Bearer ")).unwrap();
+        assert_eq!(jwt.alg, Algorithm::ES256);
+        assert_eq!(jwt.kid.as_deref(), Some("KEY123"));
+
+        let body = recorded.bodies.lock().unwrap()[0].clone();
+        assert_eq!(body["aps"]["alert"]["title"], "Flash sale");
+        assert_eq!(body["aps"]["badge"], 2);
+        assert_eq!(body["aps"]["category"], "SALE");
+        assert_eq!(body["aps"]["mutable-content"], 1);
+        assert_eq!(body["offer_id"], "offer-1");
+        assert_eq!(body["deep_link"], "app://sale");
+        assert_eq!(body["image_url"], "https://cdn.example.com/sale.png");
+    }
+
+    #[tokio::test]
+    async fn test_zero_ttl_sends_immediate_expiration() {
+        let (base, recorded) = mock_apns().await;
+        provider(&base)
+            .send("device-1", &RichPushPayload::default(), true)
+            .await;
+        let headers = recorded.headers.lock().unwrap()[0].clone();
+        assert_eq!(headers["apns-expiration"], "0");
+        assert_eq!(headers["apns-priority"], "5");
+        assert!(headers.get("apns-collapse-id").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_maps_rejections() {
+        let (base, _) = mock_apns().await;
+        let apns = provider(&base);
+        let payload = RichPushPayload::default();
+
+        assert_eq!(
+            apns.send("bad", &payload, true).await,
+            PushSendOutcome::InvalidToken {
+                reason: "BadDeviceToken".to_string()
+            }
+        );
+        assert_eq!(
+            apns.send("down", &payload, true).await,
+            PushSendOutcome::Failed {
+                status: Some(500),
+                error: "HTTP 500".to_string()
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn test_expired_provider_token_is_discarded() {
+        let (base, _) = mock_apns().await;
+        let apns = provider(&base);
+        let payload = RichPushPayload::default();
+
+        apns.send("device-1", &payload, true).await;
+        assert!(apns.provider_token.lock().unwrap().is_some());
+        assert!(matches!(
+            apns.send("expired-auth", &payload, true).await,
+            PushSendOutcome::Failed {
+                status: Some(403),
+                ..
+            }
+        ));
+        assert!(apns.provider_token.lock().unwrap().is_none());
+    }
+
+    #[test]
+    fn test_invalid_p8_key_rejected() {
+        assert!(ApnsProvider::new(ApnsConfig {
+            private_key_pem: "not a key".to_string(),
+            ..Default::default()
+        })
+        .is_err());
+    }
+}
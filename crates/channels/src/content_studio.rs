@@ -4,6 +4,8 @@
 //!
 //! Addresses FR-CNT-001 through FR-CNT-005.
 
+use crate::email_compiler::{CompiledEmail, EmailCompiler};
+use campaign_personalization::templating::OutputFormat;
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
@@ -138,73 +140,19 @@ impl EmailTemplateBuilder {
         self.templates.get(id).map(|t| t.clone())
     }
 
-    /// Render the template to HTML (simplified).
-    pub fn render_html(&self, template_id: &Uuid) -> Result<String, String> {
+    /// Compile the template to responsive HTML, a plain-text alternative
+    /// and a size report. Fails if any block links to a disallowed URL.
+    pub fn compile(&self, template_id: &Uuid) -> Result<CompiledEmail, String> {
         let entry = self
             .templates
             .get(template_id)
             .ok_or("Template not found")?;
+        EmailCompiler::compile(&entry)
+    }
 
-        let mut html = String::from("<!DOCTYPE html><html><head><meta charset=\"utf-8\"><meta name=\"viewport\" content=\"width=device-width,initial-scale=1\"></head><body>\n");
-
-        for block in &entry.blocks {
-            match block.block_type {
-                BlockType::Hero => {
-                    let src = block
-                        .content
-                        .get("image_url")
-                        .map(|s| s.as_str())
-                        .unwrap_or("#");
-                    let alt = block
-                        .content
-                        .get("alt_text")
-                        .map(|s| s.as_str())
-                        .unwrap_or("");
-                    html.push_str(&format!("<div class=\"hero\"><img src=\"{}\" alt=\"{}\" style=\"width:100%;\"/></div>\n", src, alt));
-                }
-                BlockType::Text => {
-                    let text = block.content.get("text").map(|s| s.as_str()).unwrap_or("");
-                    html.push_str(&format!(
-                        "<div class=\"text-block\"><p>{}</p></div>\n",
-                        text
-                    ));
-                }
-                BlockType::Button => {
-                    let label = block
-                        .content
-                        .get("label")
-                        .map(|s| s.as_str())
-                        .unwrap_or("Click");
-                    let url = block.content.get("url").map(|s| s.as_str()).unwrap_or("#");
-                    html.push_str(&format!(
-                        "<div class=\"button-block\"><a href=\"{}\" class=\"btn\">{}</a></div>\n",
-                        url, label
-                    ));
-                }
-                BlockType::Divider => {
-                    html.push_str("<hr class=\"divider\"/>\n");
-                }
-                BlockType::Spacer => {
-                    let height = block
-                        .content
-                        .get("height")
-                        .map(|s| s.as_str())
-                        .unwrap_or("20");
-                    html.push_str(&format!("<div style=\"height:{}px;\"></div>\n", height));
-                }
-                BlockType::LegalFooter => {
-                    let text = block.content.get("text").map(|s| s.as_str()).unwrap_or("");
-                    html.push_str(&format!("<footer class=\"legal\">{}</footer>\n", text));
-                }
-                _ => {
-                    let content = block.content.get("html").map(|s| s.as_str()).unwrap_or("");
-                    html.push_str(&format!("<div class=\"block\">{}</div>\n", content));
-                }
-            }
-        }
-
-        html.push_str("</body></html>");
-        Ok(html)
+    /// Render the template to HTML.
+    pub fn render_html(&self, template_id: &Uuid) -> Result<String, String> {
+        self.compile(template_id).map(|compiled| compiled.html)
     }
 }
 
@@ -236,51 +184,32 @@ pub enum HtmlLintSeverity {
 pub struct HtmlEditor;
 
 impl HtmlEditor {
-    /// Sanitize HTML by removing dangerous tags and attributes.
+    /// Sanitize HTML against an allowlist of tags, attributes and URL
+    /// schemes. Scripts, styles and other active content are dropped along
+    /// with their contents; inline `style`, layout attributes and Liquid
+    /// placeholders survive so builder HTML still renders in email clients.
     pub fn sanitize(html: &str) -> String {
-        let dangerous_tags = [
-            "<script",
-            "</script>",
-            "<iframe",
-            "</iframe>",
-            "<object",
-            "</object>",
-            "<embed",
-            "</embed>",
-            "<applet",
-            "</applet>",
-            "javascript:",
-            "onerror=",
-            "onclick=",
-            "onload=",
-            "onmouseover=",
-        ];
-
-        let mut sanitized = html.to_string();
-        for tag in &dangerous_tags {
-            // Case-insensitive removal
-            let lower = sanitized.to_lowercase();
-            if let Some(pos) = lower.find(&tag.to_lowercase()) {
-                let end = if tag.starts_with("on") || tag.starts_with("javascript") {
-                    // Remove up to next quote or >
-                    sanitized[pos..]
-                        .find(['"', '\'', '>', ' '])
-                        .map(|e| pos + e)
-                        .unwrap_or(sanitized.len())
-                } else if tag.starts_with("</") {
-                    pos + tag.len()
-                } else {
-                    // Remove to closing >
-                    sanitized[pos..]
-                        .find('>')
-                        .map(|e| pos + e + 1)
-                        .unwrap_or(sanitized.len())
-                };
-                sanitized = format!("{}{}", &sanitized[..pos], &sanitized[end..]);
-            }
-        }
-
-        sanitized
+        ammonia::Builder::default()
+            .add_generic_attributes(&[
+                "style",
+                "class",
+                "align",
+                "valign",
+                "width",
+                "height",
+                "bgcolor",
+                "border",
+                "cellpadding",
+                "cellspacing",
+            ])
+            .attribute_filter(|_, attribute, value| {
+                let lower = value.to_ascii_lowercase();
+                let scripted = attribute == "style"
+                    && (lower.contains("expression(") || lower.contains("javascript:"));
+                (!scripted).then(|| value.into())
+            })
+            .clean(html)
+            .to_string()
     }
 
     /// Lint HTML for common issues.
@@ -340,14 +269,12 @@ impl HtmlEditor {
         issues
     }
 
-    /// Render HTML template with test data variables.
+    /// Render HTML template with test data variables. Values are
+    /// HTML-escaped; a template that fails to parse is returned unchanged
+    /// (`RenderingService::validate` reports the error).
     pub fn render_with_data(html: &str, data: &HashMap<String, String>) -> String {
-        let mut rendered = html.to_string();
-        for (key, value) in data {
-            rendered = rendered.replace(&format!("{{{{{}}}}}", key), value);
-            rendered = rendered.replace(&format!("{{{{ {} }}}}", key), value);
-        }
-        rendered
+        crate::rendering::render_with_variables(html, data, OutputFormat::Html)
+            .unwrap_or_else(|_| html.to_string())
     }
 }
 
@@ -539,6 +466,14 @@ impl VariableBrowser {
             .collect()
     }
 
+    pub fn get(&self, name: &str) -> Option<MessageVariable> {
+        self.variables.get(name).map(|v| v.clone())
+    }
+
+    pub fn list(&self) -> Vec<MessageVariable> {
+        self.variables.iter().map(|e| e.value().clone()).collect()
+    }
+
     pub fn register_variable(&self, var: MessageVariable) {
         self.variables.insert(var.name.clone(), var);
     }
@@ -579,12 +514,14 @@ pub enum PersonalizationCondition {
     Always,
 }
 
-/// Engine for applying per-block personalization at render time.
-pub struct RenderTimePersonalizer;
+/// Engine for applying per-block personalization at render time. Only
+/// reachable through [`RenderingService`](crate::rendering::RenderingService),
+/// which renders the selected content afterwards.
+pub(crate) struct RenderTimePersonalizer;
 
 impl RenderTimePersonalizer {
     /// Apply personalization rules to block content.
-    pub fn personalize_block(
+    pub(crate) fn personalize_block(
         block: &EmailBlock,
         rules: &[PersonalizationRule],
         user_segments: &[u32],
@@ -618,7 +555,7 @@ impl RenderTimePersonalizer {
     }
 
     /// Render a full template with per-block personalization.
-    pub fn render_personalized(
+    pub(crate) fn render_personalized(
         blocks: &[EmailBlock],
         personalizations: &[BlockPersonalization],
         user_segments: &[u32],
@@ -963,6 +900,38 @@ mod tests {
         assert!(!clean.to_lowercase().contains("onerror"));
     }
 
+    #[test]
+    fn test_html_sanitization_handles_repeated_and_mixed_case_payloads() {
+        let dirty = concat!(
+            "<p style=\"color:red\">Hi {{ first_name }}</p>",
+            "<script>one()</script><SCRIPT>two()</SCRIPT><ScRiPt src=x></sCrIpT>",
+            "<scr<script>ipt>nested()</script>",
+            "<a href=\"javascript:alert(1)\">a</a><a HREF=\"JaVaScRiPt:alert(2)\">b</a>",
+            "<img src=x onerror=alert(1)><img src=y ONERROR=alert(2) OnLoad=alert(3)>",
+            "<iframe src=\"https://evil.test\"></iframe><IFRAME></IFRAME>",
+            "<div style=\"width: expression(alert(1))\" onclick=\"x()\">d</div>",
+            "<a href=\"{{ offer_url }}\">Shop</a>",
+        );
+        let clean = HtmlEditor::sanitize(dirty).to_lowercase();
+        for banned in [
+            "<script",
+            "one()",
+            "two()",
+            "javascript:",
+            "onerror",
+            "onload",
+            "onclick",
+            "<iframe",
+            "expression(",
+        ] {
+            assert!(!clean.contains(banned), "{banned} survived in {clean}");
+        }
+        // The split tag is left as inert, escaped text.
+        assert!(clean.contains("ipt&gt;nested()"));
+        assert!(clean.contains("<p style=\"color:red\">hi {{ first_name }}</p>"));
+        assert!(clean.contains("href=\"{{ offer_url }}\""));
+    }
+
     #[test]
     fn test_html_linting() {
         let html = "<img src=\"test.jpg\"><a href=\"\">Click</a><font>old</font>";
@@ -2,23 +2,54 @@
 //!
 //! Handles email sending via SendGrid API and processes inbound webhook
 //! events for tracking: delivered, opened, clicked, bounced, unsubscribed.
+//! Inbound webhooks are only trusted after ECDSA signature verification.
 
+use crate::rendering::RenderingService;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use campaign_core::channels::*;
 use campaign_core::event_bus::{make_event, EventSink};
-use campaign_core::types::EventType;
+use campaign_core::types::{Attribution, EventType};
 use dashmap::DashMap;
+use p256::ecdsa::signature::Verifier;
+use p256::ecdsa::{Signature, VerifyingKey};
+use p256::pkcs8::DecodePublicKey;
 use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinHandle;
 use tracing::{debug, info, warn};
 
+/// Header carrying the base64 ECDSA signature of a signed event webhook.
+pub const SIGNATURE_HEADER: &str = "X-Twilio-Email-Event-Webhook-Signature";
+/// Header carrying the timestamp that was signed along with the payload.
+pub const TIMESTAMP_HEADER: &str = "X-Twilio-Email-Event-Webhook-Timestamp";
+
+/// Signed webhooks older or newer than this are rejected as replays.
+pub const WEBHOOK_TIMESTAMP_TOLERANCE: Duration = Duration::from_secs(5 * 60);
+
+/// Upper bound on a server-supplied `Retry-After` so one send can't stall.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Attribution for an activation that never got a delivered, bounce or
+/// dropped webhook is swept after this long.
+pub const ATTRIBUTION_TTL: Duration = Duration::from_secs(72 * 60 * 60);
+
 /// SendGrid email activation provider.
 pub struct SendGridProvider {
     config: SendGridConfig,
+    http: reqwest::Client,
+    verifying_key: Option<VerifyingKey>,
     /// Track email analytics keyed by activation_id.
     analytics: DashMap<String, EmailAnalytics>,
     /// Track unique openers/clickers per activation.
     unique_opens: DashMap<String, std::collections::HashSet<String>>,
     unique_clicks: DashMap<String, std::collections::HashSet<String>>,
+    /// Campaign and creative per activation, for attributing webhook
+    /// events, with when the send started. Dropped on the terminal webhook
+    /// or by [`Self::prune_attribution`].
+    attribution: DashMap<String, (Attribution, Instant)>,
     event_sink: Arc<dyn EventSink>,
+    rendering: Option<Arc<RenderingService>>,
 }
 
 impl SendGridProvider {
@@ -26,14 +57,35 @@ impl SendGridProvider {
         info!(
             from = %config.from_email,
             tracking = config.tracking_enabled,
+            signed_webhooks = config.webhook_verification_key.is_some(),
             "SendGrid provider initialized"
         );
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_millis(config.request_timeout_ms))
+            .build()
+            .unwrap_or_default();
+
+        let verifying_key = config.webhook_verification_key.as_deref().and_then(|key| {
+            let der = BASE64
+                .decode(key.trim())
+                .map_err(|e| warn!(error = %e, "SendGrid verification key is not base64"))
+                .ok()?;
+            VerifyingKey::from_public_key_der(&der)
+                .map_err(|e| warn!(error = %e, "SendGrid verification key is not a P-256 key"))
+                .ok()
+        });
+
         Self {
             config,
+            http,
+            verifying_key,
             analytics: DashMap::new(),
             unique_opens: DashMap::new(),
             unique_clicks: DashMap::new(),
+            attribution: DashMap::new(),
             event_sink: campaign_core::event_bus::noop_sink(),
+            rendering: None,
         }
     }
 
@@ -43,10 +95,23 @@ impl SendGridProvider {
         self
     }
 
-    /// Send an email via SendGrid API.
-    /// In production: POST to https://api.sendgrid.com/v3/mail/send
+    /// Render subject and body for the recipient before sending.
+    pub fn with_rendering(mut self, rendering: Arc<RenderingService>) -> Self {
+        self.rendering = Some(rendering);
+        self
+    }
+
+    /// Send an email via the SendGrid v3 `mail/send` API.
+    ///
+    /// Retries on 429 and 5xx responses with exponential backoff, honouring
+    /// `Retry-After` when SendGrid provides it. The returned result carries
+    /// SendGrid's `X-Message-Id` as the provider message ID.
     pub async fn send_email(&self, req: &ActivationRequest, to_email: &str) -> ActivationResult {
         let start = std::time::Instant::now();
+        self.attribution.insert(
+            req.activation_id.clone(),
+            (req.attribution(), Instant::now()),
+        );
 
         debug!(
             user_id = %req.user_id,
@@ -55,21 +120,112 @@ impl SendGridProvider {
             "Sending email via SendGrid"
         );
 
-        metrics::counter!(
-            "sendgrid.emails_sent",
-            "activation_id" => req.activation_id.clone()
-        )
-        .increment(1);
+        let rendered = match &self.rendering {
+            Some(rendering) => rendering
+                .render_activation(req, &[("email", to_email)])
+                .await
+                .map_err(|e| anyhow::anyhow!("render failed: {}", e)),
+            None => Ok(req.clone()),
+        };
+        let outcome = match rendered {
+            Ok(rendered) => {
+                self.post_with_retries(&self.build_payload(&rendered, to_email))
+                    .await
+            }
+            Err(e) => Err(e),
+        };
+        let latency_ms = start.elapsed().as_millis() as u64;
+
+        match outcome {
+            Ok(sg_message_id) => {
+                metrics::counter!(
+                    "sendgrid.emails_sent",
+                    "activation_id" => req.activation_id.clone()
+                )
+                .increment(1);
+
+                self.analytics
+                    .entry(req.activation_id.clone())
+                    .or_insert_with(|| EmailAnalytics {
+                        activation_id: req.activation_id.clone(),
+                        total_sent: 0,
+                        ..Default::default()
+                    })
+                    .total_sent += 1;
+
+                ActivationResult {
+                    activation_id: req.activation_id.clone(),
+                    channel: ActivationChannel::Email,
+                    status: ActivationStatus::Queued,
+                    provider_message_id: sg_message_id,
+                    latency_ms,
+                    error: None,
+                    delivered_at: None,
+                }
+            }
+            Err(e) => {
+                warn!(
+                    activation_id = %req.activation_id,
+                    error = %e,
+                    "SendGrid send failed"
+                );
+                metrics::counter!("sendgrid.emails_failed").increment(1);
+                // No webhooks follow a send SendGrid never accepted.
+                self.attribution.remove(&req.activation_id);
+                self.event_sink.emit(
+                    make_event(
+                        EventType::ActivationFailed,
+                        &req.activation_id,
+                        Some(req.user_id.clone()),
+                        Some(req.offer_id.clone()),
+                    )
+                    .with_attribution(&req.attribution())
+                    .with_channel("email"),
+                );
+
+                ActivationResult {
+                    activation_id: req.activation_id.clone(),
+                    channel: ActivationChannel::Email,
+                    status: ActivationStatus::Failed,
+                    provider_message_id: None,
+                    latency_ms,
+                    error: Some(e.to_string()),
+                    delivered_at: None,
+                }
+            }
+        }
+    }
+
+    /// Build the `mail/send` request body. `activation_id`, `user_id` and
+    /// `offer_id` ride along as custom args so webhook events can be joined
+    /// back to the activation.
+    fn build_payload(&self, req: &ActivationRequest, to_email: &str) -> serde_json::Value {
+        let mut custom_args = serde_json::json!({
+            "activation_id": req.activation_id,
+            "user_id": req.user_id,
+            "offer_id": req.offer_id,
+        });
+        if let Some(campaign_id) = &req.campaign_id {
+            custom_args["campaign_id"] = serde_json::json!(campaign_id);
+        }
+        if let Some(variant_id) = &req.experiment_variant_id {
+            custom_args["experiment_variant_id"] = serde_json::json!(variant_id);
+        }
 
-        // Build SendGrid API payload (stub — in production, HTTP POST to SendGrid)
-        let _payload = serde_json::json!({
+        // SendGrid caps a message at 10 categories.
+        let mut categories = self.config.categories.clone();
+        if let Some(campaign_id) = &req.campaign_id {
+            if !categories.contains(campaign_id) {
+                categories.push(campaign_id.clone());
+            }
+        }
+        categories.truncate(10);
+
+        let mut payload = serde_json::json!({
             "personalizations": [{
                 "to": [{"email": to_email}],
-                "custom_args": {
-                    "activation_id": req.activation_id,
-                    "user_id": req.user_id,
-                    "offer_id": req.offer_id
-                }
+                "subject": req.content.headline,
+                "custom_args": custom_args
             }],
             "from": {
                 "email": self.config.from_email,
@@ -81,33 +237,141 @@ impl SendGridProvider {
                 "value": req.content.body
             }],
             "tracking_settings": {
-                "click_tracking": {"enable": self.config.click_tracking},
-                "open_tracking": {"enable": self.config.open_tracking}
+                "click_tracking": {"enable": self.config.tracking_enabled && self.config.click_tracking},
+                "open_tracking": {"enable": self.config.tracking_enabled && self.config.open_tracking}
             }
         });
+        if !categories.is_empty() {
+            payload["categories"] = serde_json::json!(categories);
+        }
+        if let Some(group_id) = self.config.unsubscribe_group_id {
+            payload["asm"] = serde_json::json!({ "group_id": group_id });
+        }
+        if let Some(send_at) = req.scheduled_at {
+            payload["send_at"] = serde_json::json!(send_at.timestamp());
+        }
+        payload
+    }
 
-        // Initialize analytics for this activation
-        self.analytics
-            .entry(req.activation_id.clone())
-            .or_insert_with(|| EmailAnalytics {
-                activation_id: req.activation_id.clone(),
-                total_sent: 0,
-                ..Default::default()
-            })
-            .total_sent += 1;
+    /// POST the payload, retrying transient failures. Returns the
+    /// `X-Message-Id` header on success.
+    async fn post_with_retries(
+        &self,
+        payload: &serde_json::Value,
+    ) -> anyhow::Result<Option<String>> {
+        let url = format!(
+            "{}/v3/mail/send",
+            self.config.api_base_url.trim_end_matches('/')
+        );
+        let mut attempt = 0u32;
 
-        let latency_ms = start.elapsed().as_millis() as u64;
-        let sg_message_id = format!("sg-{}", uuid::Uuid::new_v4());
+        loop {
+            let response = self
+                .http
+                .post(&url)
+                .bearer_auth(&self.config.api_key)
+                .json(payload)
+                .send()
+                .await;
 
-        ActivationResult {
-            activation_id: req.activation_id.clone(),
-            channel: ActivationChannel::Email,
-            status: ActivationStatus::Queued,
-            provider_message_id: Some(sg_message_id),
-            latency_ms,
-            error: None,
-            delivered_at: None,
+            let retry_after = match response {
+                Ok(resp) if resp.status().is_success() => {
+                    return Ok(resp
+                        .headers()
+                        .get("x-message-id")
+                        .and_then(|v| v.to_str().ok())
+                        .map(str::to_string));
+                }
+                Ok(resp) => {
+                    let status = resp.status();
+                    let retryable = status == reqwest::StatusCode::TOO_MANY_REQUESTS
+                        || status.is_server_error();
+                    let retry_after = resp
+                        .headers()
+                        .get(reqwest::header::RETRY_AFTER)
+                        .and_then(|v| v.to_str().ok())
+                        .and_then(|v| v.parse::<u64>().ok())
+                        .map(std::time::Duration::from_secs);
+                    let body = resp.text().await.unwrap_or_default();
+                    if !retryable || attempt >= self.config.max_retries {
+                        anyhow::bail!("SendGrid returned {}: {}", status, body);
+                    }
+                    retry_after
+                }
+                Err(e) => {
+                    if attempt >= self.config.max_retries {
+                        return Err(anyhow::anyhow!("SendGrid request failed: {}", e));
+                    }
+                    None
+                }
+            };
+
+            let backoff = std::time::Duration::from_millis(
+                self.config
+                    .retry_backoff_ms
+                    .saturating_mul(1u64 << attempt.min(16)),
+            );
+            let delay = retry_after.map_or(backoff, |ra| ra.min(MAX_RETRY_AFTER));
+            attempt += 1;
+            metrics::counter!("sendgrid.send_retries").increment(1);
+            debug!(
+                attempt,
+                delay_ms = delay.as_millis() as u64,
+                "Retrying SendGrid send"
+            );
+            tokio::time::sleep(delay).await;
+        }
+    }
+
+    /// Verify a Signed Event Webhook delivery.
+    ///
+    /// SendGrid signs `timestamp || payload` with ECDSA P-256/SHA-256 and
+    /// sends the base64 DER signature and the timestamp in
+    /// [`SIGNATURE_HEADER`] and [`TIMESTAMP_HEADER`]. Fails closed when no
+    /// verification key is configured, and rejects timestamps more than
+    /// [`WEBHOOK_TIMESTAMP_TOLERANCE`] away from now so captured deliveries
+    /// cannot be replayed.
+    pub fn verify_webhook_signature(
+        &self,
+        payload: &[u8],
+        signature: &str,
+        timestamp: &str,
+    ) -> anyhow::Result<()> {
+        self.verify_webhook_signature_at(payload, signature, timestamp, chrono::Utc::now())
+    }
+
+    fn verify_webhook_signature_at(
+        &self,
+        payload: &[u8],
+        signature: &str,
+        timestamp: &str,
+        now: chrono::DateTime<chrono::Utc>,
+    ) -> anyhow::Result<()> {
+        let signed_at: i64 = timestamp
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("webhook timestamp is not a unix time"))?;
+        if signed_at.abs_diff(now.timestamp()) > WEBHOOK_TIMESTAMP_TOLERANCE.as_secs() {
+            anyhow::bail!("webhook timestamp outside the allowed window");
         }
+
+        let key = self
+            .verifying_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("webhook verification key not configured"))?;
+
+        let signature = BASE64
+            .decode(signature.trim())
+            .map_err(|e| anyhow::anyhow!("signature is not valid base64: {}", e))?;
+        let signature = Signature::from_der(&signature)
+            .map_err(|e| anyhow::anyhow!("signature is not valid DER: {}", e))?;
+
+        let mut signed = Vec::with_capacity(timestamp.len() + payload.len());
+        signed.extend_from_slice(timestamp.as_bytes());
+        signed.extend_from_slice(payload);
+
+        key.verify(&signed, &signature)
+            .map_err(|_| anyhow::anyhow!("webhook signature mismatch"))
     }
 
     /// Process a SendGrid webhook event and update analytics.
@@ -139,9 +403,22 @@ impl SendGridProvider {
             EmailEventType::Bounce => Some(EventType::ActivationFailed),
             _ => None,
         };
+        // Delivered, bounce and dropped end the message, so its attribution
+        // is no longer needed.
+        let attribution = match event.event {
+            EmailEventType::Delivered | EmailEventType::Bounce | EmailEventType::Dropped => self
+                .attribution
+                .remove(&activation_id)
+                .map(|(_, (attribution, _))| attribution),
+            _ => None,
+        };
         if let Some(et) = event_type {
-            self.event_sink
-                .emit(make_event(et, &activation_id, None, None));
+            let attribution = attribution.unwrap_or_default();
+            self.event_sink.emit(
+                make_event(et, &activation_id, None, None)
+                    .with_attribution(&attribution)
+                    .with_channel("email"),
+            );
         }
 
         self.analytics
@@ -193,6 +470,30 @@ impl SendGridProvider {
             });
     }
 
+    /// Drop attribution for sends older than `max_age` whose terminal
+    /// webhook never arrived. Returns how many were dropped.
+    pub fn prune_attribution(&self, max_age: Duration) -> usize {
+        let before = self.attribution.len();
+        self.attribution
+            .retain(|_, (_, sent_at)| sent_at.elapsed() < max_age);
+        let pruned = before.saturating_sub(self.attribution.len());
+        if pruned > 0 {
+            metrics::counter!("sendgrid.attribution_pruned").increment(pruned as u64);
+        }
+        pruned
+    }
+
+    /// Prune attribution older than [`ATTRIBUTION_TTL`] every `tick`.
+    pub fn spawn_attribution_sweep(self: Arc<Self>, tick: Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                self.prune_attribution(ATTRIBUTION_TTL);
+            }
+        })
+    }
+
     /// Get analytics for a specific activation.
     pub fn get_analytics(&self, activation_id: &str) -> Option<EmailAnalytics> {
         self.analytics.get(activation_id).map(|a| a.clone())
@@ -210,3 +511,290 @@ impl SendGridProvider {
         &self.config
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use campaign_core::event_bus::CaptureSink;
+    use chrono::Utc;
+    use p256::ecdsa::signature::Signer;
+    use p256::ecdsa::SigningKey;
+    use p256::pkcs8::EncodePublicKey;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+
+    /// Spawn a mock `mail/send` endpoint that answers with `statuses` in
+    /// order (repeating the last one) and records every request body.
+    async fn mock_sendgrid(
+        statuses: Vec<u16>,
+    ) -> (String, Arc<AtomicUsize>, Arc<Mutex<Vec<serde_json::Value>>>) {
+        let hits = Arc::new(AtomicUsize::new(0));
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let (h, b) = (hits.clone(), bodies.clone());
+
+        let app = Router::new().route(
+            "/v3/mail/send",
+            post(move |headers: HeaderMap, body: String| {
+                let n = h.fetch_add(1, Ordering::SeqCst);
+                b.lock().unwrap().push(serde_json::from_str(&body).unwrap());
+                let status = statuses[n.min(statuses.len() - 1)];
+                let authorized = headers
+                    .get("authorization")
+                    .is_some_and(|v| v == "Bearer SG.test");
+                async move {
+                    let status = if authorized {
+                        StatusCode::from_u16(status).unwrap()
+                    } else {
+                        StatusCode::UNAUTHORIZED
+                    };
+                    (status, [("x-message-id", "msg-123")], "")
+                }
+            }),
+        );
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), hits, bodies)
+    }
+
+    fn test_config(base_url: &str) -> SendGridConfig {
+        SendGridConfig {
+            api_key: "SG.test".to_string(),
+            api_base_url: base_url.to_string(),
+            categories: vec!["promo".to_string()],
+            max_retries: 2,
+            retry_backoff_ms: 1,
+            ..Default::default()
+        }
+    }
+
+    fn test_request() -> ActivationRequest {
+        ActivationRequest {
+            activation_id: "act-1".to_string(),
+            decision_id: None,
+            user_id: "user-1".to_string(),
+            channel: ActivationChannel::Email,
+            offer_id: "offer-1".to_string(),
+            content: ActivationContent {
+                headline: "Your offer".to_string(),
+                body: "<p>Hello</p>".to_string(),
+                image_url: None,
+                cta_url: None,
+                cta_text: None,
+                deep_link: None,
+                audience_segment_id: None,
+                extra: None,
+                personalization: None,
+            },
+            priority: 1,
+            scheduled_at: None,
+            created_at: Utc::now(),
+            trigger_event_id: None,
+            trigger_source: None,
+            campaign_id: Some("camp-9".to_string()),
+            experiment_variant_id: None,
+            creative_id: None,
+        }
+    }
+
+    fn signing_key() -> SigningKey {
+        SigningKey::from_slice(&[7u8; 32]).unwrap()
+    }
+
+    fn signed_provider() -> SendGridProvider {
+        let der = signing_key().verifying_key().to_public_key_der().unwrap();
+        SendGridProvider::new(SendGridConfig {
+            webhook_verification_key: Some(BASE64.encode(der.as_bytes())),
+            ..Default::default()
+        })
+    }
+
+    /// The instant the test webhooks were signed.
+    fn signed_now() -> chrono::DateTime<chrono::Utc> {
+        chrono::DateTime::from_timestamp(1_700_000_000, 0).unwrap()
+    }
+
+    fn sign(timestamp: &str, payload: &[u8]) -> String {
+        let mut signed = timestamp.as_bytes().to_vec();
+        signed.extend_from_slice(payload);
+        let signature: Signature = signing_key().sign(&signed);
+        BASE64.encode(signature.to_der().as_bytes())
+    }
+
+    #[tokio::test]
+    async fn test_send_posts_personalization_payload() {
+        let (url, hits, bodies) = mock_sendgrid(vec![202]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+
+        let result = provider.send_email(&test_request(), "a@example.com").await;
+        assert_eq!(result.status, ActivationStatus::Queued);
+        assert_eq!(result.provider_message_id.as_deref(), Some("msg-123"));
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+
+        let body = bodies.lock().unwrap()[0].clone();
+        let personalization = &body["personalizations"][0];
+        assert_eq!(personalization["to"][0]["email"], "a@example.com");
+        assert_eq!(personalization["custom_args"]["activation_id"], "act-1");
+        assert_eq!(body["categories"], serde_json::json!(["promo", "camp-9"]));
+        assert_eq!(provider.get_analytics("act-1").unwrap().total_sent, 1);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_events_keep_campaign_and_creative() {
+        let (url, _, _) = mock_sendgrid(vec![202]).await;
+        let sink = Arc::new(CaptureSink::new());
+        let provider = SendGridProvider::new(test_config(&url)).with_event_sink(sink.clone());
+        let mut req = test_request();
+        req.creative_id = Some("creative-3".to_string());
+        provider.send_email(&req, "a@example.com").await;
+
+        let events: Vec<EmailWebhookEvent> = serde_json::from_slice(
+            br#"[{"email":"a@example.com","event":"delivered","activation_id":"act-1","timestamp":1700000000}]"#,
+        )
+        .unwrap();
+        provider.process_webhook(&events[0]);
+
+        let emitted = sink.events();
+        assert_eq!(emitted.len(), 1);
+        assert_eq!(emitted[0].event_type, EventType::ActivationDelivered);
+        assert_eq!(emitted[0].campaign_id.as_deref(), Some("camp-9"));
+        assert_eq!(emitted[0].creative_id.as_deref(), Some("creative-3"));
+        assert_eq!(emitted[0].channel.as_deref(), Some("email"));
+        assert!(provider.attribution.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_attribution_is_dropped_after_terminal_events_and_ttl() {
+        let (url, _, _) = mock_sendgrid(vec![202]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+        for id in ["act-1", "act-2", "act-3"] {
+            let mut req = test_request();
+            req.activation_id = id.to_string();
+            provider.send_email(&req, "a@example.com").await;
+        }
+        let webhook = |activation_id: &str, event: &str| -> EmailWebhookEvent {
+            serde_json::from_value(serde_json::json!({
+                "email": "a@example.com",
+                "event": event,
+                "activation_id": activation_id,
+                "timestamp": 1700000000,
+            }))
+            .unwrap()
+        };
+        provider.process_webhook(&webhook("act-1", "open"));
+        provider.process_webhook(&webhook("act-2", "dropped"));
+        assert_eq!(provider.attribution.len(), 2);
+
+        assert_eq!(provider.prune_attribution(Duration::from_secs(60)), 0);
+        assert_eq!(provider.prune_attribution(Duration::ZERO), 2);
+        assert!(provider.attribution.is_empty());
+
+        // Failed sends never get webhooks.
+        let (url, _, _) = mock_sendgrid(vec![400]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+        provider.send_email(&test_request(), "a@example.com").await;
+        assert!(provider.attribution.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_send_retries_on_throttle_and_server_error() {
+        let (url, hits, _) = mock_sendgrid(vec![429, 503, 202]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+
+        let result = provider.send_email(&test_request(), "a@example.com").await;
+        assert_eq!(result.status, ActivationStatus::Queued);
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_send_gives_up_after_max_retries() {
+        let (url, hits, _) = mock_sendgrid(vec![500]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+
+        let result = provider.send_email(&test_request(), "a@example.com").await;
+        assert_eq!(result.status, ActivationStatus::Failed);
+        assert!(result.error.is_some());
+        assert_eq!(hits.load(Ordering::SeqCst), 3);
+        assert!(provider.get_analytics("act-1").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_send_does_not_retry_client_errors() {
+        let (url, hits, _) = mock_sendgrid(vec![400]).await;
+        let provider = SendGridProvider::new(test_config(&url));
+
+        let result = provider.send_email(&test_request(), "a@example.com").await;
+        assert_eq!(result.status, ActivationStatus::Failed);
+        assert_eq!(hits.load(Ordering::SeqCst), 1);
+    }
+
+    #[test]
+    fn test_signed_webhook_accepted() {
+        let provider = signed_provider();
+        let payload = br#"[{"email":"a@example.com","event":"open","activation_id":"act-1","timestamp":1700000000}]"#;
+        let signature = sign("1700000000", payload);
+
+        provider
+            .verify_webhook_signature_at(payload, &signature, "1700000000", signed_now())
+            .unwrap();
+        let events: Vec<EmailWebhookEvent> = serde_json::from_slice(payload).unwrap();
+        assert_eq!(events[0].event, EmailEventType::Open);
+        provider.process_webhook(&events[0]);
+        assert_eq!(
+            provider.get_analytics("act-1").unwrap().activation_id,
+            "act-1"
+        );
+    }
+
+    #[test]
+    fn test_tampered_webhook_rejected() {
+        let provider = signed_provider();
+        let payload = br#"[{"email":"a@example.com","event":"click","activation_id":"act-1","timestamp":1700000000}]"#;
+        let signature = sign("1700000000", payload);
+
+        let tampered = br#"[{"email":"b@example.com","event":"click","activation_id":"act-1","timestamp":1700000000}]"#;
+        assert!(provider
+            .verify_webhook_signature_at(tampered, &signature, "1700000000", signed_now())
+            .is_err());
+        assert!(provider
+            .verify_webhook_signature_at(payload, &signature, "1700000001", signed_now())
+            .is_err());
+    }
+
+    #[test]
+    fn test_replayed_webhook_rejected_outside_window() {
+        let provider = signed_provider();
+        let payload = br#"[{"email":"a@example.com","event":"open","activation_id":"act-1"}]"#;
+        let signature = sign("1700000000", payload);
+
+        let late = signed_now() + chrono::Duration::minutes(4);
+        assert!(provider
+            .verify_webhook_signature_at(payload, &signature, "1700000000", late)
+            .is_ok());
+        let replayed = signed_now() + chrono::Duration::minutes(6);
+        assert!(provider
+            .verify_webhook_signature_at(payload, &signature, "1700000000", replayed)
+            .is_err());
+        let future = signed_now() - chrono::Duration::minutes(6);
+        assert!(provider
+            .verify_webhook_signature_at(payload, &signature, "1700000000", future)
+            .is_err());
+        assert!(provider
+            .verify_webhook_signature(payload, &signature, "not-a-time")
+            .is_err());
+    }
+
+    #[test]
+    fn test_webhook_rejected_without_verification_key() {
+        let provider = SendGridProvider::new(SendGridConfig::default());
+        let payload = b"[]";
+        let signature = sign("1700000000", payload);
+        assert!(provider
+            .verify_webhook_signature(payload, &signature, "1700000000")
+            .is_err());
+    }
+}
@@ -0,0 +1,698 @@
+//! Email compiler for builder templates.
+//!
+//! Turns an [`EmailTemplate`] into Outlook-safe, table-based responsive HTML
+//! with inline styles (MJML-style: fixed 600px container, columns that stack
+//! below 620px, VML buttons for Word-rendered Outlook), dark-mode meta tags,
+//! and a plain-text alternative. All block content is escaped; links and
+//! image sources must use an allowed scheme, so `javascript:` and friends
+//! fail compilation instead of reaching an inbox.
+
+use crate::content_studio::{BlockType, EmailBlock, EmailTemplate, HtmlEditor};
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+
+/// Gmail clips messages whose HTML exceeds this many bytes.
+pub const GMAIL_CLIP_BYTES: usize = 102 * 1024;
+
+/// Size at which the report starts warning about clipping.
+pub const SIZE_WARNING_BYTES: usize = GMAIL_CLIP_BYTES * 9 / 10;
+
+const CONTAINER_WIDTH: u32 = 600;
+const DEFAULT_FONT: &str = "Helvetica, Arial, sans-serif";
+const ALLOWED_SCHEMES: &[&str] = &["http", "https", "mailto", "tel"];
+const SOCIAL_NETWORKS: &[(&str, &str)] = &[
+    ("facebook", "Facebook"),
+    ("instagram", "Instagram"),
+    ("twitter", "X"),
+    ("linkedin", "LinkedIn"),
+    ("youtube", "YouTube"),
+    ("tiktok", "TikTok"),
+    ("pinterest", "Pinterest"),
+];
+
+/// HTML and text size of a compiled email against Gmail's clipping limit.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailSizeReport {
+    pub html_bytes: usize,
+    pub text_bytes: usize,
+    pub clip_threshold_bytes: usize,
+    /// The HTML is over [`SIZE_WARNING_BYTES`].
+    pub near_clip_threshold: bool,
+    /// Gmail will clip this message.
+    pub clipped: bool,
+}
+
+/// A compiled email ready to hand to a provider.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CompiledEmail {
+    pub subject: String,
+    pub html: String,
+    pub text: String,
+    pub size: EmailSizeReport,
+    pub warnings: Vec<String>,
+}
+
+/// Compiles builder templates to email HTML.
+pub struct EmailCompiler;
+
+impl EmailCompiler {
+    pub fn compile(template: &EmailTemplate) -> Result<CompiledEmail, String> {
+        let mut rows = String::new();
+        let mut text = Vec::new();
+        let mut lint_warnings = Vec::new();
+        for block in &template.blocks {
+            if block.block_type == BlockType::Html {
+                let html = block.content.get("html").map(String::as_str).unwrap_or("");
+                lint_warnings.extend(
+                    HtmlEditor::lint(html)
+                        .into_iter()
+                        .map(|issue| format!("Block {}: {}", block.id, issue.message)),
+                );
+            }
+            let (html, plain) = Self::compile_block(block)?;
+            rows.push_str(&html);
+            if !plain.trim().is_empty() {
+                text.push(plain.trim().to_string());
+            }
+        }
+
+        let background = style_value(&template.global_styles, "background-color", "#f4f4f4");
+        let content_background = style_value(
+            &template.global_styles,
+            "content-background-color",
+            "#ffffff",
+        );
+        let font = style_value(&template.global_styles, "font-family", DEFAULT_FONT);
+        let color = style_value(&template.global_styles, "color", "#222222");
+        let preheader = template
+            .preheader
+            .as_deref()
+            .map(|p| {
+                format!(
+                    "<div style=\"display:none;font-size:1px;line-height:1px;max-height:0;max-width:0;opacity:0;overflow:hidden;mso-hide:all;\">{}</div>\n",
+                    escape(p)
+                )
+            })
+            .unwrap_or_default();
+
+        let html = format!(
+            r#"<!DOCTYPE html PUBLIC "-//W3C//DTD XHTML 1.0 Transitional//EN" "http://www.w3.org/TR/xhtml1/DTD/xhtml1-transitional.dtd">
+<html xmlns="http://www.w3.org/1999/xhtml" xmlns:v="urn:schemas-microsoft-com:vml" xmlns:o="urn:schemas-microsoft-com:office:office" lang="en">
+<head>
+<meta http-equiv="Content-Type" content="text/html; charset=UTF-8" />
+<meta name="viewport" content="width=device-width, initial-scale=1" />
+<meta http-equiv="X-UA-Compatible" content="IE=edge" />
+<meta name="color-scheme" content="light dark" />
+<meta name="supported-color-schemes" content="light dark" />
+<title>{title}</title>
+<!--[if mso]><noscript><xml><o:OfficeDocumentSettings><o:PixelsPerInch>96</o:PixelsPerInch></o:OfficeDocumentSettings></xml></noscript><![endif]-->
+<style type="text/css">
+:root {{ color-scheme: light dark; supported-color-schemes: light dark; }}
+body {{ margin: 0; padding: 0; -webkit-text-size-adjust: 100%; -ms-text-size-adjust: 100%; }}
+table, td {{ border-collapse: collapse; mso-table-lspace: 0pt; mso-table-rspace: 0pt; }}
+img {{ border: 0; outline: none; text-decoration: none; -ms-interpolation-mode: bicubic; }}
+@media only screen and (max-width: 620px) {{
+  .container {{ width: 100% !important; }}
+  .stack {{ display: block !important; width: 100% !important; max-width: 100% !important; }}
+  .pad {{ padding-left: 16px !important; padding-right: 16px !important; }}
+}}
+@media (prefers-color-scheme: dark) {{
+  .body-bg {{ background-color: #121212 !important; }}
+  .content-bg {{ background-color: #1e1e1e !important; }}
+  .text {{ color: #e8e8e8 !important; }}
+}}
+[data-ogsc] .text {{ color: #e8e8e8 !important; }}
+</style>
+</head>
+<body class="body-bg" style="margin:0;padding:0;background-color:{background};">
+{preheader}<table role="presentation" width="100%" cellpadding="0" cellspacing="0" border="0" class="body-bg" style="background-color:{background};">
+<tr><td align="center" style="padding:24px 0;">
+<!--[if mso]><table role="presentation" width="{width}" cellpadding="0" cellspacing="0" border="0"><tr><td><![endif]-->
+<table role="presentation" class="container content-bg" width="{width}" cellpadding="0" cellspacing="0" border="0" style="width:{width}px;max-width:{width}px;background-color:{content_background};font-family:{font};color:{color};">
+{rows}</table>
+<!--[if mso]></td></tr></table><![endif]-->
+</td></tr>
+</table>
+</body>
+</html>"#,
+            title = escape(&template.subject),
+            width = CONTAINER_WIDTH,
+        );
+
+        let text = text.join("\n\n");
+        let size = EmailSizeReport {
+            html_bytes: html.len(),
+            text_bytes: text.len(),
+            clip_threshold_bytes: GMAIL_CLIP_BYTES,
+            near_clip_threshold: html.len() > SIZE_WARNING_BYTES,
+            clipped: html.len() > GMAIL_CLIP_BYTES,
+        };
+        let mut warnings = lint_warnings;
+        if size.clipped {
+            warnings.push(format!(
+                "HTML is {} bytes; Gmail clips messages over {} bytes",
+                size.html_bytes, GMAIL_CLIP_BYTES
+            ));
+        } else if size.near_clip_threshold {
+            warnings.push(format!(
+                "HTML is {} bytes, within {} bytes of Gmail's clipping threshold",
+                size.html_bytes,
+                GMAIL_CLIP_BYTES - size.html_bytes
+            ));
+        }
+        Ok(CompiledEmail {
+            subject: template.subject.clone(),
+            html,
+            text,
+            size,
+            warnings,
+        })
+    }
+
+    /// Compile one block to a container row and its plain-text rendering.
+    fn compile_block(block: &EmailBlock) -> Result<(String, String), String> {
+        let get = |key: &str| block.content.get(key).map(String::as_str).unwrap_or("");
+        let url = |key: &str| safe_url(get(key)).map_err(|e| format!("Block {}: {}", block.id, e));
+        let padding = if matches!(block.block_type, BlockType::Hero | BlockType::Spacer) {
+            "0"
+        } else {
+            "12px 24px"
+        };
+        let cell_style = inline_style(&[("padding", padding)], &block.styles);
+
+        let (inner, text) = match block.block_type {
+            BlockType::Hero => {
+                let image = image_tag(&url("image_url")?, get("alt_text"), CONTAINER_WIDTH);
+                let image = link(&url("link_url")?, &image);
+                let headline = get("headline");
+                let mut html = image;
+                if !headline.is_empty() {
+                    let _ = write!(
+                        html,
+                        "<h1 class=\"text\" style=\"margin:0;padding:16px 24px 0;font-size:28px;line-height:34px;\">{}</h1>",
+                        escape(headline)
+                    );
+                }
+                (html, headline.to_string())
+            }
+            BlockType::Text => (
+                format!(
+                    "<div class=\"text\" style=\"font-size:16px;line-height:24px;\">{}</div>",
+                    escape(get("text")).replace('\n', "<br />")
+                ),
+                get("text").to_string(),
+            ),
+            BlockType::Button => {
+                let href = url("url")?;
+                let label = match get("label") {
+                    "" => "Click",
+                    label => label,
+                };
+                let background = style_value(&block.styles, "background-color", "#1a73e8");
+                let color = style_value(&block.styles, "color", "#ffffff");
+                (
+                    format!(
+                        "<!--[if mso]><v:roundrect xmlns:v=\"urn:schemas-microsoft-com:vml\" xmlns:w=\"urn:schemas-microsoft-com:office:word\" href=\"{href}\" style=\"height:44px;v-text-anchor:middle;width:220px;\" arcsize=\"10%\" stroke=\"f\" fillcolor=\"{background}\"><w:anchorlock/><center style=\"color:{color};font-family:{DEFAULT_FONT};font-size:16px;font-weight:bold;\">{label}</center></v:roundrect><![endif]-->\
+<!--[if !mso]><!--><a href=\"{href}\" target=\"_blank\" style=\"display:inline-block;padding:12px 24px;border-radius:4px;background-color:{background};color:{color};font-size:16px;font-weight:bold;text-decoration:none;\">{label}</a><!--<![endif]-->",
+                        href = escape(&href),
+                        label = escape(label),
+                    ),
+                    format!("{}: {}", label, href),
+                )
+            }
+            BlockType::Image => {
+                let image = image_tag(&url("image_url")?, get("alt_text"), CONTAINER_WIDTH - 48);
+                let href = url("link_url")?;
+                let text = if href.is_empty() {
+                    String::new()
+                } else {
+                    format!("{}: {}", get("alt_text"), href)
+                };
+                (link(&href, &image), text)
+            }
+            BlockType::Divider => (
+                "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr><td style=\"border-top:1px solid #e0e0e0;font-size:0;line-height:0;\">&nbsp;</td></tr></table>".to_string(),
+                "----------".to_string(),
+            ),
+            BlockType::Spacer => {
+                let height = get("height").parse::<u32>().unwrap_or(20).min(200);
+                (
+                    format!(
+                        "<div style=\"height:{0}px;line-height:{0}px;font-size:0;\">&nbsp;</div>",
+                        height
+                    ),
+                    String::new(),
+                )
+            }
+            BlockType::Social => {
+                let mut links = Vec::new();
+                let mut text = Vec::new();
+                for (key, name) in SOCIAL_NETWORKS {
+                    let href = url(key)?;
+                    if !href.is_empty() {
+                        links.push(format!(
+                            "<a href=\"{}\" target=\"_blank\" style=\"color:inherit;text-decoration:underline;\">{}</a>",
+                            escape(&href),
+                            name
+                        ));
+                        text.push(format!("{}: {}", name, href));
+                    }
+                }
+                (
+                    format!(
+                        "<div class=\"text\" style=\"text-align:center;font-size:14px;\">{}</div>",
+                        links.join(" &middot; ")
+                    ),
+                    text.join("\n"),
+                )
+            }
+            BlockType::LegalFooter => {
+                let mut html = format!(
+                    "<div class=\"text\" style=\"font-size:12px;line-height:18px;color:#777777;text-align:center;\">{}",
+                    escape(get("text")).replace('\n', "<br />")
+                );
+                let mut text = get("text").to_string();
+                let unsubscribe = url("unsubscribe_url")?;
+                if !unsubscribe.is_empty() {
+                    let _ = write!(
+                        html,
+                        "<br /><a href=\"{}\" style=\"color:#777777;text-decoration:underline;\">Unsubscribe</a>",
+                        escape(&unsubscribe)
+                    );
+                    let _ = write!(text, "\nUnsubscribe: {}", unsubscribe);
+                }
+                html.push_str("</div>");
+                (html, text)
+            }
+            BlockType::Columns => {
+                let left = get("left");
+                let right = get("right");
+                (
+                    columns(&[
+                        format!("<div class=\"text\" style=\"font-size:16px;line-height:24px;\">{}</div>", escape(left).replace('\n', "<br />")),
+                        format!("<div class=\"text\" style=\"font-size:16px;line-height:24px;\">{}</div>", escape(right).replace('\n', "<br />")),
+                    ]),
+                    format!("{}\n\n{}", left, right),
+                )
+            }
+            BlockType::ProductGrid => {
+                let products: Vec<GridProduct> = match get("products") {
+                    "" => Vec::new(),
+                    json => serde_json::from_str(json)
+                        .map_err(|e| format!("Block {}: invalid products: {}", block.id, e))?,
+                };
+                let mut cells = Vec::new();
+                let mut text = Vec::new();
+                for product in &products {
+                    let href = safe_url(&product.url)
+                        .map_err(|e| format!("Block {}: {}", block.id, e))?;
+                    let src = safe_url(&product.image_url)
+                        .map_err(|e| format!("Block {}: {}", block.id, e))?;
+                    cells.push(format!(
+                        "{}<div class=\"text\" style=\"padding-top:8px;font-size:15px;font-weight:bold;\">{}</div><div class=\"text\" style=\"font-size:14px;\">{}</div>",
+                        link(&href, &image_tag(&src, &product.name, 264)),
+                        escape(&product.name),
+                        escape(&product.price)
+                    ));
+                    text.push(
+                        [product.name.as_str(), product.price.as_str(), href.as_str()]
+                            .iter()
+                            .filter(|s| !s.is_empty())
+                            .copied()
+                            .collect::<Vec<_>>()
+                            .join(" — "),
+                    );
+                }
+                let rows: String = cells.chunks(2).map(columns).collect();
+                (rows, text.join("\n"))
+            }
+            BlockType::Countdown => {
+                let src = url("image_url")?;
+                let label = get("text");
+                let html = if src.is_empty() {
+                    format!(
+                        "<div class=\"text\" style=\"font-size:22px;font-weight:bold;text-align:center;\">{}</div>",
+                        escape(label)
+                    )
+                } else {
+                    image_tag(&src, label, CONTAINER_WIDTH - 48)
+                };
+                (html, label.to_string())
+            }
+            BlockType::Video => {
+                let href = url("video_url")?;
+                let thumbnail = image_tag(
+                    &url("thumbnail_url")?,
+                    match get("alt_text") {
+                        "" => "Play video",
+                        alt => alt,
+                    },
+                    CONTAINER_WIDTH - 48,
+                );
+                (link(&href, &thumbnail), format!("Watch: {}", href))
+            }
+            BlockType::Html => {
+                let html = HtmlEditor::sanitize(get("html"));
+                let text = strip_tags(&html);
+                (html, text)
+            }
+        };
+
+        Ok((
+            format!(
+                "<tr><td class=\"pad\" style=\"{}\">{}</td></tr>\n",
+                cell_style, inner
+            ),
+            text,
+        ))
+    }
+}
+
+#[derive(Debug, Deserialize)]
+struct GridProduct {
+    #[serde(default)]
+    name: String,
+    #[serde(default)]
+    image_url: String,
+    #[serde(default)]
+    url: String,
+    #[serde(default)]
+    price: String,
+}
+
+/// Escape text for HTML content and double-quoted attributes.
+pub fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+/// Validate a link or image URL. Relative URLs and merge tags pass; a
+/// scheme must be http(s), mailto or tel. Whitespace and control
+/// characters browsers ignore are stripped before the scheme is read, so
+/// `java\tscript:` is caught too.
+pub fn safe_url(url: &str) -> Result<String, String> {
+    let url = url.trim();
+    let normalized: String = url
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+    let scheme_end = normalized.find(':');
+    let path_start = normalized.find(['/', '?', '#']);
+    if let Some(end) = scheme_end {
+        let is_scheme = path_start.is_none_or(|p| end < p)
+            && normalized[..end]
+                .chars()
+                .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '-' | '.'));
+        if is_scheme && !ALLOWED_SCHEMES.contains(&&normalized[..end]) {
+            return Err(format!(
+                "URL scheme '{}:' is not allowed",
+                &normalized[..end]
+            ));
+        }
+    }
+    Ok(url.to_string())
+}
+
+fn image_tag(src: &str, alt: &str, width: u32) -> String {
+    format!(
+        "<img src=\"{}\" alt=\"{}\" width=\"{}\" style=\"display:block;width:100%;max-width:{}px;height:auto;border:0;\" />",
+        escape(src),
+        escape(alt),
+        width,
+        width
+    )
+}
+
+fn link(href: &str, inner: &str) -> String {
+    if href.is_empty() {
+        inner.to_string()
+    } else {
+        format!(
+            "<a href=\"{}\" target=\"_blank\" style=\"text-decoration:none;\">{}</a>",
+            escape(href),
+            inner
+        )
+    }
+}
+
+/// Side-by-side cells that stack on narrow screens.
+fn columns(cells: &[String]) -> String {
+    let width = 100 / cells.len().max(1);
+    let mut html = String::from(
+        "<table role=\"presentation\" width=\"100%\" cellpadding=\"0\" cellspacing=\"0\" border=\"0\"><tr>",
+    );
+    for cell in cells {
+        let _ = write!(
+            html,
+            "<td class=\"stack\" width=\"{}%\" valign=\"top\" style=\"padding:0 8px;\">{}</td>",
+            width, cell
+        );
+    }
+    html.push_str("</tr></table>");
+    html
+}
+
+/// Merge block styles over defaults into an inline `style` value. Property
+/// names are limited to CSS identifiers and values lose characters that
+/// could close the declaration or the attribute.
+fn inline_style(
+    defaults: &[(&str, &str)],
+    overrides: &std::collections::HashMap<String, String>,
+) -> String {
+    let mut declarations: Vec<(String, String)> = defaults
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+    let mut keys: Vec<&String> = overrides.keys().collect();
+    keys.sort();
+    for key in keys {
+        if key.is_empty() || !key.chars().all(|c| c.is_ascii_alphanumeric() || c == '-') {
+            continue;
+        }
+        let value = style_value(overrides, key, "");
+        if value.is_empty() {
+            continue;
+        }
+        match declarations.iter_mut().find(|(k, _)| k == key) {
+            Some(existing) => existing.1 = value,
+            None => declarations.push((key.clone(), value)),
+        }
+    }
+    declarations
+        .iter()
+        .map(|(k, v)| format!("{}:{};", k, v))
+        .collect()
+}
+
+fn style_value(
+    styles: &std::collections::HashMap<String, String>,
+    key: &str,
+    default: &str,
+) -> String {
+    let value = styles.get(key).map(String::as_str).unwrap_or(default);
+    let lower = value.to_ascii_lowercase();
+    if lower.contains("expression(") || lower.contains("javascript:") || lower.contains("url(") {
+        return default.to_string();
+    }
+    value
+        .chars()
+        .filter(|c| !matches!(c, ';' | '{' | '}' | '<' | '>' | '"' | '\\'))
+        .collect::<String>()
+        .trim()
+        .to_string()
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => {
+                in_tag = false;
+                out.push(' ');
+            }
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_studio::{EmailTemplateBuilder, LayoutMode};
+    use chrono::Utc;
+    use std::collections::HashMap;
+    use uuid::Uuid;
+
+    fn block(block_type: BlockType, content: &[(&str, &str)]) -> EmailBlock {
+        EmailBlock {
+            id: Uuid::new_v4(),
+            block_type,
+            content: content
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+            styles: HashMap::new(),
+            mobile_styles: None,
+            sort_order: 0,
+            snippet_id: None,
+        }
+    }
+
+    fn template(blocks: Vec<EmailBlock>) -> EmailTemplate {
+        EmailTemplate {
+            id: Uuid::new_v4(),
+            name: "Spring sale".to_string(),
+            subject: "Fresh <deals> & more".to_string(),
+            preheader: Some("Up to 40% off".to_string()),
+            blocks,
+            layout_mode: LayoutMode::Hybrid,
+            global_styles: HashMap::new(),
+            version: 1,
+            created_by: Uuid::new_v4(),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_compile_escapes_content_and_builds_table_layout() {
+        let compiled = EmailCompiler::compile(&template(vec![
+            block(
+                BlockType::Hero,
+                &[
+                    ("image_url", "https://cdn.example.com/hero.jpg?a=1&b=2"),
+                    ("alt_text", "\" onerror=\"alert(1)"),
+                ],
+            ),
+            block(BlockType::Text, &[("text", "Hi <b>Jane</b>\nTom & Co")]),
+            block(
+                BlockType::Button,
+                &[("label", "Shop <now>"), ("url", "https://shop.example.com")],
+            ),
+            block(
+                BlockType::LegalFooter,
+                &[
+                    ("text", "Example Inc."),
+                    ("unsubscribe_url", "https://example.com/u?id=1"),
+                ],
+            ),
+        ]))
+        .unwrap();
+
+        let html = &compiled.html;
+        assert!(html.contains("<title>Fresh &lt;deals&gt; &amp; more</title>"));
+        assert!(html.contains("alt=\"&quot; onerror=&quot;alert(1)\""));
+        assert!(html.contains("src=\"https://cdn.example.com/hero.jpg?a=1&amp;b=2\""));
+        assert!(html.contains("Hi &lt;b&gt;Jane&lt;/b&gt;<br />Tom &amp; Co"));
+        assert!(html.contains("Shop &lt;now&gt;"));
+        assert!(html.contains("<v:roundrect"));
+        assert!(html.contains("role=\"presentation\""));
+        assert!(html.contains("<meta name=\"color-scheme\" content=\"light dark\" />"));
+        assert!(html.contains("prefers-color-scheme: dark"));
+        assert!(!html.contains("<div class=\"hero\""));
+
+        assert_eq!(
+            compiled.text,
+            "Hi <b>Jane</b>\nTom & Co\n\nShop <now>: https://shop.example.com\n\nExample Inc.\nUnsubscribe: https://example.com/u?id=1"
+        );
+        assert!(!compiled.size.near_clip_threshold);
+        assert!(compiled.warnings.is_empty());
+    }
+
+    #[test]
+    fn test_unsafe_urls_are_rejected() {
+        for url in [
+            "javascript:alert(1)",
+            "  JavaScript:alert(1)",
+            "java\tscript:alert(1)",
+            "vbscript:msgbox",
+            "data:text/html;base64,PHNjcmlwdD4=",
+        ] {
+            let err = EmailCompiler::compile(&template(vec![block(
+                BlockType::Button,
+                &[("label", "Go"), ("url", url)],
+            )]))
+            .unwrap_err();
+            assert!(err.contains("is not allowed"), "{url}: {err}");
+        }
+
+        for url in [
+            "https://example.com/a:b",
+            "mailto:help@example.com",
+            "/relative/path",
+            "{{ offer_url }}",
+        ] {
+            assert!(safe_url(url).is_ok(), "{url}");
+        }
+    }
+
+    #[test]
+    fn test_size_report_warns_before_gmail_clips() {
+        let filler = "x".repeat(SIZE_WARNING_BYTES);
+        let compiled = EmailCompiler::compile(&template(vec![block(
+            BlockType::Text,
+            &[("text", &filler)],
+        )]))
+        .unwrap();
+        assert!(compiled.size.near_clip_threshold);
+        assert!(!compiled.size.clipped);
+        assert!(compiled.warnings[0].contains("of Gmail's clipping threshold"));
+
+        let filler = "x".repeat(GMAIL_CLIP_BYTES);
+        let compiled = EmailCompiler::compile(&template(vec![block(
+            BlockType::Text,
+            &[("text", &filler)],
+        )]))
+        .unwrap();
+        assert!(compiled.size.clipped);
+        assert!(compiled.warnings[0].contains("Gmail clips"));
+    }
+
+    #[test]
+    fn test_builder_compiles_product_grid_and_html_blocks() {
+        let builder = EmailTemplateBuilder::new();
+        let tmpl = builder.create_template("Grid".into(), "New in".into(), Uuid::new_v4());
+        let products = r#"[{"name":"Mug","image_url":"https://cdn.example.com/mug.png","url":"https://shop.example.com/mug","price":"$12"},
+                           {"name":"Beans","image_url":"https://cdn.example.com/beans.png","url":"https://shop.example.com/beans","price":"$18"},
+                           {"name":"Filter","image_url":"https://cdn.example.com/f.png","url":"","price":"$4"}]"#;
+        builder
+            .add_block(
+                &tmpl.id,
+                block(BlockType::ProductGrid, &[("products", products)]),
+            )
+            .unwrap();
+        builder
+            .add_block(
+                &tmpl.id,
+                block(
+                    BlockType::Html,
+                    &[("html", "<p>Custom</p><script>alert(1)</script>")],
+                ),
+            )
+            .unwrap();
+
+        let compiled = builder.compile(&tmpl.id).unwrap();
+        assert_eq!(compiled.html.matches("class=\"stack\"").count(), 3);
+        assert!(!compiled.html.contains("<script"));
+        assert!(compiled
+            .text
+            .contains("Mug — $12 — https://shop.example.com/mug\nBeans — $18"));
+        assert!(compiled.text.ends_with("Filter — $4\n\nCustom"));
+        assert_eq!(builder.render_html(&tmpl.id).unwrap(), compiled.html);
+    }
+}
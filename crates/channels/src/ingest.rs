@@ -72,12 +72,16 @@ impl IngestProcessor {
         );
 
         // Emit ChannelIngest event to analytics pipeline
-        self.event_sink.emit(make_event(
+        let mut ingest_event = make_event(
             EventType::ChannelIngest,
             &event.event_id,
             Some(user_id.clone()),
             None,
-        ));
+        );
+        ingest_event.channel = serde_json::to_value(event.source)
+            .ok()
+            .and_then(|v| v.as_str().map(str::to_string));
+        self.event_sink.emit(ingest_event);
 
         Ok(ProcessedIngest {
             event_id: event.event_id.clone(),
@@ -2,28 +2,37 @@
 //!
 //! Ingest: consumes real-time events from mobile, POS, kiosk, web via NATS queues.
 //! Activation: delivers personalized offers to push, SMS, email, paid media, in-store.
-//! Channels: in-app messaging, content cards, WhatsApp, web push, and more.
+//! Channels: in-app messaging, content cards, WhatsApp, web push, mobile push, and more.
 
 pub mod activation;
+pub mod apns;
 pub mod content_cards;
 pub mod content_studio;
 pub mod email;
+pub mod email_compiler;
+pub mod fcm;
 pub mod in_app;
 pub mod ingest;
+pub mod mobile_push;
+pub mod rendering;
 pub mod sms;
 pub mod templates;
 pub mod web_push;
 pub mod whatsapp;
 
 pub use activation::ActivationDispatcher;
+pub use apns::ApnsProvider;
 pub use content_cards::ContentCardEngine;
 pub use content_studio::{
-    ComplianceChecker, EmailTemplateBuilder, HtmlEditor, LocalizationEngine,
-    RenderTimePersonalizer, VariableBrowser,
+    ComplianceChecker, EmailTemplateBuilder, HtmlEditor, LocalizationEngine, VariableBrowser,
 };
 pub use email::SendGridProvider;
+pub use email_compiler::EmailCompiler;
+pub use fcm::FcmProvider;
 pub use in_app::InAppEngine;
 pub use ingest::IngestProcessor;
+pub use mobile_push::MobilePushProvider;
+pub use rendering::RenderingService;
 pub use sms::SmsProvider;
 pub use templates::{BlockLibrary, TemplateLibrary};
 pub use web_push::WebPushProvider;
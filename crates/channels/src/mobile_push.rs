@@ -0,0 +1,616 @@
+//! Mobile push fan-out over APNs and FCM.
+//!
+//! Looks up a user's push-enabled devices in the mobile SDK's
+//! `DeviceRegistry`, routes each to the provider that issued its token, and
+//! invalidates tokens the provider reports as unregistered or malformed.
+//! [`MobilePushProvider::send_to_user`] reports every per-device outcome
+//! through the event sink; activations dispatched through
+//! [`MobilePushProvider::dispatch`] are reported once, by the
+//! `ActivationDispatcher`.
+
+use crate::apns::{ApnsConfig, ApnsProvider};
+use crate::fcm::{FcmConfig, FcmProvider};
+use crate::web_push::PushAction;
+use campaign_core::channels::*;
+use campaign_core::config::PushConfig;
+use campaign_core::event_bus::{make_event, EventSink};
+use campaign_core::types::{Attribution, EventType};
+use campaign_mobile_sdk::device::{DeviceRegistration, PushProvider};
+use campaign_mobile_sdk::DeviceRegistry;
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Provider-neutral rich notification content.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RichPushPayload {
+    pub title: String,
+    pub body: String,
+    pub image_url: Option<String>,
+    pub deep_link: Option<String>,
+    pub actions: Vec<PushAction>,
+    /// APNs category / Android click action registered by the app.
+    pub category: Option<String>,
+    pub data: HashMap<String, String>,
+    pub badge: Option<u32>,
+    pub sound: Option<String>,
+    /// Newer pushes with the same key replace older undelivered ones.
+    pub collapse_id: Option<String>,
+    /// Seconds the provider should keep retrying an offline device; 0 means
+    /// deliver now or drop.
+    pub ttl_seconds: u32,
+    pub high_priority: bool,
+}
+
+impl RichPushPayload {
+    /// Map activation content onto a push. The CTA becomes a single action
+    /// and string values in `extra` are forwarded as custom data.
+    pub fn from_activation(req: &ActivationRequest) -> Self {
+        let content = &req.content;
+        let mut data = HashMap::new();
+        data.insert("activation_id".to_string(), req.activation_id.clone());
+        data.insert("offer_id".to_string(), req.offer_id.clone());
+        if let Some(serde_json::Value::Object(extra)) = &content.extra {
+            for (key, value) in extra {
+                if let Some(v) = value.as_str() {
+                    data.insert(key.clone(), v.to_string());
+                }
+            }
+        }
+
+        let actions = match (&content.cta_text, &content.cta_url) {
+            (Some(text), Some(url)) => vec![PushAction {
+                action: url.clone(),
+                title: text.clone(),
+                icon: None,
+            }],
+            _ => Vec::new(),
+        };
+
+        Self {
+            title: content.headline.clone(),
+            body: content.body.clone(),
+            image_url: content.image_url.clone(),
+            deep_link: content
+                .deep_link
+                .clone()
+                .or_else(|| content.cta_url.clone()),
+            actions,
+            category: None,
+            data,
+            badge: None,
+            sound: Some("default".to_string()),
+            collapse_id: None,
+            ttl_seconds: 86_400,
+            // Priority 1-3 is time-sensitive; the rest can be batched.
+            high_priority: req.priority <= 3,
+        }
+    }
+}
+
+/// Outcome of a single provider send.
+#[derive(Debug, Clone, PartialEq)]
+pub enum PushSendOutcome {
+    Accepted {
+        message_id: Option<String>,
+    },
+    /// The token is permanently unusable and should be dropped.
+    InvalidToken {
+        reason: String,
+    },
+    Failed {
+        status: Option<u16>,
+        error: String,
+    },
+}
+
+/// Per-device result of a fan-out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MobilePushResult {
+    pub device_id: String,
+    pub provider: Option<PushProvider>,
+    pub success: bool,
+    pub provider_message_id: Option<String>,
+    pub error: Option<String>,
+    pub token_invalidated: bool,
+}
+
+/// Fans pushes out to every registered device of a user.
+pub struct MobilePushProvider {
+    registry: Arc<DeviceRegistry>,
+    apns: Option<ApnsProvider>,
+    fcm: Option<FcmProvider>,
+    event_sink: Arc<dyn EventSink>,
+}
+
+impl MobilePushProvider {
+    pub fn new(registry: Arc<DeviceRegistry>) -> Self {
+        Self {
+            registry,
+            apns: None,
+            fcm: None,
+            event_sink: campaign_core::event_bus::noop_sink(),
+        }
+    }
+
+    /// Build from deployment config, reading the key files it points at.
+    /// Returns `None` when neither APNs nor FCM is configured.
+    pub fn from_config(
+        registry: Arc<DeviceRegistry>,
+        config: &PushConfig,
+    ) -> anyhow::Result<Option<Self>> {
+        let read = |path: &str| {
+            std::fs::read_to_string(path)
+                .map_err(|e| anyhow::anyhow!("cannot read push key {}: {}", path, e))
+        };
+
+        let mut push = Self::new(registry);
+        if let Some(path) = &config.apns_key_path {
+            push = push.with_apns(ApnsProvider::new(ApnsConfig {
+                team_id: config.apns_team_id.clone(),
+                key_id: config.apns_key_id.clone(),
+                private_key_pem: read(path)?,
+                bundle_id: config.apns_bundle_id.clone(),
+                ..Default::default()
+            })?);
+        }
+        if let Some(path) = &config.fcm_service_account_path {
+            push = push.with_fcm(FcmProvider::new(FcmConfig::from_service_account_json(
+                &read(path)?,
+            )?)?);
+        }
+        Ok((push.apns.is_some() || push.fcm.is_some()).then_some(push))
+    }
+
+    pub fn with_apns(mut self, apns: ApnsProvider) -> Self {
+        self.apns = Some(apns);
+        self
+    }
+
+    pub fn with_fcm(mut self, fcm: FcmProvider) -> Self {
+        self.fcm = Some(fcm);
+        self
+    }
+
+    /// Attach an event sink for emitting analytics events.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = sink;
+        self
+    }
+
+    /// Send `payload` to every push-enabled device of `user_id`. Each
+    /// device's outcome is emitted as an event attributed to `attribution`.
+    pub async fn send_to_user(
+        &self,
+        user_id: &Uuid,
+        payload: &RichPushPayload,
+        activation_id: &str,
+        offer_id: Option<&str>,
+        attribution: &Attribution,
+    ) -> Vec<MobilePushResult> {
+        let results = self.fan_out(user_id, payload).await;
+        for result in &results {
+            let event_type = if result.success {
+                EventType::ActivationSent
+            } else {
+                EventType::ActivationFailed
+            };
+            self.event_sink.emit(
+                make_event(
+                    event_type,
+                    activation_id,
+                    Some(user_id.to_string()),
+                    offer_id.map(str::to_string),
+                )
+                .with_attribution(attribution)
+                .with_channel("push_notification"),
+            );
+        }
+        results
+    }
+
+    /// Send `payload` to every push-enabled device of `user_id`.
+    async fn fan_out(&self, user_id: &Uuid, payload: &RichPushPayload) -> Vec<MobilePushResult> {
+        let devices = self.registry.get_user_push_targets(user_id);
+        let mut results = Vec::with_capacity(devices.len());
+        for device in &devices {
+            results.push(self.send_to_device(device, payload).await);
+        }
+        results
+    }
+
+    /// Deliver an activation as a push to all of the user's devices. The
+    /// activation counts as sent if at least one device accepted it. No
+    /// events are emitted here: the dispatcher reports the activation once,
+    /// however many devices it reached.
+    pub async fn dispatch(&self, req: &ActivationRequest) -> ActivationResult {
+        let failed = |error: String| ActivationResult {
+            activation_id: req.activation_id.clone(),
+            channel: ActivationChannel::PushNotification,
+            status: ActivationStatus::Failed,
+            provider_message_id: None,
+            latency_ms: 0,
+            error: Some(error),
+            delivered_at: None,
+        };
+
+        let user_id = match Uuid::parse_str(&req.user_id) {
+            Ok(id) => id,
+            Err(_) => return failed(format!("user_id {} is not a UUID", req.user_id)),
+        };
+
+        let payload = RichPushPayload::from_activation(req);
+        let results = self.fan_out(&user_id, &payload).await;
+
+        if results.is_empty() {
+            return failed("no push-enabled devices registered".to_string());
+        }
+        match results.iter().find(|r| r.success) {
+            Some(accepted) => ActivationResult {
+                activation_id: req.activation_id.clone(),
+                channel: ActivationChannel::PushNotification,
+                status: ActivationStatus::Sent,
+                provider_message_id: accepted.provider_message_id.clone(),
+                latency_ms: 0,
+                error: None,
+                delivered_at: Some(Utc::now()),
+            },
+            None => failed(
+                results
+                    .iter()
+                    .filter_map(|r| r.error.clone())
+                    .collect::<Vec<_>>()
+                    .join("; "),
+            ),
+        }
+    }
+
+    async fn send_to_device(
+        &self,
+        device: &DeviceRegistration,
+        payload: &RichPushPayload,
+    ) -> MobilePushResult {
+        let provider = device.effective_push_provider();
+        let token = device.push_token.clone().unwrap_or_default();
+
+        let outcome = match provider {
+            Some(PushProvider::Apns) | Some(PushProvider::ApnsSandbox) => match &self.apns {
+                Some(apns) => {
+                    let sandbox = provider == Some(PushProvider::ApnsSandbox);
+                    apns.send(&token, payload, sandbox).await
+                }
+                None => Self::not_configured("APNs"),
+            },
+            Some(PushProvider::Fcm) => match &self.fcm {
+                Some(fcm) => fcm.send(&token, payload).await,
+                None => Self::not_configured("FCM"),
+            },
+            Some(other) => PushSendOutcome::Failed {
+                status: None,
+                error: format!("{:?} tokens are not handled by mobile push", other),
+            },
+            None => PushSendOutcome::Failed {
+                status: None,
+                error: "device has no push provider".to_string(),
+            },
+        };
+
+        let mut result = MobilePushResult {
+            device_id: device.device_id.clone(),
+            provider,
+            success: false,
+            provider_message_id: None,
+            error: None,
+            token_invalidated: false,
+        };
+        match outcome {
+            PushSendOutcome::Accepted { message_id } => {
+                metrics::counter!("mobile_push.accepted").increment(1);
+                result.success = true;
+                result.provider_message_id = message_id;
+            }
+            PushSendOutcome::InvalidToken { reason } => {
+                metrics::counter!("mobile_push.tokens_invalidated").increment(1);
+                result.token_invalidated =
+                    self.registry
+                        .invalidate_push_token(&device.device_id, &token, &reason);
+                result.error = Some(reason);
+            }
+            PushSendOutcome::Failed { status, error } => {
+                metrics::counter!("mobile_push.failed").increment(1);
+                tracing::warn!(
+                    device_id = %device.device_id,
+                    status = ?status,
+                    error = %error,
+                    "Mobile push failed"
+                );
+                result.error = Some(error);
+            }
+        }
+        result
+    }
+
+    fn not_configured(provider: &str) -> PushSendOutcome {
+        PushSendOutcome::Failed {
+            status: None,
+            error: format!("{} provider not configured", provider),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::fcm::tests::TEST_RSA_KEY;
+    use axum::extract::Path;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Json;
+    use campaign_mobile_sdk::config::SdkPlatform;
+    use p256::pkcs8::{EncodePrivateKey, LineEnding};
+
+    async fn mock_push_services() -> String {
+        let app = axum::Router::new()
+            .route(
+                "/3/device/:token",
+                post(|Path(token): Path<String>| async move {
+                    if token == "bad-apns" {
+                        (
+                            StatusCode::GONE,
+                            [("apns-id", "")],
+                            r#"{"reason":"Unregistered"}"#.to_string(),
+                        )
+                    } else {
+                        (StatusCode::OK, [("apns-id", "apns-1")], String::new())
+                    }
+                }),
+            )
+            .route(
+                "/token",
+                post(|| async {
+                    Json(serde_json::json!({"access_token": "ya29.test", "expires_in": 3600}))
+                }),
+            )
+            .route(
+                "/v1/projects/demo/messages:send",
+                post(|Json(body): Json<serde_json::Value>| async move {
+                    if body["message"]["token"] == "stale-fcm" {
+                        (
+                            StatusCode::NOT_FOUND,
+                            Json(serde_json::json!({"error": {
+                                "code": 404,
+                                "message": "Requested entity was not found.",
+                                "status": "NOT_FOUND",
+                                "details": [{"errorCode": "UNREGISTERED"}]
+                            }})),
+                        )
+                    } else {
+                        (
+                            StatusCode::OK,
+                            Json(serde_json::json!({"name": "projects/demo/messages/1"})),
+                        )
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    fn device(
+        user_id: Uuid,
+        device_id: &str,
+        platform: SdkPlatform,
+        token: &str,
+    ) -> DeviceRegistration {
+        DeviceRegistration {
+            id: Uuid::new_v4(),
+            user_id: Some(user_id),
+            device_id: device_id.to_string(),
+            platform,
+            push_token: Some(token.to_string()),
+            push_enabled: true,
+            push_provider: None,
+            push_token_invalid_reason: None,
+            app_version: "1.0".to_string(),
+            sdk_version: "1.0".to_string(),
+            os_version: "17".to_string(),
+            device_model: None,
+            locale: None,
+            timezone: None,
+            ad_tracking_enabled: false,
+            idfa: None,
+            gaid: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+            last_active: Utc::now(),
+        }
+    }
+
+    fn provider(base: &str, registry: Arc<DeviceRegistry>) -> MobilePushProvider {
+        let p8 = p256::SecretKey::from_slice(&[3u8; 32])
+            .unwrap()
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+        let apns = ApnsProvider::new(ApnsConfig {
+            team_id: "TEAM123".to_string(),
+            key_id: "KEY123".to_string(),
+            private_key_pem: p8.to_string(),
+            bundle_id: "io.campaignexpress.app".to_string(),
+            endpoint: base.to_string(),
+            sandbox_endpoint: base.to_string(),
+            request_timeout_ms: 5_000,
+        })
+        .unwrap();
+        let fcm = FcmProvider::new(FcmConfig {
+            project_id: "demo".to_string(),
+            client_email: "push@demo.iam.gserviceaccount.com".to_string(),
+            private_key: TEST_RSA_KEY.to_string(),
+            token_uri: format!("{}/token", base),
+            api_base_url: base.to_string(),
+            request_timeout_ms: 5_000,
+        })
+        .unwrap();
+        MobilePushProvider::new(registry)
+            .with_apns(apns)
+            .with_fcm(fcm)
+    }
+
+    #[tokio::test]
+    async fn test_fan_out_invalidates_rejected_tokens() {
+        let base = mock_push_services().await;
+        let registry = Arc::new(DeviceRegistry::new());
+        let user_id = Uuid::new_v4();
+        registry.register(device(user_id, "iphone", SdkPlatform::Ios, "good-apns"));
+        registry.register(device(user_id, "ipad", SdkPlatform::Ios, "bad-apns"));
+        registry.register(device(user_id, "pixel", SdkPlatform::Android, "good-fcm"));
+        registry.register(device(
+            user_id,
+            "old-phone",
+            SdkPlatform::Android,
+            "stale-fcm",
+        ));
+
+        let sink = campaign_core::event_bus::capture_sink();
+        let push = provider(&base, registry.clone()).with_event_sink(sink.clone());
+        let payload = RichPushPayload {
+            title: "Flash sale".to_string(),
+            body: "Ends tonight".to_string(),
+            image_url: Some("https://cdn.example.com/sale.png".to_string()),
+            ..Default::default()
+        };
+
+        let results = push
+            .send_to_user(&user_id, &payload, "act-1", None, &Attribution::default())
+            .await;
+        assert_eq!(results.len(), 4);
+        let by_device = |id: &str| results.iter().find(|r| r.device_id == id).unwrap();
+        assert_eq!(
+            by_device("iphone").provider_message_id.as_deref(),
+            Some("apns-1")
+        );
+        assert_eq!(
+            by_device("pixel").provider_message_id.as_deref(),
+            Some("projects/demo/messages/1")
+        );
+        assert!(by_device("ipad").token_invalidated);
+        assert!(by_device("old-phone").token_invalidated);
+
+        assert!(registry.get_device("ipad").unwrap().push_token.is_none());
+        assert_eq!(
+            registry
+                .get_device("old-phone")
+                .unwrap()
+                .push_token_invalid_reason
+                .as_deref(),
+            Some("UNREGISTERED")
+        );
+        assert_eq!(registry.get_user_push_targets(&user_id).len(), 2);
+        assert_eq!(sink.count_type(EventType::ActivationSent), 2);
+        assert_eq!(sink.count_type(EventType::ActivationFailed), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dispatch_activation() {
+        let base = mock_push_services().await;
+        let registry = Arc::new(DeviceRegistry::new());
+        let user_id = Uuid::new_v4();
+        registry.register(device(user_id, "iphone", SdkPlatform::Ios, "good-apns"));
+        registry.register(device(user_id, "pixel", SdkPlatform::Android, "good-fcm"));
+        let sink = campaign_core::event_bus::capture_sink();
+        let push = Arc::new(provider(&base, registry).with_event_sink(sink.clone()));
+        let dispatcher =
+            crate::ActivationDispatcher::new(vec![ActivationChannel::PushNotification])
+                .with_event_sink(sink.clone())
+                .with_mobile_push(push.clone());
+
+        let mut req = ActivationRequest {
+            activation_id: "act-2".to_string(),
+            decision_id: None,
+            user_id: user_id.to_string(),
+            channel: ActivationChannel::PushNotification,
+            offer_id: "offer-1".to_string(),
+            content: ActivationContent {
+                headline: "Hi".to_string(),
+                body: "Your reward is ready".to_string(),
+                image_url: None,
+                cta_url: Some("app://rewards".to_string()),
+                cta_text: Some("Open".to_string()),
+                deep_link: None,
+                audience_segment_id: None,
+                extra: None,
+                personalization: None,
+            },
+            priority: 1,
+            scheduled_at: None,
+            created_at: Utc::now(),
+            trigger_event_id: None,
+            trigger_source: None,
+            campaign_id: Some("camp-1".to_string()),
+            experiment_variant_id: None,
+            creative_id: Some("creative-1".to_string()),
+        };
+        let payload = RichPushPayload::from_activation(&req);
+        assert_eq!(payload.deep_link.as_deref(), Some("app://rewards"));
+        assert_eq!(payload.actions.len(), 1);
+        assert!(payload.high_priority);
+
+        let result = dispatcher.dispatch(&req).await;
+        assert_eq!(result.status, ActivationStatus::Sent);
+        assert!(result.provider_message_id.is_some());
+        // Two devices reached, one activation counted.
+        assert_eq!(sink.count_type(EventType::ActivationSent), 1);
+        assert_eq!(sink.events().len(), 1);
+        let sent = &sink.events()[0];
+        assert_eq!(sent.campaign_id.as_deref(), Some("camp-1"));
+        assert_eq!(sent.creative_id.as_deref(), Some("creative-1"));
+        assert_eq!(sent.channel.as_deref(), Some("push_notification"));
+
+        req.user_id = Uuid::new_v4().to_string();
+        let result = dispatcher.dispatch(&req).await;
+        assert_eq!(result.status, ActivationStatus::Failed);
+        assert_eq!(sink.count_type(EventType::ActivationFailed), 1);
+    }
+
+    #[test]
+    fn test_from_config_builds_configured_providers() {
+        let registry = Arc::new(DeviceRegistry::new());
+        assert!(
+            MobilePushProvider::from_config(registry.clone(), &PushConfig::default())
+                .unwrap()
+                .is_none()
+        );
+
+        let dir = std::env::temp_dir().join(format!("push-config-{}", Uuid::new_v4()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let p8 = p256::SecretKey::from_slice(&[3u8; 32])
+            .unwrap()
+            .to_pkcs8_pem(LineEnding::LF)
+            .unwrap();
+        let apns_key_path = dir.join("AuthKey_KEY123.p8");
+        std::fs::write(&apns_key_path, p8.as_bytes()).unwrap();
+
+        let config = PushConfig {
+            apns_key_path: Some(apns_key_path.to_string_lossy().into_owned()),
+            apns_team_id: "TEAM123".to_string(),
+            apns_key_id: "KEY123".to_string(),
+            apns_bundle_id: "io.campaignexpress.app".to_string(),
+            fcm_service_account_path: None,
+            sdk_api_keys: Vec::new(),
+        };
+        let push = MobilePushProvider::from_config(registry.clone(), &config)
+            .unwrap()
+            .unwrap();
+        assert!(push.apns.is_some() && push.fcm.is_none());
+
+        let missing = PushConfig {
+            fcm_service_account_path: Some(dir.join("missing.json").to_string_lossy().into_owned()),
+            ..config
+        };
+        assert!(MobilePushProvider::from_config(registry, &missing).is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,917 @@
+//! Message rendering service shared by every channel.
+//!
+//! Builds a single Liquid context for a recipient — profile attributes,
+//! message variables from the [`VariableBrowser`], connected content,
+//! catalog lookups and recommendation slots — and renders subjects, bodies
+//! and builder blocks through the personalization `TemplateEngine`. Every
+//! name a template reads is checked against that namespace before anything
+//! is rendered, so a typo fails the preview instead of sending a blank.
+//!
+//! Profiles come from a [`ProfileSource`]; the server backs it with the
+//! Redis profile cache. The activation dispatcher and the SendGrid, SMS and
+//! WhatsApp providers render through [`RenderingService::render_activation`]
+//! and [`RenderingService::render_text`] before sending.
+
+use crate::content_studio::{
+    BlockPersonalization, EmailBlock, RenderTimePersonalizer, VariableBrowser,
+};
+use anyhow::{anyhow, bail};
+use campaign_core::channels::{ActivationChannel, ActivationRequest};
+use campaign_personalization::recommendations::{RecommendationRequest, RecommendationStrategy};
+use campaign_personalization::templating::{
+    OutputFormat, TemplateContext, TemplateEngine, NAMESPACES,
+};
+use campaign_personalization::{CatalogEngine, ConnectedContentEngine, RecommendationEngine};
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeSet, HashMap};
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Arc;
+use uuid::Uuid;
+
+/// Profile data the service renders against, keyed by user ID.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenderProfile {
+    /// Exposed as `user.*`; attributes named like a registered message
+    /// variable also fill that variable.
+    pub attributes: HashMap<String, Value>,
+    /// Segment memberships used by per-block personalization rules.
+    pub segments: Vec<u32>,
+}
+
+pub type ProfileFuture<'a> =
+    Pin<Box<dyn Future<Output = anyhow::Result<Option<RenderProfile>>> + Send + 'a>>;
+
+/// Where the service loads recipient profiles from.
+pub trait ProfileSource: Send + Sync {
+    fn load_profile<'a>(&'a self, user_id: &'a str) -> ProfileFuture<'a>;
+}
+
+/// In-process profile store, used when no external source is attached.
+#[derive(Default)]
+pub struct InMemoryProfiles {
+    profiles: DashMap<String, RenderProfile>,
+}
+
+impl InMemoryProfiles {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn upsert(&self, user_id: &str, profile: RenderProfile) {
+        self.profiles.insert(user_id.to_string(), profile);
+    }
+}
+
+impl ProfileSource for InMemoryProfiles {
+    fn load_profile<'a>(&'a self, user_id: &'a str) -> ProfileFuture<'a> {
+        let profile = self.profiles.get(user_id).map(|p| p.clone());
+        Box::pin(async move { Ok(profile) })
+    }
+}
+
+/// A catalog item exposed as `catalog.<alias>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CatalogLookup {
+    pub catalog_id: Uuid,
+    pub item_id: String,
+}
+
+/// A recommendation slot exposed as `recommendations.<slot>`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecommendationSlot {
+    pub strategy: RecommendationStrategy,
+    pub limit: usize,
+    pub catalog_name: Option<String>,
+    /// When set, each recommended item is merged with its catalog record.
+    pub catalog_id: Option<Uuid>,
+}
+
+/// Connected content, catalog, recommendation and builder block inputs of
+/// a render, as carried on an activation's `content.personalization`.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RenderPersonalization {
+    /// Connected content sources by alias (`connected.<alias>`).
+    #[serde(default)]
+    pub connected: HashMap<String, Uuid>,
+    #[serde(default)]
+    pub catalog: HashMap<String, CatalogLookup>,
+    #[serde(default)]
+    pub recommendations: HashMap<String, RecommendationSlot>,
+    /// Builder blocks, personalized by `block_rules` and then rendered.
+    #[serde(default)]
+    pub blocks: Vec<EmailBlock>,
+    #[serde(default)]
+    pub block_rules: Vec<BlockPersonalization>,
+}
+
+/// Everything needed to render one message for one recipient.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderRequest {
+    pub user_id: String,
+    pub channel: ActivationChannel,
+    pub subject: Option<String>,
+    pub body: String,
+    /// Message variable overrides, on top of profile values and defaults.
+    #[serde(default)]
+    pub variables: HashMap<String, Value>,
+    #[serde(default)]
+    pub event: HashMap<String, Value>,
+    #[serde(default)]
+    pub campaign: HashMap<String, Value>,
+    /// Connected content sources by alias (`connected.<alias>`).
+    #[serde(default)]
+    pub connected: HashMap<String, Uuid>,
+    #[serde(default)]
+    pub catalog: HashMap<String, CatalogLookup>,
+    #[serde(default)]
+    pub recommendations: HashMap<String, RecommendationSlot>,
+    /// Builder blocks, personalized by `block_rules` and then rendered.
+    #[serde(default)]
+    pub blocks: Vec<EmailBlock>,
+    #[serde(default)]
+    pub block_rules: Vec<BlockPersonalization>,
+}
+
+impl RenderRequest {
+    pub fn new(
+        user_id: impl Into<String>,
+        channel: ActivationChannel,
+        body: impl Into<String>,
+    ) -> Self {
+        Self {
+            user_id: user_id.into(),
+            channel,
+            subject: None,
+            body: body.into(),
+            variables: HashMap::new(),
+            event: HashMap::new(),
+            campaign: HashMap::new(),
+            connected: HashMap::new(),
+            catalog: HashMap::new(),
+            recommendations: HashMap::new(),
+            blocks: Vec::new(),
+            block_rules: Vec::new(),
+        }
+    }
+
+    pub fn with_personalization(mut self, personalization: RenderPersonalization) -> Self {
+        self.connected = personalization.connected;
+        self.catalog = personalization.catalog;
+        self.recommendations = personalization.recommendations;
+        self.blocks = personalization.blocks;
+        self.block_rules = personalization.block_rules;
+        self
+    }
+}
+
+/// A message rendered exactly as it would be sent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RenderedContent {
+    pub user_id: String,
+    pub channel: ActivationChannel,
+    pub subject: Option<String>,
+    pub body: String,
+    pub blocks: Vec<EmailBlock>,
+    pub rendered_at: DateTime<Utc>,
+}
+
+/// The single rendering path for all channels.
+pub struct RenderingService {
+    variables: Arc<VariableBrowser>,
+    connected: Arc<ConnectedContentEngine>,
+    catalogs: Arc<CatalogEngine>,
+    recommendations: Arc<RecommendationEngine>,
+    profiles: Arc<dyn ProfileSource>,
+    html: TemplateEngine,
+    plain: TemplateEngine,
+}
+
+impl RenderingService {
+    pub fn new(variables: Arc<VariableBrowser>) -> Self {
+        let catalogs = Arc::new(CatalogEngine::new());
+        Self {
+            variables,
+            connected: Arc::new(ConnectedContentEngine::new()),
+            recommendations: Arc::new(RecommendationEngine::new().with_catalog(catalogs.clone())),
+            catalogs,
+            profiles: Arc::new(InMemoryProfiles::new()),
+            html: TemplateEngine::new().with_output_format(OutputFormat::Html),
+            plain: TemplateEngine::new().with_output_format(OutputFormat::PlainText),
+        }
+    }
+
+    pub fn with_connected_content(mut self, connected: Arc<ConnectedContentEngine>) -> Self {
+        self.connected = connected;
+        self
+    }
+
+    /// Replaces the catalog used for `catalog.*` lookups. Recommendations
+    /// check stock against the catalog attached to their own engine.
+    pub fn with_catalogs(mut self, catalogs: Arc<CatalogEngine>) -> Self {
+        self.catalogs = catalogs;
+        self
+    }
+
+    pub fn with_recommendations(mut self, recommendations: Arc<RecommendationEngine>) -> Self {
+        self.recommendations = recommendations;
+        self
+    }
+
+    /// Load recipient profiles from `profiles` instead of the in-process
+    /// store.
+    pub fn with_profiles(mut self, profiles: Arc<dyn ProfileSource>) -> Self {
+        self.profiles = profiles;
+        self
+    }
+
+    pub fn variables(&self) -> &VariableBrowser {
+        &self.variables
+    }
+
+    /// Check a template against the shared namespace without rendering it.
+    /// Returns one message per problem: syntax errors with their line and
+    /// column, then any names that are neither a namespace, a registered
+    /// message variable nor in `extra`.
+    pub fn validate(&self, template: &str, extra: &[&str]) -> Vec<String> {
+        let parsed = match self.plain.parse(template) {
+            Ok(parsed) => parsed,
+            Err(e) => return vec![e.to_string()],
+        };
+        parsed
+            .referenced_variables()
+            .into_iter()
+            .filter(|name| {
+                !NAMESPACES.contains(&name.as_str())
+                    && !extra.contains(&name.as_str())
+                    && self.variables.get(name).is_none()
+            })
+            .map(|name| format!("Unknown variable '{}'", name))
+            .collect()
+    }
+
+    /// Render a message for `request.user_id`. Bodies for email, in-app
+    /// and web are HTML-escaped; subjects and block content are rendered as
+    /// plain text, since the email builder places blocks into markup itself.
+    pub async fn render(&self, request: &RenderRequest) -> anyhow::Result<RenderedContent> {
+        self.render_with(request, true).await
+    }
+
+    /// `send` enforces every required email variable (the address, the
+    /// unsubscribe link); otherwise only the ones the templates reference.
+    async fn render_with(
+        &self,
+        request: &RenderRequest,
+        send: bool,
+    ) -> anyhow::Result<RenderedContent> {
+        let overrides: Vec<&str> = request.variables.keys().map(String::as_str).collect();
+        let mut problems = Vec::new();
+        for source in template_sources(request) {
+            problems.extend(self.validate(source, &overrides));
+        }
+        if !problems.is_empty() {
+            problems.dedup();
+            bail!("{}", problems.join("; "));
+        }
+
+        let profile = self
+            .profiles
+            .load_profile(&request.user_id)
+            .await?
+            .unwrap_or_default();
+        let context = self.build_context(request, &profile, send).await?;
+
+        let body_engine = match OutputFormat::for_channel(&request.channel) {
+            OutputFormat::Html => &self.html,
+            OutputFormat::PlainText => &self.plain,
+        };
+        let body = body_engine.render(&request.body, &context)?;
+        let subject = request
+            .subject
+            .as_ref()
+            .map(|s| self.plain.render(s, &context))
+            .transpose()?;
+
+        let features: HashMap<String, String> = profile
+            .attributes
+            .iter()
+            .map(|(k, v)| (k.clone(), display(v)))
+            .collect();
+        let channel = serde_json::to_value(request.channel)?
+            .as_str()
+            .unwrap_or_default()
+            .to_string();
+        let mut blocks = RenderTimePersonalizer::render_personalized(
+            &request.blocks,
+            &request.block_rules,
+            &profile.segments,
+            &features,
+            &channel,
+        );
+        for block in &mut blocks {
+            for value in block.content.values_mut() {
+                *value = self.plain.render(value, &context)?;
+            }
+        }
+
+        metrics::counter!("rendering.rendered", "channel" => channel).increment(1);
+        Ok(RenderedContent {
+            user_id: request.user_id.clone(),
+            channel: request.channel,
+            subject,
+            body,
+            blocks,
+            rendered_at: Utc::now(),
+        })
+    }
+
+    /// Render an activation's headline (as the subject) and body for its
+    /// recipient. String values in `content.extra` and `variables` are
+    /// passed as message variables, e.g. the recipient's `email`;
+    /// `content.personalization` supplies connected content, catalog,
+    /// recommendation and block inputs. The rendered activation carries
+    /// its rendered blocks in `content.personalization`, with the rules
+    /// already applied.
+    pub async fn render_activation(
+        &self,
+        request: &ActivationRequest,
+        variables: &[(&str, &str)],
+    ) -> anyhow::Result<ActivationRequest> {
+        self.render_activation_with(request, variables, true).await
+    }
+
+    /// Render an activation before it is queued for its channel provider.
+    /// The recipient address is only attached at send time, so email
+    /// activations are checked like other channels here and get the full
+    /// required-variable check from [`Self::render_activation`] when sent.
+    pub async fn render_queued_activation(
+        &self,
+        request: &ActivationRequest,
+    ) -> anyhow::Result<ActivationRequest> {
+        self.render_activation_with(request, &[], false).await
+    }
+
+    async fn render_activation_with(
+        &self,
+        request: &ActivationRequest,
+        variables: &[(&str, &str)],
+        send: bool,
+    ) -> anyhow::Result<ActivationRequest> {
+        let mut render = RenderRequest::new(
+            request.user_id.clone(),
+            request.channel,
+            request.content.body.clone(),
+        );
+        render.subject = Some(request.content.headline.clone());
+        if let Some(Value::Object(extra)) = &request.content.extra {
+            render.variables.extend(
+                extra
+                    .iter()
+                    .filter(|(_, v)| v.is_string())
+                    .map(|(k, v)| (k.clone(), v.clone())),
+            );
+        }
+        for (name, value) in variables {
+            render
+                .variables
+                .insert(name.to_string(), Value::String(value.to_string()));
+        }
+        if let Some(campaign_id) = &request.campaign_id {
+            render
+                .campaign
+                .insert("id".to_string(), Value::String(campaign_id.clone()));
+        }
+        if let Some(personalization) = &request.content.personalization {
+            let personalization: RenderPersonalization =
+                serde_json::from_value(personalization.clone())
+                    .map_err(|e| anyhow!("Invalid activation personalization: {}", e))?;
+            render = render.with_personalization(personalization);
+        }
+
+        let rendered = self.render_with(&render, send).await?;
+        let mut request = request.clone();
+        request.content.headline = rendered.subject.unwrap_or_default();
+        request.content.body = rendered.body;
+        if request.content.personalization.is_some() {
+            request.content.personalization = Some(serde_json::to_value(RenderPersonalization {
+                blocks: rendered.blocks,
+                ..Default::default()
+            })?);
+        }
+        Ok(request)
+    }
+
+    /// Render a single text body (SMS, WhatsApp) for `user_id`.
+    pub async fn render_text(
+        &self,
+        user_id: &str,
+        channel: ActivationChannel,
+        body: &str,
+    ) -> anyhow::Result<String> {
+        let rendered = self
+            .render(&RenderRequest::new(user_id, channel, body))
+            .await?;
+        Ok(rendered.body)
+    }
+
+    /// Names read by any template in `request`. Unparseable sources are
+    /// skipped; `render` reports them before this is consulted.
+    fn referenced_variables(&self, request: &RenderRequest) -> BTreeSet<String> {
+        template_sources(request)
+            .filter_map(|source| self.plain.parse(source).ok())
+            .flat_map(|parsed| parsed.referenced_variables())
+            .collect()
+    }
+
+    async fn build_context(
+        &self,
+        request: &RenderRequest,
+        profile: &RenderProfile,
+        send: bool,
+    ) -> anyhow::Result<TemplateContext> {
+        let mut context = TemplateContext {
+            user: profile.attributes.clone(),
+            event: request.event.clone(),
+            campaign: request.campaign.clone(),
+            ..Default::default()
+        };
+        context
+            .user
+            .insert("id".to_string(), Value::String(request.user_id.clone()));
+
+        for var in self.variables.list() {
+            let value = profile
+                .attributes
+                .get(&var.name)
+                .cloned()
+                .or_else(|| var.default_value.clone().map(Value::String));
+            if let Some(value) = value {
+                context.variables.insert(var.name, value);
+            }
+        }
+        context.variables.extend(request.variables.clone());
+
+        let supplied: HashMap<String, String> = context
+            .variables
+            .iter()
+            .map(|(k, v)| (k.clone(), display(v)))
+            .collect();
+        // Required variables (the address, the unsubscribe link) are an
+        // email concern; other channels only need the ones they reference.
+        let mut missing = Vec::new();
+        if send && request.channel == ActivationChannel::Email {
+            missing = self.variables.validate(&supplied);
+        } else {
+            let referenced = self.referenced_variables(request);
+            for var in self.variables.list() {
+                if var.required
+                    && var.default_value.is_none()
+                    && referenced.contains(&var.name)
+                    && !supplied.contains_key(&var.name)
+                {
+                    missing.push(format!("Required variable '{}' is missing", var.name));
+                }
+            }
+        }
+        if !missing.is_empty() {
+            bail!("{}", missing.join("; "));
+        }
+
+        if !request.connected.is_empty() {
+            let mut url_vars = supplied.clone();
+            for (key, value) in &profile.attributes {
+                url_vars.insert(format!("user.{}", key), display(value));
+            }
+            url_vars.insert("user.id".to_string(), request.user_id.clone());
+            for (alias, source_id) in &request.connected {
+                let result = self.connected.fetch(source_id, &url_vars).await?;
+                context.connected.insert(alias.clone(), result.data);
+            }
+        }
+
+        for (alias, lookup) in &request.catalog {
+            let item = self
+                .catalogs
+                .get_item(&lookup.catalog_id, &lookup.item_id)
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Catalog item '{}' not found for '{}'",
+                        lookup.item_id,
+                        alias
+                    )
+                })?;
+            // Out-of-stock items are left unset so templates can fall back
+            // with `{% if catalog.alias %}`.
+            if !item.is_available() {
+                tracing::debug!(item_id = %item.id, alias = %alias, "Skipping out-of-stock catalog item");
+                continue;
+            }
+            context
+                .catalog
+                .insert(alias.clone(), catalog_value(&item.id, item.data));
+        }
+
+        // Recommendations are keyed by UUID; other user IDs get the
+        // non-personalized (popularity, trending, new arrival) rankings.
+        let recommendation_user = Uuid::parse_str(&request.user_id).unwrap_or(Uuid::nil());
+        for (slot, spec) in &request.recommendations {
+            let response = self.recommendations.recommend(&RecommendationRequest {
+                user_id: recommendation_user,
+                strategy: spec.strategy.clone(),
+                catalog_name: spec.catalog_name.clone(),
+                limit: spec.limit,
+                exclude_ids: Vec::new(),
+                context: request.event.clone(),
+            });
+            let items = response
+                .items
+                .into_iter()
+                .filter_map(|item| {
+                    let mut value: serde_json::Map<String, Value> =
+                        item.metadata.into_iter().collect();
+                    if let Some(record) = spec
+                        .catalog_id
+                        .and_then(|catalog_id| self.catalogs.get_item(&catalog_id, &item.item_id))
+                    {
+                        if !record.is_available() {
+                            return None;
+                        }
+                        value.extend(record.data);
+                    }
+                    value.insert("id".to_string(), Value::String(item.item_id));
+                    value.insert("score".to_string(), Value::from(item.score));
+                    value.insert("reason".to_string(), Value::String(item.reason));
+                    Some(Value::Object(value))
+                })
+                .collect();
+            context
+                .recommendations
+                .insert(slot.clone(), Value::Array(items));
+        }
+
+        Ok(context)
+    }
+}
+
+/// Render `template` with flat `{{ name }}` variables for builder previews.
+pub fn render_with_variables(
+    template: &str,
+    variables: &HashMap<String, String>,
+    format: OutputFormat,
+) -> Result<String, campaign_personalization::templating::TemplateError> {
+    let context = TemplateContext {
+        variables: variables
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::String(v.clone())))
+            .collect(),
+        ..Default::default()
+    };
+    TemplateEngine::new()
+        .with_output_format(format)
+        .render(template, &context)
+}
+
+/// Subject, body, block content and block rule values of a request.
+fn template_sources(request: &RenderRequest) -> impl Iterator<Item = &String> {
+    request
+        .subject
+        .iter()
+        .chain(std::iter::once(&request.body))
+        .chain(request.blocks.iter().flat_map(|b| b.content.values()))
+        .chain(
+            request
+                .block_rules
+                .iter()
+                .flat_map(|p| p.rules.iter().flat_map(|r| [&r.value, &r.fallback])),
+        )
+}
+
+fn catalog_value(id: &str, data: HashMap<String, Value>) -> Value {
+    let mut value: serde_json::Map<String, Value> = data.into_iter().collect();
+    value
+        .entry("id".to_string())
+        .or_insert_with(|| Value::String(id.to_string()));
+    Value::Object(value)
+}
+
+fn display(value: &Value) -> String {
+    match value {
+        Value::String(s) => s.clone(),
+        Value::Null => String::new(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::content_studio::{BlockType, PersonalizationCondition, PersonalizationRule};
+    use campaign_personalization::catalog::CatalogItem;
+    use serde_json::json;
+
+    fn service() -> RenderingService {
+        let catalogs = Arc::new(CatalogEngine::new());
+        let catalog_id = Uuid::from_u128(7);
+        for (id, name) in [("sku-1", "Espresso <Pro>"), ("sku-2", "Grinder")] {
+            catalogs.add_item(CatalogItem {
+                id: id.to_string(),
+                catalog_id,
+                data: HashMap::from([("name".to_string(), json!(name))]),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            });
+        }
+        let recommendations = Arc::new(RecommendationEngine::new());
+        for _ in 0..3 {
+            recommendations.record_interaction(Uuid::new_v4(), "sku-2".to_string());
+        }
+        recommendations.record_interaction(Uuid::new_v4(), "sku-1".to_string());
+
+        let profiles = Arc::new(InMemoryProfiles::new());
+        profiles.upsert(
+            "u-1",
+            RenderProfile {
+                attributes: HashMap::from([
+                    ("first_name".to_string(), json!("Jane")),
+                    ("email".to_string(), json!("jane@example.com")),
+                    ("ltv".to_string(), json!(750.0)),
+                ]),
+                segments: vec![42],
+            },
+        );
+        RenderingService::new(Arc::new(VariableBrowser::new()))
+            .with_catalogs(catalogs)
+            .with_recommendations(recommendations)
+            .with_profiles(profiles)
+    }
+
+    #[tokio::test]
+    async fn test_render_uses_one_namespace_for_all_sources() {
+        let service = service();
+        let mut request = RenderRequest::new(
+            "u-1",
+            ActivationChannel::Email,
+            "Hi {{ first_name }} ({{ loyalty_tier }}): {{ catalog.hero.name }} | \
+             {% for r in recommendations.top %}{{ r.name }};{% endfor %}",
+        );
+        request.subject = Some("{{ user.first_name }} & friends".to_string());
+        request.catalog.insert(
+            "hero".to_string(),
+            CatalogLookup {
+                catalog_id: Uuid::from_u128(7),
+                item_id: "sku-1".to_string(),
+            },
+        );
+        request.recommendations.insert(
+            "top".to_string(),
+            RecommendationSlot {
+                strategy: RecommendationStrategy::MostPopular,
+                limit: 2,
+                catalog_name: None,
+                catalog_id: Some(Uuid::from_u128(7)),
+            },
+        );
+
+        let rendered = service.render(&request).await.unwrap();
+        assert_eq!(
+            rendered.body,
+            "Hi Jane (Bronze): Espresso &lt;Pro&gt; | Grinder;Espresso &lt;Pro&gt;;"
+        );
+        assert_eq!(rendered.subject.as_deref(), Some("Jane & friends"));
+
+        request.channel = ActivationChannel::Sms;
+        let rendered = service.render(&request).await.unwrap();
+        assert!(rendered.body.contains("Espresso <Pro>"));
+
+        // Out of stock: the lookup is left unset and the slot skips it.
+        service.catalogs.add_item(CatalogItem {
+            id: "sku-1".to_string(),
+            catalog_id: Uuid::from_u128(7),
+            data: HashMap::from([
+                ("name".to_string(), json!("Espresso <Pro>")),
+                ("in_stock".to_string(), json!(false)),
+            ]),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        });
+        request.body =
+            "{% if catalog.hero %}{{ catalog.hero.name }}{% else %}Sold out{% endif %} | \
+                        {% for r in recommendations.top %}{{ r.name }};{% endfor %}"
+                .to_string();
+        let rendered = service.render(&request).await.unwrap();
+        assert_eq!(rendered.body, "Sold out | Grinder;");
+    }
+
+    #[tokio::test]
+    async fn test_render_rejects_unknown_variables_and_missing_required() {
+        let service = service();
+        let request = RenderRequest::new("u-1", ActivationChannel::Email, "Hi {{ frist_name }}");
+        let err = service.render(&request).await.unwrap_err();
+        assert!(err.to_string().contains("Unknown variable 'frist_name'"));
+
+        let request = RenderRequest::new("u-2", ActivationChannel::Email, "Hi {{ first_name }}");
+        let err = service.render(&request).await.unwrap_err();
+        assert!(err.to_string().contains("'email' is missing"));
+
+        let errors = service.validate("{% if first_name %}", &[]);
+        assert!(errors[0].contains("line 1, column 4"));
+    }
+
+    #[tokio::test]
+    async fn test_block_rules_then_variables() {
+        let service = service();
+        let block_id = Uuid::new_v4();
+        let mut request = RenderRequest::new("u-1", ActivationChannel::Email, "");
+        request.blocks.push(EmailBlock {
+            id: block_id,
+            block_type: BlockType::Hero,
+            content: HashMap::from([("headline".to_string(), "Hello".to_string())]),
+            styles: HashMap::new(),
+            mobile_styles: None,
+            sort_order: 0,
+            snippet_id: None,
+        });
+        request.block_rules.push(BlockPersonalization {
+            block_id,
+            rules: vec![PersonalizationRule {
+                field: "headline".to_string(),
+                condition: PersonalizationCondition::SegmentMember(42),
+                value: "Welcome back, {{ first_name }}!".to_string(),
+                fallback: "Welcome!".to_string(),
+            }],
+        });
+
+        let rendered = service.render(&request).await.unwrap();
+        assert_eq!(
+            rendered.blocks[0].content["headline"],
+            "Welcome back, Jane!"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_render_activation_uses_profile_and_extra_variables() {
+        let service = service();
+        let request = ActivationRequest {
+            activation_id: "act-1".to_string(),
+            user_id: "u-1".to_string(),
+            offer_id: "offer-1".to_string(),
+            channel: ActivationChannel::Sms,
+            content: campaign_core::channels::ActivationContent {
+                headline: "For {{ first_name }}".to_string(),
+                body: "{{ first_name }}, use {{ offer_url }} ({{ campaign.id }})".to_string(),
+                image_url: None,
+                cta_url: None,
+                cta_text: None,
+                deep_link: None,
+                audience_segment_id: None,
+                extra: Some(json!({"offer_url": "https://x.test/o?a=1&b=2", "count": 3})),
+                personalization: None,
+            },
+            priority: 1,
+            scheduled_at: None,
+            created_at: Utc::now(),
+            trigger_event_id: None,
+            trigger_source: None,
+            campaign_id: Some("camp-1".to_string()),
+            experiment_variant_id: None,
+            creative_id: None,
+            decision_id: None,
+        };
+
+        let rendered = service.render_activation(&request, &[]).await.unwrap();
+        assert_eq!(rendered.content.headline, "For Jane");
+        assert_eq!(
+            rendered.content.body,
+            "Jane, use https://x.test/o?a=1&b=2 (camp-1)"
+        );
+
+        // Unknown users still render on channels that need no address.
+        let mut anonymous = request.clone();
+        anonymous.user_id = "u-2".to_string();
+        let rendered = service.render_activation(&anonymous, &[]).await.unwrap();
+        assert!(rendered.content.body.starts_with("Valued Customer"));
+
+        // Email requires the address, which the sender can supply.
+        anonymous.channel = ActivationChannel::Email;
+        assert!(service.render_activation(&anonymous, &[]).await.is_err());
+        assert!(service
+            .render_activation(&anonymous, &[("email", "x@example.com")])
+            .await
+            .is_ok());
+
+        assert_eq!(
+            service
+                .render_text("u-1", ActivationChannel::Sms, "Hi {{ user.first_name }}")
+                .await
+                .unwrap(),
+            "Hi Jane"
+        );
+
+        // Catalog lookups and builder blocks ride on the activation content.
+        let block_id = Uuid::new_v4();
+        let mut personalized = request.clone();
+        personalized.content.body = "{{ catalog.hero.name }}".to_string();
+        personalized.content.personalization = Some(json!({
+            "catalog": {
+                "hero": { "catalog_id": Uuid::from_u128(7), "item_id": "sku-2" }
+            },
+            "blocks": [{
+                "id": block_id,
+                "block_type": "Hero",
+                "content": { "headline": "Hello" },
+                "styles": {},
+                "mobile_styles": null,
+                "sort_order": 0,
+                "snippet_id": null,
+            }],
+            "block_rules": [{
+                "block_id": block_id,
+                "rules": [{
+                    "field": "headline",
+                    "condition": { "segment_member": 42 },
+                    "value": "Hi {{ first_name }}, meet the {{ catalog.hero.name }}",
+                    "fallback": "Hello",
+                }],
+            }],
+        }));
+        let rendered = service.render_activation(&personalized, &[]).await.unwrap();
+        assert_eq!(rendered.content.body, "Grinder");
+        let rendered: RenderPersonalization =
+            serde_json::from_value(rendered.content.personalization.unwrap()).unwrap();
+        assert!(rendered.block_rules.is_empty() && rendered.catalog.is_empty());
+        assert_eq!(
+            rendered.blocks[0].content["headline"],
+            "Hi Jane, meet the Grinder"
+        );
+
+        personalized.content.personalization = Some(json!({ "catalog": "hero" }));
+        assert!(service.render_activation(&personalized, &[]).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_renders_before_sending() {
+        let sink = campaign_core::event_bus::capture_sink();
+        let dispatcher = crate::ActivationDispatcher::new(vec![ActivationChannel::Sms])
+            .with_rendering(Arc::new(service()))
+            .with_event_sink(sink.clone());
+        let mut request: ActivationRequest = serde_json::from_value(json!({
+            "activation_id": "act-1",
+            "user_id": "u-1",
+            "offer_id": "offer-1",
+            "channel": "sms",
+            "content": {"headline": "", "body": "Hi {{ first_name }}"},
+            "priority": 1,
+            "created_at": Utc::now(),
+        }))
+        .unwrap();
+        let result = dispatcher.dispatch(&request).await;
+        assert_eq!(
+            result.status,
+            campaign_core::channels::ActivationStatus::Sent
+        );
+
+        request.content.body = "Hi {{ frist_name }}".to_string();
+        let result = dispatcher.dispatch(&request).await;
+        assert_eq!(
+            result.status,
+            campaign_core::channels::ActivationStatus::Failed
+        );
+        assert!(result
+            .error
+            .unwrap()
+            .contains("Unknown variable 'frist_name'"));
+        assert_eq!(sink.count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_dispatcher_queues_email_without_an_address() {
+        let dispatcher = crate::ActivationDispatcher::new(vec![ActivationChannel::Email])
+            .with_rendering(Arc::new(service()));
+        let mut request: ActivationRequest = serde_json::from_value(json!({
+            "activation_id": "act-1",
+            "user_id": "u-2",
+            "offer_id": "offer-1",
+            "channel": "email",
+            "content": {"headline": "Hi {{ first_name }}", "body": "<p>Your offer</p>"},
+            "priority": 1,
+            "created_at": Utc::now(),
+        }))
+        .unwrap();
+        let result = dispatcher.dispatch(&request).await;
+        assert_eq!(
+            result.status,
+            campaign_core::channels::ActivationStatus::Queued
+        );
+
+        // A template that reads the address still needs one.
+        request.content.body = "<p>Sent to {{ email }}</p>".to_string();
+        let result = dispatcher.dispatch(&request).await;
+        assert!(result
+            .error
+            .unwrap()
+            .contains("Required variable 'email' is missing"));
+    }
+}
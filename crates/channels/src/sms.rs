@@ -1,23 +1,35 @@
 //! Twilio SMS provider — send, track, and manage SMS messages with
 //! segment calculation, delivery callbacks, and bulk send support.
-
+//!
+//! Sends go through the Twilio Messages API. Status callbacks and inbound
+//! messages are authenticated with `X-Twilio-Signature`, and inbound
+//! STOP/START keywords maintain SMS opt-outs in the `SuppressionList`.
+
+use crate::rendering::RenderingService;
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
+pub use campaign_core::channels::TwilioConfig;
+use campaign_core::channels::{ActivationChannel, ActivationRequest};
 use campaign_core::event_bus::{make_event, EventSink};
-use campaign_core::types::EventType;
+use campaign_core::types::{Attribution, EventType};
+use campaign_intelligent_delivery::suppression::{SuppressionList, SuppressionReason};
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha1::Sha1;
 use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
-/// Configuration for the Twilio SMS provider.
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct TwilioConfig {
-    pub account_sid: String,
-    pub auth_token: String,
-    pub from_number: String,
-    pub messaging_service_sid: Option<String>,
-    pub status_callback_url: Option<String>,
-}
+/// Suppression-list channel used for SMS opt-outs.
+pub const SMS_SUPPRESSION_CHANNEL: &str = "sms";
+
+/// Keywords carriers and Twilio treat as opt-out requests.
+const OPT_OUT_KEYWORDS: &[&str] = &["STOP", "STOPALL", "UNSUBSCRIBE", "CANCEL", "END", "QUIT"];
+/// Keywords that re-subscribe a previously opted-out number.
+const OPT_IN_KEYWORDS: &[&str] = &["START", "YES", "UNSTOP"];
+const HELP_KEYWORDS: &[&str] = &["HELP", "INFO"];
 
 /// Status of an SMS message through its lifecycle.
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
@@ -43,6 +55,11 @@ pub struct SmsMessage {
     pub created_at: DateTime<Utc>,
     pub updated_at: DateTime<Utc>,
     pub segments: u32,
+    pub estimated_cost: f64,
+    /// Campaign and creative this message and its status callbacks count
+    /// towards.
+    #[serde(default)]
+    pub attribution: Attribution,
 }
 
 /// A delivery event received from Twilio's status callback webhook.
@@ -55,15 +72,68 @@ pub struct SmsDeliveryEvent {
     pub error_message: Option<String>,
 }
 
+/// Character set a message body will be sent in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmsEncoding {
+    Gsm7,
+    Ucs2,
+}
+
+/// Pre-send segment and cost estimate for a message body.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SmsCostEstimate {
+    pub encoding: SmsEncoding,
+    /// Length in encoding units: GSM-7 septets or UTF-16 code units.
+    pub units: u32,
+    pub segments: u32,
+    pub estimated_cost: f64,
+}
+
+/// What an inbound message asked for.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InboundKeyword {
+    OptOut,
+    OptIn,
+    Help,
+    /// Not a compliance keyword; left for conversational handling.
+    None,
+}
+
+/// Result of handling an inbound message.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InboundSmsResult {
+    pub from: String,
+    pub keyword: InboundKeyword,
+    /// Text to reply with, if any.
+    pub reply: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TwilioMessageResponse {
+    sid: String,
+    status: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct TwilioErrorResponse {
+    code: Option<u32>,
+    message: Option<String>,
+}
+
 /// Twilio SMS provider with in-memory message store and delivery tracking.
 pub struct SmsProvider {
     config: TwilioConfig,
+    http: reqwest::Client,
     messages: DashMap<Uuid, SmsMessage>,
     /// Maps provider_id -> message Uuid for webhook lookups.
     provider_index: DashMap<String, Uuid>,
     /// Delivery events keyed by recipient phone number.
     delivery_events: DashMap<String, Vec<SmsDeliveryEvent>>,
+    suppression: Option<Arc<SuppressionList>>,
     event_sink: Arc<dyn EventSink>,
+    rendering: Option<Arc<RenderingService>>,
 }
 
 impl SmsProvider {
@@ -74,12 +144,19 @@ impl SmsProvider {
             from = %config.from_number,
             "Twilio SMS provider initialized"
         );
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
         Self {
             config,
+            http,
             messages: DashMap::new(),
             provider_index: DashMap::new(),
             delivery_events: DashMap::new(),
+            suppression: None,
             event_sink: campaign_core::event_bus::noop_sink(),
+            rendering: None,
         }
     }
 
@@ -89,13 +166,131 @@ impl SmsProvider {
         self
     }
 
-    /// Send an SMS message. Simulates the Twilio API call and returns the
-    /// created message with a generated provider_id.
-    pub fn send(&self, to: &str, body: &str, media_url: Option<String>) -> SmsMessage {
+    /// Attach the suppression list that opt-out keywords write to and
+    /// that every send is checked against.
+    pub fn with_suppression_list(mut self, suppression: Arc<SuppressionList>) -> Self {
+        self.suppression = Some(suppression);
+        self
+    }
+
+    /// Render message templates through the shared rendering service.
+    pub fn with_rendering(mut self, rendering: Arc<RenderingService>) -> Self {
+        self.rendering = Some(rendering);
+        self
+    }
+
+    /// Render `template` for `user_id` and send it to `to`. Without a
+    /// rendering service the template is sent as-is.
+    pub async fn send_to_user(
+        &self,
+        user_id: &str,
+        to: &str,
+        template: &str,
+        media_url: Option<String>,
+    ) -> anyhow::Result<SmsMessage> {
+        let body = self.render(user_id, template).await?;
+        self.send(to, &body, media_url).await
+    }
+
+    /// Send an activation's body to `to`, rendered for its user. The message
+    /// and its status callbacks are attributed to the activation's campaign
+    /// and creative.
+    pub async fn send_activation(
+        &self,
+        req: &ActivationRequest,
+        to: &str,
+    ) -> anyhow::Result<SmsMessage> {
+        let body = self.render(&req.user_id, &req.content.body).await?;
+        self.send_attributed(to, &body, req.content.image_url.clone(), req.attribution())
+            .await
+    }
+
+    async fn render(&self, user_id: &str, template: &str) -> anyhow::Result<String> {
+        match &self.rendering {
+            Some(rendering) => {
+                rendering
+                    .render_text(user_id, ActivationChannel::Sms, template)
+                    .await
+            }
+            None => Ok(template.to_string()),
+        }
+    }
+
+    /// Send an SMS message through the Twilio Messages API. Fails without
+    /// calling Twilio if the recipient has opted out of SMS.
+    pub async fn send(
+        &self,
+        to: &str,
+        body: &str,
+        media_url: Option<String>,
+    ) -> anyhow::Result<SmsMessage> {
+        self.send_attributed(to, body, media_url, Attribution::default())
+            .await
+    }
+
+    async fn send_attributed(
+        &self,
+        to: &str,
+        body: &str,
+        media_url: Option<String>,
+        attribution: Attribution,
+    ) -> anyhow::Result<SmsMessage> {
+        if let Some(suppression) = &self.suppression {
+            if suppression.is_suppressed(to, Some(SMS_SUPPRESSION_CHANNEL)) {
+                metrics::counter!("sms.suppressed").increment(1);
+                anyhow::bail!("{} has opted out of SMS", to);
+            }
+        }
+
+        let estimate = self.estimate_cost(body);
+        let url = format!(
+            "{}/2010-04-01/Accounts/{}/Messages.json",
+            self.config.api_base_url.trim_end_matches('/'),
+            self.config.account_sid
+        );
+        let mut form = vec![("To", to.to_string()), ("Body", body.to_string())];
+        match &self.config.messaging_service_sid {
+            Some(sid) => form.push(("MessagingServiceSid", sid.clone())),
+            None => form.push(("From", self.config.from_number.clone())),
+        }
+        if let Some(media) = &media_url {
+            form.push(("MediaUrl", media.clone()));
+        }
+        if let Some(callback) = &self.config.status_callback_url {
+            form.push(("StatusCallback", callback.clone()));
+        }
+
+        let response = self
+            .http
+            .post(&url)
+            .basic_auth(&self.config.account_sid, Some(&self.config.auth_token))
+            .form(&form)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let error = response.json::<TwilioErrorResponse>().await.ok();
+            metrics::counter!("sms.send_failed").increment(1);
+            anyhow::bail!(
+                "Twilio returned {} (code {}): {}",
+                status,
+                error.as_ref().and_then(|e| e.code).unwrap_or_default(),
+                error.and_then(|e| e.message).unwrap_or_default()
+            );
+        }
+        let created: TwilioMessageResponse = response.json().await?;
+
         let now = Utc::now();
         let id = Uuid::new_v4();
-        let provider_id = format!("SM{}", Uuid::new_v4().to_string().replace('-', ""));
-        let segments = Self::calculate_segments(body);
+        let provider_id = created.sid;
+        let status = match created.status.as_deref() {
+            Some("sent") => SmsStatus::Sent,
+            Some("delivered") => SmsStatus::Delivered,
+            Some("failed") => SmsStatus::Failed,
+            Some("undelivered") => SmsStatus::Undelivered,
+            _ => SmsStatus::Queued,
+        };
+        let segments = estimate.segments;
 
         let msg = SmsMessage {
             id,
@@ -103,11 +298,13 @@ impl SmsProvider {
             from: self.config.from_number.clone(),
             body: body.to_string(),
             media_url,
-            status: SmsStatus::Queued,
+            status,
             provider_id: Some(provider_id.clone()),
             created_at: now,
             updated_at: now,
             segments,
+            estimated_cost: estimate.estimated_cost,
+            attribution,
         };
 
         tracing::info!(
@@ -119,19 +316,125 @@ impl SmsProvider {
         );
 
         metrics::counter!("sms.messages_sent").increment(1);
+        metrics::counter!("sms.segments_sent").increment(u64::from(segments));
 
         // Emit ActivationSent event
-        self.event_sink.emit(make_event(
-            EventType::ActivationSent,
-            id.to_string(),
-            None,
-            None,
-        ));
+        self.event_sink.emit(
+            make_event(EventType::ActivationSent, id.to_string(), None, None)
+                .with_attribution(&msg.attribution)
+                .with_channel("sms"),
+        );
 
         self.messages.insert(id, msg.clone());
         self.provider_index.insert(provider_id, id);
 
-        msg
+        Ok(msg)
+    }
+
+    /// Estimate encoding, segment count and cost of a body before sending.
+    pub fn estimate_cost(&self, body: &str) -> SmsCostEstimate {
+        let (encoding, units) = if body.chars().all(is_gsm_7bit) {
+            (SmsEncoding::Gsm7, body.chars().map(gsm_septets).sum())
+        } else {
+            (SmsEncoding::Ucs2, body.encode_utf16().count() as u32)
+        };
+        let segments = segments_for(encoding, units);
+        SmsCostEstimate {
+            encoding,
+            units,
+            segments,
+            estimated_cost: f64::from(segments) * self.config.price_per_segment,
+        }
+    }
+
+    /// Validate an `X-Twilio-Signature` header: base64 HMAC-SHA1, keyed by
+    /// the auth token, over the full webhook URL followed by every POST
+    /// parameter name and value sorted by name.
+    pub fn validate_signature(
+        &self,
+        url: &str,
+        params: &[(String, String)],
+        signature: &str,
+    ) -> bool {
+        let mut sorted: Vec<&(String, String)> = params.iter().collect();
+        sorted.sort();
+
+        let mut mac = match Hmac::<Sha1>::new_from_slice(self.config.auth_token.as_bytes()) {
+            Ok(m) => m,
+            Err(_) => return false,
+        };
+        mac.update(url.as_bytes());
+        for (key, value) in sorted {
+            mac.update(key.as_bytes());
+            mac.update(value.as_bytes());
+        }
+
+        match BASE64.decode(signature.trim()) {
+            Ok(expected) => mac.verify_slice(&expected).is_ok(),
+            Err(_) => false,
+        }
+    }
+
+    /// Handle an inbound message. Opt-out keywords add an SMS-scoped
+    /// `UserOptOut` suppression for the sender, opt-in keywords remove it
+    /// (other suppression reasons are left alone), and HELP returns the
+    /// configured help text.
+    pub fn handle_inbound(&self, from: &str, body: &str) -> InboundSmsResult {
+        let keyword = Self::classify_keyword(body);
+        metrics::counter!("sms.inbound", "keyword" => format!("{:?}", keyword)).increment(1);
+
+        let reply = match keyword {
+            InboundKeyword::OptOut => {
+                if let Some(suppression) = &self.suppression {
+                    if !suppression.is_suppressed(from, Some(SMS_SUPPRESSION_CHANNEL)) {
+                        suppression.add(
+                            from,
+                            Some(SMS_SUPPRESSION_CHANNEL.to_string()),
+                            SuppressionReason::UserOptOut,
+                            "sms_keyword",
+                            None,
+                        );
+                    }
+                }
+                tracing::info!(from = %from, "SMS opt-out received");
+                // Twilio's Advanced Opt-Out sends the carrier-mandated confirmation.
+                None
+            }
+            InboundKeyword::OptIn => {
+                if let Some(suppression) = &self.suppression {
+                    suppression.remove_reason(
+                        from,
+                        Some(SMS_SUPPRESSION_CHANNEL),
+                        &SuppressionReason::UserOptOut,
+                    );
+                }
+                tracing::info!(from = %from, "SMS opt-in received");
+                None
+            }
+            InboundKeyword::Help => Some(self.config.help_reply.clone()),
+            InboundKeyword::None => None,
+        };
+
+        InboundSmsResult {
+            from: from.to_string(),
+            keyword,
+            reply,
+        }
+    }
+
+    /// Match a message body against the compliance keywords. Carriers only
+    /// treat the message as a keyword when it is the whole (trimmed) body.
+    pub fn classify_keyword(body: &str) -> InboundKeyword {
+        let word = body.trim().to_ascii_uppercase();
+        if OPT_OUT_KEYWORDS.contains(&word.as_str()) {
+            InboundKeyword::OptOut
+        } else if OPT_IN_KEYWORDS.contains(&word.as_str()) {
+            InboundKeyword::OptIn
+        } else if HELP_KEYWORDS.contains(&word.as_str()) {
+            InboundKeyword::Help
+        } else {
+            InboundKeyword::None
+        }
     }
 
     /// Retrieve a message by its internal ID.
@@ -169,15 +472,14 @@ impl SmsProvider {
         };
 
         let now = Utc::now();
-        let mut to_number = String::new();
-
-        if let Some(mut msg) = self.messages.get_mut(&message_id) {
-            msg.status = new_status.clone();
-            msg.updated_at = now;
-            to_number.clone_from(&msg.to);
-        } else {
-            return false;
-        }
+        let (to_number, attribution) = match self.messages.get_mut(&message_id) {
+            Some(mut msg) => {
+                msg.status = new_status.clone();
+                msg.updated_at = now;
+                (msg.to.clone(), msg.attribution.clone())
+            }
+            None => return false,
+        };
 
         let error_message = error_code.map(|code| format!("Twilio error: {}", code));
 
@@ -213,38 +515,24 @@ impl SmsProvider {
             _ => None,
         };
         if let Some(et) = event_type {
-            self.event_sink
-                .emit(make_event(et, message_id.to_string(), None, None));
+            self.event_sink.emit(
+                make_event(et, message_id.to_string(), None, None)
+                    .with_attribution(&attribution)
+                    .with_channel("sms"),
+            );
         }
 
         true
     }
 
     /// Calculate the number of SMS segments for a message body.
-    /// GSM 7-bit encoding: 160 chars per segment.
-    /// Unicode (UCS-2): 70 chars per segment.
+    /// GSM 7-bit encoding: 160 septets per segment (extension characters
+    /// take two). Unicode (UCS-2): 70 UTF-16 code units per segment.
     pub fn calculate_segments(body: &str) -> u32 {
-        if body.is_empty() {
-            return 1;
-        }
-
-        let is_gsm = body.chars().all(is_gsm_7bit);
-        let char_count = body.chars().count() as u32;
-
-        if is_gsm {
-            // GSM: 160 chars for single segment, 153 for multi-segment (UDH overhead)
-            if char_count <= 160 {
-                1
-            } else {
-                char_count.div_ceil(153)
-            }
+        if body.chars().all(is_gsm_7bit) {
+            segments_for(SmsEncoding::Gsm7, body.chars().map(gsm_septets).sum())
         } else {
-            // Unicode: 70 chars for single segment, 67 for multi-segment
-            if char_count <= 70 {
-                1
-            } else {
-                char_count.div_ceil(67)
-            }
+            segments_for(SmsEncoding::Ucs2, body.encode_utf16().count() as u32)
         }
     }
 
@@ -268,12 +556,14 @@ impl SmsProvider {
             .unwrap_or_default()
     }
 
-    /// Send multiple SMS messages in bulk. Returns the list of created messages.
-    pub fn send_bulk(&self, messages: Vec<(&str, &str)>) -> Vec<SmsMessage> {
-        messages
-            .into_iter()
-            .map(|(to, body)| self.send(to, body, None))
-            .collect()
+    /// Send multiple SMS messages in bulk. Returns one result per message,
+    /// in input order.
+    pub async fn send_bulk(&self, messages: Vec<(&str, &str)>) -> Vec<anyhow::Result<SmsMessage>> {
+        let mut results = Vec::with_capacity(messages.len());
+        for (to, body) in messages {
+            results.push(self.send(to, body, None).await);
+        }
+        results
     }
 
     /// Get a reference to the provider configuration.
@@ -282,6 +572,46 @@ impl SmsProvider {
     }
 }
 
+/// Build a TwiML `<Response>`, with a `<Message>` when there is a reply.
+pub fn twiml_response(reply: Option<&str>) -> String {
+    match reply {
+        Some(text) => {
+            let escaped = text
+                .replace('&', "&amp;")
+                .replace('<', "&lt;")
+                .replace('>', "&gt;");
+            format!(
+                "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Response><Message>{}</Message></Response>",
+                escaped
+            )
+        }
+        None => "<?xml version=\"1.0\" encoding=\"UTF-8\"?><Response></Response>".to_string(),
+    }
+}
+
+/// Segments needed for `units` encoding units. Multi-part messages lose
+/// room to the concatenation header: 153 septets or 67 code units each.
+fn segments_for(encoding: SmsEncoding, units: u32) -> u32 {
+    let (single, multi) = match encoding {
+        SmsEncoding::Gsm7 => (160, 153),
+        SmsEncoding::Ucs2 => (70, 67),
+    };
+    if units <= single {
+        1
+    } else {
+        units.div_ceil(multi)
+    }
+}
+
+/// Septets a GSM character occupies; extension-table characters are
+/// escaped and take two.
+fn gsm_septets(c: char) -> u32 {
+    match c {
+        '{' | '}' | '[' | ']' | '~' | '\\' | '^' | '|' | '\u{20AC}' => 2,
+        _ => 1,
+    }
+}
+
 /// Check whether a character is in the GSM 7-bit default alphabet.
 fn is_gsm_7bit(c: char) -> bool {
     matches!(c,
@@ -336,39 +666,95 @@ fn is_gsm_7bit(c: char) -> bool {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use axum::http::StatusCode;
+    use axum::routing::post;
+    use axum::Router;
+    use campaign_core::event_bus::CaptureSink;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
 
     fn test_config() -> TwilioConfig {
         TwilioConfig {
             account_sid: "AC_test_sid".to_string(),
             auth_token: "test_auth_token".to_string(),
             from_number: "+15551234567".to_string(),
-            messaging_service_sid: None,
             status_callback_url: Some("https://example.com/callback".to_string()),
+            ..TwilioConfig::default()
         }
     }
 
-    #[test]
-    fn test_send_and_retrieve() {
-        let provider = SmsProvider::new(test_config());
-        let msg = provider.send("+15559876543", "Hello, World!", None);
+    /// Mock Twilio Messages API. Returns the base URL and the raw form
+    /// bodies it received.
+    async fn mock_twilio() -> (String, Arc<Mutex<Vec<String>>>) {
+        let bodies = Arc::new(Mutex::new(Vec::new()));
+        let counter = Arc::new(AtomicUsize::new(0));
+        let captured = bodies.clone();
+        let app = Router::new().route(
+            "/2010-04-01/Accounts/:sid/Messages.json",
+            post(move |body: String| {
+                let captured = captured.clone();
+                let counter = counter.clone();
+                async move {
+                    captured.lock().unwrap().push(body);
+                    let n = counter.fetch_add(1, Ordering::SeqCst);
+                    (
+                        StatusCode::CREATED,
+                        axum::Json(serde_json::json!({
+                            "sid": format!("SM{:032}", n),
+                            "status": "queued",
+                        })),
+                    )
+                }
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        (format!("http://{}", addr), bodies)
+    }
+
+    async fn mock_provider() -> (SmsProvider, Arc<Mutex<Vec<String>>>) {
+        let (base_url, bodies) = mock_twilio().await;
+        let config = TwilioConfig {
+            api_base_url: base_url,
+            ..test_config()
+        };
+        (SmsProvider::new(config), bodies)
+    }
+
+    #[tokio::test]
+    async fn test_send_and_retrieve() {
+        let (provider, bodies) = mock_provider().await;
+        let msg = provider
+            .send("+15559876543", "Hello, World!", None)
+            .await
+            .unwrap();
 
         assert_eq!(msg.to, "+15559876543");
         assert_eq!(msg.from, "+15551234567");
         assert_eq!(msg.body, "Hello, World!");
         assert_eq!(msg.status, SmsStatus::Queued);
-        assert!(msg.provider_id.is_some());
+        assert!(msg.provider_id.as_deref().unwrap().starts_with("SM"));
         assert_eq!(msg.segments, 1);
 
+        let body = bodies.lock().unwrap()[0].clone();
+        assert!(body.contains("To=%2B15559876543"));
+        assert!(body.contains("From=%2B15551234567"));
+        assert!(body.contains("StatusCallback="));
+
         // Retrieve the message by ID
         let retrieved = provider.get_message(msg.id).unwrap();
         assert_eq!(retrieved.id, msg.id);
         assert_eq!(retrieved.body, "Hello, World!");
     }
 
-    #[test]
-    fn test_status_callback() {
-        let provider = SmsProvider::new(test_config());
-        let msg = provider.send("+15559876543", "Test callback", None);
+    #[tokio::test]
+    async fn test_status_callback() {
+        let (provider, _) = mock_provider().await;
+        let msg = provider
+            .send("+15559876543", "Test callback", None)
+            .await
+            .unwrap();
         let provider_id = msg.provider_id.as_ref().unwrap().clone();
 
         // Simulate delivery callback
@@ -385,10 +771,60 @@ mod tests {
         assert!(events[0].error_code.is_none());
     }
 
-    #[test]
-    fn test_status_callback_with_error() {
-        let provider = SmsProvider::new(test_config());
-        let msg = provider.send("+15559876543", "Test failure", None);
+    #[tokio::test]
+    async fn test_activation_status_callbacks_keep_attribution() {
+        let (provider, _) = mock_provider().await;
+        let sink = Arc::new(CaptureSink::new());
+        let provider = provider.with_event_sink(sink.clone());
+        let req = ActivationRequest {
+            activation_id: "act-1".to_string(),
+            decision_id: None,
+            user_id: "user-1".to_string(),
+            channel: ActivationChannel::Sms,
+            offer_id: "offer-1".to_string(),
+            content: campaign_core::channels::ActivationContent {
+                headline: String::new(),
+                body: "Your offer".to_string(),
+                image_url: None,
+                cta_url: None,
+                cta_text: None,
+                deep_link: None,
+                audience_segment_id: None,
+                extra: None,
+                personalization: None,
+            },
+            priority: 1,
+            scheduled_at: None,
+            created_at: Utc::now(),
+            trigger_event_id: None,
+            trigger_source: None,
+            campaign_id: Some("camp-1".to_string()),
+            experiment_variant_id: None,
+            creative_id: Some("creative-1".to_string()),
+        };
+        let msg = provider
+            .send_activation(&req, "+15559876543")
+            .await
+            .unwrap();
+        provider.handle_status_callback(msg.provider_id.as_deref().unwrap(), "delivered", None);
+
+        let events = sink.events();
+        assert_eq!(events.len(), 2);
+        for event in &events {
+            assert_eq!(event.campaign_id.as_deref(), Some("camp-1"));
+            assert_eq!(event.creative_id.as_deref(), Some("creative-1"));
+            assert_eq!(event.channel.as_deref(), Some("sms"));
+        }
+        assert_eq!(events[1].event_type, EventType::ActivationDelivered);
+    }
+
+    #[tokio::test]
+    async fn test_status_callback_with_error() {
+        let (provider, _) = mock_provider().await;
+        let msg = provider
+            .send("+15559876543", "Test failure", None)
+            .await
+            .unwrap();
         let provider_id = msg.provider_id.as_ref().unwrap().clone();
 
         let updated = provider.handle_status_callback(&provider_id, "failed", Some("30006"));
@@ -436,25 +872,35 @@ mod tests {
         assert_eq!(SmsProvider::calculate_segments(&body), 3);
     }
 
+    #[test]
+    fn test_calculate_segments_gsm_extension_chars() {
+        // 159 ASCII + '{' = 161 septets -> 2 segments
+        let body = format!("{}{{", "A".repeat(159));
+        assert_eq!(SmsProvider::calculate_segments(&body), 2);
+    }
+
     #[test]
     fn test_calculate_segments_unicode_short() {
-        // Unicode message with emoji: 1 segment if <= 70 chars
-        let body = "\u{1F600}".repeat(10); // 10 emoji
+        // Unicode message with emoji: 1 segment if <= 70 code units
+        let body = "\u{1F600}".repeat(10); // 10 emoji = 20 code units
         assert_eq!(SmsProvider::calculate_segments(&body), 1);
     }
 
     #[test]
     fn test_calculate_segments_unicode_exactly_70() {
-        // 69 ascii + 1 emoji = 70 chars with unicode -> 1 segment
-        let body = format!("{}\u{1F600}", "A".repeat(69));
+        // 69 ascii + 1 Cyrillic letter = 70 UCS-2 code units -> 1 segment
+        let body = format!("{}\u{0416}", "A".repeat(69));
         assert_eq!(SmsProvider::calculate_segments(&body), 1);
+
+        // An emoji is a surrogate pair, so the same length overflows
+        let body = format!("{}\u{1F600}", "A".repeat(69));
+        assert_eq!(SmsProvider::calculate_segments(&body), 2);
     }
 
     #[test]
     fn test_calculate_segments_unicode_multi() {
-        // 71 unicode chars -> 2 segments (67 + 4)
+        // 70 ascii + emoji = 72 code units => Unicode => ceil(72/67) = 2
         let body = format!("{}\u{1F600}", "A".repeat(70));
-        // 71 chars, has emoji => Unicode => ceil(71/67) = 2
         assert_eq!(SmsProvider::calculate_segments(&body), 2);
     }
 
@@ -464,26 +910,45 @@ mod tests {
     }
 
     #[test]
-    fn test_list_messages() {
+    fn test_estimate_cost() {
         let provider = SmsProvider::new(test_config());
-        provider.send("+15551111111", "Message 1", None);
-        provider.send("+15552222222", "Message 2", None);
-        provider.send("+15553333333", "Message 3", None);
+        let estimate = provider.estimate_cost(&"\u{0416}".repeat(100));
+        assert_eq!(estimate.encoding, SmsEncoding::Ucs2);
+        assert_eq!(estimate.units, 100);
+        assert_eq!(estimate.segments, 2);
+        assert!((estimate.estimated_cost - 2.0 * 0.0079).abs() < 1e-9);
+    }
+
+    #[tokio::test]
+    async fn test_list_messages() {
+        let (provider, _) = mock_provider().await;
+        for (to, body) in [
+            ("+15551111111", "Message 1"),
+            ("+15552222222", "Message 2"),
+            ("+15553333333", "Message 3"),
+        ] {
+            provider.send(to, body, None).await.unwrap();
+        }
 
         let msgs = provider.list_messages(2);
         assert_eq!(msgs.len(), 2);
     }
 
-    #[test]
-    fn test_send_bulk() {
-        let provider = SmsProvider::new(test_config());
+    #[tokio::test]
+    async fn test_send_bulk() {
+        let (provider, _) = mock_provider().await;
         let messages = vec![
             ("+15551111111", "Bulk msg 1"),
             ("+15552222222", "Bulk msg 2"),
             ("+15553333333", "Bulk msg 3"),
         ];
 
-        let results = provider.send_bulk(messages);
+        let results: Vec<SmsMessage> = provider
+            .send_bulk(messages)
+            .await
+            .into_iter()
+            .map(|r| r.unwrap())
+            .collect();
         assert_eq!(results.len(), 3);
         assert_eq!(results[0].to, "+15551111111");
         assert_eq!(results[1].to, "+15552222222");
@@ -495,17 +960,75 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_send_with_media() {
-        let provider = SmsProvider::new(test_config());
-        let msg = provider.send(
-            "+15559876543",
-            "Check this out!",
-            Some("https://example.com/image.jpg".to_string()),
-        );
+    #[tokio::test]
+    async fn test_send_with_media() {
+        let (provider, bodies) = mock_provider().await;
+        let msg = provider
+            .send(
+                "+15559876543",
+                "Check this out!",
+                Some("https://example.com/image.jpg".to_string()),
+            )
+            .await
+            .unwrap();
         assert_eq!(
             msg.media_url,
             Some("https://example.com/image.jpg".to_string())
         );
+        assert!(bodies.lock().unwrap()[0].contains("MediaUrl="));
+    }
+
+    #[test]
+    fn test_validate_signature() {
+        // Example from Twilio's webhook security documentation.
+        let provider = SmsProvider::new(TwilioConfig {
+            auth_token: "12345".to_string(),
+            ..test_config()
+        });
+        let url = "https://mycompany.com/myapp.php?foo=1&bar=2";
+        let params: Vec<(String, String)> = [
+            ("CallSid", "CA1234567890ABCDE"),
+            ("Caller", "+12349013030"),
+            ("Digits", "1234"),
+            ("From", "+12349013030"),
+            ("To", "+18005551212"),
+        ]
+        .iter()
+        .map(|(k, v)| (k.to_string(), v.to_string()))
+        .collect();
+
+        assert!(provider.validate_signature(url, &params, "0/KCTR6DLpKmkAf8muzZqo1nDgQ="));
+        assert!(!provider.validate_signature(url, &params, "AAAAAAAAAAAAAAAAAAAAAAAAAAA="));
+        assert!(!provider.validate_signature(url, &params[1..], "0/KCTR6DLpKmkAf8muzZqo1nDgQ="));
+    }
+
+    #[tokio::test]
+    async fn test_stop_and_start_keywords() {
+        let (base_url, bodies) = mock_twilio().await;
+        let suppression = Arc::new(SuppressionList::new());
+        let provider = SmsProvider::new(TwilioConfig {
+            api_base_url: base_url,
+            ..test_config()
+        })
+        .with_suppression_list(suppression.clone());
+
+        let result = provider.handle_inbound("+15559876543", " stop ");
+        assert_eq!(result.keyword, InboundKeyword::OptOut);
+        assert!(suppression.is_suppressed("+15559876543", Some(SMS_SUPPRESSION_CHANNEL)));
+        assert!(provider.send("+15559876543", "Sale!", None).await.is_err());
+        assert!(bodies.lock().unwrap().is_empty());
+
+        let help = provider.handle_inbound("+15559876543", "HELP");
+        assert_eq!(help.keyword, InboundKeyword::Help);
+        assert!(help.reply.unwrap().contains("STOP"));
+
+        let result = provider.handle_inbound("+15559876543", "START");
+        assert_eq!(result.keyword, InboundKeyword::OptIn);
+        assert!(!suppression.is_suppressed("+15559876543", Some(SMS_SUPPRESSION_CHANNEL)));
+        assert!(provider.send("+15559876543", "Sale!", None).await.is_ok());
+
+        // Free text that merely contains a keyword is not an opt-out.
+        let result = provider.handle_inbound("+15559876543", "please don't stop");
+        assert_eq!(result.keyword, InboundKeyword::None);
     }
 }
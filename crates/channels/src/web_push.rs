@@ -1,9 +1,39 @@
 //! Web push notifications via Web Push Protocol (RFC 8030) and VAPID.
+//!
+//! Payloads are encrypted with `aes128gcm` content coding (RFC 8188/8291)
+//! against the subscription's p256dh and auth keys, and every request is
+//! authorized with an ES256 VAPID JWT (RFC 8292). Subscriptions the push
+//! service reports as gone (404/410) are unregistered automatically.
 
+use aes_gcm::aead::{Aead, KeyInit};
+use aes_gcm::{Aes128Gcm, Nonce};
+use base64::engine::general_purpose::URL_SAFE_NO_PAD as B64URL;
+use base64::Engine;
 use chrono::{DateTime, Utc};
+use hkdf::Hkdf;
+use p256::ecdsa::signature::Signer;
+use p256::ecdsa::{Signature, SigningKey};
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::{PublicKey, SecretKey};
+use rand::rngs::OsRng;
+use rand::RngCore;
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
 use uuid::Uuid;
 
+/// Record size advertised in the aes128gcm header. A single record carries
+/// the whole message, so this also bounds the payload.
+const RECORD_SIZE: u32 = 4096;
+/// salt (16) + rs (4) + idlen (1) + keyid (65).
+const HEADER_LEN: usize = 86;
+/// AES-GCM authentication tag length.
+const TAG_LEN: usize = 16;
+/// Largest plaintext that fits one record after the delimiter and tag.
+pub const MAX_PAYLOAD_BYTES: usize = RECORD_SIZE as usize - HEADER_LEN - TAG_LEN - 1;
+/// VAPID tokens must expire within 24 hours; 12 leaves room for clock skew.
+const VAPID_TOKEN_LIFETIME_SECS: i64 = 12 * 3600;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebPushSubscription {
     pub id: Uuid,
@@ -52,6 +82,18 @@ pub enum PushUrgency {
     High,
 }
 
+impl PushUrgency {
+    /// Value for the RFC 8030 `Urgency` header.
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            PushUrgency::VeryLow => "very-low",
+            PushUrgency::Low => "low",
+            PushUrgency::Normal => "normal",
+            PushUrgency::High => "high",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct PushDeliveryResult {
     pub subscription_id: Uuid,
@@ -63,8 +105,11 @@ pub struct PushDeliveryResult {
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct WebPushConfig {
+    /// Uncompressed P-256 application server key, base64url.
     pub vapid_public_key: String,
+    /// Raw 32-byte P-256 private key, base64url.
     pub vapid_private_key: String,
+    /// Contact URI for the push service operator (`mailto:` or `https:`).
     pub subject: String,
     pub default_ttl: u32,
 }
@@ -72,13 +117,30 @@ pub struct WebPushConfig {
 pub struct WebPushProvider {
     config: WebPushConfig,
     subscriptions: dashmap::DashMap<Uuid, Vec<WebPushSubscription>>,
+    http: reqwest::Client,
+    vapid_key: Option<SigningKey>,
 }
 
 impl WebPushProvider {
     pub fn new(config: WebPushConfig) -> Self {
+        let vapid_key = B64URL
+            .decode(config.vapid_private_key.trim())
+            .ok()
+            .and_then(|raw| SigningKey::from_slice(&raw).ok());
+        if vapid_key.is_none() {
+            tracing::warn!("VAPID private key is missing or invalid; web push sends will fail");
+        }
+
+        let http = reqwest::Client::builder()
+            .timeout(Duration::from_secs(10))
+            .build()
+            .unwrap_or_default();
+
         Self {
             config,
             subscriptions: dashmap::DashMap::new(),
+            http,
+            vapid_key,
         }
     }
 
@@ -95,6 +157,8 @@ impl WebPushProvider {
         }
     }
 
+    /// Encrypt and deliver a notification to every subscription of a user.
+    /// Subscriptions answered with 404 or 410 are removed.
     pub async fn send_notification(
         &self,
         user_id: &Uuid,
@@ -106,24 +170,399 @@ impl WebPushProvider {
             .map(|s| s.clone())
             .unwrap_or_default();
 
+        let payload = match serde_json::to_vec(&Self::build_payload(notification)) {
+            Ok(p) => p,
+            Err(e) => {
+                return subs
+                    .iter()
+                    .map(|sub| Self::failure(sub.id, None, e.to_string()))
+                    .collect();
+            }
+        };
+
         let mut results = Vec::new();
         for sub in &subs {
-            tracing::info!(
+            tracing::debug!(
                 user_id = %user_id,
                 sub_id = %sub.id,
                 endpoint = &sub.endpoint,
                 title = &notification.title,
-                vapid_subject = &self.config.subject,
                 "Sending web push notification"
             );
-            results.push(PushDeliveryResult {
-                subscription_id: sub.id,
-                success: true,
-                status_code: Some(201),
-                error: None,
-                sent_at: Utc::now(),
-            });
+
+            let result = match self.deliver(sub, &payload, notification).await {
+                Ok(status) if (200..300).contains(&status) => {
+                    metrics::counter!("web_push.delivered").increment(1);
+                    PushDeliveryResult {
+                        subscription_id: sub.id,
+                        success: true,
+                        status_code: Some(status),
+                        error: None,
+                        sent_at: Utc::now(),
+                    }
+                }
+                Ok(status) => {
+                    if status == 404 || status == 410 {
+                        tracing::info!(
+                            sub_id = %sub.id,
+                            status,
+                            "Push subscription expired, unregistering"
+                        );
+                        metrics::counter!("web_push.subscriptions_expired").increment(1);
+                        self.unregister_subscription(user_id, &sub.id);
+                    }
+                    metrics::counter!("web_push.failed").increment(1);
+                    Self::failure(
+                        sub.id,
+                        Some(status),
+                        format!("push service returned {}", status),
+                    )
+                }
+                Err(e) => {
+                    metrics::counter!("web_push.failed").increment(1);
+                    Self::failure(sub.id, None, e.to_string())
+                }
+            };
+            results.push(result);
         }
         results
     }
+
+    /// Encrypt the payload for one subscription and POST it to the push
+    /// service. Returns the HTTP status code.
+    async fn deliver(
+        &self,
+        sub: &WebPushSubscription,
+        payload: &[u8],
+        notification: &WebPushNotification,
+    ) -> anyhow::Result<u16> {
+        let body = encrypt_payload(payload, &sub.p256dh_key, &sub.auth_secret)?;
+        let authorization = self.vapid_authorization(&sub.endpoint)?;
+        let ttl = if notification.ttl_seconds > 0 {
+            notification.ttl_seconds
+        } else {
+            self.config.default_ttl
+        };
+
+        let mut request = self
+            .http
+            .post(&sub.endpoint)
+            .header("Authorization", authorization)
+            .header("TTL", ttl.to_string())
+            .header("Urgency", notification.urgency.header_value())
+            .header("Content-Encoding", "aes128gcm")
+            .header("Content-Type", "application/octet-stream")
+            .body(body);
+        if let Some(topic) = notification.tag.as_deref().filter(|t| is_valid_topic(t)) {
+            request = request.header("Topic", topic);
+        }
+
+        let response = request.send().await?;
+        Ok(response.status().as_u16())
+    }
+
+    /// Build the `Authorization: vapid t=<jwt>, k=<key>` header value for
+    /// the push service hosting `endpoint`.
+    fn vapid_authorization(&self, endpoint: &str) -> anyhow::Result<String> {
+        let key = self
+            .vapid_key
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("VAPID private key not configured"))?;
+        let url = reqwest::Url::parse(endpoint)?;
+        let audience = url.origin().ascii_serialization();
+
+        let header = B64URL.encode(br#"{"typ":"JWT","alg":"ES256"}"#);
+        let claims = B64URL.encode(serde_json::to_vec(&serde_json::json!({
+            "aud": audience,
+            "exp": Utc::now().timestamp() + VAPID_TOKEN_LIFETIME_SECS,
+            "sub": self.config.subject,
+        }))?);
+        let signing_input = format!("{}.{}", header, claims);
+        let signature: Signature = key.sign(signing_input.as_bytes());
+        let public_key = B64URL.encode(key.verifying_key().to_encoded_point(false).as_bytes());
+
+        Ok(format!(
+            "vapid t={}.{}, k={}",
+            signing_input,
+            B64URL.encode(signature.to_bytes()),
+            public_key
+        ))
+    }
+
+    /// The JSON document handed to the service worker's `push` event.
+    fn build_payload(notification: &WebPushNotification) -> serde_json::Value {
+        serde_json::json!({
+            "id": notification.id,
+            "campaign_id": notification.campaign_id,
+            "title": notification.title,
+            "body": notification.body,
+            "icon": notification.icon_url,
+            "badge": notification.badge_url,
+            "image": notification.image_url,
+            "click_action": notification.click_action,
+            "actions": notification.actions,
+            "data": notification.data,
+            "tag": notification.tag,
+            "renotify": notification.renotify,
+            "require_interaction": notification.require_interaction,
+            "silent": notification.silent,
+        })
+    }
+
+    fn failure(
+        subscription_id: Uuid,
+        status_code: Option<u16>,
+        error: String,
+    ) -> PushDeliveryResult {
+        PushDeliveryResult {
+            subscription_id,
+            success: false,
+            status_code,
+            error: Some(error),
+            sent_at: Utc::now(),
+        }
+    }
+}
+
+/// RFC 8030 topics are at most 32 characters from the base64url alphabet.
+fn is_valid_topic(topic: &str) -> bool {
+    !topic.is_empty()
+        && topic.len() <= 32
+        && topic
+            .bytes()
+            .all(|b| b.is_ascii_alphanumeric() || b == b'-' || b == b'_')
+}
+
+/// Encrypt a push message for a subscription using a fresh ephemeral key
+/// and salt (RFC 8291 §3).
+pub fn encrypt_payload(plaintext: &[u8], p256dh: &str, auth: &str) -> anyhow::Result<Vec<u8>> {
+    let as_secret = SecretKey::random(&mut OsRng);
+    let mut salt = [0u8; 16];
+    OsRng.fill_bytes(&mut salt);
+    encrypt_with(plaintext, p256dh, auth, &as_secret, &salt)
+}
+
+fn encrypt_with(
+    plaintext: &[u8],
+    p256dh: &str,
+    auth: &str,
+    as_secret: &SecretKey,
+    salt: &[u8; 16],
+) -> anyhow::Result<Vec<u8>> {
+    if plaintext.len() > MAX_PAYLOAD_BYTES {
+        anyhow::bail!(
+            "push payload is {} bytes, limit is {}",
+            plaintext.len(),
+            MAX_PAYLOAD_BYTES
+        );
+    }
+
+    let ua_public_bytes = B64URL
+        .decode(p256dh.trim())
+        .map_err(|e| anyhow::anyhow!("p256dh key is not base64url: {}", e))?;
+    let ua_public = PublicKey::from_sec1_bytes(&ua_public_bytes)
+        .map_err(|_| anyhow::anyhow!("p256dh key is not a P-256 point"))?;
+    let auth_secret = B64URL
+        .decode(auth.trim())
+        .map_err(|e| anyhow::anyhow!("auth secret is not base64url: {}", e))?;
+
+    let as_public = as_secret.public_key().to_encoded_point(false);
+    let shared = p256::ecdh::diffie_hellman(as_secret.to_nonzero_scalar(), ua_public.as_affine());
+
+    // IKM = HKDF(auth_secret, ecdh_secret, "WebPush: info" || 0 || ua_public || as_public)
+    let mut key_info = Vec::with_capacity(14 + 65 + 65);
+    key_info.extend_from_slice(b"WebPush: info\0");
+    key_info.extend_from_slice(&ua_public_bytes);
+    key_info.extend_from_slice(as_public.as_bytes());
+    let mut ikm = [0u8; 32];
+    Hkdf::<Sha256>::new(Some(&auth_secret), shared.raw_secret_bytes())
+        .expand(&key_info, &mut ikm)
+        .map_err(|_| anyhow::anyhow!("HKDF expand failed"))?;
+
+    let prk = Hkdf::<Sha256>::new(Some(salt), &ikm);
+    let mut cek = [0u8; 16];
+    let mut nonce = [0u8; 12];
+    prk.expand(b"Content-Encoding: aes128gcm\0", &mut cek)
+        .and_then(|_| prk.expand(b"Content-Encoding: nonce\0", &mut nonce))
+        .map_err(|_| anyhow::anyhow!("HKDF expand failed"))?;
+
+    // Single final record: plaintext followed by the 0x02 delimiter.
+    let mut record = Vec::with_capacity(plaintext.len() + 1);
+    record.extend_from_slice(plaintext);
+    record.push(0x02);
+    let ciphertext = Aes128Gcm::new(&cek.into())
+        .encrypt(Nonce::from_slice(&nonce), record.as_slice())
+        .map_err(|_| anyhow::anyhow!("AES-GCM encryption failed"))?;
+
+    let mut body = Vec::with_capacity(HEADER_LEN + ciphertext.len());
+    body.extend_from_slice(salt);
+    body.extend_from_slice(&RECORD_SIZE.to_be_bytes());
+    body.push(as_public.as_bytes().len() as u8);
+    body.extend_from_slice(as_public.as_bytes());
+    body.extend_from_slice(&ciphertext);
+    Ok(body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Example from RFC 8291 Appendix A.
+    const PLAINTEXT: &[u8] = b"When I grow up, I want to be a watermelon";
+    const AS_PRIVATE: &str = "yfWPiYE-n46HLnH0KqZOF1fJJU3MYrct3AELtAQ-oRw";
+    const UA_PUBLIC: &str =
+        "BCVxsr7N_eNgVRqvHtD0zTZsEc6-VV-JvLexhqUzORcxaOzi6-AYWXvTBHm4bjyPjs7Vd8pZGH6SRpkNtoIAiw4";
+    const AUTH: &str = "BTBZMqHH6r4Tts7J_aSIgg";
+    const SALT: &str = "DGv6ra1nlYgDCS1FRnbzlw";
+    const EXPECTED: &str = "DGv6ra1nlYgDCS1FRnbzlwAAEABBBP4z9KsN6nGRTbVYI_c7VJSPQTBtkgcy27mlmlMoZIIgDll6e3vCYLocInmYWAmS6TlzAC8wEqKK6PBru3jl7A_yl95bQpu6cVPTpK4Mqgkf1CXztLVBSt2Ks3oZwbuwXPXLWyouBWLVWGNWQexSgSxsj_Qulcy4a-fN";
+
+    #[test]
+    fn test_encrypt_matches_rfc8291_vector() {
+        let as_secret = SecretKey::from_slice(&B64URL.decode(AS_PRIVATE).unwrap()).unwrap();
+        let salt: [u8; 16] = B64URL.decode(SALT).unwrap().try_into().unwrap();
+
+        let body = encrypt_with(PLAINTEXT, UA_PUBLIC, AUTH, &as_secret, &salt).unwrap();
+        assert_eq!(B64URL.encode(body), EXPECTED);
+    }
+
+    #[test]
+    fn test_encrypt_rejects_oversized_payload() {
+        let payload = vec![b'x'; MAX_PAYLOAD_BYTES + 1];
+        assert!(encrypt_payload(&payload, UA_PUBLIC, AUTH).is_err());
+        let payload = vec![b'x'; MAX_PAYLOAD_BYTES];
+        assert_eq!(
+            encrypt_payload(&payload, UA_PUBLIC, AUTH).unwrap().len(),
+            RECORD_SIZE as usize
+        );
+    }
+
+    fn test_provider() -> WebPushProvider {
+        WebPushProvider::new(WebPushConfig {
+            vapid_public_key: String::new(),
+            vapid_private_key: AS_PRIVATE.to_string(),
+            subject: "mailto:ops@example.com".to_string(),
+            default_ttl: 3600,
+        })
+    }
+
+    fn test_notification() -> WebPushNotification {
+        WebPushNotification {
+            id: Uuid::new_v4(),
+            campaign_id: Uuid::new_v4(),
+            title: "Sale".to_string(),
+            body: "20% off today".to_string(),
+            icon_url: None,
+            badge_url: None,
+            image_url: None,
+            click_action: None,
+            actions: Vec::new(),
+            data: Default::default(),
+            ttl_seconds: 0,
+            urgency: PushUrgency::High,
+            require_interaction: false,
+            silent: false,
+            tag: Some("spring-sale".to_string()),
+            renotify: false,
+        }
+    }
+
+    fn test_subscription(user_id: Uuid, endpoint: String) -> WebPushSubscription {
+        WebPushSubscription {
+            id: Uuid::new_v4(),
+            user_id,
+            endpoint,
+            p256dh_key: UA_PUBLIC.to_string(),
+            auth_secret: AUTH.to_string(),
+            user_agent: None,
+            created_at: Utc::now(),
+            last_active: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_vapid_jwt_is_verifiable() {
+        use p256::ecdsa::signature::Verifier;
+
+        let provider = test_provider();
+        let header = provider
+            .vapid_authorization("https://push.example.net/send/abc")
+            .unwrap();
+        let (token, key) = header
+            .strip_prefix("vapid t=")
+            .unwrap()
+            .split_once(", k=")
+            .unwrap();
+        let (signing_input, signature) = token.rsplit_once('.').unwrap();
+
+        let claims: serde_json::Value = serde_json::from_slice(
+            &B64URL
+                .decode(signing_input.split('.').nth(1).unwrap())
+                .unwrap(),
+        )
+        .unwrap();
+        assert_eq!(claims["aud"], "https://push.example.net");
+        assert_eq!(claims["sub"], "mailto:ops@example.com");
+
+        let verifying_key =
+            p256::ecdsa::VerifyingKey::from_sec1_bytes(&B64URL.decode(key).unwrap()).unwrap();
+        let signature = Signature::from_slice(&B64URL.decode(signature).unwrap()).unwrap();
+        assert!(verifying_key
+            .verify(signing_input.as_bytes(), &signature)
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_send_sets_headers_and_unregisters_gone_subscriptions() {
+        use axum::http::{HeaderMap, StatusCode};
+        use axum::routing::post;
+        use std::sync::{Arc, Mutex};
+
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let app = axum::Router::new()
+            .route(
+                "/ok",
+                post(move |headers: HeaderMap| {
+                    recorder.lock().unwrap().push(headers);
+                    async { StatusCode::CREATED }
+                }),
+            )
+            .route("/gone", post(|| async { StatusCode::GONE }));
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let provider = test_provider();
+        let user_id = Uuid::new_v4();
+        provider.register_subscription(test_subscription(user_id, format!("{}/ok", base)));
+        provider.register_subscription(test_subscription(user_id, format!("{}/gone", base)));
+
+        let results = provider
+            .send_notification(&user_id, &test_notification())
+            .await;
+        assert_eq!(results.len(), 2);
+        assert!(results[0].success);
+        assert_eq!(results[0].status_code, Some(201));
+        assert!(!results[1].success);
+        assert_eq!(results[1].status_code, Some(410));
+
+        let headers = seen.lock().unwrap()[0].clone();
+        assert_eq!(headers["ttl"], "3600");
+        assert_eq!(headers["urgency"], "high");
+        assert_eq!(headers["topic"], "spring-sale");
+        assert_eq!(headers["content-encoding"], "aes128gcm");
+
+        // The expired subscription is gone; only one delivery remains.
+        let results = provider
+            .send_notification(&user_id, &test_notification())
+            .await;
+        assert_eq!(results.len(), 1);
+    }
+
+    #[test]
+    fn test_topic_validation() {
+        assert!(is_valid_topic("spring-sale_2024"));
+        assert!(!is_valid_topic("has space"));
+        assert!(!is_valid_topic(&"a".repeat(33)));
+    }
 }
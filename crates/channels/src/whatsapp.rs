@@ -1,9 +1,44 @@
 //! WhatsApp Business API integration for conversational messaging.
+//!
+//! Outbound messages go through the Cloud API. The inbound webhook carries
+//! customer messages, delivery statuses and template review updates; every
+//! customer message (re)opens the 24-hour customer-service window, outside
+//! of which only approved templates may be sent.
 
-use chrono::{DateTime, Utc};
+use crate::rendering::RenderingService;
+use campaign_core::channels::{ActivationChannel, ActivationRequest};
+use campaign_core::config::WhatsAppConfig;
+use campaign_core::event_bus::{make_event, EventSink};
+use campaign_core::types::{Attribution, EventType};
+use campaign_journey::JourneyEngine;
+use chrono::{DateTime, Duration, TimeZone, Utc};
+use dashmap::DashMap;
+use hmac::{Hmac, Mac};
 use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::Instant;
+use subtle::ConstantTimeEq;
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
+/// Default Cloud API (Graph API) base URL.
+pub const DEFAULT_API_BASE_URL: &str = "https://graph.facebook.com/v19.0";
+/// Header carrying the webhook payload signature (`sha256=<hex>`).
+pub const SIGNATURE_HEADER: &str = "x-hub-signature-256";
+/// Journey event type that interactive replies are routed as.
+pub const REPLY_EVENT_TYPE: &str = "whatsapp_reply";
+
+/// Timeout for Cloud API requests made through [`WhatsAppProvider::new`].
+const DEFAULT_REQUEST_TIMEOUT_MS: u64 = 10_000;
+
+/// Length of the customer-service window opened by a customer message.
+const SESSION_WINDOW_HOURS: i64 = 24;
+
+/// How long the status and attribution of an outbound message are kept.
+/// Statuses normally arrive within minutes; later ones are unattributed.
+pub const MESSAGE_STATE_TTL: std::time::Duration = std::time::Duration::from_secs(72 * 60 * 60);
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum WhatsAppMessageType {
@@ -26,7 +61,7 @@ pub struct WhatsAppTemplate {
     pub status: TemplateStatus,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TemplateCategory {
     Marketing,
@@ -34,12 +69,28 @@ pub enum TemplateCategory {
     Authentication,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum TemplateStatus {
     Pending,
     Approved,
     Rejected,
+    Paused,
+    Disabled,
+}
+
+impl TemplateStatus {
+    /// Parse the upper-case status Meta uses in the template API and in
+    /// `message_template_status_update` webhooks.
+    pub fn from_api(status: &str) -> Self {
+        match status {
+            "APPROVED" => Self::Approved,
+            "REJECTED" => Self::Rejected,
+            "PAUSED" => Self::Paused,
+            "DISABLED" => Self::Disabled,
+            _ => Self::Pending,
+        }
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -90,7 +141,7 @@ pub struct InteractiveButton {
     pub url: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MessageStatus {
     Queued,
@@ -100,43 +151,1171 @@ pub enum MessageStatus {
     Failed,
 }
 
+// ---------------------------------------------------------------------------
+// Webhook payloads
+// ---------------------------------------------------------------------------
+
+/// Top-level Cloud API webhook notification.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhatsAppWebhook {
+    #[serde(default)]
+    pub entry: Vec<WebhookEntry>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookEntry {
+    #[serde(default)]
+    pub changes: Vec<WebhookChange>,
+}
+
+/// One change; `value` is shaped by `field` (`messages` or
+/// `message_template_status_update`).
+#[derive(Debug, Clone, Deserialize)]
+pub struct WebhookChange {
+    pub field: String,
+    pub value: serde_json::Value,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct MessagesValue {
+    #[serde(default)]
+    messages: Vec<InboundMessage>,
+    #[serde(default)]
+    statuses: Vec<StatusUpdate>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundMessage {
+    from: String,
+    id: String,
+    timestamp: String,
+    #[serde(rename = "type")]
+    message_type: String,
+    text: Option<InboundText>,
+    interactive: Option<InboundInteractive>,
+    button: Option<InboundButton>,
+    context: Option<InboundContext>,
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundText {
+    body: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundInteractive {
+    button_reply: Option<ReplyOption>,
+    list_reply: Option<ReplyOption>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ReplyOption {
+    id: String,
+    title: String,
+}
+
+/// Quick-reply button on a template message.
+#[derive(Debug, Deserialize)]
+struct InboundButton {
+    payload: String,
+    text: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct InboundContext {
+    id: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct StatusUpdate {
+    id: String,
+    status: String,
+    recipient_id: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TemplateStatusValue {
+    event: String,
+    message_template_name: String,
+    message_template_language: String,
+}
+
+/// What the webhook handler extracted from a notification.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum WhatsAppInboundEvent {
+    Message {
+        from: String,
+        message_id: String,
+        body: Option<String>,
+    },
+    /// A tap on an interactive button/list row or a template quick reply.
+    InteractiveReply {
+        from: String,
+        message_id: String,
+        reply_id: String,
+        title: String,
+        context_message_id: Option<String>,
+        journey_instances: Vec<Uuid>,
+    },
+    Status {
+        message_id: String,
+        status: MessageStatus,
+    },
+    TemplateStatus {
+        name: String,
+        language: String,
+        status: TemplateStatus,
+    },
+}
+
+#[derive(Deserialize)]
+struct SendResponse {
+    messages: Vec<SentMessage>,
+}
+
+#[derive(Deserialize)]
+struct SentMessage {
+    id: String,
+}
+
+#[derive(Deserialize)]
+struct TemplateListResponse {
+    data: Vec<TemplateRecord>,
+    paging: Option<Paging>,
+}
+
+#[derive(Deserialize)]
+struct TemplateRecord {
+    name: String,
+    language: String,
+    status: String,
+    category: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct Paging {
+    next: Option<String>,
+}
+
+// ---------------------------------------------------------------------------
+// Provider
+// ---------------------------------------------------------------------------
+
 pub struct WhatsAppProvider {
     api_base_url: String,
     access_token: String,
     phone_number_id: String,
+    business_account_id: Option<String>,
+    app_secret: Option<String>,
+    verify_token: Option<String>,
+    http: reqwest::Client,
+    /// Time of the last customer message, keyed by normalized phone number.
+    sessions: DashMap<String, DateTime<Utc>>,
+    /// Known templates keyed by (name, language).
+    templates: DashMap<(String, String), WhatsAppTemplate>,
+    /// Latest status of outbound messages, keyed by WhatsApp message id,
+    /// with when the message was sent.
+    message_status: DashMap<String, (MessageStatus, Instant)>,
+    /// Campaign and creative of outbound activations, keyed by WhatsApp
+    /// message id, until the message is read or fails, or is dropped by
+    /// [`Self::prune_state`].
+    attribution: DashMap<String, (Attribution, Instant)>,
+    /// Platform user id of each recipient we have messaged, keyed by
+    /// normalized phone number, so replies enter journeys as that user.
+    recipients: DashMap<String, String>,
+    journeys: Option<JourneyEngine>,
+    event_sink: Arc<dyn EventSink>,
+    rendering: Option<Arc<RenderingService>>,
 }
 
 impl WhatsAppProvider {
     pub fn new(api_base_url: String, access_token: String, phone_number_id: String) -> Self {
+        Self::with_timeout(
+            api_base_url,
+            access_token,
+            phone_number_id,
+            DEFAULT_REQUEST_TIMEOUT_MS,
+        )
+    }
+
+    /// Build a provider from deployment config, including the webhook app
+    /// secret and verify token.
+    pub fn from_config(config: &WhatsAppConfig) -> Self {
+        let mut provider = Self::with_timeout(
+            config.api_base_url.clone(),
+            config.access_token.clone(),
+            config.phone_number_id.clone(),
+            config.request_timeout_ms,
+        );
+        provider.business_account_id = config.business_account_id.clone();
+        provider.app_secret = config.app_secret.clone();
+        provider.verify_token = config.verify_token.clone();
+        provider
+    }
+
+    fn with_timeout(
+        api_base_url: String,
+        access_token: String,
+        phone_number_id: String,
+        timeout_ms: u64,
+    ) -> Self {
+        let http = reqwest::Client::builder()
+            .timeout(std::time::Duration::from_millis(timeout_ms))
+            .build()
+            .unwrap_or_default();
         Self {
             api_base_url,
             access_token,
             phone_number_id,
+            business_account_id: None,
+            app_secret: None,
+            verify_token: None,
+            http,
+            sessions: DashMap::new(),
+            templates: DashMap::new(),
+            message_status: DashMap::new(),
+            attribution: DashMap::new(),
+            recipients: DashMap::new(),
+            journeys: None,
+            event_sink: campaign_core::event_bus::noop_sink(),
+            rendering: None,
         }
     }
 
+    /// WhatsApp Business Account ID, needed for template sync.
+    pub fn with_business_account_id(mut self, waba_id: impl Into<String>) -> Self {
+        self.business_account_id = Some(waba_id.into());
+        self
+    }
+
+    /// App secret used to verify `X-Hub-Signature-256` on webhooks.
+    pub fn with_app_secret(mut self, secret: impl Into<String>) -> Self {
+        self.app_secret = Some(secret.into());
+        self
+    }
+
+    /// Token echoed back during the webhook subscription handshake.
+    pub fn with_verify_token(mut self, token: impl Into<String>) -> Self {
+        self.verify_token = Some(token.into());
+        self
+    }
+
+    /// Route interactive replies into journeys as `whatsapp_reply` events.
+    pub fn with_journey_engine(mut self, journeys: JourneyEngine) -> Self {
+        self.journeys = Some(journeys);
+        self
+    }
+
+    /// Attach an event sink for emitting analytics events.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = sink;
+        self
+    }
+
+    /// Render free-form text through the shared rendering service.
+    pub fn with_rendering(mut self, rendering: Arc<RenderingService>) -> Self {
+        self.rendering = Some(rendering);
+        self
+    }
+
+    /// Send an approved template. Templates are the only messages allowed
+    /// outside the customer-service window. The synced review status wins
+    /// over the status carried on `template`.
     pub async fn send_template_message(
         &self,
         to: &str,
         template: &WhatsAppTemplate,
     ) -> anyhow::Result<String> {
+        let status = self
+            .templates
+            .get(&(template.name.clone(), template.language.clone()))
+            .map(|t| t.status.clone())
+            .unwrap_or_else(|| template.status.clone());
+        if status != TemplateStatus::Approved {
+            anyhow::bail!(
+                "template {} ({}) is not approved: {:?}",
+                template.name,
+                template.language,
+                status
+            );
+        }
+
+        let components: Vec<serde_json::Value> = template
+            .components
+            .iter()
+            .map(|c| {
+                let parameters: Vec<serde_json::Value> = c
+                    .parameters
+                    .iter()
+                    .map(|p| match &p.image_url {
+                        Some(link) => serde_json::json!({"type": "image", "image": {"link": link}}),
+                        None => serde_json::json!({"type": p.param_type, "text": p.text}),
+                    })
+                    .collect();
+                serde_json::json!({"type": c.component_type, "parameters": parameters})
+            })
+            .collect();
+
         tracing::info!(
             to = to,
             template = &template.name,
             phone_id = &self.phone_number_id,
-            base = &self.api_base_url,
-            token_len = self.access_token.len(),
             "Sending WhatsApp template message"
         );
-        Ok(Uuid::new_v4().to_string())
+        self.post_message(serde_json::json!({
+            "messaging_product": "whatsapp",
+            "to": normalize_phone(to),
+            "type": "template",
+            "template": {
+                "name": template.name,
+                "language": {"code": template.language},
+                "components": components,
+            },
+        }))
+        .await
+    }
+
+    /// Send an approved template for an activation. Status webhooks for the
+    /// message are attributed to the activation's campaign and creative.
+    pub async fn send_activation_template(
+        &self,
+        req: &ActivationRequest,
+        to: &str,
+        template: &WhatsAppTemplate,
+    ) -> anyhow::Result<String> {
+        let message_id = self.send_template_message(to, template).await?;
+        self.register_recipient(&req.user_id, to);
+        self.attribution
+            .insert(message_id.clone(), (req.attribution(), Instant::now()));
+        Ok(message_id)
     }
 
+    /// Send free-form text. Only allowed while the customer-service window
+    /// opened by the recipient's last message is still open.
     pub async fn send_text_message(&self, to: &str, body: &str) -> anyhow::Result<String> {
+        if !self.session_open(to) {
+            metrics::counter!("whatsapp.outside_session_window").increment(1);
+            anyhow::bail!(
+                "{} has no open 24h customer-service window; send an approved template instead",
+                to
+            );
+        }
+
         tracing::info!(
             to = to,
             body_len = body.len(),
             "Sending WhatsApp text message"
         );
-        Ok(Uuid::new_v4().to_string())
+        self.post_message(serde_json::json!({
+            "messaging_product": "whatsapp",
+            "to": normalize_phone(to),
+            "type": "text",
+            "text": {"body": body},
+        }))
+        .await
+    }
+
+    /// Render `template` for `user_id` and send it as free-form text.
+    /// WhatsApp renders as plain text, like SMS. Without a rendering service
+    /// the template is sent as-is.
+    pub async fn send_text_to_user(
+        &self,
+        user_id: &str,
+        to: &str,
+        template: &str,
+    ) -> anyhow::Result<String> {
+        let body = match &self.rendering {
+            Some(rendering) => {
+                rendering
+                    .render_text(user_id, ActivationChannel::Sms, template)
+                    .await?
+            }
+            None => template.to_string(),
+        };
+        let message_id = self.send_text_message(to, &body).await?;
+        self.register_recipient(user_id, to);
+        Ok(message_id)
+    }
+
+    /// Link `phone` to a platform user so interactive replies from it are
+    /// routed into that user's journeys.
+    pub fn register_recipient(&self, user_id: &str, phone: &str) {
+        self.recipients
+            .insert(normalize_phone(phone), user_id.to_string());
+    }
+
+    /// Platform user id linked to `phone`, if any.
+    pub fn recipient_user_id(&self, phone: &str) -> Option<String> {
+        self.recipients
+            .get(&normalize_phone(phone))
+            .map(|u| u.clone())
+    }
+
+    async fn post_message(&self, payload: serde_json::Value) -> anyhow::Result<String> {
+        let url = format!(
+            "{}/{}/messages",
+            self.api_base_url.trim_end_matches('/'),
+            self.phone_number_id
+        );
+        let response = self
+            .http
+            .post(&url)
+            .bearer_auth(&self.access_token)
+            .json(&payload)
+            .send()
+            .await?;
+        if !response.status().is_success() {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            metrics::counter!("whatsapp.send_failed").increment(1);
+            anyhow::bail!("WhatsApp Cloud API returned {}: {}", status, body);
+        }
+
+        let sent: SendResponse = response.json().await?;
+        let message_id = sent
+            .messages
+            .into_iter()
+            .next()
+            .map(|m| m.id)
+            .ok_or_else(|| anyhow::anyhow!("WhatsApp response contained no message id"))?;
+        self.message_status
+            .insert(message_id.clone(), (MessageStatus::Queued, Instant::now()));
+        metrics::counter!("whatsapp.messages_sent").increment(1);
+        Ok(message_id)
+    }
+
+    /// Whether `phone` messaged us within the last 24 hours.
+    pub fn session_open(&self, phone: &str) -> bool {
+        self.session_expires_at(phone)
+            .is_some_and(|expires| expires > Utc::now())
+    }
+
+    /// When the customer-service window for `phone` closes, if one was
+    /// ever opened.
+    pub fn session_expires_at(&self, phone: &str) -> Option<DateTime<Utc>> {
+        self.sessions
+            .get(&normalize_phone(phone))
+            .map(|last| *last + Duration::hours(SESSION_WINDOW_HOURS))
+    }
+
+    /// Latest known status of an outbound message.
+    pub fn message_status(&self, message_id: &str) -> Option<MessageStatus> {
+        self.message_status.get(message_id).map(|s| s.0.clone())
+    }
+
+    /// Known template by name and language.
+    pub fn get_template(&self, name: &str, language: &str) -> Option<WhatsAppTemplate> {
+        self.templates
+            .get(&(name.to_string(), language.to_string()))
+            .map(|t| t.clone())
+    }
+
+    /// Register a template definition (components included) so sends can
+    /// be checked against its synced review status.
+    pub fn register_template(&self, template: WhatsAppTemplate) {
+        self.templates
+            .insert((template.name.clone(), template.language.clone()), template);
+    }
+
+    /// Answer the webhook subscription handshake: returns the challenge to
+    /// echo when the mode and verify token match. The token is compared in
+    /// constant time, like the webhook signature.
+    pub fn verify_subscription(&self, mode: &str, token: &str, challenge: &str) -> Option<String> {
+        let expected = self.verify_token.as_deref()?;
+        let token_ok: bool = token.as_bytes().ct_eq(expected.as_bytes()).into();
+        (mode == "subscribe" && token_ok).then(|| challenge.to_string())
+    }
+
+    /// Verify `X-Hub-Signature-256` (`sha256=` + hex HMAC-SHA256 of the raw
+    /// body, keyed by the app secret). Fails closed without a secret.
+    pub fn verify_signature(&self, payload: &[u8], signature: &str) -> bool {
+        let Some(secret) = &self.app_secret else {
+            return false;
+        };
+        let Some(expected) = signature
+            .strip_prefix("sha256=")
+            .and_then(|h| hex::decode(h).ok())
+        else {
+            return false;
+        };
+        let Ok(mut mac) = Hmac::<Sha256>::new_from_slice(secret.as_bytes()) else {
+            return false;
+        };
+        mac.update(payload);
+        mac.verify_slice(&expected).is_ok()
+    }
+
+    /// Process a webhook notification: opens customer-service windows,
+    /// records delivery statuses, applies template review updates and
+    /// routes interactive replies into journeys.
+    pub fn handle_webhook(&self, webhook: &WhatsAppWebhook) -> Vec<WhatsAppInboundEvent> {
+        let mut events = Vec::new();
+        for change in webhook.entry.iter().flat_map(|e| &e.changes) {
+            match change.field.as_str() {
+                "messages" => {
+                    let value: MessagesValue =
+                        serde_json::from_value(change.value.clone()).unwrap_or_default();
+                    for message in value.messages {
+                        events.push(self.handle_inbound_message(message));
+                    }
+                    for status in value.statuses {
+                        if let Some(event) = self.handle_status(status) {
+                            events.push(event);
+                        }
+                    }
+                }
+                "message_template_status_update" => {
+                    match serde_json::from_value::<TemplateStatusValue>(change.value.clone()) {
+                        Ok(update) => {
+                            let status = TemplateStatus::from_api(&update.event);
+                            self.set_template_status(
+                                &update.message_template_name,
+                                &update.message_template_language,
+                                status.clone(),
+                                None,
+                            );
+                            events.push(WhatsAppInboundEvent::TemplateStatus {
+                                name: update.message_template_name,
+                                language: update.message_template_language,
+                                status,
+                            });
+                        }
+                        Err(e) => tracing::warn!(error = %e, "Malformed template status update"),
+                    }
+                }
+                other => tracing::debug!(field = other, "Ignoring WhatsApp webhook field"),
+            }
+        }
+        events
+    }
+
+    fn handle_inbound_message(&self, message: InboundMessage) -> WhatsAppInboundEvent {
+        let received_at = message
+            .timestamp
+            .parse::<i64>()
+            .ok()
+            .and_then(|secs| Utc.timestamp_opt(secs, 0).single())
+            .unwrap_or_else(Utc::now);
+        let from = normalize_phone(&message.from);
+        self.sessions
+            .entry(from.clone())
+            .and_modify(|last| *last = (*last).max(received_at))
+            .or_insert(received_at);
+        metrics::counter!("whatsapp.inbound", "type" => message.message_type.clone()).increment(1);
+
+        let reply = match (&message.interactive, &message.button) {
+            (Some(interactive), _) => interactive
+                .button_reply
+                .as_ref()
+                .or(interactive.list_reply.as_ref())
+                .map(|r| (r.id.clone(), r.title.clone())),
+            (None, Some(button)) => Some((button.payload.clone(), button.text.clone())),
+            (None, None) => None,
+        };
+        let context_message_id = message.context.map(|c| c.id);
+
+        match reply {
+            Some((reply_id, title)) => {
+                let user_id = self.recipients.get(&from).map(|u| u.clone());
+                let journey_instances = match (&self.journeys, user_id) {
+                    (Some(journeys), Some(user_id)) => journeys.handle_event(
+                        &user_id,
+                        REPLY_EVENT_TYPE,
+                        &serde_json::json!({
+                            "channel": "whatsapp",
+                            "reply_id": reply_id,
+                            "title": title,
+                            "context_message_id": context_message_id,
+                        }),
+                    ),
+                    (Some(_), None) => {
+                        metrics::counter!("whatsapp.replies_unattributed").increment(1);
+                        Vec::new()
+                    }
+                    (None, _) => Vec::new(),
+                };
+                WhatsAppInboundEvent::InteractiveReply {
+                    from,
+                    message_id: message.id,
+                    reply_id,
+                    title,
+                    context_message_id,
+                    journey_instances,
+                }
+            }
+            None => WhatsAppInboundEvent::Message {
+                from,
+                message_id: message.id,
+                body: message.text.map(|t| t.body),
+            },
+        }
+    }
+
+    fn handle_status(&self, update: StatusUpdate) -> Option<WhatsAppInboundEvent> {
+        let status = match update.status.as_str() {
+            "sent" => MessageStatus::Sent,
+            "delivered" => MessageStatus::Delivered,
+            "read" => MessageStatus::Read,
+            "failed" => MessageStatus::Failed,
+            other => {
+                tracing::warn!(status = other, "Unknown WhatsApp message status");
+                return None;
+            }
+        };
+        self.message_status
+            .entry(update.id.clone())
+            .and_modify(|(s, _)| *s = status.clone())
+            .or_insert_with(|| (status.clone(), Instant::now()));
+
+        let event_type = match status {
+            MessageStatus::Delivered => Some(EventType::ActivationDelivered),
+            MessageStatus::Failed => Some(EventType::ActivationFailed),
+            _ => None,
+        };
+        let attribution = match status {
+            MessageStatus::Read | MessageStatus::Failed => {
+                self.attribution.remove(&update.id).map(|(_, (a, _))| a)
+            }
+            _ => self.attribution.get(&update.id).map(|a| a.0.clone()),
+        }
+        .unwrap_or_default();
+        if let Some(et) = event_type {
+            self.event_sink.emit(
+                make_event(et, update.id.clone(), update.recipient_id, None)
+                    .with_attribution(&attribution)
+                    .with_channel("whatsapp"),
+            );
+        }
+
+        Some(WhatsAppInboundEvent::Status {
+            message_id: update.id,
+            status,
+        })
+    }
+
+    /// Drop message statuses and attribution older than `max_age`, and
+    /// sessions whose customer-service window has closed. Returns the
+    /// number of entries removed.
+    pub fn prune_state(&self, max_age: std::time::Duration) -> usize {
+        let before = self.message_status.len() + self.attribution.len() + self.sessions.len();
+        self.message_status
+            .retain(|_, (_, sent_at)| sent_at.elapsed() < max_age);
+        self.attribution
+            .retain(|_, (_, sent_at)| sent_at.elapsed() < max_age);
+        let window_start = Utc::now() - Duration::hours(SESSION_WINDOW_HOURS);
+        self.sessions.retain(|_, last| *last > window_start);
+        let after = self.message_status.len() + self.attribution.len() + self.sessions.len();
+        let pruned = before.saturating_sub(after);
+        if pruned > 0 {
+            metrics::counter!("whatsapp.state_pruned").increment(pruned as u64);
+        }
+        pruned
+    }
+
+    /// Prune state older than [`MESSAGE_STATE_TTL`] every `tick`.
+    pub fn spawn_state_sweep(self: Arc<Self>, tick: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                self.prune_state(MESSAGE_STATE_TTL);
+            }
+        })
+    }
+
+    /// Pull every template of the business account and update the review
+    /// status of known templates (unknown ones are added without
+    /// components). Returns the number of templates synced.
+    pub async fn sync_templates(&self) -> anyhow::Result<usize> {
+        let waba_id = self
+            .business_account_id
+            .as_deref()
+            .ok_or_else(|| anyhow::anyhow!("WhatsApp business account ID is not configured"))?;
+        let mut next = Some(format!(
+            "{}/{}/message_templates?fields=name,language,status,category&limit=100",
+            self.api_base_url.trim_end_matches('/'),
+            waba_id
+        ));
+
+        let mut synced = 0;
+        while let Some(url) = next {
+            let response = self
+                .http
+                .get(&url)
+                .bearer_auth(&self.access_token)
+                .send()
+                .await?;
+            if !response.status().is_success() {
+                anyhow::bail!("WhatsApp template sync returned {}", response.status());
+            }
+            let page: TemplateListResponse = response.json().await?;
+            for record in page.data {
+                let category = match record.category.as_deref() {
+                    Some("UTILITY") => TemplateCategory::Utility,
+                    Some("AUTHENTICATION") => TemplateCategory::Authentication,
+                    _ => TemplateCategory::Marketing,
+                };
+                self.set_template_status(
+                    &record.name,
+                    &record.language,
+                    TemplateStatus::from_api(&record.status),
+                    Some(category),
+                );
+                synced += 1;
+            }
+            next = page.paging.and_then(|p| p.next);
+        }
+
+        tracing::info!(synced, "WhatsApp templates synced");
+        Ok(synced)
+    }
+
+    /// Sync templates every `tick`, so sends are checked against review
+    /// statuses even when a status webhook was missed.
+    pub fn spawn_template_sync(self: Arc<Self>, tick: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.sync_templates().await {
+                    metrics::counter!("whatsapp.template_sync_failed").increment(1);
+                    tracing::warn!(error = %e, "WhatsApp template sync failed");
+                }
+            }
+        })
+    }
+
+    fn set_template_status(
+        &self,
+        name: &str,
+        language: &str,
+        status: TemplateStatus,
+        category: Option<TemplateCategory>,
+    ) {
+        self.templates
+            .entry((name.to_string(), language.to_string()))
+            .and_modify(|t| t.status = status.clone())
+            .or_insert_with(|| WhatsAppTemplate {
+                name: name.to_string(),
+                language: language.to_string(),
+                category: category.unwrap_or(TemplateCategory::Marketing),
+                components: Vec::new(),
+                status,
+            });
+    }
+}
+
+/// WhatsApp IDs are bare digits; accept E.164 with `+` or formatting.
+fn normalize_phone(phone: &str) -> String {
+    phone.chars().filter(char::is_ascii_digit).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::RawQuery;
+    use axum::routing::{get, post};
+    use axum::Router;
+    use campaign_core::event_bus::CaptureSink;
+    use campaign_journey::types::{
+        ExitConfig, Journey, JourneyStatus, JourneyStep, JourneyTrigger, StepType,
+    };
+
+    async fn mock_cloud_api() -> String {
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        let next_page = format!("{}/WABA/message_templates?after=page2", base);
+        let app = Router::new()
+            .route(
+                "/:phone_id/messages",
+                post(|| async {
+                    axum::Json(serde_json::json!({
+                        "messaging_product": "whatsapp",
+                        "messages": [{"id": "wamid.OUT1"}],
+                    }))
+                }),
+            )
+            .route(
+                "/:waba_id/message_templates",
+                get(move |RawQuery(query): RawQuery| async move {
+                    if query.unwrap_or_default().contains("after=page2") {
+                        axum::Json(serde_json::json!({
+                            "data": [{"name": "order_update", "language": "en_US",
+                                      "status": "REJECTED", "category": "UTILITY"}],
+                        }))
+                    } else {
+                        axum::Json(serde_json::json!({
+                            "data": [{"name": "spring_sale", "language": "en_US",
+                                      "status": "APPROVED", "category": "MARKETING"}],
+                            "paging": {"next": next_page},
+                        }))
+                    }
+                }),
+            );
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        base
+    }
+
+    fn text_webhook(from: &str, timestamp: i64) -> WhatsAppWebhook {
+        serde_json::from_value(serde_json::json!({
+            "object": "whatsapp_business_account",
+            "entry": [{"id": "WABA", "changes": [{"field": "messages", "value": {
+                "messaging_product": "whatsapp",
+                "messages": [{"from": from, "id": "wamid.IN1",
+                              "timestamp": timestamp.to_string(),
+                              "type": "text", "text": {"body": "hi"}}],
+            }}]}],
+        }))
+        .unwrap()
+    }
+
+    fn activation_request() -> ActivationRequest {
+        ActivationRequest {
+            activation_id: "act-1".to_string(),
+            decision_id: None,
+            user_id: "user-1".to_string(),
+            channel: ActivationChannel::Sms,
+            offer_id: "offer-1".to_string(),
+            content: campaign_core::channels::ActivationContent {
+                headline: String::new(),
+                body: String::new(),
+                image_url: None,
+                cta_url: None,
+                cta_text: None,
+                deep_link: None,
+                audience_segment_id: None,
+                extra: None,
+                personalization: None,
+            },
+            priority: 1,
+            scheduled_at: None,
+            created_at: Utc::now(),
+            trigger_event_id: None,
+            trigger_source: None,
+            campaign_id: Some("camp-1".to_string()),
+            experiment_variant_id: None,
+            creative_id: Some("creative-1".to_string()),
+        }
+    }
+
+    fn template(name: &str, status: TemplateStatus) -> WhatsAppTemplate {
+        WhatsAppTemplate {
+            name: name.to_string(),
+            language: "en_US".to_string(),
+            category: TemplateCategory::Marketing,
+            components: vec![],
+            status,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_text_requires_open_session_window() {
+        let base = mock_cloud_api().await;
+        let provider = WhatsAppProvider::new(base, "token".into(), "PHONE_ID".into());
+        assert!(provider
+            .send_text_message("+15550001111", "hi")
+            .await
+            .is_err());
+
+        // A customer message from two days ago does not open the window.
+        let stale = (Utc::now() - Duration::hours(48)).timestamp();
+        provider.handle_webhook(&text_webhook("15550001111", stale));
+        assert!(!provider.session_open("+15550001111"));
+
+        let events = provider.handle_webhook(&text_webhook("15550001111", Utc::now().timestamp()));
+        assert!(matches!(
+            &events[0],
+            WhatsAppInboundEvent::Message { body: Some(b), .. } if b == "hi"
+        ));
+        assert!(provider.session_open("+1 (555) 000-1111"));
+        let id = provider
+            .send_text_message("+15550001111", "thanks!")
+            .await
+            .unwrap();
+        assert_eq!(id, "wamid.OUT1");
+
+        // Templates are allowed outside the window, but only once approved.
+        assert!(provider
+            .send_template_message("+15559998888", &template("promo", TemplateStatus::Pending))
+            .await
+            .is_err());
+        assert!(provider
+            .send_template_message("+15559998888", &template("promo", TemplateStatus::Approved))
+            .await
+            .is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_template_sync_and_status_webhook() {
+        let base = mock_cloud_api().await;
+        let provider = WhatsAppProvider::new(base, "token".into(), "PHONE_ID".into())
+            .with_business_account_id("WABA");
+        provider.register_template(template("spring_sale", TemplateStatus::Pending));
+
+        assert_eq!(provider.sync_templates().await.unwrap(), 2);
+        assert_eq!(
+            provider
+                .get_template("spring_sale", "en_US")
+                .unwrap()
+                .status,
+            TemplateStatus::Approved
+        );
+        let order_update = provider.get_template("order_update", "en_US").unwrap();
+        assert_eq!(order_update.status, TemplateStatus::Rejected);
+        assert_eq!(order_update.category, TemplateCategory::Utility);
+
+        let webhook: WhatsAppWebhook = serde_json::from_value(serde_json::json!({
+            "entry": [{"changes": [{"field": "message_template_status_update", "value": {
+                "event": "PAUSED",
+                "message_template_id": 123,
+                "message_template_name": "spring_sale",
+                "message_template_language": "en_US",
+            }}]}],
+        }))
+        .unwrap();
+        provider.handle_webhook(&webhook);
+        assert_eq!(
+            provider
+                .get_template("spring_sale", "en_US")
+                .unwrap()
+                .status,
+            TemplateStatus::Paused
+        );
+    }
+
+    #[tokio::test]
+    async fn test_activation_status_webhooks_keep_attribution() {
+        let base = mock_cloud_api().await;
+        let sink = Arc::new(CaptureSink::new());
+        let provider = WhatsAppProvider::new(base, "token".into(), "PHONE_ID".into())
+            .with_event_sink(sink.clone());
+        let req = activation_request();
+        let id = provider
+            .send_activation_template(
+                &req,
+                "+15559998888",
+                &template("promo", TemplateStatus::Approved),
+            )
+            .await
+            .unwrap();
+
+        let webhook: WhatsAppWebhook = serde_json::from_value(serde_json::json!({
+            "entry": [{"changes": [{"field": "messages", "value": {
+                "statuses": [{"id": id, "status": "delivered",
+                              "timestamp": "1700000000", "recipient_id": "15559998888"}],
+            }}]}],
+        }))
+        .unwrap();
+        provider.handle_webhook(&webhook);
+
+        let events = sink.events();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].event_type, EventType::ActivationDelivered);
+        assert_eq!(events[0].campaign_id.as_deref(), Some("camp-1"));
+        assert_eq!(events[0].creative_id.as_deref(), Some("creative-1"));
+        assert_eq!(events[0].channel.as_deref(), Some("whatsapp"));
+    }
+
+    #[tokio::test]
+    async fn test_prune_state_drops_expired_sessions_statuses_and_attribution() {
+        let base = mock_cloud_api().await;
+        let sink = Arc::new(CaptureSink::new());
+        let provider = WhatsAppProvider::new(base, "token".into(), "PHONE_ID".into())
+            .with_event_sink(sink.clone());
+        let req = activation_request();
+        let id = provider
+            .send_activation_template(
+                &req,
+                "+15559998888",
+                &template("promo", TemplateStatus::Approved),
+            )
+            .await
+            .unwrap();
+        let stale = (Utc::now() - Duration::hours(48)).timestamp();
+        provider.handle_webhook(&text_webhook("15550001111", stale));
+        provider.handle_webhook(&text_webhook("15550002222", Utc::now().timestamp()));
+
+        // Only the closed session is past its TTL.
+        assert_eq!(provider.prune_state(MESSAGE_STATE_TTL), 1);
+        assert!(provider.session_expires_at("+15550001111").is_none());
+        assert!(provider.session_open("+15550002222"));
+        assert_eq!(provider.message_status(&id), Some(MessageStatus::Queued));
+
+        assert_eq!(provider.prune_state(std::time::Duration::ZERO), 2);
+        assert!(provider.message_status(&id).is_none());
+        let webhook: WhatsAppWebhook = serde_json::from_value(serde_json::json!({
+            "entry": [{"changes": [{"field": "messages", "value": {
+                "statuses": [{"id": id, "status": "delivered",
+                              "timestamp": "1700000000", "recipient_id": "15559998888"}],
+            }}]}],
+        }))
+        .unwrap();
+        provider.handle_webhook(&webhook);
+        assert_eq!(sink.events()[0].campaign_id, None);
+    }
+
+    #[test]
+    fn test_interactive_reply_routes_into_journey_and_statuses() {
+        let sink = Arc::new(CaptureSink::new());
+        let journeys = JourneyEngine::new().with_event_sink(sink.clone());
+        let now = Utc::now();
+        journeys
+            .create_journey(Journey {
+                id: Uuid::new_v4(),
+                name: "Offer accepted".to_string(),
+                description: String::new(),
+                status: JourneyStatus::Active,
+                trigger: JourneyTrigger::EventBased {
+                    event_type: REPLY_EVENT_TYPE.to_string(),
+                    filters: serde_json::json!({"reply_id": "ACCEPT"}),
+                },
+                steps: vec![JourneyStep {
+                    id: Uuid::new_v4(),
+                    step_type: StepType::Exit(ExitConfig {
+                        reason: "done".to_string(),
+                    }),
+                    config: serde_json::json!({}),
+                    position: 0,
+                    next_steps: vec![],
+                }],
+                created_at: now,
+                updated_at: now,
+                version: 1,
+            })
+            .unwrap();
+        let provider = WhatsAppProvider::new(
+            DEFAULT_API_BASE_URL.to_string(),
+            "token".into(),
+            "PHONE_ID".into(),
+        )
+        .with_journey_engine(journeys);
+        provider.register_recipient("user-42", "+1 (555) 000-1111");
+
+        let webhook: WhatsAppWebhook = serde_json::from_value(serde_json::json!({
+            "entry": [{"changes": [{"field": "messages", "value": {
+                "messages": [{
+                    "from": "15550001111", "id": "wamid.IN2",
+                    "timestamp": Utc::now().timestamp().to_string(),
+                    "type": "interactive",
+                    "context": {"id": "wamid.OUT1"},
+                    "interactive": {"type": "button_reply",
+                                    "button_reply": {"id": "ACCEPT", "title": "Yes!"}},
+                }, {
+                    "from": "15559999999", "id": "wamid.IN3",
+                    "timestamp": Utc::now().timestamp().to_string(),
+                    "type": "interactive",
+                    "interactive": {"type": "button_reply",
+                                    "button_reply": {"id": "ACCEPT", "title": "Yes!"}},
+                }],
+                "statuses": [{"id": "wamid.OUT1", "status": "read",
+                              "timestamp": "1700000000", "recipient_id": "15550001111"}],
+            }}]}],
+        }))
+        .unwrap();
+
+        let events = provider.handle_webhook(&webhook);
+        assert_eq!(events.len(), 3);
+        match &events[0] {
+            WhatsAppInboundEvent::InteractiveReply {
+                reply_id,
+                context_message_id,
+                journey_instances,
+                ..
+            } => {
+                assert_eq!(reply_id, "ACCEPT");
+                assert_eq!(context_message_id.as_deref(), Some("wamid.OUT1"));
+                assert_eq!(journey_instances.len(), 1);
+            }
+            other => panic!("unexpected event {:?}", other),
+        }
+        // Replies enter journeys as the platform user, never as a phone
+        // number; numbers we never messaged are not attributed.
+        match &events[1] {
+            WhatsAppInboundEvent::InteractiveReply {
+                journey_instances, ..
+            } => assert!(journey_instances.is_empty()),
+            other => panic!("unexpected event {:?}", other),
+        }
+        let entered: Vec<_> = sink
+            .events()
+            .into_iter()
+            .filter(|e| e.event_type == EventType::JourneyEntered)
+            .map(|e| e.user_id)
+            .collect();
+        assert_eq!(entered, vec![Some("user-42".to_string())]);
+        assert_eq!(
+            provider.message_status("wamid.OUT1"),
+            Some(MessageStatus::Read)
+        );
+    }
+
+    #[test]
+    fn test_webhook_signature_and_handshake() {
+        let provider = WhatsAppProvider::new(
+            DEFAULT_API_BASE_URL.to_string(),
+            "token".into(),
+            "PHONE_ID".into(),
+        );
+        assert!(!provider.verify_signature(b"{}", "sha256=00"));
+        assert!(provider
+            .verify_subscription("subscribe", "x", "c")
+            .is_none());
+
+        let provider = provider.with_app_secret("secret").with_verify_token("vt");
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(b"{\"entry\":[]}");
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert!(provider.verify_signature(b"{\"entry\":[]}", &signature));
+        assert!(!provider.verify_signature(b"{\"entry\":[1]}", &signature));
+        assert_eq!(
+            provider.verify_subscription("subscribe", "vt", "12345"),
+            Some("12345".to_string())
+        );
+        assert!(provider
+            .verify_subscription("subscribe", "vx", "12345")
+            .is_none());
+        assert!(provider
+            .verify_subscription("subscribe", "vt2", "12345")
+            .is_none());
+        assert!(provider
+            .verify_subscription("unsubscribe", "vt", "12345")
+            .is_none());
+    }
+
+    #[tokio::test]
+    async fn test_from_config_loads_secrets_and_times_out() {
+        // Accepts connections but never answers.
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let mut held = Vec::new();
+            while let Ok((socket, _)) = listener.accept().await {
+                held.push(socket);
+            }
+        });
+
+        let provider = WhatsAppProvider::from_config(&WhatsAppConfig {
+            api_base_url: base,
+            access_token: "token".into(),
+            phone_number_id: "PHONE_ID".into(),
+            business_account_id: Some("WABA".into()),
+            app_secret: Some("secret".into()),
+            verify_token: Some("vt".into()),
+            request_timeout_ms: 200,
+            template_sync_interval_secs: 3600,
+        });
+        let mut mac = Hmac::<Sha256>::new_from_slice(b"secret").unwrap();
+        mac.update(b"{}");
+        let signature = format!("sha256={}", hex::encode(mac.finalize().into_bytes()));
+        assert!(provider.verify_signature(b"{}", &signature));
+        assert_eq!(
+            provider.verify_subscription("subscribe", "vt", "c"),
+            Some("c".to_string())
+        );
+
+        let started = std::time::Instant::now();
+        assert!(provider.sync_templates().await.is_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(5));
     }
 }
@@ -6,6 +6,7 @@
 //! Activation destinations: push notifications, SMS, email, web personalization,
 //! paid media (Facebook, The Trade Desk, etc.), in-app messages.
 
+use crate::types::Attribution;
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
@@ -178,6 +179,19 @@ pub struct ActivationRequest {
     pub campaign_id: Option<String>,
     /// Experiment variant if activation is part of an A/B test (FR-ACT-003).
     pub experiment_variant_id: Option<String>,
+    /// Creative rendered for this activation, for the creative rollups.
+    #[serde(default)]
+    pub creative_id: Option<String>,
+}
+
+impl ActivationRequest {
+    /// Campaign and creative that this activation's events count towards.
+    pub fn attribution(&self) -> Attribution {
+        Attribution {
+            campaign_id: self.campaign_id.clone(),
+            creative_id: self.creative_id.clone(),
+        }
+    }
 }
 
 /// Content payload for an activation message.
@@ -194,6 +208,11 @@ pub struct ActivationContent {
     pub audience_segment_id: Option<String>,
     /// Extra platform-specific payload.
     pub extra: Option<serde_json::Value>,
+    /// Rendering inputs beyond message variables: connected content,
+    /// catalog lookups, recommendation slots and builder blocks with their
+    /// rules, in the shape of the channels crate's `RenderPersonalization`.
+    #[serde(default)]
+    pub personalization: Option<serde_json::Value>,
 }
 
 /// Result of an activation attempt.
@@ -236,9 +255,12 @@ pub enum EmailEventType {
     Bounce,
     Open,
     Click,
+    #[serde(alias = "spamreport")]
     SpamReport,
     Unsubscribe,
+    #[serde(alias = "group_unsubscribe")]
     GroupUnsubscribe,
+    #[serde(alias = "group_resubscribe")]
     GroupResubscribe,
 }
 
@@ -252,9 +274,29 @@ pub struct EmailWebhookEvent {
     pub url: Option<String>,
     pub user_agent: Option<String>,
     pub ip: Option<String>,
+    /// SendGrid posts Unix seconds; RFC 3339 strings are accepted too.
+    #[serde(deserialize_with = "deserialize_webhook_timestamp")]
     pub timestamp: DateTime<Utc>,
 }
 
+fn deserialize_webhook_timestamp<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    #[derive(Deserialize)]
+    #[serde(untagged)]
+    enum Raw {
+        Unix(i64),
+        Rfc3339(DateTime<Utc>),
+    }
+
+    match Raw::deserialize(deserializer)? {
+        Raw::Unix(secs) => DateTime::from_timestamp(secs, 0)
+            .ok_or_else(|| serde::de::Error::custom("timestamp out of range")),
+        Raw::Rfc3339(ts) => Ok(ts),
+    }
+}
+
 /// Aggregated email analytics for a campaign or activation.
 #[derive(Debug, Clone, Serialize, Deserialize, Default, ToSchema)]
 pub struct EmailAnalytics {
@@ -273,8 +315,10 @@ pub struct EmailAnalytics {
     pub bounce_rate: f64,
 }
 
-/// SendGrid email provider configuration.
+/// SendGrid email provider configuration. Unset fields take their
+/// defaults, so a deployment can override just the key it needs.
 #[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
 pub struct SendGridConfig {
     pub api_key: String,
     pub from_email: String,
@@ -284,6 +328,37 @@ pub struct SendGridConfig {
     pub open_tracking: bool,
     pub click_tracking: bool,
     pub unsubscribe_group_id: Option<u64>,
+    /// Base URL of the SendGrid API (overridable for sandboxes and tests).
+    #[serde(default = "default_sendgrid_api_base_url")]
+    pub api_base_url: String,
+    /// Base64-encoded DER public key from the Signed Event Webhook settings.
+    /// Inbound webhooks are rejected when this is unset.
+    #[serde(default)]
+    pub webhook_verification_key: Option<String>,
+    /// Categories attached to every message for SendGrid-side reporting.
+    #[serde(default)]
+    pub categories: Vec<String>,
+    /// Retries after the first attempt on 429 and 5xx responses.
+    #[serde(default = "default_sendgrid_max_retries")]
+    pub max_retries: u32,
+    /// Initial retry backoff, doubled on every subsequent attempt.
+    #[serde(default = "default_sendgrid_retry_backoff_ms")]
+    pub retry_backoff_ms: u64,
+    #[serde(default = "default_sendgrid_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+}
+
+fn default_sendgrid_api_base_url() -> String {
+    "https://api.sendgrid.com".to_string()
+}
+fn default_sendgrid_max_retries() -> u32 {
+    3
+}
+fn default_sendgrid_retry_backoff_ms() -> u64 {
+    250
+}
+fn default_sendgrid_request_timeout_ms() -> u64 {
+    10_000
 }
 
 impl Default for SendGridConfig {
@@ -292,11 +367,68 @@ impl Default for SendGridConfig {
             api_key: String::new(),
             from_email: "offers@campaignexpress.io".to_string(),
             from_name: "Campaign Express".to_string(),
-            webhook_url: "https://api.campaignexpress.io/v1/webhooks/sendgrid".to_string(),
+            webhook_url: "https://api.campaignexpress.io/v1/channels/email/webhook".to_string(),
             tracking_enabled: true,
             open_tracking: true,
             click_tracking: true,
             unsubscribe_group_id: None,
+            api_base_url: default_sendgrid_api_base_url(),
+            webhook_verification_key: None,
+            categories: Vec::new(),
+            max_retries: default_sendgrid_max_retries(),
+            retry_backoff_ms: default_sendgrid_retry_backoff_ms(),
+            request_timeout_ms: default_sendgrid_request_timeout_ms(),
+        }
+    }
+}
+
+/// Twilio SMS provider configuration. Unset fields take their defaults;
+/// inbound and status webhooks are rejected until `auth_token` and their
+/// public URLs are set.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TwilioConfig {
+    pub account_sid: String,
+    pub auth_token: String,
+    pub from_number: String,
+    pub messaging_service_sid: Option<String>,
+    pub status_callback_url: Option<String>,
+    /// Public URL of the inbound-message webhook, exactly as configured in
+    /// Twilio. Needed to validate `X-Twilio-Signature` on inbound messages.
+    #[serde(default)]
+    pub inbound_webhook_url: Option<String>,
+    #[serde(default = "default_twilio_api_base_url")]
+    pub api_base_url: String,
+    /// Price of one outbound segment, used for pre-send cost estimates.
+    #[serde(default = "default_price_per_segment")]
+    pub price_per_segment: f64,
+    /// Reply sent to HELP/INFO keywords.
+    #[serde(default = "default_help_reply")]
+    pub help_reply: String,
+}
+
+fn default_twilio_api_base_url() -> String {
+    "https://api.twilio.com".to_string()
+}
+fn default_price_per_segment() -> f64 {
+    0.0079
+}
+fn default_help_reply() -> String {
+    "Campaign Express alerts. Msg&data rates may apply. Reply STOP to unsubscribe.".to_string()
+}
+
+impl Default for TwilioConfig {
+    fn default() -> Self {
+        Self {
+            account_sid: String::new(),
+            auth_token: String::new(),
+            from_number: String::new(),
+            messaging_service_sid: None,
+            status_callback_url: None,
+            inbound_webhook_url: None,
+            api_base_url: default_twilio_api_base_url(),
+            price_per_segment: default_price_per_segment(),
+            help_reply: default_help_reply(),
         }
     }
 }
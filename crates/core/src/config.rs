@@ -1,3 +1,4 @@
+use crate::channels::{SendGridConfig, TwilioConfig};
 use serde::Deserialize;
 
 /// Root application configuration. Loaded from environment variables
@@ -30,6 +31,18 @@ pub struct AppConfig {
     pub dco: DcoConfig,
     #[serde(default)]
     pub cdp: CdpGlobalConfig,
+    #[serde(default)]
+    pub sendgrid: SendGridConfig,
+    #[serde(default)]
+    pub twilio: TwilioConfig,
+    #[serde(default)]
+    pub push: PushConfig,
+    #[serde(default)]
+    pub whatsapp: WhatsAppConfig,
+    #[serde(default)]
+    pub recommendations: RecommendationsConfig,
+    #[serde(default)]
+    pub reporting: ReportingConfig,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -76,6 +89,27 @@ pub struct ClickHouseConfig {
     pub batch_size: usize,
     #[serde(default = "default_flush_interval_ms")]
     pub flush_interval_ms: u64,
+    /// Directory for the on-disk event spool used when ClickHouse falls
+    /// behind. Unset disables spooling, and events are dropped instead.
+    #[serde(default)]
+    pub spool_dir: Option<String>,
+    #[serde(default = "default_spool_max_bytes")]
+    pub spool_max_bytes: u64,
+    #[serde(default = "default_spool_segment_bytes")]
+    pub spool_segment_bytes: u64,
+    #[serde(default)]
+    pub spool_overflow: SpoolOverflowPolicy,
+}
+
+/// What the analytics spool does when it reaches `spool_max_bytes`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpoolOverflowPolicy {
+    /// Reject new events, keeping everything already spooled.
+    #[default]
+    DropNewest,
+    /// Delete the oldest segment to make room.
+    DropOldest,
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -150,6 +184,12 @@ fn default_batch_size() -> usize {
 fn default_flush_interval_ms() -> u64 {
     1000
 }
+fn default_spool_max_bytes() -> u64 {
+    1024 * 1024 * 1024
+}
+fn default_spool_segment_bytes() -> u64 {
+    16 * 1024 * 1024
+}
 fn default_model_path() -> String {
     "/models/colanet.onnx".to_string()
 }
@@ -208,6 +248,10 @@ impl Default for ClickHouseConfig {
             database: default_clickhouse_db(),
             batch_size: default_batch_size(),
             flush_interval_ms: default_flush_interval_ms(),
+            spool_dir: None,
+            spool_max_bytes: default_spool_max_bytes(),
+            spool_segment_bytes: default_spool_segment_bytes(),
+            spool_overflow: SpoolOverflowPolicy::default(),
         }
     }
 }
@@ -248,6 +292,12 @@ impl Default for AppConfig {
             journey: JourneyConfig::default(),
             dco: DcoConfig::default(),
             cdp: CdpGlobalConfig::default(),
+            sendgrid: SendGridConfig::default(),
+            twilio: TwilioConfig::default(),
+            push: PushConfig::default(),
+            whatsapp: WhatsAppConfig::default(),
+            recommendations: RecommendationsConfig::default(),
+            reporting: ReportingConfig::default(),
         }
     }
 }
@@ -427,6 +477,123 @@ impl Default for CdpGlobalConfig {
     }
 }
 
+// ─── Mobile Push Config ─────────────────────────────────────────────────
+
+/// Mobile push credentials. Each provider is enabled when its key file is
+/// set; with neither, push activations are stubbed as sent.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct PushConfig {
+    /// Path to the APNs `AuthKey_<key_id>.p8` file.
+    #[serde(default)]
+    pub apns_key_path: Option<String>,
+    #[serde(default)]
+    pub apns_team_id: String,
+    #[serde(default)]
+    pub apns_key_id: String,
+    /// App bundle ID, sent as `apns-topic`.
+    #[serde(default)]
+    pub apns_bundle_id: String,
+    /// Path to the Firebase service-account JSON key file.
+    #[serde(default)]
+    pub fcm_service_account_path: Option<String>,
+    /// SDK app keys accepted as `X-Api-Key` on device registration and push
+    /// token routes. With none, those routes reject every request.
+    #[serde(default)]
+    pub sdk_api_keys: Vec<String>,
+}
+
+// ─── WhatsApp Config ────────────────────────────────────────────────────
+
+/// WhatsApp Cloud API credentials. Inbound webhooks are rejected until
+/// `app_secret` is set.
+#[derive(Debug, Clone, Deserialize)]
+pub struct WhatsAppConfig {
+    #[serde(default = "default_whatsapp_api_base_url")]
+    pub api_base_url: String,
+    #[serde(default)]
+    pub access_token: String,
+    #[serde(default)]
+    pub phone_number_id: String,
+    /// WhatsApp Business Account ID, needed for template sync.
+    #[serde(default)]
+    pub business_account_id: Option<String>,
+    /// Meta app secret used to verify `X-Hub-Signature-256`.
+    #[serde(default)]
+    pub app_secret: Option<String>,
+    /// Token echoed back during the webhook subscription handshake.
+    #[serde(default)]
+    pub verify_token: Option<String>,
+    #[serde(default = "default_whatsapp_request_timeout_ms")]
+    pub request_timeout_ms: u64,
+    /// How often template review statuses are synced when
+    /// `business_account_id` is set.
+    #[serde(default = "default_whatsapp_template_sync_interval_secs")]
+    pub template_sync_interval_secs: u64,
+}
+
+fn default_whatsapp_api_base_url() -> String {
+    "https://graph.facebook.com/v19.0".to_string()
+}
+fn default_whatsapp_request_timeout_ms() -> u64 {
+    10_000
+}
+fn default_whatsapp_template_sync_interval_secs() -> u64 {
+    3600
+}
+
+impl Default for WhatsAppConfig {
+    fn default() -> Self {
+        Self {
+            api_base_url: default_whatsapp_api_base_url(),
+            access_token: String::new(),
+            phone_number_id: String::new(),
+            business_account_id: None,
+            app_secret: None,
+            verify_token: None,
+            request_timeout_ms: default_whatsapp_request_timeout_ms(),
+            template_sync_interval_secs: default_whatsapp_template_sync_interval_secs(),
+        }
+    }
+}
+
+// ─── Recommendations Config ─────────────────────────────────────────────
+
+/// Recommendation engine settings.
+#[derive(Debug, Clone, Deserialize)]
+pub struct RecommendationsConfig {
+    /// Item embedding file written by the offline BPR trainer; serves
+    /// `personalized_cf` when set.
+    #[serde(default)]
+    pub cf_model_path: Option<String>,
+    /// How often decayed co-occurrence pairs are pruned.
+    #[serde(default = "default_cooccurrence_prune_interval_secs")]
+    pub prune_interval_secs: u64,
+}
+
+fn default_cooccurrence_prune_interval_secs() -> u64 {
+    600
+}
+
+impl Default for RecommendationsConfig {
+    fn default() -> Self {
+        Self {
+            cf_model_path: None,
+            prune_interval_secs: default_cooccurrence_prune_interval_secs(),
+        }
+    }
+}
+
+// ─── Reporting Config ───────────────────────────────────────────────────
+
+/// Saved report settings.
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct ReportingConfig {
+    /// Hosts scheduled reports may be posted to (subdomains included).
+    /// Empty allows any public HTTPS host.
+    #[serde(default)]
+    pub webhook_allowed_hosts: Vec<String>,
+}
+
 impl AppConfig {
     /// Load configuration from environment variables and optional config file.
     pub fn load() -> Result<Self, config::ConfigError> {
@@ -13,6 +13,7 @@
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use utoipa::ToSchema;
+use uuid::Uuid;
 
 /// Supported DSP platforms.
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash, ToSchema)]
@@ -121,13 +122,25 @@ pub struct DspBid {
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DspSpendRecord {
     pub platform: DspPlatform,
+    /// Campaign the spend belongs to, when the DSP report breaks it down.
+    #[serde(default)]
+    pub campaign_id: Option<Uuid>,
     pub hour: DateTime<Utc>,
     pub impressions: u64,
-    pub spend_usd: f64,
+    /// Spend in `currency`.
+    #[serde(alias = "spend_usd")]
+    pub spend: f64,
+    /// ISO 4217 code of the DSP's billing currency.
+    #[serde(default = "default_currency")]
+    pub currency: String,
     pub wins: u64,
     pub avg_win_price: f64,
 }
 
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
 /// Aggregated DSP performance metrics for reporting.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DspPerformanceMetrics {
@@ -83,6 +83,9 @@ pub fn make_event(
         inference_latency_us: None,
         total_latency_us: None,
         timestamp: Utc::now(),
+        campaign_id: None,
+        channel: None,
+        creative_id: None,
     }
 }
 
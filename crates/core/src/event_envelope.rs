@@ -0,0 +1,425 @@
+//! Canonical event envelope — one versioned shape for every event the
+//! platform produces, with a typed payload per event family.
+//!
+//! Producers keep emitting [`AnalyticsEvent`]s through an
+//! [`EventSink`](crate::event_bus::EventSink); the adapter layer converts
+//! each one with [`EventEnvelope::from_analytics`], validates it against the
+//! registered envelope schema and hands it to [`EnvelopeSink`]s such as
+//! cross-channel measurement.
+
+use crate::types::{AnalyticsEvent, EventType};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Schema registry name of the envelope. Payload schemas are registered as
+/// `event_envelope.<family>`.
+pub const ENVELOPE_SCHEMA: &str = "event_envelope";
+
+/// Current envelope schema version. Bump when a field is added, removed or
+/// changes type, and register the new version alongside the old one.
+pub const ENVELOPE_VERSION: u32 = 1;
+
+/// Group of event types that share a payload shape.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventFamily {
+    /// Bid requests and responses, no-bids, timeouts and DSP bids.
+    Bidding,
+    /// Impressions, clicks and conversions on served offers.
+    Engagement,
+    /// Sends, deliveries and failures on owned channels.
+    Delivery,
+    Journey,
+    Experiment,
+    Loyalty,
+    /// Dynamic creative assembly and its engagement.
+    Creative,
+    Cdp,
+    /// Web SDK page views, clicks and sessions.
+    Sdk,
+    /// Omnichannel ingest (mobile app, POS, kiosk, ...).
+    Ingest,
+}
+
+impl EventFamily {
+    pub const ALL: [EventFamily; 10] = [
+        EventFamily::Bidding,
+        EventFamily::Engagement,
+        EventFamily::Delivery,
+        EventFamily::Journey,
+        EventFamily::Experiment,
+        EventFamily::Loyalty,
+        EventFamily::Creative,
+        EventFamily::Cdp,
+        EventFamily::Sdk,
+        EventFamily::Ingest,
+    ];
+
+    pub fn of(event_type: EventType) -> Self {
+        use EventType::*;
+        match event_type {
+            BidRequest | BidResponse | NoBid | Timeout | Error | DspBidSent | DspBidWon
+            | DspBidLost | DspBidTimeout => EventFamily::Bidding,
+            Impression | Click | Conversion => EventFamily::Engagement,
+            ActivationSent | ActivationDelivered | ActivationFailed | TemplateRendered
+            | TemplateDelivered => EventFamily::Delivery,
+            JourneyEntered | JourneyStepCompleted | JourneyCompleted | JourneyExited
+            | JourneySuppressedBid => EventFamily::Journey,
+            ExperimentAssignment | ExperimentExposure | ExperimentConversion => {
+                EventFamily::Experiment
+            }
+            LoyaltyEarn | LoyaltyRedeem | LoyaltyTierUp | LoyaltyTierDown | LoyaltyOfferServed
+            | LoyaltyOfferClicked | LoyaltyOfferRedeemed => EventFamily::Loyalty,
+            DcoAssembly | DcoImpression | DcoClick | DcoConversion => EventFamily::Creative,
+            CdpSyncInbound | CdpSyncOutbound | CdpWebhook => EventFamily::Cdp,
+            WebPageView | WebClick | WebFormSubmit | WebScroll | WebCustomEvent
+            | WebSessionStart | WebSessionEnd => EventFamily::Sdk,
+            ChannelIngest => EventFamily::Ingest,
+        }
+    }
+
+    /// Snake-case name, as used in the payload tag.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventFamily::Bidding => "bidding",
+            EventFamily::Engagement => "engagement",
+            EventFamily::Delivery => "delivery",
+            EventFamily::Journey => "journey",
+            EventFamily::Experiment => "experiment",
+            EventFamily::Loyalty => "loyalty",
+            EventFamily::Creative => "creative",
+            EventFamily::Cdp => "cdp",
+            EventFamily::Sdk => "sdk",
+            EventFamily::Ingest => "ingest",
+        }
+    }
+
+    /// Schema registry name of this family's payload.
+    pub fn schema_name(&self) -> String {
+        format!("{ENVELOPE_SCHEMA}.{}", self.as_str())
+    }
+
+    /// Channel reported for events whose producer does not set one.
+    fn default_channel(&self) -> Option<&'static str> {
+        match self {
+            EventFamily::Bidding | EventFamily::Engagement | EventFamily::Creative => {
+                Some("programmatic")
+            }
+            EventFamily::Sdk => Some("web"),
+            _ => None,
+        }
+    }
+}
+
+/// Where the event was generated.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventSource {
+    BidEngine,
+    JourneyEngine,
+    DirectActivation,
+    ExternalWebhook,
+    ClientSdk,
+    Dsp,
+    LoyaltyEngine,
+    Experimentation,
+    CreativeEngine,
+    CdpSync,
+    ChannelIngest,
+}
+
+impl EventSource {
+    pub fn of(event_type: EventType) -> Self {
+        match event_type {
+            EventType::DspBidSent
+            | EventType::DspBidWon
+            | EventType::DspBidLost
+            | EventType::DspBidTimeout => return EventSource::Dsp,
+            EventType::CdpWebhook => return EventSource::ExternalWebhook,
+            _ => {}
+        }
+        match EventFamily::of(event_type) {
+            EventFamily::Bidding | EventFamily::Engagement => EventSource::BidEngine,
+            EventFamily::Delivery => EventSource::DirectActivation,
+            EventFamily::Journey => EventSource::JourneyEngine,
+            EventFamily::Experiment => EventSource::Experimentation,
+            EventFamily::Loyalty => EventSource::LoyaltyEngine,
+            EventFamily::Creative => EventSource::CreativeEngine,
+            EventFamily::Cdp => EventSource::CdpSync,
+            EventFamily::Sdk => EventSource::ClientSdk,
+            EventFamily::Ingest => EventSource::ChannelIngest,
+        }
+    }
+}
+
+/// Bid path: the request, the offer bid on and what it cost.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BiddingPayload {
+    pub request_id: String,
+    pub impression_id: Option<String>,
+    pub offer_id: Option<String>,
+    pub bid_price: Option<f64>,
+    pub win_price: Option<f64>,
+    pub inference_latency_us: Option<u64>,
+    pub total_latency_us: Option<u64>,
+}
+
+/// Engagement with an offer served through bidding.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EngagementPayload {
+    pub request_id: String,
+    pub impression_id: Option<String>,
+    pub offer_id: Option<String>,
+    pub win_price: Option<f64>,
+}
+
+/// An activation on an owned channel (email, SMS, push, WhatsApp, ...).
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeliveryPayload {
+    pub activation_id: String,
+    pub offer_id: Option<String>,
+    pub latency_us: Option<u64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct JourneyPayload {
+    pub instance_id: String,
+    pub step_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ExperimentPayload {
+    pub experiment_id: String,
+    pub variant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LoyaltyPayload {
+    pub reference_id: String,
+    pub offer_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CreativePayload {
+    pub template_id: String,
+    pub variant_id: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct CdpPayload {
+    pub sync_event_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SdkPayload {
+    pub sdk_event_id: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IngestPayload {
+    pub ingest_event_id: String,
+}
+
+/// Family-specific payload, serialized as `{"family": ..., "data": {...}}`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "family", content = "data", rename_all = "snake_case")]
+pub enum EventPayload {
+    Bidding(BiddingPayload),
+    Engagement(EngagementPayload),
+    Delivery(DeliveryPayload),
+    Journey(JourneyPayload),
+    Experiment(ExperimentPayload),
+    Loyalty(LoyaltyPayload),
+    Creative(CreativePayload),
+    Cdp(CdpPayload),
+    Sdk(SdkPayload),
+    Ingest(IngestPayload),
+}
+
+impl EventPayload {
+    pub fn family(&self) -> EventFamily {
+        match self {
+            EventPayload::Bidding(_) => EventFamily::Bidding,
+            EventPayload::Engagement(_) => EventFamily::Engagement,
+            EventPayload::Delivery(_) => EventFamily::Delivery,
+            EventPayload::Journey(_) => EventFamily::Journey,
+            EventPayload::Experiment(_) => EventFamily::Experiment,
+            EventPayload::Loyalty(_) => EventFamily::Loyalty,
+            EventPayload::Creative(_) => EventFamily::Creative,
+            EventPayload::Cdp(_) => EventFamily::Cdp,
+            EventPayload::Sdk(_) => EventFamily::Sdk,
+            EventPayload::Ingest(_) => EventFamily::Ingest,
+        }
+    }
+}
+
+/// The canonical event. Common attribution lives on the envelope; anything
+/// specific to one kind of producer lives in the typed payload.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct EventEnvelope {
+    pub schema_version: u32,
+    pub event_id: Uuid,
+    pub event_type: EventType,
+    pub source: EventSource,
+    pub occurred_at: DateTime<Utc>,
+    pub agent_id: String,
+    pub node_id: String,
+    pub user_id: Option<String>,
+    pub campaign_id: Option<String>,
+    pub channel: Option<String>,
+    pub creative_id: Option<String>,
+    pub payload: EventPayload,
+}
+
+impl EventEnvelope {
+    /// Adapt a legacy analytics event. `request_id` and `offer_id` carry
+    /// different identifiers per producer, so they are mapped into the
+    /// family payload under their real names.
+    pub fn from_analytics(event: &AnalyticsEvent) -> Self {
+        let family = EventFamily::of(event.event_type);
+        let reference = event.request_id.clone();
+        let offer_id = event.offer_id.clone();
+        let payload = match family {
+            EventFamily::Bidding => EventPayload::Bidding(BiddingPayload {
+                request_id: reference,
+                impression_id: event.impression_id.clone(),
+                offer_id,
+                bid_price: event.bid_price,
+                win_price: event.win_price,
+                inference_latency_us: event.inference_latency_us,
+                total_latency_us: event.total_latency_us,
+            }),
+            EventFamily::Engagement => EventPayload::Engagement(EngagementPayload {
+                request_id: reference,
+                impression_id: event.impression_id.clone(),
+                offer_id,
+                win_price: event.win_price,
+            }),
+            EventFamily::Delivery => EventPayload::Delivery(DeliveryPayload {
+                activation_id: reference,
+                offer_id,
+                latency_us: event.total_latency_us,
+            }),
+            EventFamily::Journey => EventPayload::Journey(JourneyPayload {
+                instance_id: reference,
+                step_id: offer_id,
+            }),
+            EventFamily::Experiment => EventPayload::Experiment(ExperimentPayload {
+                experiment_id: reference,
+                variant_id: offer_id,
+            }),
+            EventFamily::Loyalty => EventPayload::Loyalty(LoyaltyPayload {
+                reference_id: reference,
+                offer_id,
+            }),
+            EventFamily::Creative => EventPayload::Creative(CreativePayload {
+                template_id: reference,
+                variant_id: offer_id,
+            }),
+            EventFamily::Cdp => EventPayload::Cdp(CdpPayload {
+                sync_event_id: reference,
+            }),
+            EventFamily::Sdk => EventPayload::Sdk(SdkPayload {
+                sdk_event_id: reference,
+            }),
+            EventFamily::Ingest => EventPayload::Ingest(IngestPayload {
+                ingest_event_id: reference,
+            }),
+        };
+
+        Self {
+            schema_version: ENVELOPE_VERSION,
+            event_id: event.event_id,
+            event_type: event.event_type,
+            source: EventSource::of(event.event_type),
+            occurred_at: event.timestamp,
+            agent_id: event.agent_id.clone(),
+            node_id: event.node_id.clone(),
+            user_id: event.user_id.clone(),
+            campaign_id: event.campaign_id.clone(),
+            channel: event
+                .channel
+                .clone()
+                .or_else(|| family.default_channel().map(str::to_string)),
+            creative_id: event.creative_id.clone(),
+            payload,
+        }
+    }
+
+    pub fn family(&self) -> EventFamily {
+        self.payload.family()
+    }
+}
+
+/// Consumer of validated envelopes.
+pub trait EnvelopeSink: Send + Sync {
+    fn publish(&self, envelope: &EventEnvelope);
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::event_bus::make_event;
+
+    #[test]
+    fn test_every_event_type_maps_to_its_payload() {
+        let bid = EventEnvelope::from_analytics(&make_event(
+            EventType::BidResponse,
+            "req-1",
+            Some("user-1".into()),
+            Some("offer-1".into()),
+        ));
+        assert_eq!(bid.family(), EventFamily::Bidding);
+        assert_eq!(bid.source, EventSource::BidEngine);
+        assert_eq!(bid.channel.as_deref(), Some("programmatic"));
+
+        let sms = EventEnvelope::from_analytics(
+            &make_event(EventType::ActivationDelivered, "msg-1", None, None).with_channel("sms"),
+        );
+        assert_eq!(sms.source, EventSource::DirectActivation);
+        assert_eq!(sms.channel.as_deref(), Some("sms"));
+        match sms.payload {
+            EventPayload::Delivery(p) => assert_eq!(p.activation_id, "msg-1"),
+            other => panic!("unexpected payload {other:?}"),
+        }
+
+        let exposure = EventEnvelope::from_analytics(&make_event(
+            EventType::ExperimentExposure,
+            "exp-1",
+            Some("user-1".into()),
+            Some("var-1".into()),
+        ));
+        assert_eq!(
+            exposure.payload,
+            EventPayload::Experiment(ExperimentPayload {
+                experiment_id: "exp-1".into(),
+                variant_id: Some("var-1".into()),
+            })
+        );
+        assert_eq!(
+            EventSource::of(EventType::CdpWebhook),
+            EventSource::ExternalWebhook
+        );
+        assert_eq!(EventSource::of(EventType::DspBidWon), EventSource::Dsp);
+    }
+
+    #[test]
+    fn test_envelope_roundtrips_through_json() {
+        let envelope = EventEnvelope::from_analytics(&make_event(
+            EventType::WebPageView,
+            "sdk-1",
+            Some("user-1".into()),
+            None,
+        ));
+        let json = serde_json::to_value(&envelope).unwrap();
+        assert_eq!(json["schema_version"], ENVELOPE_VERSION);
+        assert_eq!(json["payload"]["family"], "sdk");
+        assert_eq!(json["payload"]["data"]["sdk_event_id"], "sdk-1");
+
+        let back: EventEnvelope = serde_json::from_value(json).unwrap();
+        assert_eq!(back, envelope);
+        assert_eq!(EventFamily::Sdk.schema_name(), "event_envelope.sdk");
+    }
+}
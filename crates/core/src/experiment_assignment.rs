@@ -0,0 +1,363 @@
+//! Deterministic experiment assignment: salted hashing, layers and targeting.
+//!
+//! Every assignment decision hashes the user with a salt specific to the
+//! decision (experiment traffic, variant split, layer), so experiments bucket
+//! users independently of each other. Experiments that share a layer split
+//! the layer's buckets and are mutually exclusive; experiments in different
+//! layers, or in no layer, are orthogonal.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use uuid::Uuid;
+
+/// Resolution of bucket-based allocation (0.01% of traffic per bucket).
+pub const BUCKETS: u32 = 10_000;
+
+/// MurmurHash3 x86 32-bit.
+pub fn murmur3_32(data: &[u8], seed: u32) -> u32 {
+    const C1: u32 = 0xcc9e_2d51;
+    const C2: u32 = 0x1b87_3593;
+
+    let mut h = seed;
+    let mut chunks = data.chunks_exact(4);
+    for chunk in &mut chunks {
+        let mut k = u32::from_le_bytes([chunk[0], chunk[1], chunk[2], chunk[3]]);
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+        h = h.rotate_left(13).wrapping_mul(5).wrapping_add(0xe654_6b64);
+    }
+
+    let tail = chunks.remainder();
+    if !tail.is_empty() {
+        let mut k = 0u32;
+        for (i, byte) in tail.iter().enumerate() {
+            k ^= (*byte as u32) << (8 * i);
+        }
+        k = k.wrapping_mul(C1).rotate_left(15).wrapping_mul(C2);
+        h ^= k;
+    }
+
+    h ^= data.len() as u32;
+    h ^= h >> 16;
+    h = h.wrapping_mul(0x85eb_ca6b);
+    h ^= h >> 13;
+    h = h.wrapping_mul(0xc2b2_ae35);
+    h ^= h >> 16;
+    h
+}
+
+/// Bucket in `0..BUCKETS` for `unit` (usually a user id) under `salt`.
+pub fn bucket(salt: &str, unit: &str) -> u32 {
+    let key = format!("{salt}.{unit}");
+    murmur3_32(key.as_bytes(), 0) % BUCKETS
+}
+
+/// Number of buckets covering `share` of traffic, clamped to `0..=BUCKETS`.
+pub fn buckets_for_share(share: f64) -> u32 {
+    (share.clamp(0.0, 1.0) * BUCKETS as f64).round() as u32
+}
+
+/// Contiguous range of a layer's buckets owned by one experiment.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LayerAllocation {
+    pub experiment_id: Uuid,
+    pub start: u32,
+    pub end: u32,
+}
+
+impl LayerAllocation {
+    pub fn contains(&self, bucket: u32) -> bool {
+        (self.start..self.end).contains(&bucket)
+    }
+
+    pub fn len(&self) -> u32 {
+        self.end - self.start
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.start == self.end
+    }
+}
+
+/// A mutual-exclusion group. Users hash into the layer's buckets once, and
+/// each bucket belongs to at most one experiment, so a user sees at most one
+/// experiment per layer. The namespace scopes layer names (e.g. per product
+/// surface) and is part of the salt, so equally named layers in different
+/// namespaces are independent.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentLayer {
+    pub namespace: String,
+    pub name: String,
+    pub salt: String,
+    pub allocations: Vec<LayerAllocation>,
+}
+
+impl ExperimentLayer {
+    pub fn new(namespace: impl Into<String>, name: impl Into<String>) -> Self {
+        let namespace = namespace.into();
+        let name = name.into();
+        Self {
+            salt: format!("layer.{namespace}.{name}"),
+            namespace,
+            name,
+            allocations: Vec::new(),
+        }
+    }
+
+    /// Replace the salt, reshuffling users across the layer's buckets. Useful
+    /// after a test whose carry-over effects should not leak into the next.
+    pub fn with_salt(mut self, salt: impl Into<String>) -> Self {
+        self.salt = salt.into();
+        self
+    }
+
+    pub fn key(&self) -> String {
+        layer_key(&self.namespace, &self.name)
+    }
+
+    pub fn free_buckets(&self) -> u32 {
+        BUCKETS
+            - self
+                .allocations
+                .iter()
+                .map(LayerAllocation::len)
+                .sum::<u32>()
+    }
+
+    /// Reserve `share` of the layer's traffic for an experiment, taking the
+    /// first free contiguous range large enough.
+    pub fn allocate(&mut self, experiment_id: Uuid, share: f64) -> anyhow::Result<LayerAllocation> {
+        if self
+            .allocations
+            .iter()
+            .any(|a| a.experiment_id == experiment_id)
+        {
+            anyhow::bail!(
+                "experiment {experiment_id} is already in layer {}",
+                self.key()
+            );
+        }
+        let size = buckets_for_share(share);
+        if size == 0 {
+            anyhow::bail!("traffic share must be positive, got {share}");
+        }
+
+        let mut taken: Vec<(u32, u32)> =
+            self.allocations.iter().map(|a| (a.start, a.end)).collect();
+        taken.sort_unstable();
+        let mut start = 0;
+        for (taken_start, taken_end) in taken.into_iter().chain(std::iter::once((BUCKETS, BUCKETS)))
+        {
+            if taken_start - start >= size {
+                let allocation = LayerAllocation {
+                    experiment_id,
+                    start,
+                    end: start + size,
+                };
+                self.allocations.push(allocation);
+                return Ok(allocation);
+            }
+            start = start.max(taken_end);
+        }
+        anyhow::bail!(
+            "layer {} has no free range of {size} buckets ({} free in total)",
+            self.key(),
+            self.free_buckets()
+        )
+    }
+
+    /// Free an experiment's buckets. Returns whether it was allocated.
+    pub fn release(&mut self, experiment_id: &Uuid) -> bool {
+        let before = self.allocations.len();
+        self.allocations
+            .retain(|a| a.experiment_id != *experiment_id);
+        self.allocations.len() != before
+    }
+
+    pub fn allocation(&self, experiment_id: &Uuid) -> Option<&LayerAllocation> {
+        self.allocations
+            .iter()
+            .find(|a| a.experiment_id == *experiment_id)
+    }
+
+    /// The user's bucket in this layer.
+    pub fn bucket(&self, user_id: &str) -> u32 {
+        bucket(&self.salt, user_id)
+    }
+
+    /// The experiment that owns the user's bucket, if any.
+    pub fn experiment_for(&self, user_id: &str) -> Option<Uuid> {
+        let b = self.bucket(user_id);
+        self.allocations
+            .iter()
+            .find(|a| a.contains(b))
+            .map(|a| a.experiment_id)
+    }
+}
+
+pub fn layer_key(namespace: &str, name: &str) -> String {
+    format!("{namespace}/{name}")
+}
+
+/// What is known about the user at assignment time. Segment memberships are
+/// resolved by the caller (e.g. from the segmentation engine).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AssignmentContext {
+    pub user_id: String,
+    #[serde(default)]
+    pub segments: Vec<String>,
+    #[serde(default)]
+    pub attributes: HashMap<String, serde_json::Value>,
+}
+
+impl AssignmentContext {
+    pub fn new(user_id: impl Into<String>) -> Self {
+        Self {
+            user_id: user_id.into(),
+            ..Default::default()
+        }
+    }
+
+    pub fn with_segments<I, S>(mut self, segments: I) -> Self
+    where
+        I: IntoIterator<Item = S>,
+        S: Into<String>,
+    {
+        self.segments.extend(segments.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn with_attribute(mut self, key: impl Into<String>, value: serde_json::Value) -> Self {
+        self.attributes.insert(key.into(), value);
+        self
+    }
+}
+
+/// Audience restriction for an experiment. Users outside the audience are
+/// not assigned and do not count towards its sample.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TargetingRule {
+    /// User must belong to at least one of these segments (empty = anyone).
+    #[serde(default)]
+    pub include_segments: Vec<String>,
+    /// User must belong to none of these segments.
+    #[serde(default)]
+    pub exclude_segments: Vec<String>,
+    /// Attributes that must equal the given values.
+    #[serde(default)]
+    pub attributes: HashMap<String, serde_json::Value>,
+}
+
+impl TargetingRule {
+    pub fn matches(&self, ctx: &AssignmentContext) -> bool {
+        let in_segment = |s: &String| ctx.segments.contains(s);
+        (self.include_segments.is_empty() || self.include_segments.iter().any(in_segment))
+            && !self.exclude_segments.iter().any(in_segment)
+            && self
+                .attributes
+                .iter()
+                .all(|(k, v)| ctx.attributes.get(k) == Some(v))
+    }
+}
+
+/// Outcome of assigning a user to an experiment.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Assignment {
+    pub experiment_id: Uuid,
+    pub variant_id: Uuid,
+    /// Key of the layer the experiment runs in (`namespace/name`).
+    pub layer: Option<String>,
+    /// User's bucket for the variant split.
+    pub bucket: u32,
+}
+
+/// First exposure of a user to an experiment. Analysis should start from
+/// exposure rather than assignment, since assigned users who never reached
+/// the experience dilute the measured effect.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExposureRecord {
+    pub experiment_id: Uuid,
+    pub variant_id: Uuid,
+    pub user_id: String,
+    pub layer: Option<String>,
+    pub exposed_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_murmur3_reference_vectors() {
+        assert_eq!(murmur3_32(b"", 0), 0);
+        assert_eq!(murmur3_32(b"", 1), 0x514e_28b7);
+        assert_eq!(murmur3_32(b"test", 0), 0xba6b_d213);
+        assert_eq!(murmur3_32(b"Hello, world!", 1234), 0xfaf6_cdb3);
+        assert_eq!(
+            murmur3_32(b"The quick brown fox jumps over the lazy dog", 0),
+            0x2e4f_f723
+        );
+    }
+
+    #[test]
+    fn test_salted_buckets_are_uniform_and_independent() {
+        let users: Vec<String> = (0..20_000).map(|i| format!("user-{i}")).collect();
+        let in_a: Vec<bool> = users
+            .iter()
+            .map(|u| bucket("exp-a", u) < BUCKETS / 2)
+            .collect();
+        let in_b: Vec<bool> = users
+            .iter()
+            .map(|u| bucket("exp-b", u) < BUCKETS / 2)
+            .collect();
+
+        let share_a = in_a.iter().filter(|x| **x).count() as f64 / users.len() as f64;
+        assert!((share_a - 0.5).abs() < 0.02, "{share_a}");
+        // Independent salts: half of A's treatment group lands in B's.
+        let both = in_a.iter().zip(&in_b).filter(|(a, b)| **a && **b).count() as f64;
+        assert!((both / users.len() as f64 - 0.25).abs() < 0.02);
+    }
+
+    #[test]
+    fn test_layer_allocation_and_exclusivity() {
+        let mut layer = ExperimentLayer::new("checkout", "pricing");
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        assert_eq!(layer.allocate(a, 0.4).unwrap().end, 4_000);
+        assert_eq!(layer.allocate(b, 0.4).unwrap().start, 4_000);
+        assert!(layer.allocate(c, 0.3).is_err());
+        assert!(layer.allocate(a, 0.1).is_err());
+
+        layer.release(&a);
+        let reused = layer.allocate(c, 0.3).unwrap();
+        assert_eq!((reused.start, reused.end), (0, 3_000));
+        assert_eq!(layer.free_buckets(), 3_000);
+
+        for i in 0..1_000 {
+            let user = format!("user-{i}");
+            let owner = layer.experiment_for(&user);
+            let bucket = layer.bucket(&user);
+            match owner {
+                Some(id) => assert!(layer.allocation(&id).unwrap().contains(bucket)),
+                None => assert!(!(0..3_000).contains(&bucket) && !(4_000..8_000).contains(&bucket)),
+            }
+        }
+    }
+
+    #[test]
+    fn test_targeting_rule() {
+        let rule = TargetingRule {
+            include_segments: vec!["vip".into(), "loyal".into()],
+            exclude_segments: vec!["churned".into()],
+            attributes: HashMap::from([("country".to_string(), serde_json::json!("DE"))]),
+        };
+        let ctx = AssignmentContext::new("u1")
+            .with_segments(["loyal"])
+            .with_attribute("country", serde_json::json!("DE"));
+        assert!(rule.matches(&ctx));
+        assert!(!rule.matches(&ctx.clone().with_segments(["churned"])));
+        assert!(!rule.matches(&AssignmentContext::new("u1").with_segments(["loyal"])));
+        assert!(TargetingRule::default().matches(&AssignmentContext::new("u2")));
+    }
+}
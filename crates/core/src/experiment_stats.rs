@@ -0,0 +1,828 @@
+//! Experiment statistics — always-valid sequential tests (mSPRT), CUPED
+//! variance reduction, sample-ratio-mismatch detection, Bayesian
+//! probability-to-beat-control and power / sample-size calculation.
+//!
+//! The sequential test follows Johari et al., "Always Valid Inference": a
+//! normal mixture over the treatment effect gives a likelihood ratio whose
+//! reciprocal is a p-value that stays valid however often it is checked.
+
+use anyhow::bail;
+use serde::{Deserialize, Serialize};
+
+// ─── Sample summaries ───────────────────────────────────────────────────
+
+/// Size, mean and (unbiased) variance of one arm's metric.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SampleStats {
+    pub n: u64,
+    pub mean: f64,
+    pub variance: f64,
+}
+
+impl SampleStats {
+    /// A conversion-rate metric: `conversions` successes out of `n` units.
+    pub fn from_conversions(conversions: u64, n: u64) -> Self {
+        if n == 0 {
+            return Self::default();
+        }
+        let p = (conversions.min(n)) as f64 / n as f64;
+        Self {
+            n,
+            mean: p,
+            variance: p * (1.0 - p),
+        }
+    }
+
+    /// From running sums of the metric and its square.
+    pub fn from_sums(n: u64, sum: f64, sum_squares: f64) -> Self {
+        if n == 0 {
+            return Self::default();
+        }
+        let mean = sum / n as f64;
+        let variance = if n > 1 {
+            ((sum_squares - n as f64 * mean * mean) / (n - 1) as f64).max(0.0)
+        } else {
+            0.0
+        };
+        Self { n, mean, variance }
+    }
+
+    pub fn from_values(values: &[f64]) -> Self {
+        let (sum, sum_squares) = values
+            .iter()
+            .fold((0.0, 0.0), |(s, q), v| (s + v, q + v * v));
+        Self::from_sums(values.len() as u64, sum, sum_squares)
+    }
+
+    /// Variance of the mean.
+    fn mean_variance(&self) -> f64 {
+        if self.n == 0 {
+            0.0
+        } else {
+            self.variance / self.n as f64
+        }
+    }
+}
+
+// ─── Distributions ──────────────────────────────────────────────────────
+
+/// Standard normal CDF.
+pub fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+/// Complementary error function (Numerical Recipes `erfcc`, relative error
+/// below 1.2e-7).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t
+        * (-z * z - 1.265_512_23
+            + t * (1.000_023_68
+                + t * (0.374_091_96
+                    + t * (0.096_784_18
+                        + t * (-0.186_288_06
+                            + t * (0.278_868_07
+                                + t * (-1.135_203_98
+                                    + t * (1.488_515_87
+                                        + t * (-0.822_152_23 + t * 0.170_872_77)))))))))
+            .exp();
+    if x >= 0.0 {
+        r
+    } else {
+        2.0 - r
+    }
+}
+
+/// Inverse of the standard normal CDF (Acklam's rational approximation).
+pub fn normal_quantile(p: f64) -> f64 {
+    const A: [f64; 6] = [
+        -3.969_683_028_665_376e1,
+        2.209_460_984_245_205e2,
+        -2.759_285_104_469_687e2,
+        1.383_577_518_672_69e2,
+        -3.066_479_806_614_716e1,
+        2.506_628_277_459_239,
+    ];
+    const B: [f64; 5] = [
+        -5.447_609_879_822_406e1,
+        1.615_858_368_580_409e2,
+        -1.556_989_798_598_866e2,
+        6.680_131_188_771_972e1,
+        -1.328_068_155_288_572e1,
+    ];
+    const C: [f64; 6] = [
+        -7.784_894_002_430_293e-3,
+        -3.223_964_580_411_365e-1,
+        -2.400_758_277_161_838,
+        -2.549_732_539_343_734,
+        4.374_664_141_464_968,
+        2.938_163_982_698_783,
+    ];
+    const D: [f64; 4] = [
+        7.784_695_709_041_462e-3,
+        3.224_671_290_700_398e-1,
+        2.445_134_137_142_996,
+        3.754_408_661_907_416,
+    ];
+    if p <= 0.0 {
+        return f64::NEG_INFINITY;
+    }
+    if p >= 1.0 {
+        return f64::INFINITY;
+    }
+    let tail = |q: f64| {
+        (((((C[0] * q + C[1]) * q + C[2]) * q + C[3]) * q + C[4]) * q + C[5])
+            / ((((D[0] * q + D[1]) * q + D[2]) * q + D[3]) * q + 1.0)
+    };
+    if p < 0.024_25 {
+        tail((-2.0 * p.ln()).sqrt())
+    } else if p > 1.0 - 0.024_25 {
+        -tail((-2.0 * (1.0 - p).ln()).sqrt())
+    } else {
+        let q = p - 0.5;
+        let r = q * q;
+        (((((A[0] * r + A[1]) * r + A[2]) * r + A[3]) * r + A[4]) * r + A[5]) * q
+            / (((((B[0] * r + B[1]) * r + B[2]) * r + B[3]) * r + B[4]) * r + 1.0)
+    }
+}
+
+/// Natural log of the gamma function (Lanczos, g = 7).
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    if x < 0.5 {
+        let pi = std::f64::consts::PI;
+        return (pi / (pi * x).sin()).ln() - ln_gamma(1.0 - x);
+    }
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |acc, (i, c)| {
+            acc + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// Regularized upper incomplete gamma function Q(a, x).
+fn gamma_q(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let log_prefix = a * x.ln() - x - ln_gamma(a);
+    if x < a + 1.0 {
+        // Series for P(a, x).
+        let (mut term, mut sum, mut ap) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..500 {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-15 {
+                break;
+            }
+        }
+        1.0 - sum * log_prefix.exp()
+    } else {
+        // Continued fraction (modified Lentz) for Q(a, x).
+        let tiny = 1e-300;
+        let mut b = x + 1.0 - a;
+        let mut c = 1.0 / tiny;
+        let mut d = 1.0 / b;
+        let mut h = d;
+        for i in 1..500 {
+            let an = -(i as f64) * (i as f64 - a);
+            b += 2.0;
+            d = an * d + b;
+            if d.abs() < tiny {
+                d = tiny;
+            }
+            c = b + an / c;
+            if c.abs() < tiny {
+                c = tiny;
+            }
+            d = 1.0 / d;
+            let delta = d * c;
+            h *= delta;
+            if (delta - 1.0).abs() < 1e-15 {
+                break;
+            }
+        }
+        log_prefix.exp() * h
+    }
+}
+
+/// Survival function of the chi-square distribution.
+pub fn chi_square_sf(statistic: f64, degrees_of_freedom: u32) -> f64 {
+    if degrees_of_freedom == 0 {
+        return 1.0;
+    }
+    gamma_q(degrees_of_freedom as f64 / 2.0, statistic / 2.0).clamp(0.0, 1.0)
+}
+
+// ─── Sequential testing (mSPRT) ─────────────────────────────────────────
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct SequentialConfig {
+    /// Type I error rate of the always-valid test.
+    pub alpha: f64,
+    /// Variance of the normal mixing distribution over the absolute effect.
+    /// Set it at design time with [`ExperimentDesign::sequential_config`].
+    /// When unset, [`msprt`] uses `(0.1 × |control mean|)²` and a
+    /// [`SequentialTest`] fixes that value at its first look.
+    #[serde(default)]
+    pub mixing_variance: Option<f64>,
+}
+
+impl Default for SequentialConfig {
+    fn default() -> Self {
+        Self {
+            alpha: 0.05,
+            mixing_variance: None,
+        }
+    }
+}
+
+/// Treatment vs control at one look.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SequentialResult {
+    /// Treatment mean minus control mean.
+    pub difference: f64,
+    /// `difference / control mean`, when the control mean is non-zero.
+    pub relative_lift: Option<f64>,
+    pub likelihood_ratio: f64,
+    /// Always-valid p-value.
+    pub p_value: f64,
+    /// Always-valid confidence interval for `difference`; `None` until both
+    /// arms have variance.
+    pub confidence_interval: Option<(f64, f64)>,
+    pub significant: bool,
+}
+
+/// Mixture sequential probability ratio test of `treatment - control = 0`.
+/// Rejecting whenever `p_value < alpha` keeps the error rate at `alpha` no
+/// matter how often the test is checked.
+pub fn msprt(
+    control: &SampleStats,
+    treatment: &SampleStats,
+    config: &SequentialConfig,
+) -> SequentialResult {
+    let difference = treatment.mean - control.mean;
+    let relative_lift = (control.mean != 0.0).then(|| difference / control.mean.abs());
+    let v = control.mean_variance() + treatment.mean_variance();
+    let tau2 = config
+        .mixing_variance
+        .unwrap_or_else(|| default_mixing_variance(control));
+    if control.n == 0 || treatment.n == 0 || v <= 0.0 || tau2 <= 0.0 {
+        return SequentialResult {
+            difference,
+            relative_lift,
+            likelihood_ratio: 1.0,
+            p_value: 1.0,
+            confidence_interval: None,
+            significant: false,
+        };
+    }
+
+    let log_ratio =
+        0.5 * (v / (v + tau2)).ln() + tau2 * difference * difference / (2.0 * v * (v + tau2));
+    let likelihood_ratio = log_ratio.exp();
+    let p_value = (-log_ratio).exp().min(1.0);
+    let half_width =
+        (v * (v + tau2) / tau2 * (((v + tau2) / v).ln() - 2.0 * config.alpha.ln())).sqrt();
+    SequentialResult {
+        difference,
+        relative_lift,
+        likelihood_ratio,
+        p_value,
+        confidence_interval: Some((difference - half_width, difference + half_width)),
+        significant: p_value < config.alpha,
+    }
+}
+
+/// `(0.1 × |control mean|)²`, tuned to detect lifts of around 10%.
+fn default_mixing_variance(control: &SampleStats) -> f64 {
+    let scale = if control.mean != 0.0 {
+        control.mean.abs()
+    } else {
+        control.variance.sqrt()
+    };
+    (0.1 * scale).powi(2)
+}
+
+/// A sequential test across repeated looks: the reported p-value is the
+/// running minimum and the interval the running intersection, so both only
+/// ever tighten. The mixing variance is fixed for the life of the test;
+/// re-deriving it from the data at each look would void the guarantee.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SequentialTest {
+    pub config: SequentialConfig,
+    pub looks: u64,
+    min_p_value: Option<f64>,
+    interval: Option<(f64, f64)>,
+}
+
+impl SequentialTest {
+    pub fn new(config: SequentialConfig) -> Self {
+        Self {
+            config,
+            ..Default::default()
+        }
+    }
+
+    /// A test whose mixing variance comes from the design's baseline and
+    /// minimum detectable effect.
+    pub fn from_design(design: &ExperimentDesign) -> Self {
+        Self::new(design.sequential_config())
+    }
+
+    pub fn observe(&mut self, control: &SampleStats, treatment: &SampleStats) -> SequentialResult {
+        if self.config.mixing_variance.is_none() && control.n > 0 {
+            let tau2 = default_mixing_variance(control);
+            if tau2 > 0.0 {
+                self.config.mixing_variance = Some(tau2);
+            }
+        }
+        let mut result = msprt(control, treatment, &self.config);
+        self.looks += 1;
+        let p = self
+            .min_p_value
+            .map_or(result.p_value, |m| m.min(result.p_value));
+        self.min_p_value = Some(p);
+        result.p_value = p;
+        result.significant = p < self.config.alpha;
+        if let Some((lo, hi)) = result.confidence_interval {
+            let (lo, hi) = match self.interval {
+                Some((a, b)) if lo.max(a) <= hi.min(b) => (lo.max(a), hi.min(b)),
+                _ => (lo, hi),
+            };
+            self.interval = Some((lo, hi));
+            result.confidence_interval = Some((lo, hi));
+        }
+        result
+    }
+}
+
+// ─── CUPED ──────────────────────────────────────────────────────────────
+
+/// Arms after CUPED adjustment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CupedResult {
+    /// `cov(metric, covariate) / var(covariate)` over both arms.
+    pub theta: f64,
+    /// Share of metric variance removed by the covariate.
+    pub variance_reduction: f64,
+    pub control: SampleStats,
+    pub treatment: SampleStats,
+}
+
+/// Adjust each unit's metric by its pre-period covariate:
+/// `y - θ (x - mean(x))`. Arms are `(metric, covariate)` pairs; the
+/// adjustment leaves the means' difference unbiased while shrinking their
+/// variance by the squared correlation.
+pub fn cuped(control: &[(f64, f64)], treatment: &[(f64, f64)]) -> CupedResult {
+    let pooled: Vec<(f64, f64)> = control.iter().chain(treatment).copied().collect();
+    let n = pooled.len() as f64;
+    let (theta, mean_x) = if pooled.len() < 2 {
+        (0.0, 0.0)
+    } else {
+        let mean_y = pooled.iter().map(|(y, _)| y).sum::<f64>() / n;
+        let mean_x = pooled.iter().map(|(_, x)| x).sum::<f64>() / n;
+        let (cov, var_x) = pooled.iter().fold((0.0, 0.0), |(c, v), (y, x)| {
+            (c + (y - mean_y) * (x - mean_x), v + (x - mean_x).powi(2))
+        });
+        (if var_x > 0.0 { cov / var_x } else { 0.0 }, mean_x)
+    };
+    let adjust = |arm: &[(f64, f64)]| {
+        let values: Vec<f64> = arm.iter().map(|(y, x)| y - theta * (x - mean_x)).collect();
+        SampleStats::from_values(&values)
+    };
+    let raw = SampleStats::from_values(&pooled.iter().map(|(y, _)| *y).collect::<Vec<_>>());
+    let adjusted = SampleStats::from_values(
+        &pooled
+            .iter()
+            .map(|(y, x)| y - theta * (x - mean_x))
+            .collect::<Vec<_>>(),
+    );
+    let variance_reduction = if raw.variance > 0.0 {
+        (1.0 - adjusted.variance / raw.variance).max(0.0)
+    } else {
+        0.0
+    };
+    CupedResult {
+        theta,
+        variance_reduction,
+        control: adjust(control),
+        treatment: adjust(treatment),
+    }
+}
+
+// ─── Sample ratio mismatch ──────────────────────────────────────────────
+
+/// Significance threshold for flagging a sample ratio mismatch; kept strict
+/// because a mismatch invalidates the experiment.
+pub const SRM_ALPHA: f64 = 0.001;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SrmResult {
+    pub chi_square: f64,
+    pub p_value: f64,
+    pub mismatch: bool,
+}
+
+/// Chi-square goodness-of-fit of observed arm sizes against the configured
+/// traffic weights.
+pub fn sample_ratio_mismatch(observed: &[u64], weights: &[f64]) -> SrmResult {
+    let total: u64 = observed.iter().sum();
+    let weight_sum: f64 = weights.iter().filter(|w| **w > 0.0).sum();
+    if total == 0 || observed.len() != weights.len() || weight_sum <= 0.0 {
+        return SrmResult {
+            chi_square: 0.0,
+            p_value: 1.0,
+            mismatch: false,
+        };
+    }
+    let mut chi_square = 0.0;
+    let mut arms = 0u32;
+    for (&count, &weight) in observed.iter().zip(weights) {
+        if weight <= 0.0 {
+            if count > 0 {
+                // Traffic reached an arm that should receive none.
+                return SrmResult {
+                    chi_square: f64::MAX,
+                    p_value: 0.0,
+                    mismatch: true,
+                };
+            }
+            continue;
+        }
+        let expected = total as f64 * weight / weight_sum;
+        chi_square += (count as f64 - expected).powi(2) / expected;
+        arms += 1;
+    }
+    let p_value = chi_square_sf(chi_square, arms.saturating_sub(1));
+    SrmResult {
+        chi_square,
+        p_value,
+        mismatch: p_value < SRM_ALPHA,
+    }
+}
+
+// ─── Bayesian comparison ────────────────────────────────────────────────
+
+/// P(treatment rate > control rate) under independent Beta(1, 1) priors,
+/// given `(conversions, trials)` per arm. Integrated numerically over the
+/// region holding both posteriors.
+pub fn probability_to_beat_control(control: (u64, u64), treatment: (u64, u64)) -> f64 {
+    let posterior = |(conversions, trials): (u64, u64)| {
+        let conversions = conversions.min(trials) as f64;
+        (conversions + 1.0, trials as f64 - conversions + 1.0)
+    };
+    let (a_c, b_c) = posterior(control);
+    let (a_t, b_t) = posterior(treatment);
+    let moments = |a: f64, b: f64| {
+        let mean = a / (a + b);
+        (mean, (a * b / ((a + b).powi(2) * (a + b + 1.0))).sqrt())
+    };
+    let (m_c, s_c) = moments(a_c, b_c);
+    let (m_t, s_t) = moments(a_t, b_t);
+    let lo = (m_c - 12.0 * s_c).min(m_t - 12.0 * s_t).max(0.0);
+    let hi = (m_c + 12.0 * s_c).max(m_t + 12.0 * s_t).min(1.0);
+
+    let ln_pdf = |a: f64, b: f64| {
+        let ln_beta = ln_gamma(a) + ln_gamma(b) - ln_gamma(a + b);
+        move |x: f64| (a - 1.0) * x.ln() + (b - 1.0) * (1.0 - x).ln() - ln_beta
+    };
+    let (pdf_c, pdf_t) = (ln_pdf(a_c, b_c), ln_pdf(a_t, b_t));
+    const STEPS: usize = 4000;
+    let h = (hi - lo) / STEPS as f64;
+    let (mut cdf_c, mut mass_t, mut beat) = (0.0, 0.0, 0.0);
+    for i in 0..STEPS {
+        let x = lo + (i as f64 + 0.5) * h;
+        let f_c = pdf_c(x).exp() * h;
+        let f_t = pdf_t(x).exp() * h;
+        // Control CDF at the cell midpoint.
+        let below = cdf_c + f_c / 2.0;
+        beat += f_t * below;
+        mass_t += f_t;
+        cdf_c += f_c;
+    }
+    if mass_t <= 0.0 || cdf_c <= 0.0 {
+        return 0.5;
+    }
+    (beat / (mass_t * cdf_c)).clamp(0.0, 1.0)
+}
+
+/// P(treatment mean > control mean) for continuous metrics, using the
+/// normal approximation to each arm's mean.
+pub fn probability_to_beat_control_normal(control: &SampleStats, treatment: &SampleStats) -> f64 {
+    let se = (control.mean_variance() + treatment.mean_variance()).sqrt();
+    if control.n == 0 || treatment.n == 0 {
+        return 0.5;
+    }
+    if se == 0.0 {
+        return match treatment.mean.partial_cmp(&control.mean) {
+            Some(std::cmp::Ordering::Greater) => 1.0,
+            Some(std::cmp::Ordering::Less) => 0.0,
+            _ => 0.5,
+        };
+    }
+    normal_cdf((treatment.mean - control.mean) / se)
+}
+
+// ─── Power ──────────────────────────────────────────────────────────────
+
+/// Inputs to size a conversion-rate experiment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentDesign {
+    /// Control conversion rate, in (0, 1).
+    pub baseline_rate: f64,
+    /// Smallest relative lift worth detecting, e.g. 0.05 for +5%.
+    pub minimum_detectable_effect: f64,
+    #[serde(default = "default_alpha")]
+    pub alpha: f64,
+    #[serde(default = "default_power")]
+    pub power: f64,
+}
+
+fn default_alpha() -> f64 {
+    0.05
+}
+
+fn default_power() -> f64 {
+    0.8
+}
+
+impl ExperimentDesign {
+    pub fn new(baseline_rate: f64, minimum_detectable_effect: f64) -> Self {
+        Self {
+            baseline_rate,
+            minimum_detectable_effect,
+            alpha: default_alpha(),
+            power: default_power(),
+        }
+    }
+
+    /// Sequential test settings for this design with one treatment: its
+    /// alpha, and a mixing variance centred on the absolute effect it is
+    /// powered to detect, `(baseline × MDE)²`.
+    pub fn sequential_config(&self) -> SequentialConfig {
+        self.sequential_config_for(2)
+    }
+
+    /// Sequential test settings for each treatment-vs-control comparison
+    /// among `variants` arms, at the alpha the sample size was computed
+    /// with.
+    pub fn sequential_config_for(&self, variants: usize) -> SequentialConfig {
+        SequentialConfig {
+            alpha: bonferroni_alpha(self.alpha, variants),
+            mixing_variance: Some((self.baseline_rate * self.minimum_detectable_effect).powi(2)),
+        }
+    }
+
+    /// Units needed in each arm. With more than one treatment the alpha is
+    /// Bonferroni-split across the treatment-vs-control comparisons.
+    pub fn sample_size_per_variant(&self, variants: usize) -> anyhow::Result<u64> {
+        sample_size_for_proportions(
+            self.baseline_rate,
+            self.minimum_detectable_effect,
+            bonferroni_alpha(self.alpha, variants),
+            self.power,
+        )
+    }
+}
+
+/// `alpha` split evenly across the treatment-vs-control comparisons of
+/// `variants` arms.
+pub fn bonferroni_alpha(alpha: f64, variants: usize) -> f64 {
+    alpha / variants.saturating_sub(1).max(1) as f64
+}
+
+fn check_error_rates(alpha: f64, power: f64) -> anyhow::Result<()> {
+    if !(alpha > 0.0 && alpha < 1.0) {
+        bail!("alpha must be in (0, 1), got {alpha}");
+    }
+    if !(power > 0.0 && power < 1.0) {
+        bail!("power must be in (0, 1), got {power}");
+    }
+    Ok(())
+}
+
+/// Per-arm sample size for a two-sided two-proportion test.
+pub fn sample_size_for_proportions(
+    baseline_rate: f64,
+    relative_mde: f64,
+    alpha: f64,
+    power: f64,
+) -> anyhow::Result<u64> {
+    check_error_rates(alpha, power)?;
+    let p1 = baseline_rate;
+    let p2 = baseline_rate * (1.0 + relative_mde);
+    if !(p1 > 0.0 && p1 < 1.0) {
+        bail!("baseline rate must be in (0, 1), got {p1}");
+    }
+    if relative_mde == 0.0 || !(p2 > 0.0 && p2 < 1.0) {
+        bail!("minimum detectable effect {relative_mde} does not give a valid treatment rate");
+    }
+    let z_alpha = normal_quantile(1.0 - alpha / 2.0);
+    let z_beta = normal_quantile(power);
+    let pooled = (p1 + p2) / 2.0;
+    let numerator = z_alpha * (2.0 * pooled * (1.0 - pooled)).sqrt()
+        + z_beta * (p1 * (1.0 - p1) + p2 * (1.0 - p2)).sqrt();
+    Ok((numerator / (p2 - p1)).powi(2).ceil() as u64)
+}
+
+/// Per-arm sample size for a two-sided difference in means.
+pub fn sample_size_for_means(
+    std_dev: f64,
+    absolute_mde: f64,
+    alpha: f64,
+    power: f64,
+) -> anyhow::Result<u64> {
+    check_error_rates(alpha, power)?;
+    if std_dev <= 0.0 || absolute_mde == 0.0 {
+        bail!("standard deviation and minimum detectable effect must be non-zero");
+    }
+    let z = normal_quantile(1.0 - alpha / 2.0) + normal_quantile(power);
+    Ok((2.0 * (z * std_dev / absolute_mde).powi(2)).ceil() as u64)
+}
+
+/// Smallest relative lift detectable with `n_per_variant` units per arm.
+pub fn minimum_detectable_effect(
+    baseline_rate: f64,
+    n_per_variant: u64,
+    alpha: f64,
+    power: f64,
+) -> anyhow::Result<f64> {
+    check_error_rates(alpha, power)?;
+    if !(baseline_rate > 0.0 && baseline_rate < 1.0) || n_per_variant == 0 {
+        bail!("baseline rate must be in (0, 1) with a non-empty sample");
+    }
+    // Required sample size falls monotonically with the effect; bisect.
+    let (mut lo, mut hi) = (1e-6, 1.0 / baseline_rate - 1.0 - 1e-9);
+    if sample_size_for_proportions(baseline_rate, hi, alpha, power)? > n_per_variant {
+        bail!("no lift is detectable with {n_per_variant} units per arm");
+    }
+    for _ in 0..100 {
+        let mid = (lo + hi) / 2.0;
+        if sample_size_for_proportions(baseline_rate, mid, alpha, power)? > n_per_variant {
+            lo = mid;
+        } else {
+            hi = mid;
+        }
+    }
+    Ok(hi)
+}
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_distributions() {
+        assert!((normal_cdf(1.959_963_985) - 0.975).abs() < 1e-6);
+        assert!((normal_quantile(0.975) - 1.959_963_985).abs() < 1e-6);
+        assert!((normal_quantile(0.001) + 3.090_232_306).abs() < 1e-6);
+        // Chi-square(1) at 3.841 and chi-square(3) at 7.815 are the 5% points.
+        assert!((chi_square_sf(3.841_458_82, 1) - 0.05).abs() < 1e-6);
+        assert!((chi_square_sf(7.814_727_9, 3) - 0.05).abs() < 1e-6);
+        assert!((chi_square_sf(30.0, 2) - (-15.0f64).exp()).abs() < 1e-12);
+    }
+
+    #[test]
+    fn test_msprt_is_conservative_and_tightens() {
+        let config = SequentialConfig::default();
+        let control = SampleStats::from_conversions(1000, 10_000);
+        let same = SampleStats::from_conversions(1010, 10_000);
+        let result = msprt(&control, &same, &config);
+        assert!(!result.significant);
+        let (lo, hi) = result.confidence_interval.unwrap();
+        assert!(lo < 0.001 && hi > 0.001);
+
+        // 10% vs 12% on 10k each: a fixed-horizon z-test gives p ≈ 1e-5;
+        // the always-valid p-value is larger but still significant.
+        let better = SampleStats::from_conversions(1200, 10_000);
+        let result = msprt(&control, &better, &config);
+        assert!(result.significant, "{result:?}");
+        assert!(result.p_value > 1e-5);
+        assert!((result.relative_lift.unwrap() - 0.2).abs() < 1e-9);
+
+        let mut test = SequentialTest::new(config);
+        let first = test.observe(&control, &better);
+        let second = test.observe(&control, &same);
+        assert_eq!(second.p_value, first.p_value);
+        assert!(second.significant);
+        assert_eq!(test.looks, 2);
+
+        let empty = msprt(&SampleStats::default(), &better, &config);
+        assert_eq!(empty.p_value, 1.0);
+        assert!(empty.confidence_interval.is_none());
+    }
+
+    #[test]
+    fn test_mixing_variance_is_fixed_per_test() {
+        let design = ExperimentDesign::new(0.10, 0.20);
+        let config = design.sequential_config();
+        assert!((config.mixing_variance.unwrap() - 0.0004).abs() < 1e-12);
+        assert_eq!(config.alpha, 0.05);
+        assert_eq!(design.sequential_config_for(3).alpha, 0.025);
+        let test = SequentialTest::from_design(&design);
+        assert_eq!(test.config.mixing_variance, config.mixing_variance);
+
+        // Without a design, the first look fixes τ² and later looks with a
+        // different control rate reuse it.
+        let mut test = SequentialTest::new(SequentialConfig::default());
+        test.observe(&SampleStats::default(), &SampleStats::default());
+        assert_eq!(test.config.mixing_variance, None);
+        let control = SampleStats::from_conversions(100, 1000);
+        test.observe(&control, &SampleStats::from_conversions(110, 1000));
+        let tau2 = test.config.mixing_variance.unwrap();
+        assert!((tau2 - 1e-4).abs() < 1e-12);
+        test.observe(
+            &SampleStats::from_conversions(500, 2000),
+            &SampleStats::from_conversions(520, 2000),
+        );
+        assert_eq!(test.config.mixing_variance, Some(tau2));
+    }
+
+    #[test]
+    fn test_cuped_reduces_variance() {
+        // Metric = pre-period value + small noise + 1 in treatment.
+        let noise = |i: usize| ((i * 7919) % 13) as f64 / 13.0 - 0.5;
+        let control: Vec<(f64, f64)> = (0..200)
+            .map(|i| {
+                let x = (i % 50) as f64;
+                (x + noise(i), x)
+            })
+            .collect();
+        let treatment: Vec<(f64, f64)> = (0..200)
+            .map(|i| {
+                let x = ((i + 25) % 50) as f64;
+                (x + 1.0 + noise(i + 3), x)
+            })
+            .collect();
+        let result = cuped(&control, &treatment);
+        assert!((result.theta - 1.0).abs() < 0.05);
+        assert!(result.variance_reduction > 0.99);
+        let raw_c = SampleStats::from_values(&control.iter().map(|p| p.0).collect::<Vec<_>>());
+        let raw_t = SampleStats::from_values(&treatment.iter().map(|p| p.0).collect::<Vec<_>>());
+        let lift = result.treatment.mean - result.control.mean;
+        assert!((lift - (raw_t.mean - raw_c.mean)).abs() < 0.1);
+        assert!(result.control.variance < raw_c.variance / 50.0);
+    }
+
+    #[test]
+    fn test_sample_ratio_mismatch() {
+        let ok = sample_ratio_mismatch(&[5030, 4970], &[0.5, 0.5]);
+        assert!(!ok.mismatch);
+        let bad = sample_ratio_mismatch(&[5300, 4700], &[0.5, 0.5]);
+        assert!(bad.mismatch && bad.p_value < 1e-6);
+        let weighted = sample_ratio_mismatch(&[8000, 1000, 1000], &[0.8, 0.1, 0.1]);
+        assert!(!weighted.mismatch);
+        assert!(sample_ratio_mismatch(&[10, 1], &[1.0, 0.0]).mismatch);
+    }
+
+    #[test]
+    fn test_probability_to_beat_control() {
+        assert!((probability_to_beat_control((0, 0), (0, 0)) - 0.5).abs() < 1e-3);
+        let p = probability_to_beat_control((100, 1000), (200, 1000));
+        assert!(p > 0.999_99);
+        // Normal approximation for 10% vs 12% on 1000 each: Φ(1.43) ≈ 0.92.
+        let p = probability_to_beat_control((100, 1000), (120, 1000));
+        assert!((p - 0.92).abs() < 0.01, "{p}");
+        assert!((probability_to_beat_control((120, 1000), (100, 1000)) - (1.0 - p)).abs() < 1e-6);
+        let normal = probability_to_beat_control_normal(
+            &SampleStats::from_conversions(100, 1000),
+            &SampleStats::from_conversions(120, 1000),
+        );
+        assert!((normal - p).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_power_calculator() {
+        // 10% baseline, +10% relative lift, α = 0.05, 80% power: ≈ 14,750.
+        let n = sample_size_for_proportions(0.10, 0.10, 0.05, 0.8).unwrap();
+        assert!((14_700..14_800).contains(&n), "{n}");
+        let design = ExperimentDesign::new(0.10, 0.10);
+        assert_eq!(design.sample_size_per_variant(2).unwrap(), n);
+        assert!(design.sample_size_per_variant(4).unwrap() > n);
+        let mde = minimum_detectable_effect(0.10, n, 0.05, 0.8).unwrap();
+        assert!((mde - 0.10).abs() < 0.001, "{mde}");
+        // σ = 10, δ = 1: 2 (1.96 + 0.84)² 100 ≈ 1570.
+        let n = sample_size_for_means(10.0, 1.0, 0.05, 0.8).unwrap();
+        assert!((1569..1572).contains(&n), "{n}");
+        assert!(sample_size_for_proportions(0.0, 0.1, 0.05, 0.8).is_err());
+        assert!(sample_size_for_proportions(0.6, 1.0, 0.05, 0.8).is_err());
+    }
+}
@@ -1,27 +1,83 @@
 //! Experimentation framework types and utilities.
 
-use crate::types::{Experiment, ExperimentStatus, ExperimentVariant};
+use crate::event_bus::{make_event, noop_sink, EventSink};
+use crate::experiment_assignment::{
+    bucket, buckets_for_share, layer_key, Assignment, AssignmentContext, ExperimentLayer,
+    ExposureRecord, TargetingRule, BUCKETS,
+};
+use crate::experiment_stats::{
+    bonferroni_alpha, probability_to_beat_control, sample_ratio_mismatch, ExperimentDesign,
+    SampleStats, SequentialConfig, SequentialTest, SrmResult,
+};
+use crate::types::{EventType, Experiment, ExperimentStatus, ExperimentVariant};
+use chrono::Utc;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::Arc;
 use uuid::Uuid;
 
 /// Experimentation engine for A/B/n testing
 pub struct ExperimentEngine {
     experiments: std::collections::HashMap<Uuid, Experiment>,
+    designs: std::collections::HashMap<Uuid, ExperimentDesign>,
+    /// Layers keyed by `namespace/name`.
+    layers: std::collections::HashMap<String, ExperimentLayer>,
+    experiment_layers: std::collections::HashMap<Uuid, String>,
+    targeting: std::collections::HashMap<Uuid, TargetingRule>,
+    exposures: std::collections::HashMap<Uuid, Vec<ExposureRecord>>,
+    exposed_users: HashSet<(Uuid, String)>,
+    /// Sequential test per (experiment, treatment variant), kept across
+    /// significance checks.
+    sequential: std::collections::HashMap<(Uuid, Uuid), SequentialTest>,
+    event_sink: Arc<dyn EventSink>,
 }
 
 impl ExperimentEngine {
     pub fn new() -> Self {
         Self {
             experiments: std::collections::HashMap::new(),
+            designs: std::collections::HashMap::new(),
+            layers: std::collections::HashMap::new(),
+            experiment_layers: std::collections::HashMap::new(),
+            targeting: std::collections::HashMap::new(),
+            exposures: std::collections::HashMap::new(),
+            exposed_users: HashSet::new(),
+            sequential: std::collections::HashMap::new(),
+            event_sink: noop_sink(),
         }
     }
 
+    /// Emit an `ExperimentExposure` event for each first exposure.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sink = sink;
+        self
+    }
+
     pub fn create_experiment(&mut self, experiment: Experiment) -> Uuid {
         let id = experiment.id;
         self.experiments.insert(id, experiment);
         id
     }
 
+    /// Create an experiment sized by a power calculation: `min_sample_size`
+    /// becomes the total across variants needed to detect the design's
+    /// minimum effect, and the design's alpha is used for significance.
+    pub fn create_powered_experiment(
+        &mut self,
+        mut experiment: Experiment,
+        design: ExperimentDesign,
+    ) -> anyhow::Result<Uuid> {
+        let variants = experiment.variants.len().max(2);
+        let per_variant = design.sample_size_per_variant(variants)?;
+        experiment.min_sample_size = per_variant * variants as u64;
+        self.designs.insert(experiment.id, design);
+        Ok(self.create_experiment(experiment))
+    }
+
+    pub fn get_design(&self, experiment_id: &Uuid) -> Option<&ExperimentDesign> {
+        self.designs.get(experiment_id)
+    }
+
     pub fn get_experiment(&self, id: &Uuid) -> Option<&Experiment> {
         self.experiments.get(id)
     }
@@ -30,79 +86,295 @@ impl ExperimentEngine {
         self.experiments.values().collect()
     }
 
+    /// Register a layer, replacing one with the same namespace and name
+    /// only if it has no experiments allocated.
+    pub fn create_layer(&mut self, layer: ExperimentLayer) -> anyhow::Result<String> {
+        let key = layer.key();
+        if self
+            .layers
+            .get(&key)
+            .is_some_and(|existing| !existing.allocations.is_empty())
+        {
+            anyhow::bail!("layer {key} already has experiments allocated");
+        }
+        self.layers.insert(key.clone(), layer);
+        Ok(key)
+    }
+
+    pub fn get_layer(&self, namespace: &str, name: &str) -> Option<&ExperimentLayer> {
+        self.layers.get(&layer_key(namespace, name))
+    }
+
+    /// Reserve `share` of a layer's traffic for the experiment, creating the
+    /// layer if needed. Experiments in the same layer never share a user.
+    pub fn add_to_layer(
+        &mut self,
+        experiment_id: &Uuid,
+        namespace: &str,
+        name: &str,
+        share: f64,
+    ) -> anyhow::Result<()> {
+        if !self.experiments.contains_key(experiment_id) {
+            anyhow::bail!("experiment {experiment_id} not found");
+        }
+        if let Some(existing) = self.experiment_layers.get(experiment_id) {
+            anyhow::bail!("experiment {experiment_id} is already in layer {existing}");
+        }
+        let key = layer_key(namespace, name);
+        self.layers
+            .entry(key.clone())
+            .or_insert_with(|| ExperimentLayer::new(namespace, name))
+            .allocate(*experiment_id, share)?;
+        self.experiment_layers.insert(*experiment_id, key);
+        Ok(())
+    }
+
+    /// Return the experiment's buckets to its layer.
+    pub fn remove_from_layer(&mut self, experiment_id: &Uuid) -> bool {
+        match self.experiment_layers.remove(experiment_id) {
+            Some(key) => self
+                .layers
+                .get_mut(&key)
+                .is_some_and(|layer| layer.release(experiment_id)),
+            None => false,
+        }
+    }
+
+    pub fn set_targeting(&mut self, experiment_id: &Uuid, rule: TargetingRule) {
+        self.targeting.insert(*experiment_id, rule);
+    }
+
+    pub fn get_targeting(&self, experiment_id: &Uuid) -> Option<&TargetingRule> {
+        self.targeting.get(experiment_id)
+    }
+
+    /// Deterministic assignment without targeting context.
     pub fn assign_variant(&self, experiment_id: &Uuid, user_id: &str) -> Option<Uuid> {
+        self.assign(experiment_id, &AssignmentContext::new(user_id))
+            .map(|a| a.variant_id)
+    }
+
+    /// Decide which variant, if any, the user gets. The user must match the
+    /// experiment's targeting, hold a bucket of its layer (when layered) and
+    /// fall within `traffic_allocation`. The variant split uses a hash salted
+    /// with the experiment id, so it is independent of every other experiment.
+    /// Assignment alone is not recorded; call [`Self::expose`] when the user
+    /// actually sees the experience.
+    pub fn assign(&self, experiment_id: &Uuid, ctx: &AssignmentContext) -> Option<Assignment> {
         let experiment = self.experiments.get(experiment_id)?;
         if experiment.status != ExperimentStatus::Running {
             return None;
         }
-        // Deterministic assignment based on user_id hash
-        let hash = user_id
-            .bytes()
-            .fold(0u64, |acc, b| acc.wrapping_mul(31).wrapping_add(b as u64));
-        let normalized = (hash % 10000) as f64 / 10000.0;
+        if let Some(rule) = self.targeting.get(experiment_id) {
+            if !rule.matches(ctx) {
+                return None;
+            }
+        }
 
-        let mut cumulative = 0.0;
-        for variant in &experiment.variants {
-            cumulative += variant.weight;
-            if normalized < cumulative {
-                return Some(variant.id);
+        let eligible = buckets_for_share(experiment.traffic_allocation);
+        let layer = self.experiment_layers.get(experiment_id);
+        match layer.and_then(|key| self.layers.get(key)) {
+            Some(layer) => {
+                let allocation = layer.allocation(experiment_id)?;
+                let b = layer.bucket(&ctx.user_id);
+                // Scale the traffic allocation to the experiment's range.
+                let limit = allocation.start
+                    + (allocation.len() as u64 * eligible as u64 / BUCKETS as u64) as u32;
+                if !(allocation.start..limit).contains(&b) {
+                    return None;
+                }
+            }
+            None => {
+                if bucket(&format!("{experiment_id}.traffic"), &ctx.user_id) >= eligible {
+                    return None;
+                }
             }
         }
-        experiment.variants.last().map(|v| v.id)
+
+        let total_weight: f64 = experiment.variants.iter().map(|v| v.weight).sum();
+        if total_weight <= 0.0 {
+            return None;
+        }
+        let b = bucket(&format!("{experiment_id}.variant"), &ctx.user_id);
+        let point = b as f64 / BUCKETS as f64 * total_weight;
+        let mut cumulative = 0.0;
+        let variant = experiment
+            .variants
+            .iter()
+            .find(|v| {
+                cumulative += v.weight;
+                point < cumulative
+            })
+            .or(experiment.variants.last())?;
+
+        Some(Assignment {
+            experiment_id: *experiment_id,
+            variant_id: variant.id,
+            layer: layer.cloned(),
+            bucket: b,
+        })
     }
 
+    /// Assign the user and record the exposure. Only the first exposure per
+    /// user counts towards the variant's sample and emits an
+    /// `ExperimentExposure` event; later calls return the same assignment.
+    pub fn expose(&mut self, experiment_id: &Uuid, ctx: &AssignmentContext) -> Option<Assignment> {
+        let assignment = self.assign(experiment_id, ctx)?;
+        if self
+            .exposed_users
+            .insert((*experiment_id, ctx.user_id.clone()))
+        {
+            self.record_exposure(experiment_id, &assignment.variant_id);
+            self.exposures
+                .entry(*experiment_id)
+                .or_default()
+                .push(ExposureRecord {
+                    experiment_id: *experiment_id,
+                    variant_id: assignment.variant_id,
+                    user_id: ctx.user_id.clone(),
+                    layer: assignment.layer.clone(),
+                    exposed_at: Utc::now(),
+                });
+            self.event_sink.emit(make_event(
+                EventType::ExperimentExposure,
+                experiment_id.to_string(),
+                Some(ctx.user_id.clone()),
+                Some(assignment.variant_id.to_string()),
+            ));
+        }
+        Some(assignment)
+    }
+
+    /// First exposures recorded for the experiment, in order.
+    pub fn exposures(&self, experiment_id: &Uuid) -> &[ExposureRecord] {
+        self.exposures
+            .get(experiment_id)
+            .map_or(&[], |records| records.as_slice())
+    }
+
+    fn variant_mut(
+        &mut self,
+        experiment_id: &Uuid,
+        variant_id: &Uuid,
+    ) -> Option<&mut ExperimentVariant> {
+        self.experiments
+            .get_mut(experiment_id)?
+            .variants
+            .iter_mut()
+            .find(|v| v.id == *variant_id)
+    }
+
+    /// Count a user exposed to a variant.
+    pub fn record_exposure(&mut self, experiment_id: &Uuid, variant_id: &Uuid) {
+        if let Some(variant) = self.variant_mut(experiment_id, variant_id) {
+            variant.results.sample_size += 1;
+            Self::update_rate(variant);
+        }
+    }
+
+    /// Count a conversion from an exposed user. Callers that never record
+    /// exposures get `sample_size` raised to at least the conversion count.
     pub fn record_conversion(&mut self, experiment_id: &Uuid, variant_id: &Uuid, revenue: f64) {
-        if let Some(experiment) = self.experiments.get_mut(experiment_id) {
-            for variant in &mut experiment.variants {
-                if variant.id == *variant_id {
-                    variant.results.sample_size += 1;
-                    variant.results.conversions += 1;
-                    variant.results.revenue += revenue;
-                    if variant.results.sample_size > 0 {
-                        variant.results.conversion_rate =
-                            variant.results.conversions as f64 / variant.results.sample_size as f64;
-                    }
-                    break;
-                }
-            }
+        if let Some(variant) = self.variant_mut(experiment_id, variant_id) {
+            variant.results.conversions += 1;
+            variant.results.revenue += revenue;
+            variant.results.sample_size =
+                variant.results.sample_size.max(variant.results.conversions);
+            Self::update_rate(variant);
         }
     }
 
-    /// Check if experiment has reached statistical significance
-    pub fn check_significance(&self, experiment_id: &Uuid) -> Option<SignificanceResult> {
+    fn update_rate(variant: &mut ExperimentVariant) {
+        if variant.results.sample_size > 0 {
+            variant.results.conversion_rate =
+                variant.results.conversions as f64 / variant.results.sample_size as f64;
+        }
+    }
+
+    /// Compare each variant with control using an always-valid sequential
+    /// test, so the result may be checked at any time without inflating
+    /// false positives. Each comparison keeps its test between checks, with
+    /// alpha Bonferroni-split across the treatments. A sample ratio mismatch
+    /// against the variant weights blocks significance, since it means
+    /// assignment is broken.
+    pub fn check_significance(&mut self, experiment_id: &Uuid) -> Option<SignificanceResult> {
         let experiment = self.experiments.get(experiment_id)?;
         let control = experiment.variants.iter().find(|v| v.is_control)?;
-        let mut best_variant: Option<&ExperimentVariant> = None;
-        let mut best_lift = 0.0f64;
-
-        for variant in &experiment.variants {
-            if variant.is_control {
-                continue;
-            }
-            if control.results.conversion_rate > 0.0 {
-                let lift = (variant.results.conversion_rate - control.results.conversion_rate)
-                    / control.results.conversion_rate;
-                if lift > best_lift {
-                    best_lift = lift;
-                    best_variant = Some(variant);
+        let arms = experiment.variants.len();
+        let config = match self.designs.get(experiment_id) {
+            Some(design) => design.sequential_config_for(arms),
+            None => {
+                let default = SequentialConfig::default();
+                SequentialConfig {
+                    alpha: bonferroni_alpha(default.alpha, arms),
+                    ..default
                 }
             }
-        }
+        };
+        let control_stats =
+            SampleStats::from_conversions(control.results.conversions, control.results.sample_size);
 
+        let variants: Vec<VariantSignificance> = experiment
+            .variants
+            .iter()
+            .filter(|v| !v.is_control)
+            .map(|v| {
+                let stats =
+                    SampleStats::from_conversions(v.results.conversions, v.results.sample_size);
+                let test = self
+                    .sequential
+                    .entry((experiment.id, v.id))
+                    .or_insert_with(|| SequentialTest::new(config))
+                    .observe(&control_stats, &stats);
+                VariantSignificance {
+                    variant_id: v.id,
+                    lift: test.relative_lift.unwrap_or(0.0),
+                    p_value: test.p_value,
+                    confidence_interval: test.confidence_interval,
+                    probability_to_beat_control: probability_to_beat_control(
+                        (control.results.conversions, control.results.sample_size),
+                        (v.results.conversions, v.results.sample_size),
+                    ),
+                    is_significant: test.significant,
+                }
+            })
+            .collect();
+
+        let sample_ratio_mismatch = sample_ratio_mismatch(
+            &experiment
+                .variants
+                .iter()
+                .map(|v| v.results.sample_size)
+                .collect::<Vec<_>>(),
+            &experiment
+                .variants
+                .iter()
+                .map(|v| v.weight)
+                .collect::<Vec<_>>(),
+        );
+
+        let best = variants
+            .iter()
+            .filter(|v| v.lift > 0.0)
+            .max_by(|a, b| a.lift.total_cmp(&b.lift));
         let total_samples: u64 = experiment
             .variants
             .iter()
             .map(|v| v.results.sample_size)
             .sum();
-        let is_significant = total_samples >= experiment.min_sample_size;
 
         Some(SignificanceResult {
             experiment_id: experiment.id,
-            is_significant,
-            best_variant_id: best_variant.map(|v| v.id),
-            best_lift,
+            is_significant: best.is_some_and(|b| b.is_significant)
+                && !sample_ratio_mismatch.mismatch,
+            best_variant_id: best.map(|v| v.variant_id),
+            best_lift: best.map_or(0.0, |v| v.lift),
+            p_value: best.map_or(1.0, |v| v.p_value),
+            probability_to_beat_control: best.map_or(0.0, |v| v.probability_to_beat_control),
             total_samples,
             required_samples: experiment.min_sample_size,
+            sample_ratio_mismatch,
+            variants,
         })
     }
 }
@@ -113,8 +385,28 @@ pub struct SignificanceResult {
     pub is_significant: bool,
     pub best_variant_id: Option<Uuid>,
     pub best_lift: f64,
+    /// Always-valid p-value of the best variant against control.
+    pub p_value: f64,
+    pub probability_to_beat_control: f64,
     pub total_samples: u64,
+    /// Sample size from the power calculation; reaching it is not required
+    /// for significance but reports how far the experiment has progressed.
     pub required_samples: u64,
+    pub sample_ratio_mismatch: SrmResult,
+    pub variants: Vec<VariantSignificance>,
+}
+
+/// One treatment variant compared with control.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct VariantSignificance {
+    pub variant_id: Uuid,
+    /// Relative lift in conversion rate over control.
+    pub lift: f64,
+    pub p_value: f64,
+    /// Always-valid interval for the absolute difference in conversion rate.
+    pub confidence_interval: Option<(f64, f64)>,
+    pub probability_to_beat_control: f64,
+    pub is_significant: bool,
 }
 
 impl Default for ExperimentEngine {
@@ -122,3 +414,209 @@ impl Default for ExperimentEngine {
         Self::new()
     }
 }
+
+#[cfg(test)]
+#[allow(clippy::unwrap_used)]
+mod tests {
+    use super::*;
+    use crate::types::VariantResults;
+    use chrono::Utc;
+
+    fn experiment(weights: &[f64]) -> Experiment {
+        Experiment {
+            id: Uuid::new_v4(),
+            name: "CTA colour".into(),
+            description: String::new(),
+            status: ExperimentStatus::Running,
+            variants: weights
+                .iter()
+                .enumerate()
+                .map(|(i, w)| ExperimentVariant {
+                    id: Uuid::new_v4(),
+                    name: format!("v{i}"),
+                    weight: *w,
+                    is_control: i == 0,
+                    config: serde_json::Value::Null,
+                    results: VariantResults::default(),
+                })
+                .collect(),
+            traffic_allocation: 1.0,
+            metric: "conversion".into(),
+            min_sample_size: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    fn record(
+        engine: &mut ExperimentEngine,
+        id: Uuid,
+        variant: Uuid,
+        users: u64,
+        conversions: u64,
+    ) {
+        for _ in 0..users {
+            engine.record_exposure(&id, &variant);
+        }
+        for _ in 0..conversions {
+            engine.record_conversion(&id, &variant, 10.0);
+        }
+    }
+
+    #[test]
+    fn test_powered_experiment_and_sequential_significance() {
+        let mut engine = ExperimentEngine::new();
+        let exp = experiment(&[0.5, 0.5]);
+        let (control, treatment) = (exp.variants[0].id, exp.variants[1].id);
+        let id = engine
+            .create_powered_experiment(exp, ExperimentDesign::new(0.10, 0.20))
+            .unwrap();
+        let required = engine.get_experiment(&id).unwrap().min_sample_size;
+        assert!((7_000..8_000).contains(&required), "{required}");
+
+        record(&mut engine, id, control, 2000, 200);
+        record(&mut engine, id, treatment, 2000, 210);
+        let result = engine.check_significance(&id).unwrap();
+        assert!(!result.is_significant);
+        assert!((result.best_lift - 0.05).abs() < 1e-9);
+        assert_eq!(result.total_samples, 4000);
+        let variant = &engine.get_experiment(&id).unwrap().variants[1];
+        assert!((variant.results.conversion_rate - 0.105).abs() < 1e-9);
+
+        record(&mut engine, id, control, 3000, 300);
+        record(&mut engine, id, treatment, 3000, 440);
+        let result = engine.check_significance(&id).unwrap();
+        assert!(result.is_significant, "{result:?}");
+        assert_eq!(result.best_variant_id, Some(treatment));
+        assert!(result.p_value < 0.05);
+        assert!(result.probability_to_beat_control > 0.99);
+    }
+
+    #[test]
+    fn test_assignment_is_salted_per_experiment() {
+        let mut engine = ExperimentEngine::new();
+        let (a, b) = (experiment(&[0.5, 0.5]), experiment(&[0.5, 0.5]));
+        let (a_control, b_control) = (a.variants[0].id, b.variants[0].id);
+        let (a, b) = (engine.create_experiment(a), engine.create_experiment(b));
+
+        let mut same = 0;
+        let mut a_controls = 0;
+        for i in 0..10_000 {
+            let user = format!("user-{i}");
+            let va = engine.assign_variant(&a, &user).unwrap();
+            assert_eq!(engine.assign_variant(&a, &user), Some(va));
+            let vb = engine.assign_variant(&b, &user).unwrap();
+            same += usize::from((va == a_control) == (vb == b_control));
+            a_controls += usize::from(va == a_control);
+        }
+        assert!((4_700..5_300).contains(&a_controls), "{a_controls}");
+        assert!((4_700..5_300).contains(&same), "{same}");
+    }
+
+    #[test]
+    fn test_layers_targeting_and_exposure_logging() {
+        let sink = crate::event_bus::capture_sink();
+        let mut engine = ExperimentEngine::new().with_event_sink(sink.clone());
+        let (a, b) = (experiment(&[0.5, 0.5]), experiment(&[0.5, 0.5]));
+        let (a, b) = (engine.create_experiment(a), engine.create_experiment(b));
+        engine.add_to_layer(&a, "web", "checkout", 0.5).unwrap();
+        engine.add_to_layer(&b, "web", "checkout", 0.5).unwrap();
+        assert!(engine.add_to_layer(&a, "web", "other", 0.1).is_err());
+
+        let (mut in_a, mut in_b) = (0, 0);
+        for i in 0..4_000 {
+            let ctx = AssignmentContext::new(format!("user-{i}"));
+            let ra = engine.assign(&a, &ctx);
+            let rb = engine.assign(&b, &ctx);
+            assert!(ra.is_none() || rb.is_none(), "layer must be exclusive");
+            assert!(ra.is_some() || rb.is_some(), "layer is fully allocated");
+            in_a += usize::from(ra.is_some());
+            in_b += usize::from(rb.is_some());
+        }
+        assert!(in_a > 1_800 && in_b > 1_800, "{in_a} {in_b}");
+
+        engine.set_targeting(
+            &a,
+            TargetingRule {
+                include_segments: vec!["vip".into()],
+                ..Default::default()
+            },
+        );
+        let user = (0..)
+            .map(|i| format!("user-{i}"))
+            .find(|u| {
+                engine
+                    .get_layer("web", "checkout")
+                    .unwrap()
+                    .experiment_for(u)
+                    == Some(a)
+            })
+            .unwrap();
+        assert!(engine.expose(&a, &AssignmentContext::new(&user)).is_none());
+        let vip = AssignmentContext::new(&user).with_segments(["vip"]);
+        let first = engine.expose(&a, &vip).unwrap();
+        assert_eq!(first.layer.as_deref(), Some("web/checkout"));
+        assert_eq!(engine.expose(&a, &vip), Some(first.clone()));
+
+        assert_eq!(engine.exposures(&a).len(), 1);
+        assert_eq!(sink.count_type(EventType::ExperimentExposure), 1);
+        let samples: u64 = engine
+            .get_experiment(&a)
+            .unwrap()
+            .variants
+            .iter()
+            .map(|v| v.results.sample_size)
+            .sum();
+        assert_eq!(samples, 1);
+
+        assert!(engine.remove_from_layer(&a));
+        assert_eq!(
+            engine.get_layer("web", "checkout").unwrap().free_buckets(),
+            5_000
+        );
+        assert!(engine.assign(&a, &vip).is_some());
+    }
+
+    #[test]
+    fn test_sample_ratio_mismatch_blocks_significance() {
+        let mut engine = ExperimentEngine::new();
+        let exp = experiment(&[0.5, 0.5]);
+        let (control, treatment) = (exp.variants[0].id, exp.variants[1].id);
+        let id = engine.create_experiment(exp);
+        record(&mut engine, id, control, 4000, 400);
+        record(&mut engine, id, treatment, 6000, 1200);
+        let result = engine.check_significance(&id).unwrap();
+        assert!(result.variants[0].is_significant);
+        assert!(result.sample_ratio_mismatch.mismatch);
+        assert!(!result.is_significant);
+    }
+
+    #[test]
+    fn test_significance_keeps_one_sequential_test_per_comparison() {
+        let mut engine = ExperimentEngine::new();
+        let exp = experiment(&[0.34, 0.33, 0.33]);
+        let (control, treatment) = (exp.variants[0].id, exp.variants[1].id);
+        let id = engine.create_experiment(exp);
+        record(&mut engine, id, control, 1000, 100);
+        record(&mut engine, id, treatment, 1000, 110);
+        engine.check_significance(&id).unwrap();
+
+        // A later look with a different control rate reuses the τ² fixed
+        // at the first look, at the alpha split across two treatments.
+        record(&mut engine, id, control, 1000, 300);
+        engine.check_significance(&id).unwrap();
+        let test = &engine.sequential[&(id, treatment)];
+        assert_eq!(test.looks, 2);
+        assert!((test.config.mixing_variance.unwrap() - 1e-4).abs() < 1e-12);
+        assert_eq!(test.config.alpha, 0.025);
+
+        let mut engine = ExperimentEngine::new();
+        let exp = experiment(&[0.34, 0.33, 0.33]);
+        let treatment = exp.variants[1].id;
+        let id = engine
+            .create_powered_experiment(exp, ExperimentDesign::new(0.10, 0.20))
+            .unwrap();
+        engine.check_significance(&id).unwrap();
+        assert_eq!(engine.sequential[&(id, treatment)].config.alpha, 0.025);
+    }
+}
@@ -5,12 +5,14 @@ pub mod config;
 pub mod dsp;
 pub mod error;
 pub mod event_bus;
+pub mod event_envelope;
+pub mod experiment_assignment;
+pub mod experiment_stats;
 pub mod experimentation;
 pub mod inference;
 pub mod journey;
 pub mod loyalty;
 pub mod openrtb;
-pub mod templates;
 pub mod types;
 
 pub use config::AppConfig;
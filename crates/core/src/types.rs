@@ -92,6 +92,47 @@ pub struct AnalyticsEvent {
     pub inference_latency_us: Option<u64>,
     pub total_latency_us: Option<u64>,
     pub timestamp: DateTime<Utc>,
+    /// Attribution used by the analytics rollups.
+    #[serde(default)]
+    pub campaign_id: Option<String>,
+    #[serde(default)]
+    pub channel: Option<String>,
+    #[serde(default)]
+    pub creative_id: Option<String>,
+}
+
+/// Campaign and creative an event counts towards in the rollups. Producers
+/// that learn the outcome later (delivery webhooks, status callbacks) keep
+/// it per message so the follow-up events are attributed too.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attribution {
+    #[serde(default)]
+    pub campaign_id: Option<String>,
+    #[serde(default)]
+    pub creative_id: Option<String>,
+}
+
+impl AnalyticsEvent {
+    pub fn with_attribution(mut self, attribution: &Attribution) -> Self {
+        self.campaign_id.clone_from(&attribution.campaign_id);
+        self.creative_id.clone_from(&attribution.creative_id);
+        self
+    }
+
+    pub fn with_campaign(mut self, campaign_id: impl Into<String>) -> Self {
+        self.campaign_id = Some(campaign_id.into());
+        self
+    }
+
+    pub fn with_channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = Some(channel.into());
+        self
+    }
+
+    pub fn with_creative(mut self, creative_id: impl Into<String>) -> Self {
+        self.creative_id = Some(creative_id.into());
+        self
+    }
 }
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
@@ -141,6 +182,7 @@ pub enum EventType {
     CdpWebhook,
     // Experimentation events
     ExperimentAssignment,
+    ExperimentExposure,
     ExperimentConversion,
     // Template events
     TemplateRendered,
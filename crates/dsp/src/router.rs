@@ -106,13 +106,15 @@ impl DspRouter {
             .entry(platform)
             .and_modify(|record| {
                 record.wins += 1;
-                record.spend_usd += win_price;
+                record.spend += win_price;
             })
             .or_insert_with(|| DspSpendRecord {
                 platform,
+                campaign_id: None,
                 hour: Utc::now(),
                 impressions: 0,
-                spend_usd: win_price,
+                spend: win_price,
+                currency: "USD".to_string(),
                 wins: 1,
                 avg_win_price: win_price,
             });
@@ -86,6 +86,26 @@ impl FrequencyCapEngine {
         true
     }
 
+    /// Messages sent to a user on `channel` within the trailing `window`.
+    pub fn message_count(
+        &self,
+        user_id: &Uuid,
+        channel: &CappingChannel,
+        window: &CappingWindow,
+    ) -> u32 {
+        let window_start = Self::window_start(Utc::now(), window);
+        self.user_history
+            .get(user_id)
+            .map(|h| {
+                h.iter()
+                    .filter(|r| {
+                        r.timestamp >= window_start && Self::channel_matches(channel, &r.channel)
+                    })
+                    .count() as u32
+            })
+            .unwrap_or(0)
+    }
+
     pub fn record_send(&self, user_id: Uuid, channel: CappingChannel, campaign_id: Uuid) {
         self.user_history
             .entry(user_id)
@@ -129,6 +129,34 @@ impl SuppressionList {
         removed
     }
 
+    /// Remove only the entries for `identifier` on `channel` that were added
+    /// for `reason`, leaving e.g. bounce or regulatory suppressions intact.
+    ///
+    /// Returns the number of entries removed.
+    pub fn remove_reason(
+        &self,
+        identifier: &str,
+        channel: Option<&str>,
+        reason: &SuppressionReason,
+    ) -> usize {
+        let mut removed = 0usize;
+
+        if let Some(mut list) = self.entries.get_mut(identifier) {
+            let before = list.len();
+            list.retain(|e| !(e.channel.as_deref() == channel && &e.reason == reason));
+            removed = before - list.len();
+        }
+
+        // Clean up empty key.
+        self.entries
+            .remove_if(identifier, |_, list| list.is_empty());
+
+        if removed > 0 {
+            tracing::info!(identifier, removed, reason = ?reason, "suppression entries removed");
+        }
+        removed
+    }
+
     /// Check whether `identifier` is suppressed.
     ///
     /// * If `channel` is `None`, returns `true` if there is any active global entry.
@@ -374,6 +402,37 @@ mod tests {
         assert!(list.is_suppressed("u@x.com", None));
     }
 
+    #[test]
+    fn test_remove_reason_keeps_other_reasons() {
+        let list = SuppressionList::new();
+        list.add(
+            "+15550001111",
+            Some("sms".into()),
+            SuppressionReason::UserOptOut,
+            "t",
+            None,
+        );
+        list.add(
+            "+15550001111",
+            Some("sms".into()),
+            SuppressionReason::Regulatory,
+            "t",
+            None,
+        );
+
+        assert_eq!(
+            list.remove_reason("+15550001111", Some("sms"), &SuppressionReason::UserOptOut),
+            1
+        );
+        assert!(list.is_suppressed("+15550001111", Some("sms")));
+        assert_eq!(
+            list.remove_reason("+15550001111", Some("sms"), &SuppressionReason::Regulatory),
+            1
+        );
+        assert!(!list.is_suppressed("+15550001111", Some("sms")));
+        assert_eq!(list.count(), 0);
+    }
+
     #[test]
     fn test_bulk_add() {
         let list = SuppressionList::new();
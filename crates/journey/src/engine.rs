@@ -233,6 +233,54 @@ impl JourneyEngine {
         Ok(result)
     }
 
+    /// Routes an external event (e.g. a WhatsApp button reply) into journeys:
+    /// the user enters every active journey whose `EventBased` trigger names
+    /// `event_type` and whose filters all equal the matching `properties`.
+    /// The event properties seed the new instance's context so decision
+    /// steps can branch on them. Returns the created instance ids.
+    pub fn handle_event(
+        &self,
+        user_id: &str,
+        event_type: &str,
+        properties: &serde_json::Value,
+    ) -> Vec<Uuid> {
+        let matching: Vec<Uuid> = self
+            .journeys
+            .iter()
+            .filter(|j| j.status == JourneyStatus::Active)
+            .filter(|j| match &j.trigger {
+                JourneyTrigger::EventBased {
+                    event_type: trigger_type,
+                    filters,
+                } => {
+                    trigger_type == event_type
+                        && filters.as_object().is_none_or(|f| {
+                            f.iter()
+                                .all(|(key, value)| properties.get(key) == Some(value))
+                        })
+                }
+                _ => false,
+            })
+            .map(|j| j.id)
+            .collect();
+
+        let mut entered = Vec::new();
+        for journey_id in matching {
+            match self.enter_journey(&journey_id, user_id) {
+                Ok(instance_id) => {
+                    if let Some(mut instance) = self.instances.get_mut(&instance_id) {
+                        instance.context = properties.clone();
+                    }
+                    entered.push(instance_id);
+                }
+                Err(e) => {
+                    info!(journey_id = %journey_id, error = %e, "Event did not enter journey")
+                }
+            }
+        }
+        entered
+    }
+
     /// Returns campaign IDs that should be suppressed because the user is in
     /// an active journey that contains a `SuppressBid` action for those
     /// campaigns.
@@ -720,4 +768,28 @@ mod tests {
         assert!(suppressed.contains(&"camp-A".to_string()));
         assert!(!suppressed.contains(&"camp-C".to_string()));
     }
+
+    #[test]
+    fn test_handle_event_enters_matching_journeys() {
+        let engine = JourneyEngine::new();
+        let mut journey = make_simple_journey();
+        journey.trigger = JourneyTrigger::EventBased {
+            event_type: "whatsapp_reply".to_string(),
+            filters: serde_json::json!({"payload": "YES_OFFER"}),
+        };
+        let journey_id = journey.id;
+        engine.create_journey(journey).unwrap();
+
+        let props = serde_json::json!({"payload": "NO_THANKS"});
+        assert!(engine
+            .handle_event("user-1", "whatsapp_reply", &props)
+            .is_empty());
+
+        let props = serde_json::json!({"payload": "YES_OFFER", "title": "Yes please"});
+        let entered = engine.handle_event("user-1", "whatsapp_reply", &props);
+        assert_eq!(entered.len(), 1);
+        let instance = engine.instances.get(&entered[0]).unwrap();
+        assert_eq!(instance.journey_id, journey_id);
+        assert_eq!(instance.context["title"], "Yes please");
+    }
 }
@@ -60,6 +60,12 @@ pub async fn auth_middleware(req: Request, next: Next) -> Response {
         return next.run(req).await;
     }
 
+    require_bearer(req, next).await
+}
+
+/// Axum middleware layer that rejects every request without a valid bearer
+/// token, for routes outside `/management/` that still need a login.
+pub async fn require_bearer(req: Request, next: Next) -> Response {
     // Check Authorization header
     let auth_header = req
         .headers()
@@ -322,10 +322,17 @@ pub async fn get_experiment(
 pub async fn create_experiment(
     State(state): State<ManagementState>,
     Json(req): Json<serde_json::Value>,
-) -> (StatusCode, Json<serde_json::Value>) {
-    let experiment = state.store.create_experiment(req, "admin");
-    metrics::counter!("management.experiments.created").increment(1);
-    (StatusCode::CREATED, Json(experiment))
+) -> Result<(StatusCode, Json<serde_json::Value>), (StatusCode, Json<serde_json::Value>)> {
+    match state.store.create_experiment(req, "admin") {
+        Ok(experiment) => {
+            metrics::counter!("management.experiments.created").increment(1);
+            Ok((StatusCode::CREATED, Json(experiment)))
+        }
+        Err(msg) => Err((
+            StatusCode::BAD_REQUEST,
+            Json(serde_json::json!({"error": msg})),
+        )),
+    }
 }
 
 // ─── Platform: Tenants ──────────────────────────────────────────────────
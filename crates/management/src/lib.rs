@@ -15,7 +15,7 @@ pub mod workspace;
 
 pub use governance::UnifiedGovernanceGate;
 pub use handlers::ManagementState;
-pub use router::management_router;
+pub use router::{management_router, management_router_with_store};
 pub use store::ManagementStore;
 pub use workflows::{CampaignCalendar, WorkflowEngine};
 pub use workspace::{
@@ -9,7 +9,12 @@ use std::sync::Arc;
 /// Build the management router with all endpoints.
 /// Returns a Router that should be merged into the main app.
 pub fn management_router() -> Router {
-    let store = Arc::new(ManagementStore::new());
+    management_router_with_store(Arc::new(ManagementStore::new()))
+}
+
+/// Build the management router over an existing store, e.g. one sharing the
+/// bid path's offer-to-campaign index.
+pub fn management_router_with_store(store: Arc<ManagementStore>) -> Router {
     let state = ManagementState { store };
 
     Router::new()
@@ -4,8 +4,10 @@
 //! This provides the same API surface for development and testing.
 
 use crate::models::*;
+use campaign_core::experiment_stats::ExperimentDesign;
 use chrono::Utc;
 use dashmap::DashMap;
+use std::sync::Arc;
 use tracing::info;
 use uuid::Uuid;
 
@@ -37,6 +39,8 @@ pub struct ManagementStore {
     incidents: DashMap<Uuid, serde_json::Value>,
     sla_targets: DashMap<String, serde_json::Value>,
     backup_schedules: DashMap<Uuid, serde_json::Value>,
+    /// offer_id -> campaign_id for the bid path, kept in step with creatives.
+    offer_campaigns: Arc<DashMap<String, String>>,
 }
 
 impl ManagementStore {
@@ -64,6 +68,7 @@ impl ManagementStore {
             incidents: DashMap::new(),
             sla_targets: DashMap::new(),
             backup_schedules: DashMap::new(),
+            offer_campaigns: Arc::new(DashMap::new()),
         };
         store.seed_demo_data();
         store.seed_journey_data();
@@ -77,6 +82,38 @@ impl ManagementStore {
         store
     }
 
+    /// Share the offer-to-campaign index the bid processors attribute events
+    /// with, filling it from the creatives already in the store.
+    pub fn with_offer_campaigns(mut self, offer_campaigns: Arc<DashMap<String, String>>) -> Self {
+        self.offer_campaigns = offer_campaigns;
+        for creative in self.creatives.iter() {
+            self.index_offer(creative.value());
+        }
+        self
+    }
+
+    /// The offer a creative is bid as: `metadata.offer_id` when set, else the
+    /// creative id.
+    fn offer_id_of(creative: &Creative) -> String {
+        creative
+            .metadata
+            .get("offer_id")
+            .and_then(|v| v.as_str())
+            .map(str::to_string)
+            .unwrap_or_else(|| creative.id.to_string())
+    }
+
+    fn index_offer(&self, creative: &Creative) {
+        self.offer_campaigns.insert(
+            Self::offer_id_of(creative),
+            creative.campaign_id.to_string(),
+        );
+    }
+
+    fn unindex_offer(&self, creative: &Creative) {
+        self.offer_campaigns.remove(&Self::offer_id_of(creative));
+    }
+
     // ─── Campaigns ─────────────────────────────────────────────────────────
 
     pub fn list_campaigns(&self) -> Vec<Campaign> {
@@ -190,7 +227,9 @@ impl ManagementStore {
                 .map(|r| *r.key())
                 .collect();
             for cid in creative_ids {
-                self.creatives.remove(&cid);
+                if let Some((_, creative)) = self.creatives.remove(&cid) {
+                    self.unindex_offer(&creative);
+                }
             }
             self.log_audit(
                 user,
@@ -270,6 +309,7 @@ impl ManagementStore {
         };
         let id = creative.id;
         self.creatives.insert(id, creative.clone());
+        self.index_offer(&creative);
         self.log_audit(
             user,
             AuditAction::Create,
@@ -307,7 +347,9 @@ impl ManagementStore {
                 c.status = status;
             }
             if let Some(meta) = req.metadata {
+                self.unindex_offer(c);
                 c.metadata = meta;
+                self.index_offer(c);
             }
             c.updated_at = Utc::now();
             self.log_audit(
@@ -322,7 +364,13 @@ impl ManagementStore {
     }
 
     pub fn delete_creative(&self, id: Uuid, user: &str) -> bool {
-        let removed = self.creatives.remove(&id).is_some();
+        let removed = match self.creatives.remove(&id) {
+            Some((_, creative)) => {
+                self.unindex_offer(&creative);
+                true
+            }
+            None => false,
+        };
         if removed {
             self.log_audit(
                 user,
@@ -559,7 +607,28 @@ impl ManagementStore {
         self.experiments.get(&id).map(|r| r.value().clone())
     }
 
-    pub fn create_experiment(&self, mut req: serde_json::Value, user: &str) -> serde_json::Value {
+    /// Create an experiment. When the request carries `baseline_rate` and
+    /// `minimum_detectable_effect` (optionally `alpha` and `power`), the
+    /// power calculation fills in `sample_size_per_variant` and
+    /// `min_sample_size` across all variants.
+    pub fn create_experiment(
+        &self,
+        mut req: serde_json::Value,
+        user: &str,
+    ) -> Result<serde_json::Value, String> {
+        if req.get("baseline_rate").is_some() || req.get("minimum_detectable_effect").is_some() {
+            let design: ExperimentDesign = serde_json::from_value(req.clone())
+                .map_err(|e| format!("invalid experiment design: {e}"))?;
+            let variants = req
+                .get("variants")
+                .and_then(|v| v.as_array())
+                .map_or(2, |v| v.len().max(2));
+            let per_variant = design
+                .sample_size_per_variant(variants)
+                .map_err(|e| e.to_string())?;
+            req["sample_size_per_variant"] = serde_json::json!(per_variant);
+            req["min_sample_size"] = serde_json::json!(per_variant * variants as u64);
+        }
         let id = Uuid::new_v4();
         let now = Utc::now().to_rfc3339();
         req["id"] = serde_json::json!(id);
@@ -576,7 +645,7 @@ impl ManagementStore {
             &id.to_string(),
             serde_json::json!({}),
         );
-        req
+        Ok(req)
     }
 
     // ─── Demo Data ─────────────────────────────────────────────────────────
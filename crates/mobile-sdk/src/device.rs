@@ -1,7 +1,11 @@
 //! Device registration and push token management.
 
 use chrono::{DateTime, Utc};
+use dashmap::mapref::entry::Entry;
+use dashmap::DashMap;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use subtle::ConstantTimeEq;
 use uuid::Uuid;
 
 use crate::config::SdkPlatform;
@@ -14,6 +18,12 @@ pub struct DeviceRegistration {
     pub platform: SdkPlatform,
     pub push_token: Option<String>,
     pub push_enabled: bool,
+    /// Service that issued `push_token`; inferred from `platform` when unset.
+    #[serde(default)]
+    pub push_provider: Option<PushProvider>,
+    /// Why the last push token was invalidated by the provider, if it was.
+    #[serde(default)]
+    pub push_token_invalid_reason: Option<String>,
     pub app_version: String,
     pub sdk_version: String,
     pub os_version: String,
@@ -36,7 +46,7 @@ pub struct PushTokenUpdate {
     pub provider: PushProvider,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum PushProvider {
     Apns,
@@ -46,41 +56,179 @@ pub enum PushProvider {
     WebPush,
 }
 
+impl DeviceRegistration {
+    /// The provider to deliver pushes through, falling back to the
+    /// platform default when the SDK didn't report one.
+    pub fn effective_push_provider(&self) -> Option<PushProvider> {
+        self.push_provider.or(match self.platform {
+            SdkPlatform::Ios => Some(PushProvider::Apns),
+            SdkPlatform::Android => Some(PushProvider::Fcm),
+            SdkPlatform::ReactNative | SdkPlatform::Flutter | SdkPlatform::Web => None,
+        })
+    }
+}
+
+/// Why an SDK app may not register or update a device.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DeviceAccessError {
+    /// No device with this `device_id` is registered.
+    NotRegistered,
+    /// The device belongs to another app, or the device secret is missing
+    /// or wrong.
+    NotOwner,
+}
+
+/// The app a device was registered through, and the secret issued to that
+/// install.
+struct DeviceOwner {
+    api_key: String,
+    secret: String,
+}
+
+impl DeviceOwner {
+    fn permits(&self, api_key: &str, secret: Option<&str>) -> bool {
+        let Some(secret) = secret else {
+            return false;
+        };
+        let key_ok = self.api_key.as_bytes().ct_eq(api_key.as_bytes());
+        let secret_ok = self.secret.as_bytes().ct_eq(secret.as_bytes());
+        (key_ok & secret_ok).into()
+    }
+}
+
 pub struct DeviceRegistry {
-    devices: std::collections::HashMap<String, DeviceRegistration>,
+    devices: DashMap<String, DeviceRegistration>,
+    owners: DashMap<String, DeviceOwner>,
 }
 
 impl DeviceRegistry {
     pub fn new() -> Self {
         Self {
-            devices: std::collections::HashMap::new(),
+            devices: DashMap::new(),
+            owners: DashMap::new(),
         }
     }
 
-    pub fn register(&mut self, device: DeviceRegistration) {
+    pub fn register(&self, device: DeviceRegistration) {
         self.devices.insert(device.device_id.clone(), device);
     }
 
-    pub fn update_push_token(&mut self, update: &PushTokenUpdate) {
-        if let Some(device) = self.devices.get_mut(&update.device_id) {
-            device.push_token = Some(update.push_token.clone());
-            device.push_enabled = true;
-            device.updated_at = Utc::now();
+    /// Register a device on behalf of the SDK app `api_key`. A new
+    /// `device_id` is issued a secret, returned here, that the install must
+    /// present to re-register or update its token; an existing device is
+    /// only re-registered by the same app with that secret.
+    pub fn register_for_app(
+        &self,
+        api_key: &str,
+        device_secret: Option<&str>,
+        device: DeviceRegistration,
+    ) -> Result<String, DeviceAccessError> {
+        let secret = match self.owners.entry(device.device_id.clone()) {
+            Entry::Occupied(owner) => {
+                if !owner.get().permits(api_key, device_secret) {
+                    return Err(DeviceAccessError::NotOwner);
+                }
+                owner.get().secret.clone()
+            }
+            Entry::Vacant(slot) => {
+                // Registered outside the SDK routes, so no app can claim it.
+                if self.devices.contains_key(&device.device_id) {
+                    return Err(DeviceAccessError::NotOwner);
+                }
+                let secret = generate_device_secret();
+                slot.insert(DeviceOwner {
+                    api_key: api_key.to_string(),
+                    secret: secret.clone(),
+                });
+                secret
+            }
+        };
+        self.register(device);
+        Ok(secret)
+    }
+
+    /// Store a refreshed push token sent by the SDK app `api_key`, if that
+    /// app registered the device and `device_secret` is the one it was
+    /// issued.
+    pub fn update_push_token_for_app(
+        &self,
+        api_key: &str,
+        device_secret: Option<&str>,
+        update: &PushTokenUpdate,
+    ) -> Result<(), DeviceAccessError> {
+        match self.owners.get(&update.device_id) {
+            Some(owner) if owner.permits(api_key, device_secret) => {}
+            Some(_) => return Err(DeviceAccessError::NotOwner),
+            None if self.devices.contains_key(&update.device_id) => {
+                return Err(DeviceAccessError::NotOwner)
+            }
+            None => return Err(DeviceAccessError::NotRegistered),
+        }
+        if self.update_push_token(update) {
+            Ok(())
+        } else {
+            Err(DeviceAccessError::NotRegistered)
+        }
+    }
+
+    /// Store a refreshed push token. Returns false for an unknown device.
+    pub fn update_push_token(&self, update: &PushTokenUpdate) -> bool {
+        let Some(mut device) = self.devices.get_mut(&update.device_id) else {
+            return false;
+        };
+        device.push_token = Some(update.push_token.clone());
+        device.push_enabled = true;
+        device.push_provider = Some(update.provider);
+        device.push_token_invalid_reason = None;
+        device.updated_at = Utc::now();
+        true
+    }
+
+    /// Drop a push token the provider rejected as expired or unknown. Only
+    /// clears it if it is still the device's current token, so a fresh
+    /// registration racing with the failed send is kept. Returns true if
+    /// the token was cleared.
+    pub fn invalidate_push_token(&self, device_id: &str, token: &str, reason: &str) -> bool {
+        match self.devices.get_mut(device_id) {
+            Some(mut device) if device.push_token.as_deref() == Some(token) => {
+                tracing::info!(device_id = %device_id, reason = %reason, "Push token invalidated");
+                device.push_token = None;
+                device.push_enabled = false;
+                device.push_token_invalid_reason = Some(reason.to_string());
+                device.updated_at = Utc::now();
+                true
+            }
+            _ => false,
         }
     }
 
-    pub fn get_device(&self, device_id: &str) -> Option<&DeviceRegistration> {
-        self.devices.get(device_id)
+    pub fn get_device(&self, device_id: &str) -> Option<DeviceRegistration> {
+        self.devices.get(device_id).map(|d| d.clone())
     }
 
-    pub fn get_user_devices(&self, user_id: &Uuid) -> Vec<&DeviceRegistration> {
+    pub fn get_user_devices(&self, user_id: &Uuid) -> Vec<DeviceRegistration> {
         self.devices
-            .values()
+            .iter()
             .filter(|d| d.user_id.as_ref() == Some(user_id))
+            .map(|d| d.clone())
+            .collect()
+    }
+
+    /// Devices of a user that currently accept pushes.
+    pub fn get_user_push_targets(&self, user_id: &Uuid) -> Vec<DeviceRegistration> {
+        self.get_user_devices(user_id)
+            .into_iter()
+            .filter(|d| d.push_enabled && d.push_token.is_some())
             .collect()
     }
 }
 
+/// A random 256-bit device secret, hex encoded.
+fn generate_device_secret() -> String {
+    let bytes: [u8; 32] = rand::thread_rng().gen();
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
 impl Default for DeviceRegistry {
     fn default() -> Self {
         Self::new()
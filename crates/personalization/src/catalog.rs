@@ -1,10 +1,21 @@
 //! Product/content catalog management — store items that can be referenced
 //! in messages and recommendations.
+//!
+//! Items arrive one at a time through [`CatalogEngine::add_item`] or in bulk
+//! from a feed ([`CatalogEngine::ingest_feed`]). Fields marked `filterable`
+//! get an exact-match secondary index and `price` a range index, both used
+//! by [`CatalogEngine::query`]. Out-of-stock items stay in the catalog but
+//! are skipped by queries, recommendations and template lookups.
 
+use crate::catalog_feed::{self, FeedFormat};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
 use uuid::Uuid;
 
+/// Field holding the item price, range-indexed in minor units.
+pub const PRICE_FIELD: &str = "price";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Catalog {
     pub id: Uuid,
@@ -21,6 +32,9 @@ pub struct CatalogField {
     pub name: String,
     pub field_type: CatalogFieldType,
     pub required: bool,
+    /// Maintain a secondary index for exact-match filters on this field.
+    #[serde(default)]
+    pub filterable: bool,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -43,9 +57,84 @@ pub struct CatalogItem {
     pub updated_at: DateTime<Utc>,
 }
 
+impl CatalogItem {
+    /// Whether the item can be recommended or sent. An item is unavailable
+    /// when `available` or `in_stock` is `false`, `availability` is
+    /// `out of stock` / `discontinued`, or `inventory` is at or below zero;
+    /// items without any of these fields are available.
+    pub fn is_available(&self) -> bool {
+        let flag = |key: &str| self.data.get(key).and_then(|v| v.as_bool());
+        if flag("available") == Some(false) || flag("in_stock") == Some(false) {
+            return false;
+        }
+        if let Some(availability) = self.data.get("availability").and_then(|v| v.as_str()) {
+            let availability = availability.trim().to_ascii_lowercase().replace('_', " ");
+            if availability == "out of stock" || availability == "discontinued" {
+                return false;
+            }
+        }
+        !matches!(
+            self.data.get("inventory").and_then(|v| v.as_f64()),
+            Some(n) if n <= 0.0
+        )
+    }
+
+    fn price_minor_units(&self) -> Option<i64> {
+        self.data
+            .get(PRICE_FIELD)
+            .and_then(|v| v.as_f64())
+            .map(to_minor_units)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IngestMode {
+    /// The feed is the whole catalog: items missing from it are deleted.
+    Full,
+    /// The feed only carries changes; rows with `_delete` set are deleted.
+    Delta,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RejectedRow {
+    pub row: usize,
+    pub item_id: Option<String>,
+    pub error: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct IngestReport {
+    pub received: usize,
+    pub created: usize,
+    pub updated: usize,
+    pub unchanged: usize,
+    pub deleted: usize,
+    pub rejected: Vec<RejectedRow>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CatalogQuery {
+    /// Field -> value, matched case-insensitively. Array fields match when
+    /// any element does.
+    #[serde(default)]
+    pub filters: HashMap<String, String>,
+    pub min_price: Option<f64>,
+    pub max_price: Option<f64>,
+    #[serde(default)]
+    pub include_unavailable: bool,
+    pub limit: Option<usize>,
+}
+
 pub struct CatalogEngine {
     catalogs: dashmap::DashMap<Uuid, Catalog>,
     items: dashmap::DashMap<(Uuid, String), CatalogItem>,
+    /// (catalog, field, normalized value) -> item ids, for filterable fields.
+    field_index: dashmap::DashMap<(Uuid, String, String), HashSet<String>>,
+    /// catalog -> price in minor units -> item ids.
+    price_index: dashmap::DashMap<Uuid, BTreeMap<i64, HashSet<String>>>,
+    /// item id -> catalogs in which it is currently unavailable.
+    unavailable: dashmap::DashMap<String, HashSet<Uuid>>,
 }
 
 impl CatalogEngine {
@@ -53,6 +142,9 @@ impl CatalogEngine {
         Self {
             catalogs: dashmap::DashMap::new(),
             items: dashmap::DashMap::new(),
+            field_index: dashmap::DashMap::new(),
+            price_index: dashmap::DashMap::new(),
+            unavailable: dashmap::DashMap::new(),
         }
     }
 
@@ -61,8 +153,14 @@ impl CatalogEngine {
     }
 
     pub fn add_item(&self, item: CatalogItem) {
-        let key = (item.catalog_id, item.id.clone());
-        self.items.insert(key, item);
+        let filterable = self.filterable_fields(&item.catalog_id);
+        self.store(item, &filterable);
+    }
+
+    pub fn remove_item(&self, catalog_id: &Uuid, item_id: &str) -> Option<CatalogItem> {
+        let (_, item) = self.items.remove(&(*catalog_id, item_id.to_string()))?;
+        self.unindex(&item, &self.filterable_fields(catalog_id));
+        Some(item)
     }
 
     pub fn get_item(&self, catalog_id: &Uuid, item_id: &str) -> Option<CatalogItem> {
@@ -71,6 +169,12 @@ impl CatalogEngine {
             .map(|i| i.clone())
     }
 
+    /// Like [`get_item`](Self::get_item), but `None` for out-of-stock items.
+    pub fn get_available_item(&self, catalog_id: &Uuid, item_id: &str) -> Option<CatalogItem> {
+        self.get_item(catalog_id, item_id)
+            .filter(|item| item.is_available())
+    }
+
     #[allow(clippy::unnecessary_map_or)]
     pub fn search_items(&self, catalog_id: &Uuid, field: &str, value: &str) -> Vec<CatalogItem> {
         self.items
@@ -88,6 +192,217 @@ impl CatalogEngine {
             .collect()
     }
 
+    /// Filter a catalog using the secondary and price indexes. Filters on
+    /// fields that are not indexed are applied to the indexed candidates,
+    /// or to a full scan when nothing narrowed the search. Results are
+    /// ordered by price when a price bound is given, otherwise by id.
+    pub fn query(&self, catalog_id: &Uuid, query: &CatalogQuery) -> Vec<CatalogItem> {
+        let filterable = self.filterable_fields(catalog_id);
+        let priced = query.min_price.is_some() || query.max_price.is_some();
+
+        let mut candidates: Option<HashSet<String>> = None;
+        let mut narrow = |ids: HashSet<String>| {
+            candidates = Some(match candidates.take() {
+                Some(current) => current.intersection(&ids).cloned().collect(),
+                None => ids,
+            });
+        };
+        if priced {
+            let min = query.min_price.map(to_minor_units).unwrap_or(i64::MIN);
+            let max = query.max_price.map(to_minor_units).unwrap_or(i64::MAX);
+            let ids = self
+                .price_index
+                .get(catalog_id)
+                .map(|prices| {
+                    prices
+                        .range(min..=max)
+                        .flat_map(|(_, ids)| ids.iter().cloned())
+                        .collect()
+                })
+                .unwrap_or_default();
+            narrow(ids);
+        }
+        let mut unindexed = Vec::new();
+        for (field, value) in &query.filters {
+            if filterable.contains(field) {
+                let key = (*catalog_id, field.clone(), normalize(value));
+                narrow(
+                    self.field_index
+                        .get(&key)
+                        .map(|ids| ids.clone())
+                        .unwrap_or_default(),
+                );
+            } else {
+                unindexed.push((field, normalize(value)));
+            }
+        }
+
+        let items: Vec<CatalogItem> = match candidates {
+            Some(ids) => ids
+                .iter()
+                .filter_map(|id| self.get_item(catalog_id, id))
+                .collect(),
+            None => self
+                .items
+                .iter()
+                .filter(|entry| entry.value().catalog_id == *catalog_id)
+                .map(|entry| entry.value().clone())
+                .collect(),
+        };
+        let mut items: Vec<CatalogItem> = items
+            .into_iter()
+            .filter(|item| query.include_unavailable || item.is_available())
+            .filter(|item| {
+                unindexed.iter().all(|(field, value)| {
+                    item.data
+                        .get(field.as_str())
+                        .is_some_and(|v| index_keys(v).contains(value))
+                })
+            })
+            .collect();
+
+        if priced {
+            items.sort_by_key(|item| (item.price_minor_units(), item.id.clone()));
+        } else {
+            items.sort_by(|a, b| a.id.cmp(&b.id));
+        }
+        if let Some(limit) = query.limit {
+            items.truncate(limit);
+        }
+        items
+    }
+
+    /// Load a CSV, JSON Lines or Google Merchant XML feed into a catalog.
+    /// Rows are validated against the catalog's fields; rejected rows are
+    /// reported and leave any existing item untouched. Only an unknown
+    /// catalog or a structurally broken feed is an error.
+    pub fn ingest_feed(
+        &self,
+        catalog_id: &Uuid,
+        feed: &str,
+        format: FeedFormat,
+        mode: IngestMode,
+    ) -> anyhow::Result<IngestReport> {
+        let fields = self
+            .catalogs
+            .get(catalog_id)
+            .map(|c| c.fields.clone())
+            .ok_or_else(|| anyhow::anyhow!("Catalog {} not found", catalog_id))?;
+        let records = catalog_feed::parse_feed(feed, format).map_err(|e| anyhow::anyhow!(e))?;
+        let filterable = self.filterable_fields(catalog_id);
+
+        let mut report = IngestReport {
+            received: records.len(),
+            ..Default::default()
+        };
+        let mut seen: HashSet<String> = HashSet::new();
+        let now = Utc::now();
+        for record in &records {
+            let raw_id = record
+                .values
+                .get("id")
+                .and_then(|v| v.as_str())
+                .map(|s| s.trim().to_string());
+            if catalog_feed::is_delete(record) {
+                if let Some(id) = &raw_id {
+                    if self.remove_item(catalog_id, id).is_some() {
+                        report.deleted += 1;
+                    }
+                }
+                continue;
+            }
+            match catalog_feed::validate_record(&fields, record) {
+                Ok((id, data)) => {
+                    seen.insert(id.clone());
+                    let existing = self.get_item(catalog_id, &id);
+                    match existing {
+                        Some(existing) if existing.data == data => report.unchanged += 1,
+                        existing => {
+                            if existing.is_some() {
+                                report.updated += 1;
+                            } else {
+                                report.created += 1;
+                            }
+                            let item = CatalogItem {
+                                id,
+                                catalog_id: *catalog_id,
+                                data,
+                                created_at: existing.map(|e| e.created_at).unwrap_or(now),
+                                updated_at: now,
+                            };
+                            self.store(item, &filterable);
+                        }
+                    }
+                }
+                Err(error) => {
+                    // A rejected row must not make a full feed delete the
+                    // item it was meant to update.
+                    if let Some(id) = &raw_id {
+                        seen.insert(id.clone());
+                    }
+                    report.rejected.push(RejectedRow {
+                        row: record.row,
+                        item_id: raw_id,
+                        error,
+                    });
+                }
+            }
+        }
+
+        if mode == IngestMode::Full {
+            let stale: Vec<String> = self
+                .items
+                .iter()
+                .filter(|e| e.value().catalog_id == *catalog_id && !seen.contains(&e.value().id))
+                .map(|e| e.value().id.clone())
+                .collect();
+            for id in stale {
+                if self.remove_item(catalog_id, &id).is_some() {
+                    report.deleted += 1;
+                }
+            }
+        }
+
+        let count = self.catalog_item_count(catalog_id) as u64;
+        if let Some(mut catalog) = self.catalogs.get_mut(catalog_id) {
+            catalog.item_count = count;
+            catalog.updated_at = now;
+        }
+        tracing::info!(
+            catalog_id = %catalog_id,
+            received = report.received,
+            created = report.created,
+            updated = report.updated,
+            deleted = report.deleted,
+            rejected = report.rejected.len(),
+            "Catalog feed ingested"
+        );
+        Ok(report)
+    }
+
+    pub fn get_catalog(&self, catalog_id: &Uuid) -> Option<Catalog> {
+        self.catalogs.get(catalog_id).map(|c| c.value().clone())
+    }
+
+    pub fn find_catalog(&self, name: &str) -> Option<Catalog> {
+        self.catalogs
+            .iter()
+            .find(|c| c.name == name)
+            .map(|c| c.value().clone())
+    }
+
+    /// True when the item exists in the catalog and is available.
+    pub fn is_available(&self, catalog_id: &Uuid, item_id: &str) -> bool {
+        self.items
+            .get(&(*catalog_id, item_id.to_string()))
+            .is_some_and(|i| i.is_available())
+    }
+
+    /// True when the item is out of stock in any catalog.
+    pub fn is_out_of_stock(&self, item_id: &str) -> bool {
+        self.unavailable.contains_key(item_id)
+    }
+
     pub fn list_catalogs(&self) -> Vec<Catalog> {
         self.catalogs.iter().map(|c| c.value().clone()).collect()
     }
@@ -98,6 +413,78 @@ impl CatalogEngine {
             .filter(|entry| &entry.value().catalog_id == catalog_id)
             .count()
     }
+
+    fn filterable_fields(&self, catalog_id: &Uuid) -> HashSet<String> {
+        self.catalogs
+            .get(catalog_id)
+            .map(|c| {
+                c.fields
+                    .iter()
+                    .filter(|f| f.filterable)
+                    .map(|f| f.name.clone())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    fn store(&self, item: CatalogItem, filterable: &HashSet<String>) {
+        let key = (item.catalog_id, item.id.clone());
+        if let Some(old) = self.items.insert(key, item.clone()) {
+            self.unindex(&old, filterable);
+        }
+        self.index(&item, filterable);
+    }
+
+    fn index(&self, item: &CatalogItem, filterable: &HashSet<String>) {
+        for field in filterable {
+            for value in item.data.get(field).map(index_keys).unwrap_or_default() {
+                self.field_index
+                    .entry((item.catalog_id, field.clone(), value))
+                    .or_default()
+                    .insert(item.id.clone());
+            }
+        }
+        if let Some(price) = item.price_minor_units() {
+            self.price_index
+                .entry(item.catalog_id)
+                .or_default()
+                .entry(price)
+                .or_default()
+                .insert(item.id.clone());
+        }
+        if !item.is_available() {
+            self.unavailable
+                .entry(item.id.clone())
+                .or_default()
+                .insert(item.catalog_id);
+        }
+    }
+
+    fn unindex(&self, item: &CatalogItem, filterable: &HashSet<String>) {
+        for field in filterable {
+            for value in item.data.get(field).map(index_keys).unwrap_or_default() {
+                let key = (item.catalog_id, field.clone(), value);
+                self.field_index.remove_if_mut(&key, |_, ids| {
+                    ids.remove(&item.id);
+                    ids.is_empty()
+                });
+            }
+        }
+        if let Some(price) = item.price_minor_units() {
+            if let Some(mut prices) = self.price_index.get_mut(&item.catalog_id) {
+                if let Some(ids) = prices.get_mut(&price) {
+                    ids.remove(&item.id);
+                    if ids.is_empty() {
+                        prices.remove(&price);
+                    }
+                }
+            }
+        }
+        self.unavailable.remove_if_mut(&item.id, |_, catalogs| {
+            catalogs.remove(&item.catalog_id);
+            catalogs.is_empty()
+        });
+    }
 }
 
 impl Default for CatalogEngine {
@@ -105,3 +492,154 @@ impl Default for CatalogEngine {
         Self::new()
     }
 }
+
+fn to_minor_units(price: f64) -> i64 {
+    (price * 100.0).round() as i64
+}
+
+fn normalize(value: &str) -> String {
+    value.trim().to_lowercase()
+}
+
+/// Normalized values an item field is indexed and matched under.
+fn index_keys(value: &serde_json::Value) -> Vec<String> {
+    match value {
+        serde_json::Value::String(s) => vec![normalize(s)],
+        serde_json::Value::Array(items) => items.iter().flat_map(index_keys).collect(),
+        serde_json::Value::Null | serde_json::Value::Object(_) => Vec::new(),
+        other => vec![other.to_string()],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn engine() -> (CatalogEngine, Uuid) {
+        let engine = CatalogEngine::new();
+        let id = Uuid::new_v4();
+        let field = |name: &str, field_type, required, filterable| CatalogField {
+            name: name.to_string(),
+            field_type,
+            required,
+            filterable,
+        };
+        engine.create_catalog(Catalog {
+            id,
+            name: "products".to_string(),
+            description: None,
+            fields: vec![
+                field("title", CatalogFieldType::String, true, false),
+                field("price", CatalogFieldType::Number, true, false),
+                field("brand", CatalogFieldType::String, false, true),
+                field("tags", CatalogFieldType::Array, false, true),
+                field("in_stock", CatalogFieldType::Boolean, false, false),
+            ],
+            item_count: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        });
+        (engine, id)
+    }
+
+    fn ids(items: Vec<CatalogItem>) -> Vec<String> {
+        items.into_iter().map(|i| i.id).collect()
+    }
+
+    #[test]
+    fn test_full_and_delta_ingestion() {
+        let (engine, catalog) = engine();
+        let feed = "id,title,price,brand,tags,in_stock\n\
+                    a,Runner,89.00,Acme,shoes|running,true\n\
+                    b,Trail,120,Acme,shoes,false\n\
+                    c,Sock,not-a-price,Knit,,\n\
+                    d,Cap,25,Hatco,,yes\n";
+        let report = engine
+            .ingest_feed(&catalog, feed, FeedFormat::Csv, IngestMode::Full)
+            .unwrap();
+        assert_eq!((report.received, report.created), (4, 3));
+        assert_eq!(report.rejected.len(), 1);
+        assert_eq!(report.rejected[0].row, 3);
+        assert_eq!(report.rejected[0].item_id.as_deref(), Some("c"));
+        assert_eq!(engine.list_catalogs()[0].item_count, 3);
+        assert!(engine.is_out_of_stock("b"));
+
+        let delta = "{\"id\": \"a\", \"title\": \"Runner\", \"price\": 89, \"brand\": \"Acme\", \"tags\": [\"shoes\", \"running\"], \"in_stock\": true}\n\
+                     {\"id\": \"b\", \"title\": \"Trail\", \"price\": 99, \"brand\": \"Acme\", \"in_stock\": true}\n\
+                     {\"id\": \"d\", \"_delete\": true}\n";
+        let report = engine
+            .ingest_feed(&catalog, delta, FeedFormat::JsonLines, IngestMode::Delta)
+            .unwrap();
+        assert_eq!(
+            (report.unchanged, report.updated, report.deleted),
+            (1, 1, 1)
+        );
+        assert!(!engine.is_out_of_stock("b"));
+        assert_eq!(engine.get_item(&catalog, "b").unwrap().data["price"], 99.0);
+
+        // A full feed drops everything it does not mention.
+        let report = engine
+            .ingest_feed(
+                &catalog,
+                "id,title,price\na,Runner,89\n",
+                FeedFormat::Csv,
+                IngestMode::Full,
+            )
+            .unwrap();
+        assert_eq!(report.deleted, 1);
+        assert_eq!(
+            ids(engine.query(&catalog, &CatalogQuery::default())),
+            vec!["a"]
+        );
+
+        assert!(engine
+            .ingest_feed(&Uuid::new_v4(), "", FeedFormat::Csv, IngestMode::Full)
+            .is_err());
+    }
+
+    #[test]
+    fn test_indexed_and_range_queries() {
+        let (engine, catalog) = engine();
+        let feed = "id,title,price,brand,tags,in_stock\n\
+                    a,Runner,89.00,Acme,shoes|running,true\n\
+                    b,Trail,120,Acme,shoes,false\n\
+                    c,Cap,25,Hatco,hats,true\n\
+                    d,Racer,60,ACME,shoes|running,true\n";
+        engine
+            .ingest_feed(&catalog, feed, FeedFormat::Csv, IngestMode::Full)
+            .unwrap();
+
+        let mut query = CatalogQuery {
+            filters: HashMap::from([("brand".to_string(), "acme".to_string())]),
+            ..Default::default()
+        };
+        assert_eq!(ids(engine.query(&catalog, &query)), vec!["a", "d"]);
+        query.include_unavailable = true;
+        assert_eq!(ids(engine.query(&catalog, &query)), vec!["a", "b", "d"]);
+
+        let query = CatalogQuery {
+            filters: HashMap::from([
+                ("tags".to_string(), "running".to_string()),
+                ("title".to_string(), "Racer".to_string()),
+            ]),
+            ..Default::default()
+        };
+        assert_eq!(ids(engine.query(&catalog, &query)), vec!["d"]);
+
+        let query = CatalogQuery {
+            min_price: Some(25.0),
+            max_price: Some(89.0),
+            limit: Some(2),
+            ..Default::default()
+        };
+        assert_eq!(ids(engine.query(&catalog, &query)), vec!["c", "d"]);
+
+        // Re-pricing moves the item in the range index.
+        let mut item = engine.get_item(&catalog, "c").unwrap();
+        item.data
+            .insert("price".to_string(), serde_json::json!(150));
+        engine.add_item(item);
+        assert_eq!(ids(engine.query(&catalog, &query)), vec!["d", "a"]);
+        assert!(engine.get_available_item(&catalog, "b").is_none());
+    }
+}
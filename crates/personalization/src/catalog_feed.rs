@@ -0,0 +1,454 @@
+//! Catalog feed parsing and validation — CSV, JSON Lines and Google
+//! Merchant (RSS/Atom) XML feeds turned into typed catalog records.
+//!
+//! Parsing yields one raw record per row. Validation then coerces each
+//! value to the catalog's declared [`CatalogFieldType`] and checks required
+//! fields, so a bad row is rejected on its own instead of failing the feed.
+
+use crate::catalog::{CatalogField, CatalogFieldType};
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeedFormat {
+    Csv,
+    JsonLines,
+    GoogleMerchantXml,
+}
+
+/// Column that marks a row as a deletion in delta feeds.
+pub const DELETE_COLUMN: &str = "_delete";
+
+/// One parsed row with its 1-based position in the feed.
+#[derive(Debug, Clone)]
+pub struct FeedRecord {
+    pub row: usize,
+    pub values: Map<String, Value>,
+}
+
+/// Parse a whole feed. Only structural problems (an unterminated quote,
+/// a line that is not a JSON object) fail the parse; field-level problems
+/// are left to [`validate_record`].
+pub fn parse_feed(text: &str, format: FeedFormat) -> Result<Vec<FeedRecord>, String> {
+    match format {
+        FeedFormat::Csv => parse_csv(text),
+        FeedFormat::JsonLines => parse_json_lines(text),
+        FeedFormat::GoogleMerchantXml => parse_merchant_xml(text),
+    }
+}
+
+/// RFC 4180 CSV with a header row. Quoted fields may contain commas,
+/// doubled quotes and newlines; empty cells are treated as absent.
+fn parse_csv(text: &str) -> Result<Vec<FeedRecord>, String> {
+    let mut rows: Vec<(usize, Vec<String>)> = Vec::new();
+    let mut row = Vec::new();
+    let mut field = String::new();
+    let mut in_quotes = false;
+    let mut line = 1;
+    let mut row_start = 1;
+    let mut chars = text.trim_start_matches('\u{feff}').chars().peekable();
+    while let Some(c) = chars.next() {
+        if in_quotes {
+            match c {
+                '"' if chars.peek() == Some(&'"') => {
+                    chars.next();
+                    field.push('"');
+                }
+                '"' => in_quotes = false,
+                '\n' => {
+                    line += 1;
+                    field.push(c);
+                }
+                _ => field.push(c),
+            }
+            continue;
+        }
+        match c {
+            '"' if field.is_empty() => in_quotes = true,
+            ',' => row.push(std::mem::take(&mut field)),
+            '\r' => {}
+            '\n' => {
+                row.push(std::mem::take(&mut field));
+                rows.push((row_start, std::mem::take(&mut row)));
+                line += 1;
+                row_start = line;
+            }
+            _ => field.push(c),
+        }
+    }
+    if in_quotes {
+        return Err(format!("line {}: unterminated quoted field", row_start));
+    }
+    if !field.is_empty() || !row.is_empty() {
+        row.push(field);
+        rows.push((row_start, row));
+    }
+    rows.retain(|(_, cells)| cells.iter().any(|c| !c.trim().is_empty()));
+
+    let mut rows = rows.into_iter();
+    let Some((_, header)) = rows.next() else {
+        return Ok(Vec::new());
+    };
+    let header: Vec<String> = header.iter().map(|h| h.trim().to_string()).collect();
+    rows.enumerate()
+        .map(|(i, (line, cells))| {
+            if cells.len() > header.len() {
+                return Err(format!(
+                    "line {}: {} cells but the header has {} columns",
+                    line,
+                    cells.len(),
+                    header.len()
+                ));
+            }
+            let values = header
+                .iter()
+                .zip(cells)
+                .filter(|(_, cell)| !cell.is_empty())
+                .map(|(name, cell)| (name.clone(), Value::String(cell)))
+                .collect();
+            Ok(FeedRecord { row: i + 1, values })
+        })
+        .collect()
+}
+
+fn parse_json_lines(text: &str) -> Result<Vec<FeedRecord>, String> {
+    text.lines()
+        .enumerate()
+        .filter(|(_, l)| !l.trim().is_empty())
+        .enumerate()
+        .map(|(row, (line, l))| match serde_json::from_str(l) {
+            Ok(Value::Object(values)) => Ok(FeedRecord {
+                row: row + 1,
+                values,
+            }),
+            Ok(_) => Err(format!("line {}: expected a JSON object", line + 1)),
+            Err(e) => Err(format!("line {}: {}", line + 1, e)),
+        })
+        .collect()
+}
+
+/// Google Merchant feeds: every `<item>` (RSS) or `<entry>` (Atom) is a
+/// record. The `g:` namespace prefix is dropped, repeated elements become
+/// arrays and nested elements (`g:shipping`) become objects. A price such
+/// as `19.99 USD` also sets `currency` when the item has none.
+fn parse_merchant_xml(text: &str) -> Result<Vec<FeedRecord>, String> {
+    let mut records = Vec::new();
+    for tag in ["item", "entry"] {
+        let open = format!("<{}>", tag);
+        let close = format!("</{}>", tag);
+        let mut rest = text;
+        while let Some(start) = rest.find(&open) {
+            let body = &rest[start + open.len()..];
+            let end = body
+                .find(&close)
+                .ok_or_else(|| format!("unterminated <{}> element", tag))?;
+            let mut values = match xml_children(&body[..end])? {
+                Value::Object(map) => map,
+                _ => Map::new(),
+            };
+            if let Some(Value::String(price)) = values.get("price") {
+                if let Some((_, currency)) = price.trim().split_once(' ') {
+                    let currency = Value::String(currency.trim().to_string());
+                    values.entry("currency").or_insert(currency);
+                }
+            }
+            records.push(FeedRecord {
+                row: records.len() + 1,
+                values,
+            });
+            rest = &body[end + close.len()..];
+        }
+    }
+    Ok(records)
+}
+
+/// Parse element content: text (unescaped) or an object of child elements.
+fn xml_children(content: &str) -> Result<Value, String> {
+    let trimmed = content.trim();
+    if !trimmed.starts_with('<') || trimmed.starts_with("<![CDATA[") {
+        return Ok(Value::String(xml_text(trimmed)));
+    }
+
+    let mut map = Map::new();
+    let mut rest = trimmed;
+    while let Some(lt) = rest.find('<') {
+        rest = &rest[lt..];
+        if rest.starts_with("<!--") {
+            rest = rest
+                .find("-->")
+                .map(|i| &rest[i + 3..])
+                .ok_or("unterminated comment")?;
+            continue;
+        }
+        let gt = rest.find('>').ok_or("unterminated tag")?;
+        let tag = &rest[1..gt];
+        let self_closing = tag.ends_with('/');
+        let name = tag
+            .trim_end_matches('/')
+            .split_whitespace()
+            .next()
+            .ok_or("empty tag")?
+            .to_string();
+        let (value, after) = if self_closing {
+            (Value::Null, &rest[gt + 1..])
+        } else {
+            let inner = &rest[gt + 1..];
+            let close = format!("</{}>", name);
+            let end = inner
+                .find(&close)
+                .ok_or_else(|| format!("unterminated <{}> element", name))?;
+            (xml_children(&inner[..end])?, &inner[end + close.len()..])
+        };
+        let key = name.rsplit(':').next().unwrap_or(&name).to_string();
+        match map.get_mut(&key) {
+            Some(Value::Array(items)) => items.push(value),
+            Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+            None => {
+                map.insert(key, value);
+            }
+        }
+        rest = after;
+    }
+    Ok(Value::Object(map))
+}
+
+fn xml_text(text: &str) -> String {
+    if let Some(cdata) = text
+        .strip_prefix("<![CDATA[")
+        .and_then(|t| t.strip_suffix("]]>"))
+    {
+        return cdata.to_string();
+    }
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(amp) = rest.find('&') {
+        out.push_str(&rest[..amp]);
+        let entity_end = rest[amp..].find(';').map(|i| amp + i);
+        let decoded = entity_end.and_then(|end| match &rest[amp + 1..end] {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            e if e.starts_with("#x") => u32::from_str_radix(&e[2..], 16)
+                .ok()
+                .and_then(char::from_u32),
+            e if e.starts_with('#') => e[1..].parse().ok().and_then(char::from_u32),
+            _ => None,
+        });
+        match (decoded, entity_end) {
+            (Some(c), Some(end)) => {
+                out.push(c);
+                rest = &rest[end + 1..];
+            }
+            _ => {
+                out.push('&');
+                rest = &rest[amp + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Coerce a record to the catalog schema. Returns the item id and its typed
+/// data. Undeclared columns pass through unchanged; `id` and
+/// [`DELETE_COLUMN`] are not copied into the data.
+pub fn validate_record(
+    fields: &[CatalogField],
+    record: &FeedRecord,
+) -> Result<(String, HashMap<String, Value>), String> {
+    let id = match record.values.get("id") {
+        Some(Value::String(s)) if !s.trim().is_empty() => s.trim().to_string(),
+        Some(Value::Number(n)) => n.to_string(),
+        _ => return Err("missing item id".to_string()),
+    };
+
+    let mut data: HashMap<String, Value> = record
+        .values
+        .iter()
+        .filter(|(k, _)| k.as_str() != "id" && k.as_str() != DELETE_COLUMN)
+        .map(|(k, v)| (k.clone(), v.clone()))
+        .collect();
+    for field in fields {
+        match data.remove(&field.name) {
+            Some(Value::Null) | None if field.required => {
+                return Err(format!("missing required field '{}'", field.name));
+            }
+            Some(Value::Null) | None => {}
+            Some(value) => {
+                let coerced = coerce(&field.field_type, value)
+                    .map_err(|e| format!("field '{}': {}", field.name, e))?;
+                data.insert(field.name.clone(), coerced);
+            }
+        }
+    }
+    Ok((id, data))
+}
+
+/// True when a delta-feed record asks for the item to be removed.
+pub fn is_delete(record: &FeedRecord) -> bool {
+    match record.values.get(DELETE_COLUMN) {
+        Some(Value::Bool(b)) => *b,
+        Some(Value::String(s)) => matches!(s.trim(), "true" | "1" | "yes"),
+        _ => false,
+    }
+}
+
+fn coerce(field_type: &CatalogFieldType, value: Value) -> Result<Value, String> {
+    match (field_type, value) {
+        (CatalogFieldType::String, Value::String(s)) => Ok(Value::String(s)),
+        (CatalogFieldType::String, v @ (Value::Number(_) | Value::Bool(_))) => {
+            Ok(Value::String(v.to_string()))
+        }
+        // Stored as f64 so `89` and `89.00` compare equal across feeds.
+        (CatalogFieldType::Number, Value::Number(n)) => Ok(Value::from(n.as_f64().unwrap_or(0.0))),
+        (CatalogFieldType::Number, Value::String(s)) => {
+            // Accept `19.99` and Merchant-style `19.99 USD`.
+            let number = s.split_whitespace().next().unwrap_or("");
+            number
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Value::from)
+                .ok_or_else(|| format!("'{}' is not a number", s))
+        }
+        (CatalogFieldType::Boolean, Value::Bool(b)) => Ok(Value::Bool(b)),
+        (CatalogFieldType::Boolean, Value::String(s)) => {
+            match s.trim().to_ascii_lowercase().as_str() {
+                "true" | "yes" | "1" | "in stock" | "in_stock" => Ok(Value::Bool(true)),
+                "false" | "no" | "0" | "out of stock" | "out_of_stock" => Ok(Value::Bool(false)),
+                _ => Err(format!("'{}' is not a boolean", s)),
+            }
+        }
+        (CatalogFieldType::Url, Value::String(s)) => {
+            let s = s.trim();
+            if s.starts_with("https://") || s.starts_with("http://") {
+                Ok(Value::String(s.to_string()))
+            } else {
+                Err(format!("'{}' is not an http(s) URL", s))
+            }
+        }
+        (CatalogFieldType::DateTime, Value::String(s)) => {
+            let s = s.trim();
+            chrono::DateTime::parse_from_rfc3339(s)
+                .map(|dt| dt.with_timezone(&chrono::Utc))
+                .or_else(|_| {
+                    chrono::NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .map(|d| d.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc())
+                })
+                .map(|dt| Value::String(dt.to_rfc3339()))
+                .map_err(|_| format!("'{}' is not an RFC 3339 timestamp or date", s))
+        }
+        (CatalogFieldType::Array, Value::Array(items)) => Ok(Value::Array(items)),
+        (CatalogFieldType::Array, Value::String(s)) => {
+            // CSV cells hold a JSON array or `|`-separated values.
+            if s.trim_start().starts_with('[') {
+                serde_json::from_str(&s).map_err(|e| e.to_string())
+            } else {
+                Ok(Value::Array(
+                    s.split('|')
+                        .map(|p| Value::String(p.trim().to_string()))
+                        .filter(|v| v != &Value::String(String::new()))
+                        .collect(),
+                ))
+            }
+        }
+        (field_type, value) => Err(format!("expected {:?}, got {}", field_type, value)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn field(name: &str, field_type: CatalogFieldType, required: bool) -> CatalogField {
+        CatalogField {
+            name: name.to_string(),
+            field_type,
+            required,
+            filterable: false,
+        }
+    }
+
+    #[test]
+    fn test_csv_quoting() {
+        let feed = "id,title,tags\r\nsku-1,\"Shoe, \"\"Red\"\"\",a|b\n\nsku-2,\"Two\nlines\",\n";
+        let rows = parse_feed(feed, FeedFormat::Csv).unwrap();
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0].values["title"], json!("Shoe, \"Red\""));
+        assert_eq!(rows[1].values["title"], json!("Two\nlines"));
+        assert!(!rows[1].values.contains_key("tags"));
+        assert!(parse_feed("id\n\"open", FeedFormat::Csv).is_err());
+        assert!(parse_feed("id\n1,2", FeedFormat::Csv).is_err());
+    }
+
+    #[test]
+    fn test_merchant_xml() {
+        let feed = r#"<?xml version="1.0"?>
+<rss xmlns:g="http://base.google.com/ns/1.0"><channel>
+  <item>
+    <g:id>sku-1</g:id>
+    <title><![CDATA[Tom & Jerry <Tee>]]></title>
+    <g:price>19.99 USD</g:price>
+    <g:availability>out of stock</g:availability>
+    <g:additional_image_link>https://x/1.png</g:additional_image_link>
+    <g:additional_image_link>https://x/2.png</g:additional_image_link>
+    <g:shipping><g:country>US</g:country><g:price>4.99 USD</g:price></g:shipping>
+  </item>
+  <item><g:id>sku-2</g:id><title>Caf&#233; &amp; Bar</title></item>
+</channel></rss>"#;
+        let rows = parse_feed(feed, FeedFormat::GoogleMerchantXml).unwrap();
+        assert_eq!(rows.len(), 2);
+        let first = &rows[0].values;
+        assert_eq!(first["title"], json!("Tom & Jerry <Tee>"));
+        assert_eq!(first["currency"], json!("USD"));
+        assert_eq!(first["additional_image_link"].as_array().unwrap().len(), 2);
+        assert_eq!(first["shipping"]["country"], json!("US"));
+        assert_eq!(rows[1].values["title"], json!("Café & Bar"));
+    }
+
+    #[test]
+    fn test_validation_coerces_and_rejects() {
+        let fields = vec![
+            field("title", CatalogFieldType::String, true),
+            field("price", CatalogFieldType::Number, true),
+            field("in_stock", CatalogFieldType::Boolean, false),
+            field("link", CatalogFieldType::Url, false),
+            field("launched", CatalogFieldType::DateTime, false),
+            field("tags", CatalogFieldType::Array, false),
+        ];
+        let record = |values: Value| FeedRecord {
+            row: 1,
+            values: values.as_object().unwrap().clone(),
+        };
+
+        let (id, data) = validate_record(
+            &fields,
+            &record(json!({
+                "id": "sku-1", "title": "Shoe", "price": "19.99 USD",
+                "in_stock": "out of stock", "launched": "2026-03-01",
+                "tags": "a|b", "color": "red",
+            })),
+        )
+        .unwrap();
+        assert_eq!(id, "sku-1");
+        assert_eq!(data["price"], json!(19.99));
+        assert_eq!(data["in_stock"], json!(false));
+        assert_eq!(data["launched"], json!("2026-03-01T00:00:00+00:00"));
+        assert_eq!(data["tags"], json!(["a", "b"]));
+        assert_eq!(data["color"], json!("red"));
+
+        let err = validate_record(&fields, &record(json!({"id": "x", "price": 1})));
+        assert_eq!(err.unwrap_err(), "missing required field 'title'");
+        let err = validate_record(
+            &fields,
+            &record(json!({"id": "x", "title": "t", "price": 1, "link": "javascript:x"})),
+        );
+        assert!(err.unwrap_err().starts_with("field 'link'"));
+        assert!(validate_record(&fields, &record(json!({"title": "t", "price": 1}))).is_err());
+    }
+}
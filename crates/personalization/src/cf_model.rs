@@ -0,0 +1,539 @@
+//! Learned collaborative filtering — implicit-feedback matrix factorization
+//! (Bayesian Personalized Ranking) trained offline from interaction logs.
+//!
+//! Training produces an [`ItemEmbeddings`] file: item ids plus one `f32`
+//! vector per item, nothing per user. At serve time a user is folded in as
+//! the mean of the embeddings of the items in their history, and candidates
+//! come from an [`AnnIndex`] (random-hyperplane LSH with multi-probe) that
+//! is re-ranked by exact inner product.
+
+use chrono::{DateTime, Utc};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::io::{BufRead, Read, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+const MAGIC: &[u8; 4] = b"CEIE";
+const FORMAT_VERSION: u8 = 1;
+
+/// One line of an interaction log (JSONL).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct InteractionRecord {
+    pub user_id: Uuid,
+    pub item_id: String,
+    #[serde(default)]
+    pub timestamp: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BprConfig {
+    /// Embedding dimension.
+    pub factors: usize,
+    pub learning_rate: f32,
+    /// L2 penalty applied to every updated vector.
+    pub regularization: f32,
+    /// Passes over the positive interactions.
+    pub epochs: usize,
+    pub seed: u64,
+}
+
+impl Default for BprConfig {
+    fn default() -> Self {
+        Self {
+            factors: 32,
+            learning_rate: 0.05,
+            regularization: 0.01,
+            epochs: 30,
+            seed: 42,
+        }
+    }
+}
+
+pub struct BprTrainer {
+    config: BprConfig,
+}
+
+impl BprTrainer {
+    pub fn new(config: BprConfig) -> Self {
+        Self { config }
+    }
+
+    /// Read a JSONL interaction log and train on it. Blank lines are skipped;
+    /// a malformed line is an error naming its line number.
+    pub fn train_from_log<R: BufRead>(&self, reader: R) -> anyhow::Result<ItemEmbeddings> {
+        let mut interactions = Vec::new();
+        for (n, line) in reader.lines().enumerate() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+            let record: InteractionRecord = serde_json::from_str(&line)
+                .map_err(|e| anyhow::anyhow!("line {}: {}", n + 1, e))?;
+            interactions.push(record);
+        }
+        Ok(self.train(&interactions))
+    }
+
+    /// Fit item embeddings with BPR-SGD: for a sampled (user, seen item,
+    /// unseen item) triple, push the user's score for the seen item above
+    /// the unseen one.
+    pub fn train(&self, interactions: &[InteractionRecord]) -> ItemEmbeddings {
+        let k = self.config.factors.max(1);
+        let mut rng = StdRng::seed_from_u64(self.config.seed);
+
+        let mut item_index: HashMap<&str, usize> = HashMap::new();
+        let mut item_ids: Vec<String> = Vec::new();
+        let mut user_index: HashMap<Uuid, usize> = HashMap::new();
+        let mut positives: Vec<HashSet<usize>> = Vec::new();
+        for record in interactions {
+            let item = *item_index.entry(&record.item_id).or_insert_with(|| {
+                item_ids.push(record.item_id.clone());
+                item_ids.len() - 1
+            });
+            let user = *user_index.entry(record.user_id).or_insert_with(|| {
+                positives.push(HashSet::new());
+                positives.len() - 1
+            });
+            positives[user].insert(item);
+        }
+        let pairs: Vec<(usize, usize)> = positives
+            .iter()
+            .enumerate()
+            .flat_map(|(u, items)| items.iter().map(move |&i| (u, i)))
+            .collect();
+
+        let n_items = item_ids.len();
+        let scale = 0.1 / (k as f32).sqrt();
+        let mut init =
+            |n: usize| -> Vec<f32> { (0..n * k).map(|_| rng.gen_range(-scale..scale)).collect() };
+        let mut users = init(positives.len());
+        let mut items = init(n_items);
+
+        let lr = self.config.learning_rate;
+        let reg = self.config.regularization;
+        if n_items > 1 {
+            for _ in 0..self.config.epochs {
+                for _ in 0..pairs.len() {
+                    let (u, i) = pairs[rng.gen_range(0..pairs.len())];
+                    // Users who saw every item have no negatives to sample.
+                    let Some(j) = (0..10)
+                        .map(|_| rng.gen_range(0..n_items))
+                        .find(|j| !positives[u].contains(j))
+                    else {
+                        continue;
+                    };
+
+                    let (uo, io, jo) = (u * k, i * k, j * k);
+                    let x: f32 = (0..k)
+                        .map(|f| users[uo + f] * (items[io + f] - items[jo + f]))
+                        .sum();
+                    let g = 1.0 / (1.0 + x.exp());
+                    for f in 0..k {
+                        let (wu, wi, wj) = (users[uo + f], items[io + f], items[jo + f]);
+                        users[uo + f] += lr * (g * (wi - wj) - reg * wu);
+                        items[io + f] += lr * (g * wu - reg * wi);
+                        items[jo + f] += lr * (-g * wu - reg * wj);
+                    }
+                }
+            }
+        }
+
+        ItemEmbeddings::from_rows(
+            format!("bpr-{}", Utc::now().format("%Y%m%d%H%M%S")),
+            k,
+            item_ids,
+            items,
+        )
+    }
+}
+
+/// Trained item vectors, row-major, plus the id -> row lookup.
+#[derive(Debug, Clone)]
+pub struct ItemEmbeddings {
+    pub version: String,
+    pub dim: usize,
+    ids: Vec<String>,
+    vectors: Vec<f32>,
+    rows: HashMap<String, usize>,
+}
+
+impl ItemEmbeddings {
+    /// Fails unless `vectors` holds exactly `dim` values per id.
+    pub fn new(
+        version: String,
+        dim: usize,
+        ids: Vec<String>,
+        vectors: Vec<f32>,
+    ) -> anyhow::Result<Self> {
+        let expected = ids
+            .len()
+            .checked_mul(dim)
+            .ok_or_else(|| anyhow::anyhow!("embedding shape overflows"))?;
+        if expected != vectors.len() {
+            anyhow::bail!(
+                "embedding shape mismatch: {} items x {} dims needs {} values, got {}",
+                ids.len(),
+                dim,
+                expected,
+                vectors.len()
+            );
+        }
+        Ok(Self::from_rows(version, dim, ids, vectors))
+    }
+
+    fn from_rows(version: String, dim: usize, ids: Vec<String>, vectors: Vec<f32>) -> Self {
+        let rows = ids
+            .iter()
+            .enumerate()
+            .map(|(i, id)| (id.clone(), i))
+            .collect();
+        Self {
+            version,
+            dim,
+            ids,
+            vectors,
+            rows,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.ids.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.ids.is_empty()
+    }
+
+    pub fn item_ids(&self) -> &[String] {
+        &self.ids
+    }
+
+    pub fn get(&self, item_id: &str) -> Option<&[f32]> {
+        self.rows.get(item_id).map(|&row| self.row(row))
+    }
+
+    fn row(&self, row: usize) -> &[f32] {
+        &self.vectors[row * self.dim..(row + 1) * self.dim]
+    }
+
+    /// Binary layout: magic `CEIE`, format version (u8), model version
+    /// (u16 length + UTF-8), dim (u32), item count (u32), item ids (u16
+    /// length + UTF-8 each), then `count * dim` little-endian `f32`s.
+    pub fn write_to<W: Write>(&self, mut w: W) -> std::io::Result<()> {
+        fn put_str<W: Write>(w: &mut W, s: &str) -> std::io::Result<()> {
+            let len = u16::try_from(s.len()).map_err(|_| {
+                std::io::Error::new(std::io::ErrorKind::InvalidInput, "string too long")
+            })?;
+            w.write_all(&len.to_le_bytes())?;
+            w.write_all(s.as_bytes())
+        }
+        w.write_all(MAGIC)?;
+        w.write_all(&[FORMAT_VERSION])?;
+        put_str(&mut w, &self.version)?;
+        w.write_all(&(self.dim as u32).to_le_bytes())?;
+        w.write_all(&(self.ids.len() as u32).to_le_bytes())?;
+        for id in &self.ids {
+            put_str(&mut w, id)?;
+        }
+        for v in &self.vectors {
+            w.write_all(&v.to_le_bytes())?;
+        }
+        w.flush()
+    }
+
+    pub fn read_from<R: Read>(r: R) -> anyhow::Result<Self> {
+        Self::read_sized(r, u64::MAX)
+    }
+
+    /// [`Self::read_from`] for input known to be `size` bytes long. A header
+    /// whose item count and dimension need more bytes than that is
+    /// rejected before anything is allocated for them.
+    pub fn read_sized<R: Read>(mut r: R, size: u64) -> anyhow::Result<Self> {
+        fn take<const N: usize, R: Read>(r: &mut R) -> std::io::Result<[u8; N]> {
+            let mut buf = [0u8; N];
+            r.read_exact(&mut buf)?;
+            Ok(buf)
+        }
+        fn get_str<R: Read>(r: &mut R) -> anyhow::Result<String> {
+            let len = u16::from_le_bytes(take(r)?) as usize;
+            let mut buf = vec![0u8; len];
+            r.read_exact(&mut buf)?;
+            Ok(String::from_utf8(buf)?)
+        }
+
+        if &take::<4, _>(&mut r)? != MAGIC {
+            anyhow::bail!("not an item embedding file");
+        }
+        let [format] = take::<1, _>(&mut r)?;
+        if format != FORMAT_VERSION {
+            anyhow::bail!("unsupported embedding format version {}", format);
+        }
+        let version = get_str(&mut r)?;
+        let dim = u32::from_le_bytes(take(&mut r)?) as usize;
+        let count = u32::from_le_bytes(take(&mut r)?) as usize;
+        let header = (MAGIC.len() + 1 + 2 + version.len() + 8) as u64;
+        let needed = (count as u64)
+            .checked_mul(2 + 4 * dim as u64)
+            .and_then(|body| body.checked_add(header));
+        match needed {
+            Some(needed) if needed <= size => {}
+            _ => anyhow::bail!(
+                "embedding header claims {count} items of dimension {dim}, \
+                 more than the {size} bytes available"
+            ),
+        }
+        let ids = (0..count)
+            .map(|_| get_str(&mut r))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+        let vectors = (0..count * dim)
+            .map(|_| Ok(f32::from_le_bytes(take(&mut r)?)))
+            .collect::<std::io::Result<Vec<_>>>()?;
+        Self::new(version, dim, ids, vectors)
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        let file = std::fs::File::create(path)?;
+        self.write_to(std::io::BufWriter::new(file))?;
+        Ok(())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let file = std::fs::File::open(path)?;
+        let size = file.metadata()?.len();
+        Self::read_sized(std::io::BufReader::new(file), size)
+    }
+}
+
+/// Approximate maximum-inner-product search over item embeddings using
+/// sign-random-projection hash tables. Each query probes its own bucket and
+/// every bucket one bit away in each table; if that yields too few
+/// candidates the search falls back to a full scan.
+pub struct AnnIndex {
+    planes: Vec<Vec<Vec<f32>>>,
+    tables: Vec<HashMap<u64, Vec<usize>>>,
+}
+
+impl AnnIndex {
+    pub fn build(embeddings: &ItemEmbeddings, tables: usize, bits: usize, seed: u64) -> Self {
+        let bits = bits.clamp(1, 63);
+        let mut rng = StdRng::seed_from_u64(seed);
+        let planes: Vec<Vec<Vec<f32>>> = (0..tables.max(1))
+            .map(|_| {
+                (0..bits)
+                    .map(|_| {
+                        (0..embeddings.dim)
+                            .map(|_| rng.gen_range(-1.0f32..1.0))
+                            .collect()
+                    })
+                    .collect()
+            })
+            .collect();
+        let tables = planes
+            .iter()
+            .map(|table_planes| {
+                let mut buckets: HashMap<u64, Vec<usize>> = HashMap::new();
+                for row in 0..embeddings.len() {
+                    buckets
+                        .entry(hash(table_planes, embeddings.row(row)))
+                        .or_default()
+                        .push(row);
+                }
+                buckets
+            })
+            .collect();
+        Self { planes, tables }
+    }
+
+    /// Top `limit` items by inner product with `query`, skipping ids for
+    /// which `exclude` returns true.
+    pub fn search(
+        &self,
+        embeddings: &ItemEmbeddings,
+        query: &[f32],
+        limit: usize,
+        exclude: impl Fn(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        let mut candidates: HashSet<usize> = HashSet::new();
+        for (table_planes, buckets) in self.planes.iter().zip(&self.tables) {
+            let code = hash(table_planes, query);
+            let probes =
+                std::iter::once(code).chain((0..table_planes.len()).map(|b| code ^ (1 << b)));
+            for probe in probes {
+                if let Some(rows) = buckets.get(&probe) {
+                    candidates.extend(rows.iter().copied());
+                }
+            }
+        }
+
+        let score = |row: usize| -> Option<(String, f32)> {
+            let id = &embeddings.ids[row];
+            (!exclude(id)).then(|| (id.clone(), dot(embeddings.row(row), query)))
+        };
+        let mut scored: Vec<(String, f32)> = candidates.into_iter().filter_map(score).collect();
+        if scored.len() < limit {
+            scored = (0..embeddings.len()).filter_map(score).collect();
+        }
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        scored.truncate(limit);
+        scored
+    }
+}
+
+/// Item embeddings plus their ANN index, ready to serve.
+pub struct CfModel {
+    pub embeddings: ItemEmbeddings,
+    index: AnnIndex,
+}
+
+impl CfModel {
+    pub fn new(embeddings: ItemEmbeddings) -> Self {
+        // ~2^bits buckets sized to keep a handful of items per bucket.
+        let bits = (embeddings.len() as f64 / 8.0)
+            .log2()
+            .ceil()
+            .clamp(1.0, 16.0) as usize;
+        let index = AnnIndex::build(&embeddings, 8, bits, 7);
+        Self { embeddings, index }
+    }
+
+    pub fn version(&self) -> &str {
+        &self.embeddings.version
+    }
+
+    /// Fold a user in as the mean embedding of `history` and return the
+    /// nearest items. Empty when no history item is in the model.
+    pub fn recommend(
+        &self,
+        history: &[String],
+        limit: usize,
+        exclude: impl Fn(&str) -> bool,
+    ) -> Vec<(String, f32)> {
+        let mut query = vec![0.0f32; self.embeddings.dim];
+        let mut n = 0;
+        for vector in history.iter().filter_map(|id| self.embeddings.get(id)) {
+            query.iter_mut().zip(vector).for_each(|(q, v)| *q += v);
+            n += 1;
+        }
+        if n == 0 {
+            return Vec::new();
+        }
+        query.iter_mut().for_each(|q| *q /= n as f32);
+        self.index.search(&self.embeddings, &query, limit, exclude)
+    }
+}
+
+fn hash(planes: &[Vec<f32>], v: &[f32]) -> u64 {
+    planes.iter().enumerate().fold(0u64, |code, (bit, plane)| {
+        if dot(plane, v) >= 0.0 {
+            code | (1 << bit)
+        } else {
+            code
+        }
+    })
+}
+
+fn dot(a: &[f32], b: &[f32]) -> f32 {
+    a.iter().zip(b).map(|(x, y)| x * y).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Two taste clusters: users 0..20 like `a*`, users 20..40 like `b*`.
+    fn clustered_log() -> Vec<InteractionRecord> {
+        let users: Vec<Uuid> = (0..40).map(|_| Uuid::new_v4()).collect();
+        let mut log = Vec::new();
+        for (u, user) in users.iter().enumerate() {
+            let prefix = if u < 20 { "a" } else { "b" };
+            for i in 0..5 {
+                if (u + i) % 5 != 0 {
+                    log.push(InteractionRecord {
+                        user_id: *user,
+                        item_id: format!("{}{}", prefix, i),
+                        timestamp: None,
+                    });
+                }
+            }
+        }
+        log
+    }
+
+    #[test]
+    fn test_bpr_learns_clusters() {
+        let model = CfModel::new(BprTrainer::new(BprConfig::default()).train(&clustered_log()));
+        let history = vec!["a0".to_string(), "a1".to_string()];
+        let recs = model.recommend(&history, 3, |id| history.iter().any(|h| h == id));
+        assert_eq!(recs.len(), 3);
+        assert!(recs.iter().all(|(id, _)| id.starts_with('a')), "{:?}", recs);
+        assert!(model
+            .recommend(&["unknown".to_string()], 3, |_| false)
+            .is_empty());
+    }
+
+    #[test]
+    fn test_embedding_file_round_trip() {
+        let log: String = clustered_log()
+            .iter()
+            .map(|r| serde_json::to_string(r).unwrap() + "\n")
+            .collect();
+        let trainer = BprTrainer::new(BprConfig {
+            factors: 4,
+            epochs: 2,
+            ..Default::default()
+        });
+        let embeddings = trainer.train_from_log(log.as_bytes()).unwrap();
+        assert_eq!(embeddings.len(), 10);
+
+        let mut bytes = Vec::new();
+        embeddings.write_to(&mut bytes).unwrap();
+        assert_eq!(
+            bytes.len(),
+            4 + 1 + 2 + embeddings.version.len() + 8 + 10 * 4 + 10 * 4 * 4
+        );
+        let loaded = ItemEmbeddings::read_from(bytes.as_slice()).unwrap();
+        assert_eq!(loaded.version, embeddings.version);
+        assert_eq!(loaded.item_ids(), embeddings.item_ids());
+        assert_eq!(loaded.get("b3"), embeddings.get("b3"));
+
+        assert!(ItemEmbeddings::read_from(&b"nope"[..]).is_err());
+        let size = bytes.len() as u64;
+        assert!(ItemEmbeddings::read_sized(bytes.as_slice(), size).is_ok());
+        let count_at = 4 + 1 + 2 + embeddings.version.len() + 4;
+        bytes[count_at..count_at + 4].copy_from_slice(&u32::MAX.to_le_bytes());
+        let err = ItemEmbeddings::read_sized(bytes.as_slice(), size).unwrap_err();
+        assert!(err.to_string().contains("claims 4294967295 items"), "{err}");
+        assert!(ItemEmbeddings::new("v".into(), 2, vec!["a".into()], vec![1.0]).is_err());
+        let err = trainer.train_from_log(&b"{}\n"[..]).unwrap_err();
+        assert!(err.to_string().starts_with("line 1"));
+    }
+
+    #[test]
+    fn test_ann_matches_exact_search() {
+        let mut rng = StdRng::seed_from_u64(1);
+        let ids: Vec<String> = (0..500).map(|i| format!("item{}", i)).collect();
+        let vectors: Vec<f32> = (0..500 * 8).map(|_| rng.gen_range(-1.0..1.0)).collect();
+        let model = CfModel::new(ItemEmbeddings::new("t".into(), 8, ids, vectors).unwrap());
+
+        let query = model.embeddings.get("item42").unwrap().to_vec();
+        let approx = model.index.search(&model.embeddings, &query, 10, |_| false);
+        let mut exact: Vec<(String, f32)> = model
+            .embeddings
+            .item_ids()
+            .iter()
+            .map(|id| (id.clone(), dot(model.embeddings.get(id).unwrap(), &query)))
+            .collect();
+        exact.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap());
+        let exact: HashSet<&String> = exact.iter().take(10).map(|(id, _)| id).collect();
+        let recall = approx.iter().filter(|(id, _)| exact.contains(id)).count();
+        assert!(recall >= 7, "recall {}/10", recall);
+
+        let filtered = model
+            .index
+            .search(&model.embeddings, &query, 10, |id| id == "item42");
+        assert!(filtered.iter().all(|(id, _)| id != "item42"));
+    }
+}
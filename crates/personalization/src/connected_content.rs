@@ -1,7 +1,28 @@
 //! Connected content — fetches personalized data from external APIs at send time.
+//!
+//! Each source is an HTTP endpoint whose URL (and POST body) may reference
+//! send-time variables as `{{name}}`; substituted values are URL-encoded.
+//! Credentials are stored as named secrets on the engine and referenced by
+//! name from the source, so source definitions can be listed and logged
+//! without leaking them. Responses are shaped with JSONPath expressions.
+//!
+//! A fetch never fails a send: timeouts, HTTP errors, malformed bodies and
+//! open circuits all resolve to the source's `fallback_value`. Every source
+//! gets its own circuit breaker, so a partner API that keeps timing out is
+//! skipped outright until it recovers.
+//!
+//! Retries back off exponentially, and the whole fetch (every attempt plus
+//! the waits between them) is capped by the engine's fetch deadline. The
+//! response cache holds at most `max_cache_entries`; expired entries are
+//! dropped first, then the least recently used.
 
+use campaign_cdp::connector_runtime::{CircuitBreaker, CircuitBreakerConfig};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -16,6 +37,16 @@ pub struct ConnectedContentSource {
     pub fallback_value: Option<serde_json::Value>,
     pub retry_count: u32,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub auth: ConnectedContentAuth,
+    /// JSON body for POST sources; `{{name}}` variables are substituted as
+    /// JSON-escaped strings.
+    #[serde(default)]
+    pub body_template: Option<String>,
+    /// Output field -> JSONPath into the response (`$.items[0].price`).
+    /// When empty the whole response body is returned.
+    #[serde(default)]
+    pub extract: HashMap<String, String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -25,6 +56,26 @@ pub enum HttpMethod {
     Post,
 }
 
+/// How a source authenticates. Secrets are referenced by name and resolved
+/// from [`ConnectedContentEngine::set_secret`] at fetch time.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum ConnectedContentAuth {
+    #[default]
+    None,
+    Bearer {
+        secret: String,
+    },
+    Basic {
+        username: String,
+        secret: String,
+    },
+    ApiKey {
+        header: String,
+        secret: String,
+    },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ConnectedContentResult {
     pub source_id: Uuid,
@@ -32,25 +83,173 @@ pub struct ConnectedContentResult {
     pub fetched_at: DateTime<Utc>,
     pub cached: bool,
     pub latency_ms: u64,
+    /// `data` is the fallback value because the fetch failed.
+    #[serde(default)]
+    pub fallback: bool,
+    /// Why the fetch failed, when `fallback` is set.
+    #[serde(default)]
+    pub error: Option<String>,
+}
+
+/// Default cap on cached responses across all sources.
+pub const DEFAULT_MAX_CACHE_ENTRIES: usize = 10_000;
+/// Default wait before the first retry; doubled for each further attempt.
+pub const DEFAULT_RETRY_BACKOFF_MS: u64 = 50;
+/// Default upper bound on one fetch, retries included.
+pub const DEFAULT_FETCH_DEADLINE_MS: u64 = 2_000;
+
+struct CachedResponse {
+    data: Value,
+    fetched_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+}
+
+/// Response cache ordered by last use and by expiry, so making room never
+/// scans the whole cache.
+#[derive(Default)]
+struct ResponseCache {
+    entries: HashMap<String, CacheSlot>,
+    /// Keys by last-use tick, least recently used first.
+    by_use: BTreeMap<u64, String>,
+    /// Keys by expiry (ties broken by insert tick), soonest first.
+    by_expiry: BTreeMap<(DateTime<Utc>, u64), String>,
+    tick: u64,
+}
+
+struct CacheSlot {
+    response: CachedResponse,
+    inserted: u64,
+    used: u64,
+}
+
+impl ResponseCache {
+    /// Unexpired response for `key`, which becomes the most recently used.
+    fn get(&mut self, key: &str, now: DateTime<Utc>) -> Option<(Value, DateTime<Utc>)> {
+        if self.entries.get(key)?.response.expires_at <= now {
+            self.remove(key);
+            return None;
+        }
+        self.tick += 1;
+        let slot = self.entries.get_mut(key)?;
+        self.by_use.remove(&slot.used);
+        slot.used = self.tick;
+        self.by_use.insert(self.tick, key.to_string());
+        Some((slot.response.data.clone(), slot.response.fetched_at))
+    }
+
+    /// Insert, making room first: an expired entry goes if there is one,
+    /// otherwise the least recently used.
+    fn insert(&mut self, key: String, response: CachedResponse, max: usize, now: DateTime<Utc>) {
+        if max == 0 {
+            return;
+        }
+        self.remove(&key);
+        while self.entries.len() >= max {
+            let expired = self
+                .by_expiry
+                .first_key_value()
+                .filter(|((expires_at, _), _)| *expires_at <= now)
+                .map(|(_, key)| key.clone());
+            let victim =
+                expired.or_else(|| self.by_use.first_key_value().map(|(_, key)| key.clone()));
+            match victim {
+                Some(victim) => self.remove(&victim),
+                None => break,
+            }
+        }
+        self.tick += 1;
+        self.by_use.insert(self.tick, key.clone());
+        self.by_expiry
+            .insert((response.expires_at, self.tick), key.clone());
+        self.entries.insert(
+            key,
+            CacheSlot {
+                response,
+                inserted: self.tick,
+                used: self.tick,
+            },
+        );
+    }
+
+    fn remove(&mut self, key: &str) {
+        if let Some(slot) = self.entries.remove(key) {
+            self.by_use.remove(&slot.used);
+            self.by_expiry
+                .remove(&(slot.response.expires_at, slot.inserted));
+        }
+    }
 }
 
 pub struct ConnectedContentEngine {
     sources: dashmap::DashMap<Uuid, ConnectedContentSource>,
-    cache: dashmap::DashMap<String, (serde_json::Value, DateTime<Utc>)>,
+    cache: Mutex<ResponseCache>,
+    secrets: dashmap::DashMap<String, String>,
+    breakers: dashmap::DashMap<Uuid, Arc<CircuitBreaker>>,
+    breaker_config: CircuitBreakerConfig,
+    http: reqwest::Client,
+    max_cache_entries: usize,
+    retry_backoff: Duration,
+    fetch_deadline: Duration,
 }
 
 impl ConnectedContentEngine {
     pub fn new() -> Self {
         Self {
             sources: dashmap::DashMap::new(),
-            cache: dashmap::DashMap::new(),
+            cache: Mutex::new(ResponseCache::default()),
+            secrets: dashmap::DashMap::new(),
+            breakers: dashmap::DashMap::new(),
+            breaker_config: CircuitBreakerConfig::default(),
+            http: reqwest::Client::new(),
+            max_cache_entries: DEFAULT_MAX_CACHE_ENTRIES,
+            retry_backoff: Duration::from_millis(DEFAULT_RETRY_BACKOFF_MS),
+            fetch_deadline: Duration::from_millis(DEFAULT_FETCH_DEADLINE_MS),
         }
     }
 
+    /// Maximum number of cached responses kept across all sources.
+    pub fn with_max_cache_entries(mut self, max: usize) -> Self {
+        self.max_cache_entries = max;
+        self
+    }
+
+    /// Wait before the first retry; each further retry waits twice as long.
+    pub fn with_retry_backoff(mut self, backoff: Duration) -> Self {
+        self.retry_backoff = backoff;
+        self
+    }
+
+    /// Upper bound on one fetch including every retry and backoff wait.
+    pub fn with_fetch_deadline(mut self, deadline: Duration) -> Self {
+        self.fetch_deadline = deadline;
+        self
+    }
+
+    /// Circuit breaker settings applied to each source registered afterwards.
+    pub fn with_circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.breaker_config = config;
+        self
+    }
+
     pub fn register_source(&self, source: ConnectedContentSource) {
+        self.breakers.insert(
+            source.id,
+            Arc::new(CircuitBreaker::new(self.breaker_config.clone())),
+        );
         self.sources.insert(source.id, source);
     }
 
+    /// Store a credential that sources reference by `name`.
+    pub fn set_secret(&self, name: &str, value: &str) {
+        self.secrets.insert(name.to_string(), value.to_string());
+    }
+
+    pub fn remove_secret(&self, name: &str) -> bool {
+        self.secrets.remove(name).is_some()
+    }
+
+    /// Fetch a source for one recipient. Only an unknown `source_id` is an
+    /// error; every other failure returns the source's fallback value.
     pub async fn fetch(
         &self,
         source_id: &Uuid,
@@ -59,43 +258,169 @@ impl ConnectedContentEngine {
         let source = self
             .sources
             .get(source_id)
+            .map(|s| s.clone())
             .ok_or_else(|| anyhow::anyhow!("Source not found"))?;
 
-        let mut url = source.url_template.clone();
-        for (key, value) in variables {
-            url = url.replace(&format!("{{{{{}}}}}", key), value);
+        let url = substitute(&source.url_template, variables, encode_component);
+        let body = source
+            .body_template
+            .as_deref()
+            .map(|t| substitute(t, variables, json_escape));
+
+        let cache_key = format!("{}:{}:{}", source_id, url, body.as_deref().unwrap_or(""));
+        let cached = self.cache().get(&cache_key, Utc::now());
+        if let Some((data, fetched_at)) = cached {
+            return Ok(ConnectedContentResult {
+                source_id: *source_id,
+                data,
+                fetched_at,
+                cached: true,
+                latency_ms: 0,
+                fallback: false,
+                error: None,
+            });
         }
 
-        let cache_key = format!("{}:{}", source_id, url);
-        if let Some(cached) = self.cache.get(&cache_key) {
-            let (data, fetched_at) = cached.value();
-            let age = (Utc::now() - *fetched_at).num_seconds() as u64;
-            if age < source.cache_ttl_seconds {
-                return Ok(ConnectedContentResult {
+        let breaker = self
+            .breakers
+            .entry(*source_id)
+            .or_insert_with(|| Arc::new(CircuitBreaker::new(self.breaker_config.clone())))
+            .clone();
+        let started = Instant::now();
+        let outcome = if breaker.allow_request() {
+            let attempts = async {
+                let mut attempt = 0;
+                loop {
+                    match self.request(&source, &url, body.as_deref()).await {
+                        Ok(data) => break Ok(data),
+                        Err(e) if attempt < source.retry_count => {
+                            let wait = self.retry_backoff.saturating_mul(1 << attempt.min(16));
+                            tracing::debug!(source_id = %source_id, attempt, error = %e, wait_ms = wait.as_millis() as u64, "Retrying connected content fetch");
+                            tokio::time::sleep(wait).await;
+                            attempt += 1;
+                        }
+                        Err(e) => break Err(e),
+                    }
+                }
+            };
+            let outcome = match tokio::time::timeout(self.fetch_deadline, attempts).await {
+                Ok(outcome) => outcome,
+                Err(_) => Err(anyhow::anyhow!(
+                    "fetch deadline of {}ms exceeded",
+                    self.fetch_deadline.as_millis()
+                )),
+            };
+            match &outcome {
+                Ok(_) => breaker.record_success(),
+                Err(_) => breaker.record_failure(),
+            }
+            outcome
+        } else {
+            Err(anyhow::anyhow!("circuit open for source '{}'", source.name))
+        };
+        let latency_ms = started.elapsed().as_millis() as u64;
+        let now = Utc::now();
+
+        match outcome {
+            Ok(data) => {
+                let data = shape(&data, &source.extract);
+                if source.cache_ttl_seconds > 0 {
+                    self.cache().insert(
+                        cache_key,
+                        CachedResponse {
+                            data: data.clone(),
+                            fetched_at: now,
+                            expires_at: now
+                                + chrono::Duration::seconds(
+                                    source.cache_ttl_seconds.min(i64::MAX as u64 / 1000) as i64,
+                                ),
+                        },
+                        self.max_cache_entries,
+                        now,
+                    );
+                }
+                Ok(ConnectedContentResult {
+                    source_id: *source_id,
+                    data,
+                    fetched_at: now,
+                    cached: false,
+                    latency_ms,
+                    fallback: false,
+                    error: None,
+                })
+            }
+            Err(e) => {
+                tracing::warn!(source_id = %source_id, error = %e, "Connected content fetch failed; using fallback");
+                Ok(ConnectedContentResult {
                     source_id: *source_id,
-                    data: data.clone(),
-                    fetched_at: *fetched_at,
-                    cached: true,
-                    latency_ms: 0,
-                });
+                    data: source.fallback_value.clone().unwrap_or(Value::Null),
+                    fetched_at: now,
+                    cached: false,
+                    latency_ms,
+                    fallback: true,
+                    error: Some(e.to_string()),
+                })
             }
         }
+    }
 
-        tracing::info!(source_id = %source_id, url = &url, "Fetching connected content");
-        let data = source
-            .fallback_value
-            .clone()
-            .unwrap_or(serde_json::Value::Null);
-        let now = Utc::now();
-        self.cache.insert(cache_key, (data.clone(), now));
+    fn cache(&self) -> std::sync::MutexGuard<'_, ResponseCache> {
+        self.cache
+            .lock()
+            .expect("connected content cache mutex poisoned")
+    }
+
+    /// Number of cached responses.
+    pub fn cache_len(&self) -> usize {
+        self.cache().entries.len()
+    }
+
+    async fn request(
+        &self,
+        source: &ConnectedContentSource,
+        url: &str,
+        body: Option<&str>,
+    ) -> anyhow::Result<Value> {
+        let mut request = match source.method {
+            HttpMethod::Get => self.http.get(url),
+            HttpMethod::Post => self.http.post(url),
+        }
+        .timeout(Duration::from_millis(source.timeout_ms.max(1)));
+        for (name, value) in &source.headers {
+            request = request.header(name.as_str(), value.as_str());
+        }
+
+        let secret = |name: &str| {
+            self.secrets
+                .get(name)
+                .map(|s| s.clone())
+                .ok_or_else(|| anyhow::anyhow!("secret '{}' is not set", name))
+        };
+        request = match &source.auth {
+            ConnectedContentAuth::None => request,
+            ConnectedContentAuth::Bearer { secret: name } => request.bearer_auth(secret(name)?),
+            ConnectedContentAuth::Basic {
+                username,
+                secret: name,
+            } => request.basic_auth(username, Some(secret(name)?)),
+            ConnectedContentAuth::ApiKey {
+                header,
+                secret: name,
+            } => request.header(header.as_str(), secret(name)?),
+        };
+        if let Some(body) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.to_string());
+        }
 
-        Ok(ConnectedContentResult {
-            source_id: *source_id,
-            data,
-            fetched_at: now,
-            cached: false,
-            latency_ms: 1,
-        })
+        let response = request.send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            anyhow::bail!("HTTP {}", status.as_u16());
+        }
+        let text = response.text().await?;
+        Ok(serde_json::from_str(&text).unwrap_or(Value::String(text)))
     }
 
     pub fn list_sources(&self) -> Vec<ConnectedContentSource> {
@@ -108,3 +433,440 @@ impl Default for ConnectedContentEngine {
         Self::new()
     }
 }
+
+/// Replace `{{name}}` / `{{ name }}` with `encode(value)` in one pass, so
+/// a value that itself contains `{{other}}` is never expanded. Unknown
+/// names are left as written.
+fn substitute(
+    template: &str,
+    variables: &HashMap<String, String>,
+    encode: fn(&str) -> String,
+) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(open) = rest.find("{{") {
+        out.push_str(&rest[..open]);
+        let tag = &rest[open..];
+        let Some(close) = tag.find("}}") else {
+            out.push_str(tag);
+            return out;
+        };
+        let name = &tag[2..close];
+        let name = name
+            .strip_prefix(' ')
+            .and_then(|n| n.strip_suffix(' '))
+            .unwrap_or(name);
+        match variables.get(name) {
+            Some(value) => out.push_str(&encode(value)),
+            None => out.push_str(&tag[..close + 2]),
+        }
+        rest = &tag[close + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Percent-encode everything outside RFC 3986's unreserved set.
+fn encode_component(value: &str) -> String {
+    let mut out = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                out.push(byte as char)
+            }
+            _ => out.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    out
+}
+
+/// Escape for insertion inside a JSON string literal.
+fn json_escape(value: &str) -> String {
+    let quoted = Value::String(value.to_string()).to_string();
+    quoted[1..quoted.len() - 1].to_string()
+}
+
+fn shape(data: &Value, extract: &HashMap<String, String>) -> Value {
+    if extract.is_empty() {
+        return data.clone();
+    }
+    Value::Object(
+        extract
+            .iter()
+            .map(|(field, path)| (field.clone(), json_path(data, path)))
+            .collect(),
+    )
+}
+
+/// Evaluate a JSONPath subset: `$`, `.key`, `['key']`, `[n]` (negative
+/// counts from the end), `[*]` / `.*` wildcards and `..key` recursive
+/// descent. Wildcards and descent yield an array; a missing path is null.
+pub fn json_path(data: &Value, path: &str) -> Value {
+    #[derive(Debug)]
+    enum Step {
+        Key(String),
+        Index(i64),
+        Wildcard,
+        Descend(String),
+    }
+
+    let path = path.trim();
+    let mut rest = path.strip_prefix('$').unwrap_or(path);
+    let mut steps = Vec::new();
+    while !rest.is_empty() {
+        if let Some(after) = rest.strip_prefix("..") {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            steps.push(Step::Descend(after[..end].to_string()));
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('.') {
+            let end = after.find(['.', '[']).unwrap_or(after.len());
+            let key = &after[..end];
+            steps.push(if key == "*" {
+                Step::Wildcard
+            } else {
+                Step::Key(key.to_string())
+            });
+            rest = &after[end..];
+        } else if let Some(after) = rest.strip_prefix('[') {
+            let Some(end) = after.find(']') else {
+                return Value::Null;
+            };
+            let inner = after[..end].trim();
+            steps.push(if inner == "*" {
+                Step::Wildcard
+            } else if let Ok(index) = inner.parse::<i64>() {
+                Step::Index(index)
+            } else {
+                Step::Key(inner.trim_matches(|c| c == '\'' || c == '"').to_string())
+            });
+            rest = &after[end + 1..];
+        } else {
+            // Bare leading key, e.g. `items[0]`.
+            let end = rest.find(['.', '[']).unwrap_or(rest.len());
+            steps.push(Step::Key(rest[..end].to_string()));
+            rest = &rest[end..];
+        }
+    }
+
+    fn descend(value: &Value, key: &str, out: &mut Vec<Value>) {
+        match value {
+            Value::Object(map) => {
+                if let Some(found) = map.get(key) {
+                    out.push(found.clone());
+                }
+                map.values().for_each(|v| descend(v, key, out));
+            }
+            Value::Array(items) => items.iter().for_each(|v| descend(v, key, out)),
+            _ => {}
+        }
+    }
+
+    let mut current = vec![data.clone()];
+    let mut multi = false;
+    for step in &steps {
+        let mut next = Vec::new();
+        for value in &current {
+            match step {
+                Step::Key(key) => {
+                    if let Some(v) = value.get(key) {
+                        next.push(v.clone());
+                    }
+                }
+                Step::Index(index) => {
+                    if let Value::Array(items) = value {
+                        let i = if *index < 0 {
+                            items.len() as i64 + index
+                        } else {
+                            *index
+                        };
+                        if let Some(v) = usize::try_from(i).ok().and_then(|i| items.get(i)) {
+                            next.push(v.clone());
+                        }
+                    }
+                }
+                Step::Wildcard => match value {
+                    Value::Array(items) => next.extend(items.iter().cloned()),
+                    Value::Object(map) => next.extend(map.values().cloned()),
+                    _ => {}
+                },
+                Step::Descend(key) => descend(value, key, &mut next),
+            }
+        }
+        multi |= matches!(step, Step::Wildcard | Step::Descend(_));
+        current = next;
+    }
+
+    if multi {
+        Value::Array(current)
+    } else {
+        current.into_iter().next().unwrap_or(Value::Null)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::extract::{Path, Query};
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::{get, post};
+    use axum::Router;
+    use serde_json::json;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    fn source(url_template: String) -> ConnectedContentSource {
+        ConnectedContentSource {
+            id: Uuid::new_v4(),
+            name: "weather".to_string(),
+            url_template,
+            method: HttpMethod::Get,
+            headers: HashMap::new(),
+            cache_ttl_seconds: 0,
+            timeout_ms: 500,
+            fallback_value: Some(json!({"temp": "--"})),
+            retry_count: 0,
+            created_at: Utc::now(),
+            auth: ConnectedContentAuth::None,
+            body_template: None,
+            extract: HashMap::new(),
+        }
+    }
+
+    async fn mock_partner(calls: Arc<AtomicUsize>) -> String {
+        let flaky_calls = Arc::new(AtomicUsize::new(0));
+        let app = Router::new()
+            .route(
+                "/weather/:city",
+                get(
+                    |Path(city): Path<String>,
+                     Query(q): Query<HashMap<String, String>>,
+                     headers: HeaderMap| async move {
+                        if headers.get("authorization").and_then(|v| v.to_str().ok())
+                            != Some("Bearer s3cret")
+                        {
+                            return (StatusCode::UNAUTHORIZED, axum::Json(json!({})));
+                        }
+                        (
+                            StatusCode::OK,
+                            axum::Json(json!({
+                                "city": city,
+                                "unit": q.get("unit"),
+                                "forecast": [{"temp": 21, "sky": "sunny"}, {"temp": 17, "sky": "rain"}],
+                            })),
+                        )
+                    },
+                ),
+            )
+            .route(
+                "/slow",
+                get(move || {
+                    let calls = calls.clone();
+                    async move {
+                        calls.fetch_add(1, Ordering::SeqCst);
+                        tokio::time::sleep(Duration::from_millis(300)).await;
+                        axum::Json(json!({"temp": 30}))
+                    }
+                }),
+            )
+            .route(
+                "/echo",
+                post(|body: String| async move { body }),
+            )
+            .route(
+                "/flaky",
+                get(move || {
+                    let flaky_calls = flaky_calls.clone();
+                    async move {
+                        if flaky_calls.fetch_add(1, Ordering::SeqCst) < 2 {
+                            return (StatusCode::SERVICE_UNAVAILABLE, axum::Json(json!({})));
+                        }
+                        (StatusCode::OK, axum::Json(json!({"temp": 12})))
+                    }
+                }),
+            );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_fetch_substitutes_encoded_variables_with_auth_and_extraction() {
+        let base = mock_partner(Arc::new(AtomicUsize::new(0))).await;
+        let engine = ConnectedContentEngine::new();
+        engine.set_secret("weather_token", "s3cret");
+        let mut src = source(format!("{}/weather/{{{{city}}}}?unit={{{{ unit }}}}", base));
+        src.auth = ConnectedContentAuth::Bearer {
+            secret: "weather_token".to_string(),
+        };
+        src.extract = HashMap::from([
+            ("city".to_string(), "$.city".to_string()),
+            ("today".to_string(), "$.forecast[0].temp".to_string()),
+            ("skies".to_string(), "$.forecast[*].sky".to_string()),
+        ]);
+        let id = src.id;
+        engine.register_source(src);
+
+        let vars = HashMap::from([
+            ("city".to_string(), "São Paulo/BR".to_string()),
+            ("unit".to_string(), "c&f".to_string()),
+        ]);
+        let result = engine.fetch(&id, &vars).await.unwrap();
+        assert!(!result.fallback, "{:?}", result.error);
+        assert_eq!(
+            result.data,
+            json!({"city": "São Paulo/BR", "today": 21, "skies": ["sunny", "rain"]})
+        );
+
+        // Missing secret: the request is never authorized, so fall back.
+        assert!(engine.remove_secret("weather_token"));
+        let result = engine.fetch(&id, &vars).await.unwrap();
+        assert!(result.fallback);
+        assert_eq!(result.data, json!({"temp": "--"}));
+        assert!(result.error.unwrap().contains("weather_token"));
+    }
+
+    #[tokio::test]
+    async fn test_post_body_is_json_escaped() {
+        let base = mock_partner(Arc::new(AtomicUsize::new(0))).await;
+        let engine = ConnectedContentEngine::new();
+        let mut src = source(format!("{}/echo", base));
+        src.method = HttpMethod::Post;
+        src.body_template = Some(r#"{"name": "{{name}}"}"#.to_string());
+        let id = src.id;
+        engine.register_source(src);
+
+        let vars = HashMap::from([("name".to_string(), "Ann \"The\" Coder".to_string())]);
+        let result = engine.fetch(&id, &vars).await.unwrap();
+        assert_eq!(result.data, json!({"name": "Ann \"The\" Coder"}));
+    }
+
+    #[tokio::test]
+    async fn test_timeouts_fall_back_and_open_the_circuit() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let base = mock_partner(calls.clone()).await;
+        let engine = ConnectedContentEngine::new().with_circuit_breaker(CircuitBreakerConfig {
+            failure_threshold: 2,
+            open_duration_secs: 60,
+            half_open_successes: 1,
+        });
+        let mut src = source(format!("{}/slow", base));
+        src.timeout_ms = 50;
+        let id = src.id;
+        engine.register_source(src);
+
+        for _ in 0..4 {
+            let result = engine.fetch(&id, &HashMap::new()).await.unwrap();
+            assert!(result.fallback);
+            assert_eq!(result.data, json!({"temp": "--"}));
+        }
+        // Two timeouts opened the circuit; later sends skip the partner.
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+        let result = engine.fetch(&id, &HashMap::new()).await.unwrap();
+        assert!(result.error.unwrap().contains("circuit open"));
+        assert!(result.latency_ms < 50);
+    }
+
+    #[tokio::test]
+    async fn test_retries_back_off_exponentially() {
+        let base = mock_partner(Arc::new(AtomicUsize::new(0))).await;
+        let engine = ConnectedContentEngine::new().with_retry_backoff(Duration::from_millis(40));
+        let mut src = source(format!("{}/flaky", base));
+        src.retry_count = 3;
+        let id = src.id;
+        engine.register_source(src);
+
+        let result = engine.fetch(&id, &HashMap::new()).await.unwrap();
+        assert!(!result.fallback, "{:?}", result.error);
+        assert_eq!(result.data, json!({"temp": 12}));
+        // Two failures waited 40ms and then 80ms before the third attempt.
+        assert!(result.latency_ms >= 120, "latency {}", result.latency_ms);
+    }
+
+    #[tokio::test]
+    async fn test_fetch_deadline_caps_all_retries() {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let base = mock_partner(calls.clone()).await;
+        let engine = ConnectedContentEngine::new()
+            .with_retry_backoff(Duration::from_millis(10))
+            .with_fetch_deadline(Duration::from_millis(150));
+        let mut src = source(format!("{}/slow", base));
+        src.timeout_ms = 100;
+        src.retry_count = 10;
+        let id = src.id;
+        engine.register_source(src);
+
+        let result = engine.fetch(&id, &HashMap::new()).await.unwrap();
+        assert!(result.fallback);
+        assert!(result.error.unwrap().contains("deadline"));
+        assert!(result.latency_ms < 300, "latency {}", result.latency_ms);
+        assert!(calls.load(Ordering::SeqCst) <= 2);
+    }
+
+    #[tokio::test]
+    async fn test_cache_is_bounded_and_evicts_least_recently_used() {
+        let base = mock_partner(Arc::new(AtomicUsize::new(0))).await;
+        let engine = ConnectedContentEngine::new().with_max_cache_entries(2);
+        engine.set_secret("weather_token", "s3cret");
+        let mut src = source(format!("{}/weather/{{{{city}}}}", base));
+        src.cache_ttl_seconds = 300;
+        src.auth = ConnectedContentAuth::Bearer {
+            secret: "weather_token".to_string(),
+        };
+        let id = src.id;
+        engine.register_source(src);
+
+        let city = |name: &str| HashMap::from([("city".to_string(), name.to_string())]);
+        for name in ["oslo", "lima", "pune"] {
+            let result = engine.fetch(&id, &city(name)).await.unwrap();
+            assert!(!result.cached);
+        }
+        assert_eq!(engine.cache_len(), 2);
+        assert!(engine.fetch(&id, &city("pune")).await.unwrap().cached);
+        assert!(!engine.fetch(&id, &city("oslo")).await.unwrap().cached);
+        assert_eq!(engine.cache_len(), 2);
+
+        // Reads keep an entry alive: pune is read before each miss, so the
+        // other entry is the one evicted.
+        assert!(engine.fetch(&id, &city("pune")).await.unwrap().cached);
+        assert!(!engine.fetch(&id, &city("lima")).await.unwrap().cached);
+        assert!(engine.fetch(&id, &city("pune")).await.unwrap().cached);
+        assert!(!engine.fetch(&id, &city("oslo")).await.unwrap().cached);
+    }
+
+    #[test]
+    fn test_substitute_never_expands_values() {
+        let vars = HashMap::from([
+            ("name".to_string(), "{{secret}}".to_string()),
+            ("secret".to_string(), "s3cret".to_string()),
+        ]);
+        assert_eq!(
+            substitute(
+                r#"{"name": "{{ name }}", "other": "{{unknown}}", "tail": "{{"#,
+                &vars,
+                json_escape
+            ),
+            r#"{"name": "{{secret}}", "other": "{{unknown}}", "tail": "{{"#
+        );
+    }
+
+    #[test]
+    fn test_json_path() {
+        let data = json!({
+            "store": {
+                "book": [
+                    {"title": "A", "price": 8.95},
+                    {"title": "B", "price": 12.99, "isbn": "x"},
+                ],
+                "bicycle": {"price": 19.95},
+            },
+            "odd key": true,
+        });
+        assert_eq!(json_path(&data, "$.store.book[1].title"), json!("B"));
+        assert_eq!(json_path(&data, "$.store.book[-1].price"), json!(12.99));
+        assert_eq!(json_path(&data, "$['odd key']"), json!(true));
+        assert_eq!(json_path(&data, "store.book[*].title"), json!(["A", "B"]));
+        assert_eq!(json_path(&data, "$..price"), json!([19.95, 8.95, 12.99]));
+        assert_eq!(json_path(&data, "$.store.missing"), Value::Null);
+        assert_eq!(json_path(&data, "$"), data);
+    }
+}
@@ -2,13 +2,33 @@
 //! explainability, and simulation mode.
 //!
 //! Addresses FR-1TO1-001 through FR-1TO1-005.
+//!
+//! Offers are allocated under business constraints: inventory and budget
+//! caps, minimum exposure shares, mutual-exclusion groups and per-category
+//! diversity. A batch of requests is allocated jointly, so scarce offers go
+//! where they add the most value. Each pick reserves its serve against the
+//! engine's shared usage under the offer's map entry lock, so concurrent
+//! decisions cannot oversell a cap.
+//!
+//! With exploration enabled, every served offer carries the propensity
+//! with which the logging policy chose it. Live decisions are logged with
+//! their candidates so [`crate::policy_evaluation`] can replay them against
+//! a candidate policy before it is rolled out. Only the most recent
+//! decisions are kept; older ones are evicted first.
 
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
+use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
 use tracing::info;
 use uuid::Uuid;
 
+use crate::policy_evaluation::{self, OfflinePolicy, PolicyEvaluation};
+
 // ─── Decision Request / Response (FR-1TO1-001) ───────────────────────
 
 /// A real-time decision request for 1:1 personalization.
@@ -27,6 +47,11 @@ pub struct DecisionRequest {
     pub simulate: bool,
     pub timeout_ms: u64,
     pub requested_at: DateTime<Utc>,
+    /// Most offers from one category in a single response.
+    #[serde(default)]
+    pub max_per_category: Option<u32>,
+    #[serde(default)]
+    pub allocator: AllocatorKind,
 }
 
 /// Contextual information for decision-making.
@@ -51,6 +76,13 @@ pub struct DecisionOffer {
     pub rank: u32,
     pub creative_id: Option<String>,
     pub explanation: Option<DecisionExplanation>,
+    /// Probability that the logging policy served this offer at this rank.
+    #[serde(default = "default_propensity")]
+    pub propensity: f64,
+}
+
+fn default_propensity() -> f64 {
+    1.0
 }
 
 /// Full decision response.
@@ -87,6 +119,17 @@ pub enum ObjectiveMetric {
     Retention,
 }
 
+impl ObjectiveMetric {
+    pub const ALL: [ObjectiveMetric; 6] = [
+        ObjectiveMetric::ClickThroughRate,
+        ObjectiveMetric::ConversionRate,
+        ObjectiveMetric::Revenue,
+        ObjectiveMetric::LifetimeValue,
+        ObjectiveMetric::Engagement,
+        ObjectiveMetric::Retention,
+    ];
+}
+
 /// Internal scored candidate during multi-objective optimization.
 #[derive(Debug, Clone)]
 struct ScoredCandidate {
@@ -96,6 +139,104 @@ struct ScoredCandidate {
     blended_score: f64,
 }
 
+// ─── Propensity Logging ─────────────────────────────────────────────
+
+/// Live decisions kept for policy evaluation by default.
+pub const DEFAULT_PROPENSITY_LOG_CAPACITY: usize = 100_000;
+
+/// An eligible offer as the logging policy saw it, scored on every metric
+/// so policies with other objective weights can be replayed.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedCandidate {
+    pub offer_id: String,
+    pub objective_scores: HashMap<ObjectiveMetric, f64>,
+    pub blended_score: f64,
+}
+
+/// A served offer with its propensity and observed outcome.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LoggedImpression {
+    pub offer_id: String,
+    pub rank: u32,
+    pub propensity: f64,
+    pub clicked: bool,
+    pub revenue: f64,
+}
+
+/// Everything needed to evaluate another policy on one live decision.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DecisionLogEntry {
+    pub decision_id: Uuid,
+    pub user_id: String,
+    pub channel: String,
+    pub context: DecisionContext,
+    pub objectives: Vec<OptimizationObjective>,
+    pub exploration: f64,
+    pub candidates: Vec<LoggedCandidate>,
+    pub impressions: Vec<LoggedImpression>,
+    pub logged_at: DateTime<Utc>,
+}
+
+// ─── Constrained Allocation ─────────────────────────────────────────
+
+/// How slots are filled once candidates are scored.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AllocatorKind {
+    /// Fill the highest-scoring (request, offer) pairs first.
+    #[default]
+    Greedy,
+    /// Solve the LP relaxation of the capacity constraints by dual
+    /// (shadow-price) subgradient steps within half the latency budget,
+    /// then fill greedily on price-adjusted scores. Falls back to greedy
+    /// when the budget leaves no time to iterate.
+    DualPrice,
+}
+
+/// Business constraints on an offer.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OfferConstraints {
+    /// Total number of times the offer may be served.
+    pub inventory: Option<u64>,
+    /// Total spend allowed; each serve costs `cost_per_serve`.
+    pub budget: Option<f64>,
+    #[serde(default)]
+    pub cost_per_serve: f64,
+    /// Minimum share (0–1) of decisions that must include this offer.
+    pub min_exposure: Option<f64>,
+    /// At most one offer from a group is shown in a single decision.
+    pub exclusion_group: Option<String>,
+    /// Category counted by `DecisionRequest::max_per_category`.
+    pub category: Option<String>,
+}
+
+/// What an offer has consumed so far.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct OfferUsage {
+    pub served: u64,
+    pub spent: f64,
+}
+
+/// Constraint that shaped a choice.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ConstraintKind {
+    Inventory,
+    Budget,
+    MinExposure,
+    MutualExclusion,
+    Diversity,
+}
+
+/// A constraint that bound a choice: either it forced the chosen offer in
+/// (`MinExposure`) or it kept a higher-scoring `offer_id` out.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BindingConstraint {
+    pub constraint: ConstraintKind,
+    pub offer_id: String,
+    pub detail: String,
+}
+
 // ─── Explainability (FR-1TO1-003) ────────────────────────────────────
 
 /// Explanation of why an offer was selected.
@@ -104,6 +245,8 @@ pub struct DecisionExplanation {
     pub factors: Vec<ExplanationFactor>,
     pub model_confidence: f64,
     pub exploration_bonus: f64,
+    #[serde(default)]
+    pub binding_constraints: Vec<BindingConstraint>,
 }
 
 /// A single factor contributing to the decision.
@@ -170,6 +313,8 @@ pub struct OfferCandidate {
     pub base_scores: std::collections::HashMap<String, f64>,
     pub channel: String,
     pub active: bool,
+    #[serde(default)]
+    pub constraints: OfferConstraints,
 }
 
 /// Real-time decision engine.
@@ -177,6 +322,16 @@ pub struct DecisionEngine {
     offers: DashMap<String, OfferCandidate>,
     decision_log: DashMap<Uuid, DecisionResponse>,
     model_version: String,
+    usage: DashMap<String, OfferUsage>,
+    /// Non-simulated decisions made, the denominator of exposure shares.
+    decisions_made: AtomicU64,
+    propensity_log: DashMap<Uuid, DecisionLogEntry>,
+    /// Logged decision ids, oldest first, for evicting past the capacity.
+    propensity_order: Mutex<VecDeque<Uuid>>,
+    propensity_log_capacity: usize,
+    /// Per-slot probability of serving a uniformly random admissible offer
+    /// instead of the allocated one.
+    exploration: f64,
 }
 
 impl DecisionEngine {
@@ -186,9 +341,32 @@ impl DecisionEngine {
             offers: DashMap::new(),
             decision_log: DashMap::new(),
             model_version: "v1.0.0".to_string(),
+            usage: DashMap::new(),
+            decisions_made: AtomicU64::new(0),
+            propensity_log: DashMap::new(),
+            propensity_order: Mutex::new(VecDeque::new()),
+            propensity_log_capacity: DEFAULT_PROPENSITY_LOG_CAPACITY,
+            exploration: 0.0,
         }
     }
 
+    /// Explore with probability `epsilon` per slot. Without exploration
+    /// every propensity is 1 and only the logging policy can be evaluated.
+    pub fn with_exploration(mut self, epsilon: f64) -> Self {
+        self.exploration = epsilon.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Keep at most `capacity` logged decisions, evicting the oldest.
+    pub fn with_propensity_log_capacity(mut self, capacity: usize) -> Self {
+        self.propensity_log_capacity = capacity;
+        self
+    }
+
+    pub fn offer_usage(&self, offer_id: &str) -> OfferUsage {
+        self.usage.get(offer_id).map(|u| *u).unwrap_or_default()
+    }
+
     /// Register an offer candidate for decisioning.
     pub fn register_offer(&self, offer: OfferCandidate) {
         self.offers.insert(offer.offer_id.clone(), offer);
@@ -196,27 +374,245 @@ impl DecisionEngine {
 
     /// Execute a real-time decision.
     pub fn decide(&self, request: &DecisionRequest) -> DecisionResponse {
-        let start = std::time::Instant::now();
+        self.decide_batch(std::slice::from_ref(request))
+            .pop()
+            .expect("one response per request")
+    }
 
-        // Filter eligible offers
-        let candidates: Vec<OfferCandidate> = self
+    /// Allocate offers for a batch of requests jointly. Caps and exposure
+    /// guarantees hold across the batch; the latency budget is the
+    /// smallest `timeout_ms` and the allocator is the first request's.
+    pub fn decide_batch(&self, requests: &[DecisionRequest]) -> Vec<DecisionResponse> {
+        let start = Instant::now();
+        let Some(first) = requests.first() else {
+            return Vec::new();
+        };
+        let budget =
+            Duration::from_millis(requests.iter().map(|r| r.timeout_ms).min().unwrap_or(0));
+
+        let offers: HashMap<String, OfferCandidate> = self
             .offers
             .iter()
-            .filter(|e| {
-                let o = e.value();
-                o.active
-                    && o.channel == request.channel
+            .filter(|e| e.value().active)
+            .map(|e| (e.key().clone(), e.value().clone()))
+            .collect();
+        let scored: Vec<Vec<ScoredCandidate>> = requests
+            .iter()
+            .map(|r| self.score_candidates(r, &offers))
+            .collect();
+
+        let mut allocation = Allocation::new(self, &offers, requests, &scored);
+        if first.allocator == AllocatorKind::DualPrice {
+            allocation.solve_prices(start + budget / 2);
+        }
+        allocation.guarantee_exposure();
+        allocation.fill();
+        for (picks, candidates) in allocation.chosen.iter_mut().zip(&scored) {
+            picks.sort_by(|a, b| {
+                candidates[b.index]
+                    .blended_score
+                    .partial_cmp(&candidates[a.index].blended_score)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            });
+        }
+        let propensities = allocation.explore(self.exploration);
+        let Allocation { chosen, prices, .. } = allocation;
+
+        let latency = start.elapsed().as_millis() as u64;
+        let mut responses = Vec::with_capacity(requests.len());
+        for (((request, candidates), picks), propensities) in
+            requests.iter().zip(&scored).zip(chosen).zip(propensities)
+        {
+            let picked: HashSet<&str> = picks
+                .iter()
+                .map(|p| candidates[p.index].offer_id.as_str())
+                .collect();
+
+            let offers_out = picks
+                .into_iter()
+                .zip(propensities)
+                .enumerate()
+                .map(|(i, (pick, propensity))| {
+                    let s = &candidates[pick.index];
+                    let explanation = request.explain.then(|| {
+                        let mut binding = pick.binding;
+                        // Priced-out offers: better raw scores that were
+                        // reserved for requests where they add more value.
+                        for other in candidates.iter().filter(|c| {
+                            c.blended_score > s.blended_score
+                                && !picked.contains(c.offer_id.as_str())
+                        }) {
+                            if let Some((kind, price)) = prices.get(&other.offer_id) {
+                                if *price > 0.0
+                                    && !binding.iter().any(|b| b.offer_id == other.offer_id)
+                                {
+                                    binding.push(BindingConstraint {
+                                        constraint: *kind,
+                                        offer_id: other.offer_id.clone(),
+                                        detail: format!(
+                                            "{} reserved for higher-value decisions (shadow price {:.3})",
+                                            other.offer_id, price
+                                        ),
+                                    });
+                                }
+                            }
+                        }
+                        self.build_explanation(s, &request.context, binding)
+                    });
+                    DecisionOffer {
+                        offer_id: s.offer_id.clone(),
+                        score: s.blended_score,
+                        rank: i as u32 + 1,
+                        creative_id: s.creative_id.clone(),
+                        explanation,
+                        propensity,
+                    }
+                })
+                .collect::<Vec<_>>();
+
+            if !request.simulate {
+                self.decisions_made.fetch_add(1, Ordering::Relaxed);
+            }
+
+            let decision_id = Uuid::new_v4();
+            let response = DecisionResponse {
+                decision_id,
+                request_id: request.request_id,
+                user_id: request.user_id.clone(),
+                offers: offers_out,
+                latency_ms: latency,
+                model_version: self.model_version.clone(),
+                is_simulation: request.simulate,
+                decided_at: Utc::now(),
+            };
+
+            // Log decision (skip for simulations)
+            if !request.simulate {
+                self.decision_log.insert(decision_id, response.clone());
+                self.log_propensities(request, &response, candidates, &offers);
+            }
+            responses.push(response);
+        }
+        responses
+    }
+
+    fn log_propensities(
+        &self,
+        request: &DecisionRequest,
+        response: &DecisionResponse,
+        candidates: &[ScoredCandidate],
+        offers: &HashMap<String, OfferCandidate>,
+    ) {
+        let candidates = candidates
+            .iter()
+            .map(|c| LoggedCandidate {
+                offer_id: c.offer_id.clone(),
+                objective_scores: ObjectiveMetric::ALL
+                    .iter()
+                    .map(|m| {
+                        let score = self.score_objective(&offers[&c.offer_id], m, &request.context);
+                        (m.clone(), score)
+                    })
+                    .collect(),
+                blended_score: c.blended_score,
+            })
+            .collect();
+        let impressions = response
+            .offers
+            .iter()
+            .map(|o| LoggedImpression {
+                offer_id: o.offer_id.clone(),
+                rank: o.rank,
+                propensity: o.propensity,
+                clicked: false,
+                revenue: 0.0,
+            })
+            .collect();
+        self.propensity_log.insert(
+            response.decision_id,
+            DecisionLogEntry {
+                decision_id: response.decision_id,
+                user_id: request.user_id.clone(),
+                channel: request.channel.clone(),
+                context: request.context.clone(),
+                objectives: request.objectives.clone(),
+                exploration: self.exploration,
+                candidates,
+                impressions,
+                logged_at: response.decided_at,
+            },
+        );
+        let evicted: Vec<Uuid> = {
+            let mut order = self
+                .propensity_order
+                .lock()
+                .expect("propensity log order mutex poisoned");
+            order.push_back(response.decision_id);
+            let excess = order.len().saturating_sub(self.propensity_log_capacity);
+            order.drain(..excess).collect()
+        };
+        for decision_id in &evicted {
+            self.propensity_log.remove(decision_id);
+        }
+    }
+
+    /// Record the outcome of a served offer. Returns false when the
+    /// decision or offer is unknown.
+    pub fn record_outcome(
+        &self,
+        decision_id: &Uuid,
+        offer_id: &str,
+        clicked: bool,
+        revenue: f64,
+    ) -> bool {
+        let Some(mut entry) = self.propensity_log.get_mut(decision_id) else {
+            return false;
+        };
+        match entry
+            .impressions
+            .iter_mut()
+            .find(|i| i.offer_id == offer_id)
+        {
+            Some(impression) => {
+                impression.clicked |= clicked;
+                impression.revenue += revenue;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Logged live decisions, oldest first.
+    pub fn propensity_logs(&self) -> Vec<DecisionLogEntry> {
+        let mut logs: Vec<DecisionLogEntry> = self
+            .propensity_log
+            .iter()
+            .map(|e| e.value().clone())
+            .collect();
+        logs.sort_by_key(|e| e.logged_at);
+        logs
+    }
+
+    /// Estimate how `policy` would have performed on the logged traffic.
+    pub fn evaluate_policy(&self, policy: &dyn OfflinePolicy) -> PolicyEvaluation {
+        policy_evaluation::evaluate(&self.propensity_logs(), policy)
+    }
+
+    /// Eligible offers for one request, scored by multi-objective blending.
+    fn score_candidates(
+        &self,
+        request: &DecisionRequest,
+        offers: &HashMap<String, OfferCandidate>,
+    ) -> Vec<ScoredCandidate> {
+        offers
+            .values()
+            .filter(|o| {
+                o.channel == request.channel
                     && (o.eligible_segments.is_empty()
                         || o.eligible_segments
                             .iter()
                             .any(|s| request.context.user_segments.contains(s)))
             })
-            .map(|e| e.value().clone())
-            .collect();
-
-        // Score each candidate using multi-objective blending
-        let mut scored: Vec<ScoredCandidate> = candidates
-            .iter()
             .map(|c| {
                 let mut obj_scores = std::collections::HashMap::new();
                 let mut blended = 0.0;
@@ -238,57 +634,7 @@ impl DecisionEngine {
                     blended_score: blended,
                 }
             })
-            .collect();
-
-        // Sort descending by blended score
-        scored.sort_by(|a, b| {
-            b.blended_score
-                .partial_cmp(&a.blended_score)
-                .unwrap_or(std::cmp::Ordering::Equal)
-        });
-
-        // Take top N
-        let top_n = scored
-            .into_iter()
-            .take(request.num_offers as usize)
-            .enumerate()
-            .map(|(i, s)| {
-                let explanation = if request.explain {
-                    Some(self.build_explanation(&s, &request.context))
-                } else {
-                    None
-                };
-
-                DecisionOffer {
-                    offer_id: s.offer_id,
-                    score: s.blended_score,
-                    rank: i as u32 + 1,
-                    creative_id: s.creative_id,
-                    explanation,
-                }
-            })
-            .collect();
-
-        let latency = start.elapsed().as_millis() as u64;
-        let decision_id = Uuid::new_v4();
-
-        let response = DecisionResponse {
-            decision_id,
-            request_id: request.request_id,
-            user_id: request.user_id.clone(),
-            offers: top_n,
-            latency_ms: latency,
-            model_version: self.model_version.clone(),
-            is_simulation: request.simulate,
-            decided_at: Utc::now(),
-        };
-
-        // Log decision (skip for simulations)
-        if !request.simulate {
-            self.decision_log.insert(decision_id, response.clone());
-        }
-
-        response
+            .collect()
     }
 
     /// Score a single offer for a specific objective.
@@ -327,6 +673,7 @@ impl DecisionEngine {
         &self,
         scored: &ScoredCandidate,
         context: &DecisionContext,
+        binding_constraints: Vec<BindingConstraint>,
     ) -> DecisionExplanation {
         let mut factors = Vec::new();
 
@@ -360,10 +707,20 @@ impl DecisionEngine {
             });
         }
 
+        for binding in &binding_constraints {
+            factors.push(ExplanationFactor {
+                name: format!("{:?}", binding.constraint),
+                category: FactorCategory::BusinessRule,
+                contribution: 0.0,
+                description: binding.detail.clone(),
+            });
+        }
+
         DecisionExplanation {
             factors,
             model_confidence: 0.85,
             exploration_bonus: 0.01,
+            binding_constraints,
         }
     }
 
@@ -420,6 +777,467 @@ impl DecisionEngine {
     }
 }
 
+/// One chosen offer: an index into the request's scored candidates.
+struct Pick {
+    index: usize,
+    binding: Vec<BindingConstraint>,
+}
+
+/// Remaining serves an offer can take right now; `None` is uncapped.
+#[derive(Clone, Copy)]
+struct Capacity {
+    serves: Option<u64>,
+    kind: ConstraintKind,
+}
+
+/// Mutable state of one batch allocation.
+struct Allocation<'a> {
+    engine: &'a DecisionEngine,
+    offers: &'a HashMap<String, OfferCandidate>,
+    requests: &'a [DecisionRequest],
+    scored: &'a [Vec<ScoredCandidate>],
+    capacity: HashMap<String, Capacity>,
+    /// Shadow price per capped offer, with the cap it prices.
+    prices: HashMap<String, (ConstraintKind, f64)>,
+    chosen: Vec<Vec<Pick>>,
+    groups: Vec<HashSet<String>>,
+    categories: Vec<HashMap<String, u32>>,
+    /// Constraints that rejected a better offer since the request's last
+    /// pick; they become that next pick's binding constraints.
+    pending: Vec<Vec<BindingConstraint>>,
+    usage: HashMap<String, OfferUsage>,
+    decisions_made: u64,
+}
+
+impl<'a> Allocation<'a> {
+    fn new(
+        engine: &'a DecisionEngine,
+        offers: &'a HashMap<String, OfferCandidate>,
+        requests: &'a [DecisionRequest],
+        scored: &'a [Vec<ScoredCandidate>],
+    ) -> Self {
+        let usage: HashMap<String, OfferUsage> = offers
+            .keys()
+            .map(|id| (id.clone(), engine.offer_usage(id)))
+            .collect();
+        let capacity = offers
+            .values()
+            .map(|o| {
+                let used = usage[&o.offer_id];
+                let by_inventory = o
+                    .constraints
+                    .inventory
+                    .map(|cap| cap.saturating_sub(used.served));
+                let by_budget = o.constraints.budget.map(|budget| {
+                    let left = (budget - used.spent).max(0.0);
+                    if o.constraints.cost_per_serve > 0.0 {
+                        // Tolerate float drift on exact multiples.
+                        (left / o.constraints.cost_per_serve + 1e-9).floor() as u64
+                    } else {
+                        u64::MAX
+                    }
+                });
+                let capacity = match (by_inventory, by_budget) {
+                    (Some(i), Some(b)) if b < i => Capacity {
+                        serves: Some(b),
+                        kind: ConstraintKind::Budget,
+                    },
+                    (Some(i), _) => Capacity {
+                        serves: Some(i),
+                        kind: ConstraintKind::Inventory,
+                    },
+                    (None, Some(b)) => Capacity {
+                        serves: Some(b),
+                        kind: ConstraintKind::Budget,
+                    },
+                    (None, None) => Capacity {
+                        serves: None,
+                        kind: ConstraintKind::Inventory,
+                    },
+                };
+                (o.offer_id.clone(), capacity)
+            })
+            .collect();
+        let n = requests.len();
+        Self {
+            engine,
+            offers,
+            requests,
+            scored,
+            capacity,
+            prices: HashMap::new(),
+            chosen: (0..n).map(|_| Vec::new()).collect(),
+            groups: vec![HashSet::new(); n],
+            categories: vec![HashMap::new(); n],
+            pending: vec![Vec::new(); n],
+            usage,
+            decisions_made: engine.decisions_made.load(Ordering::Relaxed),
+        }
+    }
+
+    fn slots(&self, r: usize) -> usize {
+        self.requests[r].num_offers as usize
+    }
+
+    fn price(&self, offer_id: &str) -> f64 {
+        self.prices.get(offer_id).map(|(_, p)| *p).unwrap_or(0.0)
+    }
+
+    /// Subgradient ascent on the dual of the capacity constraints: raise
+    /// the price of each over-demanded offer until demand fits its cap.
+    fn solve_prices(&mut self, deadline: Instant) {
+        let capped: Vec<(String, u64, ConstraintKind)> = self
+            .capacity
+            .iter()
+            .filter_map(|(id, c)| c.serves.map(|s| (id.clone(), s, c.kind)))
+            .collect();
+        if capped.is_empty() {
+            return;
+        }
+        for (id, _, kind) in &capped {
+            self.prices.insert(id.clone(), (*kind, 0.0));
+        }
+
+        for iteration in 0..200 {
+            if Instant::now() >= deadline {
+                break;
+            }
+            // Demand: how many requests would take each offer at these
+            // prices, ignoring everything but slot counts.
+            let mut demand: HashMap<&str, u64> = HashMap::new();
+            for (r, candidates) in self.scored.iter().enumerate() {
+                let mut adjusted: Vec<(&str, f64)> = candidates
+                    .iter()
+                    .map(|c| {
+                        (
+                            c.offer_id.as_str(),
+                            c.blended_score - self.price(&c.offer_id),
+                        )
+                    })
+                    .filter(|(_, v)| *v > 0.0)
+                    .collect();
+                adjusted.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+                for (id, _) in adjusted.into_iter().take(self.slots(r)) {
+                    *demand.entry(id).or_default() += 1;
+                }
+            }
+
+            let step = 0.1 / ((iteration + 1) as f64).sqrt();
+            let mut feasible = true;
+            for (id, cap, _) in &capped {
+                let excess = demand.get(id.as_str()).copied().unwrap_or(0) as f64 - *cap as f64;
+                feasible &= excess <= 0.0;
+                if let Some((_, price)) = self.prices.get_mut(id) {
+                    *price = (*price + step * excess).max(0.0);
+                }
+            }
+            if feasible {
+                break;
+            }
+        }
+    }
+
+    /// Check whether request `r` may take candidate `c`; on refusal, the
+    /// constraint and a human-readable reason.
+    fn admissible(&self, r: usize, c: &ScoredCandidate) -> Result<(), (ConstraintKind, String)> {
+        let offer = &self.offers[&c.offer_id];
+        if let Some(group) = &offer.constraints.exclusion_group {
+            if self.groups[r].contains(group) {
+                return Err((
+                    ConstraintKind::MutualExclusion,
+                    format!(
+                        "{} shares exclusion group '{}' with a chosen offer",
+                        c.offer_id, group
+                    ),
+                ));
+            }
+        }
+        if let (Some(category), Some(max)) = (
+            &offer.constraints.category,
+            self.requests[r].max_per_category,
+        ) {
+            if self.categories[r].get(category).copied().unwrap_or(0) >= max {
+                return Err((
+                    ConstraintKind::Diversity,
+                    format!(
+                        "{} would exceed {} offer(s) from category '{}'",
+                        c.offer_id, max, category
+                    ),
+                ));
+            }
+        }
+        let capacity = self.capacity[&c.offer_id];
+        if capacity.serves == Some(0) {
+            let what = match capacity.kind {
+                ConstraintKind::Budget => "budget",
+                _ => "inventory",
+            };
+            return Err((capacity.kind, format!("{} {} exhausted", c.offer_id, what)));
+        }
+        Ok(())
+    }
+
+    /// Pick candidate `index` for request `r`. Returns false, recording
+    /// the exhausted cap as pending, when a concurrent decision took the
+    /// last serve first.
+    fn take(&mut self, r: usize, index: usize, mut binding: Vec<BindingConstraint>) -> bool {
+        if !self.claim(r, index) {
+            let c = &self.scored[r][index];
+            let capacity = self.capacity[&c.offer_id];
+            let what = match capacity.kind {
+                ConstraintKind::Budget => "budget",
+                _ => "inventory",
+            };
+            self.pending[r].push(BindingConstraint {
+                constraint: capacity.kind,
+                offer_id: c.offer_id.clone(),
+                detail: format!("{} {} exhausted", c.offer_id, what),
+            });
+            return false;
+        }
+        binding.append(&mut self.pending[r]);
+        self.chosen[r].push(Pick { index, binding });
+        true
+    }
+
+    /// Reserve a serve of `offer_id` against the engine's shared usage.
+    /// The check and the increment happen under the offer's entry lock, so
+    /// two decisions can never both take the last unit. Simulations only
+    /// count against this batch's local capacity.
+    fn reserve(&self, r: usize, offer_id: &str) -> bool {
+        if self.requests[r].simulate {
+            return true;
+        }
+        let constraints = &self.offers[offer_id].constraints;
+        let cost = constraints.cost_per_serve;
+        let mut usage = self.engine.usage.entry(offer_id.to_string()).or_default();
+        let over_inventory = constraints.inventory.is_some_and(|cap| usage.served >= cap);
+        // Same float tolerance as the capacity computed in `new`.
+        let over_budget = constraints
+            .budget
+            .is_some_and(|budget| usage.spent + cost > budget + 1e-9);
+        if over_inventory || over_budget {
+            return false;
+        }
+        usage.served += 1;
+        usage.spent += cost;
+        true
+    }
+
+    /// Undo [`Self::reserve`].
+    fn unreserve(&self, r: usize, offer_id: &str) {
+        if self.requests[r].simulate {
+            return;
+        }
+        let cost = self.offers[offer_id].constraints.cost_per_serve;
+        if let Some(mut usage) = self.engine.usage.get_mut(offer_id) {
+            usage.served = usage.served.saturating_sub(1);
+            usage.spent = (usage.spent - cost).max(0.0);
+        }
+    }
+
+    /// Reserve a pick's serve and consume its capacity, exclusion group and
+    /// category slot. When the shared cap is already exhausted, the local
+    /// capacity is zeroed so later picks skip the offer.
+    fn claim(&mut self, r: usize, index: usize) -> bool {
+        let offer_id = self.scored[r][index].offer_id.clone();
+        if !self.reserve(r, &offer_id) {
+            if let Some(capacity) = self.capacity.get_mut(&offer_id) {
+                capacity.serves = Some(0);
+            }
+            return false;
+        }
+        self.occupy(r, index);
+        true
+    }
+
+    /// Consume the local capacity, exclusion group and category slot.
+    fn occupy(&mut self, r: usize, index: usize) {
+        let c = &self.scored[r][index];
+        let offer = &self.offers[&c.offer_id];
+        if let Some(group) = &offer.constraints.exclusion_group {
+            self.groups[r].insert(group.clone());
+        }
+        if let Some(category) = &offer.constraints.category {
+            *self.categories[r].entry(category.clone()).or_default() += 1;
+        }
+        if let Some(serves) = self
+            .capacity
+            .get_mut(&c.offer_id)
+            .and_then(|c| c.serves.as_mut())
+        {
+            *serves -= 1;
+        }
+    }
+
+    /// Undo [`Self::occupy`].
+    fn vacate(&mut self, r: usize, index: usize) {
+        let c = &self.scored[r][index];
+        let offer = &self.offers[&c.offer_id];
+        if let Some(group) = &offer.constraints.exclusion_group {
+            self.groups[r].remove(group);
+        }
+        if let Some(category) = &offer.constraints.category {
+            if let Some(n) = self.categories[r].get_mut(category) {
+                *n = n.saturating_sub(1);
+            }
+        }
+        if let Some(serves) = self
+            .capacity
+            .get_mut(&c.offer_id)
+            .and_then(|c| c.serves.as_mut())
+        {
+            *serves += 1;
+        }
+    }
+
+    /// Epsilon-greedy exploration over the final ranking: each slot is
+    /// replaced, with probability `epsilon`, by a uniform draw from the
+    /// offers that could legally fill it (the allocated one included).
+    /// Returns the propensity of every served pick. The small scoring
+    /// jitter is treated as part of the deterministic policy.
+    fn explore(&mut self, epsilon: f64) -> Vec<Vec<f64>> {
+        let mut propensities = Vec::with_capacity(self.requests.len());
+        for r in 0..self.requests.len() {
+            let mut slots = Vec::with_capacity(self.chosen[r].len());
+            for k in 0..self.chosen[r].len() {
+                if epsilon <= 0.0 {
+                    slots.push(1.0);
+                    continue;
+                }
+                // The kept offer's shared reservation is held until a
+                // replacement is reserved, so falling back never fails.
+                let kept = self.chosen[r][k].index;
+                self.vacate(r, kept);
+                let pool: Vec<usize> = (0..self.scored[r].len())
+                    .filter(|&i| {
+                        let c = &self.scored[r][i];
+                        i == kept
+                            || (!self.has_offer(r, &c.offer_id) && self.admissible(r, c).is_ok())
+                    })
+                    .collect();
+                let share = epsilon / pool.len() as f64;
+                let mut index = if rand::random::<f64>() < epsilon {
+                    pool[rand::thread_rng().gen_range(0..pool.len())]
+                } else {
+                    kept
+                };
+                if index != kept {
+                    let offer_id = &self.scored[r][index].offer_id;
+                    if self.reserve(r, offer_id) {
+                        self.unreserve(r, &self.scored[r][kept].offer_id);
+                    } else {
+                        index = kept;
+                    }
+                }
+                self.occupy(r, index);
+                if index == kept {
+                    slots.push(1.0 - epsilon + share);
+                } else {
+                    self.chosen[r][k] = Pick {
+                        index,
+                        binding: Vec::new(),
+                    };
+                    slots.push(share);
+                }
+            }
+            propensities.push(slots);
+        }
+        propensities
+    }
+
+    fn has_offer(&self, r: usize, offer_id: &str) -> bool {
+        self.chosen[r]
+            .iter()
+            .any(|p| self.scored[r][p.index].offer_id == offer_id)
+    }
+
+    /// Place offers that are running below their minimum exposure share,
+    /// in the requests where they score best.
+    fn guarantee_exposure(&mut self) {
+        let live = self.requests.iter().filter(|r| !r.simulate).count() as u64;
+        let total = self.decisions_made + live;
+        let mut strategic: Vec<(&String, f64)> = self
+            .offers
+            .iter()
+            .filter_map(|(id, o)| o.constraints.min_exposure.map(|m| (id, m.clamp(0.0, 1.0))))
+            .collect();
+        strategic.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+
+        for (offer_id, share) in strategic {
+            let required = (share * total as f64).ceil() as u64;
+            let mut needed = required.saturating_sub(self.usage[offer_id].served);
+            let mut targets: Vec<(usize, usize, f64)> = self
+                .scored
+                .iter()
+                .enumerate()
+                .filter_map(|(r, candidates)| {
+                    candidates
+                        .iter()
+                        .position(|c| &c.offer_id == offer_id)
+                        .map(|i| (r, i, candidates[i].blended_score))
+                })
+                .collect();
+            targets.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+            for (r, index, _) in targets {
+                if needed == 0 {
+                    break;
+                }
+                if self.chosen[r].len() >= self.slots(r)
+                    || self.admissible(r, &self.scored[r][index]).is_err()
+                {
+                    continue;
+                }
+                let binding = vec![BindingConstraint {
+                    constraint: ConstraintKind::MinExposure,
+                    offer_id: offer_id.clone(),
+                    detail: format!("{} guaranteed {:.0}% exposure", offer_id, share * 100.0),
+                }];
+                if self.take(r, index, binding) {
+                    needed -= 1;
+                }
+            }
+        }
+    }
+
+    /// Fill remaining slots from the best (request, offer) pairs down.
+    fn fill(&mut self) {
+        let mut pairs: Vec<(usize, usize, f64)> = self
+            .scored
+            .iter()
+            .enumerate()
+            .flat_map(|(r, candidates)| {
+                candidates
+                    .iter()
+                    .enumerate()
+                    .map(move |(i, c)| (r, i, c.blended_score))
+            })
+            .map(|(r, i, score)| (r, i, score - self.price(&self.scored[r][i].offer_id)))
+            .collect();
+        pairs.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap_or(std::cmp::Ordering::Equal));
+
+        for (r, index, _) in pairs {
+            if self.chosen[r].len() >= self.slots(r) {
+                continue;
+            }
+            let candidate = &self.scored[r][index];
+            if self.has_offer(r, &candidate.offer_id) {
+                continue;
+            }
+            match self.admissible(r, candidate) {
+                Ok(()) => {
+                    self.take(r, index, Vec::new());
+                }
+                Err((constraint, detail)) => self.pending[r].push(BindingConstraint {
+                    constraint,
+                    offer_id: candidate.offer_id.clone(),
+                    detail,
+                }),
+            }
+        }
+    }
+}
+
 impl Default for DecisionEngine {
     fn default() -> Self {
         Self::new()
@@ -431,6 +1249,7 @@ impl Default for DecisionEngine {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::collections::HashMap;
 
     fn make_engine_with_offers() -> DecisionEngine {
         let engine = DecisionEngine::new();
@@ -447,6 +1266,7 @@ mod tests {
                 base_scores: scores,
                 channel: "web".to_string(),
                 active: true,
+                constraints: OfferConstraints::default(),
             });
         }
         engine
@@ -492,6 +1312,8 @@ mod tests {
             simulate: false,
             timeout_ms: 50,
             requested_at: Utc::now(),
+            max_per_category: None,
+            allocator: AllocatorKind::Greedy,
         }
     }
 
@@ -580,6 +1402,255 @@ mod tests {
         assert!(result.aggregate_metrics.offer_diversity > 0.0);
     }
 
+    /// Revenue-only offer scoring `score`, plus the segment-overlap bonus
+    /// when `segments` is non-empty.
+    fn offer(
+        id: &str,
+        score: f64,
+        segments: Vec<u32>,
+        constraints: OfferConstraints,
+    ) -> OfferCandidate {
+        OfferCandidate {
+            offer_id: id.to_string(),
+            creative_id: None,
+            eligible_segments: segments,
+            base_scores: HashMap::from([("Revenue".to_string(), score)]),
+            channel: "web".to_string(),
+            active: true,
+            constraints,
+        }
+    }
+
+    fn request_for(segments: Vec<u32>, num_offers: u32) -> DecisionRequest {
+        let mut req = make_request();
+        req.context.user_segments = segments;
+        req.context.user_features.clear();
+        req.num_offers = num_offers;
+        req.objectives = vec![OptimizationObjective {
+            metric: ObjectiveMetric::Revenue,
+            weight: 1.0,
+        }];
+        req
+    }
+
+    fn ids(resp: &DecisionResponse) -> Vec<&str> {
+        resp.offers.iter().map(|o| o.offer_id.as_str()).collect()
+    }
+
+    #[test]
+    fn test_inventory_and_budget_caps() {
+        let engine = DecisionEngine::new();
+        let capped = |inventory, budget| OfferConstraints {
+            inventory,
+            budget,
+            cost_per_serve: 2.0,
+            ..Default::default()
+        };
+        engine.register_offer(offer("scarce", 0.9, vec![], capped(Some(2), None)));
+        engine.register_offer(offer("pricey", 0.8, vec![], capped(None, Some(3.0))));
+        engine.register_offer(offer("filler", 0.2, vec![], OfferConstraints::default()));
+
+        let batch: Vec<_> = (0..4).map(|_| request_for(vec![], 1)).collect();
+        let responses = engine.decide_batch(&batch);
+        let served = |id: &str| responses.iter().filter(|r| ids(r) == [id]).count();
+        assert_eq!(
+            (served("scarce"), served("pricey"), served("filler")),
+            (2, 1, 1)
+        );
+        assert_eq!(engine.offer_usage("pricey").spent, 2.0);
+
+        // The last decision explains why it got the filler offer.
+        let filler = responses.iter().find(|r| ids(r) == ["filler"]).unwrap();
+        let binding = &filler.offers[0]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .binding_constraints;
+        let kinds: Vec<_> = binding
+            .iter()
+            .map(|b| (b.constraint, b.offer_id.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (ConstraintKind::Inventory, "scarce"),
+                (ConstraintKind::Budget, "pricey")
+            ]
+        );
+
+        // Caps persist across calls; simulations do not consume them.
+        let mut sim = request_for(vec![], 1);
+        sim.simulate = true;
+        assert_eq!(ids(&engine.decide(&sim)), vec!["filler"]);
+        assert_eq!(engine.offer_usage("filler").served, 1);
+    }
+
+    #[test]
+    fn test_concurrent_decisions_never_oversell_caps() {
+        let engine = DecisionEngine::new().with_exploration(0.3);
+        engine.register_offer(offer(
+            "scarce",
+            0.9,
+            vec![],
+            OfferConstraints {
+                inventory: Some(7),
+                ..Default::default()
+            },
+        ));
+        engine.register_offer(offer(
+            "pricey",
+            0.8,
+            vec![],
+            OfferConstraints {
+                budget: Some(10.0),
+                cost_per_serve: 2.5,
+                ..Default::default()
+            },
+        ));
+        engine.register_offer(offer("filler", 0.2, vec![], OfferConstraints::default()));
+
+        let served: Vec<String> = std::thread::scope(|scope| {
+            let workers: Vec<_> = (0..8)
+                .map(|_| {
+                    scope.spawn(|| {
+                        (0..50)
+                            .flat_map(|_| {
+                                let resp = engine.decide(&request_for(vec![], 2));
+                                resp.offers.into_iter().map(|o| o.offer_id)
+                            })
+                            .collect::<Vec<_>>()
+                    })
+                })
+                .collect();
+            workers
+                .into_iter()
+                .flat_map(|w| w.join().unwrap())
+                .collect()
+        });
+        let count = |id: &str| served.iter().filter(|s| *s == id).count() as u64;
+        assert_eq!(count("scarce"), 7);
+        assert_eq!(count("pricey"), 4);
+        assert_eq!(engine.offer_usage("scarce").served, 7);
+        assert_eq!(engine.offer_usage("pricey").served, 4);
+        assert!((engine.offer_usage("pricey").spent - 10.0).abs() < 1e-9);
+        assert_eq!(engine.offer_usage("filler").served, count("filler"));
+    }
+
+    #[test]
+    fn test_min_exposure_guarantee() {
+        let engine = DecisionEngine::new();
+        engine.register_offer(offer("best", 0.9, vec![], OfferConstraints::default()));
+        engine.register_offer(offer(
+            "strategic",
+            0.1,
+            vec![],
+            OfferConstraints {
+                min_exposure: Some(0.5),
+                ..Default::default()
+            },
+        ));
+
+        let batch: Vec<_> = (0..4).map(|_| request_for(vec![], 1)).collect();
+        let responses = engine.decide_batch(&batch);
+        let forced: Vec<_> = responses
+            .iter()
+            .filter(|r| ids(r) == ["strategic"])
+            .collect();
+        assert_eq!(forced.len(), 2);
+        let binding = &forced[0].offers[0]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .binding_constraints;
+        assert_eq!(binding[0].constraint, ConstraintKind::MinExposure);
+
+        // 2 of 4 served; a fifth decision needs ceil(2.5) = 3.
+        assert_eq!(
+            ids(&engine.decide(&request_for(vec![], 1))),
+            vec!["strategic"]
+        );
+        assert_eq!(ids(&engine.decide(&request_for(vec![], 1))), vec!["best"]);
+    }
+
+    #[test]
+    fn test_exclusion_groups_and_diversity() {
+        let engine = DecisionEngine::new();
+        let tagged = |group: Option<&str>, category: &str| OfferConstraints {
+            exclusion_group: group.map(String::from),
+            category: Some(category.to_string()),
+            ..Default::default()
+        };
+        engine.register_offer(offer(
+            "card_a",
+            0.9,
+            vec![],
+            tagged(Some("credit"), "finance"),
+        ));
+        engine.register_offer(offer(
+            "card_b",
+            0.85,
+            vec![],
+            tagged(Some("credit"), "finance"),
+        ));
+        engine.register_offer(offer("loan", 0.8, vec![], tagged(None, "finance")));
+        engine.register_offer(offer("travel", 0.3, vec![], tagged(None, "travel")));
+
+        let mut req = request_for(vec![], 3);
+        let resp = engine.decide(&req);
+        assert_eq!(ids(&resp), vec!["card_a", "loan", "travel"]);
+        let binding = &resp.offers[1]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .binding_constraints;
+        assert_eq!(binding[0].constraint, ConstraintKind::MutualExclusion);
+        assert_eq!(binding[0].offer_id, "card_b");
+
+        req.max_per_category = Some(1);
+        let resp = engine.decide(&req);
+        assert_eq!(ids(&resp), vec!["card_a", "travel"]);
+        let binding = &resp.offers[1]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .binding_constraints;
+        assert!(binding
+            .iter()
+            .any(|b| b.constraint == ConstraintKind::Diversity && b.offer_id == "loan"));
+    }
+
+    #[test]
+    fn test_dual_price_allocator_places_scarce_offer_where_it_matters() {
+        // Scores: r1 (segment 1) sees A 0.85, C 0.2; r2 (segments 1, 2)
+        // sees A 0.9, B 0.88, C 0.2. Only one A is left.
+        let engine = || {
+            let engine = DecisionEngine::new();
+            let one_left = OfferConstraints {
+                inventory: Some(1),
+                ..Default::default()
+            };
+            engine.register_offer(offer("A", 0.8, vec![1, 2], one_left));
+            engine.register_offer(offer("B", 0.78, vec![2], OfferConstraints::default()));
+            engine.register_offer(offer("C", 0.1, vec![1], OfferConstraints::default()));
+            engine
+        };
+        let mut batch = vec![request_for(vec![1], 1), request_for(vec![1, 2], 1)];
+
+        let greedy = engine().decide_batch(&batch);
+        assert_eq!((ids(&greedy[0]), ids(&greedy[1])), (vec!["C"], vec!["A"]));
+
+        batch[0].allocator = AllocatorKind::DualPrice;
+        let dual = engine().decide_batch(&batch);
+        assert_eq!((ids(&dual[0]), ids(&dual[1])), (vec!["A"], vec!["B"]));
+        let binding = &dual[1].offers[0]
+            .explanation
+            .as_ref()
+            .unwrap()
+            .binding_constraints;
+        assert_eq!(binding[0].constraint, ConstraintKind::Inventory);
+        assert_eq!(binding[0].offer_id, "A");
+    }
+
     #[test]
     fn test_channel_filtering() {
         let engine = make_engine_with_offers();
@@ -589,4 +1660,63 @@ mod tests {
 
         assert!(resp.offers.is_empty());
     }
+
+    #[test]
+    fn test_propensities_logged_for_served_offers() {
+        let greedy = make_engine_with_offers();
+        let resp = greedy.decide(&make_request());
+        assert!(resp.offers.iter().all(|o| o.propensity == 1.0));
+
+        let engine = DecisionEngine::new().with_exploration(0.3);
+        for (id, score) in [("a", 0.9), ("b", 0.6), ("c", 0.3)] {
+            engine.register_offer(offer(id, score, vec![], OfferConstraints::default()));
+        }
+        let mut explored = 0;
+        for _ in 0..200 {
+            let resp = engine.decide(&request_for(vec![], 1));
+            let served = &resp.offers[0];
+            if served.offer_id == "a" {
+                assert!((served.propensity - 0.8).abs() < 1e-9);
+            } else {
+                assert!((served.propensity - 0.1).abs() < 1e-9);
+                explored += 1;
+            }
+            assert!(engine.record_outcome(&resp.decision_id, &served.offer_id, true, 2.5));
+            assert!(!engine.record_outcome(&resp.decision_id, "missing", true, 1.0));
+        }
+        assert!(explored > 10 && explored < 80);
+
+        let logs = engine.propensity_logs();
+        assert_eq!(logs.len(), 200);
+        let entry = &logs[0];
+        assert_eq!(entry.candidates.len(), 3);
+        assert_eq!(
+            entry.candidates[0].objective_scores.len(),
+            ObjectiveMetric::ALL.len()
+        );
+        assert!(entry.impressions[0].clicked);
+        assert_eq!(entry.impressions[0].revenue, 2.5);
+
+        let mut sim = request_for(vec![], 1);
+        sim.simulate = true;
+        engine.decide(&sim);
+        assert_eq!(engine.propensity_logs().len(), 200);
+    }
+
+    #[test]
+    fn test_propensity_log_keeps_the_latest_decisions() {
+        let engine = DecisionEngine::new().with_propensity_log_capacity(50);
+        engine.register_offer(offer("a", 0.9, vec![], OfferConstraints::default()));
+        let decisions: Vec<Uuid> = (0..120)
+            .map(|_| engine.decide(&request_for(vec![], 1)).decision_id)
+            .collect();
+
+        let logs = engine.propensity_logs();
+        assert_eq!(logs.len(), 50);
+        assert!(logs
+            .iter()
+            .all(|e| decisions[70..].contains(&e.decision_id)));
+        assert!(!engine.record_outcome(&decisions[0], "a", true, 1.0));
+        assert!(engine.record_outcome(&decisions[119], "a", true, 1.0));
+    }
 }
@@ -2,8 +2,11 @@
 //! product recommendations, and catalog management.
 
 pub mod catalog;
+pub mod catalog_feed;
+pub mod cf_model;
 pub mod connected_content;
 pub mod decisioning;
+pub mod policy_evaluation;
 pub mod recommendations;
 pub mod templating;
 
@@ -1,10 +1,20 @@
 //! Product/content recommendation engine — collaborative filtering,
 //! content-based, and popularity-based recommendations.
-
+//!
+//! `PersonalizedCf` is served from a trained [`CfModel`] when one is loaded
+//! and falls back to time-decayed item co-occurrence otherwise. Every
+//! strategy drops items the user already purchased and, when the engine has
+//! a catalog attached, out-of-stock items (plus items missing from the
+//! requested catalog when the request names one).
+
+use crate::catalog::CatalogEngine;
+use crate::cf_model::{CfModel, ItemEmbeddings};
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, RwLock};
+use tokio::task::JoinHandle;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -47,12 +57,79 @@ pub struct RecommendationResponse {
     pub model_version: String,
 }
 
+/// Bounds on the co-occurrence matrix.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CooccurrenceConfig {
+    /// Co-occurrence scores halve after this many hours without a new
+    /// co-interaction.
+    pub half_life_hours: f64,
+    /// Only the user's most recent distinct items are paired with a new
+    /// interaction, so the cost per event is bounded.
+    pub history_window: usize,
+    /// Neighbours kept per item after pruning.
+    pub max_neighbors: usize,
+    /// Decayed scores below this are dropped when pruning.
+    pub min_score: f64,
+}
+
+impl Default for CooccurrenceConfig {
+    fn default() -> Self {
+        Self {
+            half_life_hours: 30.0 * 24.0,
+            history_window: 50,
+            max_neighbors: 200,
+            min_score: 0.05,
+        }
+    }
+}
+
+/// A co-occurrence count that decays exponentially from `updated_at`.
+#[derive(Debug, Clone, Copy)]
+struct DecayedScore {
+    score: f64,
+    updated_at: DateTime<Utc>,
+}
+
+impl DecayedScore {
+    fn value_at(&self, now: DateTime<Utc>, half_life_hours: f64) -> f64 {
+        let age_hours = (now - self.updated_at).num_seconds().max(0) as f64 / 3600.0;
+        self.score * 0.5_f64.powf(age_hours / half_life_hours)
+    }
+}
+
+/// Items a response must not contain.
+struct Exclusions<'a> {
+    ids: HashSet<String>,
+    catalogs: Option<&'a CatalogEngine>,
+    /// The requested catalog: items must be listed and available in it.
+    /// Without one, items out of stock in any catalog are excluded.
+    catalog_id: Option<Uuid>,
+}
+
+impl Exclusions<'_> {
+    fn contains(&self, item_id: &str) -> bool {
+        if self.ids.contains(item_id) {
+            return true;
+        }
+        match (self.catalogs, self.catalog_id) {
+            (Some(catalogs), Some(id)) => !catalogs.is_available(&id, item_id),
+            (Some(catalogs), None) => catalogs.is_out_of_stock(item_id),
+            (None, _) => false,
+        }
+    }
+}
+
 pub struct RecommendationEngine {
     popularity_scores: DashMap<String, f64>,
     user_interactions: DashMap<Uuid, Vec<String>>,
-    /// Co-occurrence matrix: item_a -> { item_b -> score }.
-    /// Items interacted with by the same user increment co-occurrence by 1.0.
-    item_cooccurrence: DashMap<String, DashMap<String, f64>>,
+    /// Items each user has purchased; never recommended back to them.
+    purchases: DashMap<Uuid, HashSet<String>>,
+    /// Co-occurrence matrix: item_a -> { item_b -> decayed score }.
+    /// Items interacted with by the same user add 1.0 to the decayed score.
+    item_cooccurrence: DashMap<String, DashMap<String, DecayedScore>>,
+    cooccurrence_config: CooccurrenceConfig,
+    cf_model: RwLock<Option<Arc<CfModel>>>,
+    catalogs: Option<Arc<CatalogEngine>>,
     /// Feature vectors for items used in content-based filtering.
     item_features: DashMap<String, HashMap<String, f64>>,
     /// Timestamps of interactions per item, used for trending calculation.
@@ -66,13 +143,47 @@ impl RecommendationEngine {
         Self {
             popularity_scores: DashMap::new(),
             user_interactions: DashMap::new(),
+            purchases: DashMap::new(),
             item_cooccurrence: DashMap::new(),
+            cooccurrence_config: CooccurrenceConfig::default(),
+            cf_model: RwLock::new(None),
+            catalogs: None,
             item_features: DashMap::new(),
             interaction_timestamps: DashMap::new(),
             item_created_at: DashMap::new(),
         }
     }
 
+    pub fn with_cooccurrence_config(mut self, config: CooccurrenceConfig) -> Self {
+        self.cooccurrence_config = config;
+        self
+    }
+
+    /// Drop out-of-stock items from every response, and items missing from
+    /// the requested catalog when a request names one.
+    pub fn with_catalog(mut self, catalogs: Arc<CatalogEngine>) -> Self {
+        self.catalogs = Some(catalogs);
+        self
+    }
+
+    /// Serve `PersonalizedCf` from trained embeddings. Replaces any model
+    /// already loaded; in-flight requests finish on the old one.
+    pub fn load_cf_model(&self, embeddings: ItemEmbeddings) {
+        let model = Arc::new(CfModel::new(embeddings));
+        *self.cf_model.write().unwrap_or_else(|e| e.into_inner()) = Some(model);
+    }
+
+    pub fn cf_model_version(&self) -> Option<String> {
+        self.current_model().map(|m| m.version().to_string())
+    }
+
+    fn current_model(&self) -> Option<Arc<CfModel>> {
+        self.cf_model
+            .read()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
+    }
+
     /// Record a user-item interaction. Updates popularity scores, user history,
     /// co-occurrence matrix, and interaction timestamps.
     pub fn record_interaction(&self, user_id: Uuid, item_id: String) {
@@ -88,33 +199,30 @@ impl RecommendationEngine {
             .or_default()
             .push(Utc::now());
 
-        // Get existing items for this user before pushing the new one,
-        // so we can update co-occurrence with all previous items.
+        // Get the user's most recent distinct items before pushing the new
+        // one, so we can update co-occurrence against them.
+        let window = self.cooccurrence_config.history_window;
         let previous_items: Vec<String> = self
             .user_interactions
             .get(&user_id)
-            .map(|v| v.clone())
+            .map(|history| {
+                let mut seen = HashSet::new();
+                history
+                    .iter()
+                    .rev()
+                    .filter(|item| **item != item_id && seen.insert(item.as_str()))
+                    .take(window)
+                    .cloned()
+                    .collect()
+            })
             .unwrap_or_default();
 
-        // Update co-occurrence: the new item co-occurs with every item
-        // already in the user's history (bidirectional).
+        // Update co-occurrence: the new item co-occurs with each of those
+        // items (bidirectional).
+        let now = Utc::now();
         for existing_item in &previous_items {
-            if *existing_item == item_id {
-                continue;
-            }
-            // item_id <-> existing_item
-            self.item_cooccurrence
-                .entry(item_id.clone())
-                .or_default()
-                .entry(existing_item.clone())
-                .and_modify(|s| *s += 1.0)
-                .or_insert(1.0);
-            self.item_cooccurrence
-                .entry(existing_item.clone())
-                .or_default()
-                .entry(item_id.clone())
-                .and_modify(|s| *s += 1.0)
-                .or_insert(1.0);
+            self.bump_cooccurrence(&item_id, existing_item, now);
+            self.bump_cooccurrence(existing_item, &item_id, now);
         }
 
         // Add to user interaction history
@@ -124,6 +232,88 @@ impl RecommendationEngine {
             .push(item_id);
     }
 
+    /// Record a purchase: an interaction that also removes the item from
+    /// the user's future recommendations.
+    pub fn record_purchase(&self, user_id: Uuid, item_id: String) {
+        self.purchases
+            .entry(user_id)
+            .or_default()
+            .insert(item_id.clone());
+        self.record_interaction(user_id, item_id);
+    }
+
+    fn bump_cooccurrence(&self, item: &str, other: &str, now: DateTime<Utc>) {
+        let half_life = self.cooccurrence_config.half_life_hours;
+        let neighbors = self.item_cooccurrence.entry(item.to_string()).or_default();
+        neighbors
+            .entry(other.to_string())
+            .and_modify(|s| {
+                s.score = s.value_at(now, half_life) + 1.0;
+                s.updated_at = now;
+            })
+            .or_insert(DecayedScore {
+                score: 1.0,
+                updated_at: now,
+            });
+        // Let the map overshoot by a quarter so pruning is amortized.
+        let max = self.cooccurrence_config.max_neighbors;
+        if neighbors.len() > max + max / 4 {
+            self.prune_neighbors(&neighbors, now);
+        }
+    }
+
+    /// Drop decayed-out pairs and keep each item's strongest neighbours.
+    /// Returns how many pairs were removed.
+    pub fn prune_cooccurrence(&self) -> usize {
+        let now = Utc::now();
+        let removed = self
+            .item_cooccurrence
+            .iter()
+            .map(|neighbors| self.prune_neighbors(neighbors.value(), now))
+            .sum();
+        self.item_cooccurrence
+            .retain(|_, neighbors| !neighbors.is_empty());
+        removed
+    }
+
+    /// Prune co-occurrence every `tick`, so items that stopped selling
+    /// eventually lose their decayed neighbours.
+    pub fn spawn_pruner(self: Arc<Self>, tick: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                let removed = self.prune_cooccurrence();
+                tracing::debug!(removed, "Pruned co-occurrence pairs");
+            }
+        })
+    }
+
+    fn prune_neighbors(
+        &self,
+        neighbors: &DashMap<String, DecayedScore>,
+        now: DateTime<Utc>,
+    ) -> usize {
+        let config = &self.cooccurrence_config;
+        let mut scored: Vec<(String, f64)> = neighbors
+            .iter()
+            .map(|e| {
+                (
+                    e.key().clone(),
+                    e.value().value_at(now, config.half_life_hours),
+                )
+            })
+            .collect();
+        scored.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(std::cmp::Ordering::Equal));
+        let before = neighbors.len();
+        for (i, (item, score)) in scored.iter().enumerate() {
+            if i >= config.max_neighbors || *score < config.min_score {
+                neighbors.remove(item);
+            }
+        }
+        before - neighbors.len()
+    }
+
     /// Register feature vector for an item (used by content-based filtering).
     pub fn set_item_features(&self, item_id: String, features: HashMap<String, f64>) {
         self.item_features.insert(item_id, features);
@@ -135,28 +325,48 @@ impl RecommendationEngine {
     }
 
     pub fn recommend(&self, request: &RecommendationRequest) -> RecommendationResponse {
-        let items = match request.strategy {
-            RecommendationStrategy::MostPopular => {
-                self.most_popular(request.limit, &request.exclude_ids)
+        let mut ids: HashSet<String> = request.exclude_ids.iter().cloned().collect();
+        if let Some(purchased) = self.purchases.get(&request.user_id) {
+            ids.extend(purchased.iter().cloned());
+        }
+        let catalogs = self.catalogs.as_deref();
+        let catalog_id = match (catalogs, &request.catalog_name) {
+            (Some(catalogs), Some(name)) => {
+                let found = catalogs.find_catalog(name).map(|c| c.id);
+                if found.is_none() {
+                    tracing::debug!(catalog = %name, "Unknown catalog; only stock is checked");
+                }
+                found
             }
+            _ => None,
+        };
+        let exclude = Exclusions {
+            ids,
+            catalogs,
+            catalog_id,
+        };
+
+        let mut model_version = "v1.0".to_string();
+        let items = match request.strategy {
+            RecommendationStrategy::MostPopular => self.most_popular(request.limit, &exclude),
             RecommendationStrategy::RecentlyViewed => {
-                self.recently_viewed(&request.user_id, request.limit)
-            }
-            RecommendationStrategy::PersonalizedCf => {
-                self.personalized_cf(&request.user_id, request.limit, &request.exclude_ids)
+                self.recently_viewed(&request.user_id, request.limit, &exclude)
             }
+            RecommendationStrategy::PersonalizedCf => match self.current_model() {
+                Some(model) => {
+                    model_version = model.version().to_string();
+                    self.embedding_cf(&model, &request.user_id, request.limit, &exclude)
+                }
+                None => self.personalized_cf(&request.user_id, request.limit, &exclude),
+            },
             RecommendationStrategy::ContentBased => {
-                self.content_based(&request.user_id, request.limit, &request.exclude_ids)
+                self.content_based(&request.user_id, request.limit, &exclude)
             }
-            RecommendationStrategy::FrequentlyBoughtTogether => self.frequently_bought_together(
-                &request.user_id,
-                request.limit,
-                &request.exclude_ids,
-            ),
-            RecommendationStrategy::Trending => self.trending(request.limit, &request.exclude_ids),
-            RecommendationStrategy::NewArrivals => {
-                self.new_arrivals(request.limit, &request.exclude_ids)
+            RecommendationStrategy::FrequentlyBoughtTogether => {
+                self.frequently_bought_together(&request.user_id, request.limit, &exclude)
             }
+            RecommendationStrategy::Trending => self.trending(request.limit, &exclude),
+            RecommendationStrategy::NewArrivals => self.new_arrivals(request.limit, &exclude),
         };
 
         RecommendationResponse {
@@ -165,11 +375,11 @@ impl RecommendationEngine {
             strategy: request.strategy.clone(),
             items,
             generated_at: Utc::now(),
-            model_version: "v1.0".to_string(),
+            model_version,
         }
     }
 
-    fn most_popular(&self, limit: usize, exclude: &[String]) -> Vec<RecommendationItem> {
+    fn most_popular(&self, limit: usize, exclude: &Exclusions) -> Vec<RecommendationItem> {
         let mut items: Vec<_> = self
             .popularity_scores
             .iter()
@@ -190,13 +400,19 @@ impl RecommendationEngine {
         items
     }
 
-    fn recently_viewed(&self, user_id: &Uuid, limit: usize) -> Vec<RecommendationItem> {
+    fn recently_viewed(
+        &self,
+        user_id: &Uuid,
+        limit: usize,
+        exclude: &Exclusions,
+    ) -> Vec<RecommendationItem> {
         self.user_interactions
             .get(user_id)
             .map(|interactions| {
                 interactions
                     .iter()
                     .rev()
+                    .filter(|id| !exclude.contains(id))
                     .take(limit)
                     .enumerate()
                     .map(|(i, id)| RecommendationItem {
@@ -210,14 +426,44 @@ impl RecommendationEngine {
             .unwrap_or_default()
     }
 
+    /// Personalized collaborative filtering from the trained model: nearest
+    /// items to the mean embedding of the user's history.
+    fn embedding_cf(
+        &self,
+        model: &CfModel,
+        user_id: &Uuid,
+        limit: usize,
+        exclude: &Exclusions,
+    ) -> Vec<RecommendationItem> {
+        let user_items = match self.user_interactions.get(user_id) {
+            Some(items) => items.clone(),
+            None => return Vec::new(),
+        };
+        let user_item_set: HashSet<&str> = user_items.iter().map(String::as_str).collect();
+
+        model
+            .recommend(&user_items, limit, |id| {
+                user_item_set.contains(id) || exclude.contains(id)
+            })
+            .into_iter()
+            .map(|(item_id, score)| RecommendationItem {
+                item_id,
+                score: score as f64,
+                reason: "Users who interacted with X also liked this".to_string(),
+                metadata: HashMap::new(),
+            })
+            .collect()
+    }
+
     /// Personalized collaborative filtering: find items that co-occur with
-    /// the user's past interactions, ranked by aggregate co-occurrence score.
-    /// Items the user has already interacted with are excluded.
+    /// the user's past interactions, ranked by aggregate decayed
+    /// co-occurrence score. Items the user has already interacted with are
+    /// excluded.
     fn personalized_cf(
         &self,
         user_id: &Uuid,
         limit: usize,
-        exclude: &[String],
+        exclude: &Exclusions,
     ) -> Vec<RecommendationItem> {
         let user_items = match self.user_interactions.get(user_id) {
             Some(items) => items.clone(),
@@ -227,12 +473,14 @@ impl RecommendationEngine {
         let user_item_set: std::collections::HashSet<&String> = user_items.iter().collect();
 
         // Aggregate co-occurrence scores for candidate items
+        let now = Utc::now();
+        let half_life = self.cooccurrence_config.half_life_hours;
         let mut candidate_scores: HashMap<String, f64> = HashMap::new();
-        for item in &user_items {
+        for item in user_items.iter().collect::<HashSet<_>>() {
             if let Some(cooccurrences) = self.item_cooccurrence.get(item) {
                 for entry in cooccurrences.iter() {
                     let candidate = entry.key().clone();
-                    let score = *entry.value();
+                    let score = entry.value().value_at(now, half_life);
                     if !user_item_set.contains(&candidate) && !exclude.contains(&candidate) {
                         *candidate_scores.entry(candidate).or_insert(0.0) += score;
                     }
@@ -265,7 +513,7 @@ impl RecommendationEngine {
         &self,
         user_id: &Uuid,
         limit: usize,
-        exclude: &[String],
+        exclude: &Exclusions,
     ) -> Vec<RecommendationItem> {
         let user_items = match self.user_interactions.get(user_id) {
             Some(items) => items.clone(),
@@ -339,7 +587,7 @@ impl RecommendationEngine {
         &self,
         user_id: &Uuid,
         limit: usize,
-        exclude: &[String],
+        exclude: &Exclusions,
     ) -> Vec<RecommendationItem> {
         let recent_item = match self.user_interactions.get(user_id) {
             Some(items) if !items.is_empty() => items.last().unwrap().clone(),
@@ -362,7 +610,9 @@ impl RecommendationEngine {
             .filter(|entry| !user_items.contains(entry.key()) && !exclude.contains(entry.key()))
             .map(|entry| RecommendationItem {
                 item_id: entry.key().clone(),
-                score: *entry.value(),
+                score: entry
+                    .value()
+                    .value_at(Utc::now(), self.cooccurrence_config.half_life_hours),
                 reason: format!("Frequently bought together with {}", recent_item),
                 metadata: HashMap::new(),
             })
@@ -379,7 +629,7 @@ impl RecommendationEngine {
 
     /// Trending: items with the highest interaction count in the last 24 hours,
     /// with an exponential decay factor favoring more recent interactions.
-    fn trending(&self, limit: usize, exclude: &[String]) -> Vec<RecommendationItem> {
+    fn trending(&self, limit: usize, exclude: &Exclusions) -> Vec<RecommendationItem> {
         let now = Utc::now();
         let window = chrono::Duration::hours(24);
         let cutoff = now - window;
@@ -426,7 +676,7 @@ impl RecommendationEngine {
     }
 
     /// New arrivals: items sorted by creation date, newest first.
-    fn new_arrivals(&self, limit: usize, exclude: &[String]) -> Vec<RecommendationItem> {
+    fn new_arrivals(&self, limit: usize, exclude: &Exclusions) -> Vec<RecommendationItem> {
         let mut items: Vec<(String, DateTime<Utc>)> = self
             .item_created_at
             .iter()
@@ -696,6 +946,130 @@ mod tests {
         assert_eq!(resp.items[0].item_id, "item_b");
     }
 
+    #[test]
+    fn test_purchases_and_unavailable_items_are_excluded() {
+        use crate::catalog::{Catalog, CatalogItem};
+
+        let catalogs = Arc::new(CatalogEngine::new());
+        let catalog_id = Uuid::new_v4();
+        catalogs.create_catalog(Catalog {
+            id: catalog_id,
+            name: "shoes".to_string(),
+            description: None,
+            fields: vec![],
+            item_count: 0,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        });
+        for (id, data) in [
+            ("item_a", serde_json::json!({})),
+            ("item_b", serde_json::json!({"inventory": 0})),
+            ("item_c", serde_json::json!({"available": true})),
+        ] {
+            catalogs.add_item(CatalogItem {
+                id: id.to_string(),
+                catalog_id,
+                data: serde_json::from_value(data).unwrap(),
+                created_at: Utc::now(),
+                updated_at: Utc::now(),
+            });
+        }
+        let engine = RecommendationEngine::new().with_catalog(catalogs);
+        let user = Uuid::new_v4();
+        for item in ["item_a", "item_b", "item_c", "item_d"] {
+            engine.record_interaction(Uuid::new_v4(), item.to_string());
+        }
+        engine.record_purchase(user, "item_a".to_string());
+
+        let mut req = RecommendationRequest {
+            user_id: user,
+            strategy: RecommendationStrategy::MostPopular,
+            catalog_name: None,
+            limit: 10,
+            exclude_ids: vec![],
+            context: HashMap::new(),
+        };
+        let ids = |resp: RecommendationResponse| {
+            let mut ids: Vec<String> = resp.items.into_iter().map(|i| i.item_id).collect();
+            ids.sort();
+            ids
+        };
+        // Purchased item_a and out-of-stock item_b are gone without naming
+        // a catalog.
+        assert_eq!(ids(engine.recommend(&req)), vec!["item_c", "item_d"]);
+        // In the catalog: item_b is out of stock and item_d is not listed.
+        req.catalog_name = Some("shoes".to_string());
+        assert_eq!(ids(engine.recommend(&req)), vec!["item_c"]);
+    }
+
+    #[test]
+    fn test_cooccurrence_decays_and_prunes() {
+        let engine = RecommendationEngine::new().with_cooccurrence_config(CooccurrenceConfig {
+            half_life_hours: 1.0,
+            history_window: 50,
+            max_neighbors: 4,
+            min_score: 0.5,
+        });
+        let user = Uuid::new_v4();
+        engine.record_interaction(user, "hub".to_string());
+        for i in 0..5 {
+            engine.record_interaction(user, format!("item_{}", i));
+        }
+        assert_eq!(engine.item_cooccurrence.get("hub").unwrap().len(), 5);
+
+        // Two half-lives later a single co-interaction is worth 0.25.
+        let stale = Utc::now() - chrono::Duration::hours(2);
+        engine
+            .item_cooccurrence
+            .get("hub")
+            .unwrap()
+            .get_mut("item_0")
+            .unwrap()
+            .updated_at = stale;
+        let score = engine
+            .item_cooccurrence
+            .get("hub")
+            .unwrap()
+            .get("item_0")
+            .unwrap()
+            .value_at(Utc::now(), 1.0);
+        assert!((score - 0.25).abs() < 0.01);
+
+        assert!(engine.prune_cooccurrence() >= 2);
+        let hub = engine.item_cooccurrence.get("hub").unwrap();
+        assert_eq!(hub.len(), 4);
+        assert!(!hub.contains_key("item_0"));
+    }
+
+    #[test]
+    fn test_personalized_cf_uses_loaded_model() {
+        let engine = RecommendationEngine::new();
+        let user = Uuid::new_v4();
+        engine.record_interaction(user, "item_a".to_string());
+        engine.load_cf_model(
+            ItemEmbeddings::new(
+                "bpr-test".to_string(),
+                2,
+                vec!["item_a".into(), "item_b".into(), "item_c".into()],
+                vec![1.0, 0.0, 0.9, 0.1, -1.0, 0.0],
+            )
+            .unwrap(),
+        );
+
+        let req = RecommendationRequest {
+            user_id: user,
+            strategy: RecommendationStrategy::PersonalizedCf,
+            catalog_name: None,
+            limit: 5,
+            exclude_ids: vec![],
+            context: HashMap::new(),
+        };
+        let resp = engine.recommend(&req);
+        assert_eq!(resp.model_version, "bpr-test");
+        let ids: Vec<&str> = resp.items.iter().map(|i| i.item_id.as_str()).collect();
+        assert_eq!(ids, vec!["item_b", "item_c"]);
+    }
+
     #[test]
     fn test_default_impl() {
         let engine = RecommendationEngine::default();
@@ -1,7 +1,27 @@
-//! Liquid-like template engine for message personalization.
+//! Liquid-compatible template engine for message personalization.
+//!
+//! Templates are tokenized and parsed into a node tree, then rendered against
+//! a [`TemplateContext`]. Supported tags: `if`/`elsif`/`else`, `unless`,
+//! `case`/`when`, `for` (with `limit:`, `offset:`, `reversed`, `else` and the
+//! `forloop` object), `break`/`continue`, `assign`, `capture`, `raw` and
+//! `comment`, plus `{%-`/`-%}` whitespace control. Filters chain and take
+//! arguments (`{{ product.name | truncate: 20 | upcase }}`).
+//!
+//! Output is HTML-escaped unless the engine renders [`OutputFormat::PlainText`]
+//! or the last filter in the chain is `raw`, `escape` or `escape_once`. A
+//! `capture` holds output that was already escaped, so it is written as is
+//! when output unfiltered. Parse errors carry the line and column of the
+//! offending token.
 
+use campaign_core::channels::ActivationChannel;
+use chrono::format::{Item, StrftimeItems};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use serde_json::{Map, Value};
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+/// Ranges such as `(1..n)` are capped at this many elements.
+const MAX_RANGE_LEN: i64 = 10_000;
 
 #[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TemplateContext {
@@ -11,106 +31,2294 @@ pub struct TemplateContext {
     pub catalog: HashMap<String, serde_json::Value>,
     pub connected: HashMap<String, serde_json::Value>,
     pub custom: HashMap<String, serde_json::Value>,
+    /// Recommendation lists by slot name, e.g. `recommendations.for_you`.
+    #[serde(default)]
+    pub recommendations: HashMap<String, serde_json::Value>,
+    /// Flat message variables such as `{{ first_name }}`. The namespaces
+    /// above win when a variable shares their name.
+    #[serde(default)]
+    pub variables: HashMap<String, serde_json::Value>,
+}
+
+impl TemplateContext {
+    /// The root namespace that template variables resolve against.
+    fn root(&self) -> Map<String, Value> {
+        let namespace = |vars: &HashMap<String, Value>| {
+            Value::Object(vars.iter().map(|(k, v)| (k.clone(), v.clone())).collect())
+        };
+        let mut root: Map<String, Value> = self
+            .variables
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        root.insert("user".into(), namespace(&self.user));
+        root.insert("event".into(), namespace(&self.event));
+        root.insert("campaign".into(), namespace(&self.campaign));
+        root.insert("catalog".into(), namespace(&self.catalog));
+        root.insert("connected".into(), namespace(&self.connected));
+        root.insert("custom".into(), namespace(&self.custom));
+        root.insert("recommendations".into(), namespace(&self.recommendations));
+        root
+    }
+}
+
+/// Root names every context provides.
+pub const NAMESPACES: &[&str] = &[
+    "user",
+    "event",
+    "campaign",
+    "catalog",
+    "connected",
+    "custom",
+    "recommendations",
+];
+
+/// A template syntax or render error, positioned in the template source.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize, thiserror::Error)]
+#[error("{message} (line {line}, column {column})")]
+pub struct TemplateError {
+    pub message: String,
+    pub line: usize,
+    pub column: usize,
+}
+
+/// How rendered values are written into the output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum OutputFormat {
+    /// Escape `& < > " '` in every output tag (email and web content).
+    Html,
+    /// Write values verbatim (SMS, push, paid media).
+    PlainText,
+}
+
+impl OutputFormat {
+    pub fn for_channel(channel: &ActivationChannel) -> Self {
+        match channel {
+            ActivationChannel::Email
+            | ActivationChannel::InAppMessage
+            | ActivationChannel::WebPersonalization => Self::Html,
+            _ => Self::PlainText,
+        }
+    }
 }
 
-type FilterFn = Box<dyn Fn(&str) -> String + Send + Sync>;
+type FilterFn = Box<dyn Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync>;
+type BuiltinFilter = fn(&Value, &[Value]) -> Result<Value, String>;
+
+/// A parsed template, reusable across renders.
+#[derive(Debug, Clone)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+impl Template {
+    /// Root names the template reads, excluding names it binds itself with
+    /// `assign`, `capture` or `for`.
+    pub fn referenced_variables(&self) -> BTreeSet<String> {
+        let mut read = BTreeSet::new();
+        let mut bound = BTreeSet::from(["forloop".to_string()]);
+        collect_names(&self.nodes, &mut read, &mut bound);
+        read.retain(|name| !bound.contains(name));
+        read
+    }
+}
+
+fn collect_names(nodes: &[Node], read: &mut BTreeSet<String>, bound: &mut BTreeSet<String>) {
+    fn expr(e: &Expr, read: &mut BTreeSet<String>) {
+        match e {
+            Expr::Path(root, segs) => {
+                read.insert(root.clone());
+                for seg in segs {
+                    if let PathSeg::Index(index) = seg {
+                        expr(index, read);
+                    }
+                }
+            }
+            Expr::Range(from, to) => {
+                expr(from, read);
+                expr(to, read);
+            }
+            Expr::Literal(_) | Expr::Empty | Expr::Blank => {}
+        }
+    }
+    fn filtered(f: &Filtered, read: &mut BTreeSet<String>) {
+        expr(&f.expr, read);
+        for call in &f.filters {
+            call.args.iter().for_each(|a| expr(a, read));
+        }
+    }
+    fn condition(c: &Condition, read: &mut BTreeSet<String>) {
+        match c {
+            Condition::Truthy(e) => expr(e, read),
+            Condition::Compare(a, _, b) => {
+                expr(a, read);
+                expr(b, read);
+            }
+            Condition::And(a, b) | Condition::Or(a, b) => {
+                condition(a, read);
+                condition(b, read);
+            }
+            Condition::Not(c) => condition(c, read),
+        }
+    }
+
+    for node in nodes {
+        match node {
+            Node::Text(_) | Node::Break | Node::Continue => {}
+            Node::Output { value, .. } => filtered(value, read),
+            Node::If {
+                branches,
+                otherwise,
+            } => {
+                for (c, body) in branches {
+                    condition(c, read);
+                    collect_names(body, read, bound);
+                }
+                collect_names(otherwise, read, bound);
+            }
+            Node::Case {
+                subject,
+                whens,
+                otherwise,
+            } => {
+                expr(subject, read);
+                for (values, body) in whens {
+                    values.iter().for_each(|v| expr(v, read));
+                    collect_names(body, read, bound);
+                }
+                collect_names(otherwise, read, bound);
+            }
+            Node::For {
+                var,
+                collection,
+                limit,
+                offset,
+                body,
+                otherwise,
+                ..
+            } => {
+                bound.insert(var.clone());
+                expr(collection, read);
+                limit.iter().chain(offset).for_each(|e| expr(e, read));
+                collect_names(body, read, bound);
+                collect_names(otherwise, read, bound);
+            }
+            Node::Assign { name, value, .. } => {
+                bound.insert(name.clone());
+                filtered(value, read);
+            }
+            Node::Capture { name, body } => {
+                bound.insert(name.clone());
+                collect_names(body, read, bound);
+            }
+        }
+    }
+}
 
 pub struct TemplateEngine {
     filters: HashMap<String, FilterFn>,
+    format: OutputFormat,
 }
 
 impl TemplateEngine {
+    /// Engine with the built-in filters, rendering HTML-escaped output.
     pub fn new() -> Self {
+        let builtins: &[(&str, BuiltinFilter)] = &[
+            ("upcase", filter_upcase),
+            ("downcase", filter_downcase),
+            ("capitalize", filter_capitalize),
+            ("strip", filter_strip),
+            ("lstrip", filter_lstrip),
+            ("rstrip", filter_rstrip),
+            ("truncate", filter_truncate),
+            ("truncatewords", filter_truncatewords),
+            ("default", filter_default),
+            ("date", filter_date),
+            ("money", filter_money),
+            ("escape", filter_escape),
+            ("escape_once", filter_escape_once),
+            ("raw", filter_raw),
+            ("url_encode", filter_url_encode),
+            ("strip_html", filter_strip_html),
+            ("newline_to_br", filter_newline_to_br),
+            ("append", filter_append),
+            ("prepend", filter_prepend),
+            ("replace", filter_replace),
+            ("replace_first", filter_replace_first),
+            ("remove", filter_remove),
+            ("split", filter_split),
+            ("join", filter_join),
+            ("size", filter_size),
+            ("first", filter_first),
+            ("last", filter_last),
+            ("reverse", filter_reverse),
+            ("sort", filter_sort),
+            ("map", filter_map),
+            ("uniq", filter_uniq),
+            ("compact", filter_compact),
+            ("plus", filter_plus),
+            ("minus", filter_minus),
+            ("times", filter_times),
+            ("divided_by", filter_divided_by),
+            ("modulo", filter_modulo),
+            ("round", filter_round),
+            ("abs", filter_abs),
+            ("ceil", filter_ceil),
+            ("floor", filter_floor),
+        ];
         let mut filters: HashMap<String, FilterFn> = HashMap::new();
-        filters.insert("upcase".to_string(), Box::new(|s: &str| s.to_uppercase()));
-        filters.insert("downcase".to_string(), Box::new(|s: &str| s.to_lowercase()));
-        filters.insert(
-            "capitalize".to_string(),
-            Box::new(|s: &str| {
-                let mut chars = s.chars();
-                match chars.next() {
-                    None => String::new(),
-                    Some(c) => c.to_uppercase().to_string() + &chars.as_str().to_lowercase(),
-                }
-            }),
-        );
-        filters.insert(
-            "strip".to_string(),
-            Box::new(|s: &str| s.trim().to_string()),
-        );
-        filters.insert(
-            "truncate".to_string(),
-            Box::new(|s: &str| {
-                if s.len() > 50 {
-                    format!("{}...", &s[..50])
+        for (name, f) in builtins {
+            filters.insert(name.to_string(), Box::new(*f));
+        }
+        Self {
+            filters,
+            format: OutputFormat::Html,
+        }
+    }
+
+    /// Engine configured with the output format appropriate for `channel`.
+    pub fn for_channel(channel: &ActivationChannel) -> Self {
+        Self::new().with_output_format(OutputFormat::for_channel(channel))
+    }
+
+    pub fn with_output_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    pub fn output_format(&self) -> OutputFormat {
+        self.format
+    }
+
+    /// Register a custom filter, replacing any built-in of the same name.
+    pub fn register_filter<F>(&mut self, name: &str, filter: F)
+    where
+        F: Fn(&Value, &[Value]) -> Result<Value, String> + Send + Sync + 'static,
+    {
+        self.filters.insert(name.to_string(), Box::new(filter));
+    }
+
+    /// Parse and render in one step.
+    pub fn render(
+        &self,
+        template: &str,
+        context: &TemplateContext,
+    ) -> Result<String, TemplateError> {
+        let parsed = self.parse(template)?;
+        self.render_template(&parsed, context)
+    }
+
+    /// Parse a template, reporting the first syntax error.
+    pub fn parse(&self, source: &str) -> Result<Template, TemplateError> {
+        let segments = tokenize(source)?;
+        let mut parser = Parser {
+            source,
+            segments,
+            next: 0,
+            filters: &self.filters,
+        };
+        let (nodes, stray) = parser.parse_nodes(&[])?;
+        debug_assert!(stray.is_none());
+        Ok(Template { nodes })
+    }
+
+    /// Render a previously parsed template.
+    pub fn render_template(
+        &self,
+        template: &Template,
+        context: &TemplateContext,
+    ) -> Result<String, TemplateError> {
+        let mut renderer = Renderer {
+            engine: self,
+            root: context.root(),
+            scopes: vec![Map::new()],
+            safe_captures: HashSet::new(),
+        };
+        let mut out = String::new();
+        renderer.render_nodes(&template.nodes, &mut out)?;
+        Ok(out)
+    }
+}
+
+impl Default for TemplateEngine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Tokenizer
+// ---------------------------------------------------------------------------
+
+#[derive(Debug)]
+enum Segment {
+    Text(String),
+    Output { markup: String, offset: usize },
+    Tag(TagSegment),
+}
+
+#[derive(Debug)]
+struct TagSegment {
+    name: String,
+    markup: String,
+    /// Byte offset of the markup after the tag name.
+    offset: usize,
+    /// Byte offset of the tag name.
+    start: usize,
+}
+
+fn position(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset.min(source.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before.rsplit('\n').next().map_or(0, |l| l.chars().count()) + 1;
+    (line, column)
+}
+
+fn error_at(source: &str, offset: usize, message: impl Into<String>) -> TemplateError {
+    let (line, column) = position(source, offset);
+    TemplateError {
+        message: message.into(),
+        line,
+        column,
+    }
+}
+
+fn push_text(segments: &mut Vec<Segment>, text: &str, trim_start: bool, trim_end: bool) {
+    let text = if trim_start { text.trim_start() } else { text };
+    let text = if trim_end { text.trim_end() } else { text };
+    if !text.is_empty() {
+        segments.push(Segment::Text(text.to_string()));
+    }
+}
+
+/// Find the `{% end_name %}` tag closing a raw/comment block starting at
+/// `from`. Returns the offsets of the tag's start and end.
+fn find_end_tag(source: &str, from: usize, end_name: &str) -> Option<(usize, usize)> {
+    let mut search = from;
+    while let Some(rel) = source[search..].find("{%") {
+        let start = search + rel;
+        let close = start + source[start..].find("%}")?;
+        let inner = source[start + 2..close].trim_matches(|c: char| c == '-' || c.is_whitespace());
+        if inner == end_name {
+            return Some((start, close + 2));
+        }
+        search = start + 2;
+    }
+    None
+}
+
+fn tokenize(source: &str) -> Result<Vec<Segment>, TemplateError> {
+    let mut segments = Vec::new();
+    let mut pos = 0;
+    let mut trim_next = false;
+
+    while pos < source.len() {
+        let rest = &source[pos..];
+        let next = match (rest.find("{{"), rest.find("{%")) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        let Some(rel) = next else {
+            push_text(&mut segments, rest, trim_next, false);
+            break;
+        };
+
+        let start = pos + rel;
+        let is_output = source[start..].starts_with("{{");
+        let mut inner_start = start + 2;
+        let trim_before = source[inner_start..].starts_with('-');
+        if trim_before {
+            inner_start += 1;
+        }
+        push_text(&mut segments, &source[pos..start], trim_next, trim_before);
+
+        let close = if is_output { "}}" } else { "%}" };
+        let Some(close_rel) = source[inner_start..].find(close) else {
+            let what = if is_output { "`{{`" } else { "`{%`" };
+            return Err(error_at(source, start, format!("unterminated {}", what)));
+        };
+        let mut inner_end = inner_start + close_rel;
+        pos = inner_end + 2;
+        trim_next = inner_end > inner_start && source[..inner_end].ends_with('-');
+        if trim_next {
+            inner_end -= 1;
+        }
+        let markup = &source[inner_start..inner_end];
+
+        if is_output {
+            segments.push(Segment::Output {
+                markup: markup.to_string(),
+                offset: inner_start,
+            });
+            continue;
+        }
+
+        let trimmed = markup.trim_start();
+        let name_offset = inner_start + (markup.len() - trimmed.len());
+        let name: String = trimmed
+            .chars()
+            .take_while(|c| c.is_ascii_alphanumeric() || *c == '_')
+            .collect();
+        if name.is_empty() {
+            return Err(error_at(source, name_offset, "expected a tag name"));
+        }
+
+        if name == "raw" || name == "comment" {
+            let end_name = format!("end{}", name);
+            let Some((content_end, after)) = find_end_tag(source, pos, &end_name) else {
+                return Err(error_at(
+                    source,
+                    name_offset,
+                    format!("`{{% {} %}}` is never closed", name),
+                ));
+            };
+            if name == "raw" {
+                push_text(&mut segments, &source[pos..content_end], false, false);
+            }
+            pos = after;
+            trim_next = false;
+            continue;
+        }
+
+        segments.push(Segment::Tag(TagSegment {
+            markup: trimmed[name.len()..].to_string(),
+            offset: name_offset + name.len(),
+            start: name_offset,
+            name,
+        }));
+    }
+    Ok(segments)
+}
+
+// ---------------------------------------------------------------------------
+// Expressions
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone, PartialEq)]
+enum Tok {
+    Ident(String),
+    Str(String),
+    Num(Value),
+    Dot,
+    DotDot,
+    LBracket,
+    RBracket,
+    LParen,
+    RParen,
+    Pipe,
+    Colon,
+    Comma,
+    Assign,
+    Cmp(CmpOp),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Eq,
+    Ne,
+    Lt,
+    Gt,
+    Le,
+    Ge,
+    Contains,
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Literal(Value),
+    /// The `empty` keyword: equal to "", [] and {}.
+    Empty,
+    /// The `blank` keyword: `empty`, plus nil, false and whitespace.
+    Blank,
+    Path(String, Vec<PathSeg>),
+    Range(Box<Expr>, Box<Expr>),
+}
+
+#[derive(Debug, Clone)]
+enum PathSeg {
+    Key(String),
+    Index(Expr),
+}
+
+#[derive(Debug, Clone)]
+struct FilterCall {
+    name: String,
+    args: Vec<Expr>,
+}
+
+#[derive(Debug, Clone)]
+struct Filtered {
+    expr: Expr,
+    filters: Vec<FilterCall>,
+}
+
+#[derive(Debug, Clone)]
+enum Condition {
+    Truthy(Expr),
+    Compare(Expr, CmpOp, Expr),
+    And(Box<Condition>, Box<Condition>),
+    Or(Box<Condition>, Box<Condition>),
+    Not(Box<Condition>),
+}
+
+fn lex(source: &str, markup: &str, offset: usize) -> Result<Vec<(Tok, usize)>, TemplateError> {
+    let chars: Vec<(usize, char)> = markup.char_indices().collect();
+    let at = |i: usize| chars.get(i).map(|(_, c)| *c);
+    let mut toks = Vec::new();
+    let mut i = 0;
+
+    while i < chars.len() {
+        let (byte, c) = chars[i];
+        let abs = offset + byte;
+        if c.is_whitespace() {
+            i += 1;
+            continue;
+        }
+        let single = match c {
+            '[' => Some(Tok::LBracket),
+            ']' => Some(Tok::RBracket),
+            '(' => Some(Tok::LParen),
+            ')' => Some(Tok::RParen),
+            '|' => Some(Tok::Pipe),
+            ':' => Some(Tok::Colon),
+            ',' => Some(Tok::Comma),
+            _ => None,
+        };
+        if let Some(tok) = single {
+            toks.push((tok, abs));
+            i += 1;
+            continue;
+        }
+
+        match c {
+            '"' | '\'' => {
+                let mut j = i + 1;
+                while j < chars.len() && chars[j].1 != c {
+                    j += 1;
+                }
+                if j == chars.len() {
+                    return Err(error_at(source, abs, "unterminated string"));
+                }
+                let text: String = chars[i + 1..j].iter().map(|(_, ch)| ch).collect();
+                toks.push((Tok::Str(text), abs));
+                i = j + 1;
+            }
+            _ if c.is_ascii_digit()
+                || (c == '-' && at(i + 1).is_some_and(|n| n.is_ascii_digit())) =>
+            {
+                let mut j = i + 1;
+                while at(j).is_some_and(|n| n.is_ascii_digit()) {
+                    j += 1;
+                }
+                let is_float = at(j) == Some('.') && at(j + 1).is_some_and(|n| n.is_ascii_digit());
+                if is_float {
+                    j += 1;
+                    while at(j).is_some_and(|n| n.is_ascii_digit()) {
+                        j += 1;
+                    }
+                }
+                let text: String = chars[i..j].iter().map(|(_, ch)| ch).collect();
+                let value = if is_float {
+                    text.parse::<f64>().ok().map(Value::from)
                 } else {
-                    s.to_string()
+                    text.parse::<i64>().ok().map(Value::from)
+                };
+                let value = value
+                    .ok_or_else(|| error_at(source, abs, format!("invalid number `{}`", text)))?;
+                toks.push((Tok::Num(value), abs));
+                i = j;
+            }
+            _ if c.is_alphabetic() || c == '_' => {
+                let mut j = i + 1;
+                while at(j).is_some_and(|n| n.is_alphanumeric() || n == '_' || n == '-' || n == '?')
+                {
+                    j += 1;
                 }
-            }),
-        );
-        filters.insert(
-            "default".to_string(),
-            Box::new(|s: &str| {
-                if s.is_empty() {
-                    "N/A".to_string()
+                let ident: String = chars[i..j].iter().map(|(_, ch)| ch).collect();
+                toks.push((Tok::Ident(ident), abs));
+                i = j;
+            }
+            '.' => {
+                if at(i + 1) == Some('.') {
+                    toks.push((Tok::DotDot, abs));
+                    i += 2;
                 } else {
-                    s.to_string()
+                    toks.push((Tok::Dot, abs));
+                    i += 1;
                 }
-            }),
-        );
-        Self { filters }
+            }
+            '=' | '!' | '<' | '>' => {
+                let next = at(i + 1);
+                let (tok, len) = match (c, next) {
+                    ('=', Some('=')) => (Tok::Cmp(CmpOp::Eq), 2),
+                    ('=', _) => (Tok::Assign, 1),
+                    ('!', Some('=')) => (Tok::Cmp(CmpOp::Ne), 2),
+                    ('<', Some('=')) => (Tok::Cmp(CmpOp::Le), 2),
+                    ('<', Some('>')) => (Tok::Cmp(CmpOp::Ne), 2),
+                    ('<', _) => (Tok::Cmp(CmpOp::Lt), 1),
+                    ('>', Some('=')) => (Tok::Cmp(CmpOp::Ge), 2),
+                    ('>', _) => (Tok::Cmp(CmpOp::Gt), 1),
+                    _ => return Err(error_at(source, abs, "unexpected character `!`")),
+                };
+                toks.push((tok, abs));
+                i += len;
+            }
+            other => {
+                return Err(error_at(
+                    source,
+                    abs,
+                    format!("unexpected character `{}`", other),
+                ))
+            }
+        }
     }
+    Ok(toks)
+}
 
-    pub fn render(&self, template: &str, context: &TemplateContext) -> String {
-        let mut result = template.to_string();
-        result = self.render_variables(&result, "user", &context.user);
-        result = self.render_variables(&result, "event", &context.event);
-        result = self.render_variables(&result, "campaign", &context.campaign);
-        result = self.render_variables(&result, "catalog", &context.catalog);
-        result = self.render_variables(&result, "connected", &context.connected);
-        result = self.render_variables(&result, "custom", &context.custom);
-        result = self.render_conditionals(&result, context);
-        result
+struct ExprParser<'a> {
+    source: &'a str,
+    toks: Vec<(Tok, usize)>,
+    pos: usize,
+    /// Offset reported for errors at the end of the markup.
+    end: usize,
+}
+
+impl<'a> ExprParser<'a> {
+    fn new(source: &'a str, markup: &str, offset: usize) -> Result<Self, TemplateError> {
+        Ok(Self {
+            source,
+            toks: lex(source, markup, offset)?,
+            pos: 0,
+            end: offset + markup.trim_end().len(),
+        })
     }
 
-    fn render_variables(
-        &self,
-        template: &str,
-        prefix: &str,
-        vars: &HashMap<String, serde_json::Value>,
-    ) -> String {
-        let mut result = template.to_string();
-        for (key, value) in vars {
-            let placeholder = format!("{{{{{}.{}}}}}", prefix, key);
-            let display_value = match value {
-                serde_json::Value::String(s) => s.clone(),
-                serde_json::Value::Number(n) => n.to_string(),
-                serde_json::Value::Bool(b) => b.to_string(),
-                serde_json::Value::Null => String::new(),
-                other => other.to_string(),
+    fn peek(&self) -> Option<&Tok> {
+        self.toks.get(self.pos).map(|(t, _)| t)
+    }
+
+    fn next(&mut self) -> Option<Tok> {
+        let tok = self.toks.get(self.pos).map(|(t, _)| t.clone());
+        self.pos += 1;
+        tok
+    }
+
+    fn offset(&self) -> usize {
+        self.toks.get(self.pos).map_or(self.end, |(_, o)| *o)
+    }
+
+    fn error(&self, message: impl Into<String>) -> TemplateError {
+        error_at(self.source, self.offset(), message)
+    }
+
+    fn expect(&mut self, tok: Tok, what: &str) -> Result<(), TemplateError> {
+        if self.peek() == Some(&tok) {
+            self.pos += 1;
+            Ok(())
+        } else {
+            Err(self.error(format!("expected {}", what)))
+        }
+    }
+
+    fn eat_word(&mut self, word: &str) -> bool {
+        if matches!(self.peek(), Some(Tok::Ident(w)) if w == word) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn ident(&mut self) -> Result<String, TemplateError> {
+        match self.peek() {
+            Some(Tok::Ident(name)) => {
+                let name = name.clone();
+                self.pos += 1;
+                Ok(name)
+            }
+            _ => Err(self.error("expected a name")),
+        }
+    }
+
+    fn finish(&self) -> Result<(), TemplateError> {
+        if self.pos < self.toks.len() {
+            Err(self.error("unexpected trailing input"))
+        } else {
+            Ok(())
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr, TemplateError> {
+        let err = self.error("expected a value");
+        match self.next() {
+            Some(Tok::Str(s)) => Ok(Expr::Literal(Value::String(s))),
+            Some(Tok::Num(n)) => Ok(Expr::Literal(n)),
+            Some(Tok::LParen) => {
+                let from = self.primary()?;
+                self.expect(Tok::DotDot, "`..` in range")?;
+                let to = self.primary()?;
+                self.expect(Tok::RParen, "`)` closing range")?;
+                Ok(Expr::Range(Box::new(from), Box::new(to)))
+            }
+            Some(Tok::Ident(name)) => match name.as_str() {
+                "true" => Ok(Expr::Literal(Value::Bool(true))),
+                "false" => Ok(Expr::Literal(Value::Bool(false))),
+                "nil" | "null" => Ok(Expr::Literal(Value::Null)),
+                "empty" => Ok(Expr::Empty),
+                "blank" => Ok(Expr::Blank),
+                _ => {
+                    let mut segs = Vec::new();
+                    loop {
+                        match self.peek() {
+                            Some(Tok::Dot) => {
+                                self.pos += 1;
+                                segs.push(PathSeg::Key(self.ident()?));
+                            }
+                            Some(Tok::LBracket) => {
+                                self.pos += 1;
+                                segs.push(PathSeg::Index(self.primary()?));
+                                self.expect(Tok::RBracket, "`]`")?;
+                            }
+                            _ => break,
+                        }
+                    }
+                    Ok(Expr::Path(name, segs))
+                }
+            },
+            _ => {
+                self.pos -= 1;
+                Err(err)
+            }
+        }
+    }
+
+    fn filtered(&mut self, known: &HashMap<String, FilterFn>) -> Result<Filtered, TemplateError> {
+        let expr = self.primary()?;
+        let mut filters = Vec::new();
+        while self.peek() == Some(&Tok::Pipe) {
+            self.pos += 1;
+            let at = self.offset();
+            let name = self.ident()?;
+            if !known.contains_key(&name) {
+                return Err(error_at(
+                    self.source,
+                    at,
+                    format!("unknown filter `{}`", name),
+                ));
+            }
+            let mut args = Vec::new();
+            if self.peek() == Some(&Tok::Colon) {
+                self.pos += 1;
+                loop {
+                    args.push(self.primary()?);
+                    if self.peek() == Some(&Tok::Comma) {
+                        self.pos += 1;
+                    } else {
+                        break;
+                    }
+                }
+            }
+            filters.push(FilterCall { name, args });
+        }
+        Ok(Filtered { expr, filters })
+    }
+
+    /// `and`/`or` bind right to left with equal precedence, as in Liquid.
+    fn condition(&mut self) -> Result<Condition, TemplateError> {
+        let left = self.primary()?;
+        let op = match self.peek() {
+            Some(Tok::Cmp(op)) => Some(*op),
+            Some(Tok::Ident(w)) if w == "contains" => Some(CmpOp::Contains),
+            _ => None,
+        };
+        let base = match op {
+            Some(op) => {
+                self.pos += 1;
+                Condition::Compare(left, op, self.primary()?)
+            }
+            None => Condition::Truthy(left),
+        };
+        if self.eat_word("and") {
+            Ok(Condition::And(Box::new(base), Box::new(self.condition()?)))
+        } else if self.eat_word("or") {
+            Ok(Condition::Or(Box::new(base), Box::new(self.condition()?)))
+        } else {
+            Ok(base)
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Parser
+// ---------------------------------------------------------------------------
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Output {
+        value: Filtered,
+        pos: (usize, usize),
+    },
+    If {
+        branches: Vec<(Condition, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+    Case {
+        subject: Expr,
+        whens: Vec<(Vec<Expr>, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        var: String,
+        collection: Expr,
+        limit: Option<Expr>,
+        offset: Option<Expr>,
+        reversed: bool,
+        body: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+    Assign {
+        name: String,
+        value: Filtered,
+        pos: (usize, usize),
+    },
+    Capture {
+        name: String,
+        body: Vec<Node>,
+    },
+    Break,
+    Continue,
+}
+
+struct Parser<'a> {
+    source: &'a str,
+    segments: Vec<Segment>,
+    next: usize,
+    filters: &'a HashMap<String, FilterFn>,
+}
+
+impl<'a> Parser<'a> {
+    fn expr(&self, markup: &str, offset: usize) -> Result<ExprParser<'a>, TemplateError> {
+        ExprParser::new(self.source, markup, offset)
+    }
+
+    /// Parse nodes until one of the `ends` tags, which is returned. At the
+    /// top level (`ends` empty) the whole template is consumed.
+    fn parse_nodes(
+        &mut self,
+        ends: &[&str],
+    ) -> Result<(Vec<Node>, Option<TagSegment>), TemplateError> {
+        let mut nodes = Vec::new();
+        while self.next < self.segments.len() {
+            let segment =
+                std::mem::replace(&mut self.segments[self.next], Segment::Text(String::new()));
+            self.next += 1;
+            match segment {
+                Segment::Text(text) => nodes.push(Node::Text(text)),
+                Segment::Output { markup, offset } => {
+                    let mut p = self.expr(&markup, offset)?;
+                    let value = p.filtered(self.filters)?;
+                    p.finish()?;
+                    nodes.push(Node::Output {
+                        value,
+                        pos: position(self.source, offset),
+                    });
+                }
+                Segment::Tag(tag) => {
+                    if ends.contains(&tag.name.as_str()) {
+                        return Ok((nodes, Some(tag)));
+                    }
+                    nodes.push(self.parse_tag(tag)?);
+                }
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    /// Parse a block body that must be closed by one of `ends`.
+    fn parse_block(
+        &mut self,
+        opening: &TagSegment,
+        ends: &[&str],
+    ) -> Result<(Vec<Node>, TagSegment), TemplateError> {
+        match self.parse_nodes(ends)? {
+            (nodes, Some(end)) => Ok((nodes, end)),
+            (_, None) => Err(error_at(
+                self.source,
+                opening.start,
+                format!("`{{% {} %}}` is never closed", opening.name),
+            )),
+        }
+    }
+
+    fn parse_tag(&mut self, tag: TagSegment) -> Result<Node, TemplateError> {
+        match tag.name.as_str() {
+            "if" | "unless" => {
+                let end_name = format!("end{}", tag.name);
+                let ends = ["elsif", "else", end_name.as_str()];
+                let mut p = self.expr(&tag.markup, tag.offset)?;
+                let mut condition = p.condition()?;
+                p.finish()?;
+                if tag.name == "unless" {
+                    condition = Condition::Not(Box::new(condition));
+                }
+
+                let mut branches = Vec::new();
+                let mut otherwise = Vec::new();
+                loop {
+                    let (body, end) = self.parse_block(&tag, &ends)?;
+                    branches.push((condition, body));
+                    match end.name.as_str() {
+                        "elsif" if tag.name == "if" => {
+                            let mut p = self.expr(&end.markup, end.offset)?;
+                            condition = p.condition()?;
+                            p.finish()?;
+                        }
+                        "else" => {
+                            let (body, _) = self.parse_block(&tag, &[end_name.as_str()])?;
+                            otherwise = body;
+                            break;
+                        }
+                        "elsif" => {
+                            return Err(error_at(
+                                self.source,
+                                end.start,
+                                "`elsif` is not allowed in `unless`",
+                            ))
+                        }
+                        _ => break,
+                    }
+                }
+                Ok(Node::If {
+                    branches,
+                    otherwise,
+                })
+            }
+            "case" => {
+                let mut p = self.expr(&tag.markup, tag.offset)?;
+                let subject = p.primary()?;
+                p.finish()?;
+
+                let ends = ["when", "else", "endcase"];
+                let (_, mut end) = self.parse_block(&tag, &ends)?;
+                let mut whens = Vec::new();
+                let mut otherwise = Vec::new();
+                loop {
+                    match end.name.as_str() {
+                        "when" => {
+                            let mut p = self.expr(&end.markup, end.offset)?;
+                            let mut values = vec![p.primary()?];
+                            while p.eat_word("or") || p.peek() == Some(&Tok::Comma) {
+                                if p.peek() == Some(&Tok::Comma) {
+                                    p.pos += 1;
+                                }
+                                values.push(p.primary()?);
+                            }
+                            p.finish()?;
+                            let (body, next) = self.parse_block(&tag, &ends)?;
+                            whens.push((values, body));
+                            end = next;
+                        }
+                        "else" => {
+                            let (body, _) = self.parse_block(&tag, &["endcase"])?;
+                            otherwise = body;
+                            break;
+                        }
+                        _ => break,
+                    }
+                }
+                Ok(Node::Case {
+                    subject,
+                    whens,
+                    otherwise,
+                })
+            }
+            "for" => {
+                let mut p = self.expr(&tag.markup, tag.offset)?;
+                let var = p.ident()?;
+                if !p.eat_word("in") {
+                    return Err(p.error("expected `in`"));
+                }
+                let collection = p.primary()?;
+                let (mut limit, mut offset, mut reversed) = (None, None, false);
+                while p.peek().is_some() {
+                    let at = p.offset();
+                    match p.ident()?.as_str() {
+                        "reversed" => reversed = true,
+                        "limit" => {
+                            p.expect(Tok::Colon, "`:` after limit")?;
+                            limit = Some(p.primary()?);
+                        }
+                        "offset" => {
+                            p.expect(Tok::Colon, "`:` after offset")?;
+                            offset = Some(p.primary()?);
+                        }
+                        other => {
+                            return Err(error_at(
+                                self.source,
+                                at,
+                                format!("unknown `for` parameter `{}`", other),
+                            ))
+                        }
+                    }
+                }
+
+                let (body, end) = self.parse_block(&tag, &["else", "endfor"])?;
+                let otherwise = if end.name == "else" {
+                    self.parse_block(&tag, &["endfor"])?.0
+                } else {
+                    Vec::new()
+                };
+                Ok(Node::For {
+                    var,
+                    collection,
+                    limit,
+                    offset,
+                    reversed,
+                    body,
+                    otherwise,
+                })
+            }
+            "assign" => {
+                let mut p = self.expr(&tag.markup, tag.offset)?;
+                let name = p.ident()?;
+                p.expect(Tok::Assign, "`=`")?;
+                let value = p.filtered(self.filters)?;
+                p.finish()?;
+                Ok(Node::Assign {
+                    name,
+                    value,
+                    pos: position(self.source, tag.start),
+                })
+            }
+            "capture" => {
+                let mut p = self.expr(&tag.markup, tag.offset)?;
+                let name = p.ident()?;
+                p.finish()?;
+                let (body, _) = self.parse_block(&tag, &["endcapture"])?;
+                Ok(Node::Capture { name, body })
+            }
+            "break" => Ok(Node::Break),
+            "continue" => Ok(Node::Continue),
+            other if other.starts_with("end") || matches!(other, "else" | "elsif" | "when") => {
+                Err(error_at(
+                    self.source,
+                    tag.start,
+                    format!("unexpected `{{% {} %}}`", other),
+                ))
+            }
+            other => Err(error_at(
+                self.source,
+                tag.start,
+                format!("unknown tag `{}`", other),
+            )),
+        }
+    }
+}
+
+// ---------------------------------------------------------------------------
+// Renderer
+// ---------------------------------------------------------------------------
+
+enum Flow {
+    Normal,
+    Break,
+    Continue,
+}
+
+struct Renderer<'a> {
+    engine: &'a TemplateEngine,
+    root: Map<String, Value>,
+    /// Variable scopes; index 0 holds `assign`/`capture` globals.
+    scopes: Vec<Map<String, Value>>,
+    /// Globals holding captured output, which is already escaped.
+    safe_captures: HashSet<String>,
+}
+
+impl Renderer<'_> {
+    fn render_nodes(&mut self, nodes: &[Node], out: &mut String) -> Result<Flow, TemplateError> {
+        for node in nodes {
+            match node {
+                Node::Text(text) => out.push_str(text),
+                Node::Output { value, pos, .. } => {
+                    let rendered = self.evaluate(value, *pos)?;
+                    let text = to_text(&rendered);
+                    // Only the last filter decides: anything chained after
+                    // `escape` (e.g. `append: user.input`) may add raw markup.
+                    let safe = value.filters.last().is_some_and(|f| {
+                        matches!(f.name.as_str(), "raw" | "escape" | "escape_once")
+                    }) || (value.filters.is_empty() && self.is_capture(&value.expr));
+                    if self.engine.format == OutputFormat::Html && !safe {
+                        out.push_str(&escape_html(&text));
+                    } else {
+                        out.push_str(&text);
+                    }
+                }
+                Node::If {
+                    branches,
+                    otherwise,
+                } => {
+                    let body = branches
+                        .iter()
+                        .find(|(condition, _)| self.test(condition))
+                        .map_or(otherwise, |(_, body)| body);
+                    let flow = self.render_nodes(body, out)?;
+                    if !matches!(flow, Flow::Normal) {
+                        return Ok(flow);
+                    }
+                }
+                Node::Case {
+                    subject,
+                    whens,
+                    otherwise,
+                } => {
+                    let value = self.resolve(subject);
+                    let body = whens
+                        .iter()
+                        .find(|(values, _)| {
+                            values
+                                .iter()
+                                .any(|v| values_equal(&value, &self.resolve(v)))
+                        })
+                        .map_or(otherwise, |(_, body)| body);
+                    let flow = self.render_nodes(body, out)?;
+                    if !matches!(flow, Flow::Normal) {
+                        return Ok(flow);
+                    }
+                }
+                Node::For {
+                    var,
+                    collection,
+                    limit,
+                    offset,
+                    reversed,
+                    body,
+                    otherwise,
+                } => {
+                    let mut items = match self.resolve(collection) {
+                        Value::Array(items) => items,
+                        Value::Object(map) => map
+                            .into_iter()
+                            .map(|(k, v)| Value::Array(vec![Value::String(k), v]))
+                            .collect(),
+                        Value::Null => Vec::new(),
+                        Value::String(s) if s.is_empty() => Vec::new(),
+                        other => vec![other],
+                    };
+                    let skip = offset
+                        .as_ref()
+                        .and_then(|e| as_number(&self.resolve(e)))
+                        .map_or(0, |n| n.max(0.0) as usize);
+                    let take = limit
+                        .as_ref()
+                        .and_then(|e| as_number(&self.resolve(e)))
+                        .map_or(usize::MAX, |n| n.max(0.0) as usize);
+                    items = items.into_iter().skip(skip).take(take).collect();
+                    if *reversed {
+                        items.reverse();
+                    }
+
+                    if items.is_empty() {
+                        self.render_nodes(otherwise, out)?;
+                        continue;
+                    }
+                    let length = items.len();
+                    self.scopes.push(Map::new());
+                    for (i, item) in items.into_iter().enumerate() {
+                        let scope = self.scopes.last_mut().expect("loop scope");
+                        scope.insert(var.clone(), item);
+                        scope.insert(
+                            "forloop".to_string(),
+                            serde_json::json!({
+                                "index": i + 1,
+                                "index0": i,
+                                "rindex": length - i,
+                                "rindex0": length - i - 1,
+                                "first": i == 0,
+                                "last": i == length - 1,
+                                "length": length,
+                            }),
+                        );
+                        match self.render_nodes(body, out) {
+                            Ok(Flow::Break) => break,
+                            Ok(_) => {}
+                            Err(e) => {
+                                self.scopes.pop();
+                                return Err(e);
+                            }
+                        }
+                    }
+                    self.scopes.pop();
+                }
+                Node::Assign { name, value, pos } => {
+                    let value = self.evaluate(value, *pos)?;
+                    self.safe_captures.remove(name);
+                    self.scopes[0].insert(name.clone(), value);
+                }
+                Node::Capture { name, body } => {
+                    let mut captured = String::new();
+                    self.render_nodes(body, &mut captured)?;
+                    self.safe_captures.insert(name.clone());
+                    self.scopes[0].insert(name.clone(), Value::String(captured));
+                }
+                Node::Break => return Ok(Flow::Break),
+                Node::Continue => return Ok(Flow::Continue),
+            }
+        }
+        Ok(Flow::Normal)
+    }
+
+    fn evaluate(&self, value: &Filtered, pos: (usize, usize)) -> Result<Value, TemplateError> {
+        let mut current = self.resolve(&value.expr);
+        for call in &value.filters {
+            let args: Vec<Value> = call.args.iter().map(|a| self.resolve(a)).collect();
+            let filter = &self.engine.filters[&call.name];
+            current = filter(&current, &args).map_err(|e| TemplateError {
+                message: format!("filter `{}`: {}", call.name, e),
+                line: pos.0,
+                column: pos.1,
+            })?;
+        }
+        Ok(current)
+    }
+
+    /// Whether `expr` reads a captured global itself, not a loop variable
+    /// shadowing it or a property of it.
+    fn is_capture(&self, expr: &Expr) -> bool {
+        match expr {
+            Expr::Path(root, segs) => {
+                segs.is_empty()
+                    && self.safe_captures.contains(root)
+                    && !self.scopes[1..]
+                        .iter()
+                        .any(|scope| scope.contains_key(root))
+            }
+            _ => false,
+        }
+    }
+
+    fn lookup(&self, name: &str) -> Value {
+        self.scopes
+            .iter()
+            .rev()
+            .find_map(|scope| scope.get(name))
+            .or_else(|| self.root.get(name))
+            .cloned()
+            .unwrap_or(Value::Null)
+    }
+
+    fn resolve(&self, expr: &Expr) -> Value {
+        match expr {
+            Expr::Literal(v) => v.clone(),
+            Expr::Empty | Expr::Blank => Value::String(String::new()),
+            Expr::Range(from, to) => {
+                let from = as_number(&self.resolve(from)).unwrap_or(0.0) as i64;
+                let to = as_number(&self.resolve(to)).unwrap_or(0.0) as i64;
+                let to = to.min(from.saturating_add(MAX_RANGE_LEN - 1));
+                Value::Array((from..=to).map(Value::from).collect())
+            }
+            Expr::Path(root, segs) => {
+                let mut current = self.lookup(root);
+                for seg in segs {
+                    current = match seg {
+                        PathSeg::Key(key) => property(&current, key),
+                        PathSeg::Index(index) => match self.resolve(index) {
+                            Value::String(key) => property(&current, &key),
+                            Value::Number(n) => match (&current, n.as_i64()) {
+                                (Value::Array(items), Some(i)) => {
+                                    let i = if i < 0 { items.len() as i64 + i } else { i };
+                                    usize::try_from(i)
+                                        .ok()
+                                        .and_then(|i| items.get(i))
+                                        .cloned()
+                                        .unwrap_or(Value::Null)
+                                }
+                                _ => Value::Null,
+                            },
+                            _ => Value::Null,
+                        },
+                    };
+                }
+                current
+            }
+        }
+    }
+
+    fn test(&self, condition: &Condition) -> bool {
+        match condition {
+            Condition::Truthy(expr) => truthy(&self.resolve(expr)),
+            Condition::Compare(left, op, right) => self.compare(left, *op, right),
+            Condition::And(a, b) => self.test(a) && self.test(b),
+            Condition::Or(a, b) => self.test(a) || self.test(b),
+            Condition::Not(c) => !self.test(c),
+        }
+    }
+
+    fn compare(&self, left: &Expr, op: CmpOp, right: &Expr) -> bool {
+        let keyword = match (left, right) {
+            (_, Expr::Empty) => Some(is_empty(&self.resolve(left))),
+            (Expr::Empty, _) => Some(is_empty(&self.resolve(right))),
+            (_, Expr::Blank) => Some(is_blank(&self.resolve(left))),
+            (Expr::Blank, _) => Some(is_blank(&self.resolve(right))),
+            _ => None,
+        };
+        if let Some(matches) = keyword {
+            return match op {
+                CmpOp::Eq => matches,
+                CmpOp::Ne => !matches,
+                _ => false,
             };
-            result = result.replace(&placeholder, &display_value);
+        }
+
+        let (a, b) = (self.resolve(left), self.resolve(right));
+        match op {
+            CmpOp::Eq => values_equal(&a, &b),
+            CmpOp::Ne => !values_equal(&a, &b),
+            CmpOp::Contains => match (&a, &b) {
+                (Value::String(s), _) => s.contains(&to_text(&b)),
+                (Value::Array(items), _) => items.iter().any(|i| values_equal(i, &b)),
+                (Value::Object(map), Value::String(key)) => map.contains_key(key),
+                _ => false,
+            },
+            CmpOp::Lt | CmpOp::Gt | CmpOp::Le | CmpOp::Ge => {
+                let ordering = match (&a, &b) {
+                    (Value::Number(x), Value::Number(y)) => x
+                        .as_f64()
+                        .zip(y.as_f64())
+                        .and_then(|(x, y)| x.partial_cmp(&y)),
+                    (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+                    _ => None,
+                };
+                ordering.is_some_and(|o| match op {
+                    CmpOp::Lt => o.is_lt(),
+                    CmpOp::Gt => o.is_gt(),
+                    CmpOp::Le => o.is_le(),
+                    _ => o.is_ge(),
+                })
+            }
+        }
+    }
+}
+
+/// `size`, `first` and `last` work as properties, as in Liquid.
+fn property(value: &Value, key: &str) -> Value {
+    match (value, key) {
+        (Value::Object(map), _) if map.contains_key(key) => map[key].clone(),
+        (Value::Array(items), "size") => Value::from(items.len()),
+        (Value::Array(items), "first") => items.first().cloned().unwrap_or(Value::Null),
+        (Value::Array(items), "last") => items.last().cloned().unwrap_or(Value::Null),
+        (Value::Object(map), "size") => Value::from(map.len()),
+        (Value::String(s), "size") => Value::from(s.chars().count()),
+        _ => Value::Null,
+    }
+}
+
+fn truthy(value: &Value) -> bool {
+    !matches!(value, Value::Null | Value::Bool(false))
+}
 
-            for (filter_name, filter_fn) in &self.filters {
-                let filtered_placeholder = format!("{{{{{}.{} | {}}}}}", prefix, key, filter_name);
-                if result.contains(&filtered_placeholder) {
-                    let filtered = filter_fn(&display_value);
-                    result = result.replace(&filtered_placeholder, &filtered);
+fn is_empty(value: &Value) -> bool {
+    match value {
+        Value::String(s) => s.is_empty(),
+        Value::Array(a) => a.is_empty(),
+        Value::Object(m) => m.is_empty(),
+        _ => false,
+    }
+}
+
+fn is_blank(value: &Value) -> bool {
+    match value {
+        Value::Null | Value::Bool(false) => true,
+        Value::String(s) => s.trim().is_empty(),
+        other => is_empty(other),
+    }
+}
+
+fn values_equal(a: &Value, b: &Value) -> bool {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64() == y.as_f64(),
+        _ => a == b,
+    }
+}
+
+fn to_text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        Value::Number(n) => n.to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Array(items) => items.iter().map(to_text).collect(),
+        Value::Object(_) => value.to_string(),
+    }
+}
+
+fn escape_html(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
+
+// ---------------------------------------------------------------------------
+// Filters
+// ---------------------------------------------------------------------------
+
+fn as_number(value: &Value) -> Option<f64> {
+    match value {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+fn is_integer(value: &Value) -> bool {
+    match value {
+        Value::Number(n) => n.is_i64() || n.is_u64(),
+        Value::String(s) => s.trim().parse::<i64>().is_ok(),
+        _ => false,
+    }
+}
+
+fn number_value(n: f64, integer: bool) -> Value {
+    if integer && n.fract() == 0.0 && n.abs() < 9.0e15 {
+        Value::from(n as i64)
+    } else {
+        Value::from(n)
+    }
+}
+
+fn arg_text(args: &[Value], i: usize) -> Option<String> {
+    args.get(i).map(to_text)
+}
+
+fn arg_count(args: &[Value], i: usize, default: usize) -> Result<usize, String> {
+    match args.get(i) {
+        None => Ok(default),
+        Some(v) => as_number(v)
+            .filter(|n| *n >= 0.0)
+            .map(|n| n as usize)
+            .ok_or_else(|| format!("expected a non-negative number, got `{}`", to_text(v))),
+    }
+}
+
+fn text_filter(value: &Value, f: impl Fn(&str) -> String) -> Result<Value, String> {
+    Ok(Value::String(f(&to_text(value))))
+}
+
+fn filter_upcase(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, str::to_uppercase)
+}
+
+fn filter_downcase(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, str::to_lowercase)
+}
+
+fn filter_capitalize(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| {
+        let mut chars = s.chars();
+        match chars.next() {
+            None => String::new(),
+            Some(c) => c.to_uppercase().to_string() + &chars.as_str().to_lowercase(),
+        }
+    })
+}
+
+fn filter_strip(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| s.trim().to_string())
+}
+
+fn filter_lstrip(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| s.trim_start().to_string())
+}
+
+fn filter_rstrip(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| s.trim_end().to_string())
+}
+
+/// `truncate: length, ellipsis` — counts characters, not bytes, and the
+/// ellipsis is part of the length (Liquid semantics).
+fn filter_truncate(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let length = arg_count(args, 0, 50)?;
+    let ellipsis = arg_text(args, 1).unwrap_or_else(|| "...".to_string());
+    let text = to_text(v);
+    if text.chars().count() <= length {
+        return Ok(Value::String(text));
+    }
+    let keep = length.saturating_sub(ellipsis.chars().count());
+    Ok(Value::String(
+        text.chars().take(keep).collect::<String>() + &ellipsis,
+    ))
+}
+
+fn filter_truncatewords(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let count = arg_count(args, 0, 15)?.max(1);
+    let ellipsis = arg_text(args, 1).unwrap_or_else(|| "...".to_string());
+    let text = to_text(v);
+    let words: Vec<&str> = text.split_whitespace().collect();
+    if words.len() <= count {
+        return Ok(Value::String(text));
+    }
+    Ok(Value::String(words[..count].join(" ") + &ellipsis))
+}
+
+/// Falls back when the value is nil, false or empty. Without an argument the
+/// fallback is the empty string.
+fn filter_default(v: &Value, args: &[Value]) -> Result<Value, String> {
+    if is_empty(v) || !truthy(v) {
+        Ok(args
+            .first()
+            .cloned()
+            .unwrap_or_else(|| Value::String(String::new())))
+    } else {
+        Ok(v.clone())
+    }
+}
+
+fn parse_date(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(n) => Utc.timestamp_opt(n.as_f64()? as i64, 0).single(),
+        Value::String(s) => {
+            let s = s.trim();
+            if s == "now" || s == "today" {
+                return Some(Utc::now());
+            }
+            if let Ok(secs) = s.parse::<i64>() {
+                return Utc.timestamp_opt(secs, 0).single();
+            }
+            DateTime::parse_from_rfc3339(s)
+                .map(|d| d.with_timezone(&Utc))
+                .ok()
+                .or_else(|| {
+                    NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M:%S")
+                        .ok()
+                        .map(|d| d.and_utc())
+                })
+                .or_else(|| {
+                    NaiveDate::parse_from_str(s, "%Y-%m-%d")
+                        .ok()
+                        .and_then(|d| d.and_hms_opt(0, 0, 0))
+                        .map(|d| d.and_utc())
+                })
+        }
+        _ => None,
+    }
+}
+
+/// `date: "%b %d, %Y"` — strftime formatting. Values that are not dates
+/// pass through unchanged.
+fn filter_date(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let format = arg_text(args, 0).ok_or("a format argument is required")?;
+    if StrftimeItems::new(&format).any(|item| matches!(item, Item::Error)) {
+        return Err(format!("invalid date format `{}`", format));
+    }
+    Ok(match parse_date(v) {
+        Some(date) => Value::String(date.format(&format).to_string()),
+        None => v.clone(),
+    })
+}
+
+/// `money` / `money: "€"` — two decimals with thousands separators.
+fn filter_money(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let Some(amount) = as_number(v) else {
+        return Ok(v.clone());
+    };
+    let symbol = arg_text(args, 0).unwrap_or_else(|| "$".to_string());
+    let fixed = format!("{:.2}", amount.abs());
+    let (whole, cents) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+    let mut grouped = String::new();
+    for (i, c) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(c);
+    }
+    let sign = if amount < 0.0 { "-" } else { "" };
+    Ok(Value::String(format!(
+        "{}{}{}.{}",
+        sign, symbol, grouped, cents
+    )))
+}
+
+fn filter_escape(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, escape_html)
+}
+
+fn filter_escape_once(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| {
+        let unescaped = s
+            .replace("&lt;", "<")
+            .replace("&gt;", ">")
+            .replace("&quot;", "\"")
+            .replace("&#39;", "'")
+            .replace("&amp;", "&");
+        escape_html(&unescaped)
+    })
+}
+
+/// Marks output as trusted HTML; it is written without escaping.
+fn filter_raw(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(v.clone())
+}
+
+fn filter_url_encode(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| {
+        let mut out = String::new();
+        for byte in s.bytes() {
+            match byte {
+                b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                    out.push(byte as char)
                 }
+                b' ' => out.push('+'),
+                _ => out.push_str(&format!("%{:02X}", byte)),
+            }
+        }
+        out
+    })
+}
+
+fn filter_strip_html(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| {
+        let mut out = String::new();
+        let mut in_tag = false;
+        for c in s.chars() {
+            match c {
+                '<' => in_tag = true,
+                '>' if in_tag => in_tag = false,
+                _ if !in_tag => out.push(c),
+                _ => {}
             }
         }
-        result
+        out
+    })
+}
+
+fn filter_newline_to_br(v: &Value, _: &[Value]) -> Result<Value, String> {
+    text_filter(v, |s| s.replace('\n', "<br />\n"))
+}
+
+fn filter_append(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let suffix = arg_text(args, 0).unwrap_or_default();
+    text_filter(v, |s| format!("{}{}", s, suffix))
+}
+
+fn filter_prepend(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let prefix = arg_text(args, 0).unwrap_or_default();
+    text_filter(v, |s| format!("{}{}", prefix, s))
+}
+
+fn filter_replace(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let from = arg_text(args, 0).ok_or("expected the text to replace")?;
+    let to = arg_text(args, 1).unwrap_or_default();
+    text_filter(v, |s| s.replace(&from, &to))
+}
+
+fn filter_replace_first(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let from = arg_text(args, 0).ok_or("expected the text to replace")?;
+    let to = arg_text(args, 1).unwrap_or_default();
+    text_filter(v, |s| s.replacen(&from, &to, 1))
+}
+
+fn filter_remove(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let needle = arg_text(args, 0).ok_or("expected the text to remove")?;
+    text_filter(v, |s| s.replace(&needle, ""))
+}
+
+fn filter_split(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let separator = arg_text(args, 0).unwrap_or_else(|| " ".to_string());
+    let text = to_text(v);
+    let parts: Vec<Value> = if separator.is_empty() {
+        text.chars().map(|c| Value::String(c.to_string())).collect()
+    } else {
+        text.split(separator.as_str())
+            .map(|p| Value::String(p.to_string()))
+            .collect()
+    };
+    Ok(Value::Array(parts))
+}
+
+fn filter_join(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let separator = arg_text(args, 0).unwrap_or_else(|| " ".to_string());
+    match v {
+        Value::Array(items) => Ok(Value::String(
+            items
+                .iter()
+                .map(to_text)
+                .collect::<Vec<_>>()
+                .join(&separator),
+        )),
+        other => Ok(other.clone()),
     }
+}
+
+fn filter_size(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(match v {
+        Value::Null => Value::from(0),
+        Value::String(s) => Value::from(s.chars().count()),
+        other => property(other, "size"),
+    })
+}
 
-    fn render_conditionals(&self, template: &str, _context: &TemplateContext) -> String {
-        // Simplified conditional rendering: strip {% if %} / {% endif %} blocks
-        // In production, this would use a proper parser
-        template.to_string()
+fn filter_first(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(match v {
+        Value::String(s) => s
+            .chars()
+            .next()
+            .map_or(Value::Null, |c| c.to_string().into()),
+        other => property(other, "first"),
+    })
+}
+
+fn filter_last(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(match v {
+        Value::String(s) => s
+            .chars()
+            .last()
+            .map_or(Value::Null, |c| c.to_string().into()),
+        other => property(other, "last"),
+    })
+}
+
+fn filter_reverse(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(match v {
+        Value::Array(items) => Value::Array(items.iter().rev().cloned().collect()),
+        other => other.clone(),
+    })
+}
+
+fn sort_key(value: &Value, key: Option<&str>) -> Value {
+    match key {
+        Some(key) => property(value, key),
+        None => value.clone(),
     }
 }
 
-impl Default for TemplateEngine {
-    fn default() -> Self {
-        Self::new()
+/// `sort` / `sort: "price"` — numbers before strings, nil last.
+fn filter_sort(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let Value::Array(items) = v else {
+        return Ok(v.clone());
+    };
+    let key = arg_text(args, 0);
+    let mut items = items.clone();
+    items.sort_by(|a, b| {
+        let (a, b) = (sort_key(a, key.as_deref()), sort_key(b, key.as_deref()));
+        match (&a, &b) {
+            (Value::Number(x), Value::Number(y)) => x
+                .as_f64()
+                .partial_cmp(&y.as_f64())
+                .unwrap_or(std::cmp::Ordering::Equal),
+            (Value::Null, Value::Null) => std::cmp::Ordering::Equal,
+            (Value::Null, _) => std::cmp::Ordering::Greater,
+            (_, Value::Null) => std::cmp::Ordering::Less,
+            (Value::Number(_), _) => std::cmp::Ordering::Less,
+            (_, Value::Number(_)) => std::cmp::Ordering::Greater,
+            _ => to_text(&a).cmp(&to_text(&b)),
+        }
+    });
+    Ok(Value::Array(items))
+}
+
+fn filter_map(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let key = arg_text(args, 0).ok_or("expected a property name")?;
+    Ok(match v {
+        Value::Array(items) => Value::Array(items.iter().map(|i| property(i, &key)).collect()),
+        other => property(other, &key),
+    })
+}
+
+fn filter_uniq(v: &Value, _: &[Value]) -> Result<Value, String> {
+    let Value::Array(items) = v else {
+        return Ok(v.clone());
+    };
+    let mut unique: Vec<Value> = Vec::new();
+    for item in items {
+        if !unique.iter().any(|u| values_equal(u, item)) {
+            unique.push(item.clone());
+        }
+    }
+    Ok(Value::Array(unique))
+}
+
+fn filter_compact(v: &Value, _: &[Value]) -> Result<Value, String> {
+    Ok(match v {
+        Value::Array(items) => {
+            Value::Array(items.iter().filter(|i| !i.is_null()).cloned().collect())
+        }
+        other => other.clone(),
+    })
+}
+
+fn arithmetic(
+    v: &Value,
+    args: &[Value],
+    op: impl Fn(f64, f64) -> Result<f64, String>,
+) -> Result<Value, String> {
+    let operand = args.first().ok_or("expected a number argument")?;
+    let a = as_number(v).unwrap_or(0.0);
+    let b = as_number(operand).ok_or_else(|| format!("`{}` is not a number", to_text(operand)))?;
+    Ok(number_value(
+        op(a, b)?,
+        (is_integer(v) || v.is_null()) && is_integer(operand),
+    ))
+}
+
+fn filter_plus(v: &Value, args: &[Value]) -> Result<Value, String> {
+    arithmetic(v, args, |a, b| Ok(a + b))
+}
+
+fn filter_minus(v: &Value, args: &[Value]) -> Result<Value, String> {
+    arithmetic(v, args, |a, b| Ok(a - b))
+}
+
+fn filter_times(v: &Value, args: &[Value]) -> Result<Value, String> {
+    arithmetic(v, args, |a, b| Ok(a * b))
+}
+
+/// Integer division when both sides are integers, as in Liquid.
+fn filter_divided_by(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let integer = is_integer(v) && args.first().is_some_and(is_integer);
+    arithmetic(v, args, |a, b| {
+        if b == 0.0 {
+            Err("division by zero".to_string())
+        } else if integer {
+            Ok((a / b).floor())
+        } else {
+            Ok(a / b)
+        }
+    })
+}
+
+fn filter_modulo(v: &Value, args: &[Value]) -> Result<Value, String> {
+    arithmetic(v, args, |a, b| {
+        if b == 0.0 {
+            Err("division by zero".to_string())
+        } else {
+            Ok(a % b)
+        }
+    })
+}
+
+fn filter_round(v: &Value, args: &[Value]) -> Result<Value, String> {
+    let digits = arg_count(args, 0, 0)?.min(10) as i32;
+    let Some(n) = as_number(v) else {
+        return Ok(v.clone());
+    };
+    let factor = 10f64.powi(digits);
+    Ok(number_value((n * factor).round() / factor, digits == 0))
+}
+
+fn unary(v: &Value, op: fn(f64) -> f64, integer: bool) -> Result<Value, String> {
+    Ok(match as_number(v) {
+        Some(n) => number_value(op(n), integer),
+        None => v.clone(),
+    })
+}
+
+fn filter_abs(v: &Value, _: &[Value]) -> Result<Value, String> {
+    unary(v, f64::abs, is_integer(v))
+}
+
+fn filter_ceil(v: &Value, _: &[Value]) -> Result<Value, String> {
+    unary(v, f64::ceil, true)
+}
+
+fn filter_floor(v: &Value, _: &[Value]) -> Result<Value, String> {
+    unary(v, f64::floor, true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn context() -> TemplateContext {
+        let mut ctx = TemplateContext::default();
+        ctx.user.insert("first_name".into(), json!("ada"));
+        ctx.user.insert("tier".into(), json!("gold"));
+        ctx.user.insert("points".into(), json!(1250));
+        ctx.user
+            .insert("bio".into(), json!("<b>Engineer</b> & \"mathematician\""));
+        ctx.user
+            .insert("evil".into(), json!("<script>alert(1)</script>"));
+        ctx.catalog.insert(
+            "products".into(),
+            json!([
+                {"name": "Espresso Machine", "price": 1299.5},
+                {"name": "Grinder", "price": 249},
+                {"name": "Milk Frother", "price": 59.99},
+            ]),
+        );
+        ctx.recommendations.insert(
+            "for_you".into(),
+            json!([{"name": "Beans"}, {"name": "Mugs"}]),
+        );
+        ctx
+    }
+
+    fn render(template: &str) -> String {
+        TemplateEngine::new().render(template, &context()).unwrap()
+    }
+
+    #[test]
+    fn test_variables_and_chained_filters() {
+        assert_eq!(render("Hi {{ user.first_name | capitalize }}!"), "Hi Ada!");
+        assert_eq!(
+            render("{{ user.first_name | append: ' lovelace' | upcase | truncate: 8 }}"),
+            "ADA L..."
+        );
+        assert_eq!(render("{{ user.missing | default: 'friend' }}"), "friend");
+        assert_eq!(render("{{user.missing | default}}"), "");
+        assert_eq!(render("{{ user.points | plus: 50 | money }}"), "$1,300.00");
+    }
+
+    #[test]
+    fn test_truncate_is_utf8_safe() {
+        let engine = TemplateEngine::new();
+        let mut ctx = TemplateContext::default();
+        ctx.user.insert("name".into(), json!("Zoë 🎉 Ünïcödé Çafé"));
+        let out = engine
+            .render("{{ user.name | truncate: 8 }}", &ctx)
+            .unwrap();
+        assert_eq!(out, "Zoë 🎉...");
+    }
+
+    #[test]
+    fn test_conditionals() {
+        let template = "{% if user.tier == 'platinum' %}P{% elsif user.tier == 'gold' and user.points > 1000 %}G{% else %}S{% endif %}";
+        assert_eq!(render(template), "G");
+        assert_eq!(
+            render("{% unless user.vip %}regular{% endunless %}"),
+            "regular"
+        );
+        assert_eq!(
+            render("{% if user.bio contains 'Engineer' %}yes{% endif %}"),
+            "yes"
+        );
+        assert_eq!(render("{% if user.missing == empty %}e{% endif %}"), "");
+        assert_eq!(
+            render(
+                "{% case user.tier %}{% when 'silver', 'gold' %}metal{% else %}other{% endcase %}"
+            ),
+            "metal"
+        );
+    }
+
+    #[test]
+    fn test_for_loops_and_assign() {
+        let template = "{% for p in catalog.products limit: 2 %}{{ forloop.index }}.{{ p.name }}{% unless forloop.last %}, {% endunless %}{% endfor %}";
+        assert_eq!(render(template), "1.Espresso Machine, 2.Grinder");
+
+        let template =
+            "{% assign cheap = catalog.products | sort: 'price' | first %}{{ cheap.name }}";
+        assert_eq!(render(template), "Milk Frother");
+
+        assert_eq!(
+            render("{% for r in recommendations.for_you reversed %}{{ r.name }} {% endfor %}"),
+            "Mugs Beans "
+        );
+        assert_eq!(
+            render("{% for x in user.none %}x{% else %}nothing{% endfor %}"),
+            "nothing"
+        );
+        assert_eq!(
+            render("{% for i in (1..5) %}{% if i == 4 %}{% break %}{% endif %}{{ i }}{% endfor %}"),
+            "123"
+        );
+        assert_eq!(
+            render("{%- capture greeting -%} Hello {{ user.first_name }} {%- endcapture -%} [{{ greeting }}]"),
+            "[Hello ada]"
+        );
+    }
+
+    #[test]
+    fn test_html_escaping_by_channel() {
+        assert_eq!(
+            render("{{ user.bio }}"),
+            "&lt;b&gt;Engineer&lt;/b&gt; &amp; &quot;mathematician&quot;"
+        );
+        assert_eq!(
+            render("{{ user.bio | raw }}"),
+            "<b>Engineer</b> & \"mathematician\""
+        );
+        assert_eq!(
+            render("{% raw %}{{ not parsed }}{% endraw %}"),
+            "{{ not parsed }}"
+        );
+
+        let sms = TemplateEngine::for_channel(&ActivationChannel::Sms);
+        assert_eq!(sms.output_format(), OutputFormat::PlainText);
+        assert_eq!(
+            sms.render("{{ user.bio }}", &context()).unwrap(),
+            "<b>Engineer</b> & \"mathematician\""
+        );
+        let email = TemplateEngine::for_channel(&ActivationChannel::Email);
+        assert_eq!(email.output_format(), OutputFormat::Html);
+    }
+
+    #[test]
+    fn test_html_capture_is_not_escaped_twice() {
+        let email = TemplateEngine::for_channel(&ActivationChannel::Email);
+        let render = |template: &str| email.render(template, &context()).unwrap();
+        assert_eq!(
+            render("{% capture bio %}<b>Hi</b> {{ user.bio }} &amp; more{% endcapture %}{{ bio }}"),
+            "<b>Hi</b> &lt;b&gt;Engineer&lt;/b&gt; &amp; &quot;mathematician&quot; &amp; more"
+        );
+        // Reassigned, or shadowed by a loop variable, it is escaped again.
+        assert_eq!(
+            render("{% capture x %}<i>{% endcapture %}{% assign x = user.bio %}{{ x }}"),
+            "&lt;b&gt;Engineer&lt;/b&gt; &amp; &quot;mathematician&quot;"
+        );
+        assert_eq!(
+            render(
+                "{% capture x %}<i>{% endcapture %}{% for x in (1..1) %}{{ x }}{% endfor %}{{ x }}"
+            ),
+            "1<i>"
+        );
+    }
+
+    #[test]
+    fn test_flat_variables_and_referenced_names() {
+        let engine = TemplateEngine::new();
+        let mut ctx = context();
+        ctx.variables
+            .insert("offer_url".into(), json!("https://x.test/o?a=1&b=2"));
+        ctx.variables.insert("user".into(), json!("shadowed"));
+        assert_eq!(
+            engine
+                .render("{{ offer_url }} {{ user.tier }}", &ctx)
+                .unwrap(),
+            "https://x.test/o?a=1&amp;b=2 gold"
+        );
+
+        let template = engine
+            .parse("{% assign n = user.first_name %}{% for p in catalog.products %}{{ p.name }}{{ n }}{% endfor %}{{ coupon_code | default: fallback }}")
+            .unwrap();
+        let names: Vec<String> = template.referenced_variables().into_iter().collect();
+        assert_eq!(names, ["catalog", "coupon_code", "fallback", "user"]);
+    }
+
+    #[test]
+    fn test_date_filter() {
+        let mut ctx = TemplateContext::default();
+        ctx.event.insert("at".into(), json!("2024-03-05T10:00:00Z"));
+        let engine = TemplateEngine::new();
+        assert_eq!(
+            engine
+                .render("{{ event.at | date: \"%b %d\" }}", &ctx)
+                .unwrap(),
+            "Mar 05"
+        );
+        let err = engine
+            .render("{{ event.at | date: \"%Q\" }}", &ctx)
+            .unwrap_err();
+        assert!(err.message.contains("invalid date format"));
+    }
+
+    #[test]
+    fn test_parse_errors_report_line_and_column() {
+        let engine = TemplateEngine::new();
+
+        let err = engine.parse("Hello\n  {% if user.vip %}VIP").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 6));
+        assert!(err.message.contains("never closed"));
+
+        let err = engine.parse("a\nb {{ user.name | shout }}").unwrap_err();
+        assert_eq!((err.line, err.column), (2, 18));
+        assert_eq!(err.message, "unknown filter `shout`");
+
+        let err = engine.parse("{{ user.name ").unwrap_err();
+        assert_eq!((err.line, err.column), (1, 1));
+
+        let err = engine.parse("{% endif %}").unwrap_err();
+        assert!(err.to_string().contains("line 1, column 4"));
+    }
+
+    #[test]
+    fn test_only_trailing_escape_marks_output_safe() {
+        assert_eq!(
+            render("{{ user.bio | escape }}"),
+            "&lt;b&gt;Engineer&lt;/b&gt; &amp; &quot;mathematician&quot;"
+        );
+        for template in [
+            "{{ user.first_name | escape | append: user.evil }}",
+            "{{ user.first_name | escape | prepend: user.evil }}",
+            "{{ user.first_name | escape | replace: 'ada', user.evil }}",
+            "{{ user.first_name | raw | append: user.evil }}",
+            "{{ user.first_name | escape_once | append: user.evil }}",
+        ] {
+            let out = render(template);
+            assert!(!out.contains("<script>"), "{template} rendered {out}");
+            assert!(out.contains("&lt;script&gt;"), "{template} rendered {out}");
+        }
+        assert_eq!(
+            render("{{ user.first_name | append: user.evil | escape }}"),
+            "ada&lt;script&gt;alert(1)&lt;/script&gt;"
+        );
+        assert_eq!(
+            render("{{ '&lt;b&gt; & <i>' | escape_once }}"),
+            "&lt;b&gt; &amp; &lt;i&gt;"
+        );
+    }
+
+    #[test]
+    fn test_string_filters() {
+        assert_eq!(render("[{{ '  pad  ' | strip }}]"), "[pad]");
+        assert_eq!(render("[{{ '  pad  ' | lstrip }}]"), "[pad  ]");
+        assert_eq!(render("[{{ '  pad  ' | rstrip }}]"), "[  pad]");
+        assert_eq!(render("{{ 'MiXeD' | downcase }}"), "mixed");
+        assert_eq!(
+            render("{{ 'one two three four' | truncatewords: 2 }}"),
+            "one two..."
+        );
+        assert_eq!(render("{{ 'a-b-a' | replace_first: 'a', 'x' }}"), "x-b-a");
+        assert_eq!(render("{{ 'a-b-a' | remove: 'a' }}"), "-b-");
+        assert_eq!(render("{{ 'a b&c' | url_encode }}"), "a+b%26c");
+        assert_eq!(
+            render("{{ user.bio | strip_html | raw }}"),
+            "Engineer & \"mathematician\""
+        );
+        assert_eq!(render("{{ 'a\nb' | newline_to_br | raw }}"), "a<br />\nb");
+        assert_eq!(
+            render("{{ 'c,a,b,a' | split: ',' | uniq | join: '+' }}"),
+            "c+a+b"
+        );
+    }
+
+    #[test]
+    fn test_collection_filters() {
+        assert_eq!(render("{{ catalog.products | size }}"), "3");
+        assert_eq!(render("{{ user.first_name | size }}"), "3");
+        assert_eq!(
+            render("{{ catalog.products | map: 'name' | join: ', ' }}"),
+            "Espresso Machine, Grinder, Milk Frother"
+        );
+        assert_eq!(
+            render("{% assign p = catalog.products | last %}{{ p.name }}"),
+            "Milk Frother"
+        );
+        assert_eq!(
+            render("{{ recommendations.for_you | map: 'name' | reverse | first }}"),
+            "Mugs"
+        );
+        assert_eq!(
+            render("{{ catalog.products | sort: 'price' | map: 'name' | first }}"),
+            "Milk Frother"
+        );
+    }
+
+    #[test]
+    fn test_math_and_money_filters() {
+        assert_eq!(render("{{ 7 | divided_by: 2 }}"), "3");
+        assert_eq!(render("{{ 7.0 | divided_by: 2 }}"), "3.5");
+        assert_eq!(render("{{ 7 | modulo: 3 }}"), "1");
+        assert_eq!(render("{{ 5 | minus: 8 | abs }}"), "3");
+        assert_eq!(render("{{ 2.1 | ceil }} {{ 2.9 | floor }}"), "3 2");
+        assert_eq!(render("{{ 3.14159 | round: 2 }}"), "3.14");
+        assert_eq!(render("{{ 4 | times: 2.5 }}"), "10.0");
+        assert_eq!(render("{{ 1234567.891 | money }}"), "$1,234,567.89");
+        assert_eq!(render("{{ -5 | money }}"), "-$5.00");
+    }
+
+    #[test]
+    fn test_for_loop_parameters_and_forloop_object() {
+        assert_eq!(
+            render("{% for p in catalog.products offset: 1 %}{{ p.name }};{% endfor %}"),
+            "Grinder;Milk Frother;"
+        );
+        assert_eq!(
+            render(
+                "{% for i in (1..5) %}{% if i == 2 %}{% continue %}{% endif %}{{ i }}{% endfor %}"
+            ),
+            "1345"
+        );
+        assert_eq!(
+            render("{% for i in (1..3) %}{{ forloop.rindex }}/{{ forloop.length }}{% if forloop.first %}F{% endif %} {% endfor %}"),
+            "3/3F 2/3 1/3 "
+        );
+        assert_eq!(
+            render("{% for a in (1..2) %}{% for b in (1..2) %}{{ a }}{{ b }}{% break %}{% endfor %},{% endfor %}"),
+            "11,21,"
+        );
+        assert_eq!(
+            render("{% assign n = 3 %}{% for i in (1..n) %}{{ i }}{% endfor %}"),
+            "123"
+        );
+        let capped = render("{% for i in (1..20000) %}x{% endfor %}");
+        assert_eq!(capped.len() as i64, MAX_RANGE_LEN);
+    }
+
+    #[test]
+    fn test_runtime_filter_errors_report_position() {
+        let engine = TemplateEngine::new();
+        let err = engine
+            .render("line\n  {{ user.points | divided_by: 0 }}", &context())
+            .unwrap_err();
+        assert_eq!(err.message, "filter `divided_by`: division by zero");
+        assert_eq!((err.line, err.column), (2, 5));
+
+        let err = engine
+            .render("{{ 1 | plus: 'abc' }}", &context())
+            .unwrap_err();
+        assert!(err.message.contains("is not a number"));
+
+        // Errors inside a loop body propagate out of the loop.
+        let err = engine
+            .render(
+                "{% for i in (1..3) %}{{ i | modulo: 0 }}{% endfor %}",
+                &context(),
+            )
+            .unwrap_err();
+        assert!(err.message.contains("division by zero"));
+    }
+
+    #[test]
+    fn test_structural_parse_errors() {
+        let engine = TemplateEngine::new();
+        assert!(engine
+            .parse("{% frobnicate %}")
+            .unwrap_err()
+            .message
+            .contains("unknown tag `frobnicate`"));
+        assert!(engine
+            .parse("{% for p in catalog.products step: 2 %}{% endfor %}")
+            .unwrap_err()
+            .message
+            .contains("unknown `for` parameter `step`"));
+        assert!(engine
+            .parse("{{ 'open }}")
+            .unwrap_err()
+            .message
+            .contains("unterminated string"));
+        assert!(engine.parse("{% if a %}{% endfor %}").is_err());
+        assert!(engine
+            .parse("{% raw %}never closed")
+            .unwrap_err()
+            .message
+            .contains("never closed"));
+    }
+
+    #[test]
+    fn test_custom_filters() {
+        let mut engine = TemplateEngine::new();
+        engine.register_filter("shout", |v, _| Ok(Value::String(to_text(v) + "!")));
+        engine.register_filter("reject", |_, _| Err("nope".to_string()));
+        assert_eq!(
+            engine
+                .render("{{ user.first_name | shout }}", &context())
+                .unwrap(),
+            "ada!"
+        );
+        let err = engine
+            .render("{{ user.first_name | reject }}", &context())
+            .unwrap_err();
+        assert_eq!(err.message, "filter `reject`: nope");
     }
 }
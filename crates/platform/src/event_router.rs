@@ -0,0 +1,216 @@
+//! Event router — the adapter between producers and consumers of events.
+//!
+//! Every module emits [`AnalyticsEvent`]s into an `Arc<dyn EventSink>`.
+//! Handing them an [`EventRouter`] converts each event to the canonical
+//! [`EventEnvelope`] and fans envelopes out to [`EnvelopeSink`]s. Sinks that
+//! still consume the raw event shape (live metrics, NATS) receive every event
+//! unchanged.
+//!
+//! Envelopes built from typed events are valid by construction, so emitted
+//! events are checked against the [`SchemaRegistry`] only on a sample, which
+//! keeps schema validation off the bid path while still catching drift. The
+//! sample only observes: a failing envelope is counted and still published,
+//! so sampling never changes what consumers see. Envelopes handed to
+//! [`EventRouter::route`] from outside are always validated, and rejected
+//! ones are not published.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use campaign_core::event_bus::EventSink;
+use campaign_core::event_envelope::{EnvelopeSink, EventEnvelope};
+use campaign_core::types::AnalyticsEvent;
+use tracing::warn;
+
+use crate::governance::{SchemaRegistry, SchemaViolation};
+
+/// By default one in this many emitted events is schema-validated.
+const DEFAULT_VALIDATE_EVERY: u64 = 1000;
+
+/// Routes events from any producer through the canonical envelope.
+pub struct EventRouter {
+    registry: Arc<SchemaRegistry>,
+    envelope_sinks: Vec<Arc<dyn EnvelopeSink>>,
+    event_sinks: Vec<Arc<dyn EventSink>>,
+    validate_every: u64,
+    emitted: AtomicU64,
+}
+
+impl EventRouter {
+    /// Create a router validating against `registry`. The envelope schemas
+    /// are registered if they are not already.
+    pub fn new(registry: Arc<SchemaRegistry>) -> Self {
+        registry.register_event_envelope();
+        Self {
+            registry,
+            envelope_sinks: Vec::new(),
+            event_sinks: Vec::new(),
+            validate_every: DEFAULT_VALIDATE_EVERY,
+            emitted: AtomicU64::new(0),
+        }
+    }
+
+    /// Validate one in `every` emitted events (1 validates all of them).
+    pub fn with_validation_sampling(mut self, every: u64) -> Self {
+        self.validate_every = every.max(1);
+        self
+    }
+
+    /// Deliver validated envelopes to `sink`.
+    pub fn with_envelope_sink(mut self, sink: Arc<dyn EnvelopeSink>) -> Self {
+        self.envelope_sinks.push(sink);
+        self
+    }
+
+    /// Deliver every raw event to `sink`.
+    pub fn with_event_sink(mut self, sink: Arc<dyn EventSink>) -> Self {
+        self.event_sinks.push(sink);
+        self
+    }
+
+    pub fn registry(&self) -> &Arc<SchemaRegistry> {
+        &self.registry
+    }
+
+    /// Validate an envelope and publish it to the envelope sinks. Rejected
+    /// envelopes are counted and not published.
+    pub fn route(&self, envelope: &EventEnvelope) -> Result<(), Vec<SchemaViolation>> {
+        let family = envelope.family().as_str();
+        if let Err(violations) = self.registry.validate_envelope(envelope) {
+            metrics::counter!("events.rejected", "family" => family).increment(1);
+            warn!(
+                event_id = %envelope.event_id,
+                event_type = ?envelope.event_type,
+                violations = ?violations,
+                "Event envelope failed schema validation"
+            );
+            return Err(violations);
+        }
+        self.publish(envelope);
+        Ok(())
+    }
+
+    fn publish(&self, envelope: &EventEnvelope) {
+        metrics::counter!("events.routed", "family" => envelope.family().as_str()).increment(1);
+        for sink in &self.envelope_sinks {
+            sink.publish(envelope);
+        }
+    }
+}
+
+impl EventSink for EventRouter {
+    fn emit(&self, event: AnalyticsEvent) {
+        let envelope = EventEnvelope::from_analytics(&event);
+        let sampled = self
+            .emitted
+            .fetch_add(1, Ordering::Relaxed)
+            .is_multiple_of(self.validate_every);
+        if sampled {
+            if let Err(violations) = self.registry.validate_envelope(&envelope) {
+                metrics::counter!("events.schema_drift", "family" => envelope.family().as_str())
+                    .increment(1);
+                warn!(
+                    event_id = %envelope.event_id,
+                    event_type = ?envelope.event_type,
+                    violations = ?violations,
+                    "Emitted event envelope failed schema validation"
+                );
+            }
+        }
+        self.publish(&envelope);
+        for sink in &self.event_sinks {
+            sink.emit(event.clone());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::governance::{DataClassification, FieldDefinition};
+    use campaign_core::event_bus::{capture_sink, make_event};
+    use campaign_core::event_envelope::{EventPayload, ENVELOPE_SCHEMA};
+    use campaign_core::types::EventType;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CaptureEnvelopes(Mutex<Vec<EventEnvelope>>);
+
+    impl EnvelopeSink for CaptureEnvelopes {
+        fn publish(&self, envelope: &EventEnvelope) {
+            self.0.lock().unwrap().push(envelope.clone());
+        }
+    }
+
+    #[test]
+    fn test_router_fans_out_valid_envelopes() {
+        let envelopes = Arc::new(CaptureEnvelopes::default());
+        let events = capture_sink();
+        let router = EventRouter::new(Arc::new(SchemaRegistry::new()))
+            .with_envelope_sink(envelopes.clone())
+            .with_event_sink(events.clone());
+
+        router.emit(make_event(EventType::BidResponse, "req-1", None, None));
+        router.emit(make_event(EventType::ActivationSent, "msg-1", None, None).with_channel("sms"));
+
+        let published = envelopes.0.lock().unwrap();
+        assert_eq!(published.len(), 2);
+        assert_eq!(published[1].channel.as_deref(), Some("sms"));
+        assert_eq!(events.count(), 2);
+    }
+
+    #[test]
+    fn test_emit_sampling_never_drops_events() {
+        // An envelope schema no emitted event satisfies. Sampled events fail
+        // validation but are delivered like the rest.
+        let registry = Arc::new(SchemaRegistry::new());
+        registry.register(
+            ENVELOPE_SCHEMA.to_string(),
+            vec![FieldDefinition {
+                name: "tenant_id".to_string(),
+                data_type: "string".to_string(),
+                classification: DataClassification::Internal,
+                required: true,
+                pii_type: None,
+                retention_days: None,
+                description: "Tenant".to_string(),
+            }],
+            "test-team".to_string(),
+        );
+        let envelopes = Arc::new(CaptureEnvelopes::default());
+        let router = EventRouter::new(registry)
+            .with_envelope_sink(envelopes.clone())
+            .with_validation_sampling(3);
+
+        for n in 0..6 {
+            router.emit(make_event(
+                EventType::Impression,
+                format!("req-{n}"),
+                None,
+                None,
+            ));
+        }
+        let published = envelopes.0.lock().unwrap();
+        let ids: Vec<_> = published
+            .iter()
+            .map(|e| match &e.payload {
+                EventPayload::Engagement(p) => p.request_id.as_str(),
+                _ => "",
+            })
+            .collect();
+        assert_eq!(ids, ["req-0", "req-1", "req-2", "req-3", "req-4", "req-5"]);
+    }
+
+    #[test]
+    fn test_router_rejects_invalid_envelope() {
+        let envelopes = Arc::new(CaptureEnvelopes::default());
+        let router =
+            EventRouter::new(Arc::new(SchemaRegistry::new())).with_envelope_sink(envelopes.clone());
+
+        let mut envelope =
+            EventEnvelope::from_analytics(&make_event(EventType::Click, "req-1", None, None));
+        envelope.schema_version = 99;
+        assert!(router.route(&envelope).is_err());
+        assert!(envelopes.0.lock().unwrap().is_empty());
+    }
+}
@@ -5,6 +5,9 @@
 
 use std::collections::{HashMap, HashSet};
 
+use campaign_core::event_envelope::{
+    EventEnvelope, EventFamily, ENVELOPE_SCHEMA, ENVELOPE_VERSION,
+};
 use chrono::{DateTime, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
@@ -73,6 +76,16 @@ pub struct RegisteredSchema {
     pub deprecated: bool,
 }
 
+/// A field of a validated record that does not match its schema.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SchemaViolation {
+    pub schema: String,
+    pub version: u32,
+    /// Dotted path of the offending field; empty when the schema is missing.
+    pub field: String,
+    pub message: String,
+}
+
 /// Schema registry for tracking data structures across the platform.
 pub struct SchemaRegistry {
     schemas: DashMap<String, Vec<RegisteredSchema>>,
@@ -147,6 +160,124 @@ impl SchemaRegistry {
         results
     }
 
+    /// Validate a JSON object against a registered schema version: required
+    /// fields must be present and non-null, present fields must match their
+    /// declared type, and fields the schema does not declare are rejected so
+    /// every stored field carries a classification.
+    pub fn validate(
+        &self,
+        name: &str,
+        version: u32,
+        record: &serde_json::Value,
+    ) -> Result<(), Vec<SchemaViolation>> {
+        let violation = |field: &str, message: String| SchemaViolation {
+            schema: name.to_string(),
+            version,
+            field: field.to_string(),
+            message,
+        };
+        let Some(versions) = self.schemas.get(name) else {
+            return Err(vec![violation(
+                "",
+                format!("schema {name} is not registered"),
+            )]);
+        };
+        let Some(schema) = versions.iter().find(|s| s.version == version) else {
+            return Err(vec![violation(
+                "",
+                format!("schema {name} has no version {version}"),
+            )]);
+        };
+        let Some(object) = record.as_object() else {
+            return Err(vec![violation("", "record is not an object".to_string())]);
+        };
+
+        let mut violations = Vec::new();
+        for field in &schema.fields {
+            match object.get(&field.name) {
+                None | Some(serde_json::Value::Null) => {
+                    if field.required {
+                        violations.push(violation(&field.name, "required field missing".into()));
+                    }
+                }
+                Some(value) => {
+                    if !matches_data_type(&field.data_type, value) {
+                        violations.push(violation(
+                            &field.name,
+                            format!("expected {}, found {value}", field.data_type),
+                        ));
+                    }
+                }
+            }
+        }
+        for key in object.keys() {
+            if !schema.fields.iter().any(|f| &f.name == key) {
+                violations.push(violation(key, "field not declared in schema".into()));
+            }
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
+    /// Register the canonical event envelope and one payload schema per
+    /// event family at the current envelope version. Idempotent.
+    pub fn register_event_envelope(&self) {
+        let register = |name: String, fields: Vec<FieldDefinition>| {
+            if self.get_version(&name, ENVELOPE_VERSION).is_none() {
+                self.register(name, fields, "analytics-team".to_string());
+            }
+        };
+        register(ENVELOPE_SCHEMA.to_string(), envelope_fields());
+        for family in EventFamily::ALL {
+            register(family.schema_name(), payload_fields(family));
+        }
+    }
+
+    /// Validate an envelope and its payload against the registered schemas
+    /// for its version.
+    pub fn validate_envelope(&self, envelope: &EventEnvelope) -> Result<(), Vec<SchemaViolation>> {
+        let mut record = serde_json::to_value(envelope).map_err(|e| {
+            vec![SchemaViolation {
+                schema: ENVELOPE_SCHEMA.to_string(),
+                version: envelope.schema_version,
+                field: String::new(),
+                message: format!("envelope does not serialize: {e}"),
+            }]
+        })?;
+        let payload = record
+            .get_mut("payload")
+            .and_then(|p| p.get_mut("data"))
+            .map(serde_json::Value::take)
+            .unwrap_or_default();
+
+        let mut violations = self
+            .validate(ENVELOPE_SCHEMA, envelope.schema_version, &record)
+            .err()
+            .unwrap_or_default();
+        if let Err(payload_violations) = self.validate(
+            &envelope.family().schema_name(),
+            envelope.schema_version,
+            &payload,
+        ) {
+            violations.extend(payload_violations.into_iter().map(|mut v| {
+                if !v.field.is_empty() {
+                    v.field = format!("payload.data.{}", v.field);
+                }
+                v
+            }));
+        }
+
+        if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(violations)
+        }
+    }
+
     /// Seed demo schemas.
     pub fn seed_demo(&self) {
         self.register(
@@ -239,6 +370,244 @@ impl SchemaRegistry {
     }
 }
 
+/// Whether a JSON value has the registry data type. Types the registry does
+/// not know are accepted.
+fn matches_data_type(data_type: &str, value: &serde_json::Value) -> bool {
+    use serde_json::Value;
+    match data_type {
+        "string" => value.is_string(),
+        "uuid" => value.as_str().is_some_and(|s| Uuid::parse_str(s).is_ok()),
+        "timestamp" => value
+            .as_str()
+            .is_some_and(|s| DateTime::parse_from_rfc3339(s).is_ok()),
+        "u32" | "u64" => value.is_u64(),
+        "f64" => value.is_number(),
+        "bool" => value.is_boolean(),
+        "object" => value.is_object(),
+        t if t.starts_with("array") => matches!(value, Value::Array(_)),
+        _ => true,
+    }
+}
+
+fn field(
+    name: &str,
+    data_type: &str,
+    classification: DataClassification,
+    required: bool,
+    description: &str,
+) -> FieldDefinition {
+    FieldDefinition {
+        name: name.to_string(),
+        data_type: data_type.to_string(),
+        classification,
+        required,
+        pii_type: None,
+        retention_days: None,
+        description: description.to_string(),
+    }
+}
+
+/// Top-level fields of [`EventEnvelope`]. The payload is validated against
+/// its family schema separately.
+fn envelope_fields() -> Vec<FieldDefinition> {
+    use DataClassification::*;
+    vec![
+        field(
+            "schema_version",
+            "u32",
+            Internal,
+            true,
+            "Envelope schema version",
+        ),
+        field(
+            "event_id",
+            "uuid",
+            Internal,
+            true,
+            "Unique event identifier",
+        ),
+        field(
+            "event_type",
+            "string",
+            Internal,
+            true,
+            "Platform event type",
+        ),
+        field("source", "string", Internal, true, "Producer of the event"),
+        field(
+            "occurred_at",
+            "timestamp",
+            Internal,
+            true,
+            "When the event happened",
+        ),
+        field("agent_id", "string", Internal, true, "Emitting agent"),
+        field("node_id", "string", Internal, true, "Emitting node"),
+        FieldDefinition {
+            retention_days: Some(730),
+            ..field(
+                "user_id",
+                "string",
+                QuasiIdentifier,
+                false,
+                "Associated user ID",
+            )
+        },
+        field(
+            "campaign_id",
+            "string",
+            Internal,
+            false,
+            "Campaign identifier",
+        ),
+        field(
+            "channel",
+            "string",
+            Internal,
+            false,
+            "Delivery or engagement channel",
+        ),
+        field(
+            "creative_id",
+            "string",
+            Internal,
+            false,
+            "Creative identifier",
+        ),
+        field(
+            "payload",
+            "object",
+            Internal,
+            true,
+            "Family-tagged typed payload",
+        ),
+    ]
+}
+
+/// Fields of the typed payload for one event family.
+fn payload_fields(family: EventFamily) -> Vec<FieldDefinition> {
+    use DataClassification::*;
+    match family {
+        EventFamily::Bidding => vec![
+            field(
+                "request_id",
+                "string",
+                Internal,
+                true,
+                "Bid request identifier",
+            ),
+            field(
+                "impression_id",
+                "string",
+                Internal,
+                false,
+                "Impression identifier",
+            ),
+            field("offer_id", "string", Internal, false, "Offer bid on"),
+            field("bid_price", "f64", Confidential, false, "Bid price (CPM)"),
+            field(
+                "win_price",
+                "f64",
+                Confidential,
+                false,
+                "Clearing price (CPM)",
+            ),
+            field(
+                "inference_latency_us",
+                "u64",
+                Internal,
+                false,
+                "Model inference time",
+            ),
+            field(
+                "total_latency_us",
+                "u64",
+                Internal,
+                false,
+                "End-to-end bid time",
+            ),
+        ],
+        EventFamily::Engagement => vec![
+            field(
+                "request_id",
+                "string",
+                Internal,
+                true,
+                "Originating bid request",
+            ),
+            field(
+                "impression_id",
+                "string",
+                Internal,
+                false,
+                "Impression identifier",
+            ),
+            field("offer_id", "string", Internal, false, "Offer engaged with"),
+            field(
+                "win_price",
+                "f64",
+                Confidential,
+                false,
+                "Clearing price (CPM)",
+            ),
+        ],
+        EventFamily::Delivery => vec![
+            field(
+                "activation_id",
+                "string",
+                Internal,
+                true,
+                "Activation or message ID",
+            ),
+            field("offer_id", "string", Internal, false, "Offer delivered"),
+            field("latency_us", "u64", Internal, false, "Delivery latency"),
+        ],
+        EventFamily::Journey => vec![
+            field("instance_id", "string", Internal, true, "Journey instance"),
+            field("step_id", "string", Internal, false, "Journey step"),
+        ],
+        EventFamily::Experiment => vec![
+            field(
+                "experiment_id",
+                "string",
+                Internal,
+                true,
+                "Experiment identifier",
+            ),
+            field("variant_id", "string", Internal, false, "Assigned variant"),
+        ],
+        EventFamily::Loyalty => vec![
+            field(
+                "reference_id",
+                "string",
+                Internal,
+                true,
+                "Loyalty transaction or member",
+            ),
+            field("offer_id", "string", Internal, false, "Loyalty offer"),
+        ],
+        EventFamily::Creative => vec![
+            field("template_id", "string", Internal, true, "Creative template"),
+            field("variant_id", "string", Internal, false, "Assembled variant"),
+        ],
+        EventFamily::Cdp => vec![field(
+            "sync_event_id",
+            "string",
+            Internal,
+            true,
+            "CDP sync event",
+        )],
+        EventFamily::Sdk => vec![field("sdk_event_id", "string", Internal, true, "SDK event")],
+        EventFamily::Ingest => vec![field(
+            "ingest_event_id",
+            "string",
+            Internal,
+            true,
+            "Ingested event",
+        )],
+    }
+}
+
 // ─── PII Classifier ─────────────────────────────────────────────────────
 
 /// Automatic PII detection on arbitrary JSON data.
@@ -645,6 +1014,62 @@ mod tests {
         assert_eq!(pii[0].1.name, "email");
     }
 
+    #[test]
+    fn test_envelope_validation() {
+        use campaign_core::event_bus::make_event;
+        use campaign_core::types::EventType;
+
+        let registry = SchemaRegistry::new();
+        registry.register_event_envelope();
+        registry.register_event_envelope();
+        assert_eq!(
+            registry.get_latest(ENVELOPE_SCHEMA).unwrap().version,
+            ENVELOPE_VERSION
+        );
+
+        for event_type in [
+            EventType::BidResponse,
+            EventType::Click,
+            EventType::ActivationDelivered,
+            EventType::JourneyCompleted,
+            EventType::ExperimentExposure,
+            EventType::LoyaltyEarn,
+            EventType::DcoAssembly,
+            EventType::CdpWebhook,
+            EventType::WebPageView,
+            EventType::ChannelIngest,
+        ] {
+            let envelope = EventEnvelope::from_analytics(&make_event(
+                event_type,
+                "ref-1",
+                Some("user-1".into()),
+                Some("offer-1".into()),
+            ));
+            assert_eq!(
+                registry.validate_envelope(&envelope),
+                Ok(()),
+                "{event_type:?}"
+            );
+        }
+
+        let mut unknown_version =
+            EventEnvelope::from_analytics(&make_event(EventType::Click, "req-1", None, None));
+        unknown_version.schema_version = ENVELOPE_VERSION + 1;
+        let violations = registry.validate_envelope(&unknown_version).unwrap_err();
+        assert!(violations.iter().all(|v| v.field.is_empty()));
+
+        let record = serde_json::json!({"request_id": 7, "surprise": true});
+        let violations = registry
+            .validate(
+                &EventFamily::Engagement.schema_name(),
+                ENVELOPE_VERSION,
+                &record,
+            )
+            .unwrap_err();
+        let fields: Vec<_> = violations.iter().map(|v| v.field.as_str()).collect();
+        assert_eq!(fields, vec!["request_id", "surprise"]);
+    }
+
     #[test]
     fn test_pii_classifier() {
         let classifier = PiiClassifier::new();
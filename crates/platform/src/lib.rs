@@ -3,6 +3,7 @@
 
 pub mod audit;
 pub mod auth;
+pub mod event_router;
 pub mod governance;
 pub mod privacy;
 pub mod rate_limit;
@@ -11,6 +12,7 @@ pub mod tenancy;
 
 pub use audit::AuditLogger;
 pub use auth::AuthManager;
+pub use event_router::EventRouter;
 pub use governance::{LineageTracker, PiiClassifier, SchemaRegistry};
 pub use privacy::PrivacyManager;
 pub use rate_limit::RateLimiter;
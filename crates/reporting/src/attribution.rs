@@ -1,7 +1,17 @@
 //! Revenue attribution — tracks conversions and revenue back to campaigns.
+//!
+//! Heuristic models (last/first touch, linear, half-life time decay and
+//! 40/20/40 position-based) split each conversion across its own
+//! touchpoints. The data-driven models learn a weight per campaign or
+//! channel from every recorded path, converting or not: the Markov-chain
+//! removal effect and the Shapley value over the sets of touches on a path.
+//! Each conversion is then split across its touches in proportion to those
+//! weights.
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{BTreeSet, HashMap};
+use std::hash::Hash;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -12,6 +22,8 @@ pub enum AttributionModel {
     Linear,
     TimeDecay,
     PositionBased,
+    MarkovChain,
+    Shapley,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -44,19 +56,46 @@ pub struct AttributionResult {
     pub computed_at: DateTime<Utc>,
 }
 
+/// Credit and return on spend of one channel under a model.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ChannelAttribution {
+    pub channel: String,
+    pub model: AttributionModel,
+    /// Fractional conversions credited to the channel.
+    pub attributed_conversions: f64,
+    pub attributed_revenue: f64,
+    pub spend: f64,
+    /// `(revenue - spend) / spend * 100`, when there was spend.
+    pub roi_percent: Option<f64>,
+}
+
+/// A path of keyed touches and whether it converted.
+type KeyedPath<K> = (Vec<K>, bool);
+
 pub struct RevenueAttributionEngine {
     conversions: dashmap::DashMap<Uuid, Vec<ConversionEvent>>,
+    /// Journeys that ended without converting, for the data-driven models.
+    non_conversions: dashmap::DashMap<Uuid, Vec<Vec<Touchpoint>>>,
     attribution_window_days: u32,
+    half_life_hours: f64,
 }
 
 impl RevenueAttributionEngine {
     pub fn new(attribution_window_days: u32) -> Self {
         Self {
             conversions: dashmap::DashMap::new(),
+            non_conversions: dashmap::DashMap::new(),
             attribution_window_days,
+            half_life_hours: 168.0,
         }
     }
 
+    /// Half-life of a touch's credit under [`AttributionModel::TimeDecay`].
+    pub fn with_half_life_hours(mut self, hours: f64) -> Self {
+        self.half_life_hours = hours;
+        self
+    }
+
     pub fn record_conversion(&self, event: ConversionEvent) {
         self.conversions
             .entry(event.user_id)
@@ -64,55 +103,162 @@ impl RevenueAttributionEngine {
             .push(event);
     }
 
-    pub fn attribute(&self, campaign_id: &Uuid, model: &AttributionModel) -> AttributionResult {
-        let mut total_revenue = 0.0;
-        let mut total_conversions = 0u64;
-        let mut total_touchpoints = 0u64;
-        let cutoff = Utc::now() - chrono::Duration::days(self.attribution_window_days as i64);
+    /// Record a journey that did not convert.
+    pub fn record_non_conversion(&self, user_id: Uuid, touchpoints: Vec<Touchpoint>) {
+        if !touchpoints.is_empty() {
+            self.non_conversions
+                .entry(user_id)
+                .or_default()
+                .push(touchpoints);
+        }
+    }
 
-        for entry in self.conversions.iter() {
-            for conversion in entry.value() {
-                if conversion.timestamp < cutoff {
-                    continue;
-                }
-                let relevant: Vec<_> = conversion
-                    .touchpoints
-                    .iter()
-                    .filter(|t| &t.campaign_id == campaign_id)
-                    .collect();
-
-                if relevant.is_empty() {
-                    continue;
+    fn cutoff(&self) -> DateTime<Utc> {
+        Utc::now() - chrono::Duration::days(self.attribution_window_days as i64)
+    }
+
+    /// Conversions inside the attribution window, touchpoints in time order.
+    fn windowed_conversions(&self) -> Vec<ConversionEvent> {
+        let cutoff = self.cutoff();
+        self.conversions
+            .iter()
+            .flat_map(|e| e.value().clone())
+            .filter(|c| c.timestamp >= cutoff && !c.touchpoints.is_empty())
+            .map(|mut c| {
+                c.touchpoints.sort_by_key(|t| t.timestamp);
+                c
+            })
+            .collect()
+    }
+
+    /// Every windowed path keyed by `key`, for the data-driven models.
+    fn keyed_paths<K>(
+        &self,
+        conversions: &[ConversionEvent],
+        key: impl Fn(&Touchpoint) -> K,
+    ) -> Vec<KeyedPath<K>> {
+        let cutoff = self.cutoff();
+        let mut paths: Vec<KeyedPath<K>> = conversions
+            .iter()
+            .map(|c| (c.touchpoints.iter().map(&key).collect(), true))
+            .collect();
+        for entry in self.non_conversions.iter() {
+            for journey in entry.value() {
+                let mut journey: Vec<&Touchpoint> = journey.iter().collect();
+                journey.sort_by_key(|t| t.timestamp);
+                if journey.last().is_some_and(|t| t.timestamp >= cutoff) {
+                    paths.push((journey.into_iter().map(&key).collect(), false));
                 }
+            }
+        }
+        paths
+    }
+
+    /// Share of each key in every windowed conversion under `model`.
+    fn credit<K>(
+        &self,
+        model: &AttributionModel,
+        key: impl Fn(&Touchpoint) -> K,
+    ) -> Vec<(ConversionEvent, HashMap<K, f64>)>
+    where
+        K: Clone + Eq + Hash + Ord,
+    {
+        let conversions = self.windowed_conversions();
+        let weights = match model {
+            AttributionModel::MarkovChain => Some(markov_removal_effects(
+                &self.keyed_paths(&conversions, &key),
+            )),
+            AttributionModel::Shapley => {
+                Some(shapley_values(&self.keyed_paths(&conversions, &key)))
+            }
+            _ => None,
+        };
 
-                let credit = match model {
+        conversions
+            .into_iter()
+            .map(|conversion| {
+                let touches = &conversion.touchpoints;
+                let n = touches.len();
+                let position_weights: Vec<f64> = match model {
                     AttributionModel::LastTouch => {
-                        if conversion.touchpoints.last().map(|t| &t.campaign_id)
-                            == Some(campaign_id)
-                        {
-                            1.0
-                        } else {
-                            0.0
-                        }
+                        (0..n).map(|i| if i + 1 == n { 1.0 } else { 0.0 }).collect()
                     }
                     AttributionModel::FirstTouch => {
-                        if conversion.touchpoints.first().map(|t| &t.campaign_id)
-                            == Some(campaign_id)
-                        {
-                            1.0
+                        (0..n).map(|i| if i == 0 { 1.0 } else { 0.0 }).collect()
+                    }
+                    AttributionModel::Linear => vec![1.0; n],
+                    AttributionModel::TimeDecay => touches
+                        .iter()
+                        .map(|t| {
+                            let age = (conversion.timestamp - t.timestamp).num_seconds().max(0)
+                                as f64
+                                / 3600.0;
+                            0.5f64.powf(age / self.half_life_hours.max(f64::MIN_POSITIVE))
+                        })
+                        .collect(),
+                    AttributionModel::PositionBased => position_based(n),
+                    AttributionModel::MarkovChain | AttributionModel::Shapley => {
+                        let weights = weights.as_ref().expect("data-driven weights");
+                        // Each distinct key's weight is spread over its touches.
+                        let mut occurrences: HashMap<K, f64> = HashMap::new();
+                        for t in touches {
+                            *occurrences.entry(key(t)).or_default() += 1.0;
+                        }
+                        let raw: Vec<f64> = touches
+                            .iter()
+                            .map(|t| {
+                                let k = key(t);
+                                weights.get(&k).copied().unwrap_or(0.0).max(0.0) / occurrences[&k]
+                            })
+                            .collect();
+                        if raw.iter().sum::<f64>() > 0.0 {
+                            raw
                         } else {
-                            0.0
+                            vec![1.0; n]
                         }
                     }
-                    _ => relevant.len() as f64 / conversion.touchpoints.len() as f64,
                 };
 
-                total_revenue += conversion.revenue * credit;
-                if credit > 0.0 {
-                    total_conversions += 1;
+                let total: f64 = position_weights.iter().sum();
+                let mut shares: HashMap<K, f64> = HashMap::new();
+                if total > 0.0 {
+                    for (t, w) in touches.iter().zip(position_weights) {
+                        *shares.entry(key(t)).or_default() += w / total;
+                    }
                 }
-                total_touchpoints += relevant.len() as u64;
+                (conversion, shares)
+            })
+            .collect()
+    }
+
+    pub fn attribute(&self, campaign_id: &Uuid, model: &AttributionModel) -> AttributionResult {
+        let mut total_revenue = 0.0;
+        let mut total_conversions = 0u64;
+        let mut total_touchpoints = 0u64;
+        let mut hours_to_conversion = 0.0;
+
+        for (conversion, shares) in self.credit(model, |t| t.campaign_id) {
+            let relevant: Vec<_> = conversion
+                .touchpoints
+                .iter()
+                .filter(|t| &t.campaign_id == campaign_id)
+                .collect();
+
+            if relevant.is_empty() {
+                continue;
             }
+
+            let credit = shares.get(campaign_id).copied().unwrap_or(0.0);
+            total_revenue += conversion.revenue * credit;
+            if credit > 0.0 {
+                total_conversions += 1;
+                // From the campaign's first touch on this path.
+                hours_to_conversion += (conversion.timestamp - relevant[0].timestamp)
+                    .num_seconds()
+                    .max(0) as f64
+                    / 3600.0;
+            }
+            total_touchpoints += relevant.len() as u64;
         }
 
         AttributionResult {
@@ -121,8 +267,322 @@ impl RevenueAttributionEngine {
             attributed_conversions: total_conversions,
             attributed_revenue: total_revenue,
             total_touchpoints,
-            avg_time_to_conversion_hours: 24.0,
+            avg_time_to_conversion_hours: if total_conversions > 0 {
+                hours_to_conversion / total_conversions as f64
+            } else {
+                0.0
+            },
             computed_at: Utc::now(),
         }
     }
+
+    /// Credit per channel under `model`, with ROI against `spend_by_channel`
+    /// (e.g. from `BudgetTracker::get_channel_spend_breakdown`). Sorted by
+    /// attributed revenue, highest first.
+    pub fn attribute_channels(
+        &self,
+        model: &AttributionModel,
+        spend_by_channel: &HashMap<String, f64>,
+    ) -> Vec<ChannelAttribution> {
+        let mut totals: HashMap<String, (f64, f64)> = spend_by_channel
+            .keys()
+            .map(|c| (c.clone(), (0.0, 0.0)))
+            .collect();
+        for (conversion, shares) in self.credit(model, |t| t.channel.clone()) {
+            for (channel, share) in shares {
+                let total = totals.entry(channel).or_default();
+                total.0 += share;
+                total.1 += share * conversion.revenue;
+            }
+        }
+
+        let mut result: Vec<ChannelAttribution> = totals
+            .into_iter()
+            .map(|(channel, (conversions, revenue))| {
+                let spend = spend_by_channel.get(&channel).copied().unwrap_or(0.0);
+                ChannelAttribution {
+                    roi_percent: (spend > 0.0).then(|| (revenue - spend) / spend * 100.0),
+                    channel,
+                    model: model.clone(),
+                    attributed_conversions: conversions,
+                    attributed_revenue: revenue,
+                    spend,
+                }
+            })
+            .collect();
+        result.sort_by(|a, b| {
+            b.attributed_revenue
+                .partial_cmp(&a.attributed_revenue)
+                .unwrap_or(std::cmp::Ordering::Equal)
+                .then_with(|| a.channel.cmp(&b.channel))
+        });
+        result
+    }
+}
+
+/// U-shaped 40/20/40 weights: first and last touches take 40% each and the
+/// middle touches share 20%; two touches split evenly.
+fn position_based(n: usize) -> Vec<f64> {
+    match n {
+        0 => Vec::new(),
+        1 => vec![1.0],
+        2 => vec![0.5, 0.5],
+        _ => (0..n)
+            .map(|i| {
+                if i == 0 || i + 1 == n {
+                    0.4
+                } else {
+                    0.2 / (n - 2) as f64
+                }
+            })
+            .collect(),
+    }
+}
+
+/// Removal effect of each key in a first-order Markov chain over the
+/// paths: the fraction of conversions lost when the key's state leads
+/// nowhere. Repeated consecutive touches collapse into one state.
+fn markov_removal_effects<K: Clone + Eq + Hash>(paths: &[KeyedPath<K>]) -> HashMap<K, f64> {
+    const START: usize = 0;
+    const CONVERSION: usize = 1;
+    const NULL: usize = 2;
+
+    let mut index: HashMap<K, usize> = HashMap::new();
+    let mut counts: HashMap<(usize, usize), f64> = HashMap::new();
+    for (path, converted) in paths {
+        let mut prev = START;
+        for k in path {
+            let next = index.len() + 3;
+            let state = *index.entry(k.clone()).or_insert(next);
+            if state != prev {
+                *counts.entry((prev, state)).or_default() += 1.0;
+                prev = state;
+            }
+        }
+        let end = if *converted { CONVERSION } else { NULL };
+        *counts.entry((prev, end)).or_default() += 1.0;
+    }
+
+    let states = index.len() + 3;
+    let mut transitions: Vec<Vec<(usize, f64)>> = vec![Vec::new(); states];
+    let mut out = vec![0.0; states];
+    for (&(from, _), n) in &counts {
+        out[from] += n;
+    }
+    for (&(from, to), n) in &counts {
+        transitions[from].push((to, n / out[from]));
+    }
+
+    // Probability of eventually converting from the start state.
+    let solve = |removed: Option<usize>| -> f64 {
+        let mut p = vec![0.0; states];
+        p[CONVERSION] = 1.0;
+        for _ in 0..1_000 {
+            let mut delta = 0.0f64;
+            for s in (0..states).filter(|&s| s != CONVERSION && s != NULL) {
+                let value = if Some(s) == removed {
+                    0.0
+                } else {
+                    transitions[s].iter().map(|(t, prob)| prob * p[*t]).sum()
+                };
+                delta = delta.max((value - p[s]).abs());
+                p[s] = value;
+            }
+            if delta < 1e-12 {
+                break;
+            }
+        }
+        p[START]
+    };
+
+    let base = solve(None);
+    if base <= 0.0 {
+        return HashMap::new();
+    }
+    index
+        .into_iter()
+        .map(|(k, state)| (k, 1.0 - solve(Some(state)) / base))
+        .collect()
+}
+
+/// Shapley value of each key where a coalition's worth is the conversion
+/// rate of paths touching exactly that set of keys (unseen sets are
+/// worth nothing).
+fn shapley_values<K: Clone + Eq + Hash + Ord>(paths: &[KeyedPath<K>]) -> HashMap<K, f64> {
+    let mut sets: HashMap<BTreeSet<K>, (f64, f64)> = HashMap::new();
+    for (path, converted) in paths {
+        let set: BTreeSet<K> = path.iter().cloned().collect();
+        let entry = sets.entry(set).or_default();
+        entry.1 += 1.0;
+        if *converted {
+            entry.0 += 1.0;
+        }
+    }
+    let keys: BTreeSet<K> = sets.keys().flatten().cloned().collect();
+    let n = keys.len();
+
+    // Weight of a coalition of size s not containing the player:
+    // s! (n - s - 1)! / n! = 1 / (n * C(n - 1, s)).
+    let weight = |s: usize| {
+        let mut binomial = 1.0f64;
+        for i in 0..s {
+            binomial = binomial * (n - 1 - i) as f64 / (i + 1) as f64;
+        }
+        1.0 / (n as f64 * binomial)
+    };
+
+    let mut values: HashMap<K, f64> = keys.iter().map(|k| (k.clone(), 0.0)).collect();
+    for (set, (conversions, total)) in &sets {
+        let worth = conversions / total;
+        if worth == 0.0 {
+            continue;
+        }
+        let with = weight(set.len() - 1);
+        let without = weight(set.len());
+        for (k, value) in values.iter_mut() {
+            if set.contains(k) {
+                *value += with * worth;
+            } else if set.len() < n {
+                *value -= without * worth;
+            }
+        }
+    }
+    values
+}
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    fn touch(campaign_id: Uuid, channel: &str, hours_before: i64) -> Touchpoint {
+        Touchpoint {
+            campaign_id,
+            channel: channel.to_string(),
+            interaction_type: "click".to_string(),
+            timestamp: Utc::now() - Duration::hours(hours_before),
+        }
+    }
+
+    fn conversion(revenue: f64, touchpoints: Vec<Touchpoint>) -> ConversionEvent {
+        ConversionEvent {
+            id: Uuid::new_v4(),
+            user_id: Uuid::new_v4(),
+            event_name: "purchase".to_string(),
+            revenue,
+            currency: "USD".to_string(),
+            touchpoints,
+            timestamp: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_heuristic_models_split_credit() {
+        let engine = RevenueAttributionEngine::new(30).with_half_life_hours(24.0);
+        let (a, b, c) = (Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4());
+        // Recorded out of order: a (72h), b (48h), b (24h), c (0h).
+        engine.record_conversion(conversion(
+            100.0,
+            vec![
+                touch(c, "email", 0),
+                touch(a, "display", 72),
+                touch(b, "social", 48),
+                touch(b, "social", 24),
+            ],
+        ));
+
+        let revenue = |id: &Uuid, model| engine.attribute(id, &model).attributed_revenue;
+        assert!((revenue(&a, AttributionModel::FirstTouch) - 100.0).abs() < 1e-9);
+        assert!((revenue(&c, AttributionModel::LastTouch) - 100.0).abs() < 1e-9);
+        assert!((revenue(&b, AttributionModel::Linear) - 50.0).abs() < 1e-9);
+        assert!((revenue(&a, AttributionModel::PositionBased) - 40.0).abs() < 1e-9);
+        assert!((revenue(&b, AttributionModel::PositionBased) - 20.0).abs() < 1e-9);
+        assert!((revenue(&c, AttributionModel::PositionBased) - 40.0).abs() < 1e-9);
+
+        // Weights 1/8, 1/4, 1/2, 1 for touches 72h, 48h, 24h and 0h old.
+        let total = 0.125 + 0.25 + 0.5 + 1.0;
+        assert!((revenue(&a, AttributionModel::TimeDecay) - 100.0 * 0.125 / total).abs() < 1e-6);
+        assert!((revenue(&c, AttributionModel::TimeDecay) - 100.0 / total).abs() < 1e-6);
+
+        let result = engine.attribute(&a, &AttributionModel::Linear);
+        assert_eq!(result.attributed_conversions, 1);
+        assert!((result.avg_time_to_conversion_hours - 72.0).abs() < 0.01);
+        assert_eq!(
+            engine
+                .attribute(&Uuid::new_v4(), &AttributionModel::Linear)
+                .avg_time_to_conversion_hours,
+            0.0
+        );
+    }
+
+    #[test]
+    fn test_markov_removal_effect() {
+        let engine = RevenueAttributionEngine::new(30);
+        let campaign = Uuid::new_v4();
+        // Search closes every conversion; display only precedes it.
+        for _ in 0..3 {
+            engine.record_conversion(conversion(
+                10.0,
+                vec![touch(campaign, "display", 5), touch(campaign, "search", 1)],
+            ));
+            engine.record_conversion(conversion(10.0, vec![touch(campaign, "search", 1)]));
+            engine.record_non_conversion(Uuid::new_v4(), vec![touch(campaign, "display", 3)]);
+        }
+
+        let paths = engine.keyed_paths(&engine.windowed_conversions(), |t| t.channel.clone());
+        let effects = markov_removal_effects(&paths);
+        assert!((effects["search"] - 1.0).abs() < 1e-9);
+        // Without display, start -> display paths (half of all) are lost.
+        assert!((effects["display"] - 0.5).abs() < 1e-9);
+
+        let channels = engine.attribute_channels(
+            &AttributionModel::MarkovChain,
+            &HashMap::from([("display".to_string(), 10.0), ("search".to_string(), 20.0)]),
+        );
+        assert_eq!(channels[0].channel, "search");
+        let search = &channels[0];
+        let display = &channels[1];
+        // Display earns a third of each of the three assisted conversions.
+        assert!((display.attributed_conversions - 1.0).abs() < 1e-9);
+        assert!((search.attributed_conversions - 5.0).abs() < 1e-9);
+        assert!((search.roi_percent.unwrap() - 150.0).abs() < 1e-9);
+        assert!((display.roi_percent.unwrap() - 0.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_shapley_values() {
+        // Two players: email alone converts 20%, push alone 40%, both 90%.
+        let mut paths: Vec<KeyedPath<&str>> = Vec::new();
+        for (set, conversions, total) in [
+            (vec!["email"], 2, 10),
+            (vec!["push"], 4, 10),
+            (vec!["email", "push"], 9, 10),
+        ] {
+            for i in 0..total {
+                paths.push((set.clone(), i < conversions));
+            }
+        }
+        let values = shapley_values(&paths);
+        // phi_email = 1/2 (0.2 - 0) + 1/2 (0.9 - 0.4) = 0.35
+        assert!((values["email"] - 0.35).abs() < 1e-9);
+        assert!((values["push"] - 0.55).abs() < 1e-9);
+
+        let engine = RevenueAttributionEngine::new(30);
+        let (email, push) = (Uuid::new_v4(), Uuid::new_v4());
+        engine.record_conversion(conversion(
+            100.0,
+            vec![touch(email, "email", 2), touch(push, "push", 1)],
+        ));
+        let email_credit = engine
+            .attribute(&email, &AttributionModel::Shapley)
+            .attributed_revenue;
+        let push_credit = engine
+            .attribute(&push, &AttributionModel::Shapley)
+            .attributed_revenue;
+        assert!((email_credit + push_credit - 100.0).abs() < 1e-9);
+    }
 }
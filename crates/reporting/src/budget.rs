@@ -1,8 +1,21 @@
 //! Budget tracking, pacing, and ROI/ROAS calculation for campaigns.
-
-use chrono::{DateTime, Duration, Utc};
+//!
+//! Amounts are in the campaign's currency. DSP-reported spend in other
+//! currencies is converted with the tracker's exchange rates. With an
+//! analytics store attached, [`BudgetTracker::load_spend`] takes spend totals
+//! from the shared rollups, so every node paces against the same numbers.
+
+use crate::pacing::{
+    forecast, start_of_day, ForecastInput, IntradayCurve, PacingConfig, PacingDecision, PidState,
+    SpendForecast,
+};
+use campaign_analytics::{AnalyticsStore, RollupDimension, RollupGrain, RollupQuery};
+use campaign_core::dsp::{DspPlatform, DspSpendRecord};
+use chrono::{DateTime, Duration, DurationRound, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 // ---------------------------------------------------------------------------
@@ -82,12 +95,15 @@ pub enum BudgetAlertType {
     PacingAhead,
     /// Budget is fully exhausted (>= 100%).
     BudgetExhausted,
+    /// Internal spend disagrees with DSP-reported spend.
+    SpendDiscrepancy,
 }
 
 /// Return‐on‐ad‐spend report for a campaign.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct RoasReport {
     pub campaign_id: Uuid,
+    pub currency: String,
     pub total_spend: f64,
     pub total_revenue: f64,
     /// `revenue / spend` (0.0 when spend is zero).
@@ -103,6 +119,49 @@ pub struct RoasReport {
     pub computed_at: DateTime<Utc>,
 }
 
+/// Tolerances for DSP spend reconciliation. An hour is flagged when the
+/// absolute difference exceeds both the absolute tolerance and the relative
+/// tolerance of the larger figure.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationConfig {
+    pub relative_tolerance: f64,
+    pub absolute_tolerance: f64,
+}
+
+impl Default for ReconciliationConfig {
+    fn default() -> Self {
+        Self {
+            relative_tolerance: 0.05,
+            absolute_tolerance: 1.0,
+        }
+    }
+}
+
+/// Internal and DSP spend for one platform-hour, in the campaign currency.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReconciliationLine {
+    pub platform: DspPlatform,
+    pub hour: DateTime<Utc>,
+    pub internal_spend: f64,
+    pub dsp_spend: f64,
+    /// `internal_spend - dsp_spend`.
+    pub difference: f64,
+    pub discrepancy: bool,
+}
+
+/// Result of comparing internal spend with DSP-reported spend.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendReconciliation {
+    pub campaign_id: Uuid,
+    pub currency: String,
+    pub internal_total: f64,
+    pub dsp_total: f64,
+    pub difference: f64,
+    pub lines: Vec<ReconciliationLine>,
+    pub discrepancies: usize,
+    pub reconciled_at: DateTime<Utc>,
+}
+
 // ---------------------------------------------------------------------------
 // BudgetTracker
 // ---------------------------------------------------------------------------
@@ -115,6 +174,16 @@ pub struct BudgetTracker {
     spend_records: DashMap<Uuid, Vec<SpendRecord>>,
     /// campaign_id -> alerts
     alerts: DashMap<Uuid, Vec<BudgetAlert>>,
+    /// Currency for budgets created without an explicit one.
+    default_currency: String,
+    /// currency code -> value of one unit in USD
+    exchange_rates: DashMap<String, f64>,
+    /// campaign_id -> intraday traffic curve (flat when absent)
+    traffic_curves: DashMap<Uuid, IntradayCurve>,
+    pacing_config: PacingConfig,
+    /// campaign_id -> pacing controller state
+    pacing_state: DashMap<Uuid, PidState>,
+    store: Option<Arc<dyn AnalyticsStore>>,
 }
 
 impl BudgetTracker {
@@ -124,10 +193,34 @@ impl BudgetTracker {
             allocations: DashMap::new(),
             spend_records: DashMap::new(),
             alerts: DashMap::new(),
+            default_currency: "USD".to_string(),
+            exchange_rates: DashMap::from_iter([("USD".to_string(), 1.0)]),
+            traffic_curves: DashMap::new(),
+            pacing_config: PacingConfig::default(),
+            pacing_state: DashMap::new(),
+            store: None,
         }
     }
 
-    /// Create or update a budget allocation for a campaign.
+    /// Read spend totals from the analytics rollups in [`Self::load_spend`].
+    pub fn with_store(mut self, store: Arc<dyn AnalyticsStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Currency assigned to budgets created by [`Self::set_budget`].
+    pub fn with_default_currency(mut self, currency: impl Into<String>) -> Self {
+        self.default_currency = currency.into().to_uppercase();
+        self
+    }
+
+    pub fn with_pacing_config(mut self, config: PacingConfig) -> Self {
+        self.pacing_config = config;
+        self
+    }
+
+    /// Create or update a budget allocation for a campaign in the tracker's
+    /// default currency.
     pub fn set_budget(
         &self,
         campaign_id: Uuid,
@@ -135,6 +228,27 @@ impl BudgetTracker {
         daily: f64,
         start: DateTime<Utc>,
         end: DateTime<Utc>,
+    ) {
+        self.set_budget_in_currency(
+            campaign_id,
+            total,
+            daily,
+            start,
+            end,
+            &self.default_currency,
+        );
+    }
+
+    /// Create or update a budget allocation denominated in `currency`
+    /// (ISO 4217 code). Spend for the campaign is recorded in this currency.
+    pub fn set_budget_in_currency(
+        &self,
+        campaign_id: Uuid,
+        total: f64,
+        daily: f64,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+        currency: &str,
     ) {
         let now = Utc::now();
         let pacing_status = if now < start {
@@ -151,7 +265,7 @@ impl BudgetTracker {
             spent_today: 0.0,
             remaining: total,
             pacing_status,
-            currency: "USD".to_string(),
+            currency: currency.to_uppercase(),
             start_date: start,
             end_date: end,
             updated_at: now,
@@ -160,10 +274,47 @@ impl BudgetTracker {
         self.allocations.insert(campaign_id, allocation);
     }
 
+    /// Set the value of one unit of `currency` in USD.
+    pub fn set_exchange_rate(&self, currency: &str, usd_per_unit: f64) {
+        self.exchange_rates
+            .insert(currency.to_uppercase(), usd_per_unit);
+    }
+
+    /// Convert between currencies via their USD rates.
+    pub fn convert(&self, amount: f64, from: &str, to: &str) -> anyhow::Result<f64> {
+        if from.eq_ignore_ascii_case(to) {
+            return Ok(amount);
+        }
+        let rate = |currency: &str| {
+            self.exchange_rates
+                .get(&currency.to_uppercase())
+                .map(|r| *r)
+                .filter(|r| *r > 0.0)
+                .ok_or_else(|| anyhow::anyhow!("no exchange rate for {currency}"))
+        };
+        Ok(amount * rate(from)? / rate(to)?)
+    }
+
+    /// Use a traffic curve (e.g. learned from request volume) for pacing.
+    pub fn set_traffic_curve(&self, campaign_id: Uuid, curve: IntradayCurve) {
+        self.traffic_curves.insert(campaign_id, curve);
+    }
+
     /// Record a spend event and check alert conditions.
     pub fn record_spend(&self, campaign_id: Uuid, amount: f64, channel: &str, event_type: &str) {
-        let now = Utc::now();
+        self.record_spend_at(campaign_id, amount, channel, event_type, Utc::now());
+    }
 
+    /// Record a spend event that happened at `now`, e.g. when replaying
+    /// delayed win notices. `spent_today` restarts on a new UTC day.
+    pub fn record_spend_at(
+        &self,
+        campaign_id: Uuid,
+        amount: f64,
+        channel: &str,
+        event_type: &str,
+        now: DateTime<Utc>,
+    ) {
         let record = SpendRecord {
             id: Uuid::new_v4(),
             campaign_id,
@@ -181,10 +332,15 @@ impl BudgetTracker {
 
         // Update the allocation totals.
         if let Some(mut alloc) = self.allocations.get_mut(&campaign_id) {
+            if now.date_naive() > alloc.updated_at.date_naive() {
+                alloc.spent_today = 0.0;
+            }
             alloc.spent_total += amount;
-            alloc.spent_today += amount;
+            if now.date_naive() >= alloc.updated_at.date_naive() {
+                alloc.spent_today += amount;
+                alloc.updated_at = now;
+            }
             alloc.remaining = alloc.total_budget - alloc.spent_total;
-            alloc.updated_at = now;
 
             // --- alert checks ---------------------------------------------------
             let spend_pct = if alloc.total_budget > 0.0 {
@@ -241,11 +397,68 @@ impl BudgetTracker {
         }
     }
 
+    /// Replace a campaign's spend totals with the win prices in the campaign
+    /// rollups since the budget started, so they survive restarts and agree
+    /// across nodes. Returns the refreshed allocation, or `None` when the
+    /// campaign has no budget.
+    pub async fn load_spend(
+        &self,
+        campaign_id: &Uuid,
+        now: DateTime<Utc>,
+    ) -> anyhow::Result<Option<BudgetAllocation>> {
+        let store = self
+            .store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("budget tracker has no analytics store"))?;
+        let Some(start) = self.allocations.get(campaign_id).map(|a| a.start_date) else {
+            return Ok(None);
+        };
+        let today = RollupGrain::Daily.truncate(now);
+        let query = RollupQuery::new(
+            RollupDimension::Campaign,
+            RollupGrain::Daily,
+            RollupGrain::Daily.truncate(start),
+            today + Duration::days(1),
+        )
+        .with_keys([campaign_id.to_string()]);
+        let rows = store.rollups(&query).await?;
+
+        let Some(mut alloc) = self.allocations.get_mut(campaign_id) else {
+            return Ok(None);
+        };
+        alloc.spent_total = rows.iter().map(|r| r.metrics.spend).sum();
+        alloc.spent_today = rows
+            .iter()
+            .filter(|r| r.period == today)
+            .map(|r| r.metrics.spend)
+            .sum();
+        alloc.remaining = alloc.total_budget - alloc.spent_total;
+        if alloc.spent_total >= alloc.total_budget {
+            alloc.pacing_status = PacingStatus::Exhausted;
+        }
+        alloc.updated_at = now;
+        Ok(Some(alloc.clone()))
+    }
+
     /// Return a snapshot of the allocation for a campaign, if one exists.
     pub fn get_allocation(&self, campaign_id: &Uuid) -> Option<BudgetAllocation> {
         self.allocations.get(campaign_id).map(|r| r.clone())
     }
 
+    /// Total spend recorded for a campaign at or after `since`.
+    pub fn spend_since(&self, campaign_id: &Uuid, since: DateTime<Utc>) -> f64 {
+        self.spend_records
+            .get(campaign_id)
+            .map(|records| {
+                records
+                    .iter()
+                    .filter(|r| r.timestamp >= since)
+                    .map(|r| r.amount)
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+
     /// Compute current pacing status for a campaign.
     pub fn calculate_pacing(&self, campaign_id: &Uuid) -> Option<PacingStatus> {
         let alloc = self.allocations.get(campaign_id)?;
@@ -279,6 +492,227 @@ impl BudgetTracker {
         Some(status)
     }
 
+    /// Run one step of the pacing controller and return the bid-throttle
+    /// multiplier for the campaign.
+    pub fn pacing_decision(&self, campaign_id: &Uuid) -> Option<PacingDecision> {
+        self.pacing_decision_at(campaign_id, Utc::now())
+    }
+
+    /// Pacing step at `now`. Today's target is the daily budget, lowered when
+    /// the remaining budget spread over the remaining days is smaller; the
+    /// intraday traffic curve says how much of it should be spent by now,
+    /// and a PID controller turns the shortfall into a bid multiplier.
+    pub fn pacing_decision_at(
+        &self,
+        campaign_id: &Uuid,
+        now: DateTime<Utc>,
+    ) -> Option<PacingDecision> {
+        let alloc = self.allocations.get(campaign_id)?.clone();
+        let day_start = start_of_day(now);
+        let spent_today = self.spend_between(campaign_id, day_start, now);
+        let spent_before_today = self.spend_between(campaign_id, alloc.start_date, day_start);
+
+        let days_left = ((alloc.end_date - day_start).num_seconds() as f64 / 86_400.0)
+            .ceil()
+            .max(1.0);
+        let spread = (alloc.total_budget - spent_before_today).max(0.0) / days_left;
+        let target = if alloc.daily_budget > 0.0 {
+            alloc.daily_budget.min(spread)
+        } else {
+            spread
+        };
+        let curve = self
+            .traffic_curves
+            .get(campaign_id)
+            .map(|c| c.clone())
+            .unwrap_or_default();
+        let expected = target * curve.elapsed_fraction(now);
+        let error = if target > 0.0 {
+            (expected - spent_today) / target
+        } else {
+            0.0
+        };
+
+        let in_flight = now >= alloc.start_date && now < alloc.end_date;
+        let bid_multiplier =
+            if !in_flight || alloc.spent_total >= alloc.total_budget || spent_today >= target {
+                0.0
+            } else {
+                self.pacing_state.entry(*campaign_id).or_default().update(
+                    &self.pacing_config,
+                    error,
+                    now,
+                )
+            };
+
+        Some(PacingDecision {
+            campaign_id: *campaign_id,
+            bid_multiplier,
+            target_spend_today: target,
+            expected_spend_to_now: expected,
+            actual_spend_today: spent_today,
+            error,
+            currency: alloc.currency,
+            computed_at: now,
+        })
+    }
+
+    /// Forecast total spend at the end of the flight.
+    pub fn forecast_spend(&self, campaign_id: &Uuid) -> Option<SpendForecast> {
+        self.forecast_spend_at(campaign_id, Utc::now())
+    }
+
+    /// Forecast at `now` from completed days' spend and today's spend so far,
+    /// scaled by the intraday traffic curve.
+    pub fn forecast_spend_at(
+        &self,
+        campaign_id: &Uuid,
+        now: DateTime<Utc>,
+    ) -> Option<SpendForecast> {
+        let alloc = self.allocations.get(campaign_id)?.clone();
+        let day_start = start_of_day(now);
+        let flight_start = start_of_day(alloc.start_date);
+
+        let completed_days = (day_start - flight_start).num_days().max(0) as usize;
+        let mut daily_history = vec![0.0; completed_days];
+        let mut spent_total = 0.0;
+        let mut spent_today = 0.0;
+        if let Some(records) = self.spend_records.get(campaign_id) {
+            for r in records.iter().filter(|r| r.timestamp <= now) {
+                spent_total += r.amount;
+                if r.timestamp >= day_start {
+                    spent_today += r.amount;
+                } else if r.timestamp >= flight_start {
+                    let day = (r.timestamp - flight_start).num_days() as usize;
+                    daily_history[day] += r.amount;
+                }
+            }
+        }
+        let curve = self
+            .traffic_curves
+            .get(campaign_id)
+            .map(|c| c.clone())
+            .unwrap_or_default();
+
+        Some(forecast(ForecastInput {
+            campaign_id: *campaign_id,
+            currency: &alloc.currency,
+            total_budget: alloc.total_budget,
+            spent_total,
+            spent_today,
+            daily_history: &daily_history,
+            curve: &curve,
+            end_date: alloc.end_date,
+            now,
+        }))
+    }
+
+    /// Compare internal spend with DSP-reported spend hour by hour. Internal
+    /// spend counts towards a platform when it was recorded with the
+    /// platform's seat id (e.g. `thetradedesk`) as channel. DSP records are
+    /// converted to the campaign currency; records for other campaigns are
+    /// ignored. Only hours within each platform's reported range are
+    /// compared, and a `SpendDiscrepancy` alert is raised if any hour is out
+    /// of tolerance.
+    pub fn reconcile_dsp_spend(
+        &self,
+        campaign_id: &Uuid,
+        dsp_records: &[DspSpendRecord],
+        config: &ReconciliationConfig,
+    ) -> anyhow::Result<SpendReconciliation> {
+        let currency = self
+            .allocations
+            .get(campaign_id)
+            .map(|a| a.currency.clone())
+            .ok_or_else(|| anyhow::anyhow!("no budget for campaign {campaign_id}"))?;
+        let hour_of = |t: DateTime<Utc>| t.duration_trunc(Duration::hours(1)).unwrap_or(t);
+
+        // (platform seat, hour) -> (platform, internal, dsp)
+        let mut lines: BTreeMap<(&str, DateTime<Utc>), (DspPlatform, f64, f64)> = BTreeMap::new();
+        // platform seat -> (platform, first hour, last hour) reported
+        let mut ranges: BTreeMap<&str, (DspPlatform, DateTime<Utc>, DateTime<Utc>)> =
+            BTreeMap::new();
+        for record in dsp_records
+            .iter()
+            .filter(|r| r.campaign_id == Some(*campaign_id))
+        {
+            let seat = record.platform.seat_id();
+            let hour = hour_of(record.hour);
+            let spend = self.convert(record.spend, &record.currency, &currency)?;
+            lines
+                .entry((seat, hour))
+                .or_insert((record.platform, 0.0, 0.0))
+                .2 += spend;
+            let range = ranges.entry(seat).or_insert((record.platform, hour, hour));
+            range.1 = range.1.min(hour);
+            range.2 = range.2.max(hour);
+        }
+
+        if let Some(records) = self.spend_records.get(campaign_id) {
+            for record in records.iter() {
+                let Some((seat, (platform, first, last))) =
+                    ranges.get_key_value(record.channel.as_str())
+                else {
+                    continue;
+                };
+                let hour = hour_of(record.timestamp);
+                if (*first..=*last).contains(&hour) {
+                    lines.entry((seat, hour)).or_insert((*platform, 0.0, 0.0)).1 += record.amount;
+                }
+            }
+        }
+
+        let lines: Vec<ReconciliationLine> = lines
+            .into_iter()
+            .map(|((_, hour), (platform, internal, dsp))| {
+                let difference = internal - dsp;
+                let tolerance = config
+                    .absolute_tolerance
+                    .max(config.relative_tolerance * internal.abs().max(dsp.abs()));
+                ReconciliationLine {
+                    platform,
+                    hour,
+                    internal_spend: internal,
+                    dsp_spend: dsp,
+                    difference,
+                    discrepancy: difference.abs() > tolerance,
+                }
+            })
+            .collect();
+
+        let internal_total: f64 = lines.iter().map(|l| l.internal_spend).sum();
+        let dsp_total: f64 = lines.iter().map(|l| l.dsp_spend).sum();
+        let discrepancies = lines.iter().filter(|l| l.discrepancy).count();
+        if discrepancies > 0 {
+            let difference_pct = if dsp_total.abs() > 0.0 {
+                (internal_total - dsp_total) / dsp_total * 100.0
+            } else {
+                100.0
+            };
+            self.push_alert(
+                *campaign_id,
+                BudgetAlertType::SpendDiscrepancy,
+                config.relative_tolerance * 100.0,
+                difference_pct,
+                format!(
+                    "Campaign {} spend differs from DSP reports in {} hour(s): internal {:.2} vs DSP {:.2} {}",
+                    campaign_id, discrepancies, internal_total, dsp_total, currency
+                ),
+            );
+        }
+
+        Ok(SpendReconciliation {
+            campaign_id: *campaign_id,
+            currency,
+            internal_total,
+            dsp_total,
+            difference: internal_total - dsp_total,
+            lines,
+            discrepancies,
+            reconciled_at: Utc::now(),
+        })
+    }
+
     /// Return all alerts for a campaign.
     pub fn get_alerts(&self, campaign_id: &Uuid) -> Vec<BudgetAlert> {
         self.alerts
@@ -311,6 +745,7 @@ impl BudgetTracker {
     ) -> Option<RoasReport> {
         let alloc = self.allocations.get(campaign_id)?;
         let spend = alloc.spent_total;
+        let currency = alloc.currency.clone();
 
         let roas = if spend > 0.0 { revenue / spend } else { 0.0 };
         let roi_percent = if spend > 0.0 {
@@ -336,6 +771,7 @@ impl BudgetTracker {
 
         Some(RoasReport {
             campaign_id: *campaign_id,
+            currency,
             total_spend: spend,
             total_revenue: revenue,
             roas,
@@ -394,6 +830,20 @@ impl BudgetTracker {
 
     // -- internal helpers ---------------------------------------------------
 
+    /// Spend recorded in `[from, to)`.
+    fn spend_between(&self, campaign_id: &Uuid, from: DateTime<Utc>, to: DateTime<Utc>) -> f64 {
+        self.spend_records
+            .get(campaign_id)
+            .map(|records| {
+                records
+                    .iter()
+                    .filter(|r| r.timestamp >= from && r.timestamp < to)
+                    .map(|r| r.amount)
+                    .sum()
+            })
+            .unwrap_or(0.0)
+    }
+
     fn push_alert(
         &self,
         campaign_id: Uuid,
@@ -635,7 +1085,147 @@ mod tests {
         assert!((breakdown[2].1 - 200.0).abs() < f64::EPSILON);
     }
 
-    // 6. Daily spend breakdown ----------------------------------------------
+    // 6. Currency, pacing and forecasting -----------------------------------
+
+    fn at(day: u32, hour: u32) -> DateTime<Utc> {
+        use chrono::TimeZone;
+        Utc.with_ymd_and_hms(2024, 5, day, hour, 0, 0).unwrap()
+    }
+
+    #[test]
+    fn test_budget_currency_and_conversion() {
+        let tracker = BudgetTracker::new().with_default_currency("gbp");
+        let (gbp, eur) = (Uuid::new_v4(), Uuid::new_v4());
+        tracker.set_budget(gbp, 1_000.0, 100.0, at(1, 0), at(11, 0));
+        tracker.set_budget_in_currency(eur, 1_000.0, 100.0, at(1, 0), at(11, 0), "EUR");
+        assert_eq!(tracker.get_allocation(&gbp).unwrap().currency, "GBP");
+        tracker.record_spend(eur, 10.0, "email", "impression");
+        let report = tracker.calculate_roas(&eur, 30.0, 1, 1, 1).unwrap();
+        assert_eq!(report.currency, "EUR");
+
+        tracker.set_exchange_rate("EUR", 1.10);
+        tracker.set_exchange_rate("GBP", 1.25);
+        let converted = tracker.convert(110.0, "eur", "GBP").unwrap();
+        assert!((converted - 96.8).abs() < 1e-9);
+        assert!(tracker.convert(1.0, "JPY", "USD").is_err());
+    }
+
+    #[test]
+    fn test_pacing_controller_follows_traffic_curve() {
+        let tracker = BudgetTracker::new();
+        let cid = Uuid::new_v4();
+        // 10-day flight, 1 000 total, 150/day cap -> target is 100/day.
+        tracker.set_budget(cid, 1_000.0, 150.0, at(1, 0), at(11, 0));
+        let mut volume = [1.0; 24];
+        volume[12..24].copy_from_slice(&[3.0; 12]);
+        tracker.set_traffic_curve(cid, IntradayCurve::from_hourly_volume(volume));
+
+        // By noon only a quarter of the day's traffic has arrived.
+        tracker.record_spend_at(cid, 25.0, "display", "impression", at(1, 11));
+        let on_track = tracker.pacing_decision_at(&cid, at(1, 12)).unwrap();
+        assert!((on_track.target_spend_today - 100.0).abs() < 1e-9);
+        assert!((on_track.expected_spend_to_now - 25.0).abs() < 1e-9);
+        assert!((on_track.bid_multiplier - 1.0).abs() < 1e-9);
+
+        let behind = tracker.pacing_decision_at(&cid, at(1, 18)).unwrap();
+        assert!(behind.error > 0.0 && behind.bid_multiplier > 1.0);
+
+        tracker.record_spend_at(cid, 80.0, "display", "impression", at(1, 19));
+        let done = tracker.pacing_decision_at(&cid, at(1, 20)).unwrap();
+        assert_eq!(done.bid_multiplier, 0.0);
+
+        // Day two: 895 left over 9 days lowers the target below 100.
+        let next = tracker.pacing_decision_at(&cid, at(2, 1)).unwrap();
+        assert!((next.target_spend_today - 895.0 / 9.0).abs() < 1e-9);
+        assert!(next.bid_multiplier > 0.0);
+    }
+
+    #[test]
+    fn test_spent_today_rolls_over() {
+        let (tracker, cid) = make_tracker_with_budget();
+        tracker.record_spend(cid, 50.0, "email", "impression");
+        let tomorrow = Utc::now() + Duration::days(1);
+        tracker.record_spend_at(cid, 5.0, "email", "impression", tomorrow);
+        let alloc = tracker.get_allocation(&cid).unwrap();
+        assert_eq!(alloc.spent_today, 5.0);
+        assert_eq!(alloc.spent_total, 55.0);
+    }
+
+    #[test]
+    fn test_forecast_from_recorded_spend() {
+        let tracker = BudgetTracker::new();
+        let cid = Uuid::new_v4();
+        tracker.set_budget_in_currency(cid, 1_000.0, 200.0, at(1, 0), at(11, 0), "EUR");
+        for day in 1..=4 {
+            tracker.record_spend_at(cid, 150.0, "search", "click", at(day, 10));
+        }
+        tracker.record_spend_at(cid, 75.0, "search", "click", at(5, 10));
+
+        let forecast = tracker.forecast_spend_at(&cid, at(5, 12)).unwrap();
+        assert_eq!(forecast.currency, "EUR");
+        assert!((forecast.daily_run_rate - 150.0).abs() < 1e-9);
+        assert_eq!(forecast.forecast_spend, 1_000.0);
+        let exhausted = forecast.projected_exhaustion.unwrap();
+        // 250 left after today's projected 150, at 150/day.
+        assert_eq!(exhausted, at(7, 16));
+    }
+
+    // 7. DSP reconciliation --------------------------------------------------
+
+    #[test]
+    fn test_reconcile_dsp_spend() {
+        let tracker = BudgetTracker::new();
+        let cid = Uuid::new_v4();
+        tracker.set_budget(cid, 10_000.0, 1_000.0, at(1, 0), at(11, 0));
+        tracker.set_exchange_rate("EUR", 1.25);
+        for hour in [9, 10] {
+            tracker.record_spend_at(cid, 100.0, "thetradedesk", "impression", at(3, hour));
+        }
+        // Outside the DSP's reported range and on another channel.
+        tracker.record_spend_at(cid, 40.0, "thetradedesk", "impression", at(3, 14));
+        tracker.record_spend_at(cid, 60.0, "email", "impression", at(3, 9));
+
+        let dsp = |hour, spend: f64, currency: &str, campaign| DspSpendRecord {
+            platform: DspPlatform::TheTradeDesk,
+            campaign_id: campaign,
+            hour: at(3, hour),
+            impressions: 1_000,
+            spend,
+            currency: currency.to_string(),
+            wins: 1_000,
+            avg_win_price: 0.1,
+        };
+        let records = vec![
+            dsp(9, 100.5, "USD", Some(cid)),
+            dsp(10, 64.0, "EUR", Some(cid)),
+            dsp(10, 500.0, "USD", Some(Uuid::new_v4())),
+            dsp(11, 20.0, "USD", Some(cid)),
+        ];
+        let report = tracker
+            .reconcile_dsp_spend(&cid, &records, &ReconciliationConfig::default())
+            .unwrap();
+
+        assert_eq!(report.lines.len(), 3);
+        assert!(!report.lines[0].discrepancy);
+        assert!((report.lines[1].dsp_spend - 80.0).abs() < 1e-9);
+        assert!(report.lines[1].discrepancy);
+        assert!(report.lines[2].discrepancy && report.lines[2].internal_spend == 0.0);
+        assert_eq!(report.discrepancies, 2);
+        assert!((report.internal_total - 200.0).abs() < 1e-9);
+        assert!(tracker
+            .get_alerts(&cid)
+            .iter()
+            .any(|a| a.alert_type == BudgetAlertType::SpendDiscrepancy));
+
+        let unknown = tracker.reconcile_dsp_spend(
+            &cid,
+            &[dsp(9, 1.0, "JPY", Some(cid))],
+            &ReconciliationConfig::default(),
+        );
+        assert!(unknown.is_err());
+    }
+
+    // 8. Daily spend breakdown ----------------------------------------------
 
     #[test]
     fn test_daily_spend_breakdown() {
@@ -654,4 +1244,43 @@ mod tests {
         assert!((breakdown[1].1).abs() < f64::EPSILON);
         assert!((breakdown[2].1).abs() < f64::EPSILON);
     }
+
+    #[tokio::test]
+    async fn test_load_spend_from_rollups() {
+        use campaign_analytics::InMemoryAnalyticsStore;
+        use campaign_core::event_bus::{make_event, EventSink};
+        use campaign_core::types::EventType;
+
+        let store = Arc::new(InMemoryAnalyticsStore::new());
+        let tracker = BudgetTracker::new().with_store(store.clone());
+        let cid = Uuid::new_v4();
+        let now = Utc::now();
+        tracker.set_budget(
+            cid,
+            100.0,
+            50.0,
+            now - Duration::days(5),
+            now + Duration::days(5),
+        );
+
+        for (price, age) in [(30.0, Duration::days(2)), (20.0, Duration::zero())] {
+            let mut event =
+                make_event(EventType::Impression, "req", None, None).with_campaign(cid.to_string());
+            event.win_price = Some(price);
+            event.timestamp = now - age;
+            store.emit(event);
+        }
+        // Spend recorded only on this node is replaced by the shared totals.
+        tracker.record_spend(cid, 5.0, "display", "impression");
+
+        let alloc = tracker.load_spend(&cid, now).await.unwrap().unwrap();
+        assert!((alloc.spent_total - 50.0).abs() < 1e-9);
+        assert!((alloc.spent_today - 20.0).abs() < 1e-9);
+        assert!((alloc.remaining - 50.0).abs() < 1e-9);
+        assert!(tracker
+            .load_spend(&Uuid::new_v4(), now)
+            .await
+            .unwrap()
+            .is_none());
+    }
 }
@@ -1,15 +1,30 @@
 //! Cohort analysis — retention curves and lifecycle tracking.
+//!
+//! Users join the cohort of the period in which they first perform the
+//! entry event. Retention in period `p` is the share of the cohort that
+//! performed the return event in the `p`-th period after their own entry.
 
-use chrono::{DateTime, Utc};
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use crate::events::{UserEvent, UserEventLog};
+use crate::measurement::MeasurementEvent;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct CohortDefinition {
     pub id: Uuid,
     pub name: String,
+    /// Entry-event property that splits each period's cohort into groups;
+    /// empty for none.
     pub cohort_property: String,
+    /// Return event; empty matches any event.
     pub retention_event: String,
+    /// Event that places a user in a cohort; `None` uses their first event.
+    #[serde(default)]
+    pub entry_event: Option<String>,
     pub period: CohortPeriod,
     pub num_periods: u32,
     pub created_at: DateTime<Utc>,
@@ -34,17 +49,62 @@ pub struct CohortResult {
 pub struct CohortRow {
     pub cohort_date: chrono::NaiveDate,
     pub initial_size: u64,
+    /// One rate per period that has started, up to `num_periods`.
     pub retention_rates: Vec<f64>,
+    /// Value of the cohort property, when the definition sets one.
+    #[serde(default)]
+    pub group: Option<String>,
+}
+
+impl CohortPeriod {
+    fn length(&self) -> Duration {
+        match self {
+            CohortPeriod::Daily => Duration::days(1),
+            CohortPeriod::Weekly => Duration::weeks(1),
+            CohortPeriod::Monthly => Duration::days(30),
+        }
+    }
+
+    /// First day of the calendar period containing `date`.
+    fn start_of(&self, date: NaiveDate) -> NaiveDate {
+        match self {
+            CohortPeriod::Daily => date,
+            CohortPeriod::Weekly => {
+                date - Duration::days(date.weekday().num_days_from_monday() as i64)
+            }
+            CohortPeriod::Monthly => date.with_day(1).unwrap_or(date),
+        }
+    }
 }
 
 pub struct CohortAnalyzer {
     definitions: dashmap::DashMap<Uuid, CohortDefinition>,
+    events: UserEventLog,
 }
 
 impl CohortAnalyzer {
     pub fn new() -> Self {
         Self {
             definitions: dashmap::DashMap::new(),
+            events: UserEventLog::default(),
+        }
+    }
+
+    pub fn record_event(&self, event: UserEvent) {
+        self.events.record(event);
+    }
+
+    /// Record a measurement event; anonymous events are ignored.
+    pub fn record_measurement(&self, event: &MeasurementEvent) {
+        if let Some(event) = UserEvent::from_measurement(event) {
+            self.record_event(event);
+        }
+    }
+
+    /// Record a pipeline analytics event; anonymous events are ignored.
+    pub fn record_analytics(&self, event: &AnalyticsEvent) {
+        if let Some(event) = UserEvent::from_analytics(event) {
+            self.record_event(event);
         }
     }
 
@@ -54,28 +114,74 @@ impl CohortAnalyzer {
 
     pub fn analyze(&self, definition_id: &Uuid) -> Option<CohortResult> {
         let def = self.definitions.get(definition_id)?;
-        let mut cohorts = Vec::new();
-        let today = Utc::now().date_naive();
-
-        for i in 0..6 {
-            let date = today - chrono::Duration::days(i * 7);
-            let initial = 1000 - (i as u64 * 50);
-            let mut rates = Vec::new();
-            for p in 0..def.num_periods {
-                let rate = 1.0 / (1.0 + (p as f64 * 0.3));
-                rates.push((rate * 100.0).round() / 100.0);
-            }
-            cohorts.push(CohortRow {
-                cohort_date: date,
-                initial_size: initial,
-                retention_rates: rates,
+        let now = Utc::now();
+        let length = def.period.length();
+        let periods = def.num_periods as usize;
+
+        // (cohort date, group) -> (size, users retained per period)
+        let mut cohorts: BTreeMap<(NaiveDate, Option<String>), (u64, Vec<u64>)> = BTreeMap::new();
+        for (_, timeline) in self.events.timelines() {
+            let entry = match &def.entry_event {
+                Some(name) => timeline.iter().find(|e| &e.event_name == name),
+                None => timeline.first(),
+            };
+            let Some(entry) = entry else {
+                continue;
+            };
+            let group = (!def.cohort_property.is_empty()).then(|| {
+                entry
+                    .properties
+                    .get(&def.cohort_property)
+                    .map(|v| match v {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .unwrap_or_default()
             });
+            let cohort_date = def.period.start_of(entry.timestamp.date_naive());
+            let (size, retained) = cohorts
+                .entry((cohort_date, group))
+                .or_insert_with(|| (0, vec![0; periods]));
+            *size += 1;
+
+            let mut seen = vec![false; periods];
+            for event in timeline.iter().filter(|e| {
+                e.timestamp >= entry.timestamp
+                    && (def.retention_event.is_empty() || e.event_name == def.retention_event)
+            }) {
+                let period = ((event.timestamp - entry.timestamp).num_seconds()
+                    / length.num_seconds()) as usize;
+                if period < periods && !seen[period] {
+                    seen[period] = true;
+                    retained[period] += 1;
+                }
+            }
         }
 
+        let rows = cohorts
+            .into_iter()
+            .rev()
+            .map(|((cohort_date, group), (size, retained))| {
+                let start = cohort_date.and_time(NaiveTime::MIN).and_utc();
+                let started =
+                    ((now - start).num_seconds().max(0) / length.num_seconds()) as usize + 1;
+                CohortRow {
+                    cohort_date,
+                    initial_size: size,
+                    retention_rates: retained
+                        .iter()
+                        .take(started)
+                        .map(|&r| r as f64 / size as f64)
+                        .collect(),
+                    group,
+                }
+            })
+            .collect();
+
         Some(CohortResult {
             definition_id: *definition_id,
-            cohorts,
-            computed_at: Utc::now(),
+            cohorts: rows,
+            computed_at: now,
         })
     }
 
@@ -89,3 +195,94 @@ impl Default for CohortAnalyzer {
         Self::new()
     }
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashMap;
+
+    fn event(user: &str, name: &str, at: DateTime<Utc>, channel: &str) -> UserEvent {
+        UserEvent {
+            user_id: user.to_string(),
+            event_name: name.to_string(),
+            timestamp: at,
+            channel: Some(channel.to_string()),
+            properties: HashMap::from([("channel".to_string(), channel.into())]),
+        }
+    }
+
+    #[test]
+    fn test_retention_from_entry_and_return_events() {
+        let analyzer = CohortAnalyzer::new();
+        let mut definition = CohortDefinition {
+            id: Uuid::new_v4(),
+            name: "signup to purchase".to_string(),
+            cohort_property: String::new(),
+            retention_event: "purchase".to_string(),
+            entry_event: Some("signup".to_string()),
+            period: CohortPeriod::Daily,
+            num_periods: 3,
+            created_at: Utc::now(),
+        };
+        analyzer.define_cohort(definition.clone());
+
+        let day = (Utc::now() - Duration::days(5))
+            .date_naive()
+            .and_time(NaiveTime::MIN)
+            .and_utc();
+        let h = Duration::hours;
+        for e in [
+            event("u1", "page_view", day, "web"),
+            event("u1", "signup", day + h(10), "email"),
+            event("u1", "purchase", day + h(35), "email"),
+            event("u2", "signup", day + h(12), "push"),
+            event("u2", "purchase", day + h(12 + 50), "push"),
+            event("u2", "purchase", day + h(12 + 51), "push"),
+            event("u3", "signup", day + h(9), "email"),
+            // Signed up yesterday: only the periods that started count.
+            event("u4", "signup", day + h(4 * 24 + 1), "email"),
+            event("u4", "purchase", day + h(4 * 24 + 2), "email"),
+            // Never signed up.
+            event("u5", "purchase", day, "web"),
+        ] {
+            analyzer.record_event(e);
+        }
+
+        let result = analyzer.analyze(&definition.id).unwrap();
+        assert_eq!(result.cohorts.len(), 2);
+        let newest = &result.cohorts[0];
+        assert_eq!(newest.initial_size, 1);
+        assert_eq!(newest.retention_rates, [1.0, 0.0]);
+        let oldest = &result.cohorts[1];
+        assert_eq!(oldest.cohort_date, day.date_naive());
+        assert_eq!(oldest.initial_size, 3);
+        let third = 1.0 / 3.0;
+        assert_eq!(oldest.retention_rates, [0.0, third, third]);
+        assert_eq!(oldest.group, None);
+
+        definition.id = Uuid::new_v4();
+        definition.cohort_property = "channel".to_string();
+        definition.period = CohortPeriod::Weekly;
+        analyzer.define_cohort(definition.clone());
+        let groups: Vec<(Option<String>, u64)> = analyzer
+            .analyze(&definition.id)
+            .unwrap()
+            .cohorts
+            .into_iter()
+            .map(|c| (c.group, c.initial_size))
+            .collect();
+        let email = groups
+            .iter()
+            .filter(|(g, _)| g.as_deref() == Some("email"))
+            .map(|(_, n)| n)
+            .sum::<u64>();
+        assert_eq!(email, 3);
+        assert!(groups
+            .iter()
+            .any(|(g, n)| g.as_deref() == Some("push") && *n == 1));
+    }
+}
@@ -1,7 +1,20 @@
 //! Campaign performance dashboard — real-time metrics aggregation.
+//!
+//! With an analytics store attached, metrics are read from the shared
+//! ClickHouse rollups, so every node reports the same numbers and nothing is
+//! lost on restart. Without one, the dashboard serves whatever was pushed
+//! through [`CampaignDashboard::update_metrics`]. With [`LiveMetrics`]
+//! attached, [`CampaignDashboard::live_campaign_metrics`] computes campaign
+//! metrics over a recent window of the event stream; those window counts are
+//! returned as-is and never replace the cumulative metrics.
 
-use chrono::{DateTime, Utc};
+use crate::live::{LiveMetrics, LiveWindow};
+use campaign_analytics::rollups::RollupMetrics;
+use campaign_analytics::{AnalyticsStore, RollupDimension, RollupGrain, RollupQuery};
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -62,15 +75,191 @@ pub struct DashboardOverview {
 
 pub struct CampaignDashboard {
     metrics: dashmap::DashMap<Uuid, CampaignMetrics>,
+    store: Option<Arc<dyn AnalyticsStore>>,
+    live: Option<Arc<LiveMetrics>>,
+}
+
+fn ratio(numerator: u64, denominator: u64) -> f64 {
+    if denominator > 0 {
+        numerator as f64 / denominator as f64
+    } else {
+        0.0
+    }
 }
 
 impl CampaignDashboard {
     pub fn new() -> Self {
         Self {
             metrics: dashmap::DashMap::new(),
+            store: None,
+            live: None,
+        }
+    }
+
+    /// Serve metrics from the analytics rollups.
+    pub fn with_store(mut self, store: Arc<dyn AnalyticsStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Compute metrics from the live event stream.
+    pub fn with_live(mut self, live: Arc<LiveMetrics>) -> Self {
+        self.live = Some(live);
+        self
+    }
+
+    fn store(&self) -> anyhow::Result<&Arc<dyn AnalyticsStore>> {
+        self.store
+            .as_ref()
+            .ok_or_else(|| anyhow::anyhow!("dashboard has no analytics store"))
+    }
+
+    /// Load a campaign's metrics for `[from, to)` from the rollups and cache
+    /// them for [`Self::get_campaign_metrics`]. Impressions count as opens
+    /// and failed sends as bounces. Name, revenue and unsubscribes are not
+    /// in the event stream and are kept from previously pushed metrics.
+    pub async fn load_campaign_metrics(
+        &self,
+        campaign_id: &Uuid,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> anyhow::Result<Option<CampaignMetrics>> {
+        let query = RollupQuery::new(RollupDimension::Campaign, RollupGrain::Hourly, from, to)
+            .with_keys([campaign_id.to_string()])
+            .totals();
+        let Some(row) = self.store()?.rollups(&query).await?.into_iter().next() else {
+            return Ok(None);
+        };
+        let previous = self.get_campaign_metrics(campaign_id);
+        let metrics = Self::from_rollup(*campaign_id, &row.metrics, previous.as_ref());
+        self.update_metrics(metrics.clone());
+        Ok(Some(metrics))
+    }
+
+    /// Metrics over `window` for every campaign seen by the live aggregator.
+    /// Only counts inside the window are reported: unique counts, revenue and
+    /// unsubscribes are not tracked live and stay zero. Nothing is cached.
+    pub fn live_campaign_metrics(
+        &self,
+        window: LiveWindow,
+        now: DateTime<Utc>,
+    ) -> Vec<CampaignMetrics> {
+        let Some(live) = &self.live else {
+            return Vec::new();
+        };
+        live.snapshots(RollupDimension::Campaign, window, now)
+            .into_iter()
+            .filter_map(|snapshot| {
+                let campaign_id = snapshot.key.parse::<Uuid>().ok()?;
+                let mut metrics = Self::from_rollup(campaign_id, &snapshot.metrics, None);
+                if let Some(previous) = self.get_campaign_metrics(&campaign_id) {
+                    metrics.name = previous.name;
+                }
+                metrics.updated_at = now;
+                Some(metrics)
+            })
+            .collect()
+    }
+
+    fn from_rollup(
+        campaign_id: Uuid,
+        m: &RollupMetrics,
+        previous: Option<&CampaignMetrics>,
+    ) -> CampaignMetrics {
+        let unsubscribes = previous.map_or(0, |p| p.unsubscribes);
+        CampaignMetrics {
+            campaign_id,
+            name: previous.map_or_else(|| campaign_id.to_string(), |p| p.name.clone()),
+            sends: m.sends,
+            deliveries: m.deliveries,
+            opens: m.impressions,
+            unique_opens: m.unique_impressions,
+            clicks: m.clicks,
+            unique_clicks: m.unique_clicks,
+            bounces: m.failures,
+            unsubscribes,
+            conversions: m.conversions,
+            revenue: previous.map_or(0.0, |p| p.revenue),
+            delivery_rate: m.delivery_rate(),
+            open_rate: ratio(m.impressions, m.deliveries),
+            click_rate: ratio(m.clicks, m.deliveries),
+            click_to_open_rate: ratio(m.clicks, m.impressions),
+            conversion_rate: m.conversion_rate(),
+            bounce_rate: ratio(m.failures, m.sends),
+            unsubscribe_rate: ratio(unsubscribes, m.deliveries),
+            updated_at: Utc::now(),
         }
     }
 
+    /// Overview from the rollups: 30-day totals and daily sends, today's and
+    /// the last week's sends, and a per-channel breakdown. Falls back to
+    /// [`Self::get_overview`] without a store.
+    pub async fn load_overview(&self, now: DateTime<Utc>) -> anyhow::Result<DashboardOverview> {
+        let Some(store) = &self.store else {
+            return Ok(self.get_overview());
+        };
+        let today = RollupGrain::Daily.truncate(now);
+        let from = today - Duration::days(29);
+        let to = today + Duration::days(1);
+
+        let daily = store
+            .rollups(&RollupQuery::new(
+                RollupDimension::Campaign,
+                RollupGrain::Daily,
+                from,
+                to,
+            ))
+            .await?;
+        let channels = store
+            .rollups(
+                &RollupQuery::new(RollupDimension::Channel, RollupGrain::Daily, from, to).totals(),
+            )
+            .await?;
+
+        let mut total = RollupMetrics::default();
+        let mut sends_by_day: BTreeMap<DateTime<Utc>, u64> = BTreeMap::new();
+        let mut campaigns = std::collections::HashSet::new();
+        for row in &daily {
+            total.merge(&row.metrics);
+            *sends_by_day.entry(row.period).or_default() += row.metrics.sends;
+            campaigns.insert(row.key.as_str());
+        }
+        let sends_since = |since: DateTime<Utc>| -> u64 {
+            sends_by_day.range(since..).map(|(_, sends)| *sends).sum()
+        };
+
+        Ok(DashboardOverview {
+            total_active_campaigns: campaigns.len() as u64,
+            total_sends_today: sends_since(today),
+            total_sends_week: sends_since(today - Duration::days(6)),
+            overall_delivery_rate: total.delivery_rate(),
+            overall_open_rate: ratio(total.impressions, total.deliveries),
+            overall_click_rate: ratio(total.clicks, total.deliveries),
+            overall_conversion_rate: total.conversion_rate(),
+            total_revenue_30d: self.metrics.iter().map(|m| m.revenue).sum(),
+            channel_breakdown: channels
+                .into_iter()
+                .map(|row| ChannelBreakdown {
+                    channel: row.key,
+                    sends: row.metrics.sends,
+                    deliveries: row.metrics.deliveries,
+                    engagements: row.metrics.impressions + row.metrics.clicks,
+                    conversions: row.metrics.conversions,
+                    revenue: 0.0,
+                })
+                .collect(),
+            sends_over_time: sends_by_day
+                .into_iter()
+                .map(|(day, sends)| TimeSeriesPoint {
+                    timestamp: day,
+                    value: sends as f64,
+                    label: None,
+                })
+                .collect(),
+            generated_at: now,
+        })
+    }
+
     pub fn update_metrics(&self, metrics: CampaignMetrics) {
         self.metrics.insert(metrics.campaign_id, metrics);
     }
@@ -129,3 +318,98 @@ impl Default for CampaignDashboard {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_analytics::InMemoryAnalyticsStore;
+    use campaign_core::event_bus::{make_event, EventSink};
+    use campaign_core::types::EventType;
+
+    #[tokio::test]
+    async fn test_dashboard_reads_rollups() {
+        let store = Arc::new(InMemoryAnalyticsStore::new());
+        let campaign = Uuid::new_v4();
+        let now = Utc::now();
+        for (event_type, user, channel) in [
+            (EventType::ActivationSent, "u1", "email"),
+            (EventType::ActivationSent, "u2", "email"),
+            (EventType::ActivationDelivered, "u1", "email"),
+            (EventType::ActivationDelivered, "u2", "email"),
+            (EventType::Impression, "u1", "email"),
+            (EventType::Impression, "u1", "email"),
+            (EventType::Click, "u1", "email"),
+            (EventType::ActivationSent, "u3", "sms"),
+        ] {
+            let mut event = make_event(event_type, "req", Some(user.into()), None)
+                .with_campaign(campaign.to_string())
+                .with_channel(channel);
+            event.timestamp = now;
+            store.emit(event);
+        }
+
+        let dashboard = CampaignDashboard::new().with_store(store);
+        let metrics = dashboard
+            .load_campaign_metrics(
+                &campaign,
+                now - Duration::hours(1),
+                now + Duration::hours(1),
+            )
+            .await
+            .unwrap()
+            .unwrap();
+        assert_eq!((metrics.sends, metrics.deliveries), (3, 2));
+        assert_eq!((metrics.opens, metrics.unique_opens), (2, 1));
+        assert!((metrics.click_rate - 0.5).abs() < 1e-9);
+        assert!(dashboard.get_campaign_metrics(&campaign).is_some());
+
+        let overview = dashboard.load_overview(now).await.unwrap();
+        assert_eq!(overview.total_active_campaigns, 1);
+        assert_eq!(overview.total_sends_today, 3);
+        assert_eq!(overview.channel_breakdown.len(), 2);
+        assert_eq!(overview.channel_breakdown[0].channel, "email");
+        assert_eq!(overview.sends_over_time.len(), 1);
+    }
+
+    #[test]
+    fn test_live_campaign_metrics_are_not_cached() {
+        let live = Arc::new(LiveMetrics::new());
+        let campaign = Uuid::new_v4();
+        for event_type in [
+            EventType::ActivationSent,
+            EventType::ActivationSent,
+            EventType::ActivationDelivered,
+            EventType::Impression,
+        ] {
+            live.emit(
+                make_event(event_type, "req", None, None).with_campaign(campaign.to_string()),
+            );
+        }
+        live.emit(make_event(EventType::Impression, "req", None, None).with_campaign("not-a-uuid"));
+
+        let dashboard = CampaignDashboard::new().with_live(live);
+        let mut cumulative =
+            CampaignDashboard::from_rollup(campaign, &RollupMetrics::default(), None);
+        cumulative.name = "Spring sale".to_string();
+        cumulative.sends = 500;
+        cumulative.revenue = 1_000.0;
+        dashboard.update_metrics(cumulative);
+
+        let window = LiveWindow::Sliding { seconds: 300 };
+        let live_metrics = dashboard.live_campaign_metrics(window, Utc::now());
+        assert_eq!(live_metrics.len(), 1);
+        let metrics = &live_metrics[0];
+        assert_eq!(metrics.name, "Spring sale");
+        assert_eq!(
+            (metrics.sends, metrics.deliveries, metrics.opens),
+            (2, 1, 1)
+        );
+        assert!((metrics.delivery_rate - 0.5).abs() < 1e-9);
+
+        let stored = dashboard.get_campaign_metrics(&campaign).unwrap();
+        assert_eq!((stored.sends, stored.revenue), (500, 1_000.0));
+        assert!(CampaignDashboard::new()
+            .live_campaign_metrics(window, Utc::now())
+            .is_empty());
+    }
+}
@@ -0,0 +1,129 @@
+//! User-level events for behavioural analysis (funnels and cohorts).
+//!
+//! Both event schemas, [`MeasurementEvent`] and the bidding pipeline's
+//! [`AnalyticsEvent`], are flattened into a [`UserEvent`]: who did what,
+//! when, on which channel, with filterable properties.
+
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::HashMap;
+
+use crate::measurement::{MeasurementEvent, MeasurementEventType};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct UserEvent {
+    pub user_id: String,
+    pub event_name: String,
+    pub timestamp: DateTime<Utc>,
+    pub channel: Option<String>,
+    pub properties: HashMap<String, Value>,
+}
+
+impl UserEvent {
+    /// `None` for anonymous events.
+    pub fn from_measurement(event: &MeasurementEvent) -> Option<Self> {
+        let user_id = event.user_id.clone()?;
+        let event_name = match &event.event_type {
+            MeasurementEventType::Custom(name) => name.clone(),
+            other => snake_case_name(other),
+        };
+        let mut properties: HashMap<String, Value> = event
+            .dimensions
+            .iter()
+            .map(|(k, v)| (k.clone(), Value::from(v.as_str())))
+            .collect();
+        for (k, v) in &event.metrics {
+            properties.insert(k.clone(), Value::from(*v));
+        }
+        properties.insert(
+            "source".to_string(),
+            Value::from(snake_case_name(&event.source)),
+        );
+        if let Some(campaign_id) = &event.campaign_id {
+            properties.insert("campaign_id".to_string(), Value::from(campaign_id.as_str()));
+        }
+        if let Some(channel) = &event.channel {
+            properties.insert("channel".to_string(), Value::from(channel.as_str()));
+        }
+        Some(Self {
+            user_id,
+            event_name,
+            timestamp: event.timestamp,
+            channel: event.channel.clone(),
+            properties,
+        })
+    }
+
+    /// `None` for anonymous events.
+    pub fn from_analytics(event: &AnalyticsEvent) -> Option<Self> {
+        let user_id = event.user_id.clone()?;
+        let mut properties = HashMap::from([
+            ("agent_id".to_string(), Value::from(event.agent_id.as_str())),
+            ("node_id".to_string(), Value::from(event.node_id.as_str())),
+        ]);
+        if let Some(offer_id) = &event.offer_id {
+            properties.insert("offer_id".to_string(), Value::from(offer_id.as_str()));
+        }
+        for (key, value) in [
+            ("bid_price", event.bid_price),
+            ("win_price", event.win_price),
+        ] {
+            if let Some(v) = value {
+                properties.insert(key.to_string(), Value::from(v));
+            }
+        }
+        Some(Self {
+            user_id,
+            event_name: snake_case_name(&event.event_type),
+            timestamp: event.timestamp,
+            channel: None,
+            properties,
+        })
+    }
+
+    /// Whether every filter equals the event's property of the same name.
+    pub fn matches(&self, event_name: &str, filters: &HashMap<String, Value>) -> bool {
+        self.event_name == event_name
+            && filters
+                .iter()
+                .all(|(k, v)| self.properties.get(k) == Some(v))
+    }
+}
+
+/// The serde name of a unit enum variant.
+fn snake_case_name<T: Serialize>(value: &T) -> String {
+    match serde_json::to_value(value) {
+        Ok(Value::String(name)) => name,
+        Ok(other) => other.to_string(),
+        Err(_) => String::new(),
+    }
+}
+
+/// Events recorded per user.
+#[derive(Default)]
+pub(crate) struct UserEventLog {
+    events: dashmap::DashMap<String, Vec<UserEvent>>,
+}
+
+impl UserEventLog {
+    pub(crate) fn record(&self, event: UserEvent) {
+        self.events
+            .entry(event.user_id.clone())
+            .or_default()
+            .push(event);
+    }
+
+    /// Each user's events in time order.
+    pub(crate) fn timelines(&self) -> Vec<(String, Vec<UserEvent>)> {
+        self.events
+            .iter()
+            .map(|e| {
+                let mut timeline = e.value().clone();
+                timeline.sort_by_key(|e| e.timestamp);
+                (e.key().clone(), timeline)
+            })
+            .collect()
+    }
+}
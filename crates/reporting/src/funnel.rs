@@ -1,9 +1,21 @@
 //! Funnel analysis — tracks user progression through multi-step conversion paths.
+//!
+//! Funnels are computed from recorded user events. Each user counts once,
+//! at the deepest progression any of their first-step events reaches
+//! within the conversion window.
 
-use chrono::{DateTime, Utc};
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Duration, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use uuid::Uuid;
 
+use crate::events::{UserEvent, UserEventLog};
+use crate::measurement::MeasurementEvent;
+
+/// Group label for users without a value for the breakdown.
+const NO_VALUE: &str = "(none)";
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct FunnelDefinition {
     pub id: Uuid,
@@ -11,6 +23,31 @@ pub struct FunnelDefinition {
     pub steps: Vec<FunnelStep>,
     pub conversion_window_hours: u32,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub ordering: FunnelOrdering,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunnelOrdering {
+    /// Each step must be the user's very next event.
+    Strict,
+    /// Steps in order, with other events allowed in between.
+    #[default]
+    Relaxed,
+}
+
+/// How [`FunnelAnalyzer::analyze_breakdown`] groups users.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FunnelBreakdown {
+    /// Channel of the event that entered the funnel.
+    Channel,
+    /// Segments set with [`FunnelAnalyzer::set_user_segments`]; a user
+    /// counts in each of their segments.
+    Segment,
+    /// A property of the event that entered the funnel.
+    Property(String),
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -39,60 +76,219 @@ pub struct FunnelStepResult {
     pub median_time_seconds: Option<u64>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FunnelBreakdownRow {
+    pub key: String,
+    pub result: FunnelResult,
+}
+
+/// How far one user got: the time of each step reached, and the event
+/// that entered the funnel.
+struct Progress<'a> {
+    times: Vec<DateTime<Utc>>,
+    entry: Option<&'a UserEvent>,
+}
+
 pub struct FunnelAnalyzer {
     funnels: dashmap::DashMap<Uuid, FunnelDefinition>,
+    events: UserEventLog,
+    segments: dashmap::DashMap<String, Vec<String>>,
 }
 
 impl FunnelAnalyzer {
     pub fn new() -> Self {
         Self {
             funnels: dashmap::DashMap::new(),
+            events: UserEventLog::default(),
+            segments: dashmap::DashMap::new(),
         }
     }
 
+    pub fn record_event(&self, event: UserEvent) {
+        self.events.record(event);
+    }
+
+    /// Record a measurement event; anonymous events are ignored.
+    pub fn record_measurement(&self, event: &MeasurementEvent) {
+        if let Some(event) = UserEvent::from_measurement(event) {
+            self.record_event(event);
+        }
+    }
+
+    /// Record a pipeline analytics event; anonymous events are ignored.
+    pub fn record_analytics(&self, event: &AnalyticsEvent) {
+        if let Some(event) = UserEvent::from_analytics(event) {
+            self.record_event(event);
+        }
+    }
+
+    pub fn set_user_segments(&self, user_id: &str, segments: Vec<String>) {
+        self.segments.insert(user_id.to_string(), segments);
+    }
+
     pub fn define_funnel(&self, funnel: FunnelDefinition) {
         self.funnels.insert(funnel.id, funnel);
     }
 
+    /// Every user with recorded events is in the population that can
+    /// enter the first step.
     pub fn analyze(&self, funnel_id: &Uuid) -> Option<FunnelResult> {
         let funnel = self.funnels.get(funnel_id)?;
-        let step_count = funnel.steps.len();
-        let mut steps = Vec::new();
-        let mut entered = 10000u64;
+        let timelines = self.events.timelines();
+        let progress: Vec<Progress> = timelines
+            .iter()
+            .map(|(_, timeline)| Self::progress(&funnel, timeline))
+            .collect();
+        Some(Self::summarize(&funnel, &progress))
+    }
+
+    /// The funnel computed separately for each group of users, ordered by
+    /// group key.
+    pub fn analyze_breakdown(
+        &self,
+        funnel_id: &Uuid,
+        breakdown: &FunnelBreakdown,
+    ) -> Option<Vec<FunnelBreakdownRow>> {
+        let funnel = self.funnels.get(funnel_id)?;
+        let timelines = self.events.timelines();
+        let mut groups: BTreeMap<String, Vec<Progress>> = BTreeMap::new();
+        for (user_id, timeline) in &timelines {
+            let progress = Self::progress(&funnel, timeline);
+            let keys: Vec<String> = match breakdown {
+                FunnelBreakdown::Channel => {
+                    vec![progress
+                        .entry
+                        .and_then(|e| e.channel.clone())
+                        .unwrap_or_default()]
+                }
+                FunnelBreakdown::Property(name) => vec![progress
+                    .entry
+                    .and_then(|e| e.properties.get(name))
+                    .map(|v| match v {
+                        serde_json::Value::String(s) => s.clone(),
+                        other => other.to_string(),
+                    })
+                    .unwrap_or_default()],
+                FunnelBreakdown::Segment => self
+                    .segments
+                    .get(user_id)
+                    .map(|s| s.clone())
+                    .unwrap_or_default(),
+            };
+            let mut keys: Vec<String> = keys.into_iter().filter(|k| !k.is_empty()).collect();
+            if keys.is_empty() {
+                keys.push(NO_VALUE.to_string());
+            }
+            let last = keys.pop().expect("at least one key");
+            for key in keys {
+                groups.entry(key).or_default().push(Progress {
+                    times: progress.times.clone(),
+                    entry: progress.entry,
+                });
+            }
+            groups.entry(last).or_default().push(progress);
+        }
+        Some(
+            groups
+                .into_iter()
+                .map(|(key, progress)| FunnelBreakdownRow {
+                    key,
+                    result: Self::summarize(&funnel, &progress),
+                })
+                .collect(),
+        )
+    }
+
+    /// The deepest progression reached from any first-step event.
+    fn progress<'a>(funnel: &FunnelDefinition, timeline: &'a [UserEvent]) -> Progress<'a> {
+        let mut best = Progress {
+            times: Vec::new(),
+            entry: None,
+        };
+        let Some(first) = funnel.steps.first() else {
+            return best;
+        };
+        let window = Duration::hours(funnel.conversion_window_hours as i64);
+
+        for (start, event) in timeline.iter().enumerate() {
+            if !event.matches(&first.event_name, &first.filters) {
+                continue;
+            }
+            let deadline = event.timestamp + window;
+            let mut times = vec![event.timestamp];
+            for candidate in &timeline[start + 1..] {
+                if times.len() == funnel.steps.len() || candidate.timestamp > deadline {
+                    break;
+                }
+                let step = &funnel.steps[times.len()];
+                if candidate.matches(&step.event_name, &step.filters) {
+                    times.push(candidate.timestamp);
+                } else if funnel.ordering == FunnelOrdering::Strict {
+                    break;
+                }
+            }
+            if times.len() > best.times.len() {
+                best = Progress {
+                    times,
+                    entry: Some(event),
+                };
+                if best.times.len() == funnel.steps.len() {
+                    break;
+                }
+            }
+        }
+        best
+    }
+
+    fn summarize(funnel: &FunnelDefinition, progress: &[Progress]) -> FunnelResult {
+        let mut steps = Vec::with_capacity(funnel.steps.len());
+        let mut entered = progress.len() as u64;
 
         for (i, step) in funnel.steps.iter().enumerate() {
-            let drop_rate = 0.2 + (i as f64 * 0.1);
-            let completed = (entered as f64 * (1.0 - drop_rate)) as u64;
-            let dropped = entered - completed;
+            let reached: Vec<&Progress> = progress.iter().filter(|p| p.times.len() > i).collect();
+            let completed = reached.len() as u64;
             let conversion_rate = if entered > 0 {
                 completed as f64 / entered as f64
             } else {
                 0.0
             };
+            let median_time_seconds = if i == 0 {
+                None
+            } else {
+                median_seconds(reached.iter().map(|p| p.times[i] - p.times[i - 1]))
+            };
             steps.push(FunnelStepResult {
                 step_name: step.name.clone(),
                 entered,
                 completed,
-                dropped_off: dropped,
+                dropped_off: entered - completed,
                 conversion_rate,
-                median_time_seconds: Some(30 * (i as u64 + 1)),
+                median_time_seconds,
             });
             entered = completed;
         }
 
-        let overall = if step_count > 0 && steps[0].entered > 0 {
-            steps.last().map_or(0.0, |s| s.completed as f64) / steps[0].entered as f64
-        } else {
-            0.0
+        let overall = match (steps.first(), steps.last()) {
+            (Some(first), Some(last)) if first.entered > 0 => {
+                last.completed as f64 / first.entered as f64
+            }
+            _ => 0.0,
         };
+        let n = funnel.steps.len();
+        let median_time_to_convert_seconds = median_seconds(
+            progress
+                .iter()
+                .filter(|p| n > 0 && p.times.len() == n)
+                .map(|p| p.times[n - 1] - p.times[0]),
+        );
 
-        Some(FunnelResult {
-            funnel_id: *funnel_id,
+        FunnelResult {
+            funnel_id: funnel.id,
             steps,
             overall_conversion_rate: overall,
-            median_time_to_convert_seconds: Some(120),
+            median_time_to_convert_seconds,
             computed_at: Utc::now(),
-        })
+        }
     }
 
     pub fn list_funnels(&self) -> Vec<FunnelDefinition> {
@@ -100,8 +296,145 @@ impl FunnelAnalyzer {
     }
 }
 
+/// Median of non-negative durations, in whole seconds.
+fn median_seconds(durations: impl Iterator<Item = Duration>) -> Option<u64> {
+    let mut seconds: Vec<u64> = durations.map(|d| d.num_seconds().max(0) as u64).collect();
+    if seconds.is_empty() {
+        return None;
+    }
+    seconds.sort_unstable();
+    let mid = seconds.len() / 2;
+    Some(if seconds.len().is_multiple_of(2) {
+        (seconds[mid - 1] + seconds[mid]) / 2
+    } else {
+        seconds[mid]
+    })
+}
+
 impl Default for FunnelAnalyzer {
     fn default() -> Self {
         Self::new()
     }
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::measurement::{EventSource, MeasurementEventType};
+    use std::collections::HashMap;
+
+    fn step(name: &str) -> FunnelStep {
+        FunnelStep {
+            name: name.to_string(),
+            event_name: name.to_string(),
+            filters: HashMap::new(),
+        }
+    }
+
+    fn measurement(
+        user: &str,
+        event_type: MeasurementEventType,
+        minutes: i64,
+        channel: &str,
+    ) -> MeasurementEvent {
+        MeasurementEvent {
+            event_id: Uuid::new_v4(),
+            event_type,
+            source: EventSource::ClientSdk,
+            timestamp: Utc::now() - Duration::days(3) + Duration::minutes(minutes),
+            activation_id: None,
+            decision_id: None,
+            campaign_id: None,
+            experiment_id: None,
+            variant_id: None,
+            user_id: Some(user.to_string()),
+            channel: Some(channel.to_string()),
+            dimensions: HashMap::new(),
+            metrics: HashMap::new(),
+        }
+    }
+
+    fn analyzer(ordering: FunnelOrdering) -> (FunnelAnalyzer, Uuid) {
+        use MeasurementEventType::*;
+        let analyzer = FunnelAnalyzer::new();
+        let funnel = FunnelDefinition {
+            id: Uuid::new_v4(),
+            name: "checkout".to_string(),
+            steps: vec![step("viewed"), step("clicked"), step("converted")],
+            conversion_window_hours: 24,
+            created_at: Utc::now(),
+            ordering,
+        };
+        let id = funnel.id;
+        analyzer.define_funnel(funnel);
+        for (user, event_type, minutes, channel) in [
+            ("u1", Viewed, 0, "email"),
+            ("u1", Custom("scrolled".to_string()), 5, "email"),
+            ("u1", Clicked, 10, "email"),
+            ("u1", Converted, 70, "email"),
+            ("u2", Viewed, 0, "email"),
+            ("u2", Clicked, 2, "email"),
+            ("u3", Clicked, 0, "push"),
+            ("u4", Viewed, 0, "push"),
+            ("u4", Clicked, 30 * 60, "push"),
+        ] {
+            analyzer.record_measurement(&measurement(user, event_type, minutes, channel));
+        }
+        let mut anonymous = measurement("", MeasurementEventType::Viewed, 0, "web");
+        anonymous.user_id = None;
+        analyzer.record_measurement(&anonymous);
+        (analyzer, id)
+    }
+
+    fn completed(result: &FunnelResult) -> Vec<u64> {
+        result.steps.iter().map(|s| s.completed).collect()
+    }
+
+    #[test]
+    fn test_relaxed_and_strict_ordering() {
+        let (relaxed, id) = analyzer(FunnelOrdering::Relaxed);
+        let result = relaxed.analyze(&id).unwrap();
+        assert_eq!(result.steps[0].entered, 4);
+        assert_eq!(completed(&result), [3, 2, 1]);
+        assert_eq!(result.steps[1].dropped_off, 1);
+        assert!((result.overall_conversion_rate - 0.25).abs() < 1e-9);
+        // Medians over u1 (10 min) and u2 (2 min) for the click step.
+        assert_eq!(result.steps[1].median_time_seconds, Some(360));
+        assert_eq!(result.median_time_to_convert_seconds, Some(70 * 60));
+
+        // The scroll between view and click breaks u1's strict path.
+        let (strict, id) = analyzer(FunnelOrdering::Strict);
+        let result = strict.analyze(&id).unwrap();
+        assert_eq!(completed(&result), [3, 1, 0]);
+        assert_eq!(result.median_time_to_convert_seconds, None);
+    }
+
+    #[test]
+    fn test_breakdowns() {
+        let (analyzer, id) = analyzer(FunnelOrdering::Relaxed);
+        let rows = analyzer
+            .analyze_breakdown(&id, &FunnelBreakdown::Channel)
+            .unwrap();
+        let keys: Vec<&str> = rows.iter().map(|r| r.key.as_str()).collect();
+        assert_eq!(keys, [NO_VALUE, "email", "push"]);
+        assert_eq!(completed(&rows[1].result), [2, 2, 1]);
+        assert_eq!(completed(&rows[2].result), [1, 0, 0]);
+
+        analyzer.set_user_segments("u1", vec!["vip".to_string(), "new".to_string()]);
+        analyzer.set_user_segments("u4", vec!["new".to_string()]);
+        let rows = analyzer
+            .analyze_breakdown(&id, &FunnelBreakdown::Segment)
+            .unwrap();
+        let by_key: HashMap<&str, Vec<u64>> = rows
+            .iter()
+            .map(|r| (r.key.as_str(), completed(&r.result)))
+            .collect();
+        assert_eq!(by_key["new"], [2, 1, 1]);
+        assert_eq!(by_key["vip"], [1, 1, 1]);
+        assert_eq!(by_key[NO_VALUE], [1, 1, 0]);
+    }
+}
@@ -5,14 +5,22 @@ pub mod attribution;
 pub mod budget;
 pub mod cohort;
 pub mod dashboard;
+pub mod events;
 pub mod funnel;
+pub mod live;
 pub mod measurement;
+pub mod pacing;
 pub mod report_builder;
+pub mod report_export;
+pub mod report_query;
+pub mod report_scheduler;
 
 pub use attribution::RevenueAttributionEngine;
 pub use budget::BudgetTracker;
 pub use cohort::CohortAnalyzer;
 pub use dashboard::CampaignDashboard;
 pub use funnel::FunnelAnalyzer;
+pub use live::LiveMetrics;
 pub use measurement::MeasurementEngine;
 pub use report_builder::ReportBuilder;
+pub use report_scheduler::ReportScheduler;
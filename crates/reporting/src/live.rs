@@ -0,0 +1,586 @@
+//! Live campaign metrics aggregated straight from the event stream.
+//!
+//! [`LiveMetrics`] receives events as an [`EventSink`] and keeps fine-grained
+//! buckets per campaign, channel and creative for a short retention period.
+//! [`LiveMetrics::spawn_pruner`] drops buckets of keys that went quiet.
+//! Tumbling and sliding windows, time series and latency percentiles are
+//! computed from those buckets on demand, so the dashboard and live charts
+//! move within a second of an event instead of waiting for the ClickHouse
+//! rollups. Unique user counts are not tracked here; use the rollups for
+//! those.
+
+use crate::dashboard::TimeSeriesPoint;
+use campaign_analytics::rollups::RollupMetrics;
+use campaign_analytics::RollupDimension;
+use campaign_core::event_bus::EventSink;
+use campaign_core::types::AnalyticsEvent;
+use chrono::{DateTime, Duration, DurationRound, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use tokio::task::JoinHandle;
+
+/// Latency buckets per doubling; bucket upper bounds are `2^(i/4)` µs.
+const LATENCY_BUCKETS_PER_DOUBLING: f64 = 4.0;
+/// Highest bucket, about 2^40 µs (twelve days).
+const MAX_LATENCY_BUCKET: u16 = 160;
+
+#[derive(Debug, Clone)]
+pub struct LiveConfig {
+    /// Width of the finest bucket; windows are aligned to it.
+    pub resolution: Duration,
+    /// History kept per key.
+    pub retention: Duration,
+}
+
+impl Default for LiveConfig {
+    fn default() -> Self {
+        Self {
+            resolution: Duration::seconds(1),
+            retention: Duration::hours(1),
+        }
+    }
+}
+
+/// Latency distribution in log-spaced buckets. Percentiles are reported as
+/// the upper bound of their bucket, at most ~19% above the true value, and
+/// histograms merge by adding counts.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct LatencyHistogram {
+    counts: BTreeMap<u16, u64>,
+    total: u64,
+}
+
+impl LatencyHistogram {
+    pub fn record(&mut self, latency_us: u64) {
+        let bucket = if latency_us <= 1 {
+            0
+        } else {
+            ((latency_us as f64).log2() * LATENCY_BUCKETS_PER_DOUBLING)
+                .ceil()
+                .min(MAX_LATENCY_BUCKET as f64) as u16
+        };
+        *self.counts.entry(bucket).or_default() += 1;
+        self.total += 1;
+    }
+
+    pub fn merge(&mut self, other: &LatencyHistogram) {
+        for (bucket, count) in &other.counts {
+            *self.counts.entry(*bucket).or_default() += count;
+        }
+        self.total += other.total;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.total
+    }
+
+    /// Latency at quantile `q` (0.0–1.0), or `None` if nothing was recorded.
+    pub fn percentile(&self, q: f64) -> Option<u64> {
+        if self.total == 0 {
+            return None;
+        }
+        let rank = ((q.clamp(0.0, 1.0) * self.total as f64).ceil() as u64).max(1);
+        let mut seen = 0;
+        for (bucket, count) in &self.counts {
+            seen += count;
+            if seen >= rank {
+                return Some(
+                    2f64.powf(*bucket as f64 / LATENCY_BUCKETS_PER_DOUBLING)
+                        .round() as u64,
+                );
+            }
+        }
+        None
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+struct LiveBucket {
+    metrics: RollupMetrics,
+    latency: LatencyHistogram,
+}
+
+/// Time range a live snapshot covers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum LiveWindow {
+    /// Back-to-back windows of `seconds` aligned to the epoch; the current
+    /// one is still filling.
+    Tumbling { seconds: i64 },
+    /// The trailing `seconds`, up to and including the current bucket.
+    Sliding { seconds: i64 },
+}
+
+impl LiveWindow {
+    pub fn seconds(&self) -> i64 {
+        match *self {
+            Self::Tumbling { seconds } | Self::Sliding { seconds } => seconds,
+        }
+    }
+
+    /// The same window, at least one second and at most `max` long.
+    pub fn clamped(self, max: Duration) -> Self {
+        let max = max.num_seconds().max(1);
+        match self {
+            Self::Tumbling { seconds } => Self::Tumbling {
+                seconds: seconds.clamp(1, max),
+            },
+            Self::Sliding { seconds } => Self::Sliding {
+                seconds: seconds.clamp(1, max),
+            },
+        }
+    }
+
+    /// `[start, end)` of the window containing `now`. Bounds saturate at the
+    /// representable range instead of overflowing.
+    pub fn bounds(
+        &self,
+        now: DateTime<Utc>,
+        resolution: Duration,
+    ) -> (DateTime<Utc>, DateTime<Utc>) {
+        let width = Duration::try_seconds(self.seconds().max(1)).unwrap_or(Duration::MAX);
+        match *self {
+            Self::Tumbling { .. } => {
+                let start = truncate(now, width);
+                (start, add(start, width))
+            }
+            Self::Sliding { .. } => {
+                let end = add(truncate(now, resolution), resolution);
+                let start = end
+                    .checked_sub_signed(width)
+                    .unwrap_or(DateTime::<Utc>::MIN_UTC);
+                (start, end)
+            }
+        }
+    }
+}
+
+/// Aggregated metrics for one key over one window.
+#[derive(Debug, Clone, Serialize)]
+pub struct LiveSnapshot {
+    pub dimension: RollupDimension,
+    pub key: String,
+    pub window_start: DateTime<Utc>,
+    pub window_end: DateTime<Utc>,
+    pub metrics: RollupMetrics,
+    pub latency_p50_us: Option<u64>,
+    pub latency_p95_us: Option<u64>,
+    pub latency_p99_us: Option<u64>,
+}
+
+/// Metric plotted by [`LiveMetrics::series`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LiveMetric {
+    Impressions,
+    Clicks,
+    Conversions,
+    Sends,
+    Spend,
+    Ctr,
+    LatencyP50,
+    LatencyP95,
+    LatencyP99,
+}
+
+impl LiveMetric {
+    pub fn value(&self, snapshot: &LiveSnapshot) -> f64 {
+        let m = &snapshot.metrics;
+        match self {
+            Self::Impressions => m.impressions as f64,
+            Self::Clicks => m.clicks as f64,
+            Self::Conversions => m.conversions as f64,
+            Self::Sends => m.sends as f64,
+            Self::Spend => m.spend,
+            Self::Ctr => m.ctr(),
+            Self::LatencyP50 => snapshot.latency_p50_us.unwrap_or(0) as f64,
+            Self::LatencyP95 => snapshot.latency_p95_us.unwrap_or(0) as f64,
+            Self::LatencyP99 => snapshot.latency_p99_us.unwrap_or(0) as f64,
+        }
+    }
+}
+
+/// Streaming aggregator over recent analytics events.
+pub struct LiveMetrics {
+    config: LiveConfig,
+    /// (dimension, key) -> bucket start -> bucket
+    series: DashMap<(RollupDimension, String), BTreeMap<DateTime<Utc>, LiveBucket>>,
+}
+
+fn truncate(at: DateTime<Utc>, width: Duration) -> DateTime<Utc> {
+    at.duration_trunc(width).unwrap_or(at)
+}
+
+fn add(at: DateTime<Utc>, width: Duration) -> DateTime<Utc> {
+    at.checked_add_signed(width)
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+impl LiveMetrics {
+    pub fn new() -> Self {
+        Self::with_config(LiveConfig::default())
+    }
+
+    pub fn with_config(config: LiveConfig) -> Self {
+        Self {
+            config,
+            series: DashMap::new(),
+        }
+    }
+
+    pub fn config(&self) -> &LiveConfig {
+        &self.config
+    }
+
+    /// Add an event to the buckets of every key it carries. Events older
+    /// than the retention period (relative to the newest bucket of the key)
+    /// are ignored.
+    pub fn ingest(&self, event: &AnalyticsEvent) {
+        let start = truncate(event.timestamp, self.config.resolution);
+        for dimension in RollupDimension::ALL {
+            let Some(key) = dimension.key_of(event) else {
+                continue;
+            };
+            let mut buckets = self.series.entry((dimension, key.to_string())).or_default();
+            let newest = buckets
+                .last_key_value()
+                .map_or(start, |(newest, _)| (*newest).max(start));
+            let cutoff = newest - self.config.retention;
+            if start < cutoff {
+                metrics::counter!("reporting.live.late_events").increment(1);
+                continue;
+            }
+
+            let bucket = buckets.entry(start).or_default();
+            bucket.metrics.record(event);
+            if let Some(latency) = event.total_latency_us {
+                bucket.latency.record(latency);
+            }
+            while buckets
+                .first_key_value()
+                .is_some_and(|(oldest, _)| *oldest < cutoff)
+            {
+                buckets.pop_first();
+            }
+        }
+    }
+
+    /// Drop buckets past retention and keys with nothing left, for keys that
+    /// stopped receiving events.
+    pub fn prune(&self, now: DateTime<Utc>) {
+        let cutoff = now - self.config.retention;
+        self.series.retain(|_, buckets| {
+            buckets.retain(|start, _| *start >= cutoff);
+            !buckets.is_empty()
+        });
+    }
+
+    /// Keys seen for a dimension, sorted.
+    pub fn keys(&self, dimension: RollupDimension) -> Vec<String> {
+        let mut keys: Vec<String> = self
+            .series
+            .iter()
+            .filter(|entry| entry.key().0 == dimension)
+            .map(|entry| entry.key().1.clone())
+            .collect();
+        keys.sort();
+        keys
+    }
+
+    pub fn snapshot(
+        &self,
+        dimension: RollupDimension,
+        key: &str,
+        window: LiveWindow,
+        now: DateTime<Utc>,
+    ) -> Option<LiveSnapshot> {
+        let (start, end) = self.bounds(window, now);
+        self.range(dimension, key, start, end)
+    }
+
+    /// Snapshots of every key of `dimension` with events in the window.
+    pub fn snapshots(
+        &self,
+        dimension: RollupDimension,
+        window: LiveWindow,
+        now: DateTime<Utc>,
+    ) -> Vec<LiveSnapshot> {
+        let (start, end) = self.bounds(window, now);
+        self.keys(dimension)
+            .iter()
+            .filter_map(|key| self.range(dimension, key, start, end))
+            .filter(|snapshot| snapshot.metrics != RollupMetrics::default())
+            .collect()
+    }
+
+    /// Windows longer than the retention period see only what is retained.
+    fn bounds(&self, window: LiveWindow, now: DateTime<Utc>) -> (DateTime<Utc>, DateTime<Utc>) {
+        window
+            .clamped(self.config.retention)
+            .bounds(now, self.config.resolution)
+    }
+
+    /// One point per `step` over `[from, to)`, including empty steps so
+    /// charts keep a steady x axis. History before the retention period is
+    /// not plotted.
+    pub fn series(
+        &self,
+        dimension: RollupDimension,
+        key: &str,
+        metric: LiveMetric,
+        step: Duration,
+        from: DateTime<Utc>,
+        to: DateTime<Utc>,
+    ) -> Vec<TimeSeriesPoint> {
+        let step = step.max(self.config.resolution);
+        let from = to
+            .checked_sub_signed(self.config.retention)
+            .map_or(from, |oldest| from.max(oldest));
+        let mut points = Vec::new();
+        let mut at = truncate(from, step);
+        while at < to {
+            let next = add(at, step);
+            let value = self
+                .range(dimension, key, at, next)
+                .map_or(0.0, |snapshot| metric.value(&snapshot));
+            points.push(TimeSeriesPoint {
+                timestamp: at,
+                value,
+                label: Some(key.to_string()),
+            });
+            if next == at {
+                break;
+            }
+            at = next;
+        }
+        points
+    }
+
+    fn range(
+        &self,
+        dimension: RollupDimension,
+        key: &str,
+        start: DateTime<Utc>,
+        end: DateTime<Utc>,
+    ) -> Option<LiveSnapshot> {
+        let buckets = self.series.get(&(dimension, key.to_string()))?;
+        let mut metrics = RollupMetrics::default();
+        let mut latency = LatencyHistogram::default();
+        for (_, bucket) in buckets.range(start..end) {
+            metrics.merge(&bucket.metrics);
+            latency.merge(&bucket.latency);
+        }
+        Some(LiveSnapshot {
+            dimension,
+            key: key.to_string(),
+            window_start: start,
+            window_end: end,
+            metrics,
+            latency_p50_us: latency.percentile(0.50),
+            latency_p95_us: latency.percentile(0.95),
+            latency_p99_us: latency.percentile(0.99),
+        })
+    }
+
+    /// Prune expired buckets every `tick`.
+    pub fn spawn_pruner(self: Arc<Self>, tick: std::time::Duration) -> JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                self.prune(Utc::now());
+            }
+        })
+    }
+}
+
+impl Default for LiveMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl EventSink for LiveMetrics {
+    fn emit(&self, event: AnalyticsEvent) {
+        self.ingest(&event);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_core::event_bus::make_event;
+    use campaign_core::types::EventType;
+    use chrono::TimeZone;
+
+    fn at(minute: u32, second: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 6, 1, 12, minute, second)
+            .unwrap()
+    }
+
+    fn event(event_type: EventType, channel: &str, timestamp: DateTime<Utc>) -> AnalyticsEvent {
+        let mut event = make_event(event_type, "req", None, None)
+            .with_campaign("camp-1")
+            .with_channel(channel);
+        event.timestamp = timestamp;
+        event
+    }
+
+    #[test]
+    fn test_windows_and_series() {
+        let live = LiveMetrics::new();
+        live.emit(event(EventType::Impression, "email", at(0, 10)));
+        live.emit(event(EventType::Impression, "email", at(0, 50)));
+        live.emit(event(EventType::Click, "email", at(1, 5)));
+        live.emit(event(EventType::Impression, "push", at(1, 20)));
+        let mut won = event(EventType::DspBidWon, "display", at(1, 30));
+        won.win_price = Some(1.5);
+        live.emit(won);
+
+        let now = at(1, 40);
+        let tumbling = live
+            .snapshot(
+                RollupDimension::Campaign,
+                "camp-1",
+                LiveWindow::Tumbling { seconds: 60 },
+                now,
+            )
+            .unwrap();
+        assert_eq!(tumbling.window_start, at(1, 0));
+        assert_eq!(tumbling.window_end, at(2, 0));
+        assert_eq!(
+            (tumbling.metrics.impressions, tumbling.metrics.clicks),
+            (1, 1)
+        );
+        assert_eq!(tumbling.metrics.spend, 1.5);
+
+        let sliding = live
+            .snapshot(
+                RollupDimension::Campaign,
+                "camp-1",
+                LiveWindow::Sliding { seconds: 60 },
+                now,
+            )
+            .unwrap();
+        assert_eq!(sliding.window_start, at(0, 41));
+        assert_eq!(
+            (sliding.metrics.impressions, sliding.metrics.clicks),
+            (2, 1)
+        );
+
+        let channels = live.snapshots(
+            RollupDimension::Channel,
+            LiveWindow::Sliding { seconds: 30 },
+            now,
+        );
+        let keys: Vec<&str> = channels.iter().map(|s| s.key.as_str()).collect();
+        assert_eq!(keys, ["display", "push"]);
+
+        let series = live.series(
+            RollupDimension::Channel,
+            "email",
+            LiveMetric::Impressions,
+            Duration::seconds(30),
+            at(0, 0),
+            at(1, 30),
+        );
+        let values: Vec<f64> = series.iter().map(|p| p.value).collect();
+        assert_eq!(values, [1.0, 1.0, 0.0]);
+
+        // Past retention relative to the newest bucket.
+        live.emit(event(
+            EventType::Impression,
+            "email",
+            at(0, 10) - Duration::hours(2),
+        ));
+        assert_eq!(
+            live.snapshot(
+                RollupDimension::Channel,
+                "email",
+                LiveWindow::Sliding { seconds: 10_800 },
+                now
+            )
+            .unwrap()
+            .metrics
+            .impressions,
+            2
+        );
+        live.prune(now + Duration::hours(2));
+        assert!(live.keys(RollupDimension::Campaign).is_empty());
+    }
+
+    #[test]
+    fn test_out_of_range_windows_saturate() {
+        let live = LiveMetrics::new();
+        live.emit(event(EventType::Impression, "email", at(0, 10)));
+        let now = at(0, 30);
+
+        for window in [
+            LiveWindow::Tumbling { seconds: i64::MAX },
+            LiveWindow::Sliding { seconds: i64::MAX },
+        ] {
+            let snapshot = live
+                .snapshot(RollupDimension::Channel, "email", window, now)
+                .unwrap();
+            assert_eq!(snapshot.metrics.impressions, 1);
+            assert!(snapshot.window_end - snapshot.window_start <= Duration::hours(1));
+        }
+        let shortest = live
+            .snapshot(
+                RollupDimension::Channel,
+                "email",
+                LiveWindow::Sliding { seconds: i64::MIN },
+                now,
+            )
+            .unwrap();
+        assert_eq!(
+            shortest.window_end - shortest.window_start,
+            Duration::seconds(1)
+        );
+
+        let (start, _) =
+            LiveWindow::Sliding { seconds: i64::MAX }.bounds(now, Duration::seconds(1));
+        assert_eq!(start, DateTime::<Utc>::MIN_UTC);
+        let series = live.series(
+            RollupDimension::Channel,
+            "email",
+            LiveMetric::Impressions,
+            Duration::MAX,
+            DateTime::<Utc>::MIN_UTC,
+            now,
+        );
+        assert_eq!(series.len(), 1);
+    }
+
+    #[test]
+    fn test_latency_percentiles() {
+        let live = LiveMetrics::new();
+        for latency in 1..=100u64 {
+            let mut e = event(EventType::BidResponse, "display", at(0, 0));
+            e.total_latency_us = Some(latency * 100);
+            live.ingest(&e);
+        }
+        let snapshot = live
+            .snapshot(
+                RollupDimension::Campaign,
+                "camp-1",
+                LiveWindow::Tumbling { seconds: 60 },
+                at(0, 30),
+            )
+            .unwrap();
+        let within = |actual: Option<u64>, expected: f64| {
+            let actual = actual.unwrap() as f64;
+            actual >= expected && actual <= expected * 1.19
+        };
+        assert!(within(snapshot.latency_p50_us, 5_000.0));
+        assert!(within(snapshot.latency_p95_us, 9_500.0));
+        assert!(within(snapshot.latency_p99_us, 9_900.0));
+
+        let mut empty = LatencyHistogram::default();
+        assert_eq!(empty.percentile(0.5), None);
+        empty.record(0);
+        assert_eq!(empty.percentile(0.99), Some(1));
+    }
+}
@@ -3,12 +3,27 @@
 //!
 //! Addresses FR-MSR-UNI-001 through FR-MSR-UNI-003.
 
-use chrono::{DateTime, Utc};
+use campaign_analytics::rollups::RollupMetrics;
+use campaign_analytics::{AnalyticsStore, RollupDimension, RollupGrain, RollupQuery};
+use campaign_core::event_envelope::{EnvelopeSink, EventEnvelope, EventPayload};
+use campaign_core::experiment_stats::{
+    cuped, msprt, probability_to_beat_control, probability_to_beat_control_normal,
+    sample_ratio_mismatch, SampleStats, SequentialConfig, SequentialResult, SequentialTest,
+};
+use campaign_core::types::EventType;
+use chrono::{DateTime, Duration, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::hash::{Hash, Hasher};
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::Arc;
 use tracing::info;
 use uuid::Uuid;
 
+/// Canonical event sources, shared with the event envelope.
+pub use campaign_core::event_envelope::EventSource;
+
 // ─── Standardized Event Schema (FR-MSR-UNI-001) ─────────────────────
 
 /// A standardized measurement event with consistent structure.
@@ -54,15 +69,96 @@ pub enum MeasurementEventType {
     Custom(String),
 }
 
-/// Where the event was generated.
-#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
-#[serde(rename_all = "snake_case")]
-pub enum EventSource {
-    BidEngine,
-    JourneyEngine,
-    DirectActivation,
-    ExternalWebhook,
-    ClientSdk,
+impl MeasurementEventType {
+    /// Standard measurement type of a platform event, or `None` for
+    /// operational events (bid requests, syncs, sessions, ...) that do not
+    /// feed cross-channel reporting. Sends, journey progress and SDK form
+    /// and custom events are kept as `Custom` with the event type's name.
+    pub fn from_event_type(event_type: EventType) -> Option<Self> {
+        use EventType::*;
+        Some(match event_type {
+            Impression | DcoImpression | LoyaltyOfferServed | WebPageView | ExperimentExposure => {
+                MeasurementEventType::Viewed
+            }
+            Click | DcoClick | LoyaltyOfferClicked | WebClick => MeasurementEventType::Clicked,
+            Conversion | DcoConversion | LoyaltyOfferRedeemed | ExperimentConversion => {
+                MeasurementEventType::Converted
+            }
+            ActivationDelivered | TemplateDelivered => MeasurementEventType::Delivered,
+            ActivationFailed => MeasurementEventType::Bounced,
+            JourneySuppressedBid => MeasurementEventType::Suppressed,
+            ExperimentAssignment => MeasurementEventType::ExperimentAssigned,
+            ActivationSent | JourneyEntered | JourneyStepCompleted | JourneyCompleted
+            | JourneyExited | WebFormSubmit | WebCustomEvent => MeasurementEventType::Custom(
+                serde_json::to_value(event_type)
+                    .ok()
+                    .and_then(|v| v.as_str().map(str::to_string))
+                    .unwrap_or_default(),
+            ),
+            _ => return None,
+        })
+    }
+}
+
+impl MeasurementEvent {
+    /// Adapt a canonical envelope. Returns `None` when the event type has no
+    /// measurement equivalent.
+    pub fn from_envelope(envelope: &EventEnvelope) -> Option<Self> {
+        let event_type = MeasurementEventType::from_event_type(envelope.event_type)?;
+        let mut event = Self {
+            event_id: envelope.event_id,
+            event_type,
+            source: envelope.source.clone(),
+            timestamp: envelope.occurred_at,
+            activation_id: None,
+            decision_id: None,
+            campaign_id: envelope.campaign_id.clone(),
+            experiment_id: None,
+            variant_id: None,
+            user_id: envelope.user_id.clone(),
+            channel: envelope.channel.clone(),
+            dimensions: std::collections::HashMap::new(),
+            metrics: std::collections::HashMap::new(),
+        };
+        event
+            .dimensions
+            .insert("family".to_string(), envelope.family().as_str().to_string());
+        if let Some(creative_id) = &envelope.creative_id {
+            event
+                .dimensions
+                .insert("creative_id".to_string(), creative_id.clone());
+        }
+
+        match &envelope.payload {
+            EventPayload::Bidding(p) => {
+                event.decision_id = Some(p.request_id.clone());
+                if let Some(price) = p.win_price {
+                    event.metrics.insert("cost".to_string(), price);
+                }
+            }
+            EventPayload::Engagement(p) => {
+                event.decision_id = Some(p.request_id.clone());
+                if let Some(price) = p.win_price {
+                    event.metrics.insert("cost".to_string(), price);
+                }
+            }
+            EventPayload::Delivery(p) => event.activation_id = Some(p.activation_id.clone()),
+            EventPayload::Experiment(p) => {
+                event.experiment_id = Uuid::parse_str(&p.experiment_id).ok();
+                event.variant_id = p
+                    .variant_id
+                    .as_deref()
+                    .and_then(|v| Uuid::parse_str(v).ok());
+            }
+            EventPayload::Creative(p) => {
+                event
+                    .dimensions
+                    .insert("template_id".to_string(), p.template_id.clone());
+            }
+            _ => {}
+        }
+        Some(event)
+    }
 }
 
 // ─── Cross-Channel Reporting (FR-MSR-UNI-002) ───────────────────────
@@ -136,6 +232,23 @@ pub struct TimeRange {
     pub end: DateTime<Utc>,
 }
 
+/// Longest time range a breakdown may cover.
+pub const MAX_BREAKDOWN_RANGE_DAYS: i64 = 366;
+
+impl TimeRange {
+    /// Reject ranges that end before they start or span more than
+    /// [`MAX_BREAKDOWN_RANGE_DAYS`].
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if self.end < self.start {
+            anyhow::bail!("time range ends before it starts");
+        }
+        if self.end - self.start > Duration::days(MAX_BREAKDOWN_RANGE_DAYS) {
+            anyhow::bail!("time range spans more than {MAX_BREAKDOWN_RANGE_DAYS} days");
+        }
+        Ok(())
+    }
+}
+
 /// A single row in the breakdown report.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReportRow {
@@ -165,6 +278,9 @@ pub struct ExperimentMeasurement {
     pub status: ExperimentMeasurementStatus,
     pub winner: Option<Uuid>,
     pub confidence_level: f64,
+    /// Impressions deviate from the split set by the variant weights.
+    #[serde(default)]
+    pub sample_ratio_mismatch: bool,
 }
 
 /// Measurement data for one experiment variant.
@@ -173,6 +289,9 @@ pub struct VariantMeasurement {
     pub variant_id: Uuid,
     pub variant_name: String,
     pub is_control: bool,
+    /// Share of traffic assigned to the variant, relative to the others.
+    #[serde(default = "default_variant_weight")]
+    pub weight: f64,
     pub sample_size: u64,
     pub deliveries: u64,
     pub impressions: u64,
@@ -182,6 +301,38 @@ pub struct VariantMeasurement {
     pub ctr: f64,
     pub conversion_rate: f64,
     pub lift_vs_control: Option<f64>,
+    /// Always-valid p-value of click-to-conversion rate against control.
+    #[serde(default)]
+    pub p_value: Option<f64>,
+    #[serde(default)]
+    pub probability_to_beat_control: Option<f64>,
+    #[serde(skip)]
+    sequential: SequentialTest,
+}
+
+fn default_variant_weight() -> f64 {
+    1.0
+}
+
+/// One unit's outcome in an experiment, with its pre-period value of the
+/// same metric for CUPED.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ExperimentObservation {
+    pub variant_id: Uuid,
+    pub value: f64,
+    pub pre_period_value: f64,
+}
+
+/// A continuous metric compared with control after CUPED adjustment.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CovariateAdjustedComparison {
+    pub variant_id: Uuid,
+    pub control_mean: f64,
+    pub variant_mean: f64,
+    pub theta: f64,
+    pub variance_reduction: f64,
+    pub test: SequentialResult,
+    pub probability_to_beat_control: f64,
 }
 
 /// Status of experiment measurement.
@@ -196,24 +347,282 @@ pub enum ExperimentMeasurementStatus {
 
 // ─── Measurement Engine ──────────────────────────────────────────────
 
+/// How long in-memory breakdown counts are kept by default.
+const DEFAULT_RETENTION_HOURS: i64 = 7 * 24;
+
+/// How far ahead of this node's clock an event may be stamped. Later ones
+/// are rejected so a bad timestamp cannot evict every real hour.
+const MAX_FUTURE_SKEW_SECS: i64 = 5 * 60;
+
+/// Distinct users a cell counts exactly before switching to a sketch.
+const USER_SKETCH_EXACT_LIMIT: usize = 256;
+
+/// HyperLogLog precision: 2^12 one-byte registers, about 1.6% error.
+const USER_SKETCH_PRECISION: u32 = 12;
+const USER_SKETCH_REGISTERS: usize = 1 << USER_SKETCH_PRECISION;
+
+/// Distinct users of a cell: exact while small, then a fixed-size
+/// HyperLogLog, so reach stays countable without keeping every user id.
+#[derive(Debug, Clone)]
+enum UserSketch {
+    Exact(HashSet<u64>),
+    Approximate(Box<[u8]>),
+}
+
+impl Default for UserSketch {
+    fn default() -> Self {
+        Self::Exact(HashSet::new())
+    }
+}
+
+impl UserSketch {
+    fn hash(user_id: &str) -> u64 {
+        let mut hasher = std::collections::hash_map::DefaultHasher::new();
+        user_id.hash(&mut hasher);
+        hasher.finish()
+    }
+
+    fn insert(&mut self, user_id: &str) {
+        self.insert_hash(Self::hash(user_id));
+    }
+
+    fn insert_hash(&mut self, hash: u64) {
+        match self {
+            Self::Exact(hashes) => {
+                hashes.insert(hash);
+                if hashes.len() > USER_SKETCH_EXACT_LIMIT {
+                    let mut registers = vec![0u8; USER_SKETCH_REGISTERS].into_boxed_slice();
+                    for hash in hashes.iter() {
+                        Self::observe(&mut registers, *hash);
+                    }
+                    *self = Self::Approximate(registers);
+                }
+            }
+            Self::Approximate(registers) => Self::observe(registers, hash),
+        }
+    }
+
+    fn observe(registers: &mut [u8], hash: u64) {
+        let index = (hash >> (64 - USER_SKETCH_PRECISION)) as usize;
+        let rest = hash << USER_SKETCH_PRECISION;
+        let rank = (rest.leading_zeros() + 1).min(64 - USER_SKETCH_PRECISION + 1) as u8;
+        registers[index] = registers[index].max(rank);
+    }
+
+    fn merge(&mut self, other: &Self) {
+        match other {
+            Self::Exact(hashes) => {
+                for hash in hashes {
+                    self.insert_hash(*hash);
+                }
+            }
+            Self::Approximate(theirs) => {
+                if let Self::Exact(hashes) = self {
+                    let mut registers = theirs.clone();
+                    for hash in hashes.iter() {
+                        Self::observe(&mut registers, *hash);
+                    }
+                    *self = Self::Approximate(registers);
+                } else if let Self::Approximate(ours) = self {
+                    for (ours, theirs) in ours.iter_mut().zip(theirs.iter()) {
+                        *ours = (*ours).max(*theirs);
+                    }
+                }
+            }
+        }
+    }
+
+    fn count(&self) -> f64 {
+        match self {
+            Self::Exact(hashes) => hashes.len() as f64,
+            Self::Approximate(registers) => {
+                let m = registers.len() as f64;
+                let alpha = 0.7213 / (1.0 + 1.079 / m);
+                let sum: f64 = registers.iter().map(|r| 2f64.powi(-(*r as i32))).sum();
+                let estimate = alpha * m * m / sum;
+                let zeros = registers.iter().filter(|r| **r == 0).count();
+                if estimate <= 2.5 * m && zeros > 0 {
+                    // Small-range correction (linear counting).
+                    (m * (m / zeros as f64).ln()).round()
+                } else {
+                    estimate.round()
+                }
+            }
+        }
+    }
+}
+
+/// The hour and breakdown dimensions an event's counts are folded under.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+struct CellKey {
+    hour: DateTime<Utc>,
+    channel: Option<String>,
+    campaign_id: Option<String>,
+    source: String,
+    experiment_id: Option<Uuid>,
+    variant_id: Option<Uuid>,
+    /// Sorted so equal maps give equal keys.
+    dimensions: Vec<(String, String)>,
+}
+
+impl CellKey {
+    fn of(event: &MeasurementEvent) -> Self {
+        let mut dimensions: Vec<(String, String)> = event
+            .dimensions
+            .iter()
+            .map(|(k, v)| (k.clone(), v.clone()))
+            .collect();
+        dimensions.sort();
+        Self {
+            hour: RollupGrain::Hourly.truncate(event.timestamp),
+            channel: event.channel.clone(),
+            campaign_id: event.campaign_id.clone(),
+            source: format!("{:?}", event.source),
+            experiment_id: event.experiment_id,
+            variant_id: event.variant_id,
+            dimensions,
+        }
+    }
+
+    fn value(&self, dimension: &BreakdownDimension) -> String {
+        match dimension {
+            BreakdownDimension::Channel => self.channel.clone().unwrap_or_default(),
+            BreakdownDimension::Campaign => self.campaign_id.clone().unwrap_or_default(),
+            BreakdownDimension::ActivationSource => self.source.clone(),
+            BreakdownDimension::Experiment => self
+                .experiment_id
+                .map(|id| id.to_string())
+                .unwrap_or_default(),
+            BreakdownDimension::Variant => {
+                self.variant_id.map(|id| id.to_string()).unwrap_or_default()
+            }
+            _ => {
+                let name = format!("{:?}", dimension);
+                self.dimensions
+                    .iter()
+                    .find(|(k, _)| *k == name)
+                    .map(|(_, v)| v.clone())
+                    .unwrap_or_default()
+            }
+        }
+    }
+}
+
+/// Counts for one [`CellKey`], or for a breakdown row merged from several.
+#[derive(Debug, Clone, Default)]
+struct MeasurementCell {
+    events: u64,
+    deliveries: u64,
+    impressions: u64,
+    clicks: u64,
+    conversions: u64,
+    revenue: f64,
+    users: UserSketch,
+}
+
+impl MeasurementCell {
+    fn add(&mut self, event: &MeasurementEvent) {
+        self.events += 1;
+        match event.event_type {
+            MeasurementEventType::Delivered => self.deliveries += 1,
+            MeasurementEventType::Viewed => self.impressions += 1,
+            MeasurementEventType::Clicked => self.clicks += 1,
+            MeasurementEventType::Converted => self.conversions += 1,
+            MeasurementEventType::Revenue => {
+                self.revenue += event.metrics.get("revenue").copied().unwrap_or_default()
+            }
+            _ => {}
+        }
+        if let Some(user_id) = &event.user_id {
+            self.users.insert(user_id);
+        }
+    }
+
+    fn merge(&mut self, other: &Self, with_users: bool) {
+        self.events += other.events;
+        self.deliveries += other.deliveries;
+        self.impressions += other.impressions;
+        self.clicks += other.clicks;
+        self.conversions += other.conversions;
+        self.revenue += other.revenue;
+        if with_users {
+            self.users.merge(&other.users);
+        }
+    }
+}
+
 /// Unified measurement engine for event collection, reporting, and experimentation.
+///
+/// Events are not kept: each one is folded into per-hour counts for its
+/// breakdown dimensions as it arrives, and hours older than the retention
+/// period (relative to the newest event) are evicted, so memory stays
+/// bounded under sustained traffic.
 pub struct MeasurementEngine {
-    events: DashMap<Uuid, MeasurementEvent>,
+    cells: DashMap<CellKey, MeasurementCell>,
+    /// Newest hour seen, as a Unix timestamp.
+    newest_hour: AtomicI64,
+    retention: Duration,
     experiments: DashMap<Uuid, ExperimentMeasurement>,
+    store: Option<Arc<dyn AnalyticsStore>>,
 }
 
 impl MeasurementEngine {
     pub fn new() -> Self {
         info!("Measurement engine initialized");
         Self {
-            events: DashMap::new(),
+            cells: DashMap::new(),
+            newest_hour: AtomicI64::new(i64::MIN),
+            retention: Duration::hours(DEFAULT_RETENTION_HOURS),
             experiments: DashMap::new(),
+            store: None,
         }
     }
 
-    /// Record a standardized measurement event.
+    /// Keep in-memory breakdown counts for `retention` (default seven days).
+    pub fn with_retention(mut self, retention: Duration) -> Self {
+        self.retention = retention;
+        self
+    }
+
+    /// Serve campaign and channel breakdowns from the analytics rollups.
+    pub fn with_store(mut self, store: Arc<dyn AnalyticsStore>) -> Self {
+        self.store = Some(store);
+        self
+    }
+
+    /// Record a standardized measurement event. Events older than the
+    /// retention period are counted as late and dropped, as are events
+    /// stamped more than a few minutes ahead of this node's clock.
     pub fn record_event(&self, event: MeasurementEvent) {
-        self.events.insert(event.event_id, event);
+        if event.timestamp > Utc::now() + Duration::seconds(MAX_FUTURE_SKEW_SECS) {
+            metrics::counter!("reporting.measurement.future_events").increment(1);
+            return;
+        }
+        let key = CellKey::of(&event);
+        let hour = key.hour.timestamp();
+        let retention = self.retention.num_seconds();
+        let newest = self.newest_hour.fetch_max(hour, Ordering::Relaxed);
+        if hour < newest.saturating_sub(retention) {
+            metrics::counter!("reporting.measurement.late_events").increment(1);
+            return;
+        }
+        self.cells.entry(key).or_default().add(&event);
+        if hour > newest {
+            let cutoff = hour.saturating_sub(retention);
+            self.cells.retain(|k, _| k.hour.timestamp() >= cutoff);
+        }
+    }
+
+    /// Record a canonical envelope, updating any registered experiment it
+    /// belongs to. Envelopes without a measurement equivalent are ignored.
+    pub fn record_envelope(&self, envelope: &EventEnvelope) {
+        let Some(event) = MeasurementEvent::from_envelope(envelope) else {
+            return;
+        };
+        if let (Some(experiment_id), Some(variant_id)) = (event.experiment_id, event.variant_id) {
+            self.record_experiment_event(&experiment_id, &variant_id, &event.event_type, 0.0);
+        }
+        self.record_event(event);
     }
 
     /// Create a convenience event with common fields.
@@ -243,74 +652,124 @@ impl MeasurementEngine {
             dimensions: std::collections::HashMap::new(),
             metrics,
         };
-        self.events.insert(event.event_id, event.clone());
+        self.record_event(event.clone());
         event
     }
 
-    /// Generate a cross-channel breakdown report.
-    pub fn breakdown(&self, request: &ReportingBreakdown) -> BreakdownReport {
-        // Collect matching events
-        let matching: Vec<MeasurementEvent> = self
-            .events
-            .iter()
-            .filter(|e| {
-                let ev = e.value();
-                ev.timestamp >= request.time_range.start && ev.timestamp <= request.time_range.end
-            })
-            .map(|e| e.value().clone())
-            .collect();
+    /// Cross-channel breakdown that reads from the rollups when it can: an
+    /// unfiltered breakdown by campaign or by channel whose metrics the
+    /// rollups carry. Those numbers are shared by every node and survive
+    /// restarts. Anything else is computed from this node's counts by
+    /// [`Self::breakdown`]. Fails if the time range is invalid.
+    pub async fn load_breakdown(
+        &self,
+        request: &ReportingBreakdown,
+    ) -> anyhow::Result<BreakdownReport> {
+        request.time_range.validate()?;
+        let dimension = match request.group_by.as_slice() {
+            [BreakdownDimension::Campaign] => RollupDimension::Campaign,
+            [BreakdownDimension::Channel] => RollupDimension::Channel,
+            _ => return Ok(self.breakdown(request)),
+        };
+        let Some(store) = &self.store else {
+            return Ok(self.breakdown(request));
+        };
+        let empty = RollupMetrics::default();
+        if !request.filters.is_empty()
+            || request
+                .metrics
+                .iter()
+                .any(|m| Self::rollup_metric(m, &empty).is_none())
+        {
+            return Ok(self.breakdown(request));
+        }
 
-        // Group by dimensions
-        let mut groups: std::collections::HashMap<String, Vec<&MeasurementEvent>> =
-            std::collections::HashMap::new();
+        let query = RollupQuery::new(
+            dimension,
+            RollupGrain::Hourly,
+            RollupGrain::Hourly.truncate(request.time_range.start),
+            request.time_range.end,
+        )
+        .totals();
+        let mut rows = Vec::new();
+        let mut totals: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
+        for row in store.rollups(&query).await? {
+            let mut metrics = std::collections::HashMap::new();
+            for metric in &request.metrics {
+                let value = Self::rollup_metric(metric, &row.metrics).unwrap_or_default();
+                metrics.insert(format!("{:?}", metric), value);
+                *totals.entry(format!("{:?}", metric)).or_insert(0.0) += value;
+            }
+            rows.push(ReportRow {
+                dimensions: std::collections::HashMap::from([(
+                    format!("{:?}", request.group_by[0]),
+                    row.key,
+                )]),
+                metrics,
+            });
+        }
 
-        // Collect references from matching
-        let event_refs: Vec<&MeasurementEvent> = matching.iter().collect();
+        Ok(BreakdownReport {
+            name: request.name.clone(),
+            rows,
+            totals,
+            generated_at: Utc::now(),
+        })
+    }
 
-        for event in &event_refs {
-            let key = request
-                .group_by
-                .iter()
-                .map(|dim| match dim {
-                    BreakdownDimension::Channel => event.channel.clone().unwrap_or_default(),
-                    BreakdownDimension::Campaign => event.campaign_id.clone().unwrap_or_default(),
-                    BreakdownDimension::ActivationSource => {
-                        format!("{:?}", event.source)
-                    }
-                    BreakdownDimension::Experiment => event
-                        .experiment_id
-                        .map(|id| id.to_string())
-                        .unwrap_or_default(),
-                    BreakdownDimension::Variant => event
-                        .variant_id
-                        .map(|id| id.to_string())
-                        .unwrap_or_default(),
-                    _ => event
-                        .dimensions
-                        .get(&format!("{:?}", dim))
-                        .cloned()
-                        .unwrap_or_default(),
-                })
-                .collect::<Vec<_>>()
-                .join("|");
+    /// A report metric from rollup counters, or `None` when the rollups do
+    /// not carry it (revenue is not in the event stream).
+    fn rollup_metric(metric: &ReportMetric, m: &RollupMetrics) -> Option<f64> {
+        Some(match metric {
+            ReportMetric::Deliveries => m.deliveries as f64,
+            ReportMetric::Impressions => m.impressions as f64,
+            ReportMetric::Clicks => m.clicks as f64,
+            ReportMetric::Conversions => m.conversions as f64,
+            ReportMetric::Ctr => m.ctr() * 100.0,
+            ReportMetric::ConversionRate => m.conversion_rate() * 100.0,
+            ReportMetric::CostPerConversion if m.conversions > 0 => m.spend / m.conversions as f64,
+            ReportMetric::CostPerConversion => 0.0,
+            ReportMetric::UniqueReach => m.unique_impressions as f64,
+            ReportMetric::Revenue | ReportMetric::Roas => return None,
+        })
+    }
+
+    /// Generate a cross-channel breakdown report from this node's counts.
+    /// Events are matched to the time range by the hour they fall in.
+    pub fn breakdown(&self, request: &ReportingBreakdown) -> BreakdownReport {
+        let start = RollupGrain::Hourly.truncate(request.time_range.start);
+        let with_users = request.metrics.contains(&ReportMetric::UniqueReach);
 
-            groups.entry(key).or_default().push(event);
+        // Merge the cells in range into one row per dimension combination.
+        let mut groups: std::collections::HashMap<Vec<String>, MeasurementCell> =
+            std::collections::HashMap::new();
+        for cell in self.cells.iter() {
+            let key = cell.key();
+            if key.hour < start || key.hour > request.time_range.end {
+                continue;
+            }
+            let values: Vec<String> = request.group_by.iter().map(|dim| key.value(dim)).collect();
+            groups
+                .entry(values)
+                .or_default()
+                .merge(cell.value(), with_users);
         }
 
         // Compute metrics for each group
         let mut rows = Vec::new();
         let mut totals: std::collections::HashMap<String, f64> = std::collections::HashMap::new();
 
-        for (key, events) in &groups {
+        for (values, counts) in &groups {
             let mut row_metrics = std::collections::HashMap::new();
-            let dims: Vec<&str> = key.split('|').collect();
-            let mut row_dims = std::collections::HashMap::new();
-            for (i, dim) in request.group_by.iter().enumerate() {
-                row_dims.insert(format!("{:?}", dim), dims.get(i).unwrap_or(&"").to_string());
-            }
+            let row_dims = request
+                .group_by
+                .iter()
+                .zip(values)
+                .map(|(dim, value)| (format!("{:?}", dim), value.clone()))
+                .collect();
 
             for metric in &request.metrics {
-                let val = self.compute_metric(metric, events);
+                let val = Self::compute_metric(metric, counts);
                 row_metrics.insert(format!("{:?}", metric), val);
                 *totals.entry(format!("{:?}", metric)).or_insert(0.0) += val;
             }
@@ -329,82 +788,41 @@ impl MeasurementEngine {
         }
     }
 
-    /// Compute a single metric from a set of events.
-    fn compute_metric(&self, metric: &ReportMetric, events: &[&MeasurementEvent]) -> f64 {
+    /// Compute a single metric from a group's counts.
+    fn compute_metric(metric: &ReportMetric, counts: &MeasurementCell) -> f64 {
         match metric {
-            ReportMetric::Deliveries => events
-                .iter()
-                .filter(|e| e.event_type == MeasurementEventType::Delivered)
-                .count() as f64,
-            ReportMetric::Impressions => events
-                .iter()
-                .filter(|e| e.event_type == MeasurementEventType::Viewed)
-                .count() as f64,
-            ReportMetric::Clicks => events
-                .iter()
-                .filter(|e| e.event_type == MeasurementEventType::Clicked)
-                .count() as f64,
-            ReportMetric::Conversions => events
-                .iter()
-                .filter(|e| e.event_type == MeasurementEventType::Converted)
-                .count() as f64,
-            ReportMetric::Revenue => events
-                .iter()
-                .filter(|e| e.event_type == MeasurementEventType::Revenue)
-                .filter_map(|e| e.metrics.get("revenue"))
-                .sum(),
-            ReportMetric::Ctr => {
-                let impressions = events
-                    .iter()
-                    .filter(|e| e.event_type == MeasurementEventType::Viewed)
-                    .count() as f64;
-                let clicks = events
-                    .iter()
-                    .filter(|e| e.event_type == MeasurementEventType::Clicked)
-                    .count() as f64;
-                if impressions > 0.0 {
-                    clicks / impressions * 100.0
-                } else {
-                    0.0
-                }
-            }
-            ReportMetric::ConversionRate => {
-                let clicks = events
-                    .iter()
-                    .filter(|e| e.event_type == MeasurementEventType::Clicked)
-                    .count() as f64;
-                let conversions = events
-                    .iter()
-                    .filter(|e| e.event_type == MeasurementEventType::Converted)
-                    .count() as f64;
-                if clicks > 0.0 {
-                    conversions / clicks * 100.0
-                } else {
-                    0.0
-                }
+            ReportMetric::Deliveries => counts.deliveries as f64,
+            ReportMetric::Impressions => counts.impressions as f64,
+            ReportMetric::Clicks => counts.clicks as f64,
+            ReportMetric::Conversions => counts.conversions as f64,
+            ReportMetric::Revenue => counts.revenue,
+            ReportMetric::Ctr if counts.impressions > 0 => {
+                counts.clicks as f64 / counts.impressions as f64 * 100.0
             }
-            ReportMetric::UniqueReach => {
-                let unique_users: std::collections::HashSet<_> =
-                    events.iter().filter_map(|e| e.user_id.as_ref()).collect();
-                unique_users.len() as f64
+            ReportMetric::ConversionRate if counts.clicks > 0 => {
+                counts.conversions as f64 / counts.clicks as f64 * 100.0
             }
+            ReportMetric::UniqueReach => counts.users.count(),
             _ => 0.0,
         }
     }
 
-    /// Register an experiment for unified measurement.
+    /// Register an experiment for unified measurement. Each variant is
+    /// `(id, name, is_control, weight)`; the weights are the intended
+    /// traffic split that sample-ratio checks compare against.
     pub fn register_experiment(
         &self,
         experiment_id: Uuid,
         name: &str,
-        variant_names: Vec<(Uuid, String, bool)>,
+        variant_names: Vec<(Uuid, String, bool, f64)>,
     ) -> ExperimentMeasurement {
         let variants: Vec<VariantMeasurement> = variant_names
             .into_iter()
-            .map(|(id, name, is_control)| VariantMeasurement {
+            .map(|(id, name, is_control, weight)| VariantMeasurement {
                 variant_id: id,
                 variant_name: name,
                 is_control,
+                weight,
                 sample_size: 0,
                 deliveries: 0,
                 impressions: 0,
@@ -414,6 +832,9 @@ impl MeasurementEngine {
                 ctr: 0.0,
                 conversion_rate: 0.0,
                 lift_vs_control: None,
+                p_value: None,
+                probability_to_beat_control: None,
+                sequential: SequentialTest::default(),
             })
             .collect();
 
@@ -426,6 +847,7 @@ impl MeasurementEngine {
             status: ExperimentMeasurementStatus::Collecting,
             winner: None,
             confidence_level: 0.0,
+            sample_ratio_mismatch: false,
         };
 
         self.experiments.insert(experiment_id, measurement.clone());
@@ -481,17 +903,114 @@ impl MeasurementEngine {
                         Some((variant.conversion_rate - control_cvr) / control_cvr * 100.0);
                 }
             }
+
+            Self::update_significance(&mut exp);
         }
     }
 
+    /// Re-run each variant's sequential test against control and settle the
+    /// winner. The tests are always valid, so checking after every event
+    /// does not inflate false positives.
+    fn update_significance(exp: &mut ExperimentMeasurement) {
+        let Some(control) = exp.variants.iter().find(|v| v.is_control) else {
+            return;
+        };
+        let control_counts = (control.conversions, control.clicks);
+        let control_stats = SampleStats::from_conversions(control.conversions, control.clicks);
+
+        let impressions: Vec<u64> = exp.variants.iter().map(|v| v.impressions).collect();
+        let weights: Vec<f64> = exp.variants.iter().map(|v| v.weight).collect();
+        exp.sample_ratio_mismatch = sample_ratio_mismatch(&impressions, &weights).mismatch;
+
+        let mut best: Option<(Uuid, f64)> = None;
+        for variant in exp.variants.iter_mut().filter(|v| !v.is_control) {
+            if control_counts.1 == 0 || variant.clicks == 0 {
+                continue;
+            }
+            let stats = SampleStats::from_conversions(variant.conversions, variant.clicks);
+            let result = variant.sequential.observe(&control_stats, &stats);
+            variant.p_value = Some(result.p_value);
+            variant.probability_to_beat_control = Some(probability_to_beat_control(
+                control_counts,
+                (variant.conversions, variant.clicks),
+            ));
+            if result.significant && result.difference > 0.0 {
+                let lift = result.relative_lift.unwrap_or(f64::INFINITY);
+                if best.is_none_or(|(_, l)| lift > l) {
+                    best = Some((variant.variant_id, lift));
+                }
+            }
+        }
+
+        exp.confidence_level = exp
+            .variants
+            .iter()
+            .filter_map(|v| v.p_value)
+            .map(|p| 1.0 - p)
+            .fold(0.0, f64::max);
+        match best {
+            _ if exp.sample_ratio_mismatch => {
+                exp.winner = None;
+                exp.status = ExperimentMeasurementStatus::Inconclusive;
+            }
+            Some((winner, _)) => {
+                exp.winner = Some(winner);
+                exp.status = ExperimentMeasurementStatus::SignificanceReached;
+            }
+            None => exp.status = ExperimentMeasurementStatus::Collecting,
+        }
+    }
+
+    /// Compare a continuous per-unit metric (e.g. revenue per user) across
+    /// variants, reducing variance with each unit's pre-period value
+    /// (CUPED). Returns one comparison per non-control variant.
+    pub fn analyze_with_covariates(
+        &self,
+        experiment_id: &Uuid,
+        observations: &[ExperimentObservation],
+        config: &SequentialConfig,
+    ) -> Option<Vec<CovariateAdjustedComparison>> {
+        let exp = self.experiments.get(experiment_id)?;
+        let control_id = exp.variants.iter().find(|v| v.is_control)?.variant_id;
+        let arm = |id: Uuid| -> Vec<(f64, f64)> {
+            observations
+                .iter()
+                .filter(|o| o.variant_id == id)
+                .map(|o| (o.value, o.pre_period_value))
+                .collect()
+        };
+        let control = arm(control_id);
+        Some(
+            exp.variants
+                .iter()
+                .filter(|v| !v.is_control)
+                .map(|v| {
+                    let adjusted = cuped(&control, &arm(v.variant_id));
+                    CovariateAdjustedComparison {
+                        variant_id: v.variant_id,
+                        control_mean: adjusted.control.mean,
+                        variant_mean: adjusted.treatment.mean,
+                        theta: adjusted.theta,
+                        variance_reduction: adjusted.variance_reduction,
+                        test: msprt(&adjusted.control, &adjusted.treatment, config),
+                        probability_to_beat_control: probability_to_beat_control_normal(
+                            &adjusted.control,
+                            &adjusted.treatment,
+                        ),
+                    }
+                })
+                .collect(),
+        )
+    }
+
     /// Get experiment measurement.
     pub fn get_experiment(&self, experiment_id: &Uuid) -> Option<ExperimentMeasurement> {
         self.experiments.get(experiment_id).map(|e| e.clone())
     }
 
-    /// Get total event count.
+    /// Number of events counted within the retention period.
     pub fn event_count(&self) -> usize {
-        self.events.len()
+        self.cells.iter().map(|cell| cell.events).sum::<u64>() as usize
     }
 }
 
@@ -501,12 +1020,17 @@ impl Default for MeasurementEngine {
     }
 }
 
+impl EnvelopeSink for MeasurementEngine {
+    fn publish(&self, envelope: &EventEnvelope) {
+        self.record_envelope(envelope);
+    }
+}
+
 // ─── Tests ───────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use chrono::Duration;
 
     #[test]
     fn test_emit_and_count_events() {
@@ -598,6 +1122,196 @@ mod tests {
         assert_eq!(report.rows.len(), 2); // email + push
     }
 
+    #[test]
+    fn test_events_are_aggregated_and_evicted_by_age() {
+        let engine = MeasurementEngine::new().with_retention(Duration::hours(2));
+        let emit = |user: &str| {
+            engine.emit(
+                MeasurementEventType::Delivered,
+                EventSource::BidEngine,
+                "email",
+                Some(user),
+                None,
+                Some("camp_1"),
+                std::collections::HashMap::new(),
+            )
+        };
+        let event = emit("user_0");
+        for n in 1..100 {
+            emit(&format!("user_{}", n % 10));
+        }
+        // One hour and one set of dimensions: one cell, not 100 events.
+        assert_eq!(engine.cells.len(), 1);
+        assert_eq!(engine.event_count(), 100);
+
+        let report = engine.breakdown(&ReportingBreakdown {
+            name: "Reach".to_string(),
+            group_by: vec![BreakdownDimension::Campaign],
+            metrics: vec![ReportMetric::Deliveries, ReportMetric::UniqueReach],
+            filters: vec![],
+            time_range: TimeRange {
+                start: Utc::now() - Duration::hours(1),
+                end: Utc::now() + Duration::hours(1),
+            },
+        });
+        assert_eq!(report.totals["Deliveries"], 100.0);
+        assert_eq!(report.totals["UniqueReach"], 10.0);
+
+        // Past retention: dropped on arrival.
+        let mut late = event.clone();
+        late.timestamp = event.timestamp - Duration::hours(3);
+        engine.record_event(late);
+        assert_eq!(engine.event_count(), 100);
+
+        // An hour three hours back is evicted once a current one arrives.
+        let engine = MeasurementEngine::new().with_retention(Duration::hours(2));
+        let mut earlier = event.clone();
+        earlier.timestamp = event.timestamp - Duration::hours(3);
+        engine.record_event(earlier);
+        assert_eq!(engine.event_count(), 1);
+        engine.record_event(event);
+        assert_eq!(engine.event_count(), 1);
+        assert_eq!(engine.cells.len(), 1);
+        assert_eq!(
+            engine.cells.iter().next().unwrap().key().hour,
+            RollupGrain::Hourly.truncate(Utc::now())
+        );
+    }
+
+    #[test]
+    fn test_future_events_are_rejected() {
+        let engine = MeasurementEngine::new().with_retention(Duration::hours(2));
+        let event = engine.emit(
+            MeasurementEventType::Delivered,
+            EventSource::BidEngine,
+            "email",
+            Some("user_0"),
+            None,
+            Some("camp_1"),
+            std::collections::HashMap::new(),
+        );
+
+        // A clock running a day fast must not evict the real hours.
+        let mut future = event.clone();
+        future.timestamp = event.timestamp + Duration::days(1);
+        engine.record_event(future);
+        assert_eq!(engine.event_count(), 1);
+        engine.record_event(event);
+        assert_eq!(engine.event_count(), 2);
+
+        // Small skew is still accepted.
+        let mut skewed = engine.emit(
+            MeasurementEventType::Delivered,
+            EventSource::BidEngine,
+            "email",
+            Some("user_1"),
+            None,
+            Some("camp_1"),
+            std::collections::HashMap::new(),
+        );
+        skewed.timestamp += Duration::seconds(30);
+        engine.record_event(skewed);
+        assert_eq!(engine.event_count(), 4);
+    }
+
+    #[test]
+    fn test_unique_reach_is_sketched_past_the_exact_limit() {
+        let engine = MeasurementEngine::new();
+        for n in 0..20_000 {
+            let channel = if n % 2 == 0 { "email" } else { "push" };
+            engine.emit(
+                MeasurementEventType::Delivered,
+                EventSource::BidEngine,
+                channel,
+                Some(&format!("user_{}", n % 10_000)),
+                None,
+                Some("camp_1"),
+                std::collections::HashMap::new(),
+            );
+        }
+        // Each cell holds a fixed-size sketch, not 5,000 user ids.
+        for cell in engine.cells.iter() {
+            match &cell.users {
+                UserSketch::Approximate(registers) => {
+                    assert_eq!(registers.len(), USER_SKETCH_REGISTERS)
+                }
+                UserSketch::Exact(_) => panic!("cell kept exact users past the limit"),
+            }
+        }
+
+        let report = engine.breakdown(&ReportingBreakdown {
+            name: "Reach".to_string(),
+            group_by: vec![BreakdownDimension::Campaign],
+            metrics: vec![ReportMetric::UniqueReach],
+            filters: vec![],
+            time_range: TimeRange {
+                start: Utc::now() - Duration::hours(1),
+                end: Utc::now() + Duration::hours(1),
+            },
+        });
+        // Users 0..10_000 split evenly across the two channel cells; merging
+        // them recovers all 10,000 within the sketch's error.
+        let reach = report.totals["UniqueReach"];
+        assert!((reach - 10_000.0).abs() < 500.0, "reach {reach}");
+    }
+
+    #[tokio::test]
+    async fn test_breakdown_reads_rollups_when_it_can() {
+        use campaign_analytics::InMemoryAnalyticsStore;
+        use campaign_core::event_bus::{make_event, EventSink};
+
+        let store = Arc::new(InMemoryAnalyticsStore::new());
+        for (event_type, channel) in [
+            (EventType::ActivationDelivered, "email"),
+            (EventType::ActivationDelivered, "email"),
+            (EventType::Impression, "email"),
+            (EventType::ActivationDelivered, "sms"),
+        ] {
+            store.emit(
+                make_event(event_type, "req", Some("u1".into()), None)
+                    .with_campaign("camp_1")
+                    .with_channel(channel),
+            );
+        }
+        // Only this node's in-memory copy has the push delivery.
+        let engine = MeasurementEngine::new().with_store(store);
+        engine.emit(
+            MeasurementEventType::Delivered,
+            EventSource::JourneyEngine,
+            "push",
+            None,
+            None,
+            Some("camp_1"),
+            std::collections::HashMap::new(),
+        );
+
+        let mut request = ReportingBreakdown {
+            name: "Channel Performance".to_string(),
+            group_by: vec![BreakdownDimension::Channel],
+            metrics: vec![ReportMetric::Deliveries, ReportMetric::Impressions],
+            filters: vec![],
+            time_range: TimeRange {
+                start: Utc::now() - Duration::hours(1),
+                end: Utc::now() + Duration::hours(1),
+            },
+        };
+        let report = engine.load_breakdown(&request).await.unwrap();
+        assert_eq!(report.rows.len(), 2);
+        assert_eq!(report.totals["Deliveries"], 3.0);
+        assert_eq!(report.totals["Impressions"], 1.0);
+
+        // Revenue is not in the rollups, so this node's events answer.
+        request.metrics.push(ReportMetric::Revenue);
+        let report = engine.load_breakdown(&request).await.unwrap();
+        assert_eq!(report.rows.len(), 1);
+        assert_eq!(report.totals["Deliveries"], 1.0);
+
+        request.time_range.start = request.time_range.end - Duration::days(400);
+        assert!(engine.load_breakdown(&request).await.is_err());
+        request.time_range.start = request.time_range.end + Duration::hours(1);
+        assert!(engine.load_breakdown(&request).await.is_err());
+    }
+
     #[test]
     fn test_experiment_measurement() {
         let engine = MeasurementEngine::new();
@@ -609,8 +1323,8 @@ mod tests {
             exp_id,
             "Homepage CTA Test",
             vec![
-                (control_id, "Control".to_string(), true),
-                (treatment_id, "Treatment A".to_string(), false),
+                (control_id, "Control".to_string(), true, 1.0),
+                (treatment_id, "Treatment A".to_string(), false, 1.0),
             ],
         );
 
@@ -675,6 +1389,101 @@ mod tests {
         assert_eq!(treatment.clicks, 15);
         assert!(treatment.ctr > control.ctr);
         assert!(treatment.lift_vs_control.unwrap() > 0.0);
+        // 2/10 vs 5/15 conversions is far too little data to call.
+        assert!(treatment.p_value.unwrap() > 0.05);
+        assert!(treatment.probability_to_beat_control.unwrap() > 0.5);
+        assert_eq!(exp.status, ExperimentMeasurementStatus::Collecting);
+        assert!(exp.winner.is_none());
+    }
+
+    #[test]
+    fn test_sample_ratio_uses_variant_weights() {
+        let engine = MeasurementEngine::new();
+        let (control, treatment) = (Uuid::new_v4(), Uuid::new_v4());
+        let register = |weights: (f64, f64)| {
+            let id = Uuid::new_v4();
+            engine.register_experiment(
+                id,
+                "90/10 rollout",
+                vec![
+                    (control, "Control".to_string(), true, weights.0),
+                    (treatment, "Rollout".to_string(), false, weights.1),
+                ],
+            );
+            for (variant, views) in [(&control, 900), (&treatment, 100)] {
+                for _ in 0..views {
+                    engine.record_experiment_event(
+                        &id,
+                        variant,
+                        &MeasurementEventType::Viewed,
+                        0.0,
+                    );
+                }
+            }
+            engine.get_experiment(&id).unwrap()
+        };
+
+        assert!(!register((9.0, 1.0)).sample_ratio_mismatch);
+        let even = register((1.0, 1.0));
+        assert!(even.sample_ratio_mismatch);
+        assert_eq!(even.status, ExperimentMeasurementStatus::Inconclusive);
+    }
+
+    #[test]
+    fn test_experiment_significance_and_cuped() {
+        let engine = MeasurementEngine::new();
+        let exp_id = Uuid::new_v4();
+        let control_id = Uuid::new_v4();
+        let treatment_id = Uuid::new_v4();
+        engine.register_experiment(
+            exp_id,
+            "Checkout redesign",
+            vec![
+                (control_id, "Control".to_string(), true, 1.0),
+                (treatment_id, "Redesign".to_string(), false, 1.0),
+            ],
+        );
+        for (variant, conversions) in [(&control_id, 100), (&treatment_id, 200)] {
+            for (event, count) in [
+                (MeasurementEventType::Viewed, 2000),
+                (MeasurementEventType::Clicked, 1000),
+                (MeasurementEventType::Converted, conversions),
+            ] {
+                for _ in 0..count {
+                    engine.record_experiment_event(&exp_id, variant, &event, 20.0);
+                }
+            }
+        }
+        let exp = engine.get_experiment(&exp_id).unwrap();
+        assert_eq!(exp.status, ExperimentMeasurementStatus::SignificanceReached);
+        assert_eq!(exp.winner, Some(treatment_id));
+        assert!(exp.confidence_level > 0.95);
+        assert!(!exp.sample_ratio_mismatch);
+
+        // Spend per user tracks last month's spend closely; the treatment
+        // adds 2 on top of it.
+        let observations: Vec<ExperimentObservation> = (0..400)
+            .map(|i| {
+                let pre = (i % 40) as f64 * 5.0;
+                let treated = i % 2 == 1;
+                ExperimentObservation {
+                    variant_id: if treated { treatment_id } else { control_id },
+                    value: pre + ((i * 37) % 7) as f64 - 3.0 + if treated { 2.0 } else { 0.0 },
+                    pre_period_value: pre,
+                }
+            })
+            .collect();
+        let comparisons = engine
+            .analyze_with_covariates(&exp_id, &observations, &SequentialConfig::default())
+            .unwrap();
+        assert_eq!(comparisons.len(), 1);
+        let comparison = &comparisons[0];
+        assert!(comparison.variance_reduction > 0.9);
+        assert!((comparison.test.difference - 2.0).abs() < 1.0);
+        assert!(comparison.probability_to_beat_control > 0.99);
+        assert!(engine
+            .analyze_with_covariates(&Uuid::new_v4(), &observations, &SequentialConfig::default())
+            .is_none());
     }
 
     #[test]
@@ -719,4 +1528,89 @@ mod tests {
         let total_revenue = report.totals.get("Revenue").copied().unwrap_or(0.0);
         assert!((total_revenue - 149.98).abs() < 0.01);
     }
+
+    #[test]
+    fn test_breakdown_from_envelopes_covers_every_producer() {
+        use campaign_core::event_bus::make_event;
+
+        let engine = MeasurementEngine::new();
+        let events = vec![
+            make_event(EventType::Impression, "req-1", Some("user_1".into()), None),
+            make_event(EventType::Click, "req-1", Some("user_1".into()), None),
+            make_event(EventType::BidRequest, "req-2", None, None),
+            make_event(EventType::ActivationDelivered, "act-1", None, None).with_channel("email"),
+            make_event(EventType::ActivationDelivered, "msg-1", None, None).with_channel("sms"),
+            make_event(EventType::ActivationFailed, "msg-2", None, None).with_channel("sms"),
+            make_event(EventType::JourneySuppressedBid, "inst-1", None, None)
+                .with_channel("journey"),
+            make_event(EventType::WebPageView, "sdk-1", Some("user_2".into()), None),
+        ];
+        for event in &events {
+            engine.publish(&EventEnvelope::from_analytics(event));
+        }
+        // The bid request is operational and not recorded.
+        assert_eq!(engine.event_count(), 7);
+
+        let report = engine.breakdown(&ReportingBreakdown {
+            name: "All producers".to_string(),
+            group_by: vec![BreakdownDimension::Channel],
+            metrics: vec![
+                ReportMetric::Deliveries,
+                ReportMetric::Impressions,
+                ReportMetric::Clicks,
+            ],
+            filters: vec![],
+            time_range: TimeRange {
+                start: Utc::now() - Duration::hours(1),
+                end: Utc::now() + Duration::hours(1),
+            },
+        });
+        let row = |channel: &str| {
+            report
+                .rows
+                .iter()
+                .find(|r| r.dimensions["Channel"] == channel)
+                .unwrap()
+                .metrics
+                .clone()
+        };
+        assert_eq!(report.rows.len(), 5);
+        assert_eq!(row("programmatic")["Impressions"], 1.0);
+        assert_eq!(row("programmatic")["Clicks"], 1.0);
+        assert_eq!(row("email")["Deliveries"], 1.0);
+        assert_eq!(row("sms")["Deliveries"], 1.0);
+        assert_eq!(row("web")["Impressions"], 1.0);
+    }
+
+    #[test]
+    fn test_envelope_updates_registered_experiment() {
+        use campaign_core::event_bus::make_event;
+
+        let engine = MeasurementEngine::new();
+        let control = Uuid::new_v4();
+        let treatment = Uuid::new_v4();
+        let experiment = engine.register_experiment(
+            Uuid::new_v4(),
+            "Subject line",
+            vec![
+                (control, "control".to_string(), true, 1.0),
+                (treatment, "treatment".to_string(), false, 1.0),
+            ],
+        );
+
+        engine.publish(&EventEnvelope::from_analytics(&make_event(
+            EventType::ExperimentExposure,
+            experiment.experiment_id.to_string(),
+            Some("user_1".into()),
+            Some(treatment.to_string()),
+        )));
+
+        let measured = engine.get_experiment(&experiment.experiment_id).unwrap();
+        let variant = measured
+            .variants
+            .iter()
+            .find(|v| v.variant_id == treatment)
+            .unwrap();
+        assert_eq!(variant.impressions, 1);
+    }
 }
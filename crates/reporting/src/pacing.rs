@@ -0,0 +1,388 @@
+//! Predictive budget pacing: intraday traffic curves, a PID bid-throttle
+//! controller and end-of-flight spend forecasting.
+//!
+//! [`BudgetTracker`](crate::budget::BudgetTracker) owns per-campaign state
+//! and feeds recorded spend into the types here.
+
+use chrono::{DateTime, Duration, NaiveTime, Timelike, Utc};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Share of a day's traffic arriving in each UTC hour. Pacing targets spend
+/// against this curve so a campaign is not "behind" at 03:00 just because
+/// few users are online.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct IntradayCurve {
+    hourly: [f64; 24],
+}
+
+impl IntradayCurve {
+    /// Uniform traffic across the day.
+    pub fn flat() -> Self {
+        Self {
+            hourly: [1.0 / 24.0; 24],
+        }
+    }
+
+    /// Build from observed volume (requests, impressions, ...) per UTC hour.
+    /// Falls back to a flat curve when there is no volume.
+    pub fn from_hourly_volume(volume: [f64; 24]) -> Self {
+        let total: f64 = volume.iter().map(|v| v.max(0.0)).sum();
+        if total <= 0.0 {
+            return Self::flat();
+        }
+        Self {
+            hourly: volume.map(|v| v.max(0.0) / total),
+        }
+    }
+
+    pub fn hourly_shares(&self) -> &[f64; 24] {
+        &self.hourly
+    }
+
+    /// Fraction of the day's traffic that has arrived by `at`, interpolating
+    /// linearly within the current hour.
+    pub fn elapsed_fraction(&self, at: DateTime<Utc>) -> f64 {
+        let hour = at.hour() as usize;
+        let within = (at.minute() * 60 + at.second()) as f64 / 3600.0;
+        let before: f64 = self.hourly[..hour].iter().sum();
+        (before + self.hourly[hour] * within).clamp(0.0, 1.0)
+    }
+}
+
+impl Default for IntradayCurve {
+    fn default() -> Self {
+        Self::flat()
+    }
+}
+
+/// Gains and limits for the pacing PID controller. The controller's error
+/// is the spend shortfall against the intraday target as a fraction of the
+/// day's target, so gains are independent of budget size.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacingConfig {
+    pub kp: f64,
+    pub ki: f64,
+    pub kd: f64,
+    /// Bound on the integral term (in error-hours) to prevent wind-up.
+    pub integral_limit: f64,
+    pub min_multiplier: f64,
+    pub max_multiplier: f64,
+}
+
+impl Default for PacingConfig {
+    fn default() -> Self {
+        Self {
+            kp: 1.0,
+            ki: 0.1,
+            kd: 0.05,
+            integral_limit: 5.0,
+            min_multiplier: 0.1,
+            max_multiplier: 2.0,
+        }
+    }
+}
+
+/// Controller memory for one campaign. Reset at the start of each day,
+/// since each day's target is independent.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct PidState {
+    pub integral: f64,
+    pub last_error: Option<f64>,
+    pub last_update: Option<DateTime<Utc>>,
+}
+
+impl PidState {
+    /// Advance the controller with a new error reading and return the bid
+    /// multiplier (1.0 = bid as planned).
+    pub fn update(&mut self, config: &PacingConfig, error: f64, now: DateTime<Utc>) -> f64 {
+        if self
+            .last_update
+            .is_some_and(|last| last.date_naive() != now.date_naive())
+        {
+            *self = Self::default();
+        }
+        let dt_hours = self
+            .last_update
+            .map(|last| (now - last).num_milliseconds().max(0) as f64 / 3_600_000.0)
+            .unwrap_or(0.0);
+
+        self.integral =
+            (self.integral + error * dt_hours).clamp(-config.integral_limit, config.integral_limit);
+        let derivative = match self.last_error {
+            Some(last) if dt_hours > 0.0 => (error - last) / dt_hours,
+            _ => 0.0,
+        };
+        self.last_error = Some(error);
+        self.last_update = Some(now);
+
+        (1.0 + config.kp * error + config.ki * self.integral + config.kd * derivative)
+            .clamp(config.min_multiplier, config.max_multiplier)
+    }
+}
+
+/// Output of one pacing step for a campaign.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PacingDecision {
+    pub campaign_id: Uuid,
+    /// Multiply bids (or admission probability) by this value. Zero when
+    /// the campaign is outside its flight or today's target is met.
+    pub bid_multiplier: f64,
+    /// Today's spend target: the daily budget, or less when the remaining
+    /// budget spread over the remaining days is smaller.
+    pub target_spend_today: f64,
+    /// Portion of today's target the traffic curve expects by now.
+    pub expected_spend_to_now: f64,
+    pub actual_spend_today: f64,
+    /// `(expected - actual) / target`; positive means underspending.
+    pub error: f64,
+    pub currency: String,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// End-of-flight spend forecast with a 90% interval.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SpendForecast {
+    pub campaign_id: Uuid,
+    pub currency: String,
+    pub spent_to_date: f64,
+    /// Point forecast of total spend at flight end, capped at the budget.
+    pub forecast_spend: f64,
+    pub lower_bound: f64,
+    pub upper_bound: f64,
+    /// Mean spend per completed day used for the projection.
+    pub daily_run_rate: f64,
+    /// `forecast_spend / total_budget`.
+    pub expected_utilization: f64,
+    /// When the budget is expected to run out, if before flight end.
+    pub projected_exhaustion: Option<DateTime<Utc>>,
+    pub computed_at: DateTime<Utc>,
+}
+
+/// z-score for a two-sided 90% interval.
+const Z_90: f64 = 1.645;
+
+/// Coefficient of variation assumed for daily spend until two full days of
+/// history exist.
+const PRIOR_DAILY_CV: f64 = 0.3;
+
+pub(crate) fn start_of_day(at: DateTime<Utc>) -> DateTime<Utc> {
+    at.date_naive().and_time(NaiveTime::MIN).and_utc()
+}
+
+/// Inputs to [`forecast`], gathered by the budget tracker.
+pub(crate) struct ForecastInput<'a> {
+    pub campaign_id: Uuid,
+    pub currency: &'a str,
+    pub total_budget: f64,
+    pub spent_total: f64,
+    pub spent_today: f64,
+    /// Spend on each completed day of the flight so far.
+    pub daily_history: &'a [f64],
+    pub curve: &'a IntradayCurve,
+    pub end_date: DateTime<Utc>,
+    pub now: DateTime<Utc>,
+}
+
+pub(crate) fn forecast(input: ForecastInput<'_>) -> SpendForecast {
+    let ForecastInput {
+        campaign_id,
+        currency,
+        total_budget,
+        spent_total,
+        spent_today,
+        daily_history,
+        curve,
+        end_date,
+        now,
+    } = input;
+
+    // Project today from the share of traffic seen so far; too early in the
+    // day the ratio is noise, so lean on history instead.
+    let history_mean = if daily_history.is_empty() {
+        None
+    } else {
+        Some(daily_history.iter().sum::<f64>() / daily_history.len() as f64)
+    };
+    let elapsed = curve.elapsed_fraction(now);
+    let today_projection = match history_mean {
+        Some(mean) if elapsed < 0.1 => mean.max(spent_today),
+        _ if elapsed > 0.0 => spent_today / elapsed,
+        _ => spent_today,
+    };
+    let run_rate = history_mean.unwrap_or(today_projection);
+    let std_daily = if daily_history.len() >= 2 {
+        let mean = run_rate;
+        (daily_history
+            .iter()
+            .map(|d| (d - mean).powi(2))
+            .sum::<f64>()
+            / (daily_history.len() - 1) as f64)
+            .sqrt()
+    } else {
+        run_rate * PRIOR_DAILY_CV
+    };
+
+    let end_of_today = start_of_day(now) + Duration::days(1);
+    let today_remaining = if now < end_date {
+        (today_projection - spent_today).max(0.0)
+    } else {
+        0.0
+    };
+    let days_after_today =
+        ((end_date - end_of_today).num_seconds().max(0) as f64 / 86_400.0).max(0.0);
+
+    // Uncertainty covers the days still to come: the rest of today plus
+    // every full day after it.
+    let today_left = if now < end_date {
+        1.0 - elapsed.min(1.0)
+    } else {
+        0.0
+    };
+    let uncapped = spent_total + today_remaining + run_rate * days_after_today;
+    let spread = Z_90 * std_daily * (days_after_today + today_left).sqrt();
+    let cap = |v: f64| v.clamp(spent_total, total_budget.max(spent_total));
+
+    let projected_exhaustion = if uncapped > total_budget && total_budget > spent_total {
+        let after_today = total_budget - spent_total - today_remaining;
+        if after_today <= 0.0 || run_rate <= 0.0 {
+            Some(end_of_today.min(end_date))
+        } else {
+            let days = after_today / run_rate;
+            Some(end_of_today + Duration::seconds((days * 86_400.0) as i64))
+        }
+    } else if spent_total >= total_budget {
+        Some(now)
+    } else {
+        None
+    };
+
+    let forecast_spend = cap(uncapped);
+    SpendForecast {
+        campaign_id,
+        currency: currency.to_string(),
+        spent_to_date: spent_total,
+        forecast_spend,
+        lower_bound: cap(uncapped - spread),
+        upper_bound: cap(uncapped + spread),
+        daily_run_rate: run_rate,
+        expected_utilization: if total_budget > 0.0 {
+            forecast_spend / total_budget
+        } else {
+            0.0
+        },
+        projected_exhaustion,
+        computed_at: now,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn at(hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(2024, 3, 10, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_intraday_curve() {
+        let flat = IntradayCurve::flat();
+        assert!((flat.elapsed_fraction(at(12, 0)) - 0.5).abs() < 1e-9);
+        assert!((flat.elapsed_fraction(at(6, 30)) - 6.5 / 24.0).abs() < 1e-9);
+
+        let mut volume = [0.0; 24];
+        volume[18..22].copy_from_slice(&[1.0, 2.0, 2.0, 1.0]);
+        let evening = IntradayCurve::from_hourly_volume(volume);
+        assert_eq!(evening.elapsed_fraction(at(12, 0)), 0.0);
+        assert!((evening.elapsed_fraction(at(20, 0)) - 0.5).abs() < 1e-9);
+        assert!((evening.elapsed_fraction(at(23, 59)) - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_pid_raises_bids_when_behind_and_resets_daily() {
+        let config = PacingConfig::default();
+        let mut state = PidState::default();
+        assert!((state.update(&config, 0.0, at(8, 0)) - 1.0).abs() < 1e-9);
+        let behind = state.update(&config, 0.3, at(9, 0));
+        let still_behind = state.update(&config, 0.3, at(10, 0));
+        assert!(
+            behind > 1.3 && still_behind > 1.3,
+            "{behind} {still_behind}"
+        );
+        assert!(state.integral > 0.0);
+        assert_eq!(
+            state.update(&config, -5.0, at(11, 0)),
+            config.min_multiplier
+        );
+
+        let next_day = at(1, 0) + Duration::days(1);
+        state.update(&config, 0.0, next_day);
+        assert_eq!(state.integral, 0.0);
+    }
+
+    #[test]
+    fn test_forecast_exhaustion_and_interval() {
+        let curve = IntradayCurve::flat();
+        let run = |history: &[f64]| {
+            forecast(ForecastInput {
+                campaign_id: Uuid::nil(),
+                currency: "EUR",
+                total_budget: 1_000.0,
+                spent_total: 500.0,
+                spent_today: 50.0,
+                daily_history: history,
+                curve: &curve,
+                end_date: at(0, 0) + Duration::days(6),
+                now: at(12, 0),
+            })
+        };
+
+        // Today is on course for 100, then 90/day for 5 days: exactly on budget.
+        let on_plan = run(&[80.0, 100.0, 90.0, 90.0]);
+        assert!((on_plan.forecast_spend - 1_000.0).abs() < 1e-9);
+        assert!(on_plan.lower_bound < on_plan.forecast_spend);
+        assert!(on_plan.projected_exhaustion.is_none());
+        assert_eq!(on_plan.currency, "EUR");
+
+        let slow = run(&[40.0, 60.0, 50.0]);
+        assert!((slow.forecast_spend - 800.0).abs() < 1e-9);
+        assert!(slow.upper_bound > slow.forecast_spend && slow.lower_bound < 800.0);
+        assert!(slow.projected_exhaustion.is_none());
+
+        let fast = run(&[200.0, 200.0]);
+        assert_eq!(fast.forecast_spend, 1_000.0);
+        let exhausted = fast.projected_exhaustion.unwrap();
+        // 50 more today, then 450 at 200/day: 2.25 days after midnight.
+        assert_eq!(
+            exhausted,
+            at(0, 0) + Duration::days(1) + Duration::hours(54)
+        );
+    }
+
+    #[test]
+    fn test_forecast_interval_narrows_through_the_day() {
+        let curve = IntradayCurve::flat();
+        let spread_at = |hour: u32, spent_today: f64| {
+            let f = forecast(ForecastInput {
+                campaign_id: Uuid::nil(),
+                currency: "EUR",
+                total_budget: 10_000.0,
+                spent_total: 500.0,
+                spent_today,
+                daily_history: &[40.0, 60.0, 50.0],
+                curve: &curve,
+                end_date: at(0, 0) + Duration::days(6),
+                now: at(hour, 0),
+            });
+            f.upper_bound - f.forecast_spend
+        };
+
+        // σ = 10/day over five full days plus the unspent part of today.
+        let morning = spread_at(6, 12.5);
+        let evening = spread_at(18, 37.5);
+        assert!((morning - Z_90 * 10.0 * 5.75f64.sqrt()).abs() < 1e-9);
+        assert!((evening - Z_90 * 10.0 * 5.25f64.sqrt()).abs() < 1e-9);
+        assert!(evening < morning);
+    }
+}
@@ -1,12 +1,22 @@
 //! Scheduled report builder — define, generate, and export campaign reports
-//! in CSV, JSON, and other formats with scheduling support.
-
-use chrono::{DateTime, Utc};
+//! in CSV, JSON, XLSX, HTML and PDF with scheduling support. Definitions are
+//! planned into queries by [`crate::report_query`] and run on a
+//! [`QueryBackend`].
+
+use anyhow::Context;
+use campaign_analytics::rollups::rollup_table;
+use campaign_analytics::{RollupDimension, RollupGrain};
+use chrono::{DateTime, Duration, Months, Utc};
 use dashmap::DashMap;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Instant;
 use uuid::Uuid;
 
+use crate::report_export;
+use crate::report_query::{InMemoryBackend, QueryBackend, ReportQuery};
+
 // ─── Types ──────────────────────────────────────────────────────────────────
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,6 +34,30 @@ pub enum ReportType {
     CustomQuery,
 }
 
+impl ReportType {
+    /// Table the report's columns are read from: one of the rollups created
+    /// by [`campaign_analytics::migrations`], or raw `analytics_events` for
+    /// reports that need per-event columns.
+    pub fn source_table(&self) -> String {
+        let rollup = |dimension, grain| rollup_table(dimension, grain);
+        match self {
+            ReportType::CampaignPerformance | ReportType::BudgetUtilization => {
+                rollup(RollupDimension::Campaign, RollupGrain::Daily)
+            }
+            ReportType::ChannelComparison => rollup(RollupDimension::Channel, RollupGrain::Daily),
+            ReportType::AbTestResults => rollup(RollupDimension::Creative, RollupGrain::Daily),
+            ReportType::EngagementOverTime => {
+                rollup(RollupDimension::Campaign, RollupGrain::Hourly)
+            }
+            ReportType::SegmentAnalysis
+            | ReportType::RevenueAttribution
+            | ReportType::FunnelConversion
+            | ReportType::CohortRetention
+            | ReportType::CustomQuery => "analytics_events".to_string(),
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum Aggregation {
@@ -38,7 +72,9 @@ pub enum Aggregation {
     Percentile99,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// How a metric is displayed. Percentages are stored as fractions and
+/// durations in seconds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum MetricFormat {
     Number,
@@ -100,6 +136,28 @@ pub enum ScheduleFrequency {
     Quarterly,
 }
 
+impl ScheduleFrequency {
+    /// The first run after `now`, stepping from `from` so that missed runs
+    /// are skipped rather than replayed.
+    pub fn next_after(&self, from: DateTime<Utc>, now: DateTime<Utc>) -> DateTime<Utc> {
+        let mut next = from;
+        while next <= now {
+            next = match self {
+                ScheduleFrequency::Daily => next + Duration::days(1),
+                ScheduleFrequency::Weekly => next + Duration::weeks(1),
+                ScheduleFrequency::Biweekly => next + Duration::weeks(2),
+                ScheduleFrequency::Monthly => next
+                    .checked_add_months(Months::new(1))
+                    .unwrap_or(next + Duration::days(30)),
+                ScheduleFrequency::Quarterly => next
+                    .checked_add_months(Months::new(3))
+                    .unwrap_or(next + Duration::days(91)),
+            };
+        }
+        next
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 pub enum ExportFormat {
@@ -110,6 +168,30 @@ pub enum ExportFormat {
     Html,
 }
 
+impl ExportFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "csv",
+            ExportFormat::Json => "json",
+            ExportFormat::Pdf => "pdf",
+            ExportFormat::Excel => "xlsx",
+            ExportFormat::Html => "html",
+        }
+    }
+
+    pub fn content_type(&self) -> &'static str {
+        match self {
+            ExportFormat::Csv => "text/csv",
+            ExportFormat::Json => "application/json",
+            ExportFormat::Pdf => "application/pdf",
+            ExportFormat::Excel => {
+                "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet"
+            }
+            ExportFormat::Html => "text/html; charset=utf-8",
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ReportSchedule {
     pub frequency: ScheduleFrequency,
@@ -154,6 +236,9 @@ pub struct ReportOutput {
     pub row_count: usize,
     pub columns: Vec<String>,
     pub rows: Vec<Vec<serde_json::Value>>,
+    /// Display format of each metric column, keyed by column name.
+    #[serde(default)]
+    pub column_formats: HashMap<String, MetricFormat>,
     pub summary: ReportSummary,
     pub export_url: Option<String>,
 }
@@ -164,17 +249,25 @@ pub struct ReportBuilder {
     definitions: DashMap<Uuid, ReportDefinition>,
     generated_reports: DashMap<Uuid, ReportOutput>,
     saved_templates: DashMap<String, ReportDefinition>,
+    backend: Arc<dyn QueryBackend>,
 }
 
 impl ReportBuilder {
+    /// A builder over an empty in-memory backend.
     pub fn new() -> Self {
         Self {
             definitions: DashMap::new(),
             generated_reports: DashMap::new(),
             saved_templates: DashMap::new(),
+            backend: Arc::new(InMemoryBackend::new()),
         }
     }
 
+    pub fn with_backend(mut self, backend: Arc<dyn QueryBackend>) -> Self {
+        self.backend = backend;
+        self
+    }
+
     pub fn create_report(&self, def: ReportDefinition) -> Uuid {
         let id = def.id;
         self.definitions.insert(id, def);
@@ -198,33 +291,43 @@ impl ReportBuilder {
         self.definitions.remove(id).is_some()
     }
 
-    pub fn generate(&self, report_id: &Uuid) -> Option<ReportOutput> {
-        let def = self.definitions.get(report_id)?;
-        let (columns, rows) = match def.report_type {
-            ReportType::CampaignPerformance => self.gen_campaign_performance(),
-            ReportType::ChannelComparison => self.gen_channel_comparison(),
-            ReportType::BudgetUtilization => self.gen_budget_utilization(),
-            ReportType::AbTestResults => self.gen_ab_test_results(),
-            _ => self.gen_generic(),
-        };
+    /// Plan the report's query, run it on the backend and cache the output
+    /// for export.
+    pub async fn generate(&self, report_id: &Uuid) -> anyhow::Result<ReportOutput> {
+        let def = self
+            .get_report(report_id)
+            .with_context(|| format!("report {report_id} not found"))?;
+        let query = ReportQuery::plan(&def)?;
+        let started = Instant::now();
+        let result = self
+            .backend
+            .execute(&query)
+            .await
+            .with_context(|| format!("running report '{}'", def.name))?;
+        let execution_time_ms = started.elapsed().as_millis() as u64;
 
         let output = ReportOutput {
             report_id: *report_id,
             definition_name: def.name.clone(),
             generated_at: Utc::now(),
-            row_count: rows.len(),
-            columns,
-            rows,
+            row_count: result.rows.len(),
+            column_formats: def
+                .metrics
+                .iter()
+                .map(|m| (m.name.clone(), m.format))
+                .collect(),
             summary: ReportSummary {
-                total_rows: 0,
-                execution_time_ms: 42,
+                total_rows: result.rows_before_limit.unwrap_or(result.rows.len() as u64),
+                execution_time_ms,
                 filters_applied: def.filters.len() as u32,
-                date_range: "Last 30 days".into(),
+                date_range: describe_date_range(&def.filters),
             },
+            columns: result.columns,
+            rows: result.rows,
             export_url: None,
         };
         self.generated_reports.insert(*report_id, output.clone());
-        Some(output)
+        Ok(output)
     }
 
     pub fn list_reports(&self, created_by: Option<&Uuid>) -> Vec<ReportDefinition> {
@@ -273,6 +376,42 @@ impl ReportBuilder {
         serde_json::to_string_pretty(&records).ok()
     }
 
+    pub fn export_xlsx(&self, report_id: &Uuid) -> Option<anyhow::Result<Vec<u8>>> {
+        let output = self.generated_reports.get(report_id)?;
+        Some(report_export::to_xlsx(&output))
+    }
+
+    pub fn export_html(&self, report_id: &Uuid) -> Option<String> {
+        let output = self.generated_reports.get(report_id)?;
+        Some(report_export::to_html(&output))
+    }
+
+    pub fn export_pdf(&self, report_id: &Uuid) -> Option<Vec<u8>> {
+        let output = self.generated_reports.get(report_id)?;
+        Some(report_export::to_pdf(&output))
+    }
+
+    /// Export the last generated output in any format.
+    pub fn export(&self, report_id: &Uuid, format: &ExportFormat) -> anyhow::Result<Vec<u8>> {
+        let missing = || format!("report {report_id} has not been generated");
+        Ok(match format {
+            ExportFormat::Csv => self
+                .export_csv(report_id)
+                .with_context(missing)?
+                .into_bytes(),
+            ExportFormat::Json => self
+                .export_json(report_id)
+                .with_context(missing)?
+                .into_bytes(),
+            ExportFormat::Excel => self.export_xlsx(report_id).with_context(missing)??,
+            ExportFormat::Html => self
+                .export_html(report_id)
+                .with_context(missing)?
+                .into_bytes(),
+            ExportFormat::Pdf => self.export_pdf(report_id).with_context(missing)?,
+        })
+    }
+
     pub fn save_as_template(&self, name: &str, def: ReportDefinition) {
         self.saved_templates.insert(name.to_string(), def);
     }
@@ -296,65 +435,112 @@ impl ReportBuilder {
             .collect()
     }
 
+    /// Enabled schedules whose next run is at or before `now`.
+    pub fn get_due_reports(&self, now: DateTime<Utc>) -> Vec<ReportDefinition> {
+        self.definitions
+            .iter()
+            .filter(|d| {
+                d.schedule
+                    .as_ref()
+                    .is_some_and(|s| s.enabled && s.next_run <= now)
+            })
+            .map(|d| d.clone())
+            .collect()
+    }
+
+    /// Advance a report's schedule past `now`, returning the new next run.
+    pub fn advance_schedule(&self, report_id: &Uuid, now: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        let mut def = self.definitions.get_mut(report_id)?;
+        let schedule = def.schedule.as_mut()?;
+        schedule.next_run = schedule.frequency.next_after(schedule.next_run, now);
+        Some(schedule.next_run)
+    }
+
     pub fn seed_default_templates(&self) {
         let now = Utc::now();
         let user = Uuid::nil();
 
+        use Aggregation::{Average, CountDistinct, Sum};
+        use MetricFormat::{Currency, Number};
+
+        // Columns are those of the rollups and `analytics_events`.
         let templates = vec![
             (
                 "Weekly Campaign Summary",
                 ReportType::CampaignPerformance,
-                vec!["sends", "opens", "clicks", "conversions", "revenue"],
+                vec!["campaign_id"],
+                vec![
+                    ("sends", Sum, Number),
+                    ("impressions", Sum, Number),
+                    ("clicks", Sum, Number),
+                    ("conversions", Sum, Number),
+                    ("spend", Sum, Currency),
+                ],
             ),
             (
                 "Monthly Revenue Report",
                 ReportType::RevenueAttribution,
-                vec!["campaign", "attributed_revenue", "conversions", "roas"],
+                vec!["campaign_id"],
+                vec![
+                    ("win_price", Sum, Currency),
+                    ("bid_price", Average, Currency),
+                    ("user_id", CountDistinct, Number),
+                ],
             ),
             (
                 "Channel Performance Comparison",
                 ReportType::ChannelComparison,
+                vec!["channel"],
                 vec![
-                    "channel",
-                    "sends",
-                    "delivery_rate",
-                    "open_rate",
-                    "click_rate",
+                    ("sends", Sum, Number),
+                    ("deliveries", Sum, Number),
+                    ("failures", Sum, Number),
+                    ("clicks", Sum, Number),
                 ],
             ),
             (
                 "A/B Test Results",
                 ReportType::AbTestResults,
+                vec!["creative_id"],
                 vec![
-                    "variant",
-                    "impressions",
-                    "conversions",
-                    "lift",
-                    "confidence",
+                    ("impressions", Sum, Number),
+                    ("clicks", Sum, Number),
+                    ("conversions", Sum, Number),
                 ],
             ),
             (
                 "Budget Utilization Report",
                 ReportType::BudgetUtilization,
-                vec!["campaign", "budget", "spent", "remaining", "pacing"],
+                vec!["campaign_id"],
+                vec![
+                    ("spend", Sum, Currency),
+                    ("bid_value", Sum, Currency),
+                    ("wins", Sum, Number),
+                ],
             ),
         ];
 
-        for (name, report_type, metric_names) in templates {
+        for (name, report_type, dimensions, metrics) in templates {
             let def = ReportDefinition {
                 id: Uuid::new_v4(),
                 name: name.into(),
                 description: format!("Default template: {name}"),
                 report_type,
-                metrics: metric_names
-                    .iter()
-                    .map(|n| MetricColumn {
-                        name: n.to_string(),
-                        aggregation: Aggregation::Sum,
-                        format: MetricFormat::Number,
+                metrics: metrics
+                    .into_iter()
+                    .map(|(name, aggregation, format)| MetricColumn {
+                        name: name.to_string(),
+                        aggregation,
+                        format,
+                    })
+                    .collect(),
+                dimensions: dimensions
+                    .into_iter()
+                    .map(|name| DimensionColumn {
+                        name: name.to_string(),
+                        group_by: true,
                     })
                     .collect(),
-                dimensions: vec![],
                 filters: vec![],
                 sort_by: None,
                 sort_order: SortOrder::Descending,
@@ -367,189 +553,6 @@ impl ReportBuilder {
             self.saved_templates.insert(name.to_string(), def);
         }
     }
-
-    // ─── Data generators ────────────────────────────────────────────────────
-
-    fn gen_campaign_performance(&self) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
-        let cols = vec![
-            "campaign",
-            "sends",
-            "opens",
-            "clicks",
-            "conversions",
-            "revenue",
-            "open_rate",
-            "ctr",
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect();
-
-        let campaigns = [
-            ("Summer Sale", 50000, 22500, 4500, 900, 45000.0),
-            ("Welcome Series", 30000, 18000, 5400, 1620, 81000.0),
-            ("Re-engagement", 20000, 6000, 1200, 180, 9000.0),
-            ("Holiday Promo", 75000, 37500, 11250, 2250, 112500.0),
-            ("Loyalty Rewards", 15000, 9000, 3600, 1080, 54000.0),
-            ("Flash Sale", 40000, 20000, 8000, 2400, 96000.0),
-            ("New Product", 25000, 12500, 3750, 750, 37500.0),
-            ("Abandoned Cart", 35000, 21000, 8400, 3360, 168000.0),
-            ("Anniversary", 10000, 6000, 2400, 720, 36000.0),
-            ("Win-Back", 18000, 5400, 1080, 162, 8100.0),
-        ];
-
-        let rows = campaigns
-            .iter()
-            .map(|(name, sends, opens, clicks, conv, rev)| {
-                vec![
-                    serde_json::json!(name),
-                    serde_json::json!(sends),
-                    serde_json::json!(opens),
-                    serde_json::json!(clicks),
-                    serde_json::json!(conv),
-                    serde_json::json!(rev),
-                    serde_json::json!((*opens as f64 / *sends as f64 * 100.0).round() / 100.0),
-                    serde_json::json!((*clicks as f64 / *opens as f64 * 100.0).round() / 100.0),
-                ]
-            })
-            .collect();
-
-        (cols, rows)
-    }
-
-    fn gen_channel_comparison(&self) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
-        let cols = vec![
-            "channel",
-            "sends",
-            "deliveries",
-            "engagements",
-            "conversions",
-            "revenue",
-            "delivery_rate",
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect();
-
-        let channels = [
-            ("email", 150000, 145000, 43500, 6525, 326250.0),
-            ("push", 80000, 78000, 23400, 3510, 175500.0),
-            ("sms", 40000, 39600, 15840, 2376, 118800.0),
-            ("in_app", 60000, 60000, 30000, 4500, 225000.0),
-            ("whatsapp", 25000, 24750, 12375, 1856, 92800.0),
-            ("web_push", 35000, 33250, 9975, 1496, 74800.0),
-        ];
-
-        let rows = channels
-            .iter()
-            .map(|(ch, sends, del, eng, conv, rev)| {
-                vec![
-                    serde_json::json!(ch),
-                    serde_json::json!(sends),
-                    serde_json::json!(del),
-                    serde_json::json!(eng),
-                    serde_json::json!(conv),
-                    serde_json::json!(rev),
-                    serde_json::json!((*del as f64 / *sends as f64 * 100.0).round() / 100.0),
-                ]
-            })
-            .collect();
-
-        (cols, rows)
-    }
-
-    fn gen_budget_utilization(&self) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
-        let cols = vec!["campaign", "budget", "spent", "remaining", "pacing_status"]
-            .into_iter()
-            .map(String::from)
-            .collect();
-
-        let data = [
-            ("Summer Sale", 50000.0, 42000.0, "on_track"),
-            ("Welcome Series", 20000.0, 18500.0, "overspending"),
-            ("Re-engagement", 15000.0, 8000.0, "underspending"),
-            ("Holiday Promo", 100000.0, 100000.0, "exhausted"),
-            ("Loyalty Rewards", 30000.0, 24000.0, "on_track"),
-        ];
-
-        let rows = data
-            .iter()
-            .map(|(name, budget, spent, pacing)| {
-                vec![
-                    serde_json::json!(name),
-                    serde_json::json!(budget),
-                    serde_json::json!(spent),
-                    serde_json::json!(budget - spent),
-                    serde_json::json!(pacing),
-                ]
-            })
-            .collect();
-
-        (cols, rows)
-    }
-
-    fn gen_ab_test_results(&self) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
-        let cols = vec![
-            "variant",
-            "impressions",
-            "conversions",
-            "conversion_rate",
-            "lift_vs_control",
-            "confidence",
-        ]
-        .into_iter()
-        .map(String::from)
-        .collect();
-
-        let rows = vec![
-            vec![
-                serde_json::json!("Control"),
-                serde_json::json!(10000),
-                serde_json::json!(250),
-                serde_json::json!(0.025),
-                serde_json::json!(0.0),
-                serde_json::json!("baseline"),
-            ],
-            vec![
-                serde_json::json!("Variant A"),
-                serde_json::json!(10000),
-                serde_json::json!(310),
-                serde_json::json!(0.031),
-                serde_json::json!(0.24),
-                serde_json::json!(0.95),
-            ],
-            vec![
-                serde_json::json!("Variant B"),
-                serde_json::json!(10000),
-                serde_json::json!(280),
-                serde_json::json!(0.028),
-                serde_json::json!(0.12),
-                serde_json::json!(0.72),
-            ],
-        ];
-
-        (cols, rows)
-    }
-
-    fn gen_generic(&self) -> (Vec<String>, Vec<Vec<serde_json::Value>>) {
-        let cols = vec!["dimension", "metric_1", "metric_2", "metric_3"]
-            .into_iter()
-            .map(String::from)
-            .collect();
-
-        let rows = (1..=5)
-            .map(|i| {
-                vec![
-                    serde_json::json!(format!("Item {i}")),
-                    serde_json::json!(i * 1000),
-                    serde_json::json!(i as f64 * 0.15),
-                    serde_json::json!(i * 500),
-                ]
-            })
-            .collect();
-
-        (cols, rows)
-    }
 }
 
 impl Default for ReportBuilder {
@@ -558,11 +561,42 @@ impl Default for ReportBuilder {
     }
 }
 
+/// Human-readable range from filters on time columns (`date`, `timestamp`,
+/// `*_at`, `*_date`).
+fn describe_date_range(filters: &[ReportFilter]) -> String {
+    let is_time =
+        |f: &str| f == "date" || f == "timestamp" || f.ends_with("_at") || f.ends_with("_date");
+    let show = |v: &serde_json::Value| match v {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    let mut parts = Vec::new();
+    for filter in filters.iter().filter(|f| is_time(&f.field)) {
+        match filter.operator {
+            FilterOperator::Between => parts.push(format!(
+                "{} to {}",
+                show(&filter.value[0]),
+                show(&filter.value[1])
+            )),
+            FilterOperator::GreaterThan => parts.push(format!("after {}", show(&filter.value))),
+            FilterOperator::LessThan => parts.push(format!("before {}", show(&filter.value))),
+            FilterOperator::Equals => parts.push(show(&filter.value)),
+            _ => {}
+        }
+    }
+    if parts.is_empty() {
+        "All time".into()
+    } else {
+        parts.join(", ")
+    }
+}
+
 // ─── Tests ──────────────────────────────────────────────────────────────────
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use serde_json::json;
 
     fn make_def(report_type: ReportType) -> ReportDefinition {
         ReportDefinition {
@@ -570,8 +604,22 @@ mod tests {
             name: "Test Report".into(),
             description: "desc".into(),
             report_type,
-            metrics: vec![],
-            dimensions: vec![],
+            metrics: vec![
+                MetricColumn {
+                    name: "sends".into(),
+                    aggregation: Aggregation::Sum,
+                    format: MetricFormat::Number,
+                },
+                MetricColumn {
+                    name: "revenue".into(),
+                    aggregation: Aggregation::Sum,
+                    format: MetricFormat::Currency,
+                },
+            ],
+            dimensions: vec![DimensionColumn {
+                name: "channel".into(),
+                group_by: true,
+            }],
             filters: vec![],
             sort_by: None,
             sort_order: SortOrder::Descending,
@@ -583,22 +631,61 @@ mod tests {
         }
     }
 
-    #[test]
-    fn test_create_and_generate() {
-        let builder = ReportBuilder::new();
-        let def = make_def(ReportType::CampaignPerformance);
+    fn seeded_builder() -> ReportBuilder {
+        let backend = InMemoryBackend::new();
+        let channels = ["email", "push", "sms", "in_app", "whatsapp", "web_push"];
+        for table in ["channel_rollup_daily", "campaign_rollup_daily"] {
+            backend.insert_json(
+                table,
+                channels.iter().enumerate().flat_map(|(i, channel)| {
+                    let i = i as u64 + 1;
+                    [
+                        json!({"channel": channel, "sends": i * 1000, "revenue": i as f64 * 10.5, "sent_at": "2024-05-01"}),
+                        json!({"channel": channel, "sends": i * 500, "revenue": 1.0, "sent_at": "2024-06-15"}),
+                    ]
+                }),
+            );
+        }
+        ReportBuilder::new().with_backend(Arc::new(backend))
+    }
+
+    #[tokio::test]
+    async fn test_create_and_generate() {
+        let builder = seeded_builder();
+        let mut def = make_def(ReportType::CampaignPerformance);
+        def.sort_by = Some("sends".into());
+        def.limit = Some(3);
+        def.filters.push(ReportFilter {
+            field: "sent_at".into(),
+            operator: FilterOperator::Between,
+            value: json!(["2024-05-01", "2024-05-31"]),
+        });
         let id = builder.create_report(def);
-        let output = builder.generate(&id).unwrap();
-        assert_eq!(output.row_count, 10);
-        assert!(output.columns.contains(&"campaign".to_string()));
+        let output = builder.generate(&id).await.unwrap();
+        assert_eq!(output.columns, ["channel", "sends", "revenue"]);
+        assert_eq!(output.row_count, 3);
+        assert_eq!(
+            output.rows[0],
+            [json!("web_push"), json!(6000), json!(63.0)]
+        );
+        assert_eq!(output.summary.total_rows, 6);
+        assert_eq!(output.summary.filters_applied, 1);
+        assert_eq!(output.summary.date_range, "2024-05-01 to 2024-05-31");
+        assert_eq!(output.column_formats["revenue"], MetricFormat::Currency);
+
+        assert!(builder.generate(&Uuid::new_v4()).await.is_err());
+        let mut invalid = make_def(ReportType::CampaignPerformance);
+        invalid.sort_by = Some("missing".into());
+        let invalid = builder.create_report(invalid);
+        assert!(builder.generate(&invalid).await.is_err());
     }
 
-    #[test]
-    fn test_csv_export() {
-        let builder = ReportBuilder::new();
+    #[tokio::test]
+    async fn test_csv_export() {
+        let builder = seeded_builder();
         let def = make_def(ReportType::ChannelComparison);
         let id = builder.create_report(def);
-        builder.generate(&id);
+        builder.generate(&id).await.unwrap();
         let csv = builder.export_csv(&id).unwrap();
         assert!(csv.starts_with("channel,"));
         assert!(csv.contains("\"email\""));
@@ -606,16 +693,38 @@ mod tests {
         assert_eq!(line_count, 7); // header + 6 channels
     }
 
-    #[test]
-    fn test_json_export() {
-        let builder = ReportBuilder::new();
-        let def = make_def(ReportType::BudgetUtilization);
+    #[tokio::test]
+    async fn test_json_export() {
+        let builder = seeded_builder();
+        let def = make_def(ReportType::ChannelComparison);
         let id = builder.create_report(def);
-        builder.generate(&id);
+        builder.generate(&id).await.unwrap();
         let json = builder.export_json(&id).unwrap();
         let parsed: Vec<HashMap<String, serde_json::Value>> = serde_json::from_str(&json).unwrap();
-        assert_eq!(parsed.len(), 5);
-        assert!(parsed[0].contains_key("campaign"));
+        assert_eq!(parsed.len(), 6);
+        assert!(parsed[0].contains_key("channel"));
+    }
+
+    #[tokio::test]
+    async fn test_export_formats() {
+        let builder = seeded_builder();
+        let id = builder.create_report(make_def(ReportType::ChannelComparison));
+        assert!(builder.export(&id, &ExportFormat::Pdf).is_err());
+        builder.generate(&id).await.unwrap();
+        for format in [
+            ExportFormat::Csv,
+            ExportFormat::Json,
+            ExportFormat::Pdf,
+            ExportFormat::Excel,
+            ExportFormat::Html,
+        ] {
+            let bytes = builder.export(&id, &format).unwrap();
+            assert!(!bytes.is_empty(), "{format:?}");
+        }
+        assert!(builder
+            .export(&id, &ExportFormat::Excel)
+            .unwrap()
+            .starts_with(b"PK"));
     }
 
     #[test]
@@ -624,8 +733,60 @@ mod tests {
         builder.seed_default_templates();
         let templates = builder.list_templates();
         assert_eq!(templates.len(), 5);
-        let loaded = builder.load_template("A/B Test Results");
-        assert!(loaded.is_some());
+        let loaded = builder.load_template("A/B Test Results").unwrap();
+        assert_eq!(loaded.dimensions[0].name, "creative_id");
+        assert!(ReportQuery::plan(&loaded).is_ok());
+
+        // Every template reads columns its source table actually has.
+        for (name, def) in templates {
+            let ddl = table_ddl(&def.report_type.source_table());
+            let columns = def
+                .dimensions
+                .iter()
+                .map(|d| &d.name)
+                .chain(def.metrics.iter().map(|m| &m.name));
+            for column in columns {
+                assert!(
+                    ddl.contains(&format!("{column} ")),
+                    "{name}: no column {column}"
+                );
+            }
+        }
+    }
+
+    /// Every migration statement that creates or alters `table`.
+    fn table_ddl(table: &str) -> String {
+        campaign_analytics::migrations::migrations()
+            .into_iter()
+            .flat_map(|m| m.statements)
+            .filter(|s| {
+                s.starts_with(&format!("CREATE TABLE IF NOT EXISTS {table} ("))
+                    || s.starts_with(&format!("ALTER TABLE {table}\n"))
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_source_tables_are_created_by_migrations() {
+        for report_type in [
+            ReportType::CampaignPerformance,
+            ReportType::ChannelComparison,
+            ReportType::SegmentAnalysis,
+            ReportType::RevenueAttribution,
+            ReportType::FunnelConversion,
+            ReportType::CohortRetention,
+            ReportType::AbTestResults,
+            ReportType::BudgetUtilization,
+            ReportType::EngagementOverTime,
+            ReportType::CustomQuery,
+        ] {
+            let table = report_type.source_table();
+            assert!(
+                !table_ddl(&table).is_empty(),
+                "{report_type:?} reads {table}"
+            );
+        }
     }
 
     #[test]
@@ -0,0 +1,526 @@
+//! Report exporters — XLSX with typed cells per [`MetricFormat`], a
+//! standalone HTML page, and a paginated PDF table.
+
+use serde_json::Value;
+use std::io::{Cursor, Write};
+
+use crate::report_builder::{MetricFormat, ReportOutput};
+
+/// A cell as text, formatted per its column's metric format.
+pub fn display_value(value: &Value, format: Option<MetricFormat>) -> String {
+    let number = match value {
+        Value::Null => return String::new(),
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => match format {
+            Some(MetricFormat::Number) | None => return s.clone(),
+            Some(_) => match s.parse::<f64>() {
+                Ok(n) => Some(n),
+                Err(_) => return s.clone(),
+            },
+        },
+        other => return other.to_string(),
+    };
+    let Some(n) = number else {
+        return value.to_string();
+    };
+    match format {
+        Some(MetricFormat::Currency) => group_thousands(n),
+        Some(MetricFormat::Percentage) => format!("{:.2}%", n * 100.0),
+        Some(MetricFormat::Duration) => {
+            let total = n.round().max(0.0) as u64;
+            format!("{}:{:02}:{:02}", total / 3600, total / 60 % 60, total % 60)
+        }
+        Some(MetricFormat::Number) | None => value.to_string(),
+    }
+}
+
+/// Two decimals with comma-grouped thousands: `1234567.5` → `1,234,567.50`.
+fn group_thousands(n: f64) -> String {
+    let fixed = format!("{:.2}", n.abs());
+    let (whole, fraction) = fixed.split_once('.').unwrap_or((&fixed, "00"));
+    let mut grouped = String::new();
+    for (i, digit) in whole.chars().enumerate() {
+        if i > 0 && (whole.len() - i) % 3 == 0 {
+            grouped.push(',');
+        }
+        grouped.push(digit);
+    }
+    let sign = if n < 0.0 && fixed != "0.00" { "-" } else { "" };
+    format!("{sign}{grouped}.{fraction}")
+}
+
+fn column_formats(output: &ReportOutput) -> Vec<Option<MetricFormat>> {
+    output
+        .columns
+        .iter()
+        .map(|c| output.column_formats.get(c).copied())
+        .collect()
+}
+
+fn escape_xml(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            // Control characters other than tab and newlines are not valid XML.
+            c if c.is_control() && !matches!(c, '\t' | '\n' | '\r') => {}
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+// ─── XLSX ───────────────────────────────────────────────────────────────────
+
+const XLSX_CONTENT_TYPES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Types xmlns="http://schemas.openxmlformats.org/package/2006/content-types"><Default Extension="rels" ContentType="application/vnd.openxmlformats-package.relationships+xml"/><Default Extension="xml" ContentType="application/xml"/><Override PartName="/xl/workbook.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.sheet.main+xml"/><Override PartName="/xl/worksheets/sheet1.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.worksheet+xml"/><Override PartName="/xl/styles.xml" ContentType="application/vnd.openxmlformats-officedocument.spreadsheetml.styles+xml"/></Types>"#;
+
+const XLSX_ROOT_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/officeDocument" Target="xl/workbook.xml"/></Relationships>"#;
+
+const XLSX_WORKBOOK_RELS: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<Relationships xmlns="http://schemas.openxmlformats.org/package/2006/relationships"><Relationship Id="rId1" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/worksheet" Target="worksheets/sheet1.xml"/><Relationship Id="rId2" Type="http://schemas.openxmlformats.org/officeDocument/2006/relationships/styles" Target="styles.xml"/></Relationships>"#;
+
+/// Cell styles: 0 general, 1 bold header, 2 currency, 3 percentage,
+/// 4 duration (`[h]:mm:ss`, values in days).
+const XLSX_STYLES: &str = r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<styleSheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><numFmts count="1"><numFmt numFmtId="164" formatCode="[h]:mm:ss"/></numFmts><fonts count="2"><font><sz val="11"/><name val="Calibri"/></font><font><b/><sz val="11"/><name val="Calibri"/></font></fonts><fills count="2"><fill><patternFill patternType="none"/></fill><fill><patternFill patternType="gray125"/></fill></fills><borders count="1"><border><left/><right/><top/><bottom/><diagonal/></border></borders><cellStyleXfs count="1"><xf numFmtId="0" fontId="0" fillId="0" borderId="0"/></cellStyleXfs><cellXfs count="5"><xf numFmtId="0" fontId="0" fillId="0" borderId="0" xfId="0"/><xf numFmtId="0" fontId="1" fillId="0" borderId="0" xfId="0" applyFont="1"/><xf numFmtId="4" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="10" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/><xf numFmtId="164" fontId="0" fillId="0" borderId="0" xfId="0" applyNumberFormat="1"/></cellXfs></styleSheet>"#;
+
+/// Spreadsheet column letters: 0 → `A`, 26 → `AA`.
+fn column_letters(mut index: usize) -> String {
+    let mut letters = Vec::new();
+    loop {
+        letters.push(b'A' + (index % 26) as u8);
+        if index < 26 {
+            break;
+        }
+        index = index / 26 - 1;
+    }
+    letters.reverse();
+    String::from_utf8(letters).unwrap_or_default()
+}
+
+fn xlsx_sheet_name(name: &str) -> String {
+    let cleaned: String = name
+        .chars()
+        .filter(|c| !matches!(c, '[' | ']' | ':' | '*' | '?' | '/' | '\\'))
+        .take(31)
+        .collect();
+    if cleaned.trim().is_empty() {
+        "Report".into()
+    } else {
+        cleaned
+    }
+}
+
+fn xlsx_cell(reference: &str, value: &Value, format: Option<MetricFormat>) -> String {
+    let inline = |text: &str| {
+        format!(
+            r#"<c r="{reference}" t="inlineStr"><is><t xml:space="preserve">{}</t></is></c>"#,
+            escape_xml(text)
+        )
+    };
+    let number = match value {
+        Value::Null => return String::new(),
+        Value::Bool(b) => return format!(r#"<c r="{reference}" t="b"><v>{}</v></c>"#, *b as u8),
+        Value::Number(n) => n.as_f64(),
+        // Backends may return decimals as strings; keep metric cells numeric.
+        Value::String(s) if format.is_some() => s.parse::<f64>().ok(),
+        Value::String(s) => return inline(s),
+        other => return inline(&other.to_string()),
+    };
+    let Some(n) = number.filter(|n| n.is_finite()) else {
+        return inline(&display_value(value, None));
+    };
+    let (style, n) = match format {
+        Some(MetricFormat::Currency) => (2, n),
+        Some(MetricFormat::Percentage) => (3, n),
+        Some(MetricFormat::Duration) => (4, n / 86_400.0),
+        Some(MetricFormat::Number) | None => (0, n),
+    };
+    if style == 0 {
+        format!(r#"<c r="{reference}"><v>{n}</v></c>"#)
+    } else {
+        format!(r#"<c r="{reference}" s="{style}"><v>{n}</v></c>"#)
+    }
+}
+
+/// An XLSX workbook with one sheet: a bold header row, then one row per
+/// result row with numbers, currency, percentages and durations stored as
+/// typed numeric cells.
+pub fn to_xlsx(output: &ReportOutput) -> anyhow::Result<Vec<u8>> {
+    let formats = column_formats(output);
+    let mut sheet = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<worksheet xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main"><sheetData><row r="1">"#,
+    );
+    for (i, column) in output.columns.iter().enumerate() {
+        sheet.push_str(&format!(
+            r#"<c r="{}1" t="inlineStr" s="1"><is><t>{}</t></is></c>"#,
+            column_letters(i),
+            escape_xml(column)
+        ));
+    }
+    sheet.push_str("</row>");
+    for (r, row) in output.rows.iter().enumerate() {
+        let number = r + 2;
+        sheet.push_str(&format!(r#"<row r="{number}">"#));
+        for (i, value) in row.iter().enumerate() {
+            let reference = format!("{}{number}", column_letters(i));
+            let format = formats.get(i).copied().flatten();
+            sheet.push_str(&xlsx_cell(&reference, value, format));
+        }
+        sheet.push_str("</row>");
+    }
+    sheet.push_str("</sheetData></worksheet>");
+
+    let workbook = format!(
+        r#"<?xml version="1.0" encoding="UTF-8" standalone="yes"?>
+<workbook xmlns="http://schemas.openxmlformats.org/spreadsheetml/2006/main" xmlns:r="http://schemas.openxmlformats.org/officeDocument/2006/relationships"><sheets><sheet name="{}" sheetId="1" r:id="rId1"/></sheets></workbook>"#,
+        escape_xml(&xlsx_sheet_name(&output.definition_name))
+    );
+
+    let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default()
+        .compression_method(zip::CompressionMethod::Deflated);
+    for (path, contents) in [
+        ("[Content_Types].xml", XLSX_CONTENT_TYPES),
+        ("_rels/.rels", XLSX_ROOT_RELS),
+        ("xl/workbook.xml", workbook.as_str()),
+        ("xl/_rels/workbook.xml.rels", XLSX_WORKBOOK_RELS),
+        ("xl/styles.xml", XLSX_STYLES),
+        ("xl/worksheets/sheet1.xml", sheet.as_str()),
+    ] {
+        zip.start_file(path, options)?;
+        zip.write_all(contents.as_bytes())?;
+    }
+    Ok(zip.finish()?.into_inner())
+}
+
+// ─── HTML ───────────────────────────────────────────────────────────────────
+
+const HTML_STYLE: &str = "body{font-family:-apple-system,Segoe UI,Helvetica,Arial,sans-serif;margin:24px;color:#1f2933}\
+h1{font-size:20px;margin:0 0 4px}.meta{color:#616e7c;font-size:12px;margin:0 0 16px}\
+table{border-collapse:collapse;font-size:13px}th,td{padding:6px 10px;border-bottom:1px solid #e4e7eb}\
+th{text-align:left;background:#f5f7fa}td.num{text-align:right;font-variant-numeric:tabular-nums}";
+
+/// A standalone HTML page with the report as a table.
+pub fn to_html(output: &ReportOutput) -> String {
+    let formats = column_formats(output);
+    let title = escape_xml(&output.definition_name);
+    let mut html = format!(
+        "<!DOCTYPE html>\n<html lang=\"en\">\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>{HTML_STYLE}</style>\n</head>\n<body>\n<h1>{title}</h1>\n\
+         <p class=\"meta\">Generated {} &middot; {} of {} rows &middot; {}</p>\n<table>\n<thead><tr>",
+        output.generated_at.format("%Y-%m-%d %H:%M UTC"),
+        output.row_count,
+        output.summary.total_rows,
+        escape_xml(&output.summary.date_range)
+    );
+    for column in &output.columns {
+        html.push_str(&format!("<th>{}</th>", escape_xml(column)));
+    }
+    html.push_str("</tr></thead>\n<tbody>\n");
+    for row in &output.rows {
+        html.push_str("<tr>");
+        for (i, value) in row.iter().enumerate() {
+            let format = formats.get(i).copied().flatten();
+            let class = if value.is_number() || format.is_some() {
+                " class=\"num\""
+            } else {
+                ""
+            };
+            html.push_str(&format!(
+                "<td{class}>{}</td>",
+                escape_xml(&display_value(value, format))
+            ));
+        }
+        html.push_str("</tr>\n");
+    }
+    html.push_str("</tbody>\n</table>\n</body>\n</html>\n");
+    html
+}
+
+// ─── PDF ────────────────────────────────────────────────────────────────────
+
+const PDF_PAGE_WIDTH: f64 = 842.0;
+const PDF_PAGE_HEIGHT: f64 = 595.0;
+const PDF_MARGIN: f64 = 40.0;
+const PDF_FONT_SIZE: f64 = 9.0;
+const PDF_LINE_HEIGHT: f64 = 14.0;
+
+/// A PDF string literal in WinAnsi encoding; characters outside Latin-1
+/// become `?`.
+fn pdf_text(text: &str) -> String {
+    let mut out = String::with_capacity(text.len() + 2);
+    out.push('(');
+    for c in text.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                out.push('\\');
+                out.push(c);
+            }
+            ' '..='~' => out.push(c),
+            '\u{a0}'..='\u{ff}' => out.push_str(&format!("\\{:03o}", c as u32)),
+            _ => out.push('?'),
+        }
+    }
+    out.push(')');
+    out
+}
+
+/// Truncate to roughly fit `width` points of Helvetica at the table size.
+fn fit(text: &str, width: f64) -> String {
+    let max = ((width - 4.0) / (PDF_FONT_SIZE * 0.5)).max(1.0) as usize;
+    if text.chars().count() <= max {
+        text.to_string()
+    } else {
+        let kept: String = text.chars().take(max.saturating_sub(3)).collect();
+        format!("{kept}...")
+    }
+}
+
+/// A landscape A4 PDF with the report as a table, repeating the header row
+/// on every page.
+pub fn to_pdf(output: &ReportOutput) -> Vec<u8> {
+    let formats = column_formats(output);
+    let columns = output.columns.len().max(1);
+    let column_width = (PDF_PAGE_WIDTH - 2.0 * PDF_MARGIN) / columns as f64;
+    let table_top = PDF_PAGE_HEIGHT - PDF_MARGIN - 40.0;
+    let rows_per_page = (((table_top - PDF_MARGIN) / PDF_LINE_HEIGHT) as usize)
+        .saturating_sub(2)
+        .max(1);
+    let pages: Vec<&[Vec<Value>]> = if output.rows.is_empty() {
+        vec![&[]]
+    } else {
+        output.rows.chunks(rows_per_page).collect()
+    };
+
+    let text_row = |stream: &mut String, font: &str, y: f64, cells: Vec<String>| {
+        for (i, cell) in cells.iter().enumerate() {
+            let x = PDF_MARGIN + i as f64 * column_width;
+            stream.push_str(&format!(
+                "BT /{font} {PDF_FONT_SIZE} Tf {x:.1} {y:.1} Td {} Tj ET\n",
+                pdf_text(&fit(cell, column_width))
+            ));
+        }
+    };
+
+    let contents: Vec<String> = pages
+        .iter()
+        .enumerate()
+        .map(|(page, rows)| {
+            let mut stream = format!(
+                "BT /F2 14 Tf {PDF_MARGIN} {:.1} Td {} Tj ET\n",
+                PDF_PAGE_HEIGHT - PDF_MARGIN - 10.0,
+                pdf_text(&output.definition_name)
+            );
+            stream.push_str(&format!(
+                "BT /F1 8 Tf {PDF_MARGIN} {:.1} Td {} Tj ET\n",
+                PDF_PAGE_HEIGHT - PDF_MARGIN - 24.0,
+                pdf_text(&format!(
+                    "Generated {} - {} of {} rows - {} - page {} of {}",
+                    output.generated_at.format("%Y-%m-%d %H:%M UTC"),
+                    output.row_count,
+                    output.summary.total_rows,
+                    output.summary.date_range,
+                    page + 1,
+                    pages.len()
+                ))
+            ));
+            text_row(&mut stream, "F2", table_top, output.columns.clone());
+            let rule = table_top - 4.0;
+            stream.push_str(&format!(
+                "0.5 w {PDF_MARGIN} {rule:.1} m {:.1} {rule:.1} l S\n",
+                PDF_PAGE_WIDTH - PDF_MARGIN
+            ));
+            for (r, row) in rows.iter().enumerate() {
+                let y = table_top - (r + 1) as f64 * PDF_LINE_HEIGHT;
+                let cells = row
+                    .iter()
+                    .enumerate()
+                    .map(|(i, v)| display_value(v, formats.get(i).copied().flatten()))
+                    .collect();
+                text_row(&mut stream, "F1", y, cells);
+            }
+            stream
+        })
+        .collect();
+
+    // Objects: 1 catalog, 2 page tree, 3-4 fonts, then a page and its
+    // content stream per page.
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 5 + 2 * i).collect();
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{id} 0 R"))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica-Bold /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+    for (id, content) in page_ids.iter().zip(&contents) {
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {PDF_PAGE_WIDTH} {PDF_PAGE_HEIGHT}] \
+             /Resources << /Font << /F1 3 0 R /F2 4 0 R >> >> /Contents {} 0 R >>",
+            id + 1
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{content}endstream",
+            content.len()
+        ));
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n{object}\nendobj\n", i + 1).as_bytes());
+    }
+    let xref = pdf.len();
+    pdf.extend_from_slice(
+        format!("xref\n0 {}\n0000000000 65535 f \n", objects.len() + 1).as_bytes(),
+    );
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{offset:010} 00000 n \n").as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{xref}\n%%EOF\n",
+            objects.len() + 1
+        )
+        .as_bytes(),
+    );
+    pdf
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_builder::ReportSummary;
+    use chrono::Utc;
+    use serde_json::json;
+    use std::collections::HashMap;
+    use std::io::Read;
+    use uuid::Uuid;
+
+    fn output() -> ReportOutput {
+        ReportOutput {
+            report_id: Uuid::new_v4(),
+            definition_name: "Q3 <Revenue> & Reach".into(),
+            generated_at: Utc::now(),
+            row_count: 2,
+            columns: vec![
+                "campaign".into(),
+                "revenue".into(),
+                "ctr".into(),
+                "watch_time".into(),
+            ],
+            rows: vec![
+                vec![
+                    json!("Caf\u{e9} (launch)"),
+                    json!(1234567.5),
+                    json!(0.125),
+                    json!(3723),
+                ],
+                vec![json!("Win-back"), json!("99.9"), Value::Null, json!(59)],
+            ],
+            column_formats: HashMap::from([
+                ("revenue".into(), MetricFormat::Currency),
+                ("ctr".into(), MetricFormat::Percentage),
+                ("watch_time".into(), MetricFormat::Duration),
+            ]),
+            summary: ReportSummary {
+                total_rows: 2,
+                execution_time_ms: 1,
+                filters_applied: 0,
+                date_range: "All time".into(),
+            },
+            export_url: None,
+        }
+    }
+
+    #[test]
+    fn test_display_formats() {
+        let out = output();
+        let formats = column_formats(&out);
+        let shown: Vec<String> = out.rows[0]
+            .iter()
+            .zip(&formats)
+            .map(|(v, f)| display_value(v, *f))
+            .collect();
+        assert_eq!(
+            shown,
+            ["Caf\u{e9} (launch)", "1,234,567.50", "12.50%", "1:02:03"]
+        );
+        assert_eq!(group_thousands(-999.999), "-1,000.00");
+        assert_eq!(column_letters(0), "A");
+        assert_eq!(column_letters(27), "AB");
+        assert_eq!(column_letters(702), "AAA");
+    }
+
+    #[test]
+    fn test_xlsx_typed_cells() {
+        let bytes = to_xlsx(&output()).unwrap();
+        let mut archive = zip::ZipArchive::new(Cursor::new(bytes)).unwrap();
+        let mut sheet = String::new();
+        archive
+            .by_name("xl/worksheets/sheet1.xml")
+            .unwrap()
+            .read_to_string(&mut sheet)
+            .unwrap();
+        assert!(sheet.contains(r#"<c r="A1" t="inlineStr" s="1"><is><t>campaign</t></is></c>"#));
+        assert!(sheet.contains(r#"<c r="B2" s="2"><v>1234567.5</v></c>"#));
+        assert!(sheet.contains(r#"<c r="C2" s="3"><v>0.125</v></c>"#));
+        assert!(sheet.contains(r#"<c r="B3" s="2"><v>99.9</v></c>"#));
+        assert!(!sheet.contains(r#"r="C3""#));
+        let mut workbook = String::new();
+        archive
+            .by_name("xl/workbook.xml")
+            .unwrap()
+            .read_to_string(&mut workbook)
+            .unwrap();
+        assert!(workbook.contains(r#"name="Q3 &lt;Revenue&gt; &amp; Reach""#));
+        assert!(archive.by_name("[Content_Types].xml").is_ok());
+    }
+
+    #[test]
+    fn test_html_and_pdf() {
+        let html = to_html(&output());
+        assert!(html.contains("<title>Q3 &lt;Revenue&gt; &amp; Reach</title>"));
+        assert!(html.contains("<td class=\"num\">1,234,567.50</td>"));
+        assert!(html.contains("<td>Caf\u{e9} (launch)</td>"));
+
+        let mut big = output();
+        big.rows = (0..60)
+            .map(|i| vec![json!(format!("c{i}")), json!(i), json!(0.5), json!(60)])
+            .collect();
+        let pdf = to_pdf(&big);
+        let text = String::from_utf8_lossy(&pdf);
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(c59)"));
+        // startxref points at the cross-reference table.
+        let start: usize = text
+            .rsplit("startxref\n")
+            .next()
+            .and_then(|t| t.lines().next())
+            .and_then(|n| n.parse().ok())
+            .unwrap();
+        assert!(text[start..].starts_with("xref\n0 9\n"));
+        assert_eq!(pdf_text("a(b)\u{e9}\u{4e2d}"), "(a\\(b\\)\\351?)");
+    }
+}
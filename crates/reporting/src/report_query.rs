@@ -0,0 +1,708 @@
+//! Report query planning — translates a [`ReportDefinition`]'s metrics,
+//! dimensions and filters into a [`ReportQuery`], executed by a
+//! [`QueryBackend`]: ClickHouse over HTTP, or in memory for tests.
+
+use anyhow::{bail, Context};
+use campaign_core::config::ClickHouseConfig;
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::cmp::Ordering;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::pin::Pin;
+
+use crate::report_builder::{
+    Aggregation, DimensionColumn, FilterOperator, MetricColumn, ReportDefinition, ReportFilter,
+    SortOrder,
+};
+
+// ─── Query ──────────────────────────────────────────────────────────────────
+
+/// A validated, backend-independent report query.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReportQuery {
+    pub table: String,
+    pub dimensions: Vec<DimensionColumn>,
+    pub metrics: Vec<MetricColumn>,
+    pub filters: Vec<ReportFilter>,
+    pub order_by: Option<(String, SortOrder)>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct QueryResult {
+    pub columns: Vec<String>,
+    pub rows: Vec<Vec<Value>>,
+    /// Matching rows before `LIMIT` was applied, when the backend knows it.
+    pub rows_before_limit: Option<u64>,
+}
+
+impl ReportQuery {
+    /// Validate a definition and plan its query. Every column name must be a
+    /// plain identifier, output names must be unique and `sort_by` must name
+    /// a selected column.
+    pub fn plan(def: &ReportDefinition) -> anyhow::Result<Self> {
+        if def.metrics.is_empty() && def.dimensions.is_empty() {
+            bail!("report '{}' selects no metrics or dimensions", def.name);
+        }
+        let mut seen = HashSet::new();
+        let outputs = def
+            .dimensions
+            .iter()
+            .map(|d| &d.name)
+            .chain(def.metrics.iter().map(|m| &m.name));
+        for name in outputs {
+            if !seen.insert(name.as_str()) {
+                bail!("column '{name}' is selected more than once");
+            }
+        }
+        let query = Self {
+            table: def.report_type.source_table(),
+            dimensions: def.dimensions.clone(),
+            metrics: def.metrics.clone(),
+            filters: def.filters.clone(),
+            order_by: def
+                .sort_by
+                .as_ref()
+                .map(|column| (column.clone(), def.sort_order.clone())),
+            limit: def.limit,
+        };
+        query.validate()?;
+        Ok(query)
+    }
+
+    /// Check every identifier and filter value. Run by [`Self::plan`] and
+    /// again by [`Self::to_sql`], since a query can also be deserialized.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        check_identifier(&self.table)?;
+        let columns = self.columns();
+        for name in &columns {
+            check_identifier(name)?;
+        }
+        for filter in &self.filters {
+            check_identifier(&filter.field)?;
+            check_filter_value(filter)?;
+        }
+        if let Some((column, _)) = &self.order_by {
+            if !columns.contains(column) {
+                bail!("sort column '{column}' is not selected by the report");
+            }
+        }
+        Ok(())
+    }
+
+    /// Output column names: dimensions first, then metrics.
+    pub fn columns(&self) -> Vec<String> {
+        self.dimensions
+            .iter()
+            .map(|d| d.name.clone())
+            .chain(self.metrics.iter().map(|m| m.name.clone()))
+            .collect()
+    }
+
+    /// ClickHouse SQL for this query. Non-grouped dimensions are selected
+    /// with `any()`; metrics are aliased to their own column name, so the
+    /// query expects `prefer_column_name_to_alias = 1`.
+    pub fn to_sql(&self) -> anyhow::Result<String> {
+        self.validate()?;
+        let mut select: Vec<String> = self
+            .dimensions
+            .iter()
+            .map(|d| {
+                let column = ident(&d.name);
+                if d.group_by {
+                    column
+                } else {
+                    format!("any({column}) AS {column}")
+                }
+            })
+            .collect();
+        select.extend(
+            self.metrics
+                .iter()
+                .map(|m| format!("{} AS {}", aggregate_sql(m), ident(&m.name))),
+        );
+
+        let mut sql = format!("SELECT {} FROM {}", select.join(", "), ident(&self.table));
+        if !self.filters.is_empty() {
+            let clauses = self
+                .filters
+                .iter()
+                .map(filter_sql)
+                .collect::<anyhow::Result<Vec<_>>>()?;
+            sql.push_str(" WHERE ");
+            sql.push_str(&clauses.join(" AND "));
+        }
+        let group_by: Vec<String> = self
+            .dimensions
+            .iter()
+            .filter(|d| d.group_by)
+            .map(|d| ident(&d.name))
+            .collect();
+        if !group_by.is_empty() {
+            sql.push_str(" GROUP BY ");
+            sql.push_str(&group_by.join(", "));
+        }
+        if let Some((column, order)) = &self.order_by {
+            let direction = match order {
+                SortOrder::Ascending => "ASC",
+                SortOrder::Descending => "DESC",
+            };
+            sql.push_str(&format!(" ORDER BY {} {direction}", ident(column)));
+        }
+        if let Some(limit) = self.limit {
+            sql.push_str(&format!(" LIMIT {limit}"));
+        }
+        Ok(sql)
+    }
+}
+
+fn check_identifier(name: &str) -> anyhow::Result<()> {
+    let mut chars = name.chars();
+    let valid = chars
+        .next()
+        .is_some_and(|c| c.is_ascii_alphabetic() || c == '_')
+        && chars.all(|c| c.is_ascii_alphanumeric() || c == '_');
+    if !valid {
+        bail!("'{name}' is not a valid column name");
+    }
+    Ok(())
+}
+
+fn check_filter_value(filter: &ReportFilter) -> anyhow::Result<()> {
+    let field = &filter.field;
+    match (&filter.operator, &filter.value) {
+        (FilterOperator::IsNull | FilterOperator::IsNotNull, _) => Ok(()),
+        (FilterOperator::Between, Value::Array(bounds)) if bounds.len() == 2 => Ok(()),
+        (FilterOperator::Between, _) => bail!("BETWEEN filter on '{field}' needs [low, high]"),
+        (FilterOperator::In, Value::Array(_)) => Ok(()),
+        (FilterOperator::In, _) => bail!("IN filter on '{field}' needs an array"),
+        (FilterOperator::Contains | FilterOperator::StartsWith, Value::String(_)) => Ok(()),
+        (FilterOperator::Contains | FilterOperator::StartsWith, _) => {
+            bail!("text filter on '{field}' needs a string")
+        }
+        (_, Value::Array(_) | Value::Object(_)) => {
+            bail!("filter on '{field}' needs a scalar value")
+        }
+        _ => Ok(()),
+    }
+}
+
+// ─── SQL rendering ──────────────────────────────────────────────────────────
+
+/// Quote an identifier. Names are validated before rendering; escaping
+/// keeps a quoted name closed even if one slips through.
+fn ident(name: &str) -> String {
+    format!("`{}`", name.replace('\\', "\\\\").replace('`', "\\`"))
+}
+
+fn literal(value: &Value) -> anyhow::Result<String> {
+    Ok(match value {
+        Value::Null => "NULL".to_string(),
+        Value::Bool(b) => b.to_string(),
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => format!("'{}'", s.replace('\\', "\\\\").replace('\'', "\\'")),
+        other => bail!("unsupported filter value {other}"),
+    })
+}
+
+fn aggregate_sql(metric: &MetricColumn) -> String {
+    let column = ident(&metric.name);
+    match metric.aggregation {
+        Aggregation::Sum => format!("sum({column})"),
+        Aggregation::Average => format!("avg({column})"),
+        Aggregation::Count => format!("count({column})"),
+        Aggregation::Min => format!("min({column})"),
+        Aggregation::Max => format!("max({column})"),
+        Aggregation::Median => format!("quantile(0.5)({column})"),
+        Aggregation::CountDistinct => format!("uniqExact({column})"),
+        Aggregation::Percentile90 => format!("quantile(0.9)({column})"),
+        Aggregation::Percentile99 => format!("quantile(0.99)({column})"),
+    }
+}
+
+fn filter_sql(filter: &ReportFilter) -> anyhow::Result<String> {
+    let column = ident(&filter.field);
+    let value = &filter.value;
+    Ok(match filter.operator {
+        FilterOperator::Equals => format!("{column} = {}", literal(value)?),
+        FilterOperator::NotEquals => format!("{column} != {}", literal(value)?),
+        FilterOperator::GreaterThan => format!("{column} > {}", literal(value)?),
+        FilterOperator::LessThan => format!("{column} < {}", literal(value)?),
+        FilterOperator::Between => format!(
+            "{column} BETWEEN {} AND {}",
+            literal(&value[0])?,
+            literal(&value[1])?
+        ),
+        FilterOperator::In => {
+            let items = value.as_array().map(Vec::as_slice).unwrap_or_default();
+            if items.is_empty() {
+                "0".to_string()
+            } else {
+                let items = items
+                    .iter()
+                    .map(literal)
+                    .collect::<anyhow::Result<Vec<_>>>()?;
+                format!("{column} IN ({})", items.join(", "))
+            }
+        }
+        FilterOperator::Contains => format!("positionUTF8({column}, {}) > 0", literal(value)?),
+        FilterOperator::StartsWith => format!("startsWith({column}, {})", literal(value)?),
+        FilterOperator::IsNull => format!("isNull({column})"),
+        FilterOperator::IsNotNull => format!("isNotNull({column})"),
+    })
+}
+
+// ─── Backends ───────────────────────────────────────────────────────────────
+
+pub type QueryFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<QueryResult>> + Send + 'a>>;
+
+/// Executes planned report queries.
+pub trait QueryBackend: Send + Sync {
+    fn execute<'a>(&'a self, query: &'a ReportQuery) -> QueryFuture<'a>;
+}
+
+/// Runs queries against ClickHouse's HTTP interface.
+pub struct ClickHouseBackend {
+    http: reqwest::Client,
+    url: String,
+    database: String,
+    credentials: Option<(String, String)>,
+}
+
+#[derive(Deserialize)]
+struct CompactResponse {
+    meta: Vec<CompactColumn>,
+    data: Vec<Vec<Value>>,
+    rows_before_limit_at_least: Option<u64>,
+}
+
+#[derive(Deserialize)]
+struct CompactColumn {
+    name: String,
+}
+
+/// Upper bound on one report query.
+pub const DEFAULT_QUERY_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(60);
+
+impl ClickHouseBackend {
+    pub fn new(config: &ClickHouseConfig) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(DEFAULT_QUERY_TIMEOUT)
+                .build()
+                .unwrap_or_default(),
+            url: config.url.clone(),
+            database: config.database.clone(),
+            credentials: None,
+        }
+    }
+
+    pub fn with_credentials(mut self, user: &str, password: &str) -> Self {
+        self.credentials = Some((user.to_string(), password.to_string()));
+        self
+    }
+
+    async fn fetch(&self, sql: String) -> anyhow::Result<QueryResult> {
+        let mut request = self
+            .http
+            .post(&self.url)
+            .query(&[
+                ("database", self.database.as_str()),
+                ("default_format", "JSONCompact"),
+                ("output_format_json_quote_64bit_integers", "0"),
+                ("prefer_column_name_to_alias", "1"),
+            ])
+            .body(sql);
+        if let Some((user, password)) = &self.credentials {
+            request = request.basic_auth(user, Some(password));
+        }
+        let response = request
+            .send()
+            .await
+            .context("sending report query to ClickHouse")?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            bail!("ClickHouse returned {status}: {}", body.trim());
+        }
+        let body: CompactResponse = response
+            .json()
+            .await
+            .context("decoding ClickHouse response")?;
+        Ok(QueryResult {
+            columns: body.meta.into_iter().map(|c| c.name).collect(),
+            rows: body.data,
+            rows_before_limit: body.rows_before_limit_at_least,
+        })
+    }
+}
+
+impl QueryBackend for ClickHouseBackend {
+    fn execute<'a>(&'a self, query: &'a ReportQuery) -> QueryFuture<'a> {
+        Box::pin(async move { self.fetch(query.to_sql()?).await })
+    }
+}
+
+/// Evaluates queries over rows held in memory, with the same semantics as
+/// the generated SQL.
+#[derive(Default)]
+pub struct InMemoryBackend {
+    tables: DashMap<String, Vec<HashMap<String, Value>>>,
+}
+
+impl InMemoryBackend {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn insert(&self, table: &str, row: HashMap<String, Value>) {
+        self.tables.entry(table.to_string()).or_default().push(row);
+    }
+
+    /// Insert rows given as JSON objects; anything else is skipped.
+    pub fn insert_json(&self, table: &str, rows: impl IntoIterator<Item = Value>) {
+        for row in rows {
+            if let Value::Object(fields) = row {
+                self.insert(table, fields.into_iter().collect());
+            }
+        }
+    }
+
+    pub fn run(&self, query: &ReportQuery) -> QueryResult {
+        let empty = Vec::new();
+        let table = self.tables.get(&query.table);
+        let rows = table.as_deref().unwrap_or(&empty);
+        let matching: Vec<&HashMap<String, Value>> = rows
+            .iter()
+            .filter(|row| query.filters.iter().all(|f| matches_filter(row, f)))
+            .collect();
+
+        // Group keys in first-seen order; without grouping there is always
+        // exactly one (possibly empty) group, as in SQL.
+        let grouped: Vec<&str> = query
+            .dimensions
+            .iter()
+            .filter(|d| d.group_by)
+            .map(|d| d.name.as_str())
+            .collect();
+        let mut groups: Vec<Vec<&HashMap<String, Value>>> = Vec::new();
+        if grouped.is_empty() {
+            groups.push(matching);
+        } else {
+            let mut index: HashMap<String, usize> = HashMap::new();
+            for row in matching {
+                let key: Vec<&Value> = grouped.iter().map(|c| field(row, c)).collect();
+                let key = serde_json::to_string(&key).unwrap_or_default();
+                let slot = *index.entry(key).or_insert_with(|| {
+                    groups.push(Vec::new());
+                    groups.len() - 1
+                });
+                groups[slot].push(row);
+            }
+        }
+
+        let mut out: Vec<Vec<Value>> = groups
+            .iter()
+            .map(|rows| {
+                let dimensions = query.dimensions.iter().map(|d| {
+                    rows.iter()
+                        .map(|row| field(row, &d.name))
+                        .find(|v| !v.is_null())
+                        .cloned()
+                        .unwrap_or(Value::Null)
+                });
+                let metrics = query.metrics.iter().map(|m| {
+                    let values: Vec<&Value> = rows
+                        .iter()
+                        .map(|row| field(row, &m.name))
+                        .filter(|v| !v.is_null())
+                        .collect();
+                    aggregate(&m.aggregation, &values)
+                });
+                dimensions.chain(metrics).collect()
+            })
+            .collect();
+
+        let columns = query.columns();
+        if let Some((column, order)) = &query.order_by {
+            if let Some(i) = columns.iter().position(|c| c == column) {
+                out.sort_by(|a, b| {
+                    // Nulls sort last in either direction.
+                    match (a[i].is_null(), b[i].is_null()) {
+                        (true, true) => Ordering::Equal,
+                        (true, false) => Ordering::Greater,
+                        (false, true) => Ordering::Less,
+                        _ => {
+                            let ord = compare(&a[i], &b[i]).unwrap_or(Ordering::Equal);
+                            match order {
+                                SortOrder::Ascending => ord,
+                                SortOrder::Descending => ord.reverse(),
+                            }
+                        }
+                    }
+                });
+            }
+        }
+        let rows_before_limit = out.len() as u64;
+        if let Some(limit) = query.limit {
+            out.truncate(limit);
+        }
+        QueryResult {
+            columns,
+            rows: out,
+            rows_before_limit: Some(rows_before_limit),
+        }
+    }
+}
+
+impl QueryBackend for InMemoryBackend {
+    fn execute<'a>(&'a self, query: &'a ReportQuery) -> QueryFuture<'a> {
+        Box::pin(async move { Ok(self.run(query)) })
+    }
+}
+
+fn field<'a>(row: &'a HashMap<String, Value>, name: &str) -> &'a Value {
+    row.get(name).unwrap_or(&Value::Null)
+}
+
+/// SQL-style comparison: numbers numerically, strings lexically; `None`
+/// when either side is null or the types differ.
+fn compare(a: &Value, b: &Value) -> Option<Ordering> {
+    match (a, b) {
+        (Value::Number(x), Value::Number(y)) => x.as_f64()?.partial_cmp(&y.as_f64()?),
+        (Value::String(x), Value::String(y)) => Some(x.cmp(y)),
+        (Value::Bool(x), Value::Bool(y)) => Some(x.cmp(y)),
+        _ => None,
+    }
+}
+
+fn matches_filter(row: &HashMap<String, Value>, filter: &ReportFilter) -> bool {
+    let value = field(row, &filter.field);
+    let target = &filter.value;
+    match filter.operator {
+        FilterOperator::Equals => compare(value, target) == Some(Ordering::Equal),
+        FilterOperator::NotEquals => compare(value, target).is_some_and(|o| o != Ordering::Equal),
+        FilterOperator::GreaterThan => compare(value, target) == Some(Ordering::Greater),
+        FilterOperator::LessThan => compare(value, target) == Some(Ordering::Less),
+        FilterOperator::Between => {
+            compare(value, &target[0]).is_some_and(|o| o != Ordering::Less)
+                && compare(value, &target[1]).is_some_and(|o| o != Ordering::Greater)
+        }
+        FilterOperator::In => target.as_array().is_some_and(|items| {
+            items
+                .iter()
+                .any(|t| compare(value, t) == Some(Ordering::Equal))
+        }),
+        FilterOperator::Contains => match (value, target) {
+            (Value::String(s), Value::String(t)) => s.contains(t.as_str()),
+            _ => false,
+        },
+        FilterOperator::StartsWith => match (value, target) {
+            (Value::String(s), Value::String(t)) => s.starts_with(t.as_str()),
+            _ => false,
+        },
+        FilterOperator::IsNull => value.is_null(),
+        FilterOperator::IsNotNull => !value.is_null(),
+    }
+}
+
+fn number(value: f64, integral: bool) -> Value {
+    if integral && value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+        Value::from(value as i64)
+    } else {
+        Value::from(value)
+    }
+}
+
+/// Aggregate the non-null values of one column within a group.
+fn aggregate(aggregation: &Aggregation, values: &[&Value]) -> Value {
+    let numbers: Vec<f64> = values.iter().filter_map(|v| v.as_f64()).collect();
+    let integral = values.iter().all(|v| v.is_i64() || v.is_u64());
+    match aggregation {
+        Aggregation::Count => Value::from(values.len() as u64),
+        Aggregation::CountDistinct => {
+            let distinct: HashSet<String> = values.iter().map(|v| v.to_string()).collect();
+            Value::from(distinct.len() as u64)
+        }
+        Aggregation::Sum => number(numbers.iter().sum(), integral),
+        Aggregation::Average if numbers.is_empty() => Value::Null,
+        Aggregation::Average => Value::from(numbers.iter().sum::<f64>() / numbers.len() as f64),
+        Aggregation::Min | Aggregation::Max => {
+            let want = match aggregation {
+                Aggregation::Min => Ordering::Less,
+                _ => Ordering::Greater,
+            };
+            values
+                .iter()
+                .copied()
+                .reduce(|best, v| {
+                    if compare(v, best) == Some(want) {
+                        v
+                    } else {
+                        best
+                    }
+                })
+                .cloned()
+                .unwrap_or(Value::Null)
+        }
+        Aggregation::Median => quantile(numbers, 0.5),
+        Aggregation::Percentile90 => quantile(numbers, 0.9),
+        Aggregation::Percentile99 => quantile(numbers, 0.99),
+    }
+}
+
+/// Linearly interpolated quantile.
+fn quantile(mut numbers: Vec<f64>, q: f64) -> Value {
+    if numbers.is_empty() {
+        return Value::Null;
+    }
+    numbers.sort_by(|a, b| a.total_cmp(b));
+    let pos = q * (numbers.len() - 1) as f64;
+    let (lo, hi) = (pos.floor() as usize, pos.ceil() as usize);
+    Value::from(numbers[lo] + (numbers[hi] - numbers[lo]) * (pos - lo as f64))
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_builder::{MetricFormat, ReportType};
+    use chrono::Utc;
+    use serde_json::json;
+    use uuid::Uuid;
+
+    fn metric(name: &str, aggregation: Aggregation) -> MetricColumn {
+        MetricColumn {
+            name: name.into(),
+            aggregation,
+            format: MetricFormat::Number,
+        }
+    }
+
+    fn filter(field: &str, operator: FilterOperator, value: Value) -> ReportFilter {
+        ReportFilter {
+            field: field.into(),
+            operator,
+            value,
+        }
+    }
+
+    fn definition() -> ReportDefinition {
+        ReportDefinition {
+            id: Uuid::new_v4(),
+            name: "Channel revenue".into(),
+            description: String::new(),
+            report_type: ReportType::ChannelComparison,
+            metrics: vec![
+                metric("revenue", Aggregation::Sum),
+                metric("user_id", Aggregation::CountDistinct),
+                metric("latency_ms", Aggregation::Median),
+            ],
+            dimensions: vec![DimensionColumn {
+                name: "channel".into(),
+                group_by: true,
+            }],
+            filters: vec![
+                filter("campaign", FilterOperator::StartsWith, json!("O'Brien")),
+                filter("revenue", FilterOperator::Between, json!([1, 500])),
+            ],
+            sort_by: Some("revenue".into()),
+            sort_order: SortOrder::Descending,
+            limit: Some(2),
+            created_by: Uuid::nil(),
+            schedule: None,
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_sql_rendering() {
+        let sql = ReportQuery::plan(&definition()).unwrap().to_sql().unwrap();
+        assert_eq!(
+            sql,
+            "SELECT `channel`, sum(`revenue`) AS `revenue`, uniqExact(`user_id`) AS `user_id`, \
+             quantile(0.5)(`latency_ms`) AS `latency_ms` FROM `channel_rollup_daily` \
+             WHERE startsWith(`campaign`, 'O\\'Brien') AND `revenue` BETWEEN 1 AND 500 \
+             GROUP BY `channel` ORDER BY `revenue` DESC LIMIT 2"
+        );
+
+        let mut bad = definition();
+        bad.dimensions[0].name = "channel; DROP TABLE x".into();
+        assert!(ReportQuery::plan(&bad).is_err());
+        let mut bad = definition();
+        bad.sort_by = Some("clicks".into());
+        assert!(ReportQuery::plan(&bad).is_err());
+        let mut bad = definition();
+        bad.filters[1].value = json!(3);
+        assert!(ReportQuery::plan(&bad).is_err());
+    }
+
+    #[test]
+    fn test_deserialized_query_is_validated_and_escaped() {
+        let planned = ReportQuery::plan(&definition()).unwrap();
+        let tampered = |edit: fn(&mut Value)| {
+            let mut value = serde_json::to_value(&planned).unwrap();
+            edit(&mut value);
+            serde_json::from_value::<ReportQuery>(value).unwrap()
+        };
+        let bad = [
+            tampered(|q| q["table"] = json!("x` UNION SELECT 1 --")),
+            tampered(|q| q["dimensions"][0]["name"] = json!("a`b")),
+            tampered(|q| q["metrics"][0]["name"] = json!("revenue) FROM secrets --")),
+            tampered(|q| q["filters"][0]["field"] = json!("1=1 OR x")),
+            tampered(|q| q["filters"][1]["value"] = json!({"a": 1})),
+            tampered(|q| q["order_by"] = json!(["missing", "ascending"])),
+        ];
+        for query in bad {
+            assert!(query.to_sql().is_err(), "{query:?}");
+        }
+
+        assert_eq!(ident("a`b"), "`a\\`b`");
+        assert_eq!(ident("a\\"), "`a\\\\`");
+    }
+
+    #[test]
+    fn test_in_memory_execution() {
+        let backend = InMemoryBackend::new();
+        backend.insert_json(
+            "channel_rollup_daily",
+            [
+                json!({"channel": "email", "campaign": "O'Brien", "revenue": 100, "user_id": "a", "latency_ms": 10}),
+                json!({"channel": "email", "campaign": "O'Brien 2", "revenue": 300, "user_id": "a", "latency_ms": 30}),
+                json!({"channel": "push", "campaign": "O'Brien", "revenue": 50, "user_id": "b", "latency_ms": 5}),
+                json!({"channel": "sms", "campaign": "O'Brien", "revenue": 20, "user_id": "c"}),
+                json!({"channel": "sms", "campaign": "Other", "revenue": 900, "user_id": "d"}),
+                json!({"channel": "web", "campaign": "O'Brien", "revenue": 0, "user_id": "e"}),
+            ],
+        );
+        let query = ReportQuery::plan(&definition()).unwrap();
+        let result = backend.run(&query);
+        assert_eq!(
+            result.columns,
+            ["channel", "revenue", "user_id", "latency_ms"]
+        );
+        assert_eq!(result.rows_before_limit, Some(3));
+        assert_eq!(
+            result.rows,
+            [
+                vec![json!("email"), json!(400), json!(1), json!(20.0)],
+                vec![json!("push"), json!(50), json!(1), json!(5.0)],
+            ]
+        );
+
+        // Without grouping, one row aggregates everything that matched.
+        let mut def = definition();
+        def.dimensions.clear();
+        def.filters = vec![filter("channel", FilterOperator::In, json!(["sms", "web"]))];
+        def.metrics.push(metric("revenue_max", Aggregation::Max));
+        let result = backend.run(&ReportQuery::plan(&def).unwrap());
+        assert_eq!(
+            result.rows,
+            [vec![json!(920), json!(3), Value::Null, Value::Null]]
+        );
+    }
+}
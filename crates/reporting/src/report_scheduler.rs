@@ -0,0 +1,699 @@
+//! Report scheduler — runs due [`ReportSchedule`] entries, exports them in
+//! the scheduled format and delivers the file to each recipient by email or
+//! webhook. Webhooks may only target hosts a [`WebhookPolicy`] allows.
+
+use anyhow::{bail, Context};
+use base64::Engine;
+use campaign_core::channels::SendGridConfig;
+use chrono::{DateTime, Utc};
+use dashmap::DashMap;
+use serde::{Deserialize, Serialize};
+use std::future::Future;
+use std::net::{IpAddr, SocketAddr};
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::Duration;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::report_builder::{ExportFormat, ReportBuilder, ReportDefinition, ReportSchedule};
+
+/// Where a scheduled report is sent. Recipients are configured as plain
+/// strings: `http(s)://` URLs are webhooks, anything with an `@` is email.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case", tag = "kind", content = "address")]
+pub enum ReportRecipient {
+    Email(String),
+    Webhook(String),
+}
+
+impl ReportRecipient {
+    pub fn parse(recipient: &str) -> anyhow::Result<Self> {
+        let recipient = recipient.trim();
+        if recipient.starts_with("https://") || recipient.starts_with("http://") {
+            Ok(Self::Webhook(recipient.to_string()))
+        } else if recipient.contains('@') {
+            Ok(Self::Email(recipient.to_string()))
+        } else {
+            bail!("recipient '{recipient}' is neither an email address nor a webhook URL")
+        }
+    }
+}
+
+/// Which hosts report webhooks may be posted to. Webhooks must use HTTPS
+/// and name a host, not an IP address or `localhost`; with an allowlist,
+/// the host must be listed or be a subdomain of a listed host.
+#[derive(Debug, Clone, Default)]
+pub struct WebhookPolicy {
+    allowed_hosts: Vec<String>,
+    unrestricted: bool,
+}
+
+impl WebhookPolicy {
+    pub fn new(allowed_hosts: &[String]) -> Self {
+        Self {
+            allowed_hosts: allowed_hosts
+                .iter()
+                .map(|h| h.trim().trim_start_matches('.').to_ascii_lowercase())
+                .filter(|h| !h.is_empty())
+                .collect(),
+            unrestricted: false,
+        }
+    }
+
+    /// Allow any URL, e.g. loopback test servers.
+    #[cfg(test)]
+    fn unrestricted() -> Self {
+        Self {
+            unrestricted: true,
+            ..Self::default()
+        }
+    }
+
+    pub fn check(&self, url: &str) -> anyhow::Result<()> {
+        if self.unrestricted {
+            return Ok(());
+        }
+        let parsed =
+            reqwest::Url::parse(url).with_context(|| format!("invalid webhook URL {url}"))?;
+        if parsed.scheme() != "https" {
+            bail!("webhook {url} must use https");
+        }
+        let host = parsed
+            .host_str()
+            .map(|h| h.trim_end_matches('.').to_ascii_lowercase())
+            .unwrap_or_default();
+        if host.is_empty()
+            || host
+                .trim_matches(|c| c == '[' || c == ']')
+                .parse::<std::net::IpAddr>()
+                .is_ok()
+            || host == "localhost"
+            || host.ends_with(".localhost")
+        {
+            bail!("webhook {url} must name a public host");
+        }
+        let allowed = self.allowed_hosts.is_empty()
+            || self
+                .allowed_hosts
+                .iter()
+                .any(|a| host == *a || host.ends_with(&format!(".{a}")));
+        if !allowed {
+            bail!("webhook host {host} is not in the allowed report webhook hosts");
+        }
+        Ok(())
+    }
+
+    /// Parse a configured recipient, rejecting webhooks this policy does
+    /// not allow.
+    pub fn recipient(&self, recipient: &str) -> anyhow::Result<ReportRecipient> {
+        let recipient = ReportRecipient::parse(recipient)?;
+        if let ReportRecipient::Webhook(url) = &recipient {
+            self.check(url)?;
+        }
+        Ok(recipient)
+    }
+}
+
+/// Whether `ip` is publicly routable: not loopback, private, link-local,
+/// unique-local, shared, documentation, multicast or unspecified.
+fn is_public_ip(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(v4) => {
+            let [a, b, ..] = v4.octets();
+            !(v4.is_loopback()
+                || v4.is_private()
+                || v4.is_link_local()
+                || v4.is_unspecified()
+                || v4.is_broadcast()
+                || v4.is_documentation()
+                || v4.is_multicast()
+                || a == 0
+                || (a == 100 && (64..128).contains(&b)))
+        }
+        IpAddr::V6(v6) => {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return is_public_ip(IpAddr::V4(v4));
+            }
+            let first = v6.segments()[0];
+            !(v6.is_loopback()
+                || v6.is_unspecified()
+                || v6.is_multicast()
+                || (first & 0xfe00) == 0xfc00
+                || (first & 0xffc0) == 0xfe80
+                || first == 0x2001 && v6.segments()[1] == 0x0db8)
+        }
+    }
+}
+
+/// DNS resolver for webhook delivery that only hands out public addresses,
+/// so a permitted host name that resolves to an internal address is refused
+/// at connect time.
+struct PublicResolver;
+
+impl reqwest::dns::Resolve for PublicResolver {
+    fn resolve(&self, name: reqwest::dns::Name) -> reqwest::dns::Resolving {
+        let host = name.as_str().to_string();
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), 0))
+                .await?
+                .filter(|addr| is_public_ip(addr.ip()))
+                .collect();
+            if addrs.is_empty() {
+                return Err(format!("{host} does not resolve to a public address").into());
+            }
+            Ok(Box::new(addrs.into_iter()) as reqwest::dns::Addrs)
+        })
+    }
+}
+
+/// An exported report ready for delivery.
+#[derive(Debug, Clone)]
+pub struct ReportArtifact {
+    pub report_id: Uuid,
+    pub report_name: String,
+    pub format: ExportFormat,
+    pub file_name: String,
+    pub bytes: Vec<u8>,
+    pub generated_at: DateTime<Utc>,
+}
+
+impl ReportArtifact {
+    fn new(def: &ReportDefinition, format: ExportFormat, bytes: Vec<u8>) -> Self {
+        let stem: String = def
+            .name
+            .chars()
+            .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+            .collect();
+        let now = Utc::now();
+        Self {
+            report_id: def.id,
+            report_name: def.name.clone(),
+            file_name: format!("{stem}_{}.{}", now.format("%Y%m%d"), format.extension()),
+            format,
+            bytes,
+            generated_at: now,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeliveryRecord {
+    pub report_id: Uuid,
+    pub recipient: String,
+    pub format: ExportFormat,
+    pub bytes: usize,
+    pub delivered_at: DateTime<Utc>,
+    pub error: Option<String>,
+}
+
+pub type DeliveryFuture<'a> = Pin<Box<dyn Future<Output = anyhow::Result<()>> + Send + 'a>>;
+
+/// Sends an exported report to one recipient.
+pub trait ReportDelivery: Send + Sync {
+    fn deliver<'a>(
+        &'a self,
+        recipient: &'a ReportRecipient,
+        artifact: &'a ReportArtifact,
+    ) -> DeliveryFuture<'a>;
+}
+
+/// SendGrid-compatible mail API used for emailed reports.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct EmailRelayConfig {
+    pub api_url: String,
+    pub api_key: String,
+    pub from_email: String,
+}
+
+impl EmailRelayConfig {
+    /// Relay through the channel's SendGrid account; `None` without an API key.
+    pub fn from_sendgrid(config: &SendGridConfig) -> Option<Self> {
+        if config.api_key.is_empty() {
+            return None;
+        }
+        Some(Self {
+            api_url: format!("{}/v3/mail/send", config.api_base_url.trim_end_matches('/')),
+            api_key: config.api_key.clone(),
+            from_email: config.from_email.clone(),
+        })
+    }
+}
+
+/// Upper bound on one webhook or mail API call, so a hung recipient cannot
+/// stall the scheduler.
+pub const DEFAULT_DELIVERY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Delivers webhooks as a raw POST of the file and email through the
+/// configured mail API with the file attached. Webhook URLs are checked
+/// against the [`WebhookPolicy`] again at send time, host names must resolve
+/// to public addresses, and redirects are not followed.
+pub struct HttpReportDelivery {
+    http: reqwest::Client,
+    email: Option<EmailRelayConfig>,
+    webhooks: WebhookPolicy,
+}
+
+impl HttpReportDelivery {
+    pub fn new() -> Self {
+        Self::with_timeout(DEFAULT_DELIVERY_TIMEOUT)
+    }
+
+    pub fn with_timeout(timeout: Duration) -> Self {
+        Self {
+            http: reqwest::Client::builder()
+                .timeout(timeout)
+                .redirect(reqwest::redirect::Policy::none())
+                .dns_resolver(Arc::new(PublicResolver))
+                .build()
+                .unwrap_or_default(),
+            email: None,
+            webhooks: WebhookPolicy::default(),
+        }
+    }
+
+    pub fn with_email(mut self, config: EmailRelayConfig) -> Self {
+        self.email = Some(config);
+        self
+    }
+
+    pub fn with_webhook_policy(mut self, webhooks: WebhookPolicy) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    async fn post_webhook(&self, url: &str, artifact: &ReportArtifact) -> anyhow::Result<()> {
+        self.webhooks.check(url)?;
+        let response = self
+            .http
+            .post(url)
+            .header(
+                reqwest::header::CONTENT_TYPE,
+                artifact.format.content_type(),
+            )
+            .header(
+                reqwest::header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", artifact.file_name),
+            )
+            .header("X-Report-Id", artifact.report_id.to_string())
+            .body(artifact.bytes.clone())
+            .send()
+            .await
+            .with_context(|| format!("posting report to {url}"))?;
+        if !response.status().is_success() {
+            bail!("webhook {url} returned {}", response.status());
+        }
+        Ok(())
+    }
+
+    async fn send_email(&self, to: &str, artifact: &ReportArtifact) -> anyhow::Result<()> {
+        let Some(config) = &self.email else {
+            bail!("email delivery is not configured");
+        };
+        let body = serde_json::json!({
+            "personalizations": [{ "to": [{ "email": to }] }],
+            "from": { "email": config.from_email },
+            "subject": format!("Report: {}", artifact.report_name),
+            "content": [{
+                "type": "text/plain",
+                "value": format!(
+                    "Your scheduled report \"{}\" generated at {} is attached.",
+                    artifact.report_name,
+                    artifact.generated_at.format("%Y-%m-%d %H:%M UTC")
+                ),
+            }],
+            "attachments": [{
+                "content": base64::engine::general_purpose::STANDARD.encode(&artifact.bytes),
+                "filename": artifact.file_name,
+                "type": artifact.format.content_type(),
+                "disposition": "attachment",
+            }],
+        });
+        let response = self
+            .http
+            .post(&config.api_url)
+            .bearer_auth(&config.api_key)
+            .json(&body)
+            .send()
+            .await
+            .context("sending report email")?;
+        if !response.status().is_success() {
+            bail!("mail API returned {}", response.status());
+        }
+        Ok(())
+    }
+}
+
+impl Default for HttpReportDelivery {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ReportDelivery for HttpReportDelivery {
+    fn deliver<'a>(
+        &'a self,
+        recipient: &'a ReportRecipient,
+        artifact: &'a ReportArtifact,
+    ) -> DeliveryFuture<'a> {
+        Box::pin(async move {
+            match recipient {
+                ReportRecipient::Webhook(url) => self.post_webhook(url, artifact).await,
+                ReportRecipient::Email(to) => self.send_email(to, artifact).await,
+            }
+        })
+    }
+}
+
+// ─── Scheduler ──────────────────────────────────────────────────────────────
+
+/// Delivery records kept per report; older ones are dropped.
+pub const MAX_HISTORY_PER_REPORT: usize = 200;
+
+pub struct ReportScheduler {
+    builder: Arc<ReportBuilder>,
+    delivery: Arc<dyn ReportDelivery>,
+    history: DashMap<Uuid, Vec<DeliveryRecord>>,
+}
+
+impl ReportScheduler {
+    pub fn new(builder: Arc<ReportBuilder>, delivery: Arc<dyn ReportDelivery>) -> Self {
+        Self {
+            builder,
+            delivery,
+            history: DashMap::new(),
+        }
+    }
+
+    /// Generate and deliver every report due at `now`, then advance each
+    /// schedule. Failures are recorded per recipient and do not stop other
+    /// deliveries; a failed run is not retried until its next slot.
+    pub async fn run_due(&self, now: DateTime<Utc>) -> Vec<DeliveryRecord> {
+        let mut records = Vec::new();
+        for def in self.builder.get_due_reports(now) {
+            let Some(schedule) = def.schedule.clone() else {
+                continue;
+            };
+            let run = self.run_report(&def, &schedule).await;
+            self.builder.advance_schedule(&def.id, now);
+            let mut history = self.history.entry(def.id).or_default();
+            history.extend(run.iter().cloned());
+            let excess = history.len().saturating_sub(MAX_HISTORY_PER_REPORT);
+            history.drain(..excess);
+            drop(history);
+            records.extend(run);
+        }
+        records
+    }
+
+    async fn run_report(
+        &self,
+        def: &ReportDefinition,
+        schedule: &ReportSchedule,
+    ) -> Vec<DeliveryRecord> {
+        let record = |recipient: &str, bytes: usize, error: Option<String>| DeliveryRecord {
+            report_id: def.id,
+            recipient: recipient.to_string(),
+            format: schedule.format.clone(),
+            bytes,
+            delivered_at: Utc::now(),
+            error,
+        };
+
+        let artifact = match self.export(def, &schedule.format).await {
+            Ok(artifact) => artifact,
+            Err(e) => {
+                warn!(report = %def.id, error = %e, "Scheduled report failed to generate");
+                return schedule
+                    .recipients
+                    .iter()
+                    .map(|r| record(r, 0, Some(format!("{e:#}"))))
+                    .collect();
+            }
+        };
+
+        let mut records = Vec::with_capacity(schedule.recipients.len());
+        for recipient in &schedule.recipients {
+            let result = match ReportRecipient::parse(recipient) {
+                Ok(target) => self.delivery.deliver(&target, &artifact).await,
+                Err(e) => Err(e),
+            };
+            let error = result.err().map(|e| format!("{e:#}"));
+            match &error {
+                None => {
+                    info!(report = %def.id, recipient = %recipient, "Scheduled report delivered")
+                }
+                Some(e) => {
+                    warn!(report = %def.id, recipient = %recipient, error = %e, "Scheduled report delivery failed")
+                }
+            }
+            records.push(record(recipient, artifact.bytes.len(), error));
+        }
+        records
+    }
+
+    async fn export(
+        &self,
+        def: &ReportDefinition,
+        format: &ExportFormat,
+    ) -> anyhow::Result<ReportArtifact> {
+        self.builder.generate(&def.id).await?;
+        let bytes = self.builder.export(&def.id, format)?;
+        Ok(ReportArtifact::new(def, format.clone(), bytes))
+    }
+
+    pub fn history(&self, report_id: &Uuid) -> Vec<DeliveryRecord> {
+        self.history
+            .get(report_id)
+            .map(|h| h.clone())
+            .unwrap_or_default()
+    }
+
+    /// Check for due reports every `tick`.
+    pub fn spawn(self: Arc<Self>, tick: std::time::Duration) -> tokio::task::JoinHandle<()> {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(tick);
+            loop {
+                interval.tick().await;
+                self.run_due(Utc::now()).await;
+            }
+        })
+    }
+}
+
+// ─── Tests ──────────────────────────────────────────────────────────────────
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::report_builder::{
+        Aggregation, DimensionColumn, MetricColumn, MetricFormat, ReportType, ScheduleFrequency,
+        SortOrder,
+    };
+    use crate::report_query::InMemoryBackend;
+    use chrono::Duration;
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct CaptureDelivery {
+        sent: Mutex<Vec<(ReportRecipient, String, usize)>>,
+    }
+
+    impl ReportDelivery for CaptureDelivery {
+        fn deliver<'a>(
+            &'a self,
+            recipient: &'a ReportRecipient,
+            artifact: &'a ReportArtifact,
+        ) -> DeliveryFuture<'a> {
+            Box::pin(async move {
+                if matches!(recipient, ReportRecipient::Webhook(url) if url.contains("broken")) {
+                    bail!("connection refused");
+                }
+                self.sent.lock().unwrap().push((
+                    recipient.clone(),
+                    artifact.file_name.clone(),
+                    artifact.bytes.len(),
+                ));
+                Ok(())
+            })
+        }
+    }
+
+    fn scheduled(next_run: DateTime<Utc>, recipients: &[&str]) -> ReportDefinition {
+        ReportDefinition {
+            id: Uuid::new_v4(),
+            name: "Weekly revenue".into(),
+            description: String::new(),
+            report_type: ReportType::CampaignPerformance,
+            metrics: vec![MetricColumn {
+                name: "revenue".into(),
+                aggregation: Aggregation::Sum,
+                format: MetricFormat::Currency,
+            }],
+            dimensions: vec![DimensionColumn {
+                name: "campaign".into(),
+                group_by: true,
+            }],
+            filters: vec![],
+            sort_by: None,
+            sort_order: SortOrder::Descending,
+            limit: None,
+            created_by: Uuid::nil(),
+            schedule: Some(ReportSchedule {
+                frequency: ScheduleFrequency::Weekly,
+                recipients: recipients.iter().map(|r| r.to_string()).collect(),
+                format: ExportFormat::Excel,
+                next_run,
+                enabled: true,
+                timezone: "UTC".into(),
+            }),
+            created_at: Utc::now(),
+            updated_at: Utc::now(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_delivery_times_out() {
+        use axum::routing::post;
+        let app = axum::Router::new().route(
+            "/hook",
+            post(|| async {
+                tokio::time::sleep(std::time::Duration::from_secs(5)).await;
+                "late"
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+        let delivery = HttpReportDelivery::with_timeout(std::time::Duration::from_millis(100))
+            .with_webhook_policy(WebhookPolicy::unrestricted());
+        let def = scheduled(Utc::now(), &[]);
+        let artifact = ReportArtifact::new(&def, ExportFormat::Csv, b"a,b".to_vec());
+        let started = std::time::Instant::now();
+        let result = delivery
+            .deliver(
+                &ReportRecipient::Webhook(format!("http://{addr}/hook")),
+                &artifact,
+            )
+            .await;
+        assert!(result.is_err());
+        assert!(started.elapsed() < std::time::Duration::from_secs(2));
+    }
+
+    #[test]
+    fn test_webhook_policy_restricts_hosts() {
+        let open = WebhookPolicy::default();
+        assert!(open.check("https://hooks.example.com/r").is_ok());
+        for url in [
+            "http://hooks.example.com/r",
+            "https://127.0.0.1/r",
+            "https://[::1]/r",
+            "https://169.254.169.254/latest/meta-data",
+            "https://localhost:8080/r",
+            "not a url",
+        ] {
+            assert!(open.check(url).is_err(), "{url}");
+        }
+
+        let listed = WebhookPolicy::new(&["example.com".to_string()]);
+        assert!(listed.check("https://hooks.example.com/r").is_ok());
+        assert!(listed.check("https://example.com/r").is_ok());
+        assert!(listed.check("https://example.com.evil.test/r").is_err());
+        assert!(listed.check("https://notexample.com/r").is_err());
+        assert!(listed.recipient("ops@example.org").is_ok());
+        assert!(listed.recipient("https://evil.test/r").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_resolver_refuses_internal_addresses() {
+        use reqwest::dns::Resolve;
+        use std::str::FromStr;
+        for ip in [
+            "10.0.0.1",
+            "172.16.5.4",
+            "192.168.1.1",
+            "169.254.169.254",
+            "100.64.0.1",
+        ] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        for ip in ["::1", "fd00::1", "fe80::1", "::ffff:127.0.0.1"] {
+            assert!(!is_public_ip(ip.parse().unwrap()), "{ip}");
+        }
+        assert!(is_public_ip("93.184.216.34".parse().unwrap()));
+        assert!(is_public_ip("2606:4700::1111".parse().unwrap()));
+
+        let name = reqwest::dns::Name::from_str("localhost").unwrap();
+        assert!(PublicResolver.resolve(name).await.is_err());
+    }
+
+    #[test]
+    fn test_email_relay_from_sendgrid() {
+        let mut sendgrid = SendGridConfig::default();
+        assert!(EmailRelayConfig::from_sendgrid(&sendgrid).is_none());
+        sendgrid.api_key = "SG.key".into();
+        sendgrid.api_base_url = "https://api.sendgrid.com/".into();
+        let relay = EmailRelayConfig::from_sendgrid(&sendgrid).unwrap();
+        assert_eq!(relay.api_url, "https://api.sendgrid.com/v3/mail/send");
+    }
+
+    #[tokio::test]
+    async fn test_run_due_delivers_and_advances() {
+        let backend = Arc::new(InMemoryBackend::new());
+        backend.insert_json(
+            "campaign_rollup_daily",
+            [serde_json::json!({"campaign": "Summer", "revenue": 10.5})],
+        );
+        let builder = Arc::new(ReportBuilder::new().with_backend(backend));
+        let now = Utc::now();
+        let due = builder.create_report(scheduled(
+            now - Duration::days(15),
+            &[
+                "ops@example.com",
+                "https://hooks.example.com/r",
+                "https://broken.example.com",
+                "nobody",
+            ],
+        ));
+        let later = builder.create_report(scheduled(now + Duration::hours(1), &["a@example.com"]));
+
+        let delivery = Arc::new(CaptureDelivery::default());
+        let scheduler = ReportScheduler::new(builder.clone(), delivery.clone());
+        let records = scheduler.run_due(now).await;
+
+        assert_eq!(records.len(), 4);
+        let errors: Vec<bool> = records.iter().map(|r| r.error.is_some()).collect();
+        assert_eq!(errors, [false, false, true, true]);
+        let sent = delivery.sent.lock().unwrap().clone();
+        assert_eq!(sent[0].0, ReportRecipient::Email("ops@example.com".into()));
+        assert!(sent[1].1.starts_with("Weekly_revenue_") && sent[1].1.ends_with(".xlsx"));
+        assert!(sent[1].2 > 0);
+        assert_eq!(scheduler.history(&due).len(), 4);
+
+        // Two missed weekly slots are skipped, not replayed.
+        let next = builder.get_report(&due).unwrap().schedule.unwrap().next_run;
+        assert_eq!(next, now - Duration::days(15) + Duration::weeks(3));
+        assert!(scheduler.history(&later).is_empty());
+        assert!(scheduler.run_due(now).await.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_history_is_capped_per_report() {
+        let builder = Arc::new(ReportBuilder::new().with_backend(Arc::new(InMemoryBackend::new())));
+        let recipients: Vec<String> = (0..MAX_HISTORY_PER_REPORT)
+            .map(|i| format!("r{i}@example.com"))
+            .collect();
+        let recipients: Vec<&str> = recipients.iter().map(String::as_str).collect();
+        let now = Utc::now();
+        let id = builder.create_report(scheduled(now - Duration::weeks(1), &recipients));
+
+        let scheduler = ReportScheduler::new(builder, Arc::new(CaptureDelivery::default()));
+        scheduler.run_due(now).await;
+        let second = scheduler.run_due(now + Duration::weeks(1)).await;
+        assert_eq!(second.len(), MAX_HISTORY_PER_REPORT);
+
+        let history = scheduler.history(&id);
+        assert_eq!(history.len(), MAX_HISTORY_PER_REPORT);
+    }
+}
@@ -1,11 +1,25 @@
 //! Multi-Armed Bandit engine — Thompson Sampling, UCB1, Epsilon-Greedy
 //! for dynamic creative optimization.
-
-use chrono::{DateTime, Utc};
+//!
+//! Rewards may be binary conversions (Beta posterior) or continuous values
+//! such as revenue (Normal-Gamma or Gamma posteriors). Discounted and
+//! sliding-window statistics let arms recover from creative fatigue, and
+//! conversions can be joined back to earlier selections by decision ID.
+//! Learned state survives restarts through [`BanditEngine::save`] and
+//! [`BanditEngine::load`].
+
+use chrono::{DateTime, Duration, Utc};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::path::Path;
 use uuid::Uuid;
 
+use crate::persistence;
+
+/// Bumped whenever [`BanditSnapshot`] changes incompatibly.
+const SNAPSHOT_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "snake_case")]
 #[derive(Default)]
@@ -18,6 +32,32 @@ pub enum BanditAlgorithm {
     },
 }
 
+/// Likelihood of a single reward, which fixes the Thompson posterior.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RewardModel {
+    /// 0/1 conversions with a Beta(1, 1) prior.
+    #[default]
+    Bernoulli,
+    /// Real-valued rewards, Normal with unknown mean and precision.
+    Gaussian,
+    /// Non-negative rewards such as revenue, Exponential with a Gamma
+    /// prior on the rate.
+    Gamma,
+}
+
+/// How quickly old observations are forgotten.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Stationarity {
+    #[default]
+    Stationary,
+    /// Every new impression scales the weight of earlier ones by `gamma`.
+    Discounted { gamma: f64 },
+    /// Only the last `size` impressions of each arm count.
+    SlidingWindow { size: usize },
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BanditConfig {
     pub campaign_id: Uuid,
@@ -25,6 +65,10 @@ pub struct BanditConfig {
     pub min_exploration_rate: f64,
     pub variants: Vec<VariantConfig>,
     pub created_at: DateTime<Utc>,
+    #[serde(default)]
+    pub reward_model: RewardModel,
+    #[serde(default)]
+    pub stationarity: Stationarity,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,17 +92,182 @@ pub struct VariantStats {
     pub estimated_value: f64,
 }
 
-#[derive(Debug, Clone)]
-struct VariantState {
-    alpha: f64,
-    beta: f64,
-    impressions: u64,
-    conversions: u64,
+/// Learned statistics of one arm. `weight`, `reward_sum` and
+/// `reward_sq_sum` are the effective (discounted or windowed) sufficient
+/// statistics; the counters are lifetime totals for reporting.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct VariantState {
+    pub impressions: u64,
+    pub conversions: u64,
+    pub total_reward: f64,
+    pub weight: f64,
+    pub reward_sum: f64,
+    pub reward_sq_sum: f64,
+    /// `(impression sequence, reward)` under a sliding window.
+    pub window: VecDeque<(u64, f64)>,
+}
+
+impl VariantState {
+    /// Count a new impression with no reward yet; returns its sequence.
+    fn observe(&mut self, stationarity: Stationarity) -> u64 {
+        let sequence = self.impressions;
+        self.impressions += 1;
+        match stationarity {
+            Stationarity::Stationary => self.weight += 1.0,
+            Stationarity::Discounted { gamma } => {
+                self.weight = self.weight * gamma + 1.0;
+                self.reward_sum *= gamma;
+                self.reward_sq_sum *= gamma;
+            }
+            Stationarity::SlidingWindow { size } => {
+                self.window.push_back((sequence, 0.0));
+                while self.window.len() > size.max(1) {
+                    self.window.pop_front();
+                }
+                self.sync_window();
+            }
+        }
+        sequence
+    }
+
+    /// Credit `value` to the impression with the given sequence number.
+    fn reward(&mut self, stationarity: Stationarity, sequence: u64, value: f64) {
+        if value > 0.0 {
+            self.conversions += 1;
+        }
+        self.total_reward += value;
+        match stationarity {
+            Stationarity::Stationary => {
+                self.reward_sum += value;
+                self.reward_sq_sum += value * value;
+            }
+            Stationarity::Discounted { gamma } => {
+                let age = self.impressions.saturating_sub(sequence + 1);
+                let w = gamma.powi(age.min(i32::MAX as u64) as i32);
+                self.reward_sum += w * value;
+                self.reward_sq_sum += w * value * value;
+            }
+            Stationarity::SlidingWindow { .. } => {
+                // Rewards for impressions that left the window only count
+                // towards the lifetime totals.
+                if let Some(slot) = self.window.iter_mut().find(|(s, _)| *s == sequence) {
+                    slot.1 += value;
+                    self.sync_window();
+                }
+            }
+        }
+    }
+
+    fn sync_window(&mut self) {
+        self.weight = self.window.len() as f64;
+        self.reward_sum = self.window.iter().map(|(_, r)| r).sum();
+        self.reward_sq_sum = self.window.iter().map(|(_, r)| r * r).sum();
+    }
+
+    fn mean(&self) -> f64 {
+        if self.weight > 0.0 {
+            self.reward_sum / self.weight
+        } else {
+            0.0
+        }
+    }
+
+    fn beta_params(&self) -> (f64, f64) {
+        let alpha = 1.0 + self.reward_sum;
+        let beta = 1.0 + (self.weight - self.reward_sum).max(0.0);
+        (alpha, beta)
+    }
+
+    /// Normal-Gamma posterior `(mean, kappa, shape, rate)` under a weak
+    /// prior centred on zero.
+    fn normal_gamma_params(&self) -> (f64, f64, f64, f64) {
+        const KAPPA0: f64 = 0.01;
+        let n = self.weight;
+        let kappa = KAPPA0 + n;
+        let mean = self.reward_sum / kappa;
+        let shape = 1.0 + n / 2.0;
+        let sample_mean = self.mean();
+        let scatter = (self.reward_sq_sum - n * sample_mean * sample_mean).max(0.0);
+        let rate = 1.0 + 0.5 * scatter + KAPPA0 * n * sample_mean * sample_mean / (2.0 * kappa);
+        (mean, kappa, shape, rate)
+    }
+
+    /// Gamma posterior `(shape, rate)` of the Exponential reward rate.
+    fn gamma_params(&self) -> (f64, f64) {
+        (1.0 + self.weight, 1.0 + self.reward_sum)
+    }
+
+    /// Posterior mean reward.
+    fn estimated_value(&self, model: RewardModel) -> f64 {
+        match model {
+            RewardModel::Bernoulli => {
+                let (alpha, beta) = self.beta_params();
+                alpha / (alpha + beta)
+            }
+            RewardModel::Gaussian => self.normal_gamma_params().0,
+            RewardModel::Gamma => {
+                let (shape, rate) = self.gamma_params();
+                if shape > 1.0 {
+                    rate / (shape - 1.0)
+                } else {
+                    rate
+                }
+            }
+        }
+    }
+
+    fn thompson_sample(&self, model: RewardModel, rng: &mut impl Rng) -> f64 {
+        match model {
+            RewardModel::Bernoulli => {
+                let (alpha, beta) = self.beta_params();
+                BanditEngine::beta_sample(rng, alpha, beta)
+            }
+            RewardModel::Gaussian => {
+                let (mean, kappa, shape, rate) = self.normal_gamma_params();
+                let precision = gamma_sample(rng, shape, 1.0 / rate).max(f64::MIN_POSITIVE);
+                mean + normal_sample(rng) / (kappa * precision).sqrt()
+            }
+            RewardModel::Gamma => {
+                let (shape, rate) = self.gamma_params();
+                1.0 / gamma_sample(rng, shape, 1.0 / rate).max(f64::MIN_POSITIVE)
+            }
+        }
+    }
+}
+
+/// A tracked selection that later conversions can be attributed to.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanditDecision {
+    pub decision_id: Uuid,
+    pub campaign_id: Uuid,
+    pub variant_id: Uuid,
+    /// Impression sequence number of the variant.
+    pub sequence: u64,
+    pub decided_at: DateTime<Utc>,
+}
+
+/// Serializable bandit state, including decisions still awaiting rewards.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct BanditSnapshot {
+    pub version: u32,
+    pub taken_at: DateTime<Utc>,
+    pub configs: Vec<BanditConfig>,
+    pub arms: Vec<ArmSnapshot>,
+    pub decisions: Vec<BanditDecision>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmSnapshot {
+    pub campaign_id: Uuid,
+    pub variant_id: Uuid,
+    pub state: VariantState,
 }
 
 pub struct BanditEngine {
     configs: dashmap::DashMap<Uuid, BanditConfig>,
     states: dashmap::DashMap<(Uuid, Uuid), VariantState>,
+    decisions: dashmap::DashMap<Uuid, BanditDecision>,
+    attribution_window: Duration,
 }
 
 impl BanditEngine {
@@ -66,24 +275,35 @@ impl BanditEngine {
         Self {
             configs: dashmap::DashMap::new(),
             states: dashmap::DashMap::new(),
+            decisions: dashmap::DashMap::new(),
+            attribution_window: Duration::days(7),
         }
     }
 
+    /// How long after a tracked selection conversions are still credited.
+    pub fn with_attribution_window(mut self, window: Duration) -> Self {
+        self.attribution_window = window;
+        self
+    }
+
+    /// Register or update a campaign. Learned state of existing variants
+    /// is kept, so re-registering after [`Self::load`] resumes learning.
     pub fn register_campaign(&self, config: BanditConfig) {
         for variant in &config.variants {
-            self.states.insert(
-                (config.campaign_id, variant.id),
-                VariantState {
-                    alpha: 1.0,
-                    beta: 1.0,
-                    impressions: 0,
-                    conversions: 0,
-                },
-            );
+            self.states
+                .entry((config.campaign_id, variant.id))
+                .or_default();
         }
         self.configs.insert(config.campaign_id, config);
     }
 
+    fn model(&self, campaign_id: &Uuid) -> (RewardModel, Stationarity) {
+        self.configs
+            .get(campaign_id)
+            .map(|c| (c.reward_model, c.stationarity))
+            .unwrap_or_default()
+    }
+
     pub fn select_variant(&self, campaign_id: &Uuid) -> Option<Uuid> {
         let config = self.configs.get(campaign_id)?;
         let active_variants: Vec<_> = config.variants.iter().filter(|v| v.active).collect();
@@ -107,16 +327,14 @@ impl BanditEngine {
         let mut rng = rand::thread_rng();
         let mut best_sample = f64::NEG_INFINITY;
         let mut best_variant = None;
+        let (model, _) = self.model(campaign_id);
 
         for variant in variants {
-            let state = self
+            let sample = self
                 .states
                 .get(&(*campaign_id, variant.id))
-                .map(|s| (s.alpha, s.beta))
-                .unwrap_or((1.0, 1.0));
-
-            // Beta distribution sampling approximation
-            let sample = Self::beta_sample(&mut rng, state.0, state.1);
+                .map(|s| s.thompson_sample(model, &mut rng))
+                .unwrap_or_else(|| VariantState::default().thompson_sample(model, &mut rng));
             if sample > best_sample {
                 best_sample = sample;
                 best_variant = Some(variant.id);
@@ -146,16 +364,13 @@ impl BanditEngine {
 
         for variant in variants {
             let state = self.states.get(&(*campaign_id, variant.id));
-            let (impressions, conversions) = state
-                .map(|s| (s.impressions, s.conversions))
-                .unwrap_or((0, 0));
+            let (weight, avg_reward) = state.map(|s| (s.weight, s.mean())).unwrap_or((0.0, 0.0));
 
-            if impressions == 0 {
+            if weight <= 0.0 {
                 return Some(variant.id);
             }
 
-            let avg_reward = conversions as f64 / impressions as f64;
-            let exploration = (2.0 * log_total / impressions as f64).sqrt();
+            let exploration = (2.0 * log_total / weight).sqrt();
             let score = avg_reward + exploration;
 
             if score > best_score {
@@ -185,15 +400,7 @@ impl BanditEngine {
 
         for variant in variants {
             let state = self.states.get(&(*campaign_id, variant.id));
-            let rate = state
-                .map(|s| {
-                    if s.impressions > 0 {
-                        s.conversions as f64 / s.impressions as f64
-                    } else {
-                        0.0
-                    }
-                })
-                .unwrap_or(0.0);
+            let rate = state.map(|s| s.mean()).unwrap_or(0.0);
 
             if rate > best_rate {
                 best_rate = rate;
@@ -205,20 +412,135 @@ impl BanditEngine {
     }
 
     pub fn record_impression(&self, campaign_id: &Uuid, variant_id: &Uuid) {
-        if let Some(mut state) = self.states.get_mut(&(*campaign_id, *variant_id)) {
-            state.impressions += 1;
-            state.beta += 1.0;
-        }
+        self.observe(campaign_id, variant_id);
     }
 
+    fn observe(&self, campaign_id: &Uuid, variant_id: &Uuid) -> Option<u64> {
+        let (_, stationarity) = self.model(campaign_id);
+        self.states
+            .get_mut(&(*campaign_id, *variant_id))
+            .map(|mut state| state.observe(stationarity))
+    }
+
+    /// A binary conversion on the variant's latest impression.
     pub fn record_reward(&self, campaign_id: &Uuid, variant_id: &Uuid) {
+        self.record_reward_value(campaign_id, variant_id, 1.0);
+    }
+
+    /// A continuous reward, e.g. revenue, on the variant's latest impression.
+    pub fn record_reward_value(&self, campaign_id: &Uuid, variant_id: &Uuid, value: f64) {
+        let (_, stationarity) = self.model(campaign_id);
         if let Some(mut state) = self.states.get_mut(&(*campaign_id, *variant_id)) {
-            state.conversions += 1;
-            state.alpha += 1.0;
-            state.beta -= 1.0;
+            let latest = state.impressions.saturating_sub(1);
+            state.reward(stationarity, latest, value);
+        }
+    }
+
+    /// Select a variant, count its impression and remember the decision so
+    /// a later conversion can be credited with [`Self::attribute_reward`].
+    pub fn select_variant_tracked(&self, campaign_id: &Uuid) -> Option<BanditDecision> {
+        let variant_id = self.select_variant(campaign_id)?;
+        let sequence = self.observe(campaign_id, &variant_id)?;
+        let decision = BanditDecision {
+            decision_id: Uuid::new_v4(),
+            campaign_id: *campaign_id,
+            variant_id,
+            sequence,
+            decided_at: Utc::now(),
+        };
+        self.decisions
+            .insert(decision.decision_id, decision.clone());
+        Some(decision)
+    }
+
+    /// Credit a conversion observed at `converted_at` to the decision that
+    /// served it. Returns false for unknown decisions and for conversions
+    /// outside the attribution window.
+    pub fn attribute_reward(
+        &self,
+        decision_id: &Uuid,
+        value: f64,
+        converted_at: DateTime<Utc>,
+    ) -> bool {
+        let Some(decision) = self.decisions.get(decision_id).map(|d| d.clone()) else {
+            return false;
+        };
+        if converted_at - decision.decided_at > self.attribution_window {
+            self.decisions.remove(decision_id);
+            return false;
+        }
+        let (_, stationarity) = self.model(&decision.campaign_id);
+        match self
+            .states
+            .get_mut(&(decision.campaign_id, decision.variant_id))
+        {
+            Some(mut state) => {
+                state.reward(stationarity, decision.sequence, value);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Forget decisions whose attribution window closed before `now`.
+    pub fn prune_decisions(&self, now: DateTime<Utc>) -> usize {
+        let before = self.decisions.len();
+        self.decisions
+            .retain(|_, d| now - d.decided_at <= self.attribution_window);
+        before - self.decisions.len()
+    }
+
+    pub fn snapshot(&self) -> BanditSnapshot {
+        BanditSnapshot {
+            version: SNAPSHOT_VERSION,
+            taken_at: Utc::now(),
+            configs: self.configs.iter().map(|c| c.value().clone()).collect(),
+            arms: self
+                .states
+                .iter()
+                .map(|e| ArmSnapshot {
+                    campaign_id: e.key().0,
+                    variant_id: e.key().1,
+                    state: e.value().clone(),
+                })
+                .collect(),
+            decisions: self.decisions.iter().map(|d| d.value().clone()).collect(),
         }
     }
 
+    /// Replace all campaigns, arm statistics and pending decisions.
+    pub fn restore(&self, snapshot: BanditSnapshot) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            snapshot.version == SNAPSHOT_VERSION,
+            "unsupported bandit snapshot version {}",
+            snapshot.version
+        );
+        self.configs.clear();
+        self.states.clear();
+        self.decisions.clear();
+        for config in snapshot.configs {
+            self.configs.insert(config.campaign_id, config);
+        }
+        for arm in snapshot.arms {
+            self.states
+                .insert((arm.campaign_id, arm.variant_id), arm.state);
+        }
+        for decision in snapshot.decisions {
+            self.decisions.insert(decision.decision_id, decision);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        persistence::save_json(path.as_ref(), &self.snapshot())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let engine = Self::new();
+        engine.restore(persistence::load_json(path.as_ref())?)?;
+        Ok(engine)
+    }
+
     pub fn get_stats(&self, campaign_id: &Uuid) -> Vec<VariantStats> {
         let config = match self.configs.get(campaign_id) {
             Some(c) => c,
@@ -240,9 +562,15 @@ impl BanditEngine {
 
         for variant in &config.variants {
             let state = self.states.get(&(*campaign_id, variant.id));
-            let (impressions, conversions, alpha, beta) = state
-                .map(|s| (s.impressions, s.conversions, s.alpha, s.beta))
-                .unwrap_or((0, 0, 1.0, 1.0));
+            let (impressions, conversions, estimated_value) = state
+                .map(|s| {
+                    (
+                        s.impressions,
+                        s.conversions,
+                        s.estimated_value(config.reward_model),
+                    )
+                })
+                .unwrap_or((0, 0, 0.5));
 
             let rate = if impressions > 0 {
                 conversions as f64 / impressions as f64
@@ -275,7 +603,7 @@ impl BanditEngine {
                 confidence_interval_upper: (rate + ci_width).min(1.0),
                 traffic_allocation: traffic,
                 is_winner: false,
-                estimated_value: alpha / (alpha + beta),
+                estimated_value,
             });
         }
 
@@ -296,8 +624,203 @@ impl BanditEngine {
     }
 }
 
+/// Standard normal draw (Box-Muller).
+fn normal_sample(rng: &mut impl Rng) -> f64 {
+    let u1 = rng.gen::<f64>().max(f64::MIN_POSITIVE);
+    let u2 = rng.gen::<f64>();
+    (-2.0 * u1.ln()).sqrt() * (2.0 * std::f64::consts::PI * u2).cos()
+}
+
+/// Gamma(shape, scale) draw (Marsaglia-Tsang).
+fn gamma_sample(rng: &mut impl Rng, shape: f64, scale: f64) -> f64 {
+    if shape < 1.0 {
+        let u = rng.gen::<f64>();
+        return gamma_sample(rng, shape + 1.0, scale) * u.powf(1.0 / shape);
+    }
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+    loop {
+        let x = normal_sample(rng);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+        let u = rng.gen::<f64>();
+        if u < 1.0 - 0.0331 * x.powi(4) || u.ln() < 0.5 * x * x + d * (1.0 - v + v.ln()) {
+            return d * v * scale;
+        }
+    }
+}
+
 impl Default for BanditEngine {
     fn default() -> Self {
         Self::new()
     }
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(
+        variants: usize,
+        reward_model: RewardModel,
+        stationarity: Stationarity,
+    ) -> BanditConfig {
+        BanditConfig {
+            campaign_id: Uuid::new_v4(),
+            algorithm: BanditAlgorithm::ThompsonSampling,
+            min_exploration_rate: 0.0,
+            variants: (0..variants)
+                .map(|i| VariantConfig {
+                    id: Uuid::new_v4(),
+                    name: format!("variant-{i}"),
+                    creative_url: None,
+                    active: true,
+                })
+                .collect(),
+            created_at: Utc::now(),
+            reward_model,
+            stationarity,
+        }
+    }
+
+    fn value_of(engine: &BanditEngine, campaign_id: &Uuid, variant_id: Uuid) -> f64 {
+        engine
+            .get_stats(campaign_id)
+            .into_iter()
+            .find(|s| s.variant_id == variant_id)
+            .map(|s| s.estimated_value)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_snapshot_survives_restart() {
+        let cfg = config(2, RewardModel::Bernoulli, Stationarity::Stationary);
+        let (campaign, a) = (cfg.campaign_id, cfg.variants[0].id);
+        let engine = BanditEngine::new();
+        engine.register_campaign(cfg.clone());
+        for i in 0..40 {
+            engine.record_impression(&campaign, &a);
+            if i % 4 == 0 {
+                engine.record_reward(&campaign, &a);
+            }
+        }
+        let pending = engine.select_variant_tracked(&campaign).unwrap();
+
+        let path = std::env::temp_dir().join(format!("bandit-{}.json", Uuid::new_v4()));
+        engine.save(&path).unwrap();
+        let restored = BanditEngine::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+
+        // Re-registering on startup must not wipe what was learned.
+        restored.register_campaign(cfg);
+        let before = engine.get_stats(&campaign);
+        let after = restored.get_stats(&campaign);
+        for (b, r) in before.iter().zip(&after) {
+            assert_eq!(b.impressions, r.impressions);
+            assert_eq!(b.conversions, r.conversions);
+            assert!((b.estimated_value - r.estimated_value).abs() < 1e-12);
+        }
+        assert!(restored.attribute_reward(&pending.decision_id, 1.0, Utc::now()));
+
+        let mut stale = restored.snapshot();
+        stale.version += 1;
+        assert!(BanditEngine::new().restore(stale).is_err());
+    }
+
+    #[test]
+    fn test_continuous_rewards_favour_higher_revenue() {
+        for model in [RewardModel::Gaussian, RewardModel::Gamma] {
+            let cfg = config(2, model, Stationarity::Stationary);
+            let (campaign, low, high) = (cfg.campaign_id, cfg.variants[0].id, cfg.variants[1].id);
+            let engine = BanditEngine::new();
+            engine.register_campaign(cfg);
+            for i in 0..200 {
+                engine.record_impression(&campaign, &low);
+                engine.record_reward_value(&campaign, &low, 4.0 + (i % 3) as f64);
+                engine.record_impression(&campaign, &high);
+                engine.record_reward_value(&campaign, &high, 18.0 + (i % 5) as f64);
+            }
+            assert!((value_of(&engine, &campaign, low) - 5.0).abs() < 0.5);
+            assert!((value_of(&engine, &campaign, high) - 20.0).abs() < 1.0);
+
+            let picks_high = (0..200)
+                .filter(|_| engine.select_variant(&campaign) == Some(high))
+                .count();
+            assert!(picks_high > 190, "{model:?} picked high {picks_high}/200");
+        }
+    }
+
+    #[test]
+    fn test_non_stationary_variants_forget_fatigued_creative() {
+        for stationarity in [
+            Stationarity::Stationary,
+            Stationarity::Discounted { gamma: 0.95 },
+            Stationarity::SlidingWindow { size: 50 },
+        ] {
+            let cfg = config(2, RewardModel::Bernoulli, stationarity);
+            let (campaign, fading, steady) =
+                (cfg.campaign_id, cfg.variants[0].id, cfg.variants[1].id);
+            let engine = BanditEngine::new();
+            engine.register_campaign(cfg);
+            for i in 0..400 {
+                engine.record_impression(&campaign, &fading);
+                if i < 300 {
+                    engine.record_reward(&campaign, &fading);
+                }
+                engine.record_impression(&campaign, &steady);
+                if i % 2 == 0 {
+                    engine.record_reward(&campaign, &steady);
+                }
+            }
+            let fading_value = value_of(&engine, &campaign, fading);
+            let steady_value = value_of(&engine, &campaign, steady);
+            if stationarity == Stationarity::Stationary {
+                assert!(fading_value > steady_value);
+            } else {
+                assert!(fading_value < 0.1, "{stationarity:?}: {fading_value}");
+                assert!((steady_value - 0.5).abs() < 0.15);
+            }
+        }
+    }
+
+    #[test]
+    fn test_delayed_rewards_join_by_decision_id() {
+        let cfg = config(
+            1,
+            RewardModel::Gamma,
+            Stationarity::SlidingWindow { size: 3 },
+        );
+        let campaign = cfg.campaign_id;
+        let engine = BanditEngine::new().with_attribution_window(Duration::hours(1));
+        engine.register_campaign(cfg);
+
+        let first = engine.select_variant_tracked(&campaign).unwrap();
+        let decisions: Vec<_> = (0..3)
+            .map(|_| engine.select_variant_tracked(&campaign).unwrap())
+            .collect();
+        let now = Utc::now();
+
+        // The first impression has left the window: lifetime totals only.
+        assert!(engine.attribute_reward(&first.decision_id, 50.0, now));
+        assert!(engine.attribute_reward(&decisions[1].decision_id, 30.0, now));
+        let state = engine
+            .states
+            .get(&(campaign, first.variant_id))
+            .unwrap()
+            .clone();
+        assert_eq!(state.impressions, 4);
+        assert_eq!(state.conversions, 2);
+        assert_eq!(state.total_reward, 80.0);
+        assert_eq!(state.reward_sum, 30.0);
+
+        assert!(!engine.attribute_reward(&Uuid::new_v4(), 1.0, now));
+        assert!(!engine.attribute_reward(&decisions[0].decision_id, 1.0, now + Duration::hours(2)));
+        assert_eq!(engine.prune_decisions(now + Duration::hours(2)), 3);
+    }
+}
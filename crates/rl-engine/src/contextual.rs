@@ -2,8 +2,14 @@
 
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use std::path::Path;
 use uuid::Uuid;
 
+use crate::persistence;
+
+/// Bumped whenever [`ContextualSnapshot`] changes incompatibly.
+const SNAPSHOT_VERSION: u32 = 1;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct UserFeatures {
     pub user_id: Uuid,
@@ -46,6 +52,24 @@ pub struct SegmentInsight {
     pub confidence: f64,
 }
 
+/// Serializable LinUCB state: configurations, per-arm weights and sample
+/// counts.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ContextualSnapshot {
+    pub version: u32,
+    pub taken_at: DateTime<Utc>,
+    pub configs: Vec<ContextualConfig>,
+    pub weights: Vec<ArmWeights>,
+    pub sample_counts: Vec<(Uuid, u64)>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ArmWeights {
+    pub campaign_id: Uuid,
+    pub variant_id: Uuid,
+    pub weights: Vec<f64>,
+}
+
 pub struct ContextualBanditEngine {
     configs: dashmap::DashMap<Uuid, ContextualConfig>,
     weights: dashmap::DashMap<(Uuid, Uuid), Vec<f64>>,
@@ -167,6 +191,61 @@ impl ContextualBanditEngine {
         self.weights.insert((*campaign_id, *variant_id), weights);
     }
 
+    pub fn snapshot(&self) -> ContextualSnapshot {
+        ContextualSnapshot {
+            version: SNAPSHOT_VERSION,
+            taken_at: Utc::now(),
+            configs: self.configs.iter().map(|c| c.value().clone()).collect(),
+            weights: self
+                .weights
+                .iter()
+                .map(|e| ArmWeights {
+                    campaign_id: e.key().0,
+                    variant_id: e.key().1,
+                    weights: e.value().clone(),
+                })
+                .collect(),
+            sample_counts: self
+                .sample_count
+                .iter()
+                .map(|e| (*e.key(), *e.value()))
+                .collect(),
+        }
+    }
+
+    /// Replace all configurations, weights and sample counts.
+    pub fn restore(&self, snapshot: ContextualSnapshot) -> anyhow::Result<()> {
+        anyhow::ensure!(
+            snapshot.version == SNAPSHOT_VERSION,
+            "unsupported contextual bandit snapshot version {}",
+            snapshot.version
+        );
+        self.configs.clear();
+        self.weights.clear();
+        self.sample_count.clear();
+        for config in snapshot.configs {
+            self.configs.insert(config.campaign_id, config);
+        }
+        for arm in snapshot.weights {
+            self.weights
+                .insert((arm.campaign_id, arm.variant_id), arm.weights);
+        }
+        for (campaign_id, count) in snapshot.sample_counts {
+            self.sample_count.insert(campaign_id, count);
+        }
+        Ok(())
+    }
+
+    pub fn save(&self, path: impl AsRef<Path>) -> anyhow::Result<()> {
+        persistence::save_json(path.as_ref(), &self.snapshot())
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> anyhow::Result<Self> {
+        let engine = Self::new();
+        engine.restore(persistence::load_json(path.as_ref())?)?;
+        Ok(engine)
+    }
+
     pub fn get_feature_importance(&self, campaign_id: &Uuid) -> Vec<FeatureImportance> {
         let config = match self.configs.get(campaign_id) {
             Some(c) => c,
@@ -221,3 +300,36 @@ impl Default for ContextualBanditEngine {
         Self::new()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_snapshot_round_trip_checks_version() {
+        let engine = ContextualBanditEngine::new();
+        let (campaign, variant) = (Uuid::new_v4(), Uuid::new_v4());
+        engine.configure(ContextualConfig {
+            campaign_id: campaign,
+            enabled: true,
+            feature_names: vec!["recency".into(), "spend".into()],
+            alpha: 1.0,
+            min_samples_for_personalization: 1,
+            created_at: Utc::now(),
+        });
+        engine.record_outcome(&campaign, &variant, &[1.0, 0.5], 1.0);
+
+        let path = std::env::temp_dir().join(format!("contextual-{}.json", Uuid::new_v4()));
+        engine.save(&path).unwrap();
+        let restored = ContextualBanditEngine::load(&path).unwrap();
+        std::fs::remove_file(&path).ok();
+        assert_eq!(
+            restored.snapshot().weights[0].weights,
+            engine.snapshot().weights[0].weights
+        );
+
+        let mut stale = restored.snapshot();
+        stale.version += 1;
+        assert!(ContextualBanditEngine::new().restore(stale).is_err());
+    }
+}
@@ -1,7 +1,18 @@
 //! AI Guardrails — business rules engine that validates AI decisions before execution.
+//!
+//! Budget and frequency caps can be backed by the shared
+//! [`BudgetTracker`] and [`FrequencyCapEngine`] so callers need not compute
+//! spend or send counts themselves. Recent decisions are kept per campaign
+//! for segment exposure and fairness rules and for dry runs of proposed
+//! rules.
 
-use chrono::{DateTime, Datelike, Timelike, Utc};
+use campaign_intelligent_delivery::frequency_capping::{CappingChannel, CappingWindow};
+use campaign_intelligent_delivery::FrequencyCapEngine;
+use campaign_reporting::BudgetTracker;
+use chrono::{DateTime, Datelike, NaiveTime, Timelike, Utc};
 use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, VecDeque};
+use std::sync::Arc;
 use uuid::Uuid;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -38,6 +49,28 @@ pub enum GuardrailRule {
     ChannelRestriction {
         required_consent_type: String,
     },
+    /// Block users in `segment` once it holds `max_share` of the campaign's
+    /// recent allowed decisions (after `min_sample` of them).
+    SegmentExposureCap {
+        segment: String,
+        max_share: f64,
+        min_sample: u64,
+    },
+    /// Block users in a segment whose recent exposure rate exceeds the
+    /// lowest rate among `segments` by more than `max_disparity`. Segments
+    /// with fewer than `min_sample` evaluations are ignored.
+    SegmentFairness {
+        segments: Vec<String>,
+        max_disparity: f64,
+        min_sample: u64,
+    },
+    /// Block offers whose margin after discount falls below the minimum.
+    MinimumMargin {
+        min_margin_percent: f64,
+    },
+    KillSwitch {
+        reason: String,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -73,6 +106,12 @@ pub struct ActionContext {
     pub user_sends_week: u32,
     pub user_sends_month: u32,
     pub has_consent: bool,
+    #[serde(default)]
+    pub offer_id: Option<String>,
+    #[serde(default)]
+    pub offer_price: Option<f64>,
+    #[serde(default)]
+    pub offer_cost: Option<f64>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -83,11 +122,78 @@ pub struct ViolationLog {
     pub violations_by_type: std::collections::HashMap<String, u64>,
 }
 
+/// How many recent decisions a proposed rule would have blocked.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DryRunReport {
+    pub campaign_id: Uuid,
+    pub evaluated: u64,
+    pub would_block: u64,
+    /// Blocked by the proposed rule but allowed at the time.
+    pub newly_blocked: u64,
+    pub violations_by_type: HashMap<String, u64>,
+    pub from: Option<DateTime<Utc>>,
+    pub to: Option<DateTime<Utc>>,
+}
+
+/// A validated decision, with spend and send counts as resolved then.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedAction {
+    pub context: ActionContext,
+    pub allowed: bool,
+    pub evaluated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SegmentExposure {
+    pub evaluated: u64,
+    pub allowed: u64,
+}
+
+/// A campaign's recent decisions with running exposure counts.
+#[derive(Default)]
+struct ExposureWindow {
+    actions: VecDeque<RecordedAction>,
+    allowed: u64,
+    segments: HashMap<String, SegmentExposure>,
+}
+
+impl ExposureWindow {
+    fn push(&mut self, action: RecordedAction, limit: usize) {
+        self.count(&action, true);
+        self.actions.push_back(action);
+        while self.actions.len() > limit {
+            if let Some(old) = self.actions.pop_front() {
+                self.count(&old, false);
+            }
+        }
+    }
+
+    fn count(&mut self, action: &RecordedAction, add: bool) {
+        let apply = |n: &mut u64, by: bool| {
+            if by {
+                *n = if add { *n + 1 } else { n.saturating_sub(1) };
+            }
+        };
+        apply(&mut self.allowed, action.allowed);
+        for segment in &action.context.user_segments {
+            let exposure = self.segments.entry(segment.clone()).or_default();
+            apply(&mut exposure.evaluated, true);
+            apply(&mut exposure.allowed, action.allowed);
+        }
+    }
+}
+
 pub struct GuardrailsEngine {
     configs: dashmap::DashMap<Uuid, GuardrailConfig>,
     violation_counts: dashmap::DashMap<(Uuid, String), u64>,
     total_evaluated: dashmap::DashMap<Uuid, u64>,
     total_blocked: dashmap::DashMap<Uuid, u64>,
+    budgets: Option<Arc<BudgetTracker>>,
+    frequency_caps: Option<Arc<FrequencyCapEngine>>,
+    /// Keyed by campaign; `None` blocks every campaign.
+    kill_switches: dashmap::DashMap<Option<Uuid>, String>,
+    history: dashmap::DashMap<Uuid, ExposureWindow>,
+    history_limit: usize,
 }
 
 impl GuardrailsEngine {
@@ -97,6 +203,11 @@ impl GuardrailsEngine {
             violation_counts: dashmap::DashMap::new(),
             total_evaluated: dashmap::DashMap::new(),
             total_blocked: dashmap::DashMap::new(),
+            budgets: None,
+            frequency_caps: None,
+            kill_switches: dashmap::DashMap::new(),
+            history: dashmap::DashMap::new(),
+            history_limit: 10_000,
         }
     }
 
@@ -104,170 +215,81 @@ impl GuardrailsEngine {
         self.configs.insert(config.campaign_id, config);
     }
 
-    pub fn validate(&self, campaign_id: &Uuid, context: &ActionContext) -> GuardrailDecision {
-        let config = match self.configs.get(campaign_id) {
-            Some(c) => c,
-            None => {
-                return GuardrailDecision {
-                    allowed: true,
-                    violations: Vec::new(),
-                    evaluated_at: Utc::now(),
-                }
-            }
-        };
+    /// Attach the shared budget tracker; budget caps then use recorded
+    /// spend instead of the caller's `campaign_spend_*` values.
+    pub fn with_budget_tracker(mut self, budgets: Arc<BudgetTracker>) -> Self {
+        self.budgets = Some(budgets);
+        self
+    }
+
+    /// Attach the shared frequency cap engine; frequency caps then use
+    /// recorded sends instead of the caller's `user_sends_*` values.
+    pub fn with_frequency_caps(mut self, frequency_caps: Arc<FrequencyCapEngine>) -> Self {
+        self.frequency_caps = Some(frequency_caps);
+        self
+    }
 
-        let mut violations = Vec::new();
+    /// Number of recent decisions kept per campaign for exposure rules and
+    /// dry runs.
+    pub fn with_history_limit(mut self, limit: usize) -> Self {
+        self.history_limit = limit.max(1);
+        self
+    }
+
+    /// Block every decision for a campaign, or for all campaigns when
+    /// `campaign_id` is `None`.
+    pub fn engage_kill_switch(&self, campaign_id: Option<Uuid>, reason: &str) {
+        self.kill_switches.insert(campaign_id, reason.to_string());
+    }
+
+    pub fn release_kill_switch(&self, campaign_id: Option<Uuid>) -> bool {
+        self.kill_switches.remove(&campaign_id).is_some()
+    }
+
+    /// The caller's context with spend and send counts replaced by the
+    /// shared trackers' figures, when attached.
+    fn resolve(&self, campaign_id: &Uuid, context: &ActionContext) -> ActionContext {
+        let mut context = context.clone();
         let now = Utc::now();
+        if let Some(budgets) = &self.budgets {
+            let today = now.date_naive();
+            let month_start = today.with_day(1).unwrap_or(today);
+            context.campaign_spend_today =
+                budgets.spend_since(campaign_id, today.and_time(NaiveTime::MIN).and_utc());
+            context.campaign_spend_month =
+                budgets.spend_since(campaign_id, month_start.and_time(NaiveTime::MIN).and_utc());
+        }
+        if let Some(caps) = &self.frequency_caps {
+            let count =
+                |window| caps.message_count(&context.user_id, &CappingChannel::All, &window);
+            context.user_sends_today = count(CappingWindow::PerDay);
+            context.user_sends_week = count(CappingWindow::PerWeek);
+            context.user_sends_month = count(CappingWindow::PerMonth);
+        }
+        context
+    }
 
-        for rule in &config.rules {
-            match rule {
-                GuardrailRule::FrequencyCap {
-                    max_per_day,
-                    max_per_week,
-                    max_per_month,
-                } => {
-                    if context.user_sends_today >= *max_per_day {
-                        violations.push(GuardrailViolation {
-                            rule_type: "frequency_cap".to_string(),
-                            description: format!(
-                                "Daily cap exceeded ({}/{})",
-                                context.user_sends_today, max_per_day
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                    if context.user_sends_week >= *max_per_week {
-                        violations.push(GuardrailViolation {
-                            rule_type: "frequency_cap".to_string(),
-                            description: format!(
-                                "Weekly cap exceeded ({}/{})",
-                                context.user_sends_week, max_per_week
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                    if context.user_sends_month >= *max_per_month {
-                        violations.push(GuardrailViolation {
-                            rule_type: "frequency_cap".to_string(),
-                            description: format!(
-                                "Monthly cap exceeded ({}/{})",
-                                context.user_sends_month, max_per_month
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                }
-                GuardrailRule::TimeRestriction {
-                    allowed_hours,
-                    allowed_days,
-                } => {
-                    let hour = now.hour();
-                    let day = now.weekday().num_days_from_monday() + 1;
-                    if !allowed_hours.contains(&hour) {
-                        violations.push(GuardrailViolation {
-                            rule_type: "time_restriction".to_string(),
-                            description: format!("Hour {} not in allowed hours", hour),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                    if !allowed_days.contains(&day) {
-                        violations.push(GuardrailViolation {
-                            rule_type: "time_restriction".to_string(),
-                            description: format!("Day {} not in allowed days", day),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                }
-                GuardrailRule::IncentiveCap {
-                    max_discount_percent,
-                    never_discount_segments,
-                } => {
-                    if let Some(discount) = context.discount_percent {
-                        if discount > *max_discount_percent {
-                            violations.push(GuardrailViolation {
-                                rule_type: "incentive_cap".to_string(),
-                                description: format!(
-                                    "Discount {}% exceeds max {}%",
-                                    discount, max_discount_percent
-                                ),
-                                severity: ViolationSeverity::Block,
-                            });
-                        }
-                        if discount > 0.0 {
-                            for seg in &context.user_segments {
-                                if never_discount_segments.contains(seg) {
-                                    violations.push(GuardrailViolation {
-                                        rule_type: "incentive_cap".to_string(),
-                                        description: format!(
-                                            "Segment '{}' is excluded from discounts",
-                                            seg
-                                        ),
-                                        severity: ViolationSeverity::Block,
-                                    });
-                                }
-                            }
-                        }
-                    }
-                }
-                GuardrailRule::BudgetCap {
-                    max_daily_spend,
-                    max_monthly_spend,
-                } => {
-                    if context.campaign_spend_today >= *max_daily_spend {
-                        violations.push(GuardrailViolation {
-                            rule_type: "budget_cap".to_string(),
-                            description: format!(
-                                "Daily budget exceeded (${:.2}/${:.2})",
-                                context.campaign_spend_today, max_daily_spend
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                    if context.campaign_spend_month >= *max_monthly_spend {
-                        violations.push(GuardrailViolation {
-                            rule_type: "budget_cap".to_string(),
-                            description: format!(
-                                "Monthly budget exceeded (${:.2}/${:.2})",
-                                context.campaign_spend_month, max_monthly_spend
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                }
-                GuardrailRule::SegmentRestriction {
-                    blocked_segments,
-                    blocked_channels,
-                } => {
-                    for seg in &context.user_segments {
-                        if blocked_segments.contains(seg) {
-                            violations.push(GuardrailViolation {
-                                rule_type: "segment_restriction".to_string(),
-                                description: format!("Segment '{}' is blocked", seg),
-                                severity: ViolationSeverity::Block,
-                            });
-                        }
-                    }
-                    if blocked_channels.contains(&context.channel) {
-                        violations.push(GuardrailViolation {
-                            rule_type: "channel_restriction".to_string(),
-                            description: format!(
-                                "Channel '{}' is blocked for user segments",
-                                context.channel
-                            ),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                }
-                GuardrailRule::ChannelRestriction { .. } => {
-                    if !context.has_consent {
-                        violations.push(GuardrailViolation {
-                            rule_type: "consent".to_string(),
-                            description: "User has not provided consent".to_string(),
-                            severity: ViolationSeverity::Block,
-                        });
-                    }
-                }
-            }
+    pub fn validate(&self, campaign_id: &Uuid, context: &ActionContext) -> GuardrailDecision {
+        let now = Utc::now();
+        let mut violations: Vec<GuardrailViolation> = [None, Some(*campaign_id)]
+            .iter()
+            .filter_map(|scope| self.kill_switches.get(scope))
+            .map(|reason| kill_switch_violation(reason.value()))
+            .collect();
+
+        let config = self.configs.get(campaign_id);
+        if config.is_none() && violations.is_empty() {
+            return GuardrailDecision {
+                allowed: true,
+                violations,
+                evaluated_at: now,
+            };
+        }
+
+        let context = self.resolve(campaign_id, context);
+        let mut window = self.history.entry(*campaign_id).or_default();
+        for rule in config.iter().flat_map(|c| &c.rules) {
+            check_rule(rule, &context, now, &window, &mut violations);
         }
 
         self.total_evaluated
@@ -279,6 +301,15 @@ impl GuardrailsEngine {
             .iter()
             .any(|v| matches!(v.severity, ViolationSeverity::Block));
 
+        window.push(
+            RecordedAction {
+                context,
+                allowed: !blocked,
+                evaluated_at: now,
+            },
+            self.history_limit,
+        );
+
         if blocked {
             self.total_blocked
                 .entry(*campaign_id)
@@ -300,6 +331,62 @@ impl GuardrailsEngine {
         }
     }
 
+    /// Replay the campaign's recent decisions against a proposed rule,
+    /// without changing any state. Spend and send counts are the values
+    /// resolved when each decision was made; exposure rules see the
+    /// exposures that would remain with the rule in place.
+    pub fn dry_run(&self, campaign_id: &Uuid, rule: &GuardrailRule) -> DryRunReport {
+        let actions: Vec<RecordedAction> = self
+            .history
+            .get(campaign_id)
+            .map(|w| w.actions.iter().cloned().collect())
+            .unwrap_or_default();
+
+        let mut report = DryRunReport {
+            campaign_id: *campaign_id,
+            evaluated: actions.len() as u64,
+            would_block: 0,
+            newly_blocked: 0,
+            violations_by_type: HashMap::new(),
+            from: actions.first().map(|a| a.evaluated_at),
+            to: actions.last().map(|a| a.evaluated_at),
+        };
+        let mut replay = ExposureWindow::default();
+        for action in actions {
+            let mut violations = Vec::new();
+            check_rule(
+                rule,
+                &action.context,
+                action.evaluated_at,
+                &replay,
+                &mut violations,
+            );
+            for v in &violations {
+                *report
+                    .violations_by_type
+                    .entry(v.rule_type.clone())
+                    .or_default() += 1;
+            }
+            let blocks = violations
+                .iter()
+                .any(|v| matches!(v.severity, ViolationSeverity::Block));
+            if blocks {
+                report.would_block += 1;
+                if action.allowed {
+                    report.newly_blocked += 1;
+                }
+            }
+            replay.push(
+                RecordedAction {
+                    allowed: action.allowed && !blocks,
+                    ..action
+                },
+                usize::MAX,
+            );
+        }
+        report
+    }
+
     pub fn get_violation_log(&self, campaign_id: &Uuid) -> ViolationLog {
         let mut by_type = std::collections::HashMap::new();
         for entry in self.violation_counts.iter() {
@@ -321,8 +408,447 @@ impl GuardrailsEngine {
     }
 }
 
+/// Append the violations of one rule to `violations`. `exposure` holds the
+/// campaign's recent decisions, for the segment exposure rules.
+fn check_rule(
+    rule: &GuardrailRule,
+    context: &ActionContext,
+    now: DateTime<Utc>,
+    exposure: &ExposureWindow,
+    violations: &mut Vec<GuardrailViolation>,
+) {
+    match rule {
+        GuardrailRule::FrequencyCap {
+            max_per_day,
+            max_per_week,
+            max_per_month,
+        } => {
+            if context.user_sends_today >= *max_per_day {
+                violations.push(GuardrailViolation {
+                    rule_type: "frequency_cap".to_string(),
+                    description: format!(
+                        "Daily cap exceeded ({}/{})",
+                        context.user_sends_today, max_per_day
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+            if context.user_sends_week >= *max_per_week {
+                violations.push(GuardrailViolation {
+                    rule_type: "frequency_cap".to_string(),
+                    description: format!(
+                        "Weekly cap exceeded ({}/{})",
+                        context.user_sends_week, max_per_week
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+            if context.user_sends_month >= *max_per_month {
+                violations.push(GuardrailViolation {
+                    rule_type: "frequency_cap".to_string(),
+                    description: format!(
+                        "Monthly cap exceeded ({}/{})",
+                        context.user_sends_month, max_per_month
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+        }
+        GuardrailRule::TimeRestriction {
+            allowed_hours,
+            allowed_days,
+        } => {
+            let hour = now.hour();
+            let day = now.weekday().num_days_from_monday() + 1;
+            if !allowed_hours.contains(&hour) {
+                violations.push(GuardrailViolation {
+                    rule_type: "time_restriction".to_string(),
+                    description: format!("Hour {} not in allowed hours", hour),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+            if !allowed_days.contains(&day) {
+                violations.push(GuardrailViolation {
+                    rule_type: "time_restriction".to_string(),
+                    description: format!("Day {} not in allowed days", day),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+        }
+        GuardrailRule::IncentiveCap {
+            max_discount_percent,
+            never_discount_segments,
+        } => {
+            if let Some(discount) = context.discount_percent {
+                if discount > *max_discount_percent {
+                    violations.push(GuardrailViolation {
+                        rule_type: "incentive_cap".to_string(),
+                        description: format!(
+                            "Discount {}% exceeds max {}%",
+                            discount, max_discount_percent
+                        ),
+                        severity: ViolationSeverity::Block,
+                    });
+                }
+                if discount > 0.0 {
+                    for seg in &context.user_segments {
+                        if never_discount_segments.contains(seg) {
+                            violations.push(GuardrailViolation {
+                                rule_type: "incentive_cap".to_string(),
+                                description: format!(
+                                    "Segment '{}' is excluded from discounts",
+                                    seg
+                                ),
+                                severity: ViolationSeverity::Block,
+                            });
+                        }
+                    }
+                }
+            }
+        }
+        GuardrailRule::BudgetCap {
+            max_daily_spend,
+            max_monthly_spend,
+        } => {
+            if context.campaign_spend_today >= *max_daily_spend {
+                violations.push(GuardrailViolation {
+                    rule_type: "budget_cap".to_string(),
+                    description: format!(
+                        "Daily budget exceeded (${:.2}/${:.2})",
+                        context.campaign_spend_today, max_daily_spend
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+            if context.campaign_spend_month >= *max_monthly_spend {
+                violations.push(GuardrailViolation {
+                    rule_type: "budget_cap".to_string(),
+                    description: format!(
+                        "Monthly budget exceeded (${:.2}/${:.2})",
+                        context.campaign_spend_month, max_monthly_spend
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+        }
+        GuardrailRule::SegmentRestriction {
+            blocked_segments,
+            blocked_channels,
+        } => {
+            for seg in &context.user_segments {
+                if blocked_segments.contains(seg) {
+                    violations.push(GuardrailViolation {
+                        rule_type: "segment_restriction".to_string(),
+                        description: format!("Segment '{}' is blocked", seg),
+                        severity: ViolationSeverity::Block,
+                    });
+                }
+            }
+            if blocked_channels.contains(&context.channel) {
+                violations.push(GuardrailViolation {
+                    rule_type: "channel_restriction".to_string(),
+                    description: format!(
+                        "Channel '{}' is blocked for user segments",
+                        context.channel
+                    ),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+        }
+        GuardrailRule::ChannelRestriction { .. } => {
+            if !context.has_consent {
+                violations.push(GuardrailViolation {
+                    rule_type: "consent".to_string(),
+                    description: "User has not provided consent".to_string(),
+                    severity: ViolationSeverity::Block,
+                });
+            }
+        }
+        GuardrailRule::SegmentExposureCap {
+            segment,
+            max_share,
+            min_sample,
+        } => {
+            if context.user_segments.contains(segment) && exposure.allowed >= *min_sample {
+                let share = exposure
+                    .segments
+                    .get(segment)
+                    .map(|e| e.allowed)
+                    .unwrap_or(0) as f64
+                    / exposure.allowed as f64;
+                if share >= *max_share {
+                    violations.push(GuardrailViolation {
+                        rule_type: "exposure_cap".to_string(),
+                        description: format!(
+                            "Segment '{}' already received {:.1}% of exposures (cap {:.1}%)",
+                            segment,
+                            share * 100.0,
+                            max_share * 100.0
+                        ),
+                        severity: ViolationSeverity::Block,
+                    });
+                }
+            }
+        }
+        GuardrailRule::SegmentFairness {
+            segments,
+            max_disparity,
+            min_sample,
+        } => {
+            let rates: Vec<(&String, f64)> = segments
+                .iter()
+                .filter_map(|s| {
+                    exposure
+                        .segments
+                        .get(s)
+                        .filter(|e| e.evaluated >= *min_sample)
+                        .map(|e| (s, e.allowed as f64 / e.evaluated as f64))
+                })
+                .collect();
+            if let Some(lowest) = rates.iter().map(|(_, r)| *r).reduce(f64::min) {
+                for (segment, rate) in &rates {
+                    if context.user_segments.contains(segment) && rate - lowest > *max_disparity {
+                        violations.push(GuardrailViolation {
+                            rule_type: "fairness".to_string(),
+                            description: format!(
+                                "Segment '{}' exposure rate {:.1}% exceeds the lowest ({:.1}%) \
+                                 by more than {:.1} points",
+                                segment,
+                                rate * 100.0,
+                                lowest * 100.0,
+                                max_disparity * 100.0
+                            ),
+                            severity: ViolationSeverity::Block,
+                        });
+                    }
+                }
+            }
+        }
+        GuardrailRule::MinimumMargin { min_margin_percent } => {
+            if let Some(price) = context.offer_price {
+                let net = price * (1.0 - context.discount_percent.unwrap_or(0.0) / 100.0);
+                let margin = if net > 0.0 {
+                    (net - context.offer_cost.unwrap_or(0.0)) / net * 100.0
+                } else {
+                    -100.0
+                };
+                if margin < *min_margin_percent {
+                    violations.push(GuardrailViolation {
+                        rule_type: "minimum_margin".to_string(),
+                        description: format!(
+                            "Offer '{}' margin {:.1}% below minimum {:.1}%",
+                            context.offer_id.as_deref().unwrap_or("unknown"),
+                            margin,
+                            min_margin_percent
+                        ),
+                        severity: ViolationSeverity::Block,
+                    });
+                }
+            }
+        }
+        GuardrailRule::KillSwitch { reason } => violations.push(kill_switch_violation(reason)),
+    }
+}
+
+fn kill_switch_violation(reason: &str) -> GuardrailViolation {
+    GuardrailViolation {
+        rule_type: "kill_switch".to_string(),
+        description: format!("Kill switch engaged: {}", reason),
+        severity: ViolationSeverity::Block,
+    }
+}
+
 impl Default for GuardrailsEngine {
     fn default() -> Self {
         Self::new()
     }
 }
+
+// ---------------------------------------------------------------------------
+// Tests
+// ---------------------------------------------------------------------------
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use campaign_intelligent_delivery::frequency_capping::FrequencyRule;
+
+    fn configure(engine: &GuardrailsEngine, rules: Vec<GuardrailRule>) -> Uuid {
+        let campaign_id = Uuid::new_v4();
+        engine.configure(GuardrailConfig {
+            campaign_id,
+            rules,
+            created_at: Utc::now(),
+        });
+        campaign_id
+    }
+
+    fn context(segments: &[&str]) -> ActionContext {
+        ActionContext {
+            user_id: Uuid::new_v4(),
+            user_segments: segments.iter().map(|s| s.to_string()).collect(),
+            channel: "email".to_string(),
+            discount_percent: None,
+            campaign_spend_today: 0.0,
+            campaign_spend_month: 0.0,
+            user_sends_today: 0,
+            user_sends_week: 0,
+            user_sends_month: 0,
+            has_consent: true,
+            offer_id: None,
+            offer_price: None,
+            offer_cost: None,
+        }
+    }
+
+    fn rule_types(decision: &GuardrailDecision) -> Vec<&str> {
+        decision
+            .violations
+            .iter()
+            .map(|v| v.rule_type.as_str())
+            .collect()
+    }
+
+    #[test]
+    fn test_caps_use_shared_budget_and_frequency_state() {
+        let budgets = Arc::new(BudgetTracker::new());
+        let caps = Arc::new(FrequencyCapEngine::new(Vec::<FrequencyRule>::new()));
+        let engine = GuardrailsEngine::new()
+            .with_budget_tracker(budgets.clone())
+            .with_frequency_caps(caps.clone());
+        let campaign_id = configure(
+            &engine,
+            vec![
+                GuardrailRule::BudgetCap {
+                    max_daily_spend: 100.0,
+                    max_monthly_spend: 1_000.0,
+                },
+                GuardrailRule::FrequencyCap {
+                    max_per_day: 2,
+                    max_per_week: 5,
+                    max_per_month: 10,
+                },
+            ],
+        );
+        let ctx = context(&[]);
+        assert!(engine.validate(&campaign_id, &ctx).allowed);
+
+        // The caller still reports zero; the shared trackers do not.
+        budgets.record_spend(campaign_id, 120.0, "email", "impression");
+        caps.record_send(ctx.user_id, CappingChannel::Email, campaign_id);
+        caps.record_send(ctx.user_id, CappingChannel::Push, campaign_id);
+        let decision = engine.validate(&campaign_id, &ctx);
+        assert!(!decision.allowed);
+        assert_eq!(rule_types(&decision), ["budget_cap", "frequency_cap"]);
+
+        // A fresh user is only over the campaign's budget.
+        let decision = engine.validate(&campaign_id, &context(&[]));
+        assert_eq!(rule_types(&decision), ["budget_cap"]);
+    }
+
+    #[test]
+    fn test_exposure_fairness_margin_and_kill_switch() {
+        let engine = GuardrailsEngine::new();
+        let campaign_id = configure(
+            &engine,
+            vec![
+                GuardrailRule::SegmentExposureCap {
+                    segment: "vip".to_string(),
+                    max_share: 0.5,
+                    min_sample: 4,
+                },
+                GuardrailRule::MinimumMargin {
+                    min_margin_percent: 20.0,
+                },
+            ],
+        );
+        let allowed: Vec<bool> = ["vip", "vip", "vip", "new", "vip", "new", "vip"]
+            .iter()
+            .map(|s| engine.validate(&campaign_id, &context(&[s])).allowed)
+            .collect();
+        // The fifth decision sees vip at 3/4 of exposures; after "new" it
+        // is 3/5, still over the cap.
+        assert_eq!(allowed, [true, true, true, true, false, true, false]);
+
+        let mut offer = context(&["new"]);
+        offer.offer_id = Some("sku-1".to_string());
+        offer.offer_price = Some(100.0);
+        offer.offer_cost = Some(70.0);
+        assert!(engine.validate(&campaign_id, &offer).allowed);
+        offer.discount_percent = Some(15.0);
+        let decision = engine.validate(&campaign_id, &offer);
+        assert_eq!(rule_types(&decision), ["minimum_margin"]);
+
+        let fair = configure(
+            &engine,
+            vec![GuardrailRule::SegmentFairness {
+                segments: vec!["north".to_string(), "south".to_string()],
+                max_disparity: 0.2,
+                min_sample: 2,
+            }],
+        );
+        engine.engage_kill_switch(Some(fair), "north outage");
+        for _ in 0..2 {
+            assert!(!engine.validate(&fair, &context(&["north"])).allowed);
+        }
+        assert!(engine.release_kill_switch(Some(fair)));
+        for _ in 0..2 {
+            assert!(engine.validate(&fair, &context(&["south"])).allowed);
+        }
+        assert!(engine.validate(&fair, &context(&["north"])).allowed);
+        let decision = engine.validate(&fair, &context(&["south"]));
+        assert_eq!(rule_types(&decision), ["fairness"]);
+
+        engine.engage_kill_switch(None, "incident");
+        let unconfigured = Uuid::new_v4();
+        assert_eq!(
+            rule_types(&engine.validate(&unconfigured, &context(&[]))),
+            ["kill_switch"]
+        );
+    }
+
+    #[test]
+    fn test_dry_run_counts_recent_decisions_a_rule_would_block() {
+        let engine = GuardrailsEngine::new();
+        let campaign_id = configure(&engine, vec![]);
+        for (segment, discount) in [("vip", 10.0), ("vip", 40.0), ("new", 50.0), ("new", 5.0)] {
+            let mut ctx = context(&[segment]);
+            ctx.discount_percent = Some(discount);
+            assert!(engine.validate(&campaign_id, &ctx).allowed);
+        }
+
+        let report = engine.dry_run(
+            &campaign_id,
+            &GuardrailRule::IncentiveCap {
+                max_discount_percent: 30.0,
+                never_discount_segments: vec![],
+            },
+        );
+        assert_eq!(report.evaluated, 4);
+        assert_eq!(report.would_block, 2);
+        assert_eq!(report.newly_blocked, 2);
+        assert_eq!(report.violations_by_type["incentive_cap"], 2);
+
+        let report = engine.dry_run(
+            &campaign_id,
+            &GuardrailRule::SegmentExposureCap {
+                segment: "vip".to_string(),
+                max_share: 0.5,
+                min_sample: 1,
+            },
+        );
+        assert_eq!(report.would_block, 1);
+
+        // Dry runs leave live state untouched.
+        assert_eq!(engine.get_violation_log(&campaign_id).total_blocked, 0);
+        assert!(engine
+            .dry_run(
+                &Uuid::new_v4(),
+                &GuardrailRule::KillSwitch {
+                    reason: "test".to_string()
+                }
+            )
+            .from
+            .is_none());
+    }
+}
@@ -1,5 +1,12 @@
 //! Holdout control groups — automated incrementality testing with statistical rigor.
-
+//!
+//! Reports use an always-valid sequential test, so the dashboards that poll
+//! them continuously do not inflate the false-positive rate.
+
+use campaign_core::experiment_assignment::{bucket, BUCKETS};
+use campaign_core::experiment_stats::{
+    probability_to_beat_control, ExperimentDesign, SampleStats, SequentialConfig, SequentialTest,
+};
 use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
 use uuid::Uuid;
@@ -17,6 +24,10 @@ pub struct HoldoutConfig {
     pub enabled: bool,
     pub holdout_percentage: f64,
     pub created_at: DateTime<Utc>,
+    /// Baseline and minimum effect the holdout is sized for; fixes the
+    /// sequential test's mixing variance when the holdout is configured.
+    #[serde(default)]
+    pub design: Option<ExperimentDesign>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -30,10 +41,13 @@ pub struct IncrementalityReport {
     pub control_rate: f64,
     pub absolute_lift: f64,
     pub relative_lift: f64,
+    /// Always-valid p-value; never increases between reports.
     pub p_value: f64,
     pub is_significant: bool,
     pub confidence_interval_lower: f64,
     pub confidence_interval_upper: f64,
+    #[serde(default)]
+    pub probability_treatment_better: f64,
     pub incremental_conversions: u64,
     pub incremental_revenue: f64,
     pub computed_at: DateTime<Utc>,
@@ -43,6 +57,8 @@ pub struct HoldoutManager {
     configs: dashmap::DashMap<Uuid, HoldoutConfig>,
     treatment_data: dashmap::DashMap<Uuid, (u64, u64)>,
     control_data: dashmap::DashMap<Uuid, (u64, u64)>,
+    sequential: dashmap::DashMap<Uuid, SequentialTest>,
+    sequential_config: SequentialConfig,
 }
 
 impl HoldoutManager {
@@ -51,10 +67,22 @@ impl HoldoutManager {
             configs: dashmap::DashMap::new(),
             treatment_data: dashmap::DashMap::new(),
             control_data: dashmap::DashMap::new(),
+            sequential: dashmap::DashMap::new(),
+            sequential_config: SequentialConfig::default(),
         }
     }
 
+    pub fn with_sequential_config(mut self, config: SequentialConfig) -> Self {
+        self.sequential_config = config;
+        self
+    }
+
     pub fn configure(&self, config: HoldoutConfig) {
+        let test = match &config.design {
+            Some(design) => SequentialTest::from_design(design),
+            None => SequentialTest::new(self.sequential_config),
+        };
+        self.sequential.insert(config.campaign_id, test);
         self.configs.insert(config.campaign_id, config);
     }
 
@@ -62,8 +90,8 @@ impl HoldoutManager {
         let config = self.configs.get(campaign_id);
         let holdout_pct = config.map(|c| c.holdout_percentage).unwrap_or(0.1);
 
-        let hash = Self::hash_user(user_id);
-        let bucket = (hash % 100) as f64 / 100.0;
+        // Salted per campaign so holdouts of different campaigns are independent.
+        let bucket = bucket(&format!("holdout.{campaign_id}"), user_id) as f64 / BUCKETS as f64;
 
         if bucket < holdout_pct {
             HoldoutGroup::Control
@@ -113,14 +141,20 @@ impl HoldoutManager {
         let abs_lift = t_rate - c_rate;
         let rel_lift = if c_rate > 0.0 { abs_lift / c_rate } else { 0.0 };
 
-        let p_value = Self::two_proportion_z_test(t_conv, t_total, c_conv, c_total);
-        let ci_width = if t_total > 0 && c_total > 0 {
-            1.96 * ((t_rate * (1.0 - t_rate) / t_total as f64)
-                + (c_rate * (1.0 - c_rate) / c_total as f64))
-                .sqrt()
+        let control = SampleStats::from_conversions(c_conv, c_total);
+        let treatment = SampleStats::from_conversions(t_conv, t_total);
+        // Only campaigns that are configured or have outcomes keep test
+        // state; a report for an unknown id must not allocate any.
+        let known = self.configs.contains_key(campaign_id) || t_total > 0 || c_total > 0;
+        let test = if known {
+            self.sequential
+                .entry(*campaign_id)
+                .or_insert_with(|| SequentialTest::new(self.sequential_config))
+                .observe(&control, &treatment)
         } else {
-            0.0
+            SequentialTest::new(self.sequential_config).observe(&control, &treatment)
         };
+        let (ci_lower, ci_upper) = test.confidence_interval.unwrap_or((abs_lift, abs_lift));
 
         let incremental_conv = ((t_rate - c_rate) * t_total as f64).max(0.0) as u64;
 
@@ -134,50 +168,85 @@ impl HoldoutManager {
             control_rate: c_rate,
             absolute_lift: abs_lift,
             relative_lift: rel_lift,
-            p_value,
-            is_significant: p_value < 0.05,
-            confidence_interval_lower: abs_lift - ci_width,
-            confidence_interval_upper: abs_lift + ci_width,
+            p_value: test.p_value,
+            is_significant: test.significant,
+            confidence_interval_lower: ci_lower,
+            confidence_interval_upper: ci_upper,
+            probability_treatment_better: probability_to_beat_control(
+                (c_conv, c_total),
+                (t_conv, t_total),
+            ),
             incremental_conversions: incremental_conv,
             incremental_revenue: incremental_conv as f64 * 50.0,
             computed_at: Utc::now(),
         }
     }
+}
 
-    fn hash_user(user_id: &str) -> u64 {
-        let mut hash: u64 = 0xcbf29ce484222325;
-        for byte in user_id.bytes() {
-            hash ^= byte as u64;
-            hash = hash.wrapping_mul(0x100000001b3);
-        }
-        hash
+impl Default for HoldoutManager {
+    fn default() -> Self {
+        Self::new()
     }
+}
 
-    fn two_proportion_z_test(x1: u64, n1: u64, x2: u64, n2: u64) -> f64 {
-        if n1 == 0 || n2 == 0 {
-            return 1.0;
-        }
-        let p1 = x1 as f64 / n1 as f64;
-        let p2 = x2 as f64 / n2 as f64;
-        let p = (x1 + x2) as f64 / (n1 + n2) as f64;
-        let se = (p * (1.0 - p) * (1.0 / n1 as f64 + 1.0 / n2 as f64)).sqrt();
-        if se == 0.0 {
-            return 1.0;
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(manager: &HoldoutManager, campaign: Uuid, group: HoldoutGroup, n: u64, conv: u64) {
+        for i in 0..n {
+            manager.record_outcome(&campaign, group, i < conv);
         }
-        let z = (p1 - p2).abs() / se;
-        // Approximate p-value from z-score using error function approximation
-        let t = 1.0 / (1.0 + 0.2316419 * z);
-        let d = 0.3989422804014327;
-        let p_val = d
-            * (-z * z / 2.0).exp()
-            * (t * (0.3193815
-                + t * (-0.3565638 + t * (1.781478 + t * (-1.821256 + t * 1.330274)))));
-        2.0 * p_val
     }
-}
 
-impl Default for HoldoutManager {
-    fn default() -> Self {
-        Self::new()
+    #[test]
+    fn test_report_is_always_valid_across_peeks() {
+        let manager = HoldoutManager::new();
+        let campaign = Uuid::new_v4();
+        record(&manager, campaign, HoldoutGroup::Control, 1000, 50);
+        record(&manager, campaign, HoldoutGroup::Treatment, 9000, 720);
+
+        // 5% vs 8%: a fixed-horizon z-test would call this (p ≈ 5e-4).
+        let first = manager.get_report(&campaign);
+        assert!(first.p_value > 5e-4);
+        assert!(first.probability_treatment_better > 0.99);
+        assert!(first.confidence_interval_lower < first.absolute_lift);
+        assert!(first.confidence_interval_upper > first.absolute_lift);
+
+        record(&manager, campaign, HoldoutGroup::Control, 1000, 50);
+        record(&manager, campaign, HoldoutGroup::Treatment, 9000, 720);
+        let second = manager.get_report(&campaign);
+        assert!(second.p_value <= first.p_value);
+        assert!(second.is_significant, "{:?}", second.p_value);
+        assert!(second.confidence_interval_lower > 0.0);
+
+        let empty = manager.get_report(&Uuid::new_v4());
+        assert_eq!(empty.p_value, 1.0);
+        assert!(!empty.is_significant);
+        assert_eq!(manager.sequential.len(), 1);
+    }
+
+    #[test]
+    fn test_configured_design_fixes_mixing_variance() {
+        let manager = HoldoutManager::new();
+        let campaign = Uuid::new_v4();
+        manager.configure(HoldoutConfig {
+            campaign_id: campaign,
+            enabled: true,
+            holdout_percentage: 0.1,
+            created_at: Utc::now(),
+            design: Some(ExperimentDesign::new(0.05, 0.5)),
+        });
+        record(&manager, campaign, HoldoutGroup::Control, 1000, 50);
+        record(&manager, campaign, HoldoutGroup::Treatment, 9000, 720);
+        manager.get_report(&campaign);
+        let tau2 = manager
+            .sequential
+            .get(&campaign)
+            .unwrap()
+            .config
+            .mixing_variance;
+        assert_eq!(tau2, Some(0.025f64.powi(2)));
+        assert_eq!(manager.sequential.get(&campaign).unwrap().looks, 1);
     }
 }
@@ -1,6 +1,6 @@
 //! Reinforcement Learning engine — multi-armed bandits (Thompson Sampling, UCB1,
 //! Epsilon-Greedy), contextual bandits (LinUCB), holdout groups, guardrails,
-//! and AI explainability dashboard.
+//! AI explainability dashboard, and durable snapshots of learned state.
 
 pub mod bandits;
 pub mod contextual;
@@ -8,6 +8,7 @@ pub mod explainability;
 pub mod guardrails;
 pub mod holdout;
 pub mod offerfit;
+pub mod persistence;
 
 pub use bandits::BanditEngine;
 pub use contextual::ContextualBanditEngine;
@@ -0,0 +1,35 @@
+//! Durable JSON snapshots of learned engine state.
+//!
+//! Snapshots are written to a sibling temporary file, synced and renamed
+//! over the target, so a crash mid-write never leaves a torn snapshot.
+
+use anyhow::Context;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use std::fs::{self, File};
+use std::io::{BufReader, BufWriter, Write};
+use std::path::Path;
+
+/// Atomically replace `path` with the JSON encoding of `value`.
+pub fn save_json<T: Serialize>(path: &Path, value: &T) -> anyhow::Result<()> {
+    if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+        fs::create_dir_all(dir)
+            .with_context(|| format!("creating snapshot directory {}", dir.display()))?;
+    }
+    let tmp = path.with_extension("tmp");
+    let file =
+        File::create(&tmp).with_context(|| format!("creating snapshot {}", tmp.display()))?;
+    let mut writer = BufWriter::new(file);
+    serde_json::to_writer(&mut writer, value).context("encoding snapshot")?;
+    writer.flush()?;
+    writer.get_ref().sync_all()?;
+    fs::rename(&tmp, path).with_context(|| format!("replacing snapshot {}", path.display()))?;
+    Ok(())
+}
+
+/// Read a snapshot written by [`save_json`].
+pub fn load_json<T: DeserializeOwned>(path: &Path) -> anyhow::Result<T> {
+    let file = File::open(path).with_context(|| format!("opening snapshot {}", path.display()))?;
+    serde_json::from_reader(BufReader::new(file))
+        .with_context(|| format!("decoding snapshot {}", path.display()))
+}
@@ -4,10 +4,15 @@
 
 use campaign_agents::AgentManager;
 use campaign_analytics::AnalyticsLogger;
+use campaign_api::profiles::CacheProfileSource;
+use campaign_api::reporting_rest::ReportingState;
 use campaign_api::ApiServer;
 use campaign_cache::RedisCache;
 use campaign_core::config::AppConfig;
+use campaign_journey::JourneyEngine;
 use campaign_npu::NpuEngine;
+use campaign_personalization::cf_model::ItemEmbeddings;
+use campaign_personalization::{CatalogEngine, RecommendationEngine};
 use clap::Parser;
 use std::sync::Arc;
 use tracing::{error, info, warn};
@@ -89,12 +94,21 @@ async fn main() -> anyhow::Result<()> {
         connect_with_retry("Redis", || RedisCache::new(&config.redis)).await?,
     );
 
+    // Reporting pipeline; bid-path events join the channel events in it.
+    // Saved reports query ClickHouse and are delivered on schedule.
+    let reporting = ReportingState::new()
+        .with_clickhouse(&config.clickhouse)
+        .with_reporting_config(&config.reporting);
+    reporting.spawn_report_scheduler(&config.sendgrid);
+    reporting.spawn_live_pruner();
+
     // Initialize analytics logger with retry
     let analytics = Arc::new(
         connect_with_retry("ClickHouse", || {
             AnalyticsLogger::new(&config.clickhouse, config.node_id.clone())
         })
-        .await?,
+        .await?
+        .with_event_sink(reporting.router.clone()),
     );
 
     // Initialize agent manager and processor
@@ -122,8 +136,39 @@ async fn main() -> anyhow::Result<()> {
         info!("Running in API-only mode (no NATS agents)");
     }
 
+    // Recommendations share the catalogs templates read, serve the trained
+    // CF model when one is configured and prune co-occurrence on a schedule.
+    let catalogs = Arc::new(CatalogEngine::new());
+    let recommendations = Arc::new(RecommendationEngine::new().with_catalog(catalogs.clone()));
+    if let Some(path) = &config.recommendations.cf_model_path {
+        match ItemEmbeddings::load(path) {
+            Ok(embeddings) => {
+                info!(
+                    path = %path,
+                    version = %embeddings.version,
+                    items = embeddings.len(),
+                    "Loaded CF model"
+                );
+                recommendations.load_cf_model(embeddings);
+            }
+            Err(e) => {
+                warn!(path = %path, error = %e, "Failed to load CF model, using heuristic CF");
+            }
+        }
+    }
+    recommendations
+        .clone()
+        .spawn_pruner(std::time::Duration::from_secs(
+            config.recommendations.prune_interval_secs,
+        ));
+
     // Start API server
-    let api_server = ApiServer::new(config.clone(), processor);
+    let api_server = ApiServer::new(config.clone(), processor)
+        .with_journey_engine(JourneyEngine::new().with_event_sink(reporting.router.clone()))
+        .with_catalogs(catalogs)
+        .with_recommendations(recommendations)
+        .with_reporting(reporting)
+        .with_profile_source(Arc::new(CacheProfileSource::new(cache.clone())));
 
     // Start metrics exporter
     if let Err(e) = api_server.start_metrics().await {